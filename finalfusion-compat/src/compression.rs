@@ -0,0 +1,257 @@
+//! Transparent decompression of embedding files.
+//!
+//! Pretrained embeddings in the word2vec, text, and fastText formats
+//! are often distributed as compressed files. The readers of these
+//! formats detect compressed input by its magic bytes and decompress
+//! it while reading, so that a compressed file can be read in the same
+//! way as an uncompressed file:
+//!
+//! ```no_run
+//! use std::fs::File;
+//! use std::io::BufReader;
+//!
+//! use finalfusion_compat::text::ReadTextDims;
+//! use finalfusion_core::embeddings::Embeddings;
+//!
+//! let mut reader = BufReader::new(File::open("../testdata/similarity.txt.gz").unwrap());
+//! let embeddings = Embeddings::read_text_dims(&mut reader).unwrap();
+//! ```
+//!
+//! Decompression is provided by optional dependencies: gzip requires
+//! the `flate2` feature and xz requires the `xz2` feature. Compressed
+//! data is always detected. When the corresponding feature is not
+//! enabled, reading the data results in an error that asks for
+//! decompression of the file.
+
+#[cfg(any(feature = "flate2", feature = "xz2"))]
+use std::io::BufReader;
+use std::io::{self, BufRead, Read};
+
+#[cfg(feature = "flate2")]
+use flate2::bufread::MultiGzDecoder;
+#[cfg(feature = "xz2")]
+use xz2::bufread::XzDecoder;
+
+use finalfusion_io::io::{ErrorKind, Result};
+
+/// Magic bytes of gzip streams.
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+
+/// Magic bytes of xz streams.
+const XZ_MAGIC: &[u8] = &[0xfd, b'7', b'z', b'X', b'Z', 0x00];
+
+/// Compression of a data stream.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Compression {
+    /// Uncompressed data.
+    None,
+
+    /// gzip-compressed data.
+    Gzip,
+
+    /// xz-compressed data.
+    Xz,
+}
+
+impl Compression {
+    /// Detect the compression of the data of a reader.
+    ///
+    /// The compression is detected from the magic bytes in the buffer
+    /// of the reader, no data is consumed.
+    pub fn detect(reader: &mut impl BufRead) -> Result<Self> {
+        let buf = reader
+            .fill_buf()
+            .map_err(|e| ErrorKind::io_error("Cannot read data to detect compression", e))?;
+
+        if buf.starts_with(GZIP_MAGIC) {
+            Ok(Compression::Gzip)
+        } else if buf.starts_with(XZ_MAGIC) {
+            Ok(Compression::Xz)
+        } else {
+            Ok(Compression::None)
+        }
+    }
+}
+
+/// Reader that decompresses data transparently.
+pub enum Decompress<R> {
+    /// Uncompressed data that is read as-is.
+    Plain(R),
+
+    /// gzip-compressed data.
+    #[cfg(feature = "flate2")]
+    Gzip(BufReader<MultiGzDecoder<R>>),
+
+    /// xz-compressed data.
+    #[cfg(feature = "xz2")]
+    Xz(BufReader<XzDecoder<R>>),
+}
+
+impl<R> Decompress<R>
+where
+    R: BufRead,
+{
+    /// Construct a reader that decompresses the data of `reader`.
+    ///
+    /// Uncompressed data is read as-is. An error is returned for data
+    /// with a compression that is not enabled.
+    pub fn new(mut reader: R) -> Result<Self> {
+        match Compression::detect(&mut reader)? {
+            Compression::None => Ok(Decompress::Plain(reader)),
+            #[cfg(feature = "flate2")]
+            Compression::Gzip => Ok(Decompress::Gzip(BufReader::new(MultiGzDecoder::new(
+                reader,
+            )))),
+            #[cfg(not(feature = "flate2"))]
+            Compression::Gzip => Err(ErrorKind::Format(
+                "gzip-compressed data requires the flate2 feature, decompress the file first"
+                    .to_string(),
+            )
+            .into()),
+            #[cfg(feature = "xz2")]
+            Compression::Xz => Ok(Decompress::Xz(BufReader::new(
+                XzDecoder::new_multi_decoder(reader),
+            ))),
+            #[cfg(not(feature = "xz2"))]
+            Compression::Xz => Err(ErrorKind::Format(
+                "xz-compressed data requires the xz2 feature, decompress the file first"
+                    .to_string(),
+            )
+            .into()),
+        }
+    }
+}
+
+impl<R> Read for Decompress<R>
+where
+    R: BufRead,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Decompress::Plain(reader) => reader.read(buf),
+            #[cfg(feature = "flate2")]
+            Decompress::Gzip(reader) => reader.read(buf),
+            #[cfg(feature = "xz2")]
+            Decompress::Xz(reader) => reader.read(buf),
+        }
+    }
+}
+
+impl<R> BufRead for Decompress<R>
+where
+    R: BufRead,
+{
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        match self {
+            Decompress::Plain(reader) => reader.fill_buf(),
+            #[cfg(feature = "flate2")]
+            Decompress::Gzip(reader) => reader.fill_buf(),
+            #[cfg(feature = "xz2")]
+            Decompress::Xz(reader) => reader.fill_buf(),
+        }
+    }
+
+    fn consume(&mut self, amt: usize) {
+        match self {
+            Decompress::Plain(reader) => reader.consume(amt),
+            #[cfg(feature = "flate2")]
+            Decompress::Gzip(reader) => reader.consume(amt),
+            #[cfg(feature = "xz2")]
+            Decompress::Xz(reader) => reader.consume(amt),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::{Compression, Decompress};
+
+    /// "hello" and "world" as gzip members, compressed with level 0
+    /// (stored) and fixed codes.
+    const GZIP_MEMBERS: &[u8] = &[
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x03, 0x01, 0x05, 0x00, 0xfa, 0xff,
+        0x68, 0x65, 0x6c, 0x6c, 0x6f, 0x86, 0xa6, 0x10, 0x36, 0x05, 0x00, 0x00, 0x00, 0x1f, 0x8b,
+        0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0x2b, 0xcf, 0x2f, 0xca, 0x49, 0x01, 0x00,
+        0x43, 0x11, 0x77, 0x3a, 0x05, 0x00, 0x00, 0x00,
+    ];
+
+    /// "helloworld" as an xz stream.
+    const XZ_STREAM: &[u8] = &[
+        0xfd, 0x37, 0x7a, 0x58, 0x5a, 0x00, 0x00, 0x01, 0x69, 0x22, 0xde, 0x36, 0x02, 0x00, 0x21,
+        0x01, 0x16, 0x00, 0x00, 0x00, 0x74, 0x2f, 0xe5, 0xa3, 0x01, 0x00, 0x09, 0x68, 0x65, 0x6c,
+        0x6c, 0x6f, 0x77, 0x6f, 0x72, 0x6c, 0x64, 0x00, 0x00, 0x00, 0xad, 0x20, 0xeb, 0xf9, 0x00,
+        0x01, 0x1e, 0x0a, 0xea, 0x63, 0x12, 0x14, 0x90, 0x42, 0x99, 0x0d, 0x01, 0x00, 0x00, 0x00,
+        0x00, 0x01, 0x59, 0x5a,
+    ];
+
+    fn decompress(data: &[u8]) -> finalfusion_io::io::Result<Vec<u8>> {
+        let mut decompressed = Vec::new();
+        Decompress::new(data)?
+            .read_to_end(&mut decompressed)
+            .unwrap();
+        Ok(decompressed)
+    }
+
+    #[test]
+    fn detect_compression() {
+        assert_eq!(
+            Compression::detect(&mut &GZIP_MEMBERS[..]).unwrap(),
+            Compression::Gzip
+        );
+        assert_eq!(
+            Compression::detect(&mut &XZ_STREAM[..]).unwrap(),
+            Compression::Xz
+        );
+        assert_eq!(
+            Compression::detect(&mut &b"41 100\n"[..]).unwrap(),
+            Compression::None
+        );
+        assert_eq!(
+            Compression::detect(&mut &b""[..]).unwrap(),
+            Compression::None
+        );
+    }
+
+    #[test]
+    fn plain_data_is_read_as_is() {
+        assert_eq!(decompress(b"41 100\n").unwrap(), b"41 100\n");
+    }
+
+    #[cfg(feature = "flate2")]
+    #[test]
+    fn gzip_members_are_decompressed() {
+        assert_eq!(decompress(GZIP_MEMBERS).unwrap(), b"helloworld");
+    }
+
+    #[cfg(feature = "flate2")]
+    #[test]
+    fn corrupt_gzip_data_is_rejected() {
+        let mut data = GZIP_MEMBERS.to_vec();
+        let crc_idx = data.len() - 8;
+        data[crc_idx] ^= 0xff;
+        assert!(Decompress::new(&data[..])
+            .unwrap()
+            .read_to_end(&mut Vec::new())
+            .is_err());
+    }
+
+    #[cfg(not(feature = "flate2"))]
+    #[test]
+    fn gzip_data_is_rejected_without_flate2() {
+        assert!(Decompress::new(GZIP_MEMBERS).is_err());
+    }
+
+    #[cfg(feature = "xz2")]
+    #[test]
+    fn xz_data_is_decompressed() {
+        assert_eq!(decompress(XZ_STREAM).unwrap(), b"helloworld");
+    }
+
+    #[cfg(not(feature = "xz2"))]
+    #[test]
+    fn xz_data_is_rejected_without_xz2() {
+        assert!(Decompress::new(XZ_STREAM).is_err());
+    }
+}
@@ -0,0 +1,421 @@
+//! Reader for embeddings in delimited text formats, such as CSV.
+//!
+//! Embeddings are sometimes distributed as delimited text files that
+//! deviate from the space-separated text format that `ReadText`
+//! reads. For instance, the file may be comma-separated, have a
+//! header row, quote words that contain the delimiter, or store the
+//! word after the embedding. `DelimitedOptions` describes the layout
+//! of such files:
+//!
+//! ```
+//! use std::io::Cursor;
+//!
+//! use finalfusion_compat::delimited::{DelimitedOptions, ReadDelimited};
+//! use finalfusion_core::embeddings::Embeddings;
+//!
+//! let data = "word,x,y\n\"Berlin, Germany\",0.6,0.8\nPotsdam,1.0,0.0\n";
+//!
+//! let options = DelimitedOptions::csv().header(true);
+//! let embeddings = Embeddings::read_delimited(&mut Cursor::new(data), &options)
+//!     .unwrap();
+//!
+//! // Look up an embedding.
+//! let embedding = embeddings.embedding("Berlin, Germany");
+//! ```
+//!
+//! Quoting follows the conventions of RFC 4180: a quoted field can
+//! contain the delimiter, line breaks, and quotes, which are written
+//! as two quote characters.
+
+use std::io::BufRead;
+use std::mem;
+
+use finalfusion_core::embeddings::Embeddings;
+use finalfusion_core::storage::NdArray;
+use finalfusion_core::util::decode_string;
+use finalfusion_core::vocab::SimpleVocab;
+use finalfusion_core::warnings::{Warning, Warnings};
+use finalfusion_io::io::{ErrorKind, Result};
+
+use crate::compression::Decompress;
+use crate::duplicates::{DuplicatePolicy, UniqueRows};
+use crate::norms::{normalize, NormsPolicy};
+
+/// Position of the word in a record.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ColumnOrder {
+    /// The word is followed by the embedding.
+    WordFirst,
+
+    /// The embedding is followed by the word.
+    WordLast,
+}
+
+/// Options for reading delimited text files.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DelimitedOptions {
+    /// The delimiter that separates the fields of a record.
+    pub delimiter: u8,
+
+    /// The character that quotes fields.
+    ///
+    /// If `None`, fields are not unquoted.
+    pub quote: Option<u8>,
+
+    /// Skip the first record, which contains column names.
+    pub header: bool,
+
+    /// The position of the word in a record.
+    pub column_order: ColumnOrder,
+
+    /// Replace invalid UTF-8 in words by the replacement character,
+    /// rather than failing.
+    pub lossy: bool,
+
+    /// The policy for words that occur more than once.
+    pub duplicates: DuplicatePolicy,
+
+    /// The policy for the norms of the embeddings.
+    pub norms: NormsPolicy,
+}
+
+impl DelimitedOptions {
+    /// Options for comma-separated files with double quotes.
+    pub fn csv() -> Self {
+        DelimitedOptions {
+            delimiter: b',',
+            quote: Some(b'"'),
+            header: false,
+            column_order: ColumnOrder::WordFirst,
+            lossy: false,
+            duplicates: DuplicatePolicy::Error,
+            norms: NormsPolicy::Keep,
+        }
+    }
+
+    /// Options for tab-separated files without quoting.
+    pub fn tsv() -> Self {
+        DelimitedOptions {
+            delimiter: b'\t',
+            quote: None,
+            ..Self::csv()
+        }
+    }
+
+    /// Set the delimiter that separates the fields of a record.
+    pub fn delimiter(mut self, delimiter: u8) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Set the character that quotes fields.
+    pub fn quote(mut self, quote: Option<u8>) -> Self {
+        self.quote = quote;
+        self
+    }
+
+    /// Skip the first record, which contains column names.
+    pub fn header(mut self, header: bool) -> Self {
+        self.header = header;
+        self
+    }
+
+    /// Set the position of the word in a record.
+    pub fn column_order(mut self, column_order: ColumnOrder) -> Self {
+        self.column_order = column_order;
+        self
+    }
+
+    /// Replace invalid UTF-8 in words.
+    pub fn lossy(mut self, lossy: bool) -> Self {
+        self.lossy = lossy;
+        self
+    }
+
+    /// Set the policy for words that occur more than once.
+    pub fn duplicates(mut self, policy: DuplicatePolicy) -> Self {
+        self.duplicates = policy;
+        self
+    }
+
+    /// Set the policy for the norms of the embeddings.
+    pub fn norms(mut self, policy: NormsPolicy) -> Self {
+        self.norms = policy;
+        self
+    }
+}
+
+impl Default for DelimitedOptions {
+    /// Options for comma-separated files with double quotes.
+    fn default() -> Self {
+        Self::csv()
+    }
+}
+
+/// Method to construct `Embeddings` from a delimited text file.
+///
+/// Each record of the file contains a word and the components of its
+/// embedding, separated by the delimiter. Empty lines are skipped and
+/// a delimiter at the end of a record is ignored.
+pub trait ReadDelimited<R>
+where
+    Self: Sized,
+    R: BufRead,
+{
+    /// Read the embeddings from the given buffered reader.
+    fn read_delimited(reader: &mut R, options: &DelimitedOptions) -> Result<Self>;
+
+    /// Read the embeddings from the given buffered reader.
+    ///
+    /// Non-fatal issues, such as replaced invalid UTF-8 when `lossy`
+    /// is `true` and embeddings that cannot be normalized, are added
+    /// to `warnings`.
+    fn read_delimited_with_warnings(
+        reader: &mut R,
+        options: &DelimitedOptions,
+        warnings: &mut Warnings,
+    ) -> Result<Self>;
+}
+
+impl<R> ReadDelimited<R> for Embeddings<SimpleVocab, NdArray>
+where
+    R: BufRead,
+{
+    fn read_delimited(reader: &mut R, options: &DelimitedOptions) -> Result<Self> {
+        Self::read_delimited_with_warnings(reader, options, &mut Warnings::new())
+    }
+
+    fn read_delimited_with_warnings(
+        reader: &mut R,
+        options: &DelimitedOptions,
+        warnings: &mut Warnings,
+    ) -> Result<Self> {
+        let reader = &mut Decompress::new(reader)?;
+
+        let mut rows = UniqueRows::new(options.duplicates, None);
+        let mut fields = Vec::new();
+        let mut embedding = Vec::new();
+        let mut skip_header = options.header;
+
+        while read_record(reader, options, &mut fields)? {
+            if mem::replace(&mut skip_header, false) {
+                continue;
+            }
+
+            let word = match options.column_order {
+                ColumnOrder::WordFirst => fields.remove(0),
+                ColumnOrder::WordLast => fields.pop().expect("Record without fields"),
+            };
+            let (word, replaced) = decode_string(word, options.lossy)?;
+            if replaced {
+                warnings.push(Warning::InvalidUtf8 { word: word.clone() });
+            }
+
+            embedding.clear();
+            for field in &fields {
+                let component = String::from_utf8_lossy(field);
+                embedding.push(component.trim().parse().map_err(|e| {
+                    ErrorKind::Format(format!(
+                        "Cannot parse vector component '{}': {}",
+                        component, e
+                    ))
+                })?);
+            }
+
+            rows.push(word, &embedding, warnings)?;
+        }
+
+        let (_, vocab, storage, _) = rows.into_embeddings()?.into_parts();
+        Ok(normalize(vocab, storage, options.norms, warnings))
+    }
+}
+
+/// Read the fields of the next non-empty record.
+///
+/// Returns `false` at the end of the input. A record has at least one
+/// field.
+fn read_record(
+    reader: &mut dyn BufRead,
+    options: &DelimitedOptions,
+    fields: &mut Vec<Vec<u8>>,
+) -> Result<bool> {
+    let mut line = Vec::new();
+
+    loop {
+        fields.clear();
+        line.clear();
+        if read_line(reader, &mut line)? == 0 {
+            return Ok(false);
+        }
+
+        let mut field = Vec::new();
+        let mut in_quotes = false;
+        let mut last_quoted = false;
+        let mut pos = 0;
+
+        loop {
+            if pos == line.len() {
+                if !in_quotes {
+                    break;
+                }
+
+                // The quoted field continues on the next line.
+                line.clear();
+                pos = 0;
+                if read_line(reader, &mut line)? == 0 {
+                    return Err(
+                        ErrorKind::Format(String::from("Quoted field is not terminated")).into(),
+                    );
+                }
+            }
+
+            let byte = line[pos];
+            pos += 1;
+
+            if in_quotes {
+                if Some(byte) == options.quote {
+                    if line.get(pos) == Some(&byte) {
+                        field.push(byte);
+                        pos += 1;
+                    } else {
+                        in_quotes = false;
+                    }
+                } else {
+                    field.push(byte);
+                }
+            } else if byte == options.delimiter {
+                fields.push(mem::take(&mut field));
+                last_quoted = false;
+            } else if byte == b'\n' {
+                if field.last() == Some(&b'\r') {
+                    field.pop();
+                }
+            } else if Some(byte) == options.quote && field.is_empty() {
+                in_quotes = true;
+                last_quoted = true;
+            } else {
+                field.push(byte);
+            }
+        }
+
+        if fields.is_empty() && field.is_empty() && !last_quoted {
+            continue;
+        }
+
+        // Ignore a delimiter at the end of the record.
+        if !(field.is_empty() && !last_quoted && !fields.is_empty()) {
+            fields.push(field);
+        }
+
+        return Ok(true);
+    }
+}
+
+fn read_line(reader: &mut dyn BufRead, line: &mut Vec<u8>) -> Result<usize> {
+    reader
+        .read_until(b'\n', line)
+        .map_err(|e| ErrorKind::io_error("Cannot read line from embedding file", e).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use approx::AbsDiffEq;
+    use ndarray::{array, Array2};
+
+    use super::{read_record, ColumnOrder, DelimitedOptions, ReadDelimited};
+    use finalfusion_core::embeddings::Embeddings;
+    use finalfusion_core::storage::{NdArray, StorageView};
+    use finalfusion_core::util::l2_normalize_array;
+    use finalfusion_core::vocab::{SimpleVocab, Vocab};
+
+    fn records(data: &str, options: &DelimitedOptions) -> Vec<Vec<String>> {
+        let mut reader = Cursor::new(data);
+        let mut fields = Vec::new();
+        let mut records = Vec::new();
+        while read_record(&mut reader, options, &mut fields).unwrap() {
+            records.push(
+                fields
+                    .iter()
+                    .map(|field| String::from_utf8(field.clone()).unwrap())
+                    .collect(),
+            );
+        }
+        records
+    }
+
+    fn read(data: &str, options: &DelimitedOptions) -> Embeddings<SimpleVocab, NdArray> {
+        Embeddings::read_delimited(&mut Cursor::new(data), options).unwrap()
+    }
+
+    fn normalized(mut matrix: Array2<f32>) -> Array2<f32> {
+        l2_normalize_array(matrix.view_mut());
+        matrix
+    }
+
+    #[test]
+    fn read_records_with_quotes() {
+        let options = DelimitedOptions::csv();
+        assert_eq!(
+            records("a,1\r\n\"b,\"\"c\"\"\",2\n\n\"d\ne\",3,\n\"\",4", &options),
+            vec![
+                vec!["a", "1"],
+                vec!["b,\"c\"", "2"],
+                vec!["d\ne", "3"],
+                vec!["", "4"]
+            ]
+        );
+    }
+
+    #[test]
+    fn read_records_without_quoting() {
+        let options = DelimitedOptions::tsv();
+        assert_eq!(records("\"a\t1\n", &options), vec![vec!["\"a", "1"]]);
+    }
+
+    #[test]
+    fn read_fails_on_unterminated_quote() {
+        let mut reader = Cursor::new("\"a,1\n");
+        let mut fields = Vec::new();
+        assert!(read_record(&mut reader, &DelimitedOptions::csv(), &mut fields).is_err());
+    }
+
+    #[test]
+    fn read_csv_with_header() {
+        let embeds = read(
+            "word,x,y\n\"a, b\",3,4\nc,1,0\n",
+            &DelimitedOptions::csv().header(true),
+        );
+        assert_eq!(embeds.vocab().words(), &["a, b", "c"]);
+        assert!(embeds
+            .storage()
+            .view()
+            .abs_diff_eq(&normalized(array![[3., 4.], [1., 0.]]), 1e-6));
+        assert_eq!(embeds.norms().unwrap().view(), array![5., 1.]);
+    }
+
+    #[test]
+    fn read_tsv_word_last() {
+        let embeds = read(
+            "3\t4\ta\n 1 \t0\tc\n",
+            &DelimitedOptions::tsv().column_order(ColumnOrder::WordLast),
+        );
+        assert_eq!(embeds.vocab().words(), &["a", "c"]);
+        assert!(embeds
+            .storage()
+            .view()
+            .abs_diff_eq(&normalized(array![[3., 4.], [1., 0.]]), 1e-6));
+    }
+
+    #[test]
+    fn read_fails_on_invalid_records() {
+        for data in &["a,1,x\n", "a,1,2\nb,1\n", "a,1\na,2\n"] {
+            assert!(Embeddings::read_delimited(
+                &mut Cursor::new(data),
+                &DelimitedOptions::default()
+            )
+            .is_err());
+        }
+    }
+}
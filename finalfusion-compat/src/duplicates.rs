@@ -0,0 +1,202 @@
+//! Handling of duplicate words in embedding files.
+//!
+//! Some embedding files in the word2vec and text formats contain a
+//! word more than once. By default, the readers fail on such files.
+//! `DuplicatePolicy` can be set in the reader options to keep one of
+//! the embeddings of a duplicate word or to average them.
+
+use std::collections::HashMap;
+
+use finalfusion_core::embeddings::Embeddings;
+use finalfusion_core::storage::NdArray;
+use finalfusion_core::vocab::SimpleVocab;
+use finalfusion_core::warnings::{Warning, Warnings};
+use finalfusion_io::io::{Error, ErrorKind, Result};
+use ndarray::{Array2, Axis};
+
+/// Policy for words that occur more than once in an embedding file.
+///
+/// With every policy except `Error`, a duplicate word keeps the
+/// position of its first occurrence in the vocabulary and a
+/// `Warning::DuplicateWord` is added for each further occurrence.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum DuplicatePolicy {
+    /// Fail on duplicate words.
+    #[default]
+    Error,
+
+    /// Use the embedding of the first occurrence of a word.
+    KeepFirst,
+
+    /// Use the embedding of the last occurrence of a word.
+    KeepLast,
+
+    /// Use the average of the embeddings of all occurrences of a word.
+    Average,
+}
+
+/// Embeddings that are collected while reading an embedding file.
+pub(crate) struct UniqueRows {
+    policy: DuplicatePolicy,
+    indices: HashMap<String, usize>,
+    words: Vec<String>,
+    data: Vec<f32>,
+    counts: Vec<usize>,
+    dims: Option<usize>,
+    n_rows: usize,
+}
+
+impl UniqueRows {
+    /// Construct an empty collection of embeddings.
+    ///
+    /// If the shape of the embedding matrix is given, space is
+    /// reserved for the embeddings and every embedding must have the
+    /// given dimensionality. Otherwise, the dimensionality is that of
+    /// the first embedding.
+    pub fn new(policy: DuplicatePolicy, shape: Option<(usize, usize)>) -> Self {
+        let (words_len, dims) = shape.map(|(n, dims)| (n, Some(dims))).unwrap_or((0, None));
+
+        UniqueRows {
+            policy,
+            indices: HashMap::with_capacity(words_len),
+            words: Vec::with_capacity(words_len),
+            data: Vec::with_capacity(words_len * dims.unwrap_or(0)),
+            counts: Vec::new(),
+            dims,
+            n_rows: 0,
+        }
+    }
+
+    /// Get the number of embeddings that were added, including
+    /// duplicates.
+    pub fn n_rows(&self) -> usize {
+        self.n_rows
+    }
+
+    /// Get the dimensionality of the embeddings, if known.
+    pub fn dims(&self) -> Option<usize> {
+        self.dims
+    }
+
+    /// Add the embedding of a word.
+    pub fn push(&mut self, word: String, embedding: &[f32], warnings: &mut Warnings) -> Result<()> {
+        let dims = *self.dims.get_or_insert(embedding.len());
+        if embedding.len() != dims {
+            return Err(ErrorKind::Format(format!(
+                "Incorrect embedding dimensionality, expected: {}, got: {}",
+                dims,
+                embedding.len()
+            ))
+            .into());
+        }
+
+        self.n_rows += 1;
+
+        let idx = match self.indices.get(&word) {
+            Some(&idx) => idx,
+            None => {
+                self.indices.insert(word.clone(), self.words.len());
+                self.words.push(word);
+                self.data.extend_from_slice(embedding);
+                if self.policy == DuplicatePolicy::Average {
+                    self.counts.push(1);
+                }
+                return Ok(());
+            }
+        };
+
+        let row = &mut self.data[idx * dims..(idx + 1) * dims];
+        match self.policy {
+            DuplicatePolicy::Error => {
+                return Err(ErrorKind::Format(format!("Duplicate token: {}", word)).into())
+            }
+            DuplicatePolicy::KeepFirst => (),
+            DuplicatePolicy::KeepLast => row.copy_from_slice(embedding),
+            DuplicatePolicy::Average => {
+                row.iter_mut()
+                    .zip(embedding)
+                    .for_each(|(sum, &component)| *sum += component);
+                self.counts[idx] += 1;
+            }
+        }
+
+        warnings.push(Warning::DuplicateWord { word });
+
+        Ok(())
+    }
+
+    /// Construct embeddings from the collected embeddings.
+    pub fn into_embeddings(self) -> Result<Embeddings<SimpleVocab, NdArray>> {
+        let shape = (self.words.len(), self.dims.unwrap_or(0));
+        let mut matrix = Array2::from_shape_vec(shape, self.data).map_err(Error::Shape)?;
+
+        for (mut embedding, &count) in matrix.axis_iter_mut(Axis(0)).zip(&self.counts) {
+            if count > 1 {
+                embedding /= count as f32;
+            }
+        }
+
+        Ok(Embeddings::new_without_norms(
+            None,
+            SimpleVocab::new(self.words),
+            NdArray::new(matrix),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+
+    use super::{DuplicatePolicy, UniqueRows};
+    use finalfusion_core::storage::StorageView;
+    use finalfusion_core::vocab::Vocab;
+    use finalfusion_core::warnings::{Warning, Warnings};
+
+    fn read_duplicates(policy: DuplicatePolicy, warnings: &mut Warnings) -> UniqueRows {
+        let mut rows = UniqueRows::new(policy, None);
+        rows.push("a".to_owned(), &[1., 2.], warnings).unwrap();
+        rows.push("b".to_owned(), &[3., 4.], warnings).unwrap();
+        rows.push("a".to_owned(), &[5., 6.], warnings).unwrap();
+        rows
+    }
+
+    #[test]
+    fn duplicates_are_rejected_by_default() {
+        let mut rows = UniqueRows::new(DuplicatePolicy::default(), None);
+        let mut warnings = Warnings::new();
+        rows.push("a".to_owned(), &[1., 2.], &mut warnings).unwrap();
+        assert!(rows.push("a".to_owned(), &[3., 4.], &mut warnings).is_err());
+    }
+
+    #[test]
+    fn duplicates_are_resolved_by_policy() {
+        for (policy, check) in &[
+            (DuplicatePolicy::KeepFirst, array![[1., 2.], [3., 4.]]),
+            (DuplicatePolicy::KeepLast, array![[5., 6.], [3., 4.]]),
+            (DuplicatePolicy::Average, array![[3., 4.], [3., 4.]]),
+        ] {
+            let mut warnings = Warnings::new();
+            let rows = read_duplicates(*policy, &mut warnings);
+            assert_eq!(rows.n_rows(), 3);
+
+            let embeds = rows.into_embeddings().unwrap();
+            assert_eq!(embeds.vocab().words(), &["a", "b"]);
+            assert_eq!(embeds.storage().view(), check.view());
+            assert_eq!(
+                warnings.into_iter().collect::<Vec<_>>(),
+                vec![Warning::DuplicateWord {
+                    word: "a".to_owned()
+                }]
+            );
+        }
+    }
+
+    #[test]
+    fn embeddings_must_have_same_dimensionality() {
+        let mut rows = UniqueRows::new(DuplicatePolicy::KeepFirst, None);
+        let mut warnings = Warnings::new();
+        rows.push("a".to_owned(), &[1., 2.], &mut warnings).unwrap();
+        assert!(rows.push("b".to_owned(), &[3.], &mut warnings).is_err());
+    }
+}
@@ -0,0 +1,838 @@
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::io::{BufRead, Write};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use finalfusion_core::counts::WordCounts;
+use finalfusion_core::embeddings::Embeddings;
+use finalfusion_core::metadata::Metadata;
+use finalfusion_core::storage::{NdArray, Storage, StorageViewMut};
+use finalfusion_core::subword::BucketIndexer;
+use finalfusion_core::util::read_string_checked;
+use finalfusion_core::vocab::{FastTextSubwordVocab, SubwordIndices, Vocab};
+use finalfusion_core::warnings::{Warning, Warnings};
+use finalfusion_io::io::{Error, ErrorKind, Result};
+use ndarray::{Array2, ErrorKind as ShapeErrorKind, ShapeError};
+use serde::{Deserialize, Serialize};
+use toml::Value;
+
+use super::FastTextIndexer;
+use crate::compression::Decompress;
+use crate::norms::{normalize, NormsPolicy};
+
+const FASTTEXT_FILEFORMAT_MAGIC: u32 = 793_712_314;
+const FASTTEXT_VERSION: u32 = 12;
+
+/// Read embeddings in the fastText format.
+pub trait ReadFastText
+where
+    Self: Sized,
+{
+    /// Read embeddings in the fastText format.
+    fn read_fasttext(reader: &mut impl BufRead) -> Result<Self>;
+
+    /// Read embeddings in the fastText format lossily.
+    ///
+    /// In constrast to `read_fasttext`, this method does not fail
+    /// on reading tokens with invalid UTF-8 byte sequences. Invalid
+    /// UTF-8 sequences will be replaced by the unicode replacement
+    /// character.
+    fn read_fasttext_lossy(reader: &mut impl BufRead) -> Result<Self>;
+
+    /// Read embeddings in the fastText format.
+    ///
+    /// Non-fatal issues, such as replaced invalid UTF-8 when `lossy`
+    /// is `true` and embeddings that cannot be normalized, are added
+    /// to `warnings`.
+    fn read_fasttext_with_warnings(
+        reader: &mut impl BufRead,
+        lossy: bool,
+        warnings: &mut Warnings,
+    ) -> Result<Self>;
+
+    /// Read embeddings in the fastText format using the given options.
+    fn read_fasttext_with_options(
+        reader: &mut impl BufRead,
+        options: &FastTextOptions,
+    ) -> Result<Self> {
+        Self::read_fasttext_with_options_and_warnings(reader, options, &mut Warnings::new())
+    }
+
+    /// Read embeddings in the fastText format using the given
+    /// options, adding non-fatal issues to `warnings`.
+    fn read_fasttext_with_options_and_warnings(
+        reader: &mut impl BufRead,
+        options: &FastTextOptions,
+        warnings: &mut Warnings,
+    ) -> Result<Self>;
+}
+
+/// Options for reading fastText models.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct FastTextOptions {
+    /// Replace invalid UTF-8 in words by the replacement character,
+    /// rather than failing.
+    pub lossy: bool,
+
+    /// The policy for the norms of the word embeddings.
+    ///
+    /// Subword embeddings are never normalized.
+    pub norms: NormsPolicy,
+}
+
+impl FastTextOptions {
+    /// Replace invalid UTF-8 in words.
+    pub fn lossy(mut self, lossy: bool) -> Self {
+        self.lossy = lossy;
+        self
+    }
+
+    /// Set the policy for the norms of the word embeddings.
+    pub fn norms(mut self, policy: NormsPolicy) -> Self {
+        self.norms = policy;
+        self
+    }
+}
+
+impl ReadFastText for Embeddings<FastTextSubwordVocab, NdArray> {
+    fn read_fasttext(reader: &mut impl BufRead) -> Result<Self> {
+        Self::read_fasttext_with_options(reader, &FastTextOptions::default())
+    }
+
+    fn read_fasttext_lossy(reader: &mut impl BufRead) -> Result<Self> {
+        Self::read_fasttext_with_options(reader, &FastTextOptions::default().lossy(true))
+    }
+
+    fn read_fasttext_with_warnings(
+        reader: &mut impl BufRead,
+        lossy: bool,
+        warnings: &mut Warnings,
+    ) -> Result<Self> {
+        Self::read_fasttext_with_options_and_warnings(
+            reader,
+            &FastTextOptions::default().lossy(lossy),
+            warnings,
+        )
+    }
+
+    fn read_fasttext_with_options_and_warnings(
+        reader: &mut impl BufRead,
+        options: &FastTextOptions,
+        warnings: &mut Warnings,
+    ) -> Result<Self> {
+        let mut reader = Decompress::new(reader)?;
+
+        // The labels of supervised models are not used, only the
+        // input vectors are read.
+        let (config, vocab, counts, _) = read_model_header(&mut reader, options.lossy, warnings)?;
+
+        // Read and prepare storage.
+        let mut storage = read_matrix(&mut reader)?;
+        add_subword_embeddings(&vocab, &mut storage);
+
+        // Verify that vocab and storage shapes match.
+        if storage.shape().0 != vocab.words_len() + config.bucket as usize {
+            return Err(Error::Shape(ShapeError::from_kind(
+                ShapeErrorKind::IncompatibleShape,
+            )));
+        }
+
+        let metadata = Value::try_from(config).map_err(|e| {
+            ErrorKind::Format(format!("Cannot serialize model metadata to TOML: {}", e))
+        })?;
+
+        let mut embeddings = normalize(vocab, storage, options.norms, warnings);
+        embeddings.set_metadata(Some(Metadata::new(metadata)));
+        embeddings.set_counts(Some(counts));
+
+        Ok(embeddings)
+    }
+}
+
+/// Labels of a supervised fastText model with their counts.
+#[derive(Debug, Default)]
+pub(super) struct Labels {
+    pub labels: Vec<String>,
+    pub counts: Vec<u64>,
+}
+
+/// Read the header of a fastText model.
+///
+/// The header consists of the magic, version, configuration, and
+/// vocabulary. After reading the header, the reader is positioned
+/// at the input matrix.
+pub(super) fn read_model_header<R>(
+    reader: &mut R,
+    lossy: bool,
+    warnings: &mut Warnings,
+) -> Result<(Config, FastTextSubwordVocab, WordCounts, Labels)>
+where
+    R: BufRead,
+{
+    let magic = reader
+        .read_u32::<LittleEndian>()
+        .map_err(|e| ErrorKind::io_error("Cannot fastText read magic", e))?;
+    if magic != FASTTEXT_FILEFORMAT_MAGIC {
+        return Err(ErrorKind::Format(format!(
+            "Expected {} as magic, got: {}",
+            FASTTEXT_FILEFORMAT_MAGIC, magic
+        ))
+        .into());
+    }
+
+    let version = reader
+        .read_u32::<LittleEndian>()
+        .map_err(|e| ErrorKind::io_error("Cannot read fastText version", e))?;
+    if version > FASTTEXT_VERSION {
+        return Err(ErrorKind::Format(format!(
+            "Expected {} as version, got: {}",
+            FASTTEXT_VERSION, version
+        ))
+        .into());
+    }
+
+    let config = Config::read(reader)?;
+
+    let (vocab, counts, labels) = read_vocab(&config, reader, lossy, warnings)?;
+
+    Ok((config, vocab, counts, labels))
+}
+
+/// Read a non-quantized matrix, preceded by its quantization flag.
+pub(super) fn read_matrix<R>(reader: &mut R) -> Result<NdArray>
+where
+    R: BufRead,
+{
+    let is_quantized = reader
+        .read_u8()
+        .map_err(|e| ErrorKind::io_error("Cannot read quantization information", e))?;
+    if is_quantized == 1 {
+        return Err(ErrorKind::Format("Quantized fastText models are not supported".into()).into());
+    }
+
+    read_embeddings(reader)
+}
+
+/// Write embeddings in the fastText format.
+pub trait WriteFastText<W>
+where
+    W: Write,
+{
+    /// Write embeddings in the fastText format.
+    ///
+    /// fastText stores word embeddings without their subword
+    /// embeddings, so the subword embeddings are subtracted from the
+    /// unnormalized word embeddings. The model configuration is taken
+    /// from the metadata when the embeddings were read from a fastText
+    /// model and uses the fastText defaults otherwise. The output
+    /// matrix, which fastText only uses for training, is written as a
+    /// zero matrix.
+    fn write_fasttext(&self, write: &mut W) -> Result<()>;
+}
+
+impl<W, S> WriteFastText<W> for Embeddings<FastTextSubwordVocab, S>
+where
+    W: Write,
+    S: Storage,
+{
+    fn write_fasttext(&self, write: &mut W) -> Result<()> {
+        let vocab = self.vocab();
+        let (rows, dims) = self.storage().shape();
+        if rows != vocab.vocab_len() {
+            return Err(Error::Shape(ShapeError::from_kind(
+                ShapeErrorKind::IncompatibleShape,
+            )));
+        }
+
+        write
+            .write_u32::<LittleEndian>(FASTTEXT_FILEFORMAT_MAGIC)
+            .map_err(|e| ErrorKind::io_error("Cannot write fastText magic", e))?;
+        write
+            .write_u32::<LittleEndian>(FASTTEXT_VERSION)
+            .map_err(|e| ErrorKind::io_error("Cannot write fastText version", e))?;
+
+        Config::from_embeddings(self)?.write(write)?;
+
+        write_vocab(write, vocab, self.counts())?;
+
+        write
+            .write_u8(0)
+            .map_err(|e| ErrorKind::io_error("Cannot write quantization information", e))?;
+        write_matrix_shape(write, rows, dims)?;
+        for (word, idx) in vocab.iter() {
+            let mut embed = self.storage().embedding(idx).into_owned();
+            if let Some(norms) = self.norms() {
+                embed *= norms[idx];
+            }
+
+            // Invert the averaging of add_subword_embeddings.
+            if let Some(indices) = vocab.subword_indices(&word) {
+                embed *= (indices.len() + 1) as f32;
+                for subword_idx in indices {
+                    embed -= &self.storage().embedding(subword_idx).view();
+                }
+            }
+
+            write_embedding(write, embed.iter().cloned())?;
+        }
+        for idx in vocab.words_len()..rows {
+            write_embedding(write, self.storage().embedding(idx).iter().cloned())?;
+        }
+
+        write
+            .write_u8(0)
+            .map_err(|e| ErrorKind::io_error("Cannot write output quantization information", e))?;
+        write_matrix_shape(write, vocab.words_len(), dims)?;
+        for _ in 0..vocab.words_len() {
+            write_embedding(write, (0..dims).map(|_| 0.))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// fastText model configuration.
+#[derive(Copy, Clone, Debug, Deserialize, Serialize)]
+pub(super) struct Config {
+    pub dims: u32,
+    pub window_size: u32,
+    pub epoch: u32,
+    pub min_count: u32,
+    pub neg: u32,
+    pub word_ngrams: u32,
+    pub loss: Loss,
+    pub model: Model,
+    pub bucket: u32,
+    pub min_n: u32,
+    pub max_n: u32,
+    pub lr_update_rate: u32,
+    pub sampling_threshold: f64,
+}
+
+impl Config {
+    /// Read fastText model configuration.
+    fn read<R>(reader: &mut R) -> Result<Config>
+    where
+        R: BufRead,
+    {
+        let dims = reader
+            .read_u32::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read number of dimensions", e))?;
+        let window_size = reader
+            .read_u32::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read window size", e))?;
+        let epoch = reader
+            .read_u32::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read number of epochs", e))?;
+        let min_count = reader
+            .read_u32::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read minimum count", e))?;
+        let neg = reader
+            .read_u32::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read negative samples", e))?;
+        let word_ngrams = reader
+            .read_u32::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read word n-gram length", e))?;
+        let loss = Loss::read(reader)?;
+        let model = Model::read(reader)?;
+        let bucket = reader
+            .read_u32::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read number of buckets", e))?;
+        let min_n = reader
+            .read_u32::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read minimum subword length", e))?;
+        let max_n = reader
+            .read_u32::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read maximum subword length", e))?;
+        let lr_update_rate = reader
+            .read_u32::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read LR update rate", e))?;
+        let sampling_threshold = reader
+            .read_f64::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read sampling threshold", e))?;
+
+        Ok(Config {
+            dims,
+            window_size,
+            epoch,
+            min_count,
+            neg,
+            word_ngrams,
+            loss,
+            model,
+            bucket,
+            min_n,
+            max_n,
+            lr_update_rate,
+            sampling_threshold,
+        })
+    }
+
+    /// Get the configuration of a fastText model for embeddings.
+    ///
+    /// The training hyperparameters are read from the metadata if it
+    /// is a fastText configuration. The remaining fields are derived
+    /// from the embeddings.
+    fn from_embeddings<S>(embeddings: &Embeddings<FastTextSubwordVocab, S>) -> Result<Config>
+    where
+        S: Storage,
+    {
+        let vocab = embeddings.vocab();
+        let config = embeddings
+            .metadata()
+            .and_then(|metadata| Value::clone(metadata).try_into::<Config>().ok());
+
+        let to_u32 = |value: usize, name: &str| {
+            u32::try_from(value).map_err(|_| {
+                Error::from(ErrorKind::Format(format!(
+                    "Number of {} exceeds the fastText maximum: {}",
+                    name, value
+                )))
+            })
+        };
+
+        let dims = to_u32(embeddings.dims(), "dimensions")?;
+        let bucket = to_u32(vocab.indexer().buckets(), "buckets")?;
+        let (min_n, max_n) = (vocab.min_n(), vocab.max_n());
+
+        Ok(match config {
+            Some(config) => Config {
+                dims,
+                bucket,
+                min_n,
+                max_n,
+                ..config
+            },
+            None => Config {
+                dims,
+                window_size: 5,
+                epoch: 5,
+                min_count: 5,
+                neg: 5,
+                word_ngrams: 1,
+                loss: Loss::NegativeSampling,
+                model: Model::SkipGram,
+                bucket,
+                min_n,
+                max_n,
+                lr_update_rate: 100,
+                sampling_threshold: 1e-4,
+            },
+        })
+    }
+
+    /// Write fastText model configuration.
+    fn write<W>(&self, write: &mut W) -> Result<()>
+    where
+        W: Write,
+    {
+        let fields = [
+            (self.dims, "number of dimensions"),
+            (self.window_size, "window size"),
+            (self.epoch, "number of epochs"),
+            (self.min_count, "minimum count"),
+            (self.neg, "negative samples"),
+            (self.word_ngrams, "word n-gram length"),
+            (self.loss as u32 + 1, "loss type"),
+            (self.model as u32 + 1, "model type"),
+            (self.bucket, "number of buckets"),
+            (self.min_n, "minimum subword length"),
+            (self.max_n, "maximum subword length"),
+            (self.lr_update_rate, "LR update rate"),
+        ];
+        for &(value, name) in &fields {
+            write
+                .write_u32::<LittleEndian>(value)
+                .map_err(|e| ErrorKind::io_error(format!("Cannot write {}", name), e))?;
+        }
+        write
+            .write_f64::<LittleEndian>(self.sampling_threshold)
+            .map_err(|e| ErrorKind::io_error("Cannot write sampling threshold", e))?;
+
+        Ok(())
+    }
+}
+
+/// fastText loss type.
+///
+/// The discriminants are one less than the fastText loss identifiers.
+#[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub(super) enum Loss {
+    HierarchicalSoftmax,
+    NegativeSampling,
+    Softmax,
+    OneVsAll,
+}
+
+impl Loss {
+    fn read<R>(reader: &mut R) -> Result<Loss>
+    where
+        R: BufRead,
+    {
+        let loss = reader
+            .read_u32::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read loss type", e))?;
+
+        use self::Loss::*;
+        match loss {
+            1 => Ok(HierarchicalSoftmax),
+            2 => Ok(NegativeSampling),
+            3 => Ok(Softmax),
+            4 => Ok(OneVsAll),
+            l => Err(ErrorKind::Format(format!("Unknown loss: {}", l)).into()),
+        }
+    }
+}
+
+/// fastText model type.
+///
+/// The discriminants are one less than the fastText model identifiers.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub(super) enum Model {
+    CBOW,
+    SkipGram,
+    Supervised,
+}
+
+impl Model {
+    fn read<R>(reader: &mut R) -> Result<Model>
+    where
+        R: BufRead,
+    {
+        let model = reader
+            .read_u32::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read model type", e))?;
+
+        use self::Model::*;
+        match model {
+            1 => Ok(CBOW),
+            2 => Ok(SkipGram),
+            3 => Ok(Supervised),
+            m => Err(ErrorKind::Format(format!("Unknown model: {}", m)).into()),
+        }
+    }
+}
+
+/// Add subword embeddings to word embeddings.
+///
+/// fastText stores word embeddings without subword embeddings. This method
+/// adds the subword embeddings.
+pub(super) fn add_subword_embeddings(vocab: &FastTextSubwordVocab, embeds: &mut NdArray) {
+    for (word, idx) in vocab.iter() {
+        if let Some(indices) = vocab.subword_indices(&word) {
+            let n_embeds = indices.len() + 1;
+
+            // Sum the embedding and its subword embeddings.
+            let mut embed = embeds.embedding(idx).into_owned();
+            for subword_idx in indices {
+                embed += &embeds.embedding(subword_idx).view();
+            }
+
+            // Compute the average embedding.
+            embed /= n_embeds as f32;
+
+            embeds.view_mut().row_mut(idx).assign(&embed);
+        }
+    }
+}
+
+/// Read the embedding matrix.
+fn read_embeddings<R>(reader: &mut R) -> Result<NdArray>
+where
+    R: BufRead,
+{
+    let m = reader
+        .read_u64::<LittleEndian>()
+        .map_err(|e| ErrorKind::io_error("Cannot read number of embedding matrix rows", e))?;
+    let n = reader
+        .read_u64::<LittleEndian>()
+        .map_err(|e| ErrorKind::io_error("Cannot read number of embedding matrix columns", e))?;
+
+    let mut data = vec![0.0; (m * n) as usize];
+    reader
+        .read_f32_into::<LittleEndian>(&mut data)
+        .map_err(|e| ErrorKind::io_error("Cannot read embeddings", e))?;
+
+    let data = Array2::from_shape_vec((m as usize, n as usize), data).map_err(Error::Shape)?;
+
+    Ok(NdArray::new(data))
+}
+
+/// Write the shape of an embedding matrix.
+fn write_matrix_shape<W>(write: &mut W, rows: usize, cols: usize) -> Result<()>
+where
+    W: Write,
+{
+    write
+        .write_u64::<LittleEndian>(rows as u64)
+        .map_err(|e| ErrorKind::io_error("Cannot write number of embedding matrix rows", e))?;
+    write
+        .write_u64::<LittleEndian>(cols as u64)
+        .map_err(|e| ErrorKind::io_error("Cannot write number of embedding matrix columns", e))?;
+
+    Ok(())
+}
+
+/// Write an embedding.
+fn write_embedding<W>(write: &mut W, embedding: impl Iterator<Item = f32>) -> Result<()>
+where
+    W: Write,
+{
+    for component in embedding {
+        write
+            .write_f32::<LittleEndian>(component)
+            .map_err(|e| ErrorKind::io_error("Cannot write embedding component", e))?;
+    }
+
+    Ok(())
+}
+
+/// Write the vocabulary.
+///
+/// Words without a count are written with count 1.
+fn write_vocab<W>(
+    write: &mut W,
+    vocab: &FastTextSubwordVocab,
+    counts: Option<&WordCounts>,
+) -> Result<()>
+where
+    W: Write,
+{
+    let size = u32::try_from(vocab.words_len()).map_err(|_| {
+        ErrorKind::Format(format!(
+            "Vocabulary size exceeds the fastText maximum: {}",
+            vocab.words_len()
+        ))
+    })?;
+    let count = |idx: usize| counts.map(|counts| counts[idx]).unwrap_or(1);
+
+    write
+        .write_u32::<LittleEndian>(size)
+        .map_err(|e| ErrorKind::io_error("Cannot write vocabulary size", e))?;
+    write
+        .write_u32::<LittleEndian>(size)
+        .map_err(|e| ErrorKind::io_error("Cannot write number of words", e))?;
+    write
+        .write_u32::<LittleEndian>(0)
+        .map_err(|e| ErrorKind::io_error("Cannot write number of labels", e))?;
+    write
+        .write_u64::<LittleEndian>((0..vocab.words_len()).map(count).sum())
+        .map_err(|e| ErrorKind::io_error("Cannot write number of tokens", e))?;
+    write
+        .write_i64::<LittleEndian>(-1)
+        .map_err(|e| ErrorKind::io_error("Cannot write pruned vocabulary size", e))?;
+
+    for (word, idx) in vocab.iter() {
+        write
+            .write_all(word.as_bytes())
+            .and_then(|_| write.write_u8(0))
+            .map_err(|e| ErrorKind::io_error("Cannot write word", e))?;
+        write
+            .write_u64::<LittleEndian>(count(idx))
+            .map_err(|e| ErrorKind::io_error("Cannot write word frequency", e))?;
+        write
+            .write_u8(0)
+            .map_err(|e| ErrorKind::io_error("Cannot write entry type", e))?;
+    }
+
+    Ok(())
+}
+
+/// Read the vocabulary.
+fn read_vocab<R>(
+    config: &Config,
+    reader: &mut R,
+    lossy: bool,
+    warnings: &mut Warnings,
+) -> Result<(FastTextSubwordVocab, WordCounts, Labels)>
+where
+    R: BufRead,
+{
+    let size = reader
+        .read_u32::<LittleEndian>()
+        .map_err(|e| ErrorKind::io_error("Cannot read vocabulary size", e))?;
+    let n_words = reader
+        .read_u32::<LittleEndian>()
+        .map_err(|e| ErrorKind::io_error("Cannot read number of words", e))?;
+    let n_labels = reader
+        .read_u32::<LittleEndian>()
+        .map_err(|e| ErrorKind::io_error("Cannot number of labels", e))?;
+    if u64::from(n_words) + u64::from(n_labels) != u64::from(size) {
+        return Err(ErrorKind::Format(format!(
+            "Vocabulary size {} is not the sum of the number of words {} and labels {}",
+            size, n_words, n_labels
+        ))
+        .into());
+    }
+
+    reader
+        .read_u64::<LittleEndian>()
+        .map_err(|e| ErrorKind::io_error("Cannot read number of tokens", e))?;
+
+    let prune_idx_size = reader
+        .read_i64::<LittleEndian>()
+        .map_err(|e| ErrorKind::io_error("Cannot read pruned vocabulary size", e))?;
+    if prune_idx_size > 0 {
+        return Err(ErrorKind::Format("Pruned vocabularies are not supported".into()).into());
+    }
+
+    let mut words = Vec::with_capacity(n_words as usize);
+    let mut counts = Vec::with_capacity(n_words as usize);
+    let mut labels = Labels::default();
+    // Lossy decoding can map different tokens to the same word.
+    let mut unique = HashSet::with_capacity(size as usize);
+    for _ in 0..size {
+        let (word, replaced) = read_string_checked(reader, 0, lossy)?;
+        if replaced {
+            warnings.push(Warning::InvalidUtf8 { word: word.clone() });
+        }
+        if !unique.insert(word.clone()) {
+            return Err(ErrorKind::Format(format!("Duplicate token: {}", word)).into());
+        }
+        let count = reader
+            .read_u64::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read word frequency", e))?;
+        let entry_type = reader
+            .read_u8()
+            .map_err(|e| ErrorKind::io_error("Cannot read entry type", e))?;
+        match entry_type {
+            0 => {
+                words.push(word);
+                counts.push(count);
+            }
+            1 => {
+                labels.labels.push(word);
+                labels.counts.push(count);
+            }
+            entry_type => {
+                return Err(ErrorKind::Format(format!("Unknown entry type: {}", entry_type)).into())
+            }
+        }
+    }
+
+    if words.len() != n_words as usize {
+        return Err(ErrorKind::Format(format!(
+            "Expected {} words, vocabulary contains {} words",
+            n_words,
+            words.len()
+        ))
+        .into());
+    }
+
+    let vocab = FastTextSubwordVocab::new(
+        words,
+        config.min_n,
+        config.max_n,
+        FastTextIndexer::new(config.bucket as usize),
+    );
+
+    Ok((vocab, WordCounts::new(counts), labels))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::{BufReader, Cursor};
+
+    use approx::{assert_abs_diff_eq, AbsDiffEq};
+
+    use super::{FastTextOptions, ReadFastText, WriteFastText};
+    use crate::norms::NormsPolicy;
+    use finalfusion_core::embeddings::Embeddings;
+    use finalfusion_core::similarity::WordSimilarity;
+    use finalfusion_core::storage::StorageView;
+    use finalfusion_core::vocab::Vocab;
+
+    #[test]
+    fn test_read_fasttext() {
+        let f = File::open("../testdata/fasttext.bin").unwrap();
+        let mut reader = BufReader::new(f);
+        let embeddings = Embeddings::read_fasttext(&mut reader).unwrap();
+        let results = embeddings.word_similarity("über", 3).unwrap();
+        assert_eq!(results[0].word, "auf");
+        assert_abs_diff_eq!(*results[0].similarity, 0.568513, epsilon = 1e-6);
+        assert_eq!(results[1].word, "vor");
+        assert_abs_diff_eq!(*results[1].similarity, 0.551551, epsilon = 1e-6);
+        assert_eq!(results[2].word, "durch");
+        assert_abs_diff_eq!(*results[2].similarity, 0.547349, epsilon = 1e-6);
+
+        // fastText sorts the vocabulary by frequency.
+        let counts = embeddings.counts().unwrap();
+        assert_eq!(counts.len(), embeddings.len());
+        assert!(counts.windows(2).all(|pair| pair[0] >= pair[1]));
+        assert!(counts[counts.len() - 1] > 0);
+    }
+
+    #[test]
+    fn test_read_fasttext_unnormalized() {
+        let mut reader = BufReader::new(File::open("../testdata/fasttext.bin").unwrap());
+        let embeddings = Embeddings::read_fasttext(&mut reader).unwrap();
+
+        let mut reader = BufReader::new(File::open("../testdata/fasttext.bin").unwrap());
+        let options = FastTextOptions::default().norms(NormsPolicy::Unnormalized);
+        let unnormalized = Embeddings::read_fasttext_with_options(&mut reader, &options).unwrap();
+        assert!(unnormalized.norms().is_none());
+
+        for (word, idx) in embeddings.vocab().iter() {
+            let embedding = embeddings.embedding_with_norm(&word).unwrap();
+            assert!(unnormalized
+                .storage()
+                .view()
+                .row(idx)
+                .abs_diff_eq(&embedding.into_unnormalized(), 1e-5));
+        }
+
+        // Subword embeddings are not normalized in either case.
+        let n_words = embeddings.vocab().words_len();
+        assert_eq!(
+            unnormalized
+                .storage()
+                .view()
+                .slice(ndarray::s![n_words.., ..]),
+            embeddings
+                .storage()
+                .view()
+                .slice(ndarray::s![n_words.., ..])
+        );
+    }
+
+    #[test]
+    fn test_read_fasttext_unknown() {
+        let f = File::open("../testdata/fasttext.bin").unwrap();
+        let mut reader = BufReader::new(f);
+        let embeddings = Embeddings::read_fasttext(&mut reader).unwrap();
+        let results = embeddings.word_similarity("unknown", 3).unwrap();
+        assert_eq!(results[0].word, "einer");
+        assert_abs_diff_eq!(*results[0].similarity, 0.691177, epsilon = 1e-6);
+        assert_eq!(results[1].word, "und");
+        assert_abs_diff_eq!(*results[1].similarity, 0.576449, epsilon = 1e-6);
+        assert_eq!(results[2].word, "des");
+        assert_abs_diff_eq!(*results[2].similarity, 0.570398, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn test_write_fasttext_roundtrip() {
+        let f = File::open("../testdata/fasttext.bin").unwrap();
+        let mut reader = BufReader::new(f);
+        let check_embeddings = Embeddings::read_fasttext(&mut reader).unwrap();
+
+        let mut data = Vec::new();
+        check_embeddings.write_fasttext(&mut data).unwrap();
+        let embeddings = Embeddings::read_fasttext(&mut Cursor::new(data)).unwrap();
+
+        assert_eq!(embeddings.vocab(), check_embeddings.vocab());
+        assert_eq!(embeddings.counts(), check_embeddings.counts());
+        assert_eq!(embeddings.metadata(), check_embeddings.metadata());
+        assert!(embeddings
+            .norms()
+            .unwrap()
+            .abs_diff_eq(check_embeddings.norms().unwrap(), 1e-4));
+        for word in &["über", "zwei", "unknown"] {
+            assert!(embeddings
+                .embedding(word)
+                .unwrap()
+                .abs_diff_eq(&check_embeddings.embedding(word).unwrap(), 1e-5));
+        }
+    }
+}
@@ -0,0 +1,45 @@
+//! Reader and writer for the fastText format.
+//!
+//! This module provides support for reading and writing
+//! non-quantized/pruned fastText embeddings. Embeddings in the
+//! fastText format are read as follows:
+//!
+//! ```
+//! use std::fs::File;
+//! use std::io::BufReader;
+//!
+//! use finalfusion_compat::fasttext::ReadFastText;
+//! use finalfusion_core::embeddings::Embeddings;
+//!
+//! let mut reader = BufReader::new(File::open("../testdata/fasttext.bin").unwrap());
+//!
+//! // Read the embeddings.
+//! let embeddings = Embeddings::read_fasttext(&mut reader)
+//!     .unwrap();
+//!
+//! // Look up an embedding.
+//! let embedding = embeddings.embedding("zwei");
+//! ```
+//!
+//! Embeddings with a `FastTextSubwordVocab` can be written with
+//! `WriteFastText`, so that they can be loaded by fastText itself.
+//!
+//! `ReadFastTextPair` reads a model together with the `.vec` file
+//! that fastText writes next to it, reporting discrepancies between
+//! the two files.
+//!
+//! Supervised fastText models are read as embeddings of their input
+//! vectors. `FastTextClassifier` reads the full supervised model,
+//! including the labels and the output matrix, to predict the labels
+//! of a text.
+
+pub use finalfusion_core::subword::FastTextIndexer;
+
+mod io;
+pub use self::io::{FastTextOptions, ReadFastText, WriteFastText};
+
+mod pair;
+pub use self::pair::ReadFastTextPair;
+
+mod supervised;
+pub use self::supervised::{FastTextClassifier, Prediction};
@@ -0,0 +1,278 @@
+use std::collections::HashSet;
+use std::io::BufRead;
+
+use finalfusion_core::embeddings::Embeddings;
+use finalfusion_core::storage::{NdArray, Storage};
+use finalfusion_core::vocab::{FastTextSubwordVocab, Vocab, WordIndex};
+use finalfusion_core::warnings::{Warning, Warnings};
+use finalfusion_io::io::{ErrorKind, Result};
+
+use super::ReadFastText;
+use crate::duplicates::DuplicatePolicy;
+use crate::text::{read_text_dims_unchecked, TextOptions, Validation};
+
+/// Relative tolerance for comparing `.vec` vectors to model embeddings.
+///
+/// fastText writes `.vec` files with six significant digits.
+const VEC_TOLERANCE: f32 = 1e-4;
+
+/// Read a fastText model together with its `.vec` file.
+///
+/// fastText writes the word vectors of a model to a `.vec` file next
+/// to the binary `.bin` model. The embeddings are read from the
+/// `.bin` model, since it also contains the subword embeddings. The
+/// `.vec` file is used to check that the files belong together and
+/// that neither was truncated or corrupted.
+pub trait ReadFastTextPair
+where
+    Self: Sized,
+{
+    /// Read a fastText model and check it against its `.vec` file.
+    ///
+    /// Fails when the files do not agree.
+    fn read_fasttext_pair(bin: &mut impl BufRead, vec: &mut impl BufRead) -> Result<Self>;
+
+    /// Read a fastText model and check it against its `.vec` file.
+    ///
+    /// Only fails when the embeddings of the files have a different
+    /// dimensionality. Other discrepancies are added to `warnings`:
+    ///
+    /// * `Warning::MissingVector` for words of the model that are
+    ///   not in the `.vec` file, e.g. because it was truncated.
+    /// * `Warning::UnexpectedVector` for words in the `.vec` file
+    ///   that are not in the model.
+    /// * `Warning::VectorMismatch` for words of which the vector in
+    ///   the `.vec` file differs from the embedding in the model.
+    /// * `Warning::MalformedLine` for lines of the `.vec` file that
+    ///   cannot be parsed and for a vocabulary size on the first line
+    ///   that differs from that of the model.
+    fn read_fasttext_pair_with_warnings(
+        bin: &mut impl BufRead,
+        vec: &mut impl BufRead,
+        warnings: &mut Warnings,
+    ) -> Result<Self>;
+}
+
+impl ReadFastTextPair for Embeddings<FastTextSubwordVocab, NdArray> {
+    fn read_fasttext_pair(bin: &mut impl BufRead, vec: &mut impl BufRead) -> Result<Self> {
+        let mut warnings = Warnings::new();
+        let embeds = Self::read_fasttext_pair_with_warnings(bin, vec, &mut warnings)?;
+
+        let mut discrepancies = warnings.iter().filter(|warning| is_discrepancy(warning));
+        if let Some(first) = discrepancies.next() {
+            return Err(ErrorKind::Format(format!(
+                "fastText model and .vec file do not agree, {} discrepancies, first: {}",
+                discrepancies.count() + 1,
+                first
+            ))
+            .into());
+        }
+
+        Ok(embeds)
+    }
+
+    fn read_fasttext_pair_with_warnings(
+        bin: &mut impl BufRead,
+        vec: &mut impl BufRead,
+        warnings: &mut Warnings,
+    ) -> Result<Self> {
+        let embeds = Self::read_fasttext_with_warnings(bin, false, warnings)?;
+
+        let options = TextOptions::default()
+            .duplicates(DuplicatePolicy::KeepFirst)
+            .validation(Validation::Recover);
+        let ((n_words, dims), vec_embeds) = read_text_dims_unchecked(vec, &options, warnings)?;
+
+        if dims != embeds.dims() {
+            return Err(ErrorKind::Format(format!(
+                "Dimensionality of the fastText model ({}) and .vec file ({}) do not agree",
+                embeds.dims(),
+                dims
+            ))
+            .into());
+        }
+
+        if n_words != embeds.vocab().words_len() {
+            warnings.push(Warning::MalformedLine {
+                line: 1,
+                reason: format!(
+                    "Incorrect vocabulary size, expected: {}, got: {}",
+                    embeds.vocab().words_len(),
+                    n_words
+                ),
+            });
+        }
+
+        check_vectors(&embeds, &vec_embeds, warnings);
+
+        Ok(embeds)
+    }
+}
+
+/// Check the vectors of a `.vec` file against the model embeddings.
+fn check_vectors<V, S>(
+    embeds: &Embeddings<FastTextSubwordVocab, NdArray>,
+    vec_embeds: &Embeddings<V, S>,
+    warnings: &mut Warnings,
+) where
+    V: Vocab,
+    S: Storage,
+{
+    let vec_words = vec_embeds
+        .vocab()
+        .iter()
+        .map(|(word, _)| word)
+        .collect::<HashSet<_>>();
+    for (word, _) in embeds.vocab().iter() {
+        if !vec_words.contains(&word) {
+            warnings.push(Warning::MissingVector {
+                word: word.into_owned(),
+            });
+        }
+    }
+
+    for (word, vec_idx) in vec_embeds.vocab().iter() {
+        let idx = match embeds.vocab().idx(&word) {
+            Some(WordIndex::Word(idx)) => idx,
+            _ => {
+                warnings.push(Warning::UnexpectedVector {
+                    word: word.into_owned(),
+                });
+                continue;
+            }
+        };
+
+        let norm = embeds.norms().map(|norms| norms[idx]).unwrap_or(1.);
+        let embed = embeds.storage().embedding(idx);
+        let matches = embed
+            .iter()
+            .zip(vec_embeds.storage().embedding(vec_idx).iter())
+            .map(|(&v, &vec_v)| (v * norm, vec_v))
+            .all(|(v, vec_v)| (v - vec_v).abs() <= VEC_TOLERANCE * v.abs().max(1.));
+        if !matches {
+            warnings.push(Warning::VectorMismatch {
+                word: word.into_owned(),
+            });
+        }
+    }
+}
+
+fn is_discrepancy(warning: &Warning) -> bool {
+    matches!(
+        warning,
+        Warning::MalformedLine { .. }
+            | Warning::DuplicateWord { .. }
+            | Warning::MissingVector { .. }
+            | Warning::UnexpectedVector { .. }
+            | Warning::VectorMismatch { .. }
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::{BufReader, Cursor};
+
+    use crate::fasttext::ReadFastText;
+    use crate::text::WriteTextDims;
+    use finalfusion_core::embeddings::Embeddings;
+    use finalfusion_core::storage::NdArray;
+    use finalfusion_core::vocab::{FastTextSubwordVocab, Vocab};
+    use finalfusion_core::warnings::{Warning, Warnings};
+
+    use super::ReadFastTextPair;
+
+    fn bin_reader() -> BufReader<File> {
+        BufReader::new(File::open("../testdata/fasttext.bin").unwrap())
+    }
+
+    fn vec_lines() -> Vec<String> {
+        let embeds: Embeddings<FastTextSubwordVocab, NdArray> =
+            Embeddings::read_fasttext(&mut bin_reader()).unwrap();
+        let mut data = Vec::new();
+        embeds.write_text_dims(&mut data, true).unwrap();
+        String::from_utf8(data)
+            .unwrap()
+            .lines()
+            .map(ToOwned::to_owned)
+            .collect()
+    }
+
+    fn read_with_warnings(lines: &[String]) -> Warnings {
+        let mut warnings = Warnings::new();
+        Embeddings::<FastTextSubwordVocab, NdArray>::read_fasttext_pair_with_warnings(
+            &mut bin_reader(),
+            &mut Cursor::new(lines.join("\n")),
+            &mut warnings,
+        )
+        .unwrap();
+        warnings
+    }
+
+    #[test]
+    fn read_matching_pair() {
+        let lines = vec_lines();
+        let embeds: Embeddings<FastTextSubwordVocab, NdArray> =
+            Embeddings::read_fasttext_pair(&mut bin_reader(), &mut Cursor::new(lines.join("\n")))
+                .unwrap();
+        assert_eq!(embeds.vocab().words_len(), lines.len() - 1);
+    }
+
+    #[test]
+    fn read_pair_reports_truncated_vec() {
+        let mut lines = vec_lines();
+        let last = lines.pop().unwrap();
+        let word = last.split(' ').next().unwrap().to_owned();
+        lines.push(last[..last.len() / 2].to_owned());
+
+        let warnings = read_with_warnings(&lines);
+        let warnings = warnings.into_iter().collect::<Vec<_>>();
+        assert_eq!(warnings.len(), 2);
+        assert!(matches!(warnings[0], Warning::MalformedLine { line, .. } if line == lines.len()));
+        assert_eq!(warnings[1], Warning::MissingVector { word });
+
+        assert!(
+            Embeddings::<FastTextSubwordVocab, NdArray>::read_fasttext_pair(
+                &mut bin_reader(),
+                &mut Cursor::new(lines.join("\n"))
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn read_pair_reports_mismatched_vectors() {
+        let mut lines = vec_lines();
+        let dims = lines[1].split(' ').count() - 1;
+        lines[1] = format!(
+            "{} {}",
+            lines[1].split(' ').next().unwrap(),
+            vec!["1"; dims].join(" ")
+        );
+        lines.push(format!("unknown {}", vec!["1"; dims].join(" ")));
+
+        let word = lines[1].split(' ').next().unwrap().to_owned();
+        assert_eq!(
+            read_with_warnings(&lines).into_iter().collect::<Vec<_>>(),
+            vec![
+                Warning::VectorMismatch { word },
+                Warning::UnexpectedVector {
+                    word: "unknown".to_owned()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn read_pair_fails_on_dims_mismatch() {
+        let lines = ["1 2".to_owned(), "a 1 2".to_owned()];
+        assert!(
+            Embeddings::<FastTextSubwordVocab, NdArray>::read_fasttext_pair_with_warnings(
+                &mut bin_reader(),
+                &mut Cursor::new(lines.join("\n")),
+                &mut Warnings::new()
+            )
+            .is_err()
+        );
+    }
+}
@@ -0,0 +1,578 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::iter;
+
+use finalfusion_core::counts::WordCounts;
+use finalfusion_core::embeddings::Embeddings;
+use finalfusion_core::norms::NdNorms;
+use finalfusion_core::storage::{NdArray, Storage, StorageView, StorageViewMut};
+use finalfusion_core::subword::fasttext_hash;
+use finalfusion_core::util::l2_normalize_array;
+use finalfusion_core::vocab::{FastTextSubwordVocab, SubwordIndices, Vocab, WordIndex};
+use finalfusion_core::warnings::Warnings;
+use finalfusion_io::io::{ErrorKind, Result};
+use ndarray::{s, Array1, ArrayView1, Axis};
+
+use super::io::{add_subword_embeddings, read_matrix, read_model_header, Loss, Model};
+use super::{FastTextOptions, ReadFastText};
+use crate::compression::Decompress;
+
+/// End-of-sentence token, which fastText adds to every line.
+const EOS: &str = "</s>";
+
+/// Default fastText label prefix.
+///
+/// The label prefix is not stored in fastText models, so tokens with
+/// the default prefix are treated as labels.
+const LABEL_PREFIX: &str = "__label__";
+
+/// Multiplier of the fastText word n-gram hash.
+const WORD_NGRAM_HASH_MULTIPLIER: u64 = 116_049_371;
+
+const SIGMOID_TABLE_SIZE: usize = 512;
+
+const MAX_SIGMOID: f32 = 8.;
+
+/// A label that was predicted by a fastText classifier.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Prediction<'a> {
+    pub label: &'a str,
+    pub probability: f32,
+}
+
+/// Supervised fastText model.
+///
+/// A supervised fastText model classifies text. The model consists of
+/// input vectors for words, subwords, and word n-grams and an output
+/// matrix that maps the average input vector of a text to labels.
+///
+/// ```
+/// use std::fs::File;
+/// use std::io::BufReader;
+///
+/// use finalfusion_compat::fasttext::{FastTextClassifier, ReadFastText};
+///
+/// let mut reader = BufReader::new(File::open("../testdata/fasttext-supervised.bin").unwrap());
+/// let classifier = FastTextClassifier::read_fasttext(&mut reader).unwrap();
+///
+/// // Predict the most probable label.
+/// let predictions = classifier.predict("good movie", 1, 0.);
+/// assert_eq!(predictions[0].label, "__label__pos");
+/// ```
+#[derive(Clone, Debug)]
+pub struct FastTextClassifier {
+    vocab: FastTextSubwordVocab,
+    counts: WordCounts,
+    input: NdArray,
+    labels: Vec<String>,
+    label_indices: HashMap<String, usize>,
+    label_counts: Vec<u64>,
+    output: NdArray,
+    loss: Loss,
+    tree: Vec<(usize, usize)>,
+    word_ngrams: usize,
+    buckets: usize,
+}
+
+impl FastTextClassifier {
+    /// Get the labels in model order.
+    pub fn labels(&self) -> &[String] {
+        &self.labels
+    }
+
+    /// Get the training counts of the labels.
+    pub fn label_counts(&self) -> &[u64] {
+        &self.label_counts
+    }
+
+    /// Get the output embedding of a label.
+    ///
+    /// Returns `None` if the label is unknown. Models that are trained
+    /// with the hierarchical softmax loss do not have label embeddings,
+    /// since their output matrix contains the embeddings of the inner
+    /// nodes of the label tree.
+    pub fn label_embedding(&self, label: &str) -> Option<ArrayView1<'_, f32>> {
+        if self.loss == Loss::HierarchicalSoftmax {
+            return None;
+        }
+
+        let idx = *self.label_indices.get(label)?;
+        Some(self.output.view().index_axis_move(Axis(0), idx))
+    }
+
+    /// Get the vocabulary of the model.
+    pub fn vocab(&self) -> &FastTextSubwordVocab {
+        &self.vocab
+    }
+
+    /// Get the vector of a text.
+    ///
+    /// The vector is the average of the input vectors of the words,
+    /// subwords, and word n-grams of the text. The text is tokenized
+    /// by splitting on whitespace, as in fastText. Returns `None` if
+    /// no input vectors could be found for the text.
+    pub fn sentence_vector(&self, text: &str) -> Option<Array1<f32>> {
+        let indices = self.input_indices(text);
+        if indices.is_empty() {
+            return None;
+        }
+
+        Some(
+            self.input
+                .view()
+                .select(Axis(0), &indices)
+                .mean_axis(Axis(0))
+                .expect("Input indices are not empty"),
+        )
+    }
+
+    /// Predict the labels of a text.
+    ///
+    /// Returns at most `k` labels with a probability of at least
+    /// `threshold`, ordered by decreasing probability. The
+    /// probabilities are computed as in fastText's `predict`.
+    pub fn predict(&self, text: &str, k: usize, threshold: f32) -> Vec<Prediction<'_>> {
+        let hidden = match self.sentence_vector(text) {
+            Some(hidden) => hidden,
+            None => return Vec::new(),
+        };
+
+        let mut scores = match self.loss {
+            Loss::HierarchicalSoftmax => {
+                let mut scores = Vec::new();
+                let root = 2 * self.labels.len() - 2;
+                self.tree_scores(hidden.view(), root, 0., threshold, &mut scores);
+                scores
+            }
+            loss => {
+                let mut output = self.output.view().dot(&hidden);
+                if loss == Loss::Softmax {
+                    softmax(&mut output);
+                } else {
+                    output.mapv_inplace(sigmoid);
+                }
+
+                output
+                    .iter()
+                    .enumerate()
+                    .filter(|&(_, &prob)| prob >= threshold)
+                    .map(|(idx, &prob)| (std_log(prob), idx))
+                    .collect()
+            }
+        };
+
+        scores.sort_by(|(score1, idx1), (score2, idx2)| {
+            score2
+                .partial_cmp(score1)
+                .unwrap_or(Ordering::Equal)
+                .then(idx1.cmp(idx2))
+        });
+        scores.truncate(k);
+
+        scores
+            .into_iter()
+            .map(|(score, idx)| Prediction {
+                label: &self.labels[idx],
+                probability: score.exp(),
+            })
+            .collect()
+    }
+
+    /// Convert the model into word embeddings.
+    ///
+    /// The embeddings are constructed from the input vectors in the
+    /// same way as `ReadFastText` constructs embeddings, except that
+    /// the model configuration is not stored as metadata.
+    pub fn into_embeddings(self) -> Embeddings<FastTextSubwordVocab, NdArray> {
+        let FastTextClassifier {
+            vocab,
+            counts,
+            mut input,
+            ..
+        } = self;
+
+        add_subword_embeddings(&vocab, &mut input);
+        let norms = l2_normalize_array(input.view_mut().slice_mut(s![0..vocab.words_len(), ..]));
+
+        let mut embeddings = Embeddings::new(None, vocab, input, NdNorms::new(norms));
+        embeddings.set_counts(Some(counts));
+
+        embeddings
+    }
+
+    /// Get the input vector indices of a text.
+    ///
+    /// This follows the tokenization of fastText's `getLine` for
+    /// supervised models.
+    fn input_indices(&self, text: &str) -> Vec<usize> {
+        let mut indices = Vec::new();
+        let mut hashes = Vec::new();
+
+        let tokens = text
+            .split([' ', '\n', '\t', '\x0b', '\x0c', '\r', '\0'])
+            .filter(|token| !token.is_empty())
+            .chain(iter::once(EOS));
+        for token in tokens {
+            if self.label_indices.contains_key(token) || token.starts_with(LABEL_PREFIX) {
+                continue;
+            }
+
+            match self.vocab.idx(token) {
+                Some(WordIndex::Word(idx)) => {
+                    indices.push(idx);
+
+                    // fastText does not use subwords for the end-of-sentence token.
+                    if token != EOS {
+                        indices.extend(self.vocab.subword_indices(token).into_iter().flatten());
+                    }
+                }
+                Some(WordIndex::Subword(subword_indices)) if token != EOS => {
+                    indices.extend(subword_indices)
+                }
+                _ => (),
+            }
+
+            hashes.push(fasttext_hash(token));
+        }
+
+        if self.word_ngrams > 1 && self.buckets > 0 {
+            // fastText stores hashes as signed integers, which are
+            // sign-extended when computing the n-gram hash.
+            let widen = |hash: u32| hash as i32 as i64 as u64;
+
+            for (idx, &hash) in hashes.iter().enumerate() {
+                let mut ngram_hash = widen(hash);
+                for &hash in hashes.iter().skip(idx + 1).take(self.word_ngrams - 1) {
+                    ngram_hash = ngram_hash
+                        .wrapping_mul(WORD_NGRAM_HASH_MULTIPLIER)
+                        .wrapping_add(widen(hash));
+                    indices
+                        .push(self.vocab.words_len() + (ngram_hash % self.buckets as u64) as usize);
+                }
+            }
+        }
+
+        indices
+    }
+
+    /// Compute the log-probabilities of the labels in a subtree of the
+    /// hierarchical softmax tree.
+    fn tree_scores(
+        &self,
+        hidden: ArrayView1<f32>,
+        node: usize,
+        score: f32,
+        threshold: f32,
+        scores: &mut Vec<(f32, usize)>,
+    ) {
+        if score < std_log(threshold) {
+            return;
+        }
+
+        let n_labels = self.labels.len();
+        if node < n_labels {
+            scores.push((score, node));
+            return;
+        }
+
+        let (left, right) = self.tree[node - n_labels];
+        let f = self.output.view().row(node - n_labels).dot(&hidden);
+        let f = 1. / (1. + (-f).exp());
+
+        self.tree_scores(hidden, left, score + std_log(1. - f), threshold, scores);
+        self.tree_scores(hidden, right, score + std_log(f), threshold, scores);
+    }
+}
+
+impl ReadFastText for FastTextClassifier {
+    fn read_fasttext(reader: &mut impl BufRead) -> Result<Self> {
+        Self::read_fasttext_with_warnings(reader, false, &mut Warnings::new())
+    }
+
+    fn read_fasttext_lossy(reader: &mut impl BufRead) -> Result<Self> {
+        Self::read_fasttext_with_warnings(reader, true, &mut Warnings::new())
+    }
+
+    fn read_fasttext_with_warnings(
+        reader: &mut impl BufRead,
+        lossy: bool,
+        warnings: &mut Warnings,
+    ) -> Result<Self> {
+        Self::read_fasttext_with_options_and_warnings(
+            reader,
+            &FastTextOptions::default().lossy(lossy),
+            warnings,
+        )
+    }
+
+    /// Read a supervised fastText model using the given options.
+    ///
+    /// The norms policy of the options is not used, the input vectors
+    /// of a classifier are never normalized.
+    fn read_fasttext_with_options_and_warnings(
+        reader: &mut impl BufRead,
+        options: &FastTextOptions,
+        warnings: &mut Warnings,
+    ) -> Result<Self> {
+        let lossy = options.lossy;
+        let mut reader = Decompress::new(reader)?;
+
+        let (config, vocab, counts, labels) = read_model_header(&mut reader, lossy, warnings)?;
+        if config.model != Model::Supervised {
+            return Err(
+                ErrorKind::Format("fastText model is not a supervised model".into()).into(),
+            );
+        }
+        if labels.labels.is_empty() {
+            return Err(ErrorKind::Format("fastText model does not have labels".into()).into());
+        }
+
+        let input = read_matrix(&mut reader)?;
+        let output = read_matrix(&mut reader)?;
+
+        let dims = config.dims as usize;
+        let buckets = config.bucket as usize;
+        if input.shape() != (vocab.words_len() + buckets, dims) {
+            return Err(ErrorKind::Format(format!(
+                "Input matrix has shape {:?}, expected {:?}",
+                input.shape(),
+                (vocab.words_len() + buckets, dims)
+            ))
+            .into());
+        }
+        if output.shape() != (labels.labels.len(), dims) {
+            return Err(ErrorKind::Format(format!(
+                "Output matrix has shape {:?}, expected {:?}",
+                output.shape(),
+                (labels.labels.len(), dims)
+            ))
+            .into());
+        }
+
+        let label_indices = labels
+            .labels
+            .iter()
+            .enumerate()
+            .map(|(idx, label)| (label.clone(), idx))
+            .collect();
+        let tree = build_tree(&labels.counts);
+
+        Ok(FastTextClassifier {
+            vocab,
+            counts,
+            input,
+            labels: labels.labels,
+            label_indices,
+            label_counts: labels.counts,
+            output,
+            loss: config.loss,
+            tree,
+            word_ngrams: config.word_ngrams as usize,
+            buckets,
+        })
+    }
+}
+
+/// Build the hierarchical softmax tree of the labels.
+///
+/// This is the Huffman tree that fastText constructs from the label
+/// counts, which are sorted in descending order. Returns the children
+/// of the inner nodes, inner node *i* has node index `n_labels + i`.
+fn build_tree(counts: &[u64]) -> Vec<(usize, usize)> {
+    let n_labels = counts.len();
+    let n_nodes = 2 * n_labels - 1;
+
+    let mut node_counts = vec![1e15 as u64; n_nodes];
+    node_counts[..n_labels].copy_from_slice(counts);
+
+    let mut tree = Vec::with_capacity(n_labels - 1);
+    let mut leaf = n_labels as isize - 1;
+    let mut node = n_labels;
+    for idx in n_labels..n_nodes {
+        let mut children = [0; 2];
+        for child in &mut children {
+            if leaf >= 0 && node_counts[leaf as usize] < node_counts[node] {
+                *child = leaf as usize;
+                leaf -= 1;
+            } else {
+                *child = node;
+                node += 1;
+            }
+        }
+
+        node_counts[idx] = node_counts[children[0]] + node_counts[children[1]];
+        tree.push((children[0], children[1]));
+    }
+
+    tree
+}
+
+/// fastText's logarithm, which avoids the logarithm of zero.
+fn std_log(x: f32) -> f32 {
+    (x + 1e-5).ln()
+}
+
+/// fastText's sigmoid, which uses a lookup table.
+fn sigmoid(x: f32) -> f32 {
+    if x < -MAX_SIGMOID {
+        0.
+    } else if x > MAX_SIGMOID {
+        1.
+    } else {
+        let idx = ((x + MAX_SIGMOID) * SIGMOID_TABLE_SIZE as f32 / MAX_SIGMOID / 2.) as usize;
+        let x = (idx as f32 * 2. * MAX_SIGMOID) / SIGMOID_TABLE_SIZE as f32 - MAX_SIGMOID;
+        1. / (1. + (-x).exp())
+    }
+}
+
+fn softmax(output: &mut Array1<f32>) {
+    let max = output.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+    output.mapv_inplace(|v| (v - max).exp());
+    let z = output.sum();
+    *output /= z;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::{BufReader, Cursor, Read};
+
+    use approx::AbsDiffEq;
+    use ndarray::{arr1, ArrayView1};
+
+    use super::{FastTextClassifier, Prediction};
+    use crate::fasttext::ReadFastText;
+    use finalfusion_core::embeddings::Embeddings;
+    use finalfusion_core::storage::StorageView;
+    use finalfusion_core::vocab::Vocab;
+
+    fn read_model(loss: u8) -> FastTextClassifier {
+        let mut data = Vec::new();
+        File::open("../testdata/fasttext-supervised.bin")
+            .unwrap()
+            .read_to_end(&mut data)
+            .unwrap();
+        // Replace the loss type of the configuration.
+        data[32] = loss;
+        FastTextClassifier::read_fasttext(&mut Cursor::new(data)).unwrap()
+    }
+
+    fn assert_predictions(predictions: &[Prediction], expected: &[(&str, f32)]) {
+        assert_eq!(predictions.len(), expected.len());
+        for (prediction, &(label, probability)) in predictions.iter().zip(expected) {
+            assert_eq!(prediction.label, label);
+            assert!(prediction.probability.abs_diff_eq(&probability, 1e-4));
+        }
+    }
+
+    #[test]
+    fn read_supervised_model() {
+        let classifier = read_model(3);
+        assert_eq!(
+            classifier.labels(),
+            &["__label__pos", "__label__neg", "__label__meh"]
+        );
+        assert_eq!(classifier.label_counts(), &[3, 2, 1]);
+        assert_eq!(
+            classifier.vocab().words(),
+            &["</s>", "good", "movie", "bad"]
+        );
+        assert_eq!(
+            classifier.label_embedding("__label__neg"),
+            Some(ArrayView1::from(&[8., -12., -4.]))
+        );
+        assert_eq!(classifier.label_embedding("__label__unknown"), None);
+    }
+
+    #[test]
+    fn sentence_vector() {
+        let classifier = read_model(3);
+        assert!(classifier
+            .sentence_vector("a good movie")
+            .unwrap()
+            .abs_diff_eq(
+                &arr1(&[-0.044_827_584, -0.010_344_827, -0.051_724_14]),
+                1e-6
+            ));
+
+        // Labels are not part of the input.
+        assert_eq!(
+            classifier.sentence_vector("good movie __label__pos"),
+            classifier.sentence_vector("good movie")
+        );
+    }
+
+    #[test]
+    fn predict_softmax() {
+        let classifier = read_model(3);
+        assert_predictions(
+            &classifier.predict("a good movie", 3, 0.),
+            &[
+                ("__label__meh", 0.413_999_3),
+                ("__label__pos", 0.350_847_4),
+                ("__label__neg", 0.235_183_3),
+            ],
+        );
+        assert_predictions(
+            &classifier.predict("bad", 1, 0.),
+            &[("__label__pos", 0.516_150_9)],
+        );
+        assert_predictions(
+            &classifier.predict("bad", 3, 0.3),
+            &[("__label__pos", 0.516_150_9), ("__label__meh", 0.345_989_6)],
+        );
+    }
+
+    #[test]
+    fn predict_one_vs_all() {
+        let classifier = read_model(4);
+        assert_predictions(
+            &classifier.predict("bad", 3, 0.),
+            &[
+                ("__label__pos", 0.672_341_7),
+                ("__label__meh", 0.577_505_4),
+                ("__label__neg", 0.348_655_1),
+            ],
+        );
+    }
+
+    #[test]
+    fn predict_hierarchical_softmax() {
+        let classifier = read_model(1);
+        assert_predictions(
+            &classifier.predict("bad", 3, 0.),
+            &[
+                ("__label__neg", 0.434_286_1),
+                ("__label__pos", 0.354_353_7),
+                ("__label__meh", 0.211_393_1),
+            ],
+        );
+        assert_predictions(
+            &classifier.predict("good movie", 2, 0.3),
+            &[("__label__pos", 0.516_004_5), ("__label__neg", 0.342_117_7)],
+        );
+
+        // The output matrix contains the inner nodes of the tree.
+        assert_eq!(classifier.label_embedding("__label__pos"), None);
+    }
+
+    #[test]
+    fn into_embeddings_equals_input_vectors() {
+        let mut reader = BufReader::new(File::open("../testdata/fasttext-supervised.bin").unwrap());
+        let embeddings = Embeddings::read_fasttext(&mut reader).unwrap();
+        let classifier = read_model(3);
+        let converted = classifier.into_embeddings();
+
+        assert_eq!(converted.vocab().words(), embeddings.vocab().words());
+        assert_eq!(converted.counts(), embeddings.counts());
+        assert_eq!(converted.storage().view(), embeddings.storage().view());
+        assert_eq!(converted.embedding("movie"), embeddings.embedding("movie"));
+    }
+
+    #[test]
+    fn unsupervised_model_is_rejected() {
+        let mut reader = BufReader::new(File::open("../testdata/fasttext.bin").unwrap());
+        assert!(FastTextClassifier::read_fasttext(&mut reader).is_err());
+    }
+}
@@ -0,0 +1,317 @@
+use std::collections::HashSet;
+use std::io::{BufRead, Read};
+
+use finalfusion_core::counts::WordCounts;
+use finalfusion_core::embeddings::Embeddings;
+use finalfusion_core::norms::NdNorms;
+use finalfusion_core::storage::NdArray;
+use finalfusion_core::util::l2_normalize_array;
+use finalfusion_core::vocab::SimpleVocab;
+use finalfusion_io::io::{Error, ErrorKind, Result};
+use ndarray::Array2;
+
+use super::pickle::{read_pickle, Value};
+use crate::npy::{matrix_from_bytes, read_npy_matrix, NpyType};
+
+/// Read gensim `KeyedVectors`.
+pub trait ReadGensim
+where
+    Self: Sized,
+{
+    /// Read `KeyedVectors` with an embedding matrix that is stored in
+    /// the pickle.
+    fn read_gensim(reader: &mut impl BufRead) -> Result<Self>;
+
+    /// Read `KeyedVectors` with an embedding matrix that is stored in
+    /// a separate NumPy file.
+    ///
+    /// gensim stores the embedding matrix of `model.kv` in
+    /// `model.kv.vectors.npy`.
+    fn read_gensim_with_vectors(reader: &mut impl BufRead, vectors: &mut impl Read)
+        -> Result<Self>;
+}
+
+impl ReadGensim for Embeddings<SimpleVocab, NdArray> {
+    fn read_gensim(reader: &mut impl BufRead) -> Result<Self> {
+        let state = read_state(reader)?;
+        let matrix = match state.get("vectors").or_else(|| state.get("syn0")) {
+            Some(Value::None) | None => {
+                return Err(ErrorKind::Format(
+                    "KeyedVectors embedding matrix is stored in a separate file".to_string(),
+                )
+                .into())
+            }
+            Some(vectors) => read_pickled_matrix(&vectors)?,
+        };
+
+        embeddings_from_state(&state, matrix)
+    }
+
+    fn read_gensim_with_vectors(
+        reader: &mut impl BufRead,
+        vectors: &mut impl Read,
+    ) -> Result<Self> {
+        let state = read_state(reader)?;
+        embeddings_from_state(&state, read_npy_matrix(vectors)?)
+    }
+}
+
+/// Read the state of pickled `KeyedVectors`.
+fn read_state(reader: &mut impl BufRead) -> Result<Value> {
+    let object = match read_pickle(reader)? {
+        Value::Object(object) => object,
+        _ => {
+            return Err(format_error(
+                "Pickle does not contain a KeyedVectors object",
+            ))
+        }
+    };
+
+    let object = object.borrow();
+    match object.class() {
+        Some((module, _)) if module.starts_with("gensim.") => (),
+        class => {
+            return Err(format_error(format!(
+                "Pickle contains an object of class {:?}, expected gensim KeyedVectors",
+                class
+            )))
+        }
+    }
+
+    match &object.state {
+        Some(state @ Value::Dict(_)) => Ok(state.clone()),
+        _ => Err(format_error("KeyedVectors object does not have a state")),
+    }
+}
+
+/// Construct embeddings from `KeyedVectors` state and its matrix.
+fn embeddings_from_state(
+    state: &Value,
+    mut matrix: Array2<f32>,
+) -> Result<Embeddings<SimpleVocab, NdArray>> {
+    let (words, counts) = read_vocab(state)?;
+    if matrix.nrows() != words.len() {
+        return Err(format_error(format!(
+            "Embedding matrix has {} rows, but the vocabulary contains {} keys",
+            matrix.nrows(),
+            words.len()
+        )));
+    }
+
+    let norms = l2_normalize_array(matrix.view_mut());
+
+    let mut embeddings = Embeddings::new(
+        None,
+        SimpleVocab::new(words),
+        NdArray::new(matrix),
+        NdNorms::new(norms),
+    );
+    embeddings.set_counts(counts.map(WordCounts::new));
+
+    Ok(embeddings)
+}
+
+/// Read the vocabulary of `KeyedVectors`.
+///
+/// gensim 4 stores the keys in `index_to_key`. gensim 3 stores the
+/// keys in `index2word` or `index2entity` and their counts in `vocab`,
+/// counts are only returned for gensim 3 vocabularies.
+fn read_vocab(state: &Value) -> Result<(Vec<String>, Option<Vec<u64>>)> {
+    let keys = ["index_to_key", "index2word", "index2entity"]
+        .iter()
+        .find_map(|&key| state.get(key).and_then(|keys| keys.items()));
+    let words = match keys {
+        Some(keys) => keys
+            .iter()
+            .map(|key| {
+                key.as_str()
+                    .map(ToOwned::to_owned)
+                    .ok_or_else(|| format_error("KeyedVectors contains a key that is not a string"))
+            })
+            .collect::<Result<Vec<_>>>()?,
+        None => return Err(format_error("KeyedVectors does not contain a vocabulary")),
+    };
+
+    let mut unique = HashSet::with_capacity(words.len());
+    if let Some(word) = words.iter().find(|&word| !unique.insert(word)) {
+        return Err(format_error(format!("Duplicate key: {}", word)));
+    }
+
+    let counts = match state.get("vocab") {
+        Some(vocab @ Value::Dict(_)) => Some(read_counts(&vocab, &words)?),
+        _ => None,
+    };
+
+    Ok((words, counts))
+}
+
+/// Read the counts of a gensim 3 vocabulary.
+///
+/// The vocabulary maps every key to a `Vocab` object with a count.
+fn read_counts(vocab: &Value, words: &[String]) -> Result<Vec<u64>> {
+    words
+        .iter()
+        .map(|word| {
+            let count = match vocab.get(word) {
+                Some(Value::Object(object)) => object
+                    .borrow()
+                    .state
+                    .as_ref()
+                    .and_then(|state| state.get("count")),
+                _ => None,
+            };
+
+            match count {
+                Some(Value::Int(count)) if count >= 0 => Ok(count as u64),
+                _ => Err(format_error(format!("No valid count for key: {}", word))),
+            }
+        })
+        .collect()
+}
+
+/// Read a pickled NumPy matrix.
+///
+/// NumPy arrays are pickled as a call of `_reconstruct`, with the
+/// state `(version, shape, dtype, is_fortran, data)`.
+fn read_pickled_matrix(value: &Value) -> Result<Array2<f32>> {
+    let invalid = || format_error("KeyedVectors embedding matrix is not a NumPy array");
+
+    let object = match value {
+        Value::Object(object) => object.borrow(),
+        _ => return Err(invalid()),
+    };
+    match &object.callable {
+        Value::Global { module, name }
+            if module.ends_with("multiarray") && name == "_reconstruct" => {}
+        _ => return Err(invalid()),
+    }
+
+    let state = object
+        .state
+        .as_ref()
+        .and_then(Value::items)
+        .ok_or_else(invalid)?;
+    let (shape, dtype, fortran_order, data) = match state.as_slice() {
+        [_, shape, dtype, Value::Bool(fortran_order), data] => (
+            shape,
+            dtype,
+            *fortran_order,
+            data.to_bytes().ok_or_else(invalid)?,
+        ),
+        _ => return Err(invalid()),
+    };
+
+    let shape = shape
+        .items()
+        .ok_or_else(invalid)?
+        .iter()
+        .map(|dim| match dim {
+            Value::Int(dim) if *dim >= 0 => Ok(*dim as usize),
+            _ => Err(invalid()),
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let shape = match shape.as_slice() {
+        &[rows, cols] => (rows, cols),
+        _ => {
+            return Err(format_error(format!(
+                "Expected a two-dimensional embedding matrix, got shape: {:?}",
+                shape
+            )))
+        }
+    };
+
+    matrix_from_bytes(&data, read_dtype(dtype)?, shape, fortran_order)
+}
+
+/// Read a pickled NumPy data type.
+///
+/// Data types are pickled as `dtype(kind, align, copy)` with the state
+/// `(version, byte_order, ...)`.
+fn read_dtype(value: &Value) -> Result<NpyType> {
+    let invalid = || format_error("Invalid NumPy data type in pickle");
+
+    let object = match value {
+        Value::Object(object) => object.borrow(),
+        _ => return Err(invalid()),
+    };
+    let kind = object
+        .args
+        .items()
+        .and_then(|args| {
+            args.first()
+                .and_then(|kind| kind.as_str().map(ToOwned::to_owned))
+        })
+        .ok_or_else(invalid)?;
+    let byte_order = object
+        .state
+        .as_ref()
+        .and_then(Value::items)
+        .and_then(|state| {
+            state
+                .get(1)
+                .and_then(|order| order.as_str().map(ToOwned::to_owned))
+        })
+        .unwrap_or_else(|| "|".to_owned());
+
+    match byte_order.as_str() {
+        "<" | "=" | "|" => NpyType::from_descr(&kind),
+        order => NpyType::from_descr(&format!("{}{}", order, kind)),
+    }
+}
+
+fn format_error(msg: impl Into<String>) -> Error {
+    ErrorKind::Format(msg.into()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::BufReader;
+
+    use approx::AbsDiffEq;
+
+    use super::ReadGensim;
+    use crate::text::ReadTextDims;
+    use finalfusion_core::embeddings::Embeddings;
+    use finalfusion_core::vocab::{SimpleVocab, Vocab};
+
+    fn check_embeddings(embeddings: &Embeddings<SimpleVocab, finalfusion_core::storage::NdArray>) {
+        let mut reader = BufReader::new(File::open("../testdata/similarity.txt").unwrap());
+        let check = Embeddings::read_text_dims(&mut reader).unwrap();
+        let words = &check.vocab().words()[..10];
+
+        assert_eq!(embeddings.vocab().words(), words);
+        for word in words {
+            assert!(embeddings
+                .embedding(word)
+                .unwrap()
+                .abs_diff_eq(&check.embedding(word).unwrap(), 1e-5));
+        }
+    }
+
+    #[test]
+    fn read_gensim_with_vectors() {
+        let mut reader = BufReader::new(File::open("../testdata/gensim.kv").unwrap());
+        let mut vectors = BufReader::new(File::open("../testdata/gensim.kv.vectors.npy").unwrap());
+        let embeddings = Embeddings::read_gensim_with_vectors(&mut reader, &mut vectors).unwrap();
+        check_embeddings(&embeddings);
+        assert!(embeddings.counts().is_none());
+    }
+
+    #[test]
+    fn read_gensim_inline_vectors() {
+        let mut reader = BufReader::new(File::open("../testdata/gensim3-inline.kv").unwrap());
+        let embeddings = Embeddings::read_gensim(&mut reader).unwrap();
+        check_embeddings(&embeddings);
+        assert_eq!(
+            embeddings.counts().unwrap().to_vec(),
+            (0..10).map(|idx| 100 - idx).collect::<Vec<u64>>()
+        );
+    }
+
+    #[test]
+    fn read_gensim_fails_without_vectors() {
+        let mut reader = BufReader::new(File::open("../testdata/gensim.kv").unwrap());
+        assert!(Embeddings::read_gensim(&mut reader).is_err());
+    }
+}
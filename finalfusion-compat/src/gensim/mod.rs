@@ -0,0 +1,35 @@
+//! Reader for gensim `KeyedVectors`.
+//!
+//! gensim saves `KeyedVectors` as a pickle, such as `model.kv`. Large
+//! embedding matrices are stored in a separate NumPy file next to the
+//! pickle, `model.kv.vectors.npy`. Such embeddings are read as
+//! follows:
+//!
+//! ```
+//! use std::fs::File;
+//! use std::io::BufReader;
+//!
+//! use finalfusion_compat::gensim::ReadGensim;
+//! use finalfusion_core::embeddings::Embeddings;
+//!
+//! let mut reader = BufReader::new(File::open("../testdata/gensim.kv").unwrap());
+//! let mut vectors = BufReader::new(File::open("../testdata/gensim.kv.vectors.npy").unwrap());
+//!
+//! let embeddings = Embeddings::read_gensim_with_vectors(&mut reader, &mut vectors)
+//!     .unwrap();
+//!
+//! // Look up an embedding.
+//! let embedding = embeddings.embedding("Berlin");
+//! ```
+//!
+//! Smaller embedding matrices are stored in the pickle itself and are
+//! read with `ReadGensim::read_gensim`.
+//!
+//! The pickle is not executed by a Python interpreter. Only the data
+//! of `KeyedVectors` of gensim 3 and 4 is reconstructed, the
+//! vocabulary must consist of string keys.
+
+mod io;
+pub use self::io::ReadGensim;
+
+mod pickle;
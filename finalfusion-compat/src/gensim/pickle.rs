@@ -0,0 +1,583 @@
+//! Minimal interpreter for Python pickles.
+//!
+//! gensim stores `KeyedVectors` as pickled Python objects. This module
+//! only implements as much of the pickle virtual machine as is needed
+//! to reconstruct the data of such objects: Python classes are not
+//! instantiated, but represented by the callable that constructs them,
+//! its arguments, and the state that is set by `BUILD`.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io::{BufRead, Read};
+use std::rc::Rc;
+
+use byteorder::{BigEndian, LittleEndian, ReadBytesExt};
+use finalfusion_io::io::{ErrorKind, Result};
+
+/// Python value of a pickle.
+///
+/// Lists, dictionaries, and objects can be referenced from multiple
+/// places in a pickle, so they are shared and mutable.
+#[derive(Clone, Debug)]
+pub(crate) enum Value {
+    None,
+    Bool(bool),
+    Int(i64),
+    #[allow(dead_code)]
+    Float(f64),
+    String(String),
+    Bytes(Vec<u8>),
+    Tuple(Vec<Value>),
+    List(Rc<RefCell<Vec<Value>>>),
+    Dict(Rc<RefCell<Vec<(Value, Value)>>>),
+    Global {
+        module: String,
+        name: String,
+    },
+    Object(Rc<RefCell<Object>>),
+}
+
+impl Value {
+    fn list(items: Vec<Value>) -> Self {
+        Value::List(Rc::new(RefCell::new(items)))
+    }
+
+    fn dict(items: Vec<(Value, Value)>) -> Self {
+        Value::Dict(Rc::new(RefCell::new(items)))
+    }
+
+    /// Get the string of a string value.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Get the value of a string key in a dictionary.
+    pub fn get(&self, key: &str) -> Option<Value> {
+        match self {
+            Value::Dict(items) => items
+                .borrow()
+                .iter()
+                .find(|(k, _)| k.as_str() == Some(key))
+                .map(|(_, v)| v.clone()),
+            _ => None,
+        }
+    }
+
+    /// Get the bytes of a bytes value.
+    ///
+    /// Pickle protocols before version 3 do not support bytes. Python 3
+    /// pickles bytes as `_codecs.encode(s, 'latin1')` for these
+    /// protocols, where every character of `s` is a byte.
+    pub fn to_bytes(&self) -> Option<Vec<u8>> {
+        let object = match self {
+            Value::Bytes(bytes) => return Some(bytes.clone()),
+            Value::Object(object) => object.borrow(),
+            _ => return None,
+        };
+
+        match (&object.callable, object.args.items()?.as_slice()) {
+            (Value::Global { module, name }, [Value::String(s), encoding])
+                if module == "_codecs"
+                    && name == "encode"
+                    && encoding.as_str() == Some("latin1") =>
+            {
+                s.chars().map(|c| u8::try_from(c as u32).ok()).collect()
+            }
+            _ => None,
+        }
+    }
+
+    /// Get the elements of a list or tuple.
+    pub fn items(&self) -> Option<Vec<Value>> {
+        match self {
+            Value::List(items) => Some(items.borrow().clone()),
+            Value::Tuple(items) => Some(items.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// Python object that was reconstructed from a pickle.
+#[derive(Clone, Debug)]
+pub(crate) struct Object {
+    /// The class or function that constructs the object.
+    pub callable: Value,
+
+    /// The arguments of the callable.
+    pub args: Value,
+
+    /// The state of the object.
+    pub state: Option<Value>,
+}
+
+impl Object {
+    /// Get the class of the object.
+    ///
+    /// Objects of Python classes are often constructed using
+    /// `copyreg._reconstructor(cls, base, state)`, in which case the
+    /// class is the first argument.
+    pub fn class(&self) -> Option<(String, String)> {
+        let callable = match &self.callable {
+            Value::Global { module, name }
+                if (module == "copyreg" || module == "copy_reg") && name == "_reconstructor" =>
+            {
+                self.args.items()?.into_iter().next()?
+            }
+            callable => callable.clone(),
+        };
+
+        match callable {
+            Value::Global { module, name } => Some((module, name)),
+            _ => None,
+        }
+    }
+}
+
+/// Read a pickle.
+pub(crate) fn read_pickle<R>(read: &mut R) -> Result<Value>
+where
+    R: BufRead,
+{
+    let mut machine = Machine::default();
+    loop {
+        let opcode = read
+            .read_u8()
+            .map_err(|e| ErrorKind::io_error("Cannot read pickle opcode", e))?;
+        if let Some(value) = machine.execute(opcode, read)? {
+            return Ok(value);
+        }
+    }
+}
+
+/// Pickle virtual machine.
+#[derive(Default)]
+struct Machine {
+    stack: Vec<Value>,
+    marks: Vec<usize>,
+    memo: HashMap<usize, Value>,
+}
+
+impl Machine {
+    fn pop(&mut self) -> Result<Value> {
+        self.stack
+            .pop()
+            .ok_or_else(|| ErrorKind::Format("Pickle stack underflow".to_string()).into())
+    }
+
+    fn top(&mut self) -> Result<&mut Value> {
+        self.stack
+            .last_mut()
+            .ok_or_else(|| ErrorKind::Format("Pickle stack underflow".to_string()).into())
+    }
+
+    /// Pop the values up to the topmost mark.
+    fn pop_mark(&mut self) -> Result<Vec<Value>> {
+        let mark = self
+            .marks
+            .pop()
+            .ok_or_else(|| ErrorKind::Format("Pickle mark stack underflow".to_string()))?;
+        if mark > self.stack.len() {
+            return Err(ErrorKind::Format("Pickle stack underflow".to_string()).into());
+        }
+        Ok(self.stack.split_off(mark))
+    }
+
+    fn memoize(&mut self, idx: usize) -> Result<()> {
+        let value = self.top()?.clone();
+        self.memo.insert(idx, value);
+        Ok(())
+    }
+
+    fn get(&mut self, idx: usize) -> Result<()> {
+        let value = self.memo.get(&idx).cloned().ok_or_else(|| {
+            ErrorKind::Format(format!("Pickle memo does not contain key {}", idx))
+        })?;
+        self.stack.push(value);
+        Ok(())
+    }
+
+    fn append(&mut self, items: Vec<Value>) -> Result<()> {
+        match self.top()? {
+            // Sets are represented as lists.
+            Value::List(list) => list.borrow_mut().extend(items),
+            value => {
+                return Err(
+                    ErrorKind::Format(format!("Cannot append to pickle value {:?}", value)).into(),
+                )
+            }
+        }
+        Ok(())
+    }
+
+    fn set_items(&mut self, items: Vec<Value>) -> Result<()> {
+        if items.len() % 2 != 0 {
+            return Err(ErrorKind::Format("Odd number of pickle dictionary items".into()).into());
+        }
+        match self.top()? {
+            Value::Dict(dict) => {
+                let mut dict = dict.borrow_mut();
+                let mut items = items.into_iter();
+                while let (Some(key), Some(value)) = (items.next(), items.next()) {
+                    dict.push((key, value));
+                }
+            }
+            value => {
+                return Err(ErrorKind::Format(format!(
+                    "Cannot set items of pickle value {:?}",
+                    value
+                ))
+                .into())
+            }
+        }
+        Ok(())
+    }
+
+    fn object(&mut self, callable: Value, args: Value) {
+        self.stack.push(Value::Object(Rc::new(RefCell::new(Object {
+            callable,
+            args,
+            state: None,
+        }))));
+    }
+
+    /// Execute an opcode, returns the pickled value on `STOP`.
+    fn execute<R>(&mut self, opcode: u8, read: &mut R) -> Result<Option<Value>>
+    where
+        R: BufRead,
+    {
+        match opcode {
+            // PROTO
+            0x80 => {
+                read_bytes(read, 1)?;
+            }
+            // FRAME
+            0x95 => {
+                read_u64(read)?;
+            }
+            // STOP
+            b'.' => return self.pop().map(Some),
+            // MARK
+            b'(' => self.marks.push(self.stack.len()),
+            // POP
+            b'0' => {
+                self.pop()?;
+            }
+            // POP_MARK
+            b'1' => {
+                self.pop_mark()?;
+            }
+            // DUP
+            b'2' => {
+                let value = self.top()?.clone();
+                self.stack.push(value);
+            }
+            // NONE
+            b'N' => self.stack.push(Value::None),
+            // NEWTRUE, NEWFALSE
+            0x88 => self.stack.push(Value::Bool(true)),
+            0x89 => self.stack.push(Value::Bool(false)),
+            // BININT
+            b'J' => {
+                let value = read
+                    .read_i32::<LittleEndian>()
+                    .map_err(|e| ErrorKind::io_error("Cannot read pickle integer", e))?;
+                self.stack.push(Value::Int(value as i64));
+            }
+            // BININT1
+            b'K' => {
+                let value = read_bytes(read, 1)?[0];
+                self.stack.push(Value::Int(value as i64));
+            }
+            // BININT2
+            b'M' => {
+                let value = read
+                    .read_u16::<LittleEndian>()
+                    .map_err(|e| ErrorKind::io_error("Cannot read pickle integer", e))?;
+                self.stack.push(Value::Int(value as i64));
+            }
+            // LONG1, LONG4
+            0x8a | 0x8b => {
+                let len = if opcode == 0x8a {
+                    read_bytes(read, 1)?[0] as usize
+                } else {
+                    read_u32(read)?
+                };
+                let bytes = read_bytes(read, len)?;
+                self.stack.push(Value::Int(long_from_bytes(&bytes)?));
+            }
+            // INT, LONG
+            b'I' | b'L' => {
+                let line = read_line(read)?;
+                let value = match line.trim_end_matches('L') {
+                    "01" => Value::Bool(true),
+                    "00" => Value::Bool(false),
+                    line => Value::Int(line.parse().map_err(|e| {
+                        ErrorKind::Format(format!("Invalid pickle integer {}: {}", line, e))
+                    })?),
+                };
+                self.stack.push(value);
+            }
+            // BINFLOAT
+            b'G' => {
+                let value = read
+                    .read_f64::<BigEndian>()
+                    .map_err(|e| ErrorKind::io_error("Cannot read pickle float", e))?;
+                self.stack.push(Value::Float(value));
+            }
+            // SHORT_BINUNICODE, BINUNICODE, BINUNICODE8
+            0x8c | b'X' | 0x8d => {
+                let len = match opcode {
+                    0x8c => read_bytes(read, 1)?[0] as usize,
+                    b'X' => read_u32(read)?,
+                    _ => read_u64(read)?,
+                };
+                let s = String::from_utf8(read_bytes(read, len)?).map_err(|e| {
+                    ErrorKind::Format(format!("Pickle string is not valid UTF-8: {}", e))
+                })?;
+                self.stack.push(Value::String(s));
+            }
+            // SHORT_BINBYTES, BINBYTES, BINBYTES8, BYTEARRAY8,
+            // SHORT_BINSTRING, BINSTRING
+            b'C' | b'B' | 0x8e | 0x96 | b'U' | b'T' => {
+                let len = match opcode {
+                    b'C' | b'U' => read_bytes(read, 1)?[0] as usize,
+                    b'B' | b'T' => read_u32(read)?,
+                    _ => read_u64(read)?,
+                };
+                self.stack.push(Value::Bytes(read_bytes(read, len)?));
+            }
+            // EMPTY_TUPLE, TUPLE1, TUPLE2, TUPLE3, TUPLE
+            b')' => self.stack.push(Value::Tuple(Vec::new())),
+            0x85..=0x87 => {
+                let len = (opcode - 0x84) as usize;
+                if len > self.stack.len() {
+                    return Err(ErrorKind::Format("Pickle stack underflow".to_string()).into());
+                }
+                let items = self.stack.split_off(self.stack.len() - len);
+                self.stack.push(Value::Tuple(items));
+            }
+            b't' => {
+                let items = self.pop_mark()?;
+                self.stack.push(Value::Tuple(items));
+            }
+            // EMPTY_LIST, LIST, EMPTY_SET, FROZENSET
+            b']' | 0x8f => self.stack.push(Value::list(Vec::new())),
+            b'l' | 0x91 => {
+                let items = self.pop_mark()?;
+                self.stack.push(Value::list(items));
+            }
+            // APPEND
+            b'a' => {
+                let item = self.pop()?;
+                self.append(vec![item])?;
+            }
+            // APPENDS, ADDITEMS
+            b'e' | 0x90 => {
+                let items = self.pop_mark()?;
+                self.append(items)?;
+            }
+            // EMPTY_DICT, DICT
+            b'}' => self.stack.push(Value::dict(Vec::new())),
+            b'd' => {
+                let items = self.pop_mark()?;
+                self.stack.push(Value::dict(Vec::new()));
+                self.set_items(items)?;
+            }
+            // SETITEM
+            b's' => {
+                let value = self.pop()?;
+                let key = self.pop()?;
+                self.set_items(vec![key, value])?;
+            }
+            // SETITEMS
+            b'u' => {
+                let items = self.pop_mark()?;
+                self.set_items(items)?;
+            }
+            // MEMOIZE
+            0x94 => {
+                let idx = self.memo.len();
+                self.memoize(idx)?;
+            }
+            // BINPUT, LONG_BINPUT
+            b'q' => {
+                let idx = read_bytes(read, 1)?[0] as usize;
+                self.memoize(idx)?;
+            }
+            b'r' => {
+                let idx = read_u32(read)?;
+                self.memoize(idx)?;
+            }
+            // BINGET, LONG_BINGET
+            b'h' => {
+                let idx = read_bytes(read, 1)?[0] as usize;
+                self.get(idx)?;
+            }
+            b'j' => {
+                let idx = read_u32(read)?;
+                self.get(idx)?;
+            }
+            // GLOBAL
+            b'c' => {
+                let module = read_line(read)?;
+                let name = read_line(read)?;
+                self.stack.push(Value::Global { module, name });
+            }
+            // STACK_GLOBAL
+            0x93 => {
+                let name = self.pop()?;
+                let module = self.pop()?;
+                match (module, name) {
+                    (Value::String(module), Value::String(name)) => {
+                        self.stack.push(Value::Global { module, name })
+                    }
+                    _ => {
+                        return Err(ErrorKind::Format(
+                            "Pickle global module and name must be strings".to_string(),
+                        )
+                        .into())
+                    }
+                }
+            }
+            // REDUCE, NEWOBJ
+            b'R' | 0x81 => {
+                let args = self.pop()?;
+                let callable = self.pop()?;
+                self.object(callable, args);
+            }
+            // NEWOBJ_EX
+            0x92 => {
+                let _kwargs = self.pop()?;
+                let args = self.pop()?;
+                let callable = self.pop()?;
+                self.object(callable, args);
+            }
+            // BUILD
+            b'b' => {
+                let state = self.pop()?;
+                match self.top()? {
+                    Value::Object(object) => object.borrow_mut().state = Some(state),
+                    value => {
+                        return Err(ErrorKind::Format(format!(
+                            "Cannot set state of pickle value {:?}",
+                            value
+                        ))
+                        .into())
+                    }
+                }
+            }
+            opcode => {
+                return Err(ErrorKind::Format(format!(
+                    "Unsupported pickle opcode: {:#04x}",
+                    opcode
+                ))
+                .into())
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+fn read_bytes<R>(read: &mut R, len: usize) -> Result<Vec<u8>>
+where
+    R: Read,
+{
+    let mut bytes = Vec::new();
+    read.take(len as u64)
+        .read_to_end(&mut bytes)
+        .map_err(|e| ErrorKind::io_error("Cannot read pickle data", e))?;
+    if bytes.len() != len {
+        return Err(ErrorKind::Format("Pickle is truncated".to_string()).into());
+    }
+    Ok(bytes)
+}
+
+fn read_u32<R>(read: &mut R) -> Result<usize>
+where
+    R: Read,
+{
+    read.read_u32::<LittleEndian>()
+        .map(|v| v as usize)
+        .map_err(|e| ErrorKind::io_error("Cannot read pickle length", e).into())
+}
+
+fn read_u64<R>(read: &mut R) -> Result<usize>
+where
+    R: Read,
+{
+    let len = read
+        .read_u64::<LittleEndian>()
+        .map_err(|e| ErrorKind::io_error("Cannot read pickle length", e))?;
+    usize::try_from(len)
+        .map_err(|_| ErrorKind::Format(format!("Pickle length {} is too large", len)).into())
+}
+
+fn read_line<R>(read: &mut R) -> Result<String>
+where
+    R: BufRead,
+{
+    let mut line = String::new();
+    read.read_line(&mut line)
+        .map_err(|e| ErrorKind::io_error("Cannot read pickle line", e))?;
+    Ok(line.trim_end_matches('\n').to_owned())
+}
+
+/// Decode a little-endian two's complement integer.
+fn long_from_bytes(bytes: &[u8]) -> Result<i64> {
+    if bytes.len() > 8 {
+        return Err(ErrorKind::Format("Pickle integer does not fit in 64 bits".into()).into());
+    }
+
+    let negative = bytes.last().map(|&b| b & 0x80 != 0).unwrap_or(false);
+    let mut buf = if negative { [0xff; 8] } else { [0; 8] };
+    buf[..bytes.len()].copy_from_slice(bytes);
+    Ok(i64::from_le_bytes(buf))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::{read_pickle, Value};
+
+    #[test]
+    fn read_pickled_containers() {
+        // pickle.dumps({'a': [1, -2, 'b'], 'c': (None, True, 1.5)}, protocol=4)
+        let data = b"\x80\x04\x95)\x00\x00\x00\x00\x00\x00\x00}\x94(\x8c\x01a\x94]\x94(K\
+\x01J\xfe\xff\xff\xff\x8c\x01b\x94e\x8c\x01c\x94N\x88G?\xf8\x00\x00\x00\x00\x00\x00\x87\x94u.";
+        let value = read_pickle(&mut Cursor::new(&data[..])).unwrap();
+
+        let a = value.get("a").unwrap().items().unwrap();
+        assert!(matches!(a[0], Value::Int(1)));
+        assert!(matches!(a[1], Value::Int(-2)));
+        assert_eq!(a[2].as_str(), Some("b"));
+
+        let c = value.get("c").unwrap().items().unwrap();
+        assert!(matches!(c[0], Value::None));
+        assert!(matches!(c[1], Value::Bool(true)));
+        assert!(matches!(c[2], Value::Float(f) if f == 1.5));
+    }
+
+    #[test]
+    fn memoized_lists_are_shared() {
+        // l = []; pickle.dumps([l, l], protocol=2)
+        let data = b"\x80\x02]q\x00(]q\x01h\x01e.";
+        let value = read_pickle(&mut Cursor::new(&data[..])).unwrap();
+        let items = value.items().unwrap();
+        assert_eq!(items.len(), 2);
+        match (&items[0], &items[1]) {
+            (Value::List(l1), Value::List(l2)) => assert!(std::rc::Rc::ptr_eq(l1, l2)),
+            _ => panic!("Expected lists"),
+        }
+    }
+
+    #[test]
+    fn rejects_unsupported_opcodes() {
+        assert!(read_pickle(&mut Cursor::new(&b"\x80\x04\xff."[..])).is_err());
+    }
+}
@@ -0,0 +1,319 @@
+//! Reader for binary GloVe models.
+//!
+//! The GloVe toolkit can save a trained model in a binary format
+//! (`-binary 1` or `-binary 2`). The binary model stores a word
+//! vector and a context vector for every word, each followed by a
+//! bias. The words are stored separately, in the vocabulary file
+//! that is used for training. Such models can be read as follows:
+//!
+//! ```
+//! use finalfusion_compat::glove::{GloVeVectors, ReadGloVe};
+//! use finalfusion_core::embeddings::Embeddings;
+//!
+//! let embeddings = Embeddings::read_glove(
+//!     "../testdata/glove-vectors.bin",
+//!     "../testdata/glove-vocab.txt",
+//!     GloVeVectors::Sum,
+//! )
+//! .unwrap();
+//!
+//! // Look up an embedding.
+//! let embedding = embeddings.embedding("Berlin");
+//! ```
+//!
+//! GloVe embeddings in text format can be read with `ReadText`.
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::mem::size_of;
+use std::path::Path;
+
+use byteorder::{LittleEndian, ReadBytesExt};
+use finalfusion_core::counts::WordCounts;
+use finalfusion_core::embeddings::Embeddings;
+use finalfusion_core::norms::NdNorms;
+use finalfusion_core::storage::NdArray;
+use finalfusion_core::util::l2_normalize_array;
+use finalfusion_core::vocab::SimpleVocab;
+use finalfusion_io::io::{Error, ErrorKind, Result};
+use ndarray::{Array2, Axis};
+
+/// The vectors of a GloVe model that are used as embeddings.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum GloVeVectors {
+    /// Use the word vectors.
+    Word,
+
+    /// Use the sum of the word and context vectors.
+    ///
+    /// This is also the default of the GloVe toolkit when saving
+    /// embeddings in text format.
+    #[default]
+    Sum,
+}
+
+/// Read embeddings from a binary GloVe model and a vocabulary file.
+pub trait ReadGloVe
+where
+    Self: Sized,
+{
+    /// Read the embeddings from the given paths.
+    ///
+    /// The model is read from `path_model`, which contains the word
+    /// and context vectors with their biases as `f64` values. The
+    /// words are read from `path_vocab`, which contains a word and
+    /// its count per line, separated by a space. `vectors` determines
+    /// which vectors are used as embeddings, the biases are discarded.
+    ///
+    /// The embeddings are normalized, their norms are stored in the
+    /// norms chunk. The counts are stored in the word counts chunk.
+    fn read_glove(
+        path_model: impl AsRef<Path>,
+        path_vocab: impl AsRef<Path>,
+        vectors: GloVeVectors,
+    ) -> Result<Self>;
+
+    /// Read the embeddings from the given readers.
+    ///
+    /// See `read_glove` for a description of the data. Since the
+    /// model does not have a header, the dimensionality of the
+    /// embeddings is derived from the length of the model.
+    fn read_glove_from_readers<R>(
+        model: &mut R,
+        vocab: &mut impl BufRead,
+        vectors: GloVeVectors,
+    ) -> Result<Self>
+    where
+        R: Read + Seek;
+}
+
+impl ReadGloVe for Embeddings<SimpleVocab, NdArray> {
+    fn read_glove(
+        path_model: impl AsRef<Path>,
+        path_vocab: impl AsRef<Path>,
+        vectors: GloVeVectors,
+    ) -> Result<Self> {
+        let mut model = BufReader::new(
+            File::open(path_model)
+                .map_err(|e| ErrorKind::io_error("Cannot open GloVe model file", e))?,
+        );
+        let mut vocab = BufReader::new(
+            File::open(path_vocab)
+                .map_err(|e| ErrorKind::io_error("Cannot open vocabulary file", e))?,
+        );
+
+        Self::read_glove_from_readers(&mut model, &mut vocab, vectors)
+    }
+
+    fn read_glove_from_readers<R>(
+        model: &mut R,
+        vocab: &mut impl BufRead,
+        vectors: GloVeVectors,
+    ) -> Result<Self>
+    where
+        R: Read + Seek,
+    {
+        let (words, counts) = read_glove_vocab(vocab)?;
+        let dims = glove_dims(model, words.len())?;
+
+        let mut matrix = Array2::zeros((words.len(), dims));
+        let mut row = vec![0f64; dims + 1];
+
+        let mut read_vectors = |matrix: &mut Array2<f32>| -> Result<()> {
+            for mut embedding in matrix.axis_iter_mut(Axis(0)) {
+                model
+                    .read_f64_into::<LittleEndian>(&mut row)
+                    .map_err(|e| ErrorKind::io_error("Cannot read GloVe vector", e))?;
+                // The last component is the bias.
+                embedding
+                    .iter_mut()
+                    .zip(&row)
+                    .for_each(|(sum, &component)| *sum += component as f32);
+            }
+
+            Ok(())
+        };
+
+        // The word vectors precede the context vectors.
+        read_vectors(&mut matrix)?;
+        if vectors == GloVeVectors::Sum {
+            read_vectors(&mut matrix)?;
+        }
+
+        let norms = l2_normalize_array(matrix.view_mut());
+
+        let mut embeddings = Embeddings::new(
+            None,
+            SimpleVocab::new(words),
+            NdArray::new(matrix),
+            NdNorms::new(norms),
+        );
+        embeddings.set_counts(Some(WordCounts::new(counts)));
+
+        Ok(embeddings)
+    }
+}
+
+/// Read a GloVe vocabulary file with a word and its count per line.
+fn read_glove_vocab(vocab: &mut impl BufRead) -> Result<(Vec<String>, Vec<u64>)> {
+    let mut words = Vec::new();
+    let mut counts = Vec::new();
+    let mut unique = HashSet::new();
+
+    for line in vocab.lines() {
+        let line = line.map_err(|e| ErrorKind::io_error("Cannot read vocabulary", e))?;
+        let (word, count) = line.rsplit_once(' ').ok_or_else(|| {
+            ErrorKind::Format(format!("Vocabulary line without count: {:?}", line))
+        })?;
+        let count = count.parse().map_err(|e| {
+            ErrorKind::Format(format!("Cannot parse count of word {}: {}", word, e))
+        })?;
+
+        if !unique.insert(word.to_owned()) {
+            return Err(
+                ErrorKind::Format(format!("Duplicate word in vocabulary: {}", word)).into(),
+            );
+        }
+
+        words.push(word.to_owned());
+        counts.push(count);
+    }
+
+    Ok((words, counts))
+}
+
+/// Get the dimensionality of the vectors of a binary GloVe model.
+///
+/// The reader is positioned at the start of the model afterwards.
+fn glove_dims<R>(model: &mut R, words_len: usize) -> Result<usize>
+where
+    R: Read + Seek,
+{
+    let start = model
+        .stream_position()
+        .map_err(|e| ErrorKind::io_error("Cannot get start of GloVe model", e))?;
+    let end = model
+        .seek(SeekFrom::End(0))
+        .map_err(|e| ErrorKind::io_error("Cannot get length of GloVe model", e))?;
+    model
+        .seek(SeekFrom::Start(start))
+        .map_err(|e| ErrorKind::io_error("Cannot seek to start of GloVe model", e))?;
+
+    // Every word has a word and a context vector, each with a bias.
+    let len = (end - start) as usize;
+    let n_vectors = 2 * words_len;
+    if n_vectors == 0 || len % (n_vectors * size_of::<f64>()) != 0 {
+        return Err(ErrorKind::Format(format!(
+            "GloVe model length ({} bytes) does not match a vocabulary of {} words",
+            len, words_len
+        ))
+        .into());
+    }
+
+    match len / (n_vectors * size_of::<f64>()) {
+        0 | 1 => Err(Error::from(ErrorKind::Format(
+            "GloVe model does not contain vectors".to_string(),
+        ))),
+        row_len => Ok(row_len - 1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use approx::AbsDiffEq;
+    use byteorder::{LittleEndian, WriteBytesExt};
+    use ndarray::{array, Array2};
+
+    use super::{GloVeVectors, ReadGloVe};
+    use finalfusion_core::embeddings::Embeddings;
+    use finalfusion_core::storage::{NdArray, StorageView};
+    use finalfusion_core::util::l2_normalize_array;
+    use finalfusion_core::vocab::{SimpleVocab, Vocab};
+
+    fn glove_model(vectors: &[[f64; 3]]) -> Cursor<Vec<u8>> {
+        let mut data = Vec::new();
+        for vector in vectors {
+            for &v in vector {
+                data.write_f64::<LittleEndian>(v).unwrap();
+            }
+        }
+        Cursor::new(data)
+    }
+
+    fn read_test_glove(vectors: GloVeVectors) -> Embeddings<SimpleVocab, NdArray> {
+        let mut model = glove_model(&[[1., 2., 10.], [3., 4., 20.], [5., 6., 30.], [7., 8., 40.]]);
+        let mut vocab = Cursor::new("a 5\nb 3\n");
+        Embeddings::read_glove_from_readers(&mut model, &mut vocab, vectors).unwrap()
+    }
+
+    fn normalized(mut matrix: Array2<f32>) -> Array2<f32> {
+        l2_normalize_array(matrix.view_mut());
+        matrix
+    }
+
+    #[test]
+    fn read_glove_word_vectors() {
+        let embeds = read_test_glove(GloVeVectors::Word);
+        assert_eq!(embeds.vocab().words(), &["a", "b"]);
+        assert_eq!(embeds.counts().unwrap().to_vec(), vec![5, 3]);
+        assert!(embeds
+            .storage()
+            .view()
+            .abs_diff_eq(&normalized(array![[1., 2.], [3., 4.]]), 1e-6));
+        assert_eq!(
+            embeds.norms().unwrap().view(),
+            array![5f32.sqrt(), 5.].view()
+        );
+    }
+
+    #[test]
+    fn read_glove_summed_vectors() {
+        let embeds = read_test_glove(GloVeVectors::Sum);
+        assert!(embeds
+            .storage()
+            .view()
+            .abs_diff_eq(&normalized(array![[6., 8.], [10., 12.]]), 1e-6));
+        assert_eq!(embeds.norms().unwrap().view(), array![10., 244f32.sqrt()]);
+    }
+
+    #[test]
+    fn read_glove_rejects_mismatching_vocab() {
+        let mut vocab = Cursor::new("a 5\nb 3\nc 1\n");
+        let mut model = glove_model(&[[1., 2., 0.], [3., 4., 0.]]);
+        assert!(Embeddings::read_glove_from_readers(
+            &mut model,
+            &mut vocab,
+            GloVeVectors::default()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn read_glove_rejects_invalid_vocab() {
+        for vocab in &["a\n", "a x\n", "a 1\na 2\n", ""] {
+            let mut model = glove_model(&[[1., 2., 0.], [3., 4., 0.]]);
+            assert!(Embeddings::read_glove_from_readers(
+                &mut model,
+                &mut Cursor::new(vocab),
+                GloVeVectors::default()
+            )
+            .is_err());
+        }
+    }
+
+    #[test]
+    fn read_glove_files() {
+        let embeds: Embeddings<SimpleVocab, NdArray> = Embeddings::read_glove(
+            "../testdata/glove-vectors.bin",
+            "../testdata/glove-vocab.txt",
+            GloVeVectors::Word,
+        )
+        .unwrap();
+        assert_eq!(embeds.vocab().words_len(), 5);
+        assert_eq!(embeds.dims(), 4);
+        assert!(embeds.counts().unwrap().is_descending());
+    }
+}
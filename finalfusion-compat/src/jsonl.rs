@@ -0,0 +1,179 @@
+//! Writer for embeddings in the JSON lines format.
+//!
+//! Embeddings can be written as JSON lines, with one JSON object per
+//! word. For example:
+//!
+//! ```text
+//! {"word":"Berlin","embedding":[0.6,0.8],"norm":2.5}
+//! ```
+//!
+//! This format is intended for inspecting embeddings and for
+//! comparing embeddings with text tools such as `diff` or `jq`. Since
+//! every component is written as a decimal number, the format is not
+//! suitable for storing embeddings.
+
+use std::io::Write;
+
+use finalfusion_core::embeddings::{EmbeddingWithNorm, Embeddings};
+use finalfusion_core::storage::Storage;
+use finalfusion_core::vocab::Vocab;
+use finalfusion_io::io::{ErrorKind, Result};
+use serde::Serialize;
+
+/// Object that is written for every word.
+#[derive(Serialize)]
+struct JsonlEmbedding<'a> {
+    word: &'a str,
+    embedding: Vec<f32>,
+    norm: f32,
+}
+
+/// Write embeddings in the JSON lines format.
+///
+/// Every line is an object with the fields `word`, `embedding`, and
+/// `norm`. The embedding is the normalized embedding as returned by
+/// `Embeddings::embedding`, the norm is the norm of the original
+/// embedding.
+///
+/// ```
+/// use std::fs::File;
+/// use std::io::BufReader;
+///
+/// use finalfusion_compat::jsonl::WriteJsonl;
+/// use finalfusion_core::embeddings::Embeddings;
+/// use finalfusion_core::storage::StorageWrap;
+/// use finalfusion_core::vocab::VocabWrap;
+/// use finalfusion_io::io::ReadEmbeddings;
+///
+/// let mut reader = BufReader::new(File::open("../testdata/similarity.fifu").unwrap());
+/// let embeddings: Embeddings<VocabWrap, StorageWrap> =
+///     Embeddings::read_embeddings(&mut reader).unwrap();
+///
+/// let mut data = Vec::new();
+/// embeddings.write_jsonl_first(&mut data, 10).unwrap();
+/// assert_eq!(String::from_utf8(data).unwrap().lines().count(), 10);
+/// ```
+pub trait WriteJsonl {
+    /// Write the embeddings of all words in the vocabulary.
+    ///
+    /// Subword embeddings are not written.
+    fn write_jsonl(&self, write: &mut impl Write) -> Result<()>;
+
+    /// Write the embeddings of the first `n` words in the vocabulary.
+    fn write_jsonl_first(&self, write: &mut impl Write, n: usize) -> Result<()>;
+
+    /// Write the embeddings of the given words.
+    ///
+    /// The embeddings are written in the order of `words`. Words
+    /// that are not in the vocabulary are written when an embedding
+    /// can be constructed from subword units, other words are
+    /// skipped.
+    fn write_jsonl_words(&self, write: &mut impl Write, words: &[impl AsRef<str>]) -> Result<()>;
+}
+
+impl<V, S> WriteJsonl for Embeddings<V, S>
+where
+    V: Vocab,
+    S: Storage,
+{
+    fn write_jsonl(&self, write: &mut impl Write) -> Result<()> {
+        self.write_jsonl_first(write, self.vocab().words_len())
+    }
+
+    fn write_jsonl_first(&self, write: &mut impl Write, n: usize) -> Result<()> {
+        for (word, embed_norm) in self.iter_with_norms().take(n) {
+            write_jsonl_line(write, &word, embed_norm)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_jsonl_words(&self, write: &mut impl Write, words: &[impl AsRef<str>]) -> Result<()> {
+        for word in words {
+            let word = word.as_ref();
+            if let Some(embed_norm) = self.embedding_with_norm(word) {
+                write_jsonl_line(write, word, embed_norm)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn write_jsonl_line(
+    write: &mut impl Write,
+    word: &str,
+    embed_norm: EmbeddingWithNorm,
+) -> Result<()> {
+    let line = JsonlEmbedding {
+        word,
+        embedding: embed_norm.embedding.iter().cloned().collect(),
+        norm: embed_norm.norm,
+    };
+
+    serde_json::to_writer(&mut *write, &line)
+        .map_err(|e| ErrorKind::Format(format!("Cannot serialize embedding to JSON: {}", e)))?;
+    writeln!(write).map_err(|e| ErrorKind::io_error("Cannot write JSON line", e).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{array, Array1, Array2};
+
+    use super::WriteJsonl;
+    use finalfusion_core::embeddings::Embeddings;
+    use finalfusion_core::norms::NdNorms;
+    use finalfusion_core::storage::NdArray;
+    use finalfusion_core::vocab::SimpleVocab;
+
+    fn test_embeddings() -> Embeddings<SimpleVocab, NdArray> {
+        let vocab = SimpleVocab::new(vec!["a".to_owned(), "b\"".to_owned(), "c".to_owned()]);
+        let matrix: Array2<f32> = array![[0.6, 0.8], [1., 0.], [0., 1.]];
+        let norms: Array1<f32> = array![2.5, 1., 0.5];
+        Embeddings::new(None, vocab, NdArray::new(matrix), NdNorms::new(norms))
+    }
+
+    fn write_to_string(write: impl FnOnce(&mut Vec<u8>)) -> String {
+        let mut data = Vec::new();
+        write(&mut data);
+        String::from_utf8(data).unwrap()
+    }
+
+    #[test]
+    fn write_jsonl_all_words() {
+        let embeds = test_embeddings();
+        assert_eq!(
+            write_to_string(|data| embeds.write_jsonl(data).unwrap()),
+            "{\"word\":\"a\",\"embedding\":[0.6,0.8],\"norm\":2.5}\n\
+             {\"word\":\"b\\\"\",\"embedding\":[1.0,0.0],\"norm\":1.0}\n\
+             {\"word\":\"c\",\"embedding\":[0.0,1.0],\"norm\":0.5}\n"
+        );
+    }
+
+    #[test]
+    fn write_jsonl_first_words() {
+        let embeds = test_embeddings();
+        assert_eq!(
+            write_to_string(|data| embeds.write_jsonl_first(data, 1).unwrap()),
+            "{\"word\":\"a\",\"embedding\":[0.6,0.8],\"norm\":2.5}\n"
+        );
+        assert_eq!(
+            write_to_string(|data| embeds.write_jsonl_first(data, 10).unwrap())
+                .lines()
+                .count(),
+            3
+        );
+    }
+
+    #[test]
+    fn write_jsonl_word_list() {
+        let embeds = test_embeddings();
+        assert_eq!(
+            write_to_string(|data| embeds
+                .write_jsonl_words(data, &["c", "unknown", "a"])
+                .unwrap()),
+            "{\"word\":\"c\",\"embedding\":[0.0,1.0],\"norm\":0.5}\n\
+             {\"word\":\"a\",\"embedding\":[0.6,0.8],\"norm\":2.5}\n"
+        );
+    }
+}
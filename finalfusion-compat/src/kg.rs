@@ -0,0 +1,400 @@
+//! Reader for knowledge graph embeddings.
+//!
+//! Knowledge graph embedding models, such as TransE, train embeddings
+//! for entities and relations. Such embeddings are commonly dumped as
+//! two TSV files, one for entities and one for relations. Each line
+//! contains a label, a tab, and the embedding components:
+//!
+//! *label\tcomponent_1\tcomponent_2 ... component_n*
+//!
+//! The components can be separated by tabs or spaces. The reader
+//! constructs embeddings with a `NamespacedVocab`, where the entities
+//! are in the `entity` namespace and the relations in the `relation`
+//! namespace. For example:
+//!
+//! ```
+//! use std::io::Cursor;
+//!
+//! use finalfusion_compat::kg::{ReadKGEmbeddings, Translation};
+//! use finalfusion_core::embeddings::Embeddings;
+//!
+//! let mut entities = Cursor::new("Berlin\t1 0\nGermany\t1 1\nParis\t0 1\n");
+//! let mut relations = Cursor::new("capital_of\t0 1\n");
+//!
+//! let embeddings = Embeddings::read_kg_embeddings(&mut entities, &mut relations).unwrap();
+//! let results = embeddings.translation("Berlin", "capital_of", 1).unwrap();
+//! assert_eq!(results[0].word, "entity::Germany");
+//! ```
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::io::BufRead;
+
+use finalfusion_core::embeddings::Embeddings;
+use finalfusion_core::norms::NdNorms;
+use finalfusion_core::storage::{NdArray, Storage, StorageViewMut};
+use finalfusion_core::util::l2_normalize_array;
+use finalfusion_core::vocab::{NamespacedVocab, Vocab};
+use finalfusion_core::warnings::{Warning, Warnings};
+use finalfusion_io::io::{Error, ErrorKind, Result};
+use ndarray::{Array1, Array2};
+use ordered_float::NotNan;
+
+/// Namespace of entities in knowledge graph embeddings.
+pub const ENTITY_NAMESPACE: &str = "entity";
+
+/// Namespace of relations in knowledge graph embeddings.
+pub const RELATION_NAMESPACE: &str = "relation";
+
+/// Method to construct `Embeddings` from knowledge graph embeddings.
+pub trait ReadKGEmbeddings
+where
+    Self: Sized,
+{
+    /// Read entity and relation embeddings from the given readers.
+    fn read_kg_embeddings<E, R>(entities: &mut E, relations: &mut R) -> Result<Self>
+    where
+        E: BufRead,
+        R: BufRead;
+
+    /// Read entity and relation embeddings from the given readers.
+    ///
+    /// Non-fatal issues, such as skipped empty lines and embeddings
+    /// that cannot be normalized, are added to `warnings`.
+    fn read_kg_embeddings_with_warnings<E, R>(
+        entities: &mut E,
+        relations: &mut R,
+        warnings: &mut Warnings,
+    ) -> Result<Self>
+    where
+        E: BufRead,
+        R: BufRead;
+}
+
+impl ReadKGEmbeddings for Embeddings<NamespacedVocab, NdArray> {
+    fn read_kg_embeddings<E, R>(entities: &mut E, relations: &mut R) -> Result<Self>
+    where
+        E: BufRead,
+        R: BufRead,
+    {
+        Self::read_kg_embeddings_with_warnings(entities, relations, &mut Warnings::new())
+    }
+
+    fn read_kg_embeddings_with_warnings<E, R>(
+        entities: &mut E,
+        relations: &mut R,
+        warnings: &mut Warnings,
+    ) -> Result<Self>
+    where
+        E: BufRead,
+        R: BufRead,
+    {
+        let mut data = Vec::new();
+        let mut dims = None;
+        let entity_labels =
+            read_tsv_embeds(entities, ENTITY_NAMESPACE, &mut data, &mut dims, warnings)?;
+        let relation_labels = read_tsv_embeds(
+            relations,
+            RELATION_NAMESPACE,
+            &mut data,
+            &mut dims,
+            warnings,
+        )?;
+
+        let vocab = NamespacedVocab::new(vec![
+            (ENTITY_NAMESPACE, entity_labels),
+            (RELATION_NAMESPACE, relation_labels),
+        ]);
+
+        let shape = (data.len() / dims.unwrap_or(1), dims.unwrap_or(0));
+        let mut storage = NdArray::new(Array2::from_shape_vec(shape, data).map_err(Error::Shape)?);
+        let norms = l2_normalize_array(storage.view_mut());
+        warnings.push_zero_norms(vocab.iter(), norms.view());
+
+        Ok(Embeddings::new(None, vocab, storage, NdNorms::new(norms)))
+    }
+}
+
+/// A translation query result.
+///
+/// This data structure is used to store a pair consisting of an entity
+/// and its distance to the translated head entity.
+#[derive(Debug, Eq, PartialEq)]
+pub struct TranslationResult<'a> {
+    pub distance: NotNan<f32>,
+    pub word: &'a str,
+}
+
+impl<'a> Ord for TranslationResult<'a> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match self.distance.cmp(&other.distance) {
+            Ordering::Equal => self.word.cmp(other.word),
+            ordering => ordering,
+        }
+    }
+}
+
+impl<'a> PartialOrd for TranslationResult<'a> {
+    fn partial_cmp(&self, other: &TranslationResult) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Trait for relation translation queries.
+pub trait Translation {
+    /// Perform a relation translation query.
+    ///
+    /// This method returns the entities that are the closest tails for
+    /// the translation of the `head` entity by `relation`. Entities are
+    /// ranked by the TransE score:
+    ///
+    /// *‖embedding(head) + embedding(relation) - embedding(tail)‖*
+    ///
+    /// The distance is computed over the unnormalized embeddings when
+    /// norms are available. Lookup transforms are not applied. `head`
+    /// is excluded from the results.
+    ///
+    /// At most, `limit` results are returned, ordered by increasing
+    /// distance. `Result::Err` is returned when the head entity or the
+    /// relation is not known, indicating which of the two were present.
+    fn translation(
+        &self,
+        head: &str,
+        relation: &str,
+        limit: usize,
+    ) -> std::result::Result<Vec<TranslationResult<'_>>, [bool; 2]>;
+}
+
+impl<S> Translation for Embeddings<NamespacedVocab, S>
+where
+    S: Storage,
+{
+    fn translation(
+        &self,
+        head: &str,
+        relation: &str,
+        limit: usize,
+    ) -> std::result::Result<Vec<TranslationResult<'_>>, [bool; 2]> {
+        let head_idx = self.vocab().idx_in(ENTITY_NAMESPACE, head);
+        let relation_idx = self.vocab().idx_in(RELATION_NAMESPACE, relation);
+        let (head_idx, relation_idx) = match (head_idx, relation_idx) {
+            (Some(head_idx), Some(relation_idx)) => (head_idx, relation_idx),
+            (head_idx, relation_idx) => return Err([head_idx.is_some(), relation_idx.is_some()]),
+        };
+
+        let norm = |idx: usize| self.norms().map(|norms| norms[idx]).unwrap_or(1.);
+        let unnormalized = |idx: usize| self.storage().embedding(idx).into_owned() * norm(idx);
+        let translated = unnormalized(head_idx) + unnormalized(relation_idx);
+
+        let mut tail = Array1::zeros(translated.len());
+        let mut results = BinaryHeap::with_capacity(limit + 1);
+        for idx in self
+            .vocab()
+            .namespace_range(ENTITY_NAMESPACE)
+            .unwrap_or(0..0)
+        {
+            if idx == head_idx {
+                continue;
+            }
+
+            self.storage().embedding_into(idx, tail.view_mut());
+            let tail_norm = norm(idx);
+            let distance = translated
+                .iter()
+                .zip(tail.iter())
+                .map(|(&t, &c)| (t - tail_norm * c).powi(2))
+                .sum::<f32>()
+                .sqrt();
+
+            results.push(TranslationResult {
+                distance: NotNan::new(distance).expect("Encountered NaN"),
+                word: &self.vocab().words()[idx],
+            });
+            if results.len() > limit {
+                results.pop();
+            }
+        }
+
+        Ok(results.into_sorted_vec())
+    }
+}
+
+/// Read labels and embeddings from a TSV file.
+///
+/// The embedding components are appended to `data`. `dims` is used to
+/// check that all embeddings have the same dimensionality.
+fn read_tsv_embeds<R>(
+    reader: &mut R,
+    namespace: &str,
+    data: &mut Vec<f32>,
+    dims: &mut Option<usize>,
+    warnings: &mut Warnings,
+) -> Result<Vec<String>>
+where
+    R: BufRead,
+{
+    let mut labels = Vec::new();
+
+    for (idx, line) in reader.lines().enumerate() {
+        let line =
+            line.map_err(|e| ErrorKind::io_error("Cannot read line from embedding file", e))?;
+        if line.trim().is_empty() {
+            warnings.push(Warning::EmptyLine {
+                line: idx + 1,
+                namespace: Some(namespace.to_owned()),
+            });
+            continue;
+        }
+
+        let mut fields = line.splitn(2, '\t');
+        let label = fields.next().expect("splitn returned no fields");
+        let components = fields.next().ok_or_else(|| {
+            ErrorKind::Format(format!("Line does not contain an embedding: {}", label))
+        })?;
+
+        let data_len = data.len();
+        for part in components
+            .split(|c: char| c.is_ascii_whitespace())
+            .filter(|part| !part.is_empty())
+        {
+            data.push(part.parse().map_err(|e| {
+                ErrorKind::Format(format!("Cannot parse vector component '{}': {}", part, e))
+            })?);
+        }
+
+        let embed_len = data.len() - data_len;
+        if embed_len == 0 {
+            return Err(ErrorKind::Format(format!(
+                "Line does not contain an embedding: {}",
+                label
+            ))
+            .into());
+        }
+
+        match *dims {
+            Some(dims) if dims != embed_len => {
+                return Err(ErrorKind::Format(format!(
+                    "Incorrect embedding dimensionality for '{}', expected: {}, got: {}",
+                    label, dims, embed_len,
+                ))
+                .into())
+            }
+            Some(_) => (),
+            None => *dims = Some(embed_len),
+        }
+
+        labels.push(label.to_owned());
+    }
+
+    // Duplicate labels would result in a panic in the vocab constructor.
+    let mut sorted = labels.iter().collect::<Vec<_>>();
+    sorted.sort();
+    if let Some(label) = sorted.windows(2).find(|w| w[0] == w[1]).map(|w| w[0]) {
+        return Err(ErrorKind::Format(format!("Duplicate label: {}", label)).into());
+    }
+
+    Ok(labels)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::{ReadKGEmbeddings, Translation};
+    use finalfusion_core::embeddings::Embeddings;
+    use finalfusion_core::storage::NdArray;
+    use finalfusion_core::vocab::{NamespacedVocab, Vocab};
+    use finalfusion_core::warnings::{Warning, Warnings};
+
+    #[test]
+    fn read_kg_embeddings() {
+        let mut entities = Cursor::new("Berlin\t3 4\nGermany\t0\t2\n\n");
+        let mut relations = Cursor::new("capital_of\t1 0\n");
+        let embeds: Embeddings<NamespacedVocab, NdArray> =
+            Embeddings::read_kg_embeddings(&mut entities, &mut relations).unwrap();
+
+        assert_eq!(
+            embeds.vocab().words(),
+            &[
+                "entity::Berlin".to_owned(),
+                "entity::Germany".to_owned(),
+                "relation::capital_of".to_owned()
+            ]
+        );
+
+        let berlin = embeds.embedding_with_norm("entity::Berlin").unwrap();
+        assert_eq!(berlin.norm, 5.0);
+        assert_eq!(berlin.into_unnormalized().to_vec(), vec![3.0, 4.0]);
+    }
+
+    #[test]
+    fn read_kg_embeddings_with_warnings() {
+        let mut entities = Cursor::new("Berlin\t3 4\n\nGermany\t0\t0\n");
+        let mut relations = Cursor::new("capital_of\t1 0\n");
+        let mut warnings = Warnings::new();
+        Embeddings::<NamespacedVocab, NdArray>::read_kg_embeddings_with_warnings(
+            &mut entities,
+            &mut relations,
+            &mut warnings,
+        )
+        .unwrap();
+
+        assert_eq!(
+            warnings.into_iter().collect::<Vec<_>>(),
+            vec![
+                Warning::EmptyLine {
+                    line: 2,
+                    namespace: Some("entity".to_owned())
+                },
+                Warning::ZeroNorm {
+                    word: "entity::Germany".to_owned()
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn read_kg_embeddings_fails_on_inconsistent_dims() {
+        let mut entities = Cursor::new("Berlin\t3 4\n");
+        let mut relations = Cursor::new("capital_of\t1 0 0\n");
+        assert!(Embeddings::<NamespacedVocab, NdArray>::read_kg_embeddings(
+            &mut entities,
+            &mut relations
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn translation() {
+        let mut entities = Cursor::new("Berlin\t1 0\nGermany\t2 2\nParis\t0 1\n");
+        let mut relations = Cursor::new("capital_of\t0 1\n");
+        let embeds: Embeddings<NamespacedVocab, NdArray> =
+            Embeddings::read_kg_embeddings(&mut entities, &mut relations).unwrap();
+
+        let results = embeds.translation("Berlin", "capital_of", 5).unwrap();
+        let words = results.iter().map(|r| r.word).collect::<Vec<_>>();
+        assert_eq!(words, vec!["entity::Paris", "entity::Germany"]);
+        assert!((results[0].distance.into_inner() - 1.).abs() < 1e-6);
+        assert!((results[1].distance.into_inner() - 2f32.sqrt()).abs() < 1e-6);
+
+        let results = embeds.translation("Berlin", "capital_of", 1).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].word, "entity::Paris");
+
+        assert_eq!(
+            embeds.translation("Berlin", "located_in", 5).unwrap_err(),
+            [true, false]
+        );
+    }
+
+    #[test]
+    fn read_kg_embeddings_fails_on_duplicate_labels() {
+        let mut entities = Cursor::new("Berlin\t3 4\nBerlin\t1 2\n");
+        let mut relations = Cursor::new("capital_of\t1 0\n");
+        assert!(Embeddings::<NamespacedVocab, NdArray>::read_kg_embeddings(
+            &mut entities,
+            &mut relations
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,67 @@
+//! Readers/writers for other embedding formats.
+//!
+//! This crate reads and writes embeddings of the `finalfusion-core`
+//! crate in the fastText, word2vec, text, and other formats. The
+//! `finalfusion` crate re-exports this crate as its `compat` module.
+//!
+//! Some readers recover from irregularities in embedding files. The
+//! `*_with_warnings` methods of these readers record such issues in
+//! `Warnings`:
+//!
+//! ```
+//! use std::fs::File;
+//! use std::io::BufReader;
+//!
+//! use finalfusion_compat::text::ReadTextDims;
+//! use finalfusion_core::embeddings::Embeddings;
+//! use finalfusion_core::storage::NdArray;
+//! use finalfusion_core::vocab::SimpleVocab;
+//! use finalfusion_core::warnings::Warnings;
+//!
+//! let mut reader = BufReader::new(File::open("../testdata/similarity.txt").unwrap());
+//!
+//! let mut warnings = Warnings::new();
+//! let embeddings: Embeddings<SimpleVocab, NdArray> =
+//!     Embeddings::read_text_dims_with_warnings(&mut reader, false, &mut warnings).unwrap();
+//!
+//! for warning in &warnings {
+//!     eprintln!("Warning: {}", warning);
+//! }
+//! ```
+
+pub mod compression;
+
+pub mod delimited;
+
+pub mod duplicates;
+
+pub mod fasttext;
+
+pub mod gensim;
+
+pub mod glove;
+
+pub mod jsonl;
+
+pub mod kg;
+
+pub mod muse;
+
+pub mod norms;
+
+pub mod npy;
+
+pub mod npz;
+
+pub mod order;
+
+pub mod sentencepiece;
+
+pub mod tensorflow;
+
+#[cfg(test)]
+mod test_util;
+
+pub mod text;
+
+pub mod word2vec;
@@ -0,0 +1,297 @@
+//! Reader for aligned multilingual embeddings.
+//!
+//! Aligned multilingual embeddings, such as those of
+//! [MUSE](https://github.com/facebookresearch/MUSE), map the words of
+//! several languages into one embedding space. They are distributed
+//! as one text file with dimensions per language, named after the
+//! language, e.g. `wiki.multi.en.vec` and `wiki.multi.de.vec`.
+//!
+//! The files of a directory can be read as a map from languages to
+//! embeddings with `read_aligned_languages`, or as one set of
+//! multilingual embeddings with `read_aligned_multilingual`. In the
+//! latter case, words are tagged with their language:
+//!
+//! ```
+//! use finalfusion_compat::muse::{AlignedOptions, read_aligned_multilingual};
+//!
+//! let embeddings =
+//!     read_aligned_multilingual("../testdata/aligned", &AlignedOptions::default()).unwrap();
+//! assert!(embeddings.embedding("en:dog").is_some());
+//! assert!(embeddings.language_embedding("nl", "hond").is_some());
+//! ```
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::Path;
+
+use finalfusion_core::embeddings::Embeddings;
+use finalfusion_core::norms::NdNorms;
+use finalfusion_core::storage::{NdArray, StorageView};
+use finalfusion_core::vocab::{LanguageTagFormat, LanguageVocab, SimpleVocab, Vocab};
+use finalfusion_io::io::{Error, ErrorKind, Result};
+use ndarray::{stack, Axis};
+
+use crate::text::{ReadTextDims, TextOptions};
+
+/// Extension of aligned embedding files.
+const ALIGNED_EXTENSION: &str = ".vec";
+
+/// Options for reading aligned multilingual embeddings.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct AlignedOptions {
+    /// The options for reading the text file of each language.
+    pub text: TextOptions,
+
+    /// The format of the language tags of multilingual embeddings.
+    pub format: LanguageTagFormat,
+
+    /// The languages to read.
+    ///
+    /// If `None`, all languages of the directory are read.
+    pub languages: Option<Vec<String>>,
+}
+
+impl AlignedOptions {
+    /// Set the options for reading the text file of each language.
+    pub fn text(mut self, options: TextOptions) -> Self {
+        self.text = options;
+        self
+    }
+
+    /// Set the format of the language tags of multilingual embeddings.
+    pub fn format(mut self, format: LanguageTagFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Only read the given languages.
+    pub fn languages<I, S>(mut self, languages: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.languages = Some(languages.into_iter().map(Into::into).collect());
+        self
+    }
+}
+
+/// Read the aligned embeddings of each language in a directory.
+///
+/// Every file in `dir` with the extension `.vec`, optionally followed
+/// by `.gz`, is read as the embeddings of a language. The language is
+/// the last period-separated component of the file name without
+/// extension, so `wiki.multi.en.vec` contains the embeddings of `en`.
+/// Other files are ignored.
+///
+/// Fails when a language has more than one file, when a requested
+/// language has no file, and when the embeddings of the languages
+/// do not have the same dimensionality.
+pub fn read_aligned_languages(
+    dir: impl AsRef<Path>,
+    options: &AlignedOptions,
+) -> Result<BTreeMap<String, Embeddings<SimpleVocab, NdArray>>> {
+    let dir = dir.as_ref();
+    let entries = fs::read_dir(dir)
+        .map_err(|e| ErrorKind::io_error(format!("Cannot read directory {}", dir.display()), e))?;
+
+    let mut paths = BTreeMap::new();
+    for entry in entries {
+        let path = entry
+            .map_err(|e| ErrorKind::io_error(format!("Cannot read entry of {}", dir.display()), e))?
+            .path();
+        let language = match path.file_name().and_then(|name| name.to_str()) {
+            Some(name) => match file_language(name) {
+                Some(language) => language.to_owned(),
+                None => continue,
+            },
+            None => continue,
+        };
+
+        if let Some(languages) = &options.languages {
+            if !languages.contains(&language) {
+                continue;
+            }
+        }
+
+        if let Some(other) = paths.insert(language.clone(), path.clone()) {
+            return Err(ErrorKind::Format(format!(
+                "Language '{}' has more than one file: {} and {}",
+                language,
+                other.display(),
+                path.display()
+            ))
+            .into());
+        }
+    }
+
+    if let Some(languages) = &options.languages {
+        if let Some(missing) = languages.iter().find(|&l| !paths.contains_key(l)) {
+            return Err(ErrorKind::Format(format!(
+                "No embeddings for language '{}' in {}",
+                missing,
+                dir.display()
+            ))
+            .into());
+        }
+    }
+
+    let mut embeddings = BTreeMap::new();
+    let mut dims = None;
+    for (language, path) in paths {
+        let f = File::open(&path)
+            .map_err(|e| ErrorKind::io_error(format!("Cannot open {}", path.display()), e))?;
+        let embeds: Embeddings<SimpleVocab, NdArray> =
+            Embeddings::read_text_dims_with_options(&mut BufReader::new(f), &options.text)?;
+
+        match dims {
+            None => dims = Some((language.clone(), embeds.dims())),
+            Some((ref first, first_dims)) if first_dims != embeds.dims() => {
+                return Err(ErrorKind::Format(format!(
+                    "Embeddings of '{}' and '{}' differ in dimensionality: {} and {}",
+                    first,
+                    language,
+                    first_dims,
+                    embeds.dims()
+                ))
+                .into())
+            }
+            _ => (),
+        }
+
+        embeddings.insert(language, embeds);
+    }
+
+    Ok(embeddings)
+}
+
+/// Read aligned embeddings of a directory as multilingual embeddings.
+///
+/// The files are read as in `read_aligned_languages`. The vocabulary
+/// consists of the words of all languages, ordered by language, and
+/// tagged with their language using the tag format of the options.
+pub fn read_aligned_multilingual(
+    dir: impl AsRef<Path>,
+    options: &AlignedOptions,
+) -> Result<Embeddings<LanguageVocab<SimpleVocab>, NdArray>> {
+    let languages = read_aligned_languages(dir, options)?;
+    if languages.is_empty() {
+        return Err(ErrorKind::Format("Directory contains no aligned embeddings".into()).into());
+    }
+
+    let mut words = Vec::new();
+    for (language, embeds) in &languages {
+        words.extend(
+            embeds
+                .vocab()
+                .words()
+                .iter()
+                .map(|word| options.format.tag(language, word)),
+        );
+    }
+
+    let storage = NdArray::new(
+        stack(
+            Axis(0),
+            &languages
+                .values()
+                .map(|embeds| embeds.storage().view())
+                .collect::<Vec<_>>(),
+        )
+        .map_err(Error::Shape)?,
+    );
+
+    let norms = languages
+        .values()
+        .map(|embeds| embeds.norms().map(|norms| norms.view()))
+        .collect::<Option<Vec<_>>>()
+        .map(|norms| stack(Axis(0), &norms))
+        .transpose()
+        .map_err(Error::Shape)?;
+
+    let vocab = LanguageVocab::with_format(SimpleVocab::new(words), options.format.clone());
+    Ok(match norms {
+        Some(norms) => Embeddings::new(None, vocab, storage, NdNorms::new(norms)),
+        None => Embeddings::new_without_norms(None, vocab, storage),
+    })
+}
+
+/// Get the language of an aligned embedding file from its name.
+fn file_language(name: &str) -> Option<&str> {
+    let stem = name.strip_suffix(".gz").unwrap_or(name);
+    let stem = stem.strip_suffix(ALIGNED_EXTENSION)?;
+    stem.rsplit('.')
+        .next()
+        .filter(|language| !language.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::arr1;
+
+    use crate::norms::NormsPolicy;
+    use crate::text::TextOptions;
+    use finalfusion_core::vocab::{LanguageTagFormat, Vocab};
+
+    use super::{file_language, read_aligned_languages, read_aligned_multilingual, AlignedOptions};
+
+    #[test]
+    fn languages_from_file_names() {
+        assert_eq!(file_language("wiki.multi.en.vec"), Some("en"));
+        assert_eq!(file_language("de.vec.gz"), Some("de"));
+        assert_eq!(file_language("wiki.multi..vec"), None);
+        assert_eq!(file_language("wiki.multi.en.txt"), None);
+        assert_eq!(file_language("README"), None);
+    }
+
+    #[test]
+    fn read_languages() {
+        let languages =
+            read_aligned_languages("../testdata/aligned", &AlignedOptions::default()).unwrap();
+        assert_eq!(languages.keys().collect::<Vec<_>>(), vec!["en", "nl"]);
+        assert_eq!(languages["en"].vocab().words(), &["dog", "cat"]);
+        assert_eq!(languages["nl"].vocab().words(), &["hond", "kat", "huis"]);
+    }
+
+    #[test]
+    fn read_requested_languages() {
+        let options = AlignedOptions::default().languages(vec!["nl"]);
+        let languages = read_aligned_languages("../testdata/aligned", &options).unwrap();
+        assert_eq!(languages.keys().collect::<Vec<_>>(), vec!["nl"]);
+
+        let options = AlignedOptions::default().languages(vec!["nl", "fr"]);
+        assert!(read_aligned_languages("../testdata/aligned", &options).is_err());
+    }
+
+    #[test]
+    fn read_multilingual() {
+        let options = AlignedOptions::default().format(LanguageTagFormat::conceptnet());
+        let embeds = read_aligned_multilingual("../testdata/aligned", &options).unwrap();
+        assert_eq!(
+            embeds.vocab().inner().words(),
+            &[
+                "/c/en/dog",
+                "/c/en/cat",
+                "/c/nl/hond",
+                "/c/nl/kat",
+                "/c/nl/huis"
+            ]
+        );
+        assert_eq!(embeds.vocab().languages(), &["en", "nl"]);
+        assert_eq!(embeds.vocab().language_len("nl"), 3);
+        assert_eq!(
+            embeds.language_embedding("nl", "huis").unwrap(),
+            arr1(&[0.6, 0.8])
+        );
+        assert_eq!(embeds.norms().unwrap().view(), arr1(&[1., 2., 1., 1., 5.]));
+    }
+
+    #[test]
+    fn read_multilingual_unnormalized() {
+        let options =
+            AlignedOptions::default().text(TextOptions::default().norms(NormsPolicy::Unnormalized));
+        let embeds = read_aligned_multilingual("../testdata/aligned", &options).unwrap();
+        assert!(embeds.norms().is_none());
+        assert_eq!(embeds.embedding("nl:huis").unwrap(), arr1(&[3., 4.]));
+    }
+}
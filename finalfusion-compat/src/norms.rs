@@ -0,0 +1,139 @@
+//! Handling of embedding norms in readers.
+//!
+//! finalfusion embeddings are normalized to unit vectors. The
+//! original norms of the embeddings are stored in a norms chunk, so
+//! that the original vectors can be restored, e.g. with
+//! `EmbeddingWithNorm::into_unnormalized`. By default, the word2vec,
+//! text, and fastText readers normalize embeddings in this manner.
+//! `NormsPolicy` can be set in the reader options to discard the norms
+//! or to keep the embeddings as they are in the file:
+//!
+//! ```
+//! use std::fs::File;
+//! use std::io::BufReader;
+//!
+//! use finalfusion_compat::norms::NormsPolicy;
+//! use finalfusion_compat::text::{ReadTextDims, TextOptions};
+//! use finalfusion_core::embeddings::Embeddings;
+//!
+//! let mut reader = BufReader::new(File::open("../testdata/similarity.txt").unwrap());
+//!
+//! let options = TextOptions::default().norms(NormsPolicy::Unnormalized);
+//! let embeddings = Embeddings::read_text_dims_with_options(&mut reader, &options)
+//!     .unwrap();
+//! assert!(embeddings.norms().is_none());
+//! ```
+
+use finalfusion_core::embeddings::Embeddings;
+use finalfusion_core::norms::NdNorms;
+use finalfusion_core::storage::{NdArray, StorageViewMut};
+use finalfusion_core::util::l2_normalize_array;
+use finalfusion_core::vocab::Vocab;
+use finalfusion_core::warnings::Warnings;
+use ndarray::s;
+
+/// Policy for the norms of embeddings that are read.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum NormsPolicy {
+    /// Normalize the embeddings and store their original norms.
+    #[default]
+    Keep,
+
+    /// Normalize the embeddings and discard their original norms.
+    Discard,
+
+    /// Do not normalize the embeddings.
+    ///
+    /// Since the embeddings retain their magnitudes, no norms are
+    /// stored. Similarity and analogy queries assume unit vectors, so
+    /// they give different results for unnormalized embeddings.
+    Unnormalized,
+}
+
+/// Normalize the embeddings of words according to `policy`.
+///
+/// Only the first `vocab.words_len()` rows of the storage are
+/// normalized, subword embeddings are left as-is. Warnings are
+/// added for embeddings with norm zero.
+pub(crate) fn normalize<V>(
+    vocab: V,
+    mut storage: NdArray,
+    policy: NormsPolicy,
+    warnings: &mut Warnings,
+) -> Embeddings<V, NdArray>
+where
+    V: Vocab,
+{
+    if policy == NormsPolicy::Unnormalized {
+        return Embeddings::new_without_norms(None, vocab, storage);
+    }
+
+    #[allow(clippy::deref_addrof)]
+    let norms = l2_normalize_array(storage.view_mut().slice_mut(s![0..vocab.words_len(), ..]));
+    warnings.push_zero_norms(vocab.iter(), norms.view());
+
+    match policy {
+        NormsPolicy::Keep => Embeddings::new(None, vocab, storage, NdNorms::new(norms)),
+        _ => Embeddings::new_without_norms(None, vocab, storage),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+
+    use finalfusion_core::storage::{NdArray, StorageView};
+    use finalfusion_core::vocab::SimpleVocab;
+    use finalfusion_core::warnings::{Warning, Warnings};
+
+    use super::{normalize, NormsPolicy};
+
+    fn vocab() -> SimpleVocab {
+        SimpleVocab::new(vec!["a".to_owned(), "b".to_owned()])
+    }
+
+    #[test]
+    fn normalize_keeps_norms() {
+        let mut warnings = Warnings::new();
+        let embeds = normalize(
+            vocab(),
+            NdArray::new(array![[3., 4.], [0., 0.]]),
+            NormsPolicy::Keep,
+            &mut warnings,
+        );
+        assert_eq!(embeds.storage().view(), array![[0.6, 0.8], [0., 0.]]);
+        assert_eq!(embeds.norms().unwrap().view(), array![5., 0.]);
+        assert_eq!(
+            warnings.into_iter().collect::<Vec<_>>(),
+            vec![Warning::ZeroNorm {
+                word: "b".to_owned()
+            }]
+        );
+    }
+
+    #[test]
+    fn normalize_discards_norms() {
+        let embeds = normalize(
+            vocab(),
+            NdArray::new(array![[3., 4.], [1., 0.]]),
+            NormsPolicy::Discard,
+            &mut Warnings::new(),
+        );
+        assert_eq!(embeds.storage().view(), array![[0.6, 0.8], [1., 0.]]);
+        assert!(embeds.norms().is_none());
+    }
+
+    #[test]
+    fn unnormalized_embeddings_are_retained() {
+        let mut warnings = Warnings::new();
+        let embeds = normalize(
+            vocab(),
+            NdArray::new(array![[3., 4.], [0., 0.]]),
+            NormsPolicy::Unnormalized,
+            &mut warnings,
+        );
+        assert_eq!(embeds.storage().view(), array![[3., 4.], [0., 0.]]);
+        assert!(embeds.norms().is_none());
+        assert!(warnings.is_empty());
+    }
+}
@@ -0,0 +1,644 @@
+//! Reader and writer for embedding matrices in the NumPy `.npy` format.
+//!
+//! Embeddings can be written as a `.npy` matrix with a separate
+//! vocabulary file, which can be loaded in Python without additional
+//! bindings:
+//!
+//! ```python
+//! import numpy as np
+//!
+//! matrix = np.load("embeddings.npy")
+//! with open("embeddings.vocab", encoding="utf-8", newline="\n") as f:
+//!     words = f.read().split("\n")[:-1]
+//! ```
+//!
+//! Conversely, embeddings can be read from a `.npy` matrix and a
+//! vocabulary file with one word per line:
+//!
+//! ```
+//! use finalfusion_compat::npy::ReadNpy;
+//! use finalfusion_core::embeddings::Embeddings;
+//!
+//! let embeddings = Embeddings::read_npy(
+//!     "../testdata/gensim.kv.vectors.npy",
+//!     "../testdata/gensim.vocab",
+//! )
+//! .unwrap();
+//!
+//! // Look up an embedding.
+//! let embedding = embeddings.embedding("Berlin");
+//! ```
+
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use finalfusion_core::embeddings::Embeddings;
+use finalfusion_core::norms::NdNorms;
+use finalfusion_core::storage::{NdArray, Storage};
+use finalfusion_core::util::l2_normalize_array;
+use finalfusion_core::vocab::{SimpleVocab, Vocab};
+use finalfusion_io::io::{Error, ErrorKind, Result};
+use ndarray::{Array1, Array2, Axis, ShapeBuilder};
+
+/// Magic string of `.npy` files.
+const NPY_MAGIC: &[u8] = b"\x93NUMPY";
+
+/// Data type of the components of a NumPy array.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum NpyType {
+    F32,
+    F64,
+}
+
+impl NpyType {
+    /// Get the data type of a NumPy type string, such as `<f4`.
+    ///
+    /// Only little-endian floating point types are supported.
+    pub fn from_descr(descr: &str) -> Result<Self> {
+        match descr {
+            "<f4" | "=f4" | "f4" | "float32" => Ok(NpyType::F32),
+            "<f8" | "=f8" | "f8" | "float64" => Ok(NpyType::F64),
+            descr => Err(ErrorKind::Format(format!(
+                "Unsupported NumPy data type: {}, expected little-endian f4 or f8",
+                descr
+            ))
+            .into()),
+        }
+    }
+
+    /// Get the size of a component in bytes.
+    pub fn size(self) -> usize {
+        match self {
+            NpyType::F32 => 4,
+            NpyType::F64 => 8,
+        }
+    }
+}
+
+/// Construct a matrix from raw NumPy array data.
+pub(crate) fn matrix_from_bytes(
+    data: &[u8],
+    data_type: NpyType,
+    shape: (usize, usize),
+    fortran_order: bool,
+) -> Result<Array2<f32>> {
+    let len = shape
+        .0
+        .checked_mul(shape.1)
+        .ok_or_else(|| ErrorKind::Format(format!("NumPy array shape is too large: {:?}", shape)))?;
+    if data.len() != len * data_type.size() {
+        return Err(ErrorKind::Format(format!(
+            "NumPy array with shape {:?} has {} bytes of data, expected {}",
+            shape,
+            data.len(),
+            len * data_type.size()
+        ))
+        .into());
+    }
+
+    let mut components = vec![0f32; len];
+    let mut rdr = data;
+    match data_type {
+        NpyType::F32 => rdr
+            .read_f32_into::<LittleEndian>(&mut components)
+            .map_err(|e| ErrorKind::io_error("Cannot read NumPy array data", e))?,
+        NpyType::F64 => {
+            for component in &mut components {
+                *component = rdr
+                    .read_f64::<LittleEndian>()
+                    .map_err(|e| ErrorKind::io_error("Cannot read NumPy array data", e))?
+                    as f32;
+            }
+        }
+    }
+
+    let matrix =
+        Array2::from_shape_vec(shape.set_f(fortran_order), components).map_err(Error::Shape)?;
+    if fortran_order {
+        // Store the matrix in row-major order, as other storage.
+        Ok(matrix.as_standard_layout().into_owned())
+    } else {
+        Ok(matrix)
+    }
+}
+
+/// Read the header of a `.npy` file.
+///
+/// After reading the header, the reader is positioned at the start
+/// of the array data.
+pub(crate) fn read_npy_header<R>(read: &mut R) -> Result<NpyHeader>
+where
+    R: Read,
+{
+    let mut magic = [0u8; 6];
+    read.read_exact(&mut magic)
+        .map_err(|e| ErrorKind::io_error("Cannot read NumPy magic", e))?;
+    if magic != NPY_MAGIC {
+        return Err(ErrorKind::Format("File is not in the NumPy .npy format".to_string()).into());
+    }
+
+    let major = read
+        .read_u8()
+        .map_err(|e| ErrorKind::io_error("Cannot read NumPy format version", e))?;
+    read.read_u8()
+        .map_err(|e| ErrorKind::io_error("Cannot read NumPy format version", e))?;
+    let header_len = match major {
+        1 => read
+            .read_u16::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read NumPy header length", e))?
+            as usize,
+        2 | 3 => read
+            .read_u32::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read NumPy header length", e))?
+            as usize,
+        version => {
+            return Err(
+                ErrorKind::Format(format!("Unsupported NumPy format version: {}", version)).into(),
+            )
+        }
+    };
+
+    let mut header = vec![0u8; header_len];
+    read.read_exact(&mut header)
+        .map_err(|e| ErrorKind::io_error("Cannot read NumPy header", e))?;
+    let header = String::from_utf8(header)
+        .map_err(|e| ErrorKind::Format(format!("NumPy header is not valid UTF-8: {}", e)))?;
+    NpyHeader::parse(&header)
+}
+
+/// Read the data of a `.npy` array.
+///
+/// `item_size` is the size of an array element in bytes.
+pub(crate) fn read_npy_data<R>(
+    read: &mut R,
+    header: &NpyHeader,
+    item_size: usize,
+) -> Result<Vec<u8>>
+where
+    R: Read,
+{
+    let len = header
+        .shape
+        .iter()
+        .try_fold(item_size, |len, &dim| len.checked_mul(dim))
+        .ok_or_else(|| {
+            ErrorKind::Format(format!(
+                "NumPy array shape is too large: {:?}",
+                header.shape
+            ))
+        })?;
+    let mut data = vec![0u8; len];
+    read.read_exact(&mut data)
+        .map_err(|e| ErrorKind::io_error("Cannot read NumPy array data", e))?;
+    Ok(data)
+}
+
+/// Read a matrix in the `.npy` format.
+///
+/// The matrix must be two-dimensional and have a little-endian
+/// floating point data type. `f64` components are converted to `f32`.
+pub(crate) fn read_npy_matrix<R>(read: &mut R) -> Result<Array2<f32>>
+where
+    R: Read,
+{
+    let header = read_npy_header(read)?;
+    let data_type = NpyType::from_descr(&header.descr)?;
+    let shape = match header.shape[..] {
+        [rows, cols] => (rows, cols),
+        _ => {
+            return Err(ErrorKind::Format(format!(
+                "Expected a two-dimensional NumPy array, got shape: {:?}",
+                header.shape
+            ))
+            .into())
+        }
+    };
+
+    let data = read_npy_data(read, &header, data_type.size())?;
+    matrix_from_bytes(&data, data_type, shape, header.fortran_order)
+}
+
+/// Read a vector in the `.npy` format.
+///
+/// The vector must be one-dimensional and have a little-endian
+/// floating point data type. `f64` components are converted to `f32`.
+pub(crate) fn read_npy_vector<R>(read: &mut R) -> Result<Array1<f32>>
+where
+    R: Read,
+{
+    let header = read_npy_header(read)?;
+    let data_type = NpyType::from_descr(&header.descr)?;
+    let len = match header.shape[..] {
+        [len] => len,
+        _ => {
+            return Err(ErrorKind::Format(format!(
+                "Expected a one-dimensional NumPy array, got shape: {:?}",
+                header.shape
+            ))
+            .into())
+        }
+    };
+
+    let data = read_npy_data(read, &header, data_type.size())?;
+    Ok(matrix_from_bytes(&data, data_type, (1, len), false)?.index_axis_move(Axis(0), 0))
+}
+
+/// Read embeddings from a `.npy` matrix and a vocabulary file.
+pub trait ReadNpy
+where
+    Self: Sized,
+{
+    /// Read the embeddings from the given paths.
+    ///
+    /// The embedding matrix is read from `path_matrix` in the `.npy`
+    /// format. The matrix must be two-dimensional and have a
+    /// little-endian `f4` or `f8` data type. The words are read from
+    /// `path_vocab`, which contains one word per line in the order of
+    /// the matrix rows.
+    ///
+    /// The number of matrix rows must be equal to the number of
+    /// words. The embeddings are normalized, their norms are stored
+    /// in the norms chunk.
+    fn read_npy(path_matrix: impl AsRef<Path>, path_vocab: impl AsRef<Path>) -> Result<Self>;
+
+    /// Read the embeddings from the given readers.
+    ///
+    /// See `read_npy` for a description of the data.
+    fn read_npy_from_readers(matrix: &mut impl Read, vocab: &mut impl BufRead) -> Result<Self>;
+}
+
+impl ReadNpy for Embeddings<SimpleVocab, NdArray> {
+    fn read_npy(path_matrix: impl AsRef<Path>, path_vocab: impl AsRef<Path>) -> Result<Self> {
+        let mut matrix = BufReader::new(
+            File::open(path_matrix)
+                .map_err(|e| ErrorKind::io_error("Cannot open NumPy matrix file", e))?,
+        );
+        let mut vocab = BufReader::new(
+            File::open(path_vocab)
+                .map_err(|e| ErrorKind::io_error("Cannot open vocabulary file", e))?,
+        );
+
+        Self::read_npy_from_readers(&mut matrix, &mut vocab)
+    }
+
+    fn read_npy_from_readers(matrix: &mut impl Read, vocab: &mut impl BufRead) -> Result<Self> {
+        let words = vocab
+            .lines()
+            .collect::<std::io::Result<Vec<_>>>()
+            .map_err(|e| ErrorKind::io_error("Cannot read vocabulary", e))?;
+
+        let mut unique = HashSet::with_capacity(words.len());
+        if let Some(word) = words.iter().find(|&word| !unique.insert(word)) {
+            return Err(
+                ErrorKind::Format(format!("Duplicate word in vocabulary: {}", word)).into(),
+            );
+        }
+
+        let mut matrix = read_npy_matrix(matrix)?;
+        if matrix.nrows() != words.len() {
+            return Err(ErrorKind::Format(format!(
+                "Embedding matrix has {} rows, but the vocabulary contains {} words",
+                matrix.nrows(),
+                words.len()
+            ))
+            .into());
+        }
+
+        let norms = l2_normalize_array(matrix.view_mut());
+
+        Ok(Embeddings::new(
+            None,
+            SimpleVocab::new(words),
+            NdArray::new(matrix),
+            NdNorms::new(norms),
+        ))
+    }
+}
+
+/// Write embeddings as a `.npy` matrix and a vocabulary file.
+pub trait WriteNpy {
+    /// Write the embeddings to the given paths.
+    ///
+    /// The embedding matrix is written to `path_matrix` as a
+    /// row-major `f32` array in the `.npy` format. The words are
+    /// written to `path_vocab` as UTF-8 text, one word per line, in
+    /// the order of the matrix rows.
+    ///
+    /// Only the embeddings of known words are written, subword
+    /// embeddings are not written. The embeddings are written as
+    /// returned by `Embeddings::embedding`.
+    fn write_npy(&self, path_matrix: impl AsRef<Path>, path_vocab: impl AsRef<Path>) -> Result<()>;
+
+    /// Write the embeddings to the given writers.
+    ///
+    /// See `write_npy` for a description of the written data.
+    fn write_npy_to_writers(&self, matrix: &mut impl Write, vocab: &mut impl Write) -> Result<()>;
+}
+
+impl<V, S> WriteNpy for Embeddings<V, S>
+where
+    V: Vocab,
+    S: Storage,
+{
+    fn write_npy(&self, path_matrix: impl AsRef<Path>, path_vocab: impl AsRef<Path>) -> Result<()> {
+        let mut matrix = BufWriter::new(
+            File::create(path_matrix)
+                .map_err(|e| ErrorKind::io_error("Cannot create NumPy matrix file", e))?,
+        );
+        let mut vocab = BufWriter::new(
+            File::create(path_vocab)
+                .map_err(|e| ErrorKind::io_error("Cannot create vocabulary file", e))?,
+        );
+
+        self.write_npy_to_writers(&mut matrix, &mut vocab)?;
+
+        matrix
+            .flush()
+            .map_err(|e| ErrorKind::io_error("Cannot flush NumPy matrix file", e))?;
+        vocab
+            .flush()
+            .map_err(|e| ErrorKind::io_error("Cannot flush vocabulary file", e))
+            .map_err(Into::into)
+    }
+
+    fn write_npy_to_writers(&self, matrix: &mut impl Write, vocab: &mut impl Write) -> Result<()> {
+        // Check the words first, to avoid writing a partial matrix.
+        if let Some(word) = self.vocab().words().iter().find(|word| word.contains('\n')) {
+            return Err(ErrorKind::Format(format!(
+                "Cannot write word with a newline to vocabulary file: {:?}",
+                word
+            ))
+            .into());
+        }
+
+        write_npy_header(matrix, "<f4", &[self.vocab().words_len(), self.dims()])?;
+        for (word, embedding) in self.iter() {
+            for &component in embedding.view() {
+                matrix
+                    .write_f32::<LittleEndian>(component)
+                    .map_err(|e| ErrorKind::io_error("Cannot write embedding component", e))?;
+            }
+
+            writeln!(vocab, "{}", word).map_err(|e| ErrorKind::io_error("Cannot write word", e))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Write the header of a `.npy` file for a row-major array.
+///
+/// `descr` is the NumPy type string of the array elements, such as
+/// `<f4`.
+pub(crate) fn write_npy_header(write: &mut dyn Write, descr: &str, shape: &[usize]) -> Result<()> {
+    // One-tuples require a trailing comma in Python.
+    let shape = match shape {
+        [len] => format!("({},)", len),
+        shape => format!(
+            "({})",
+            shape
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    };
+    let mut header = format!(
+        "{{'descr': '{}', 'fortran_order': False, 'shape': {}, }}",
+        descr, shape
+    );
+
+    // The header is padded with spaces and terminated by a newline,
+    // such that the data is aligned by 64 bytes.
+    let prefix_len = NPY_MAGIC.len() + 4;
+    let padding = (64 - (prefix_len + header.len() + 1) % 64) % 64;
+    header.push_str(&" ".repeat(padding));
+    header.push('\n');
+
+    let header_len = u16::try_from(header.len())
+        .map_err(|_| ErrorKind::Format("NumPy header is too long".to_string()))?;
+
+    write
+        .write_all(NPY_MAGIC)
+        .and_then(|_| write.write_all(&[1, 0]))
+        .and_then(|_| write.write_u16::<LittleEndian>(header_len))
+        .and_then(|_| write.write_all(header.as_bytes()))
+        .map_err(|e| ErrorKind::io_error("Cannot write NumPy header", e).into())
+}
+
+/// Header of a `.npy` file.
+pub(crate) struct NpyHeader {
+    /// NumPy type string of the array elements, such as `<f4`.
+    pub descr: String,
+    pub fortran_order: bool,
+    pub shape: Vec<usize>,
+}
+
+impl NpyHeader {
+    /// Parse the header dictionary of a `.npy` file.
+    ///
+    /// The header is a Python dictionary literal, such as
+    /// `{'descr': '<f4', 'fortran_order': False, 'shape': (3, 2), }`.
+    fn parse(header: &str) -> Result<Self> {
+        let descr = header_value(header, "descr")?
+            .trim_matches(|c| c == '\'' || c == '"')
+            .to_owned();
+
+        let fortran_order = match header_value(header, "fortran_order")? {
+            "True" => true,
+            "False" => false,
+            value => {
+                return Err(ErrorKind::Format(format!(
+                    "Invalid NumPy fortran_order value: {}",
+                    value
+                ))
+                .into())
+            }
+        };
+
+        let shape = header_value(header, "shape")?
+            .trim_start_matches('(')
+            .trim_end_matches(')')
+            .split(',')
+            .map(str::trim)
+            .filter(|dim| !dim.is_empty())
+            .map(|dim| {
+                dim.parse::<usize>().map_err(|e| {
+                    Error::from(ErrorKind::Format(format!(
+                        "Invalid NumPy shape dimension {}: {}",
+                        dim, e
+                    )))
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(NpyHeader {
+            descr,
+            fortran_order,
+            shape,
+        })
+    }
+}
+
+/// Get the value of a key in a `.npy` header dictionary.
+fn header_value<'a>(header: &'a str, key: &str) -> Result<&'a str> {
+    let missing = || ErrorKind::Format(format!("NumPy header does not contain '{}'", key));
+
+    let start = header.find(&format!("'{}'", key)).ok_or_else(missing)? + key.len() + 2;
+    let value = header[start..]
+        .trim_start()
+        .strip_prefix(':')
+        .ok_or_else(missing)?
+        .trim_start();
+
+    // The shape is a tuple, which contains commas.
+    let end = if value.starts_with('(') {
+        value.find(')').map(|idx| idx + 1)
+    } else {
+        value.find([',', '}'])
+    }
+    .ok_or_else(missing)?;
+
+    Ok(value[..end].trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use approx::AbsDiffEq;
+    use byteorder::{LittleEndian, WriteBytesExt};
+    use ndarray::array;
+
+    use super::{read_npy_matrix, ReadNpy, WriteNpy};
+    use crate::test_util::test_embeddings;
+    use finalfusion_core::embeddings::Embeddings;
+    use finalfusion_core::vocab::Vocab;
+
+    fn npy_bytes(header: &str, data: &[f64], f64_data: bool) -> Vec<u8> {
+        let mut bytes = b"\x93NUMPY\x01\x00".to_vec();
+        bytes
+            .write_u16::<LittleEndian>(header.len() as u16)
+            .unwrap();
+        bytes.extend_from_slice(header.as_bytes());
+        for &component in data {
+            if f64_data {
+                bytes.write_f64::<LittleEndian>(component).unwrap();
+            } else {
+                bytes.write_f32::<LittleEndian>(component as f32).unwrap();
+            }
+        }
+        bytes
+    }
+
+    #[test]
+    fn read_npy_row_major() {
+        let data = npy_bytes(
+            "{'descr': '<f4', 'fortran_order': False, 'shape': (2, 3), }\n",
+            &[1., 2., 3., 4., 5., 6.],
+            false,
+        );
+        let matrix = read_npy_matrix(&mut Cursor::new(data)).unwrap();
+        assert_eq!(matrix, array![[1., 2., 3.], [4., 5., 6.]]);
+    }
+
+    #[test]
+    fn read_npy_fortran_order_f64() {
+        let data = npy_bytes(
+            "{'descr': '<f8', 'fortran_order': True, 'shape': (2, 3), }\n",
+            &[1., 4., 2., 5., 3., 6.],
+            true,
+        );
+        let matrix = read_npy_matrix(&mut Cursor::new(data)).unwrap();
+        assert_eq!(matrix, array![[1., 2., 3.], [4., 5., 6.]]);
+        assert!(matrix.is_standard_layout());
+    }
+
+    #[test]
+    fn write_npy_roundtrip() {
+        let embeddings = test_embeddings(vec!["a".to_owned(), "b".to_owned()]);
+
+        let mut matrix = Vec::new();
+        let mut vocab = Vec::new();
+        embeddings
+            .write_npy_to_writers(&mut matrix, &mut vocab)
+            .unwrap();
+
+        // The data has to be aligned by 64 bytes.
+        let header_len = u16::from_le_bytes([matrix[8], matrix[9]]) as usize;
+        assert_eq!((10 + header_len) % 64, 0);
+        assert_eq!(matrix[10 + header_len - 1], b'\n');
+
+        assert_eq!(
+            read_npy_matrix(&mut Cursor::new(matrix)).unwrap(),
+            array![[0., 1., 2.], [3., 4., 5.]]
+        );
+        assert_eq!(String::from_utf8(vocab).unwrap(), "a\nb\n");
+    }
+
+    #[test]
+    fn read_npy_embeddings() {
+        let embeddings = test_embeddings(vec!["a".to_owned(), "b".to_owned()]);
+        let mut matrix = Vec::new();
+        let mut vocab = Vec::new();
+        embeddings
+            .write_npy_to_writers(&mut matrix, &mut vocab)
+            .unwrap();
+
+        let read =
+            Embeddings::read_npy_from_readers(&mut Cursor::new(matrix), &mut &*vocab).unwrap();
+        assert_eq!(read.vocab().words(), &["a", "b"]);
+        for (word, embedding) in embeddings.iter() {
+            let norm = embedding.dot(&embedding).sqrt();
+            assert!(read
+                .embedding(&word)
+                .unwrap()
+                .abs_diff_eq(&(embedding.to_owned() / norm), 1e-6));
+        }
+    }
+
+    #[test]
+    fn read_npy_rejects_mismatching_vocab() {
+        let embeddings = test_embeddings(vec!["a".to_owned(), "b".to_owned()]);
+        let mut matrix = Vec::new();
+        let mut vocab = Vec::new();
+        embeddings
+            .write_npy_to_writers(&mut matrix, &mut vocab)
+            .unwrap();
+
+        for vocab in &["a\n", "a\nb\nc\n", "a\na\n"] {
+            assert!(Embeddings::read_npy_from_readers(
+                &mut Cursor::new(&matrix),
+                &mut vocab.as_bytes()
+            )
+            .is_err());
+        }
+    }
+
+    #[test]
+    fn write_npy_rejects_words_with_newlines() {
+        let embeddings = test_embeddings(vec!["a".to_owned(), "b\nc".to_owned()]);
+
+        let mut matrix = Vec::new();
+        let mut vocab = Vec::new();
+        assert!(embeddings
+            .write_npy_to_writers(&mut matrix, &mut vocab)
+            .is_err());
+        assert!(matrix.is_empty());
+    }
+
+    #[test]
+    fn read_npy_rejects_unsupported_arrays() {
+        for header in &[
+            "{'descr': '>f4', 'fortran_order': False, 'shape': (2, 3), }\n",
+            "{'descr': '<f4', 'fortran_order': False, 'shape': (6,), }\n",
+        ] {
+            let data = npy_bytes(header, &[1., 2., 3., 4., 5., 6.], false);
+            assert!(read_npy_matrix(&mut Cursor::new(data)).is_err());
+        }
+    }
+}
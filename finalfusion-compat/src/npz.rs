@@ -0,0 +1,614 @@
+//! Reader and writer for embeddings in the NumPy `.npz` format.
+//!
+//! A `.npz` file is a zip archive of `.npy` arrays. Embeddings are
+//! stored as the following arrays:
+//!
+//! * `matrix`: the embedding matrix, a row-major `f32` array with a
+//!   row for each word.
+//! * `vocab`: the words, a Unicode string array in the order of the
+//!   matrix rows.
+//! * `norms`: the norms of the embeddings, an `f32` array. This array
+//!   is only stored when the embeddings have norms.
+//!
+//! The archive can be loaded in Python without additional bindings:
+//!
+//! ```python
+//! import numpy as np
+//!
+//! data = np.load("embeddings.npz")
+//! matrix, words = data["matrix"], data["vocab"]
+//! ```
+//!
+//! Conversely, archives written with `numpy.savez` can be read:
+//!
+//! ```python
+//! np.savez("embeddings.npz", matrix=matrix, vocab=np.array(words))
+//! ```
+//!
+//! Archives written with `numpy.savez_compressed` are not supported,
+//! since their entries are compressed.
+
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use byteorder::{LittleEndian, WriteBytesExt};
+use finalfusion_core::embeddings::Embeddings;
+use finalfusion_core::norms::NdNorms;
+use finalfusion_core::storage::{NdArray, Storage};
+use finalfusion_core::util::l2_normalize_array;
+use finalfusion_core::vocab::{SimpleVocab, Vocab};
+use finalfusion_io::asset::find_zip_entry;
+use finalfusion_io::io::{ErrorKind, Result};
+
+use crate::npy::{
+    read_npy_data, read_npy_header, read_npy_matrix, read_npy_vector, write_npy_header,
+};
+
+const MATRIX_ENTRY: &str = "matrix.npy";
+const NORMS_ENTRY: &str = "norms.npy";
+const VOCAB_ENTRY: &str = "vocab.npy";
+
+const ZIP_LOCAL_HEADER_SIGNATURE: u32 = 0x0403_4b50;
+const ZIP_CENTRAL_HEADER_SIGNATURE: u32 = 0x0201_4b50;
+const ZIP_END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x0605_4b50;
+
+/// Zip version that is needed to extract uncompressed entries (2.0).
+const ZIP_VERSION: u16 = 20;
+
+/// Modification date of entries in MS-DOS format (1980-01-01).
+const ZIP_DATE: u16 = (1 << 5) | 1;
+
+/// Read embeddings from a `.npz` archive.
+pub trait ReadNpz
+where
+    Self: Sized,
+{
+    /// Read the embeddings from the archive at the given path.
+    ///
+    /// The archive must contain the `matrix` and `vocab` arrays. The
+    /// matrix must be two-dimensional and have a little-endian `f4`
+    /// or `f8` data type. The vocabulary must be a one-dimensional
+    /// array of Unicode strings with one word per matrix row.
+    ///
+    /// If the archive contains a `norms` array, the matrix is assumed
+    /// to be normalized and the norms are stored in the norms chunk.
+    /// Otherwise, the embeddings are normalized and their norms are
+    /// stored in the norms chunk.
+    fn read_npz(path: impl AsRef<Path>) -> Result<Self>;
+
+    /// Read the embeddings from the given reader.
+    ///
+    /// See `read_npz` for a description of the data.
+    fn read_npz_from_reader<R>(read: &mut R) -> Result<Self>
+    where
+        R: Read + Seek;
+}
+
+impl ReadNpz for Embeddings<SimpleVocab, NdArray> {
+    fn read_npz(path: impl AsRef<Path>) -> Result<Self> {
+        let mut read = BufReader::new(
+            File::open(path).map_err(|e| ErrorKind::io_error("Cannot open NumPy archive", e))?,
+        );
+        Self::read_npz_from_reader(&mut read)
+    }
+
+    fn read_npz_from_reader<R>(read: &mut R) -> Result<Self>
+    where
+        R: Read + Seek,
+    {
+        let words = match npz_entry(read, VOCAB_ENTRY)? {
+            Some(mut entry) => read_npy_words(&mut entry)?,
+            None => return Err(missing_entry(VOCAB_ENTRY)),
+        };
+
+        let mut unique = HashSet::with_capacity(words.len());
+        if let Some(word) = words.iter().find(|&word| !unique.insert(word)) {
+            return Err(
+                ErrorKind::Format(format!("Duplicate word in vocabulary: {}", word)).into(),
+            );
+        }
+
+        let mut matrix = match npz_entry(read, MATRIX_ENTRY)? {
+            Some(mut entry) => read_npy_matrix(&mut entry)?,
+            None => return Err(missing_entry(MATRIX_ENTRY)),
+        };
+        if matrix.nrows() != words.len() {
+            return Err(ErrorKind::Format(format!(
+                "Embedding matrix has {} rows, but the vocabulary contains {} words",
+                matrix.nrows(),
+                words.len()
+            ))
+            .into());
+        }
+
+        let norms = match npz_entry(read, NORMS_ENTRY)? {
+            Some(mut entry) => {
+                let norms = read_npy_vector(&mut entry)?;
+                if norms.len() != words.len() {
+                    return Err(ErrorKind::Format(format!(
+                        "Archive contains {} norms, but the vocabulary contains {} words",
+                        norms.len(),
+                        words.len()
+                    ))
+                    .into());
+                }
+                norms
+            }
+            None => l2_normalize_array(matrix.view_mut()),
+        };
+
+        Ok(Embeddings::new(
+            None,
+            SimpleVocab::new(words),
+            NdArray::new(matrix),
+            NdNorms::new(norms),
+        ))
+    }
+}
+
+/// Write embeddings to a `.npz` archive.
+pub trait WriteNpz {
+    /// Write the embeddings to an archive at the given path.
+    ///
+    /// The embedding matrix, vocabulary, and norms are written as
+    /// uncompressed entries of the archive. See the module
+    /// documentation for a description of the arrays.
+    ///
+    /// Only the embeddings of known words are written, subword
+    /// embeddings are not written. Archives that require ZIP64, i.e.
+    /// with arrays of 4 GiB or larger, are not supported.
+    fn write_npz(&self, path: impl AsRef<Path>) -> Result<()>;
+
+    /// Write the embeddings to the given writer.
+    ///
+    /// See `write_npz` for a description of the written data.
+    fn write_npz_to_writer(&self, write: &mut impl Write) -> Result<()>;
+}
+
+impl<V, S> WriteNpz for Embeddings<V, S>
+where
+    V: Vocab,
+    S: Storage,
+{
+    fn write_npz(&self, path: impl AsRef<Path>) -> Result<()> {
+        let mut write = BufWriter::new(
+            File::create(path)
+                .map_err(|e| ErrorKind::io_error("Cannot create NumPy archive", e))?,
+        );
+
+        self.write_npz_to_writer(&mut write)?;
+
+        write
+            .flush()
+            .map_err(|e| ErrorKind::io_error("Cannot flush NumPy archive", e))
+            .map_err(Into::into)
+    }
+
+    fn write_npz_to_writer(&self, write: &mut impl Write) -> Result<()> {
+        // NumPy strips trailing null characters from strings.
+        if let Some(word) = self
+            .vocab()
+            .words()
+            .iter()
+            .find(|word| word.ends_with('\0'))
+        {
+            return Err(ErrorKind::Format(format!(
+                "Cannot write word with a trailing null character to NumPy archive: {:?}",
+                word
+            ))
+            .into());
+        }
+
+        let mut archive = ZipWriter::new(write);
+
+        archive.write_entry(MATRIX_ENTRY, |write| {
+            write_npy_header(write, "<f4", &[self.vocab().words_len(), self.dims()])?;
+            for (_, embedding) in self.iter() {
+                for &component in embedding.view() {
+                    write
+                        .write_f32::<LittleEndian>(component)
+                        .map_err(|e| ErrorKind::io_error("Cannot write embedding component", e))?;
+                }
+            }
+            Ok(())
+        })?;
+
+        if let Some(norms) = self.norms() {
+            archive.write_entry(NORMS_ENTRY, |write| {
+                write_npy_header(write, "<f4", &[norms.len()])?;
+                for &norm in norms.iter() {
+                    write
+                        .write_f32::<LittleEndian>(norm)
+                        .map_err(|e| ErrorKind::io_error("Cannot write norm", e))?;
+                }
+                Ok(())
+            })?;
+        }
+
+        archive.write_entry(VOCAB_ENTRY, |write| {
+            write_npy_words(write, self.vocab().words())
+        })?;
+
+        archive.finish()
+    }
+}
+
+/// Get a reader for the data of an archive entry.
+///
+/// Returns `None` when the archive does not contain the entry.
+fn npz_entry<'a, R>(read: &'a mut R, name: &str) -> Result<Option<io::Take<&'a mut R>>>
+where
+    R: Read + Seek,
+{
+    let (offset, len) = match find_zip_entry(read, name)? {
+        Some(entry) => entry,
+        None => return Ok(None),
+    };
+
+    read.seek(SeekFrom::Start(offset))
+        .map_err(|e| ErrorKind::io_error(format!("Cannot seek to archive entry {}", name), e))?;
+
+    Ok(Some(read.take(len)))
+}
+
+fn missing_entry(name: &str) -> finalfusion_io::io::Error {
+    ErrorKind::Format(format!("NumPy archive does not contain '{}'", name)).into()
+}
+
+/// Read a one-dimensional Unicode string array in the `.npy` format.
+fn read_npy_words<R>(read: &mut R) -> Result<Vec<String>>
+where
+    R: Read,
+{
+    let header = read_npy_header(read)?;
+
+    // Strings are stored as fixed-length UTF-32, e.g. <U10.
+    let n_chars = header
+        .descr
+        .strip_prefix(['<', '='])
+        .and_then(|descr| descr.strip_prefix('U'))
+        .and_then(|len| len.parse::<usize>().ok())
+        .ok_or_else(|| {
+            ErrorKind::Format(format!(
+                "Expected a little-endian NumPy Unicode string array, got type: {}",
+                header.descr
+            ))
+        })?;
+    if header.shape.len() != 1 {
+        return Err(ErrorKind::Format(format!(
+            "Expected a one-dimensional NumPy array, got shape: {:?}",
+            header.shape
+        ))
+        .into());
+    }
+
+    let item_size = n_chars.checked_mul(4).ok_or_else(|| {
+        ErrorKind::Format(format!("NumPy string length is too large: {}", n_chars))
+    })?;
+    let data = read_npy_data(read, &header, item_size)?;
+
+    // A zero-length string type has no data, so iterate over the
+    // number of strings rather than chunks of the data.
+    (0..header.shape[0])
+        .map(|idx| {
+            let item = &data[idx * item_size..(idx + 1) * item_size];
+            let word = item
+                .chunks_exact(4)
+                .map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+                .take_while(|&c| c != 0)
+                .map(|c| {
+                    char::from_u32(c).ok_or_else(|| {
+                        ErrorKind::Format(format!("Invalid Unicode code point in word: {}", c))
+                    })
+                })
+                .collect::<std::result::Result<String, _>>()?;
+
+            // Null characters can only occur as padding.
+            if item[word.chars().count() * 4..].iter().any(|&b| b != 0) {
+                return Err(ErrorKind::Format(format!(
+                    "Word contains a null character: {:?}",
+                    word
+                ))
+                .into());
+            }
+
+            Ok(word)
+        })
+        .collect()
+}
+
+/// Write a one-dimensional Unicode string array in the `.npy` format.
+fn write_npy_words(write: &mut dyn Write, words: &[String]) -> Result<()> {
+    // NumPy does not support zero-length Unicode strings as array type.
+    let n_chars = words
+        .iter()
+        .map(|word| word.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max(1);
+
+    write_npy_header(write, &format!("<U{}", n_chars), &[words.len()])?;
+    for word in words {
+        let padding = n_chars - word.chars().count();
+        for c in word.chars().chain(std::iter::repeat('\0').take(padding)) {
+            write
+                .write_u32::<LittleEndian>(c as u32)
+                .map_err(|e| ErrorKind::io_error("Cannot write word", e))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Writer of zip archives with uncompressed entries.
+struct ZipWriter<'a, W> {
+    write: &'a mut W,
+    offset: u64,
+    central_directory: Vec<u8>,
+    n_entries: u16,
+}
+
+impl<'a, W> ZipWriter<'a, W>
+where
+    W: Write,
+{
+    fn new(write: &'a mut W) -> Self {
+        ZipWriter {
+            write,
+            offset: 0,
+            central_directory: Vec::new(),
+            n_entries: 0,
+        }
+    }
+
+    /// Write an entry with the data written by `data`.
+    ///
+    /// The local header precedes the data and contains its checksum,
+    /// so `data` is called twice: once to compute the checksum and
+    /// length, and once to write the data. This avoids buffering the
+    /// data in memory.
+    fn write_entry<F>(&mut self, name: &str, data: F) -> Result<()>
+    where
+        F: Fn(&mut dyn Write) -> Result<()>,
+    {
+        let mut checksum = ChecksumWriter::default();
+        data(&mut checksum)?;
+
+        let (crc, len) = (checksum.crc, zip_u32(checksum.len)?);
+        let offset = zip_u32(self.offset)?;
+
+        let header = zip_header(ZIP_LOCAL_HEADER_SIGNATURE, name, crc, len, None)
+            .map_err(|e| ErrorKind::io_error("Cannot write zip local file header", e))?;
+        self.central_directory.extend(
+            zip_header(ZIP_CENTRAL_HEADER_SIGNATURE, name, crc, len, Some(offset))
+                .map_err(|e| ErrorKind::io_error("Cannot write zip central directory header", e))?,
+        );
+
+        self.write
+            .write_all(&header)
+            .map_err(|e| ErrorKind::io_error("Cannot write zip local file header", e))?;
+        data(self.write)?;
+
+        self.offset += header.len() as u64 + len as u64;
+        self.n_entries += 1;
+
+        Ok(())
+    }
+
+    /// Write the central directory.
+    fn finish(self) -> Result<()> {
+        let cd_offset = zip_u32(self.offset)?;
+        let cd_len = zip_u32(self.central_directory.len() as u64)?;
+        let n_entries = self.n_entries;
+
+        let write = self.write;
+        write
+            .write_all(&self.central_directory)
+            .and_then(|_| write.write_u32::<LittleEndian>(ZIP_END_OF_CENTRAL_DIRECTORY_SIGNATURE))
+            // Disk numbers.
+            .and_then(|_| write.write_all(&[0; 4]))
+            .and_then(|_| write.write_u16::<LittleEndian>(n_entries))
+            .and_then(|_| write.write_u16::<LittleEndian>(n_entries))
+            .and_then(|_| write.write_u32::<LittleEndian>(cd_len))
+            .and_then(|_| write.write_u32::<LittleEndian>(cd_offset))
+            // Comment length.
+            .and_then(|_| write.write_u16::<LittleEndian>(0))
+            .map_err(|e| ErrorKind::io_error("Cannot write zip central directory", e).into())
+    }
+}
+
+/// Construct a zip header of an uncompressed entry.
+///
+/// Constructs a local file header when `offset` is `None` and a
+/// central directory header with the offset of the local file header
+/// otherwise.
+fn zip_header(
+    signature: u32,
+    name: &str,
+    crc: u32,
+    len: u32,
+    offset: Option<u32>,
+) -> io::Result<Vec<u8>> {
+    let name_len = u16::try_from(name.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "zip entry name is too long"))?;
+
+    let mut header = Vec::with_capacity(46 + name.len());
+    header.write_u32::<LittleEndian>(signature)?;
+    if offset.is_some() {
+        // Version made by.
+        header.write_u16::<LittleEndian>(ZIP_VERSION)?;
+    }
+    header.write_u16::<LittleEndian>(ZIP_VERSION)?;
+    // Flags and compression method (stored).
+    header.write_all(&[0; 4])?;
+    // Modification time and date.
+    header.write_u16::<LittleEndian>(0)?;
+    header.write_u16::<LittleEndian>(ZIP_DATE)?;
+    header.write_u32::<LittleEndian>(crc)?;
+    // Compressed and uncompressed length.
+    header.write_u32::<LittleEndian>(len)?;
+    header.write_u32::<LittleEndian>(len)?;
+    header.write_u16::<LittleEndian>(name_len)?;
+    // Extra field length.
+    header.write_u16::<LittleEndian>(0)?;
+    if let Some(offset) = offset {
+        // Comment length, disk number, and internal and external
+        // file attributes.
+        header.write_all(&[0; 10])?;
+        header.write_u32::<LittleEndian>(offset)?;
+    }
+    header.extend_from_slice(name.as_bytes());
+
+    Ok(header)
+}
+
+/// Convert an offset or length to a 32-bit zip field.
+fn zip_u32(value: u64) -> Result<u32> {
+    // u32::MAX indicates ZIP64 fields.
+    u32::try_from(value)
+        .ok()
+        .filter(|&value| value != u32::MAX)
+        .ok_or_else(|| {
+            ErrorKind::Format("NumPy archive requires ZIP64, which is not supported".to_string())
+                .into()
+        })
+}
+
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 == 1 {
+                0xedb8_8320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+    table
+}
+
+/// Update a CRC-32 checksum with `data`.
+fn crc32_update(crc: u32, data: &[u8]) -> u32 {
+    !data.iter().fold(!crc, |crc, &byte| {
+        CRC32_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+/// Writer that computes the CRC-32 checksum and length of data.
+#[derive(Default)]
+struct ChecksumWriter {
+    crc: u32,
+    len: u64,
+}
+
+impl Write for ChecksumWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.crc = crc32_update(self.crc, buf);
+        self.len += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use approx::AbsDiffEq;
+    use ndarray::array;
+
+    use super::{ReadNpz, WriteNpz};
+    use crate::test_util::test_embeddings;
+    use finalfusion_core::embeddings::Embeddings;
+    use finalfusion_core::norms::NdNorms;
+    use finalfusion_core::storage::{NdArray, StorageView};
+    use finalfusion_core::vocab::{SimpleVocab, Vocab};
+    use finalfusion_io::asset::find_zip_entry;
+
+    fn write_npz(embeddings: &Embeddings<SimpleVocab, NdArray>) -> Vec<u8> {
+        let mut data = Vec::new();
+        embeddings.write_npz_to_writer(&mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn write_npz_roundtrip() {
+        let embeddings = test_embeddings(vec!["a".to_owned(), "größer".to_owned()]);
+        let embeddings = Embeddings::new(
+            None,
+            embeddings.vocab().clone(),
+            embeddings.storage().clone(),
+            NdNorms::new(array![2., 3.]),
+        );
+
+        let read =
+            Embeddings::read_npz_from_reader(&mut Cursor::new(write_npz(&embeddings))).unwrap();
+        assert_eq!(read.vocab().words(), &["a", "größer"]);
+        assert_eq!(read.storage().view(), embeddings.storage().view());
+        assert_eq!(read.norms().unwrap().view(), array![2., 3.]);
+    }
+
+    #[test]
+    fn read_npz_without_norms_normalizes() {
+        let embeddings = test_embeddings(vec!["a".to_owned(), "b".to_owned()]);
+        let data = write_npz(&embeddings);
+        assert!(find_zip_entry(&mut Cursor::new(&data), "norms.npy")
+            .unwrap()
+            .is_none());
+
+        let read = Embeddings::read_npz_from_reader(&mut Cursor::new(data)).unwrap();
+        for (word, embedding) in embeddings.iter() {
+            let norm = embedding.dot(&embedding).sqrt();
+            assert!(read
+                .embedding(&word)
+                .unwrap()
+                .abs_diff_eq(&(embedding.to_owned() / norm), 1e-6));
+        }
+    }
+
+    #[test]
+    fn read_npz_written_by_numpy() {
+        let read = Embeddings::read_npz("../testdata/numpy.npz").unwrap();
+        assert_eq!(read.vocab().words(), &["hello", "wörld"]);
+        assert_eq!(read.norms().unwrap().view(), array![5., 1.]);
+        assert_eq!(read.embedding("hello").unwrap(), array![0.6, 0.8]);
+    }
+
+    #[test]
+    fn read_npz_rejects_missing_arrays() {
+        let embeddings = test_embeddings(vec!["a".to_owned()]);
+        let data = write_npz(&embeddings);
+
+        // Rename the vocabulary entry.
+        let mut renamed = data.clone();
+        for idx in 0..renamed.len() - 5 {
+            if &renamed[idx..idx + 5] == b"vocab" {
+                renamed[idx..idx + 5].copy_from_slice(b"words");
+            }
+        }
+        assert!(Embeddings::read_npz_from_reader(&mut Cursor::new(renamed)).is_err());
+    }
+
+    #[test]
+    fn write_npz_rejects_words_with_trailing_nulls() {
+        let embeddings = test_embeddings(vec!["a\0".to_owned()]);
+        let mut data = Vec::new();
+        assert!(embeddings.write_npz_to_writer(&mut data).is_err());
+        assert!(data.is_empty());
+    }
+}
@@ -0,0 +1,103 @@
+//! Order of the words in written embedding files.
+//!
+//! The text and word2vec writers write the words in vocabulary order
+//! by default. Some consumers expect the most frequent words first,
+//! e.g. to read only the top of a file. `WordOrder::Frequency` writes
+//! the words by descending corpus frequency instead.
+
+use finalfusion_core::embeddings::{Embeddings, IterWithNorms};
+use finalfusion_core::storage::Storage;
+use finalfusion_core::vocab::Vocab;
+use finalfusion_io::io::{ErrorKind, Result};
+
+/// Order of the words in written embedding files.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum WordOrder {
+    /// Write the words in vocabulary order.
+    #[default]
+    Vocab,
+
+    /// Write the words by descending frequency.
+    ///
+    /// Words with the same count keep their vocabulary order. Writing
+    /// fails when the embeddings do not have word counts.
+    Frequency,
+}
+
+/// Get an iterator over the words, embeddings, and norms in the given order.
+pub(crate) fn iter_with_norms<V, S>(
+    embeddings: &Embeddings<V, S>,
+    order: WordOrder,
+) -> Result<IterWithNorms<'_>>
+where
+    V: Vocab,
+    S: Storage,
+{
+    match order {
+        WordOrder::Vocab => Ok(embeddings.iter_with_norms()),
+        WordOrder::Frequency => {
+            let counts = embeddings.counts().ok_or_else(|| {
+                ErrorKind::Format(
+                    "Cannot order words by frequency, embeddings do not have counts".to_string(),
+                )
+            })?;
+
+            let mut words = embeddings.vocab().iter().collect::<Vec<_>>();
+            words.sort_by(|&(_, idx1), &(_, idx2)| counts[idx2].cmp(&counts[idx1]));
+
+            Ok(embeddings.iter_with_norms_from(Box::new(words.into_iter())))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::Array2;
+
+    use finalfusion_core::counts::WordCounts;
+    use finalfusion_core::embeddings::Embeddings;
+    use finalfusion_core::norms::NdNorms;
+    use finalfusion_core::storage::NdArray;
+    use finalfusion_core::vocab::SimpleVocab;
+
+    use super::{iter_with_norms, WordOrder};
+
+    fn test_embeddings() -> Embeddings<SimpleVocab, NdArray> {
+        let vocab = SimpleVocab::new(vec!["a".to_owned(), "b".to_owned(), "c".to_owned()]);
+        let storage = NdArray::new(Array2::from_shape_fn((3, 2), |(row, _)| row as f32));
+        let norms = NdNorms::new(ndarray::arr1(&[1., 2., 3.]));
+        Embeddings::new(None, vocab, storage, norms)
+    }
+
+    #[test]
+    fn iter_in_frequency_order() {
+        let mut embeds = test_embeddings();
+        embeds.set_counts(Some(WordCounts::new(vec![1, 5, 1])));
+
+        let ordered = iter_with_norms(&embeds, WordOrder::Frequency)
+            .unwrap()
+            .map(|(word, embed)| (word, embed.embedding[0], embed.norm))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ordered,
+            vec![
+                ("b".into(), 1., 2.),
+                ("a".into(), 0., 1.),
+                ("c".into(), 2., 3.)
+            ]
+        );
+    }
+
+    #[test]
+    fn frequency_order_requires_counts() {
+        let embeds = test_embeddings();
+        assert!(iter_with_norms(&embeds, WordOrder::Frequency).is_err());
+        assert_eq!(
+            iter_with_norms(&embeds, WordOrder::Vocab)
+                .unwrap()
+                .map(|(word, _)| word)
+                .collect::<Vec<_>>(),
+            vec!["a", "b", "c"]
+        );
+    }
+}
@@ -0,0 +1,482 @@
+//! Reader for SentencePiece models.
+//!
+//! SentencePiece segments text into pieces. Embeddings that are
+//! trained over these pieces have one embedding per piece. This
+//! module constructs a `SentencePieceVocab` from a SentencePiece
+//! model, which can be paired with such an embedding matrix.
+//!
+//! SentencePiece stores a model in two files: the `.model` file is
+//! a serialized protocol buffer with the pieces, their scores and
+//! types, and the training configuration; the `.vocab` file lists the
+//! pieces and their scores as tab-separated values. Both can be read,
+//! but the piece types and the segmentation algorithm are only stored
+//! in the `.model` file. For example:
+//!
+//! ```no_run
+//! use std::fs::File;
+//! use std::io::BufReader;
+//!
+//! use finalfusion_compat::sentencepiece::ReadSentencePiece;
+//! use finalfusion_core::embeddings::Embeddings;
+//! use ndarray::Array2;
+//!
+//! let mut model = BufReader::new(File::open("spm.model").unwrap());
+//! let matrix = Array2::zeros((8000, 100));
+//! let embeddings = Embeddings::read_sentencepiece(&mut model, matrix).unwrap();
+//! ```
+//!
+//! The model is not applied in full: the Unicode normalization of
+//! the model (such as `nmt_nfkc`) is not performed. Lookups use the
+//! unigram or BPE segmentation over the pieces of the model.
+//!
+//! The `.model` file is parsed with a minimal protocol buffer reader,
+//! which only decodes the fields that are needed to construct the
+//! vocabulary.
+
+use std::convert::{TryFrom, TryInto};
+use std::io::{BufRead, Read};
+
+use finalfusion_core::embeddings::Embeddings;
+use finalfusion_core::norms::NdNorms;
+use finalfusion_core::storage::{NdArray, StorageViewMut};
+use finalfusion_core::util::l2_normalize_array;
+use finalfusion_core::vocab::{PieceType, SentencePieceModel, SentencePieceVocab, Vocab};
+use finalfusion_core::warnings::Warnings;
+use finalfusion_io::io::{ErrorKind, Result};
+use ndarray::Array2;
+
+/// Method to construct `Embeddings` from a SentencePiece model.
+pub trait ReadSentencePiece
+where
+    Self: Sized,
+{
+    /// Read a SentencePiece `.model` file and pair it with a matrix.
+    ///
+    /// Row *i* of `matrix` is the embedding of the *i*-th piece of
+    /// the model.
+    fn read_sentencepiece<R>(model: &mut R, matrix: Array2<f32>) -> Result<Self>
+    where
+        R: Read;
+
+    /// Read a SentencePiece `.model` file and pair it with a matrix.
+    ///
+    /// Embeddings that cannot be normalized are added to `warnings`.
+    fn read_sentencepiece_with_warnings<R>(
+        model: &mut R,
+        matrix: Array2<f32>,
+        warnings: &mut Warnings,
+    ) -> Result<Self>
+    where
+        R: Read;
+}
+
+impl ReadSentencePiece for Embeddings<SentencePieceVocab, NdArray> {
+    fn read_sentencepiece<R>(model: &mut R, matrix: Array2<f32>) -> Result<Self>
+    where
+        R: Read,
+    {
+        Self::read_sentencepiece_with_warnings(model, matrix, &mut Warnings::new())
+    }
+
+    fn read_sentencepiece_with_warnings<R>(
+        model: &mut R,
+        matrix: Array2<f32>,
+        warnings: &mut Warnings,
+    ) -> Result<Self>
+    where
+        R: Read,
+    {
+        let vocab = read_sentencepiece_model(model)?;
+
+        if matrix.nrows() != vocab.vocab_len() {
+            return Err(ErrorKind::Format(format!(
+                "Number of embeddings ({}) does not match the number of pieces ({})",
+                matrix.nrows(),
+                vocab.vocab_len()
+            ))
+            .into());
+        }
+
+        let mut storage = NdArray::new(matrix);
+        let norms = l2_normalize_array(storage.view_mut());
+        warnings.push_zero_norms(vocab.iter(), norms.view());
+
+        Ok(Embeddings::new(None, vocab, storage, NdNorms::new(norms)))
+    }
+}
+
+/// Read a vocabulary from a SentencePiece `.model` file.
+pub fn read_sentencepiece_model<R>(read: &mut R) -> Result<SentencePieceVocab>
+where
+    R: Read,
+{
+    let mut data = Vec::new();
+    read.read_to_end(&mut data)
+        .map_err(|e| ErrorKind::io_error("Cannot read SentencePiece model", e))?;
+
+    let mut pieces = Vec::new();
+    let mut scores = Vec::new();
+    let mut types = Vec::new();
+    let mut model = SentencePieceModel::Unigram;
+
+    let mut message = ProtoReader::new(&data);
+    while let Some((field, value)) = message.next_field()? {
+        match (field, value) {
+            (1, ProtoValue::Bytes(piece)) => {
+                let (piece, score, piece_type) = read_piece(piece)?;
+                pieces.push(piece);
+                scores.push(score);
+                types.push(piece_type);
+            }
+            (2, ProtoValue::Bytes(trainer_spec)) => model = read_model_type(trainer_spec)?,
+            _ => (),
+        }
+    }
+
+    check_duplicate_pieces(&pieces)?;
+
+    Ok(SentencePieceVocab::new(pieces, scores, types, model))
+}
+
+/// Read a vocabulary from a SentencePiece `.vocab` file.
+///
+/// Each line of the file contains a piece and its score, separated
+/// by a tab. Since the file does not store the segmentation algorithm
+/// or the piece types, the algorithm must be provided and the types
+/// are inferred: `<unk>` is the unknown piece, `<s>`, `</s>`, and
+/// `<pad>` are control symbols, pieces of the form `<0xXX>` are byte
+/// pieces, and all other pieces are normal pieces.
+pub fn read_sentencepiece_vocab<R>(
+    read: &mut R,
+    model: SentencePieceModel,
+) -> Result<SentencePieceVocab>
+where
+    R: BufRead,
+{
+    let mut pieces = Vec::new();
+    let mut scores = Vec::new();
+    let mut types = Vec::new();
+
+    for line in read.lines() {
+        let line =
+            line.map_err(|e| ErrorKind::io_error("Cannot read line from vocabulary file", e))?;
+        if line.is_empty() {
+            continue;
+        }
+
+        let mut fields = line.splitn(2, '\t');
+        let piece = fields.next().expect("splitn returned no fields");
+        let score = fields
+            .next()
+            .ok_or_else(|| ErrorKind::Format(format!("Piece without score: {}", piece)))?;
+        let score = score.trim().parse().map_err(|e| {
+            ErrorKind::Format(format!("Cannot parse score of piece '{}': {}", piece, e))
+        })?;
+
+        types.push(infer_piece_type(piece));
+        pieces.push(piece.to_owned());
+        scores.push(score);
+    }
+
+    check_duplicate_pieces(&pieces)?;
+
+    Ok(SentencePieceVocab::new(pieces, scores, types, model))
+}
+
+fn infer_piece_type(piece: &str) -> PieceType {
+    match piece {
+        "<unk>" => PieceType::Unknown,
+        "<s>" | "</s>" | "<pad>" => PieceType::Control,
+        _ if piece.len() == 6
+            && piece.starts_with("<0x")
+            && piece.ends_with('>')
+            && piece[3..5].chars().all(|c| c.is_ascii_hexdigit()) =>
+        {
+            PieceType::Byte
+        }
+        _ => PieceType::Normal,
+    }
+}
+
+// Duplicate pieces would result in a panic in the vocab constructor.
+fn check_duplicate_pieces(pieces: &[String]) -> Result<()> {
+    let mut sorted = pieces.iter().collect::<Vec<_>>();
+    sorted.sort();
+    if let Some(piece) = sorted.windows(2).find(|w| w[0] == w[1]).map(|w| w[0]) {
+        return Err(ErrorKind::Format(format!("Duplicate piece: {}", piece)).into());
+    }
+
+    Ok(())
+}
+
+/// Read a `SentencePiece` message.
+fn read_piece(data: &[u8]) -> Result<(String, f32, PieceType)> {
+    let mut piece = String::new();
+    let mut score = 0f32;
+    let mut piece_type = PieceType::Normal;
+
+    let mut message = ProtoReader::new(data);
+    while let Some((field, value)) = message.next_field()? {
+        match (field, value) {
+            (1, ProtoValue::Bytes(bytes)) => {
+                piece = String::from_utf8(bytes.to_vec())
+                    .map_err(|e| ErrorKind::Format(format!("Piece is not valid UTF-8: {}", e)))?;
+            }
+            (2, ProtoValue::Fixed32(bits)) => score = f32::from_bits(bits),
+            (3, ProtoValue::Varint(value)) => {
+                piece_type = u8::try_from(value)
+                    .ok()
+                    .and_then(PieceType::from_u8)
+                    .ok_or_else(|| ErrorKind::Format(format!("Unknown piece type: {}", value)))?;
+            }
+            _ => (),
+        }
+    }
+
+    Ok((piece, score, piece_type))
+}
+
+/// Read the model type from a `TrainerSpec` message.
+fn read_model_type(data: &[u8]) -> Result<SentencePieceModel> {
+    let mut model = SentencePieceModel::Unigram;
+
+    let mut message = ProtoReader::new(data);
+    while let Some((field, value)) = message.next_field()? {
+        if let (3, ProtoValue::Varint(value)) = (field, value) {
+            model = match value {
+                1 => SentencePieceModel::Unigram,
+                2 => SentencePieceModel::Bpe,
+                3 => {
+                    return Err(ErrorKind::Format(
+                        "SentencePiece word models are not supported".to_string(),
+                    )
+                    .into())
+                }
+                4 => {
+                    return Err(ErrorKind::Format(
+                        "SentencePiece character models are not supported".to_string(),
+                    )
+                    .into())
+                }
+                _ => {
+                    return Err(ErrorKind::Format(format!(
+                        "Unknown SentencePiece model type: {}",
+                        value
+                    ))
+                    .into())
+                }
+            };
+        }
+    }
+
+    Ok(model)
+}
+
+/// Value of a protocol buffer field.
+enum ProtoValue<'a> {
+    Varint(u64),
+    // Not used by any of the decoded fields, skipped.
+    Fixed64,
+    Bytes(&'a [u8]),
+    Fixed32(u32),
+}
+
+/// Reader for the fields of a protocol buffer message.
+struct ProtoReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> ProtoReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        ProtoReader { data }
+    }
+
+    /// Read the next field number and value.
+    fn next_field(&mut self) -> Result<Option<(u64, ProtoValue<'a>)>> {
+        if self.data.is_empty() {
+            return Ok(None);
+        }
+
+        let key = self.read_varint()?;
+        let value = match key & 0x7 {
+            0 => ProtoValue::Varint(self.read_varint()?),
+            1 => {
+                self.read_bytes(8)?;
+                ProtoValue::Fixed64
+            }
+            2 => {
+                let len = self.read_varint()? as usize;
+                ProtoValue::Bytes(self.read_bytes(len)?)
+            }
+            5 => ProtoValue::Fixed32(u32::from_le_bytes(
+                self.read_bytes(4)?
+                    .try_into()
+                    .expect("Incorrect slice length"),
+            )),
+            wire_type => {
+                return Err(ErrorKind::Format(format!(
+                    "Unsupported protocol buffer wire type: {}",
+                    wire_type
+                ))
+                .into())
+            }
+        };
+
+        Ok(Some((key >> 3, value)))
+    }
+
+    fn read_bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        if len > self.data.len() {
+            return Err(ErrorKind::Format("Truncated protocol buffer message".to_string()).into());
+        }
+
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn read_varint(&mut self) -> Result<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.read_bytes(1)?[0];
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+
+        Err(ErrorKind::Format("Invalid protocol buffer varint".to_string()).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use ndarray::Array2;
+
+    use super::{read_sentencepiece_model, read_sentencepiece_vocab, ReadSentencePiece};
+    use finalfusion_core::embeddings::Embeddings;
+    use finalfusion_core::storage::NdArray;
+    use finalfusion_core::vocab::{
+        PieceType, SentencePieceModel, SentencePieceVocab, Vocab, WordIndex,
+    };
+
+    fn push_varint(buf: &mut Vec<u8>, mut value: u64) {
+        while value >= 0x80 {
+            buf.push((value as u8) | 0x80);
+            value >>= 7;
+        }
+        buf.push(value as u8);
+    }
+
+    fn push_bytes(buf: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+        push_varint(buf, (field << 3) | 2);
+        push_varint(buf, bytes.len() as u64);
+        buf.extend_from_slice(bytes);
+    }
+
+    fn piece(piece: &str, score: f32, piece_type: Option<u64>) -> Vec<u8> {
+        let mut buf = Vec::new();
+        push_bytes(&mut buf, 1, piece.as_bytes());
+        push_varint(&mut buf, (2 << 3) | 5);
+        buf.extend_from_slice(&score.to_le_bytes());
+        if let Some(piece_type) = piece_type {
+            push_varint(&mut buf, 3 << 3);
+            push_varint(&mut buf, piece_type);
+        }
+        buf
+    }
+
+    fn test_model(model_type: u64) -> Vec<u8> {
+        let mut buf = Vec::new();
+        push_bytes(&mut buf, 1, &piece("<unk>", 0., Some(2)));
+        push_bytes(&mut buf, 1, &piece("<s>", 0., Some(3)));
+        push_bytes(&mut buf, 1, &piece("▁low", -1., None));
+        push_bytes(&mut buf, 1, &piece("er", -2., None));
+        push_bytes(&mut buf, 1, &piece("▁", -3., None));
+
+        // Trainer spec with an unrelated field and the model type.
+        let mut trainer_spec = Vec::new();
+        push_bytes(&mut trainer_spec, 1, b"input.txt");
+        push_varint(&mut trainer_spec, 3 << 3);
+        push_varint(&mut trainer_spec, model_type);
+        push_bytes(&mut buf, 2, &trainer_spec);
+
+        // Normalizer spec, which is ignored.
+        push_bytes(&mut buf, 3, b"");
+
+        buf
+    }
+
+    #[test]
+    fn read_sentencepiece_model_pieces() {
+        let vocab = read_sentencepiece_model(&mut Cursor::new(test_model(1))).unwrap();
+        assert_eq!(vocab.words(), &["<unk>", "<s>", "▁low", "er", "▁"]);
+        assert_eq!(vocab.scores(), &[0., 0., -1., -2., -3.]);
+        assert_eq!(
+            vocab.types(),
+            &[
+                PieceType::Unknown,
+                PieceType::Control,
+                PieceType::Normal,
+                PieceType::Normal,
+                PieceType::Normal
+            ]
+        );
+        assert_eq!(vocab.model(), SentencePieceModel::Unigram);
+        assert_eq!(vocab.idx("low"), Some(WordIndex::Word(2)));
+        assert_eq!(vocab.idx("lower"), Some(WordIndex::Subword(vec![2, 3])));
+    }
+
+    #[test]
+    fn read_sentencepiece_model_rejects_word_models() {
+        assert!(read_sentencepiece_model(&mut Cursor::new(test_model(3))).is_err());
+        assert!(read_sentencepiece_model(&mut Cursor::new(vec![0x0a, 0x05, 0x0a])).is_err());
+    }
+
+    #[test]
+    fn read_sentencepiece_vocab_file() {
+        let mut read = Cursor::new("<unk>\t0\n<s>\t0\n<0x41>\t0\n▁low\t-1.5\ner\t-2\n");
+        let vocab: SentencePieceVocab =
+            read_sentencepiece_vocab(&mut read, SentencePieceModel::Unigram).unwrap();
+        assert_eq!(vocab.words(), &["<unk>", "<s>", "<0x41>", "▁low", "er"]);
+        assert_eq!(vocab.scores(), &[0., 0., 0., -1.5, -2.]);
+        assert_eq!(
+            vocab.types(),
+            &[
+                PieceType::Unknown,
+                PieceType::Control,
+                PieceType::Byte,
+                PieceType::Normal,
+                PieceType::Normal
+            ]
+        );
+
+        let mut read = Cursor::new("▁low\t-1.5\n▁low\t-2\n");
+        assert!(read_sentencepiece_vocab(&mut read, SentencePieceModel::Unigram).is_err());
+    }
+
+    #[test]
+    fn read_sentencepiece_embeddings() {
+        let matrix = Array2::from_shape_fn((5, 2), |(row, col)| (row * 2 + col) as f32);
+        let embeddings = Embeddings::<SentencePieceVocab, NdArray>::read_sentencepiece(
+            &mut Cursor::new(test_model(2)),
+            matrix,
+        )
+        .unwrap();
+        let norm = 41f32.sqrt();
+        assert_eq!(
+            embeddings.embedding("▁low").unwrap().to_vec(),
+            vec![4. / norm, 5. / norm]
+        );
+
+        assert!(
+            Embeddings::<SentencePieceVocab, NdArray>::read_sentencepiece(
+                &mut Cursor::new(test_model(1)),
+                Array2::zeros((4, 2)),
+            )
+            .is_err()
+        );
+    }
+}
@@ -0,0 +1,480 @@
+//! Writer for TensorFlow checkpoints.
+//!
+//! Embeddings can be written as a TensorFlow checkpoint with a single
+//! variable that holds the embedding matrix, together with a
+//! vocabulary file. The checkpoint can be used to initialize an
+//! embedding matrix for `tf.nn.embedding_lookup` and the vocabulary
+//! file to initialize a lookup table that maps words to rows:
+//!
+//! ```python
+//! import tensorflow as tf
+//!
+//! matrix = tf.Variable(tf.train.load_variable("embeddings", "embeddings"))
+//! table = tf.lookup.StaticVocabularyTable(
+//!     tf.lookup.TextFileInitializer(
+//!         "embeddings.vocab",
+//!         tf.string, tf.lookup.TextFileIndex.WHOLE_LINE,
+//!         tf.int64, tf.lookup.TextFileIndex.LINE_NUMBER),
+//!     num_oov_buckets=1)
+//! embeds = tf.nn.embedding_lookup(matrix, table.lookup(tf.constant(["Berlin"])))
+//! ```
+//!
+//! A checkpoint consists of a data file with the tensor data and an
+//! index file, which is a table with the data type, shape, and
+//! position of each tensor. Both file names share a prefix, which is
+//! the name by which the checkpoint is loaded.
+
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use finalfusion_core::embeddings::Embeddings;
+use finalfusion_core::storage::Storage;
+use finalfusion_core::util::write_varint;
+use finalfusion_core::vocab::Vocab;
+use finalfusion_io::io::{ErrorKind, Result};
+
+/// Suffix of the index file of a checkpoint.
+const INDEX_SUFFIX: &str = ".index";
+
+/// Suffix of the data file of a checkpoint with a single shard.
+const DATA_SUFFIX: &str = ".data-00000-of-00001";
+
+/// Magic number at the end of a table.
+const TABLE_MAGIC: u64 = 0xdb47_7524_8b80_fb57;
+
+/// Size of the footer of a table, including the magic number.
+const TABLE_FOOTER_LEN: usize = 48;
+
+/// Version of the tensor bundle format.
+const BUNDLE_VERSION: u64 = 1;
+
+/// TensorFlow data type identifier of 32-bit floats (`DT_FLOAT`).
+const DT_FLOAT: u64 = 1;
+
+const CRC32C_TABLE: [u32; 256] = crc32c_table();
+
+const fn crc32c_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 == 1 {
+                0x82f6_3b78 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+    table
+}
+
+fn crc32c_update(crc: u32, data: &[u8]) -> u32 {
+    !data.iter().fold(!crc, |crc, &byte| {
+        CRC32C_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+/// Mask a CRC-32C checksum, as done for checksums that are stored in
+/// TensorFlow files.
+fn mask_crc32c(crc: u32) -> u32 {
+    crc.rotate_right(15).wrapping_add(0xa282_ead8)
+}
+
+/// Write embeddings as a TensorFlow checkpoint and a vocabulary file.
+pub trait WriteTensorFlow {
+    /// Write the embeddings to a checkpoint with the given prefix.
+    ///
+    /// The embedding matrix is stored as the `f32` variable `variable`
+    /// with the shape `[words_len, dims]`. The checkpoint consists of
+    /// the files `<path_prefix>.index` and
+    /// `<path_prefix>.data-00000-of-00001`. The words are written to
+    /// `path_vocab` as UTF-8 text, one word per line, in the order of
+    /// the matrix rows.
+    ///
+    /// Only the embeddings of known words are written, subword
+    /// embeddings are not written. The embeddings are written as
+    /// returned by `Embeddings::embedding`.
+    fn write_tensorflow(
+        &self,
+        path_prefix: impl AsRef<Path>,
+        variable: &str,
+        path_vocab: impl AsRef<Path>,
+    ) -> Result<()>;
+
+    /// Write the embeddings to the given writers.
+    ///
+    /// See `write_tensorflow` for a description of the written data.
+    fn write_tensorflow_to_writers(
+        &self,
+        variable: &str,
+        index: &mut impl Write,
+        data: &mut impl Write,
+        vocab: &mut impl Write,
+    ) -> Result<()>;
+}
+
+impl<V, S> WriteTensorFlow for Embeddings<V, S>
+where
+    V: Vocab,
+    S: Storage,
+{
+    fn write_tensorflow(
+        &self,
+        path_prefix: impl AsRef<Path>,
+        variable: &str,
+        path_vocab: impl AsRef<Path>,
+    ) -> Result<()> {
+        let prefixed_path = |suffix| {
+            let mut path = OsString::from(path_prefix.as_ref());
+            path.push(suffix);
+            path
+        };
+
+        let mut index = BufWriter::new(
+            File::create(prefixed_path(INDEX_SUFFIX))
+                .map_err(|e| ErrorKind::io_error("Cannot create checkpoint index file", e))?,
+        );
+        let mut data = BufWriter::new(
+            File::create(prefixed_path(DATA_SUFFIX))
+                .map_err(|e| ErrorKind::io_error("Cannot create checkpoint data file", e))?,
+        );
+        let mut vocab = BufWriter::new(
+            File::create(path_vocab)
+                .map_err(|e| ErrorKind::io_error("Cannot create vocabulary file", e))?,
+        );
+
+        self.write_tensorflow_to_writers(variable, &mut index, &mut data, &mut vocab)?;
+
+        index
+            .flush()
+            .map_err(|e| ErrorKind::io_error("Cannot flush checkpoint index file", e))?;
+        data.flush()
+            .map_err(|e| ErrorKind::io_error("Cannot flush checkpoint data file", e))?;
+        vocab
+            .flush()
+            .map_err(|e| ErrorKind::io_error("Cannot flush vocabulary file", e))
+            .map_err(Into::into)
+    }
+
+    fn write_tensorflow_to_writers(
+        &self,
+        variable: &str,
+        index: &mut impl Write,
+        data: &mut impl Write,
+        vocab: &mut impl Write,
+    ) -> Result<()> {
+        // The empty key is used for the header of the index.
+        if variable.is_empty() {
+            return Err(ErrorKind::Format("Variable name cannot be empty".to_string()).into());
+        }
+
+        // Check the words first, to avoid writing a partial matrix.
+        if let Some(word) = self.vocab().words().iter().find(|word| word.contains('\n')) {
+            return Err(ErrorKind::Format(format!(
+                "Cannot write word with a newline to vocabulary file: {:?}",
+                word
+            ))
+            .into());
+        }
+
+        let mut crc = 0;
+        for (word, embedding) in self.iter() {
+            for &component in embedding.view() {
+                let component_bytes = component.to_le_bytes();
+                crc = crc32c_update(crc, &component_bytes);
+                data.write_all(&component_bytes)
+                    .map_err(|e| ErrorKind::io_error("Cannot write embedding component", e))?;
+            }
+
+            writeln!(vocab, "{}", word).map_err(|e| ErrorKind::io_error("Cannot write word", e))?;
+        }
+
+        let shape = [self.vocab().words_len(), self.dims()];
+        let entry = bundle_entry(&shape, mask_crc32c(crc));
+        write_table(
+            index,
+            &[(b"", &bundle_header()), (variable.as_bytes(), &entry)],
+        )
+    }
+}
+
+/// Encode the header of the checkpoint index as a `BundleHeaderProto`.
+fn bundle_header() -> Vec<u8> {
+    let mut version = Vec::new();
+    write_proto_varint(&mut version, 1, BUNDLE_VERSION);
+
+    let mut header = Vec::new();
+    // Number of shards, the endianness defaults to little endian.
+    write_proto_varint(&mut header, 1, 1);
+    write_proto_message(&mut header, 3, &version);
+    header
+}
+
+/// Encode the index entry of an `f32` tensor that starts at the
+/// beginning of the first shard as a `BundleEntryProto`.
+fn bundle_entry(shape: &[usize], masked_crc: u32) -> Vec<u8> {
+    let mut shape_proto = Vec::new();
+    for &size in shape {
+        let mut dim = Vec::new();
+        write_proto_varint(&mut dim, 1, size as u64);
+        write_proto_message(&mut shape_proto, 2, &dim);
+    }
+
+    let n_bytes = shape.iter().product::<usize>() * std::mem::size_of::<f32>();
+
+    let mut entry = Vec::new();
+    write_proto_varint(&mut entry, 1, DT_FLOAT);
+    write_proto_message(&mut entry, 2, &shape_proto);
+    write_proto_varint(&mut entry, 5, n_bytes as u64);
+    // Field 6 (crc32c), fixed 32-bit wire type.
+    write_varint(&mut entry, (6 << 3) | 5);
+    entry.extend_from_slice(&masked_crc.to_le_bytes());
+    entry
+}
+
+/// Write a table with a single data block.
+///
+/// `entries` must be sorted by key. The table format is that of
+/// LevelDB, without compression.
+fn write_table(write: &mut impl Write, entries: &[(&[u8], &[u8])]) -> Result<()> {
+    let mut table = Vec::new();
+
+    let data_block = encode_block(entries);
+    let data_handle = append_block(&mut table, &data_block);
+
+    let metaindex_handle = append_block(&mut table, &encode_block(&[]));
+
+    // The index block maps a key that is larger than or equal to the
+    // last key of a data block to the data block.
+    let last_key = entries.last().map(|&(key, _)| key).unwrap_or(b"");
+    let index_handle = append_block(&mut table, &encode_block(&[(last_key, &data_handle)]));
+
+    let footer_start = table.len();
+    table.extend_from_slice(&metaindex_handle);
+    table.extend_from_slice(&index_handle);
+    table.resize(
+        footer_start + TABLE_FOOTER_LEN - std::mem::size_of::<u64>(),
+        0,
+    );
+    table.extend_from_slice(&TABLE_MAGIC.to_le_bytes());
+
+    write
+        .write_all(&table)
+        .map_err(|e| ErrorKind::io_error("Cannot write checkpoint index", e).into())
+}
+
+/// Encode a block with the given sorted entries.
+///
+/// Keys are not prefix-compressed, so that every entry is a restart
+/// point.
+fn encode_block(entries: &[(&[u8], &[u8])]) -> Vec<u8> {
+    let mut block = Vec::new();
+    let mut restarts = Vec::with_capacity(entries.len().max(1));
+
+    for &(key, value) in entries {
+        restarts.push(block.len() as u32);
+        // Length of the prefix that is shared with the previous key.
+        write_varint(&mut block, 0);
+        write_varint(&mut block, key.len() as u64);
+        write_varint(&mut block, value.len() as u64);
+        block.extend_from_slice(key);
+        block.extend_from_slice(value);
+    }
+
+    // A block always has at least one restart point.
+    if restarts.is_empty() {
+        restarts.push(0);
+    }
+
+    for &restart in &restarts {
+        block.extend_from_slice(&restart.to_le_bytes());
+    }
+    block.extend_from_slice(&(restarts.len() as u32).to_le_bytes());
+
+    block
+}
+
+/// Append a block with its trailer to a table, returning the
+/// encoded handle of the block.
+fn append_block(table: &mut Vec<u8>, block: &[u8]) -> Vec<u8> {
+    let mut handle = Vec::new();
+    write_varint(&mut handle, table.len() as u64);
+    write_varint(&mut handle, block.len() as u64);
+
+    // The trailer consists of the compression type (none) and the
+    // checksum of the block and the compression type.
+    table.extend_from_slice(block);
+    table.push(0);
+    let crc = crc32c_update(crc32c_update(0, block), &[0]);
+    table.extend_from_slice(&mask_crc32c(crc).to_le_bytes());
+
+    handle
+}
+
+/// Write a varint-encoded protocol buffer field.
+fn write_proto_varint(buf: &mut Vec<u8>, field: u64, value: u64) {
+    write_varint(buf, field << 3);
+    write_varint(buf, value);
+}
+
+/// Write a length-delimited protocol buffer field.
+fn write_proto_message(buf: &mut Vec<u8>, field: u64, message: &[u8]) {
+    write_varint(buf, (field << 3) | 2);
+    write_varint(buf, message.len() as u64);
+    buf.extend_from_slice(message);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryInto;
+
+    use super::{crc32c_update, mask_crc32c, write_varint, WriteTensorFlow, TABLE_MAGIC};
+    use crate::test_util::test_embeddings;
+
+    fn read_varint(data: &[u8], pos: &mut usize) -> u64 {
+        let mut value = 0;
+        let mut shift = 0;
+        loop {
+            let byte = data[*pos];
+            *pos += 1;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return value;
+            }
+            shift += 7;
+        }
+    }
+
+    fn read_u32(data: &[u8], pos: usize) -> u32 {
+        u32::from_le_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]])
+    }
+
+    /// Read the block with the given handle, verifying its checksum.
+    fn read_block<'a>(table: &'a [u8], handle: &[u8]) -> &'a [u8] {
+        let mut pos = 0;
+        let offset = read_varint(handle, &mut pos) as usize;
+        let len = read_varint(handle, &mut pos) as usize;
+
+        let block = &table[offset..offset + len + 1];
+        assert_eq!(block[len], 0);
+        assert_eq!(
+            mask_crc32c(crc32c_update(0, block)),
+            read_u32(table, offset + len + 1)
+        );
+
+        &block[..len]
+    }
+
+    /// Read the entries of a block without prefix compression.
+    fn block_entries(block: &[u8]) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let n_restarts = read_u32(block, block.len() - 4) as usize;
+        let entries_end = block.len() - 4 * (n_restarts + 1);
+
+        let mut entries = Vec::new();
+        let mut pos = 0;
+        while pos < entries_end {
+            assert_eq!(read_varint(block, &mut pos), 0);
+            let key_len = read_varint(block, &mut pos) as usize;
+            let value_len = read_varint(block, &mut pos) as usize;
+            let key = block[pos..pos + key_len].to_vec();
+            pos += key_len;
+            entries.push((key, block[pos..pos + value_len].to_vec()));
+            pos += value_len;
+        }
+
+        entries
+    }
+
+    fn table_entries(table: &[u8]) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let footer = &table[table.len() - 48..];
+        assert_eq!(
+            u64::from_le_bytes(footer[40..].try_into().unwrap()),
+            TABLE_MAGIC
+        );
+
+        let mut pos = 0;
+        read_varint(footer, &mut pos);
+        read_varint(footer, &mut pos);
+        let index_handle = &footer[pos..];
+
+        block_entries(read_block(table, index_handle))
+            .into_iter()
+            .flat_map(|(_, handle)| block_entries(read_block(table, &handle)))
+            .collect()
+    }
+
+    #[test]
+    fn crc32c_of_check_value() {
+        assert_eq!(crc32c_update(0, b"123456789"), 0xe306_9283);
+        assert_eq!(
+            crc32c_update(crc32c_update(0, b"1234"), b"56789"),
+            0xe306_9283
+        );
+    }
+
+    #[test]
+    fn varint_encoding() {
+        let mut buf = Vec::new();
+        write_varint(&mut buf, 1);
+        write_varint(&mut buf, 300);
+        assert_eq!(buf, &[0x01, 0xac, 0x02]);
+    }
+
+    #[test]
+    fn write_tensorflow_checkpoint() {
+        let embeddings = test_embeddings(vec!["a".to_owned(), "b".to_owned()]);
+
+        let mut index = Vec::new();
+        let mut data = Vec::new();
+        let mut vocab = Vec::new();
+        embeddings
+            .write_tensorflow_to_writers("embeddings", &mut index, &mut data, &mut vocab)
+            .unwrap();
+
+        let check_data = (0..6)
+            .flat_map(|v| (v as f32).to_le_bytes())
+            .collect::<Vec<_>>();
+        assert_eq!(data, check_data);
+        assert_eq!(String::from_utf8(vocab).unwrap(), "a\nb\n");
+
+        let crc = mask_crc32c(crc32c_update(0, &check_data)).to_le_bytes();
+        assert_eq!(
+            table_entries(&index),
+            vec![
+                (vec![], vec![0x08, 0x01, 0x1a, 0x02, 0x08, 0x01]),
+                (
+                    b"embeddings".to_vec(),
+                    vec![
+                        0x08, 0x01, 0x12, 0x08, 0x12, 0x02, 0x08, 0x02, 0x12, 0x02, 0x08, 0x03,
+                        0x28, 0x18, 0x35, crc[0], crc[1], crc[2], crc[3]
+                    ]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn write_tensorflow_rejects_invalid_input() {
+        let mut index = Vec::new();
+        let mut data = Vec::new();
+        let mut vocab = Vec::new();
+
+        let embeddings = test_embeddings(vec!["a".to_owned()]);
+        assert!(embeddings
+            .write_tensorflow_to_writers("", &mut index, &mut data, &mut vocab)
+            .is_err());
+
+        let embeddings = test_embeddings(vec!["a\nb".to_owned()]);
+        assert!(embeddings
+            .write_tensorflow_to_writers("embeddings", &mut index, &mut data, &mut vocab)
+            .is_err());
+        assert!(data.is_empty());
+    }
+}
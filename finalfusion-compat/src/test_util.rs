@@ -0,0 +1,14 @@
+//! Shared fixtures for the compat tests.
+
+use finalfusion_core::embeddings::Embeddings;
+use finalfusion_core::storage::NdArray;
+use finalfusion_core::vocab::SimpleVocab;
+use ndarray::Array2;
+
+/// Embeddings without norms for `words`, with three dimensions per word.
+///
+/// The matrix is filled with `0, 1, 2, ...` in row-major order.
+pub(crate) fn test_embeddings(words: Vec<String>) -> Embeddings<SimpleVocab, NdArray> {
+    let matrix = Array2::from_shape_fn((words.len(), 3), |(row, col)| (row * 3 + col) as f32);
+    Embeddings::new_without_norms(None, SimpleVocab::new(words), NdArray::new(matrix))
+}
@@ -0,0 +1,1087 @@
+//! Readers and writers for text formats.
+//!
+//! This module provides two readers/writers:
+//!
+//! 1. `ReadText`/`WriteText`: word embeddings in text format. In this
+//!    format, each line contains a word followed by its
+//!    embedding. The word and the embedding vector components are
+//!    separated by a space. This format is used by GloVe.
+//! 2. `ReadTextDims`/`WriteTextDims`: this format is the same as (1),
+//!    but the data is preceded by a line with the shape of the
+//!    embedding matrix. This format is used by word2vec's text
+//!    output.
+//!
+//! For example:
+//!
+//! ```
+//! use std::fs::File;
+//! use std::io::BufReader;
+//!
+//! use finalfusion_compat::text::ReadTextDims;
+//! use finalfusion_core::embeddings::Embeddings;
+//!
+//! let mut reader = BufReader::new(File::open("../testdata/similarity.txt").unwrap());
+//!
+//! // Read the embeddings. The second arguments specifies whether
+//! // the embeddings should be normalized to unit vectors.
+//! let embeddings = Embeddings::read_text_dims(&mut reader)
+//!     .unwrap();
+//!
+//! // Look up an embedding.
+//! let embedding = embeddings.embedding("Berlin");
+//! ```
+//!
+//! Files that contain a word more than once can be read by setting the
+//! duplicate policy in `TextOptions`:
+//!
+//! ```
+//! use std::fs::File;
+//! use std::io::BufReader;
+//!
+//! use finalfusion_compat::duplicates::DuplicatePolicy;
+//! use finalfusion_compat::text::{ReadTextDims, TextOptions};
+//! use finalfusion_core::embeddings::Embeddings;
+//!
+//! let mut reader = BufReader::new(File::open("../testdata/similarity.txt").unwrap());
+//!
+//! let options = TextOptions::default().duplicates(DuplicatePolicy::KeepFirst);
+//! let embeddings = Embeddings::read_text_dims_with_options(&mut reader, &options)
+//!     .unwrap();
+//! ```
+//!
+//! By default, vector components are accepted as long as they can be
+//! parsed, including `NaN` and infinities. `Validation::Strict` also
+//! rejects non-finite components, while `Validation::Recover` skips
+//! malformed lines and records a `Warning::MalformedLine` for each
+//! skipped line:
+//!
+//! ```
+//! use std::io::Cursor;
+//!
+//! use finalfusion_compat::text::{ReadTextDims, TextOptions, Validation};
+//! use finalfusion_core::embeddings::Embeddings;
+//! use finalfusion_core::vocab::Vocab;
+//! use finalfusion_core::warnings::Warnings;
+//!
+//! let mut data = Cursor::new("3 2\nBerlin 1 0\nParis NaN 1\nRome 0 1\n");
+//!
+//! let options = TextOptions::default().validation(Validation::Recover);
+//! let mut warnings = Warnings::new();
+//! let embeddings = Embeddings::read_text_dims_with_options_and_warnings(
+//!     &mut data,
+//!     &options,
+//!     &mut warnings,
+//! )
+//! .unwrap();
+//! assert_eq!(embeddings.vocab().words(), &["Berlin", "Rome"]);
+//! assert_eq!(warnings.malformed_line_count(), 1);
+//! ```
+//!
+//! The readers only require `BufRead` and read the data in a single
+//! pass, so embeddings can also be read from streams that cannot seek,
+//! such as pipes or decompressors:
+//!
+//! ```no_run
+//! use std::io;
+//!
+//! use finalfusion_compat::text::ReadTextDims;
+//! use finalfusion_core::embeddings::Embeddings;
+//!
+//! let stdin = io::stdin();
+//! let embeddings = Embeddings::read_text_dims(&mut stdin.lock())
+//!     .unwrap();
+//! ```
+//!
+//! The writers use the shortest representation of each vector
+//! component that reads back as the same value. `TextWriteOptions`
+//! can set a fixed precision, scientific notation, and write the
+//! most frequent words first:
+//!
+//! ```
+//! use finalfusion_compat::order::WordOrder;
+//! use finalfusion_compat::text::{Notation, ReadTextDims, TextWriteOptions, WriteTextDims};
+//! use finalfusion_core::embeddings::Embeddings;
+//!
+//! # let mut reader = std::io::Cursor::new("2 2\nBerlin 1 0.5\nParis 0.25 1\n");
+//! let embeddings = Embeddings::read_text_dims(&mut reader).unwrap();
+//!
+//! let options = TextWriteOptions::default()
+//!     .precision(2)
+//!     .notation(Notation::Scientific)
+//!     .order(WordOrder::Vocab);
+//! let mut output = Vec::new();
+//! embeddings
+//!     .write_text_dims_with_options(&mut output, false, &options)
+//!     .unwrap();
+//! ```
+
+use std::io::{BufRead, Write};
+
+use finalfusion_core::embeddings::Embeddings;
+use finalfusion_core::storage::{NdArray, Storage};
+use finalfusion_core::util::{decode_string, read_number};
+use finalfusion_core::vocab::{SimpleVocab, Vocab};
+use finalfusion_core::warnings::{Warning, Warnings};
+use finalfusion_io::io::{ErrorKind, Result};
+use itertools::Itertools;
+use ndarray::CowArray;
+
+use crate::compression::Decompress;
+use crate::duplicates::{DuplicatePolicy, UniqueRows};
+use crate::norms::{normalize, NormsPolicy};
+use crate::order::{self, WordOrder};
+
+/// Method to construct `Embeddings` from a text file.
+///
+/// This trait defines an extension to `Embeddings` to read the word embeddings
+/// from a text stream. The text should contain one word embedding per line in
+/// the following format:
+///
+/// *word0 component_1 component_2 ... component_n*
+pub trait ReadText<R>
+where
+    Self: Sized,
+    R: BufRead,
+{
+    /// Read the embeddings from the given buffered reader.
+    fn read_text(reader: &mut R) -> Result<Self>;
+
+    /// Read the embeddings from the given buffered reader.
+    ///
+    /// In contrast to `read_text`, this constructor does not
+    /// fail if a token contains invalid UTF-8. Instead, it will
+    /// replace invalid UTF-8 characters by the replacement
+    /// character.
+    fn read_text_lossy(reader: &mut R) -> Result<Self>;
+
+    /// Read the embeddings from the given buffered reader.
+    ///
+    /// Non-fatal issues, such as replaced invalid UTF-8 when `lossy`
+    /// is `true` and embeddings that cannot be normalized, are added
+    /// to `warnings`.
+    fn read_text_with_warnings(
+        reader: &mut R,
+        lossy: bool,
+        warnings: &mut Warnings,
+    ) -> Result<Self>;
+
+    /// Read the embeddings from the given buffered reader using the
+    /// given options.
+    fn read_text_with_options(reader: &mut R, options: &TextOptions) -> Result<Self>;
+
+    /// Read the embeddings from the given buffered reader using the
+    /// given options, adding non-fatal issues to `warnings`.
+    fn read_text_with_options_and_warnings(
+        reader: &mut R,
+        options: &TextOptions,
+        warnings: &mut Warnings,
+    ) -> Result<Self>;
+}
+
+/// Options for reading text files.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct TextOptions {
+    /// Replace invalid UTF-8 in words by the replacement character,
+    /// rather than failing.
+    pub lossy: bool,
+
+    /// The policy for words that occur more than once.
+    pub duplicates: DuplicatePolicy,
+
+    /// The validation of lines.
+    pub validation: Validation,
+
+    /// The policy for the norms of the embeddings.
+    pub norms: NormsPolicy,
+}
+
+/// Validation of the lines of text files.
+///
+/// In every mode, a line is malformed when it is empty, contains a
+/// component that cannot be parsed, or has a different number of
+/// components than the other lines. Errors for malformed lines state
+/// the line and, where applicable, the column of the problem. Words
+/// are in column 1, components in the following columns.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Validation {
+    /// Fail on malformed lines, accept non-finite components.
+    #[default]
+    Lenient,
+
+    /// Fail on malformed lines and lines with `NaN` or infinite
+    /// components.
+    Strict,
+
+    /// Skip malformed lines and lines with `NaN` or infinite
+    /// components.
+    ///
+    /// A `Warning::MalformedLine` is added for every skipped line.
+    /// Skipped lines count towards the vocabulary size in the shape
+    /// of files with dimensions.
+    Recover,
+}
+
+impl TextOptions {
+    /// Replace invalid UTF-8 in words.
+    pub fn lossy(mut self, lossy: bool) -> Self {
+        self.lossy = lossy;
+        self
+    }
+
+    /// Set the policy for words that occur more than once.
+    pub fn duplicates(mut self, policy: DuplicatePolicy) -> Self {
+        self.duplicates = policy;
+        self
+    }
+
+    /// Set the validation of lines.
+    pub fn validation(mut self, validation: Validation) -> Self {
+        self.validation = validation;
+        self
+    }
+
+    /// Set the policy for the norms of the embeddings.
+    pub fn norms(mut self, policy: NormsPolicy) -> Self {
+        self.norms = policy;
+        self
+    }
+}
+
+impl<R> ReadText<R> for Embeddings<SimpleVocab, NdArray>
+where
+    R: BufRead,
+{
+    fn read_text(reader: &mut R) -> Result<Self> {
+        Self::read_text_with_warnings(reader, false, &mut Warnings::new())
+    }
+
+    fn read_text_lossy(reader: &mut R) -> Result<Self> {
+        Self::read_text_with_warnings(reader, true, &mut Warnings::new())
+    }
+
+    fn read_text_with_warnings(
+        reader: &mut R,
+        lossy: bool,
+        warnings: &mut Warnings,
+    ) -> Result<Self> {
+        let options = TextOptions::default().lossy(lossy);
+        let (_, vocab, storage, _) = Self::read_text_raw(reader, &options, warnings)?.into_parts();
+        Ok(normalize(vocab, storage, options.norms, warnings))
+    }
+
+    fn read_text_with_options(reader: &mut R, options: &TextOptions) -> Result<Self> {
+        Self::read_text_with_options_and_warnings(reader, options, &mut Warnings::new())
+    }
+
+    fn read_text_with_options_and_warnings(
+        reader: &mut R,
+        options: &TextOptions,
+        warnings: &mut Warnings,
+    ) -> Result<Self> {
+        let (_, vocab, storage, _) = Self::read_text_raw(reader, options, warnings)?.into_parts();
+        Ok(normalize(vocab, storage, options.norms, warnings))
+    }
+}
+
+pub(crate) trait ReadTextRaw<R>
+where
+    Self: Sized,
+    R: BufRead,
+{
+    /// Read the unnormalized embeddings from the given buffered reader.
+    fn read_text_raw(
+        reader: &mut R,
+        options: &TextOptions,
+        warnings: &mut Warnings,
+    ) -> Result<Self>;
+}
+
+impl<R> ReadTextRaw<R> for Embeddings<SimpleVocab, NdArray>
+where
+    R: BufRead,
+{
+    fn read_text_raw(
+        reader: &mut R,
+        options: &TextOptions,
+        warnings: &mut Warnings,
+    ) -> Result<Self> {
+        read_embeds(&mut Decompress::new(reader)?, None, true, options, warnings)
+    }
+}
+
+/// Method to construct `Embeddings` from a text file with dimensions.
+///
+/// This trait defines an extension to `Embeddings` to read the word embeddings
+/// from a text stream. The text must contain as the first line the shape of
+/// the embedding matrix:
+///
+/// *vocab_size n_components*
+///
+/// The remainder of the stream should contain one word embedding per line in
+/// the following format:
+///
+/// *word0 component_1 component_2 ... component_n*
+pub trait ReadTextDims<R>
+where
+    Self: Sized,
+    R: BufRead,
+{
+    /// Read the embeddings from the given buffered reader.
+    fn read_text_dims(reader: &mut R) -> Result<Self>;
+
+    /// Read the embeddings from the given buffered reader.
+    ///
+    /// In contrast to `read_text_dims`, this constructor does not
+    /// fail if a token contains invalid UTF-8. Instead, it will
+    /// replace invalid UTF-8 characters by the replacement
+    /// character.
+    fn read_text_dims_lossy(reader: &mut R) -> Result<Self>;
+
+    /// Read the embeddings from the given buffered reader.
+    ///
+    /// Non-fatal issues, such as replaced invalid UTF-8 when `lossy`
+    /// is `true` and embeddings that cannot be normalized, are added
+    /// to `warnings`.
+    fn read_text_dims_with_warnings(
+        reader: &mut R,
+        lossy: bool,
+        warnings: &mut Warnings,
+    ) -> Result<Self>;
+
+    /// Read the embeddings from the given buffered reader using the
+    /// given options.
+    fn read_text_dims_with_options(reader: &mut R, options: &TextOptions) -> Result<Self>;
+
+    /// Read the embeddings from the given buffered reader using the
+    /// given options, adding non-fatal issues to `warnings`.
+    fn read_text_dims_with_options_and_warnings(
+        reader: &mut R,
+        options: &TextOptions,
+        warnings: &mut Warnings,
+    ) -> Result<Self>;
+}
+
+impl<R> ReadTextDims<R> for Embeddings<SimpleVocab, NdArray>
+where
+    R: BufRead,
+{
+    fn read_text_dims(reader: &mut R) -> Result<Self> {
+        Self::read_text_dims_with_warnings(reader, false, &mut Warnings::new())
+    }
+
+    fn read_text_dims_lossy(reader: &mut R) -> Result<Self> {
+        Self::read_text_dims_with_warnings(reader, true, &mut Warnings::new())
+    }
+
+    fn read_text_dims_with_warnings(
+        reader: &mut R,
+        lossy: bool,
+        warnings: &mut Warnings,
+    ) -> Result<Self> {
+        let options = TextOptions::default().lossy(lossy);
+        let (_, vocab, storage, _) =
+            Self::read_text_dims_raw(reader, &options, warnings)?.into_parts();
+        Ok(normalize(vocab, storage, options.norms, warnings))
+    }
+
+    fn read_text_dims_with_options(reader: &mut R, options: &TextOptions) -> Result<Self> {
+        Self::read_text_dims_with_options_and_warnings(reader, options, &mut Warnings::new())
+    }
+
+    fn read_text_dims_with_options_and_warnings(
+        reader: &mut R,
+        options: &TextOptions,
+        warnings: &mut Warnings,
+    ) -> Result<Self> {
+        let (_, vocab, storage, _) =
+            Self::read_text_dims_raw(reader, options, warnings)?.into_parts();
+        Ok(normalize(vocab, storage, options.norms, warnings))
+    }
+}
+
+pub(crate) trait ReadTextDimsRaw<R>
+where
+    Self: Sized,
+    R: BufRead,
+{
+    /// Read the unnormalized embeddings from the given buffered reader.
+    fn read_text_dims_raw(
+        reader: &mut R,
+        options: &TextOptions,
+        warnings: &mut Warnings,
+    ) -> Result<Self>;
+}
+
+impl<R> ReadTextDimsRaw<R> for Embeddings<SimpleVocab, NdArray>
+where
+    R: BufRead,
+{
+    fn read_text_dims_raw(
+        reader: &mut R,
+        options: &TextOptions,
+        warnings: &mut Warnings,
+    ) -> Result<Self> {
+        let reader = &mut Decompress::new(reader)?;
+        let n_words = read_number(reader, b' ')?;
+        let embed_len = read_number(reader, b'\n')?;
+
+        read_embeds(reader, Some((n_words, embed_len)), true, options, warnings)
+    }
+}
+
+/// Read unnormalized embeddings from a text file with dimensions.
+///
+/// In contrast to `read_text_dims_raw`, the vocabulary size on the
+/// first line does not have to match the number of embeddings, so that
+/// truncated files can be read. The shape on the first line is
+/// returned with the embeddings.
+pub(crate) fn read_text_dims_unchecked<R>(
+    reader: &mut R,
+    options: &TextOptions,
+    warnings: &mut Warnings,
+) -> Result<((usize, usize), Embeddings<SimpleVocab, NdArray>)>
+where
+    R: BufRead,
+{
+    let reader = &mut Decompress::new(reader)?;
+    let n_words = read_number(reader, b' ')?;
+    let embed_len = read_number(reader, b'\n')?;
+
+    let shape = (n_words, embed_len);
+    read_embeds(reader, Some(shape), false, options, warnings).map(|embeds| (shape, embeds))
+}
+
+fn read_embeds<R>(
+    reader: &mut R,
+    shape: Option<(usize, usize)>,
+    check_n_words: bool,
+    options: &TextOptions,
+    warnings: &mut Warnings,
+) -> Result<Embeddings<SimpleVocab, NdArray>>
+where
+    R: BufRead,
+{
+    let mut rows = UniqueRows::new(options.duplicates, shape);
+    let mut embedding = Vec::new();
+    let mut n_skipped = 0;
+
+    // The shape is on the first line of files with dimensions.
+    let mut line_idx = if shape.is_some() { 1 } else { 0 };
+
+    loop {
+        let mut buf = Vec::new();
+        match reader
+            .read_until(b'\n', &mut buf)
+            .map_err(|e| ErrorKind::io_error("Cannot read line from embedding file", e))?
+        {
+            0 => break,
+            n => {
+                if buf[n - 1] == b'\n' {
+                    buf.pop();
+                }
+            }
+        };
+        line_idx += 1;
+
+        let parsed = decode_string(buf, options.lossy)
+            .map_err(|e| e.to_string())
+            .and_then(|(line, replaced)| {
+                parse_line(&line, rows.dims(), options.validation, &mut embedding)
+                    .map(|word| (word.to_owned(), replaced))
+            });
+
+        let (word, replaced) = match parsed {
+            Ok(parsed) => parsed,
+            Err(reason) if options.validation == Validation::Recover => {
+                warnings.push(Warning::MalformedLine {
+                    line: line_idx,
+                    reason,
+                });
+                n_skipped += 1;
+                continue;
+            }
+            Err(reason) => {
+                return Err(ErrorKind::Format(format!("{} (line {})", reason, line_idx)).into())
+            }
+        };
+
+        if replaced {
+            warnings.push(Warning::InvalidUtf8 { word: word.clone() });
+        }
+
+        rows.push(word, &embedding, warnings)?;
+    }
+
+    if let (Some((n_words, _)), true) = (shape, check_n_words) {
+        if rows.n_rows() + n_skipped != n_words {
+            return Err(ErrorKind::Format(format!(
+                "Incorrect vocabulary size, expected: {}, got: {}",
+                n_words,
+                rows.n_rows() + n_skipped
+            ))
+            .into());
+        }
+    }
+
+    rows.into_embeddings()
+}
+
+/// Parse a line into a word and its embedding.
+///
+/// If the line is malformed, a description of the problem is returned.
+fn parse_line<'a>(
+    line: &'a str,
+    dims: Option<usize>,
+    validation: Validation,
+    embedding: &mut Vec<f32>,
+) -> std::result::Result<&'a str, String> {
+    let mut parts = line
+        .split(|c: char| c.is_ascii_whitespace())
+        .filter(|part| !part.is_empty());
+
+    let word = parts
+        .next()
+        .ok_or_else(|| String::from("Spurious empty line"))?;
+
+    embedding.clear();
+    for (column, part) in (2..).zip(parts) {
+        let component: f32 = part.parse().map_err(|e| {
+            format!(
+                "Cannot parse vector component '{}' in column {}: {}",
+                part, column, e
+            )
+        })?;
+        if validation != Validation::Lenient && !component.is_finite() {
+            return Err(format!(
+                "Non-finite vector component '{}' in column {}",
+                part, column
+            ));
+        }
+        embedding.push(component);
+    }
+
+    if let Some(dims) = dims {
+        if embedding.len() != dims {
+            return Err(format!(
+                "Incorrect embedding dimensionality, expected: {}, got: {}",
+                dims,
+                embedding.len()
+            ));
+        }
+    }
+
+    Ok(word)
+}
+
+/// Method to write `Embeddings` to a text file.
+///
+/// This trait defines an extension to `Embeddings` to write the word embeddings
+/// as text. The text will contain one word embedding per line in the following
+/// format:
+///
+/// *word0 component_1 component_2 ... component_n*
+pub trait WriteText<W>
+where
+    W: Write,
+{
+    /// Read the embeddings from the given buffered reader.
+    ///
+    /// If `unnormalize` is `true`, the norms vector is used to
+    /// restore the original vector magnitudes.
+    fn write_text(&self, writer: &mut W, unnormalize: bool) -> Result<()>;
+
+    /// Write the embeddings to the given writer.
+    ///
+    /// The formatting of the vector components and the order of the
+    /// words are set by `options`.
+    fn write_text_with_options(
+        &self,
+        writer: &mut W,
+        unnormalize: bool,
+        options: &TextWriteOptions,
+    ) -> Result<()>;
+}
+
+impl<W, V, S> WriteText<W> for Embeddings<V, S>
+where
+    W: Write,
+    V: Vocab,
+    S: Storage,
+{
+    fn write_text(&self, write: &mut W, unnormalize: bool) -> Result<()> {
+        self.write_text_with_options(write, unnormalize, &TextWriteOptions::default())
+    }
+
+    fn write_text_with_options(
+        &self,
+        write: &mut W,
+        unnormalize: bool,
+        options: &TextWriteOptions,
+    ) -> Result<()> {
+        for (word, embed_norm) in order::iter_with_norms(self, options.order)? {
+            let embed = if unnormalize {
+                CowArray::from(embed_norm.into_unnormalized())
+            } else {
+                embed_norm.embedding
+            };
+
+            let embed_str = embed
+                .view()
+                .iter()
+                .map(|&v| options.format_component(v))
+                .join(" ");
+            writeln!(write, "{} {}", word, embed_str)
+                .map_err(|e| ErrorKind::io_error("Cannot write word embedding", e))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Method to write `Embeddings` to a text file.
+///
+/// This trait defines an extension to `Embeddings` to write the word embeddings
+/// as text. The text will contain one word embedding per line in the following
+/// format:
+///
+/// *word0 component_1 component_2 ... component_n*
+pub trait WriteTextDims<W>
+where
+    W: Write,
+{
+    /// Write the embeddings to the given writer.
+    ///
+    /// If `unnormalize` is `true`, the norms vector is used to
+    /// restore the original vector magnitudes.
+    fn write_text_dims(&self, writer: &mut W, unnormalize: bool) -> Result<()>;
+
+    /// Write the embeddings to the given writer.
+    ///
+    /// The formatting of the vector components and the order of the
+    /// words are set by `options`.
+    fn write_text_dims_with_options(
+        &self,
+        writer: &mut W,
+        unnormalize: bool,
+        options: &TextWriteOptions,
+    ) -> Result<()>;
+}
+
+impl<W, V, S> WriteTextDims<W> for Embeddings<V, S>
+where
+    W: Write,
+    V: Vocab,
+    S: Storage,
+{
+    fn write_text_dims(&self, write: &mut W, unnormalize: bool) -> Result<()> {
+        self.write_text_dims_with_options(write, unnormalize, &TextWriteOptions::default())
+    }
+
+    fn write_text_dims_with_options(
+        &self,
+        write: &mut W,
+        unnormalize: bool,
+        options: &TextWriteOptions,
+    ) -> Result<()> {
+        writeln!(write, "{} {}", self.vocab().words_len(), self.dims())
+            .map_err(|e| ErrorKind::io_error("Cannot write word embedding matrix shape", e))?;
+        self.write_text_with_options(write, unnormalize, options)
+    }
+}
+
+/// Options for writing text files.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct TextWriteOptions {
+    /// The number of digits after the decimal point.
+    ///
+    /// If `None`, the shortest representation that reads back as the
+    /// same value is written.
+    pub precision: Option<usize>,
+
+    /// The notation of vector components.
+    pub notation: Notation,
+
+    /// The order of the words.
+    pub order: WordOrder,
+}
+
+/// Notation of vector components in text files.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Notation {
+    /// Fixed-point notation, e.g. `0.00125`.
+    #[default]
+    Fixed,
+
+    /// Scientific notation, e.g. `1.25e-3`.
+    Scientific,
+}
+
+impl TextWriteOptions {
+    /// Set the number of digits after the decimal point.
+    pub fn precision(mut self, precision: usize) -> Self {
+        self.precision = Some(precision);
+        self
+    }
+
+    /// Set the notation of vector components.
+    pub fn notation(mut self, notation: Notation) -> Self {
+        self.notation = notation;
+        self
+    }
+
+    /// Set the order of the words.
+    pub fn order(mut self, order: WordOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Format a vector component.
+    fn format_component(&self, v: f32) -> String {
+        match (self.notation, self.precision) {
+            (Notation::Fixed, None) => v.to_string(),
+            (Notation::Fixed, Some(precision)) => format!("{:.*}", precision, v),
+            (Notation::Scientific, None) => format!("{:e}", v),
+            (Notation::Scientific, Some(precision)) => format!("{:.*e}", precision, v),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::{BufReader, Cursor, Read, Seek, SeekFrom};
+
+    use approx::AbsDiffEq;
+
+    use crate::duplicates::DuplicatePolicy;
+    use crate::order::WordOrder;
+    use crate::word2vec::{ReadWord2VecRaw, Word2VecOptions};
+    use finalfusion_core::counts::WordCounts;
+    use finalfusion_core::embeddings::Embeddings;
+    use finalfusion_core::storage::{NdArray, StorageView};
+    use finalfusion_core::vocab::{SimpleVocab, Vocab};
+    use finalfusion_core::warnings::{Warning, Warnings};
+
+    use super::{
+        Notation, ReadText, ReadTextDims, ReadTextDimsRaw, ReadTextRaw, TextOptions,
+        TextWriteOptions, Validation, WriteText, WriteTextDims,
+    };
+
+    fn read_word2vec() -> Embeddings<SimpleVocab, NdArray> {
+        let f = File::open("../testdata/similarity.bin").unwrap();
+        let mut reader = BufReader::new(f);
+        Embeddings::read_word2vec_binary_raw(
+            &mut reader,
+            &Word2VecOptions::default(),
+            &mut Warnings::new(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn fails_on_invalid_utf8() {
+        let f = File::open("../testdata/utf8-incomplete.txt").unwrap();
+        let mut reader = BufReader::new(f);
+        assert!(Embeddings::read_text(&mut reader).is_err());
+    }
+
+    #[test]
+    fn fails_on_invalid_utf8_dims() {
+        let f = File::open("../testdata/utf8-incomplete.dims").unwrap();
+        let mut reader = BufReader::new(f);
+        assert!(Embeddings::read_text_dims(&mut reader).is_err());
+    }
+
+    #[test]
+    fn read_lossy() {
+        let f = File::open("../testdata/utf8-incomplete.txt").unwrap();
+        let mut reader = BufReader::new(f);
+        let embeds = Embeddings::read_text_lossy(&mut reader).unwrap();
+        let words = embeds.vocab().words();
+        assert_eq!(words, &["meren", "zee�n", "rivieren"]);
+    }
+
+    #[test]
+    fn read_lossy_fails_on_replaced_duplicates() {
+        let data = b"z\xffe 1 0\nz\xfee 0 1\n";
+        assert!(Embeddings::read_text_lossy(&mut Cursor::new(&data[..])).is_err());
+    }
+
+    #[test]
+    fn read_averages_duplicates() {
+        let data = b"2 2\na 1 0\na 0 1\n";
+        let options = TextOptions::default().duplicates(DuplicatePolicy::Average);
+        let embeds = Embeddings::read_text_dims_raw(
+            &mut Cursor::new(&data[..]),
+            &options,
+            &mut Warnings::new(),
+        )
+        .unwrap();
+        assert_eq!(embeds.vocab().words(), &["a"]);
+        assert_eq!(embeds.embedding("a").unwrap(), ndarray::arr1(&[0.5, 0.5]));
+    }
+
+    #[test]
+    fn read_strict_rejects_non_finite() {
+        let data = b"2 2\na 1 0\nb inf 1\n";
+        assert!(Embeddings::read_text_dims(&mut Cursor::new(&data[..])).is_ok());
+
+        let options = TextOptions::default().validation(Validation::Strict);
+        let err = Embeddings::read_text_dims_with_options(&mut Cursor::new(&data[..]), &options)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Non-finite vector component 'inf' in column 2 (line 3)"
+        );
+    }
+
+    #[test]
+    fn read_reports_line_of_dimension_mismatch() {
+        let data = b"a 1 0\nb 0 1\nc 1\n";
+        let err = Embeddings::read_text(&mut Cursor::new(&data[..])).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Incorrect embedding dimensionality, expected: 2, got: 1 (line 3)"
+        );
+    }
+
+    #[test]
+    fn read_recover_skips_malformed_lines() {
+        let data = b"5 2\na 1 0\nb NaN 1\n\nc 0 x\nd 0 1\n";
+        let options = TextOptions::default().validation(Validation::Recover);
+        let mut warnings = Warnings::new();
+        let embeds = Embeddings::read_text_dims_with_options_and_warnings(
+            &mut Cursor::new(&data[..]),
+            &options,
+            &mut warnings,
+        )
+        .unwrap();
+        assert_eq!(embeds.vocab().words(), &["a", "d"]);
+        assert_eq!(warnings.malformed_line_count(), 3);
+        assert_eq!(
+            warnings
+                .iter()
+                .map(|warning| match warning {
+                    Warning::MalformedLine { line, .. } => *line,
+                    _ => panic!("Unexpected warning: {}", warning),
+                })
+                .collect::<Vec<_>>(),
+            vec![3, 4, 5]
+        );
+    }
+
+    #[test]
+    fn read_dims_lossy() {
+        let f = File::open("../testdata/utf8-incomplete.dims").unwrap();
+        let mut reader = BufReader::new(f);
+        let embeds = Embeddings::read_text_dims_lossy(&mut reader).unwrap();
+        let words = embeds.vocab().words();
+        assert_eq!(words, &["meren", "zee�n", "rivieren"]);
+    }
+
+    #[test]
+    fn read_dims_with_warnings() {
+        let data = b"3 2\nzero 0 0\nz\xffe 1 0\none 0 1\n";
+        let mut warnings = Warnings::new();
+        Embeddings::read_text_dims_with_warnings(&mut Cursor::new(&data[..]), true, &mut warnings)
+            .unwrap();
+        assert_eq!(
+            warnings.into_iter().collect::<Vec<_>>(),
+            vec![
+                Warning::InvalidUtf8 {
+                    word: "z\u{FFFD}e".to_owned()
+                },
+                Warning::ZeroNorm {
+                    word: "zero".to_owned()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn read_text() {
+        let f = File::open("../testdata/similarity.nodims").unwrap();
+        let mut reader = BufReader::new(f);
+        let text_embeddings =
+            Embeddings::read_text_raw(&mut reader, &TextOptions::default(), &mut Warnings::new())
+                .unwrap();
+
+        let embeddings = read_word2vec();
+        assert_eq!(text_embeddings.vocab().words(), embeddings.vocab().words());
+        assert_eq!(
+            text_embeddings.storage().view(),
+            embeddings.storage().view()
+        );
+    }
+
+    #[test]
+    fn read_text_dims_without_seek() {
+        let mut data = Vec::new();
+        File::open("../testdata/similarity.txt")
+            .unwrap()
+            .read_to_end(&mut data)
+            .unwrap();
+
+        // Chained readers do not implement Seek, the data is split in
+        // the middle of a line.
+        let mut reader = BufReader::new((&data[..100]).chain(&data[100..]));
+        let text_embeddings = Embeddings::read_text_dims(&mut reader).unwrap();
+
+        let mut reader = BufReader::new(File::open("../testdata/similarity.txt").unwrap());
+        let embeddings = Embeddings::read_text_dims(&mut reader).unwrap();
+        assert_eq!(text_embeddings.vocab().words(), embeddings.vocab().words());
+        assert_eq!(
+            text_embeddings.storage().view(),
+            embeddings.storage().view()
+        );
+    }
+
+    #[test]
+    fn read_text_dims() {
+        let f = File::open("../testdata/similarity.txt").unwrap();
+        let mut reader = BufReader::new(f);
+        let text_embeddings = Embeddings::read_text_dims_raw(
+            &mut reader,
+            &TextOptions::default(),
+            &mut Warnings::new(),
+        )
+        .unwrap();
+
+        let embeddings = read_word2vec();
+        assert_eq!(text_embeddings.vocab().words(), embeddings.vocab().words());
+        assert_eq!(
+            text_embeddings.storage().view(),
+            embeddings.storage().view()
+        );
+    }
+
+    #[test]
+    fn test_word2vec_text_roundtrip() {
+        let mut reader = BufReader::new(File::open("../testdata/similarity.nodims").unwrap());
+        let mut check = String::new();
+        reader.read_to_string(&mut check).unwrap();
+
+        // Read embeddings.
+        reader.seek(SeekFrom::Start(0)).unwrap();
+        let embeddings =
+            Embeddings::read_text_raw(&mut reader, &TextOptions::default(), &mut Warnings::new())
+                .unwrap();
+
+        // Write embeddings to a byte vector.
+        let mut output = Vec::new();
+        embeddings.write_text(&mut output, false).unwrap();
+
+        assert_eq!(check, String::from_utf8_lossy(&output));
+    }
+
+    #[test]
+    fn test_word2vec_text_dims_roundtrip() {
+        let mut reader = BufReader::new(File::open("../testdata/similarity.txt").unwrap());
+        let mut check = String::new();
+        reader.read_to_string(&mut check).unwrap();
+
+        // Read embeddings.
+        reader.seek(SeekFrom::Start(0)).unwrap();
+        let embeddings = Embeddings::read_text_dims_raw(
+            &mut reader,
+            &TextOptions::default(),
+            &mut Warnings::new(),
+        )
+        .unwrap();
+
+        // Write embeddings to a byte vector.
+        let mut output = Vec::new();
+        embeddings.write_text_dims(&mut output, false).unwrap();
+
+        assert_eq!(check, String::from_utf8_lossy(&output));
+    }
+
+    #[test]
+    fn test_word2vec_text_write_unnormalized() {
+        let mut reader = BufReader::new(File::open("../testdata/similarity.nodims").unwrap());
+
+        // Read unnormalized embeddings
+        let embeddings_check =
+            Embeddings::read_text_raw(&mut reader, &TextOptions::default(), &mut Warnings::new())
+                .unwrap();
+
+        // Read normalized embeddings.
+        reader.seek(SeekFrom::Start(0)).unwrap();
+        let embeddings = Embeddings::read_text(&mut reader).unwrap();
+
+        // Write embeddings to a byte vector.
+        let mut output = Vec::new();
+        embeddings.write_text(&mut output, true).unwrap();
+
+        let embeddings = Embeddings::read_text_raw(
+            &mut Cursor::new(&output),
+            &TextOptions::default(),
+            &mut Warnings::new(),
+        )
+        .unwrap();
+
+        assert!(embeddings
+            .storage()
+            .view()
+            .abs_diff_eq(&embeddings_check.storage().view(), 1e-6));
+    }
+
+    #[test]
+    fn write_with_precision_and_notation() {
+        let data = b"a 1 0.125\nb -2.5 1000\n";
+        let embeds = Embeddings::read_text_raw(
+            &mut Cursor::new(&data[..]),
+            &TextOptions::default(),
+            &mut Warnings::new(),
+        )
+        .unwrap();
+
+        let write = |options: TextWriteOptions| {
+            let mut output = Vec::new();
+            embeds
+                .write_text_with_options(&mut output, false, &options)
+                .unwrap();
+            String::from_utf8(output).unwrap()
+        };
+
+        assert_eq!(
+            write(TextWriteOptions::default().precision(2)),
+            "a 1.00 0.12\nb -2.50 1000.00\n"
+        );
+        assert_eq!(
+            write(TextWriteOptions::default().notation(Notation::Scientific)),
+            "a 1e0 1.25e-1\nb -2.5e0 1e3\n"
+        );
+        assert_eq!(
+            write(
+                TextWriteOptions::default()
+                    .notation(Notation::Scientific)
+                    .precision(1)
+            ),
+            "a 1.0e0 1.2e-1\nb -2.5e0 1.0e3\n"
+        );
+    }
+
+    #[test]
+    fn write_dims_in_frequency_order() {
+        let data = b"2 1\na 1\nb 2\n";
+        let mut embeds = Embeddings::read_text_dims_raw(
+            &mut Cursor::new(&data[..]),
+            &TextOptions::default(),
+            &mut Warnings::new(),
+        )
+        .unwrap();
+
+        let options = TextWriteOptions::default().order(WordOrder::Frequency);
+        assert!(embeds
+            .write_text_dims_with_options(&mut Vec::new(), false, &options)
+            .is_err());
+
+        embeds.set_counts(Some(WordCounts::new(vec![3, 7])));
+        let mut output = Vec::new();
+        embeds
+            .write_text_dims_with_options(&mut output, false, &options)
+            .unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "2 1\nb 2\na 1\n");
+    }
+}
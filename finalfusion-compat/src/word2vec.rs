@@ -0,0 +1,1429 @@
+//! Reader and writer for the word2vec binary format.
+//!
+//! Embeddings in the word2vec binary format are these formats are
+//! read as follows:
+//!
+//! ```
+//! use std::fs::File;
+//! use std::io::BufReader;
+//!
+//! use finalfusion_compat::word2vec::ReadWord2Vec;
+//! use finalfusion_core::embeddings::Embeddings;
+//!
+//! let mut reader = BufReader::new(File::open("../testdata/similarity.bin").unwrap());
+//!
+//! // Read the embeddings.
+//! let embeddings = Embeddings::read_word2vec_binary(&mut reader)
+//!     .unwrap();
+//!
+//! // Look up an embedding.
+//! let embedding = embeddings.embedding("Berlin");
+//! ```
+//!
+//! Some word2vec files deviate from the usual conventions. For instance,
+//! the Google News vectors contain phrases such as `New_York`, where the
+//! words of the phrase are joined by underscores. `Word2VecOptions`
+//! can be used to read such files, e.g. to store phrases with spaces
+//! between their words:
+//!
+//! ```
+//! use std::fs::File;
+//! use std::io::BufReader;
+//!
+//! use finalfusion_compat::word2vec::{ReadWord2Vec, Word2VecOptions};
+//! use finalfusion_core::embeddings::Embeddings;
+//!
+//! let mut reader = BufReader::new(File::open("../testdata/similarity.bin").unwrap());
+//!
+//! let options = Word2VecOptions::default().phrase_connector('_');
+//! let embeddings = Embeddings::read_word2vec_binary_with_options(&mut reader, &options)
+//!     .unwrap();
+//! ```
+//!
+//! Replacing connectors can map different tokens to the same word.
+//! Such duplicates can be resolved with `Word2VecOptions::duplicates`.
+//!
+//! The words are written in vocabulary order. Embeddings with word
+//! counts can be written with the most frequent words first using
+//! `WriteWord2Vec::write_word2vec_binary_with_order`.
+//!
+//! Large word2vec files can be converted to quantized finalfusion
+//! files with `Word2VecQuantizer`, without reading the full embedding
+//! matrix into memory. For quick experiments, such files can also be
+//! memory mapped with `MmapWord2Vec`, which only reads the vocabulary
+//! into memory:
+//!
+//! ```
+//! use std::fs::File;
+//! use std::io::BufReader;
+//!
+//! use finalfusion_compat::word2vec::MmapWord2Vec;
+//! use finalfusion_core::embeddings::Embeddings;
+//!
+//! let mut reader = BufReader::new(File::open("../testdata/similarity.bin").unwrap());
+//! let embeddings = Embeddings::mmap_word2vec_binary(&mut reader).unwrap();
+//! let embedding = embeddings.embedding("Berlin");
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Seek, SeekFrom, Write};
+use std::iter;
+use std::mem::size_of;
+
+use byteorder::{ByteOrder, LittleEndian, ReadBytesExt, WriteBytesExt};
+use finalfusion_core::embeddings::Embeddings;
+use finalfusion_core::norms::NdNorms;
+use finalfusion_core::storage::{packs_codes, sample_rows, NdArray, Storage};
+use finalfusion_core::util::{l2_normalize, l2_normalize_array, read_number, read_string_checked};
+use finalfusion_core::vocab::{SimpleVocab, Vocab};
+use finalfusion_core::warnings::{Warning, Warnings};
+use finalfusion_io::chunk::{ChunkIdentifier, Header, WriteChunk};
+use finalfusion_io::io::{Error, ErrorKind, Result};
+use finalfusion_io::progress::{Progress, ProgressUnit};
+use finalfusion_io::storage::{codes_features, QuantizedArrayWriter};
+use memmap::{Mmap, MmapOptions};
+use ndarray::{Array1, ArrayView2, ArrayViewMut1, CowArray, Ix1};
+use rand::{RngCore, SeedableRng};
+use rand_xorshift::XorShiftRng;
+use reductive::pq::{TrainPQ, PQ};
+
+use crate::compression::{Compression, Decompress};
+use crate::duplicates::{DuplicatePolicy, UniqueRows};
+use crate::norms::{normalize, NormsPolicy};
+use crate::order::{self, WordOrder};
+
+/// Number of embeddings that are quantized at once by `Word2VecQuantizer`.
+const QUANTIZE_BATCH_SIZE: usize = 1024;
+
+/// Method to construct `Embeddings` from a word2vec binary file.
+///
+/// This trait defines an extension to `Embeddings` to read the word embeddings
+/// from a file in word2vec binary format.
+pub trait ReadWord2Vec<R>
+where
+    Self: Sized,
+    R: BufRead,
+{
+    /// Read the embeddings from the given buffered reader.
+    fn read_word2vec_binary(reader: &mut R) -> Result<Self>;
+
+    /// Read the embeddings from the given buffered reader.
+    ///
+    /// In contrast to `read_word2vec_binary`, this constructor does
+    /// not fail if a token contains invalid UTF-8. Instead, it will
+    /// replace invalid UTF-8 characters by the replacement character.
+    fn read_word2vec_binary_lossy(reader: &mut R) -> Result<Self>;
+
+    /// Read the embeddings from the given buffered reader using the
+    /// given options.
+    fn read_word2vec_binary_with_options(reader: &mut R, options: &Word2VecOptions)
+        -> Result<Self>;
+
+    /// Read the embeddings from the given buffered reader using the
+    /// given options.
+    ///
+    /// Non-fatal issues, such as replaced invalid UTF-8 and
+    /// embeddings that cannot be normalized, are added to `warnings`.
+    fn read_word2vec_binary_with_warnings(
+        reader: &mut R,
+        options: &Word2VecOptions,
+        warnings: &mut Warnings,
+    ) -> Result<Self>;
+}
+
+/// Options for reading word2vec binary files.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Word2VecOptions {
+    /// The delimiter that separates a token from its embedding.
+    pub delimiter: u8,
+
+    /// The character that joins the words of a phrase.
+    ///
+    /// If set, the words of phrases are separated by a space in the
+    /// vocabulary, so that e.g. `New_York` is stored as `New York`.
+    /// Leading, trailing, or repeated connectors are not considered
+    /// to join words, so tokens such as `_` or `__init__` are retained
+    /// as-is. If `None`, tokens are not changed.
+    pub phrase_connector: Option<char>,
+
+    /// Replace invalid UTF-8 in tokens by the replacement character,
+    /// rather than failing.
+    pub lossy: bool,
+
+    /// The policy for tokens that occur more than once.
+    pub duplicates: DuplicatePolicy,
+
+    /// The policy for the norms of the embeddings.
+    pub norms: NormsPolicy,
+}
+
+impl Word2VecOptions {
+    /// Set the delimiter that separates a token from its embedding.
+    pub fn delimiter(mut self, delimiter: u8) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Set the character that joins the words of a phrase.
+    pub fn phrase_connector(mut self, connector: char) -> Self {
+        self.phrase_connector = Some(connector);
+        self
+    }
+
+    /// Replace invalid UTF-8 in tokens.
+    pub fn lossy(mut self, lossy: bool) -> Self {
+        self.lossy = lossy;
+        self
+    }
+
+    /// Set the policy for tokens that occur more than once.
+    pub fn duplicates(mut self, policy: DuplicatePolicy) -> Self {
+        self.duplicates = policy;
+        self
+    }
+
+    /// Set the policy for the norms of the embeddings.
+    pub fn norms(mut self, policy: NormsPolicy) -> Self {
+        self.norms = policy;
+        self
+    }
+
+    /// Apply the options to a token read from a file.
+    fn normalize_token(&self, token: &str) -> String {
+        let connector = match self.phrase_connector {
+            Some(connector) => connector,
+            None => return token.to_owned(),
+        };
+
+        if !token.contains(connector) || token.split(connector).any(str::is_empty) {
+            return token.to_owned();
+        }
+
+        token.split(connector).collect::<Vec<_>>().join(" ")
+    }
+}
+
+impl Default for Word2VecOptions {
+    /// Options for reading word2vec binary files as written by word2vec.
+    fn default() -> Self {
+        Word2VecOptions {
+            delimiter: b' ',
+            phrase_connector: None,
+            lossy: false,
+            duplicates: DuplicatePolicy::Error,
+            norms: NormsPolicy::Keep,
+        }
+    }
+}
+
+impl<R> ReadWord2Vec<R> for Embeddings<SimpleVocab, NdArray>
+where
+    R: BufRead,
+{
+    fn read_word2vec_binary(reader: &mut R) -> Result<Self> {
+        Self::read_word2vec_binary_with_options(reader, &Word2VecOptions::default())
+    }
+
+    fn read_word2vec_binary_lossy(reader: &mut R) -> Result<Self> {
+        Self::read_word2vec_binary_with_options(reader, &Word2VecOptions::default().lossy(true))
+    }
+
+    fn read_word2vec_binary_with_options(
+        reader: &mut R,
+        options: &Word2VecOptions,
+    ) -> Result<Self> {
+        Self::read_word2vec_binary_with_warnings(reader, options, &mut Warnings::new())
+    }
+
+    fn read_word2vec_binary_with_warnings(
+        reader: &mut R,
+        options: &Word2VecOptions,
+        warnings: &mut Warnings,
+    ) -> Result<Self> {
+        let (_, vocab, storage, _) =
+            Embeddings::read_word2vec_binary_raw(reader, options, warnings)?.into_parts();
+        Ok(normalize(vocab, storage, options.norms, warnings))
+    }
+}
+
+/// Read raw, unnormalized embeddings.
+#[doc(hidden)]
+pub trait ReadWord2VecRaw<R>
+where
+    Self: Sized,
+    R: BufRead,
+{
+    /// Read the embeddings from the given buffered reader.
+    fn read_word2vec_binary_raw(
+        reader: &mut R,
+        options: &Word2VecOptions,
+        warnings: &mut Warnings,
+    ) -> Result<Self>;
+}
+
+impl<R> ReadWord2VecRaw<R> for Embeddings<SimpleVocab, NdArray>
+where
+    R: BufRead,
+{
+    fn read_word2vec_binary_raw(
+        reader: &mut R,
+        options: &Word2VecOptions,
+        warnings: &mut Warnings,
+    ) -> Result<Self> {
+        let reader = &mut Decompress::new(reader)?;
+        let n_words = read_number(reader, b' ')?;
+        let embed_len = read_number(reader, b'\n')?;
+
+        let mut rows = UniqueRows::new(options.duplicates, Some((n_words, embed_len)));
+        let mut embedding = vec![0f32; embed_len];
+
+        for _ in 0..n_words {
+            let word = read_record(reader, options, &mut embedding, warnings)?;
+            rows.push(word, &embedding, warnings)?;
+        }
+
+        rows.into_embeddings()
+    }
+}
+
+/// Read the word and the embedding of a word2vec record.
+fn read_record(
+    reader: &mut dyn BufRead,
+    options: &Word2VecOptions,
+    embedding: &mut [f32],
+    warnings: &mut Warnings,
+) -> Result<String> {
+    let word = read_token(reader, options, warnings)?;
+
+    reader
+        .read_f32_into::<LittleEndian>(embedding)
+        .map_err(|e| ErrorKind::io_error("Cannot read word embedding", e))?;
+
+    Ok(word)
+}
+
+/// Read the word of a word2vec record.
+fn read_token(
+    reader: &mut dyn BufRead,
+    options: &Word2VecOptions,
+    warnings: &mut Warnings,
+) -> Result<String> {
+    let (word, replaced) = read_string_checked(reader, options.delimiter, options.lossy)?;
+    let word = options.normalize_token(word.trim());
+    if replaced {
+        warnings.push(Warning::InvalidUtf8 { word: word.clone() });
+    }
+
+    Ok(word)
+}
+
+/// Memory-mapped embedding matrix of a word2vec binary file.
+///
+/// The embeddings are not copied from the file. Since an embedding
+/// directly follows its word, embeddings in word2vec files are
+/// usually not aligned to 4 bytes. Aligned embeddings are retrieved
+/// as views of the memory map, unaligned embeddings are copied.
+#[derive(Debug)]
+pub struct MmapWord2VecArray {
+    map: Mmap,
+    offsets: Vec<usize>,
+    dims: usize,
+}
+
+impl MmapWord2VecArray {
+    /// Get the raw little-endian data of an embedding.
+    fn embedding_data(&self, idx: usize) -> &[u8] {
+        let offset = self.offsets[idx];
+        &self.map[offset..offset + self.dims * size_of::<f32>()]
+    }
+}
+
+impl Storage for MmapWord2VecArray {
+    fn embedding(&self, idx: usize) -> CowArray<'_, f32, Ix1> {
+        let data = self.embedding_data(idx);
+
+        #[cfg(target_endian = "little")]
+        {
+            if data.as_ptr() as usize % std::mem::align_of::<f32>() == 0 {
+                // Alignment was checked above.
+                #[allow(clippy::cast_ptr_alignment)]
+                let embedding = unsafe {
+                    ndarray::ArrayView1::from_shape_ptr(self.dims, data.as_ptr() as *const f32)
+                };
+                return CowArray::from(embedding);
+            }
+        }
+
+        let mut embedding = Array1::zeros(self.dims);
+        LittleEndian::read_f32_into(
+            data,
+            embedding
+                .as_slice_mut()
+                .expect("Cannot borrow vector as mutable slice"),
+        );
+        CowArray::from(embedding)
+    }
+
+    fn embedding_into(&self, idx: usize, mut out: ArrayViewMut1<f32>) {
+        match out.as_slice_mut() {
+            Some(out) => LittleEndian::read_f32_into(self.embedding_data(idx), out),
+            None => out.assign(&self.embedding(idx)),
+        }
+    }
+
+    fn shape(&self) -> (usize, usize) {
+        (self.offsets.len(), self.dims)
+    }
+}
+
+/// Memory map embeddings in the word2vec binary format.
+///
+/// Only the vocabulary is read into memory, the embeddings are
+/// retrieved from the memory-mapped file. This avoids copying the
+/// embedding matrix of large files, at the cost of slower lookups of
+/// embeddings that are not aligned in the file.
+///
+/// Since the mapped embeddings cannot be modified, they are not
+/// normalized. Similarity and analogy queries assume unit vectors,
+/// so they give different results than queries on embeddings that
+/// are read with `ReadWord2Vec`.
+pub trait MmapWord2Vec
+where
+    Self: Sized,
+{
+    /// Memory map the embeddings of the given reader.
+    ///
+    /// The embeddings are mapped from the current position of the
+    /// reader to the end of the file.
+    fn mmap_word2vec_binary(read: &mut BufReader<File>) -> Result<Self>;
+
+    /// Memory map the embeddings using the given options.
+    ///
+    /// Fails when the `norms` policy of the options is not
+    /// `NormsPolicy::Unnormalized` or when the duplicate policy is
+    /// `DuplicatePolicy::Average`.
+    fn mmap_word2vec_binary_with_options(
+        read: &mut BufReader<File>,
+        options: &Word2VecOptions,
+    ) -> Result<Self>;
+
+    /// Memory map the embeddings using the given options.
+    ///
+    /// Non-fatal issues, such as replaced invalid UTF-8 and duplicate
+    /// words, are added to `warnings`.
+    fn mmap_word2vec_binary_with_warnings(
+        read: &mut BufReader<File>,
+        options: &Word2VecOptions,
+        warnings: &mut Warnings,
+    ) -> Result<Self>;
+}
+
+impl MmapWord2Vec for Embeddings<SimpleVocab, MmapWord2VecArray> {
+    fn mmap_word2vec_binary(read: &mut BufReader<File>) -> Result<Self> {
+        Self::mmap_word2vec_binary_with_options(
+            read,
+            &Word2VecOptions::default().norms(NormsPolicy::Unnormalized),
+        )
+    }
+
+    fn mmap_word2vec_binary_with_options(
+        read: &mut BufReader<File>,
+        options: &Word2VecOptions,
+    ) -> Result<Self> {
+        Self::mmap_word2vec_binary_with_warnings(read, options, &mut Warnings::new())
+    }
+
+    fn mmap_word2vec_binary_with_warnings(
+        read: &mut BufReader<File>,
+        options: &Word2VecOptions,
+        warnings: &mut Warnings,
+    ) -> Result<Self> {
+        if options.norms != NormsPolicy::Unnormalized {
+            return Err(ErrorKind::Format(format!(
+                "Norms policy {:?} is not supported for memory-mapped word2vec embeddings, \
+                 which cannot be normalized",
+                options.norms
+            ))
+            .into());
+        }
+
+        if options.duplicates == DuplicatePolicy::Average {
+            return Err(ErrorKind::Format(
+                "Duplicate policy Average is not supported for memory-mapped word2vec embeddings"
+                    .to_string(),
+            )
+            .into());
+        }
+
+        if Compression::detect(read)? != Compression::None {
+            return Err(ErrorKind::Format(
+                "Cannot memory map compressed word2vec file, decompress the file first".to_string(),
+            )
+            .into());
+        }
+
+        let offset = read.stream_position().map_err(|e| {
+            ErrorKind::io_error(
+                "Cannot get file position for memory mapping word2vec file",
+                e,
+            )
+        })?;
+        let map = unsafe {
+            MmapOptions::new()
+                .offset(offset)
+                .map(read.get_ref())
+                .map_err(|e| ErrorKind::io_error("Cannot memory map word2vec file", e))?
+        };
+
+        let mut data = &map[..];
+        let n_words = read_number(&mut data, b' ')?;
+        let dims = read_number(&mut data, b'\n')?;
+        let embedding_len = dims.checked_mul(size_of::<f32>()).ok_or_else(|| {
+            ErrorKind::Format(format!("Embedding dimensionality is too large: {}", dims))
+        })?;
+
+        let mut indices = HashMap::new();
+        let mut words = Vec::new();
+        let mut offsets = Vec::new();
+        for _ in 0..n_words {
+            let word = read_token(&mut data, options, warnings)?;
+            if data.len() < embedding_len {
+                return Err(ErrorKind::Format(format!(
+                    "Cannot read embedding of '{}', word2vec file is truncated",
+                    word
+                ))
+                .into());
+            }
+            let offset = map.len() - data.len();
+            data = &data[embedding_len..];
+
+            let idx = match indices.get(&word) {
+                Some(&idx) => idx,
+                None => {
+                    indices.insert(word.clone(), words.len());
+                    words.push(word);
+                    offsets.push(offset);
+                    continue;
+                }
+            };
+
+            match options.duplicates {
+                DuplicatePolicy::Error => {
+                    return Err(ErrorKind::Format(format!("Duplicate token: {}", word)).into())
+                }
+                DuplicatePolicy::KeepLast => offsets[idx] = offset,
+                _ => (),
+            }
+
+            warnings.push(Warning::DuplicateWord { word });
+        }
+
+        Ok(Embeddings::new_without_norms(
+            None,
+            SimpleVocab::new(words),
+            MmapWord2VecArray { map, offsets, dims },
+        ))
+    }
+}
+
+/// Converter from word2vec binary files to quantized finalfusion files.
+///
+/// Quantizing embeddings with `Quantize` requires the full embedding
+/// matrix in memory. This converter reads the word2vec file in two
+/// passes instead. In the first pass, the vocabulary is read and the
+/// quantizer is trained on a uniform sample of at most `n_samples`
+/// embeddings. In the second pass, the embeddings are quantized and
+/// written one batch at a time. Only the vocabulary, the sample, and
+/// the norms are kept in memory.
+///
+/// The resulting file is the same as the file that is obtained by
+/// reading the embeddings with `ReadWord2Vec`, quantizing them with
+/// `Quantize` without normalization, and writing them with
+/// `WriteEmbeddings`, except that the quantizer is trained on a sample.
+/// The `norms` policy of the options determines whether the embeddings
+/// are normalized and the norms are written. Since duplicates are
+/// resolved as the embeddings are written, only the `Error` and
+/// `KeepFirst` duplicate policies are supported.
+///
+/// ```
+/// use std::fs::File;
+/// use std::io::{BufReader, Cursor};
+///
+/// use finalfusion_compat::word2vec::Word2VecQuantizer;
+/// use finalfusion_core::embeddings::Embeddings;
+/// use finalfusion_core::storage::StorageWrap;
+/// use finalfusion_core::vocab::VocabWrap;
+/// use finalfusion_io::io::ReadEmbeddings;
+/// use reductive::pq::PQ;
+///
+/// let mut reader = BufReader::new(File::open("../testdata/similarity.bin").unwrap());
+/// let mut data = Cursor::new(Vec::new());
+/// Word2VecQuantizer::new(10, 4)
+///     .n_iterations(5)
+///     .convert::<PQ<f32>, _, _>(&mut reader, &mut data)
+///     .unwrap();
+///
+/// data.set_position(0);
+/// let embeddings: Embeddings<VocabWrap, StorageWrap> =
+///     Embeddings::read_embeddings(&mut data).unwrap();
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Word2VecQuantizer {
+    options: Word2VecOptions,
+    n_subquantizers: usize,
+    n_subquantizer_bits: u32,
+    n_iterations: usize,
+    n_attempts: usize,
+    n_samples: usize,
+}
+
+impl Word2VecQuantizer {
+    /// Construct a converter.
+    ///
+    /// The embeddings are quantized with `n_subquantizers`
+    /// subquantizers of `n_subquantizer_bits` bits each. By default,
+    /// the quantizer is trained for 100 iterations with a single
+    /// attempt, on a sample of at most 100,000 embeddings.
+    pub fn new(n_subquantizers: usize, n_subquantizer_bits: u32) -> Self {
+        Word2VecQuantizer {
+            options: Word2VecOptions::default(),
+            n_subquantizers,
+            n_subquantizer_bits,
+            n_iterations: 100,
+            n_attempts: 1,
+            n_samples: 100_000,
+        }
+    }
+
+    /// Set the options for reading the word2vec file.
+    pub fn options(mut self, options: Word2VecOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Set the number of training iterations of the quantizer.
+    pub fn n_iterations(mut self, n_iterations: usize) -> Self {
+        self.n_iterations = n_iterations;
+        self
+    }
+
+    /// Set the number of training attempts of the quantizer.
+    pub fn n_attempts(mut self, n_attempts: usize) -> Self {
+        self.n_attempts = n_attempts;
+        self
+    }
+
+    /// Set the maximum number of embeddings to train the quantizer on.
+    pub fn n_samples(mut self, n_samples: usize) -> Self {
+        self.n_samples = n_samples;
+        self
+    }
+
+    /// Convert a word2vec binary file to a quantized finalfusion file.
+    ///
+    /// The xorshift PRNG is used for sampling embeddings and for
+    /// picking the initial quantizer centroids.
+    pub fn convert<T, R, W>(&self, read: &mut R, write: &mut W) -> Result<()>
+    where
+        T: TrainPQ<f32>,
+        R: BufRead + Seek,
+        W: Write + Seek,
+    {
+        self.convert_using::<T, _, _, _>(
+            read,
+            write,
+            XorShiftRng::from_entropy(),
+            &mut Warnings::new(),
+        )
+    }
+
+    /// Convert a word2vec binary file using the provided RNG.
+    ///
+    /// Problems with the word2vec file that do not prevent conversion
+    /// are added to `warnings`.
+    pub fn convert_using<T, R, W, G>(
+        &self,
+        read: &mut R,
+        write: &mut W,
+        rng: G,
+        warnings: &mut Warnings,
+    ) -> Result<()>
+    where
+        T: TrainPQ<f32>,
+        R: BufRead + Seek,
+        W: Write + Seek,
+        G: RngCore + SeedableRng + Send,
+    {
+        self.convert_with_progress::<T, _, _, _>(read, write, rng, warnings, |_| ())
+    }
+
+    /// Convert a word2vec binary file, reporting progress.
+    ///
+    /// After the quantizer is trained, `progress` is called with the
+    /// number of embeddings that were quantized and written, in rows.
+    pub fn convert_with_progress<T, R, W, G>(
+        &self,
+        read: &mut R,
+        write: &mut W,
+        rng: G,
+        warnings: &mut Warnings,
+        mut progress: impl FnMut(&Progress),
+    ) -> Result<()>
+    where
+        T: TrainPQ<f32>,
+        R: BufRead + Seek,
+        W: Write + Seek,
+        G: RngCore + SeedableRng + Send,
+    {
+        match self.options.duplicates {
+            DuplicatePolicy::Error | DuplicatePolicy::KeepFirst => (),
+            policy => {
+                return Err(ErrorKind::Format(format!(
+                "Duplicate policy {:?} is not supported when converting to quantized embeddings",
+                policy
+            ))
+                .into())
+            }
+        }
+
+        let start = read
+            .stream_position()
+            .map_err(|e| ErrorKind::io_error("Cannot get start of word2vec file", e))?;
+
+        let (vocab, retained, quantizer) = self.train_quantizer::<T, _, _>(read, rng, warnings)?;
+
+        read.seek(SeekFrom::Start(start))
+            .map_err(|e| ErrorKind::io_error("Cannot seek to start of word2vec file", e))?;
+        let reader = &mut Decompress::new(read)?;
+        let n_words = read_number(reader, b' ')?;
+        let dims = read_number(reader, b'\n')?;
+
+        let mut chunks = vec![vocab.chunk_identifier(), ChunkIdentifier::QuantizedArray];
+        if self.options.norms == NormsPolicy::Keep {
+            chunks.push(ChunkIdentifier::NdNorms);
+        }
+        Header::new(chunks)
+            .with_features(codes_features(packs_codes(&quantizer)))
+            .write_chunk(write)?;
+        vocab.write_chunk(write)?;
+
+        let mut writer = QuantizedArrayWriter::new(write, quantizer, vocab.words_len(), false)?;
+        let mut norms = Vec::with_capacity(vocab.words_len());
+        let mut batch = Vec::with_capacity(QUANTIZE_BATCH_SIZE * dims);
+        let mut embedding = vec![0f32; dims];
+        let mut done = 0;
+        let mut report = |done: usize| {
+            progress(
+                &Progress::new(
+                    ProgressUnit::Rows,
+                    done as u64,
+                    Some(vocab.words_len() as u64),
+                )
+                .chunk(ChunkIdentifier::QuantizedArray.to_string()),
+            )
+        };
+        for &retain in retained.iter().take(n_words) {
+            // Warnings were already added in the first pass.
+            read_record(reader, &self.options, &mut embedding, &mut Warnings::new())?;
+            if !retain {
+                continue;
+            }
+            done += 1;
+
+            let mut embedding = ArrayViewMut1::from(&mut embedding);
+            if self.options.norms != NormsPolicy::Unnormalized {
+                norms.push(l2_normalize(embedding.view_mut()));
+            }
+            batch.extend(embedding.iter());
+
+            if batch.len() == QUANTIZE_BATCH_SIZE * dims {
+                write_batch(&mut writer, &batch, dims)?;
+                batch.clear();
+                report(done);
+            }
+        }
+        write_batch(&mut writer, &batch, dims)?;
+        writer.finish()?;
+        report(done);
+
+        if self.options.norms == NormsPolicy::Unnormalized {
+            return Ok(());
+        }
+
+        let norms = NdNorms::new(norms);
+        warnings.push_zero_norms(vocab.iter(), norms.view());
+        if self.options.norms == NormsPolicy::Keep {
+            norms.write_chunk(write)?;
+        }
+
+        Ok(())
+    }
+
+    /// Read the vocabulary and train the quantizer on a sample.
+    ///
+    /// Returns the vocabulary, whether each record is retained, and
+    /// the quantizer.
+    fn train_quantizer<T, R, G>(
+        &self,
+        read: &mut R,
+        mut rng: G,
+        warnings: &mut Warnings,
+    ) -> Result<(SimpleVocab, Vec<bool>, PQ<f32>)>
+    where
+        T: TrainPQ<f32>,
+        R: BufRead,
+        G: RngCore + SeedableRng + Send,
+    {
+        let reader = &mut Decompress::new(read)?;
+        let n_words = read_number(reader, b' ')?;
+        let dims = read_number(reader, b'\n')?;
+
+        let mut words = Vec::with_capacity(n_words);
+        let mut seen = HashSet::with_capacity(n_words);
+        let mut retained = Vec::with_capacity(n_words);
+        let mut embedding = vec![0f32; dims];
+        let mut error = None;
+
+        let rows = iter::from_fn(|| {
+            while retained.len() < n_words {
+                let word = match read_record(reader, &self.options, &mut embedding, warnings) {
+                    Ok(word) => word,
+                    Err(err) => {
+                        error = Some(err);
+                        return None;
+                    }
+                };
+
+                if seen.insert(word.clone()) {
+                    words.push(word);
+                    retained.push(true);
+                    return Some(Array1::from(embedding.clone()));
+                }
+
+                if self.options.duplicates == DuplicatePolicy::Error {
+                    error = Some(ErrorKind::Format(format!("Duplicate token: {}", word)).into());
+                    return None;
+                }
+
+                warnings.push(Warning::DuplicateWord { word });
+                retained.push(false);
+            }
+
+            None
+        });
+
+        let sample = sample_rows(rows, self.n_samples, &mut rng);
+
+        // Do not train the quantizer on a partial sample.
+        if let Some(err) = error {
+            return Err(err);
+        }
+
+        let mut sample = sample?;
+        if self.options.norms != NormsPolicy::Unnormalized {
+            l2_normalize_array(sample.view_mut());
+        }
+        let quantizer = T::train_pq_using(
+            self.n_subquantizers,
+            self.n_subquantizer_bits,
+            self.n_iterations,
+            self.n_attempts,
+            sample.view(),
+            rng,
+        );
+
+        Ok((SimpleVocab::new(words), retained, quantizer))
+    }
+}
+
+/// Quantize and write a batch of embeddings.
+fn write_batch<W>(writer: &mut QuantizedArrayWriter<W>, batch: &[f32], dims: usize) -> Result<()>
+where
+    W: Write + Seek,
+{
+    if batch.is_empty() {
+        return Ok(());
+    }
+
+    let rows = ArrayView2::from_shape((batch.len() / dims, dims), batch).map_err(Error::Shape)?;
+    writer.write_rows(rows)
+}
+
+/// Method to write `Embeddings` to a word2vec binary file.
+///
+/// This trait defines an extension to `Embeddings` to write the word embeddings
+/// to a file in word2vec binary format.
+pub trait WriteWord2Vec<W>
+where
+    W: Write,
+{
+    /// Write the embeddings from the given writer.
+    ///
+    /// If `unnormalize` is `true`, the norms vector is used to
+    /// restore the original vector magnitudes.
+    fn write_word2vec_binary(&self, w: &mut W, unnormalize: bool) -> Result<()>;
+
+    /// Write the embeddings to the given writer, in the given word order.
+    fn write_word2vec_binary_with_order(
+        &self,
+        w: &mut W,
+        unnormalize: bool,
+        order: WordOrder,
+    ) -> Result<()>;
+}
+
+impl<W, V, S> WriteWord2Vec<W> for Embeddings<V, S>
+where
+    W: Write,
+    V: Vocab,
+    S: Storage,
+{
+    fn write_word2vec_binary(&self, w: &mut W, unnormalize: bool) -> Result<()>
+    where
+        W: Write,
+    {
+        self.write_word2vec_binary_with_order(w, unnormalize, WordOrder::Vocab)
+    }
+
+    fn write_word2vec_binary_with_order(
+        &self,
+        w: &mut W,
+        unnormalize: bool,
+        order: WordOrder,
+    ) -> Result<()> {
+        writeln!(w, "{} {}", self.vocab().words_len(), self.dims())
+            .map_err(|e| ErrorKind::io_error("Cannot write word embedding matrix shape", e))?;
+
+        for (word, embed_norm) in order::iter_with_norms(self, order)? {
+            write!(w, "{} ", word).map_err(|e| ErrorKind::io_error("Cannot write token", e))?;
+
+            let embed = if unnormalize {
+                CowArray::from(embed_norm.into_unnormalized())
+            } else {
+                embed_norm.embedding
+            };
+
+            for v in embed.view() {
+                w.write_f32::<LittleEndian>(*v)
+                    .map_err(|e| ErrorKind::io_error("Cannot write embedding component", e))?;
+            }
+
+            w.write_all(&[0x0a])
+                .map_err(|e| ErrorKind::io_error("Cannot write embedding separator", e))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::{BufReader, Cursor, Read, Seek, SeekFrom};
+
+    use approx::AbsDiffEq;
+    use byteorder::{LittleEndian, WriteBytesExt};
+    use ndarray::{array, Array1};
+    use rand::SeedableRng;
+    use rand_xorshift::XorShiftRng;
+    use reductive::pq::{QuantizeVector, ReconstructVector, PQ};
+
+    use crate::duplicates::DuplicatePolicy;
+    use crate::norms::NormsPolicy;
+    use crate::order::WordOrder;
+    use crate::word2vec::{
+        MmapWord2Vec, MmapWord2VecArray, ReadWord2Vec, ReadWord2VecRaw, Word2VecOptions,
+        Word2VecQuantizer, WriteWord2Vec,
+    };
+    use finalfusion_core::counts::WordCounts;
+    use finalfusion_core::embeddings::Embeddings;
+    use finalfusion_core::storage::{NdArray, QuantizedArray, Storage, StorageView};
+    use finalfusion_core::vocab::{SimpleVocab, Vocab};
+    use finalfusion_core::warnings::{Warning, Warnings};
+    use finalfusion_io::io::ReadEmbeddings;
+    use finalfusion_io::progress::ProgressUnit;
+
+    #[test]
+    fn fails_on_invalid_utf8() {
+        let f = File::open("../testdata/utf8-incomplete.bin").unwrap();
+        let mut reader = BufReader::new(f);
+        assert!(Embeddings::read_word2vec_binary(&mut reader).is_err());
+    }
+
+    #[cfg(feature = "flate2")]
+    #[test]
+    fn read_gzip_compressed() {
+        let mut reader = BufReader::new(File::open("../testdata/similarity.bin").unwrap());
+        let check = Embeddings::read_word2vec_binary(&mut reader).unwrap();
+
+        let mut reader = BufReader::new(File::open("../testdata/similarity.bin.gz").unwrap());
+        let embeds = Embeddings::read_word2vec_binary(&mut reader).unwrap();
+
+        assert_eq!(embeds.vocab().words(), check.vocab().words());
+        assert_eq!(embeds.storage().view(), check.storage().view());
+    }
+
+    #[cfg(feature = "xz2")]
+    #[test]
+    fn read_xz_compressed() {
+        let mut reader = BufReader::new(File::open("../testdata/similarity.bin").unwrap());
+        let check = Embeddings::read_word2vec_binary(&mut reader).unwrap();
+
+        let mut reader = BufReader::new(File::open("../testdata/similarity.bin.xz").unwrap());
+        let embeds = Embeddings::read_word2vec_binary(&mut reader).unwrap();
+
+        assert_eq!(embeds.vocab().words(), check.vocab().words());
+        assert_eq!(embeds.storage().view(), check.storage().view());
+    }
+
+    #[cfg(not(feature = "flate2"))]
+    #[test]
+    fn read_gzip_compressed_requires_flate2() {
+        let mut reader = BufReader::new(File::open("../testdata/similarity.bin.gz").unwrap());
+        assert!(Embeddings::read_word2vec_binary(&mut reader).is_err());
+    }
+
+    #[test]
+    fn read_lossy() {
+        let f = File::open("../testdata/utf8-incomplete.bin").unwrap();
+        let mut reader = BufReader::new(f);
+        let embeds = Embeddings::read_word2vec_binary_lossy(&mut reader).unwrap();
+        let words = embeds.vocab().words();
+        assert_eq!(words, &["meren", "zee�n", "rivieren"]);
+    }
+
+    #[test]
+    fn read_lossy_with_warnings() {
+        let f = File::open("../testdata/utf8-incomplete.bin").unwrap();
+        let mut reader = BufReader::new(f);
+        let mut warnings = Warnings::new();
+        Embeddings::read_word2vec_binary_with_warnings(
+            &mut reader,
+            &Word2VecOptions::default().lossy(true),
+            &mut warnings,
+        )
+        .unwrap();
+        assert_eq!(
+            warnings.into_iter().collect::<Vec<_>>(),
+            vec![Warning::InvalidUtf8 {
+                word: "zee\u{FFFD}n".to_owned()
+            }]
+        );
+    }
+
+    #[test]
+    fn test_read_word2vec_binary() {
+        let f = File::open("../testdata/similarity.bin").unwrap();
+        let mut reader = BufReader::new(f);
+        let embeddings = Embeddings::read_word2vec_binary_raw(
+            &mut reader,
+            &Word2VecOptions::default(),
+            &mut Warnings::new(),
+        )
+        .unwrap();
+        assert_eq!(41, embeddings.vocab().words_len());
+        assert_eq!(100, embeddings.dims());
+    }
+
+    #[test]
+    fn test_word2vec_binary_roundtrip() {
+        let mut reader = BufReader::new(File::open("../testdata/similarity.bin").unwrap());
+        let mut check = Vec::new();
+        reader.read_to_end(&mut check).unwrap();
+
+        // Read embeddings.
+        reader.seek(SeekFrom::Start(0)).unwrap();
+        let embeddings = Embeddings::read_word2vec_binary_raw(
+            &mut reader,
+            &Word2VecOptions::default(),
+            &mut Warnings::new(),
+        )
+        .unwrap();
+
+        // Write embeddings to a byte vector.
+        let mut output = Vec::new();
+        embeddings
+            .write_word2vec_binary(&mut output, false)
+            .unwrap();
+
+        assert_eq!(check, output);
+    }
+
+    #[test]
+    fn test_word2vec_binary_write_unnormalized() {
+        let mut reader = BufReader::new(File::open("../testdata/similarity.bin").unwrap());
+
+        // Read unnormalized embeddings
+        let embeddings_check = Embeddings::read_word2vec_binary_raw(
+            &mut reader,
+            &Word2VecOptions::default(),
+            &mut Warnings::new(),
+        )
+        .unwrap();
+
+        // Read normalized embeddings.
+        reader.seek(SeekFrom::Start(0)).unwrap();
+        let embeddings = Embeddings::read_word2vec_binary(&mut reader).unwrap();
+
+        // Write embeddings to a byte vector.
+        let mut output = Vec::new();
+        embeddings.write_word2vec_binary(&mut output, true).unwrap();
+
+        let embeddings = Embeddings::read_word2vec_binary_raw(
+            &mut Cursor::new(&output),
+            &Word2VecOptions::default(),
+            &mut Warnings::new(),
+        )
+        .unwrap();
+
+        assert!(embeddings
+            .storage()
+            .view()
+            .abs_diff_eq(&embeddings_check.storage().view(), 1e-6));
+    }
+
+    #[test]
+    fn write_in_frequency_order() {
+        let mut embeds =
+            Embeddings::read_word2vec_binary(&mut Cursor::new(word2vec_bytes(&["a", "b"], b' ')))
+                .unwrap();
+        embeds.set_counts(Some(WordCounts::new(vec![1, 2])));
+
+        let mut output = Vec::new();
+        embeds
+            .write_word2vec_binary_with_order(&mut output, true, WordOrder::Frequency)
+            .unwrap();
+
+        let reordered = Embeddings::read_word2vec_binary_raw(
+            &mut Cursor::new(output),
+            &Word2VecOptions::default(),
+            &mut Warnings::new(),
+        )
+        .unwrap();
+        assert_eq!(reordered.vocab().words(), &["b", "a"]);
+        assert!(reordered
+            .storage()
+            .view()
+            .abs_diff_eq(&ndarray::arr2(&[[1., 1.], [0., 1.]]), 1e-6));
+    }
+
+    fn word2vec_bytes(tokens: &[&str], delimiter: u8) -> Vec<u8> {
+        let mut data = format!("{} 2\n", tokens.len()).into_bytes();
+        for (idx, token) in tokens.iter().enumerate() {
+            data.extend_from_slice(token.as_bytes());
+            data.push(delimiter);
+            data.write_f32::<LittleEndian>(idx as f32).unwrap();
+            data.write_f32::<LittleEndian>(1.).unwrap();
+            data.push(b'\n');
+        }
+        data
+    }
+
+    #[test]
+    fn read_with_delimiter_and_phrases() {
+        let data = word2vec_bytes(&["New_York", "Berlin", "_", "__init__", "a__b"], b'\t');
+        let options = Word2VecOptions::default()
+            .delimiter(b'\t')
+            .phrase_connector('_');
+        let embeds =
+            Embeddings::read_word2vec_binary_with_options(&mut Cursor::new(data), &options)
+                .unwrap();
+        assert_eq!(
+            embeds.vocab().words(),
+            &["New York", "Berlin", "_", "__init__", "a__b"]
+        );
+        assert!(embeds.embedding("New York").is_some());
+        assert!(embeds.embedding("New_York").is_none());
+    }
+
+    #[test]
+    fn read_without_phrase_connector_keeps_tokens() {
+        let data = word2vec_bytes(&["New_York", "Berlin"], b' ');
+        let embeds = Embeddings::read_word2vec_binary(&mut Cursor::new(data)).unwrap();
+        assert_eq!(embeds.vocab().words(), &["New_York", "Berlin"]);
+    }
+
+    #[test]
+    fn fails_on_duplicate_phrases() {
+        let data = word2vec_bytes(&["New_York", "New York"], b'\t');
+        let options = Word2VecOptions::default()
+            .delimiter(b'\t')
+            .phrase_connector('_');
+        assert!(
+            Embeddings::read_word2vec_binary_with_options(&mut Cursor::new(data), &options)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn read_keeps_last_duplicate_phrase() {
+        let data = word2vec_bytes(&["New_York", "Berlin", "New York"], b'\t');
+        let options = Word2VecOptions::default()
+            .delimiter(b'\t')
+            .phrase_connector('_')
+            .duplicates(DuplicatePolicy::KeepLast);
+        let mut warnings = Warnings::new();
+        let embeds =
+            Embeddings::read_word2vec_binary_raw(&mut Cursor::new(data), &options, &mut warnings)
+                .unwrap();
+        assert_eq!(embeds.vocab().words(), &["New York", "Berlin"]);
+        assert_eq!(
+            embeds.embedding("New York").unwrap(),
+            ndarray::arr1(&[2., 1.])
+        );
+        assert_eq!(warnings.len(), 1);
+    }
+
+    fn quantizer() -> Word2VecQuantizer {
+        Word2VecQuantizer::new(10, 4).n_iterations(5)
+    }
+
+    fn check_converted(path: &str, converted: Vec<u8>) {
+        let embeds: Embeddings<SimpleVocab, QuantizedArray> =
+            Embeddings::read_embeddings(&mut Cursor::new(converted)).unwrap();
+
+        let mut reader = BufReader::new(File::open(path).unwrap());
+        let check = Embeddings::read_word2vec_binary(&mut reader).unwrap();
+        assert_eq!(embeds.vocab(), check.vocab());
+        assert_eq!(
+            embeds.norms().unwrap().view(),
+            check.norms().unwrap().view()
+        );
+
+        // The normalized embeddings are quantized.
+        let quantizer = embeds.storage().quantizer();
+        for (idx, embedding) in check.storage().view().outer_iter().enumerate() {
+            let reconstructed =
+                quantizer.reconstruct_vector(quantizer.quantize_vector::<u8, _>(embedding).view());
+            assert_eq!(embeds.storage().embedding(idx).view(), reconstructed);
+        }
+    }
+
+    #[test]
+    fn convert_quantizes_normalized_embeddings() {
+        let paths = if cfg!(feature = "flate2") {
+            &[
+                "../testdata/similarity.bin",
+                "../testdata/similarity.bin.gz",
+            ][..]
+        } else {
+            &["../testdata/similarity.bin"][..]
+        };
+        for path in paths {
+            let mut reader = BufReader::new(File::open(path).unwrap());
+            let mut converted = Cursor::new(Vec::new());
+            quantizer()
+                .convert::<PQ<f32>, _, _>(&mut reader, &mut converted)
+                .unwrap();
+            check_converted(path, converted.into_inner());
+        }
+    }
+
+    #[test]
+    fn convert_trains_on_sample() {
+        let mut reader = BufReader::new(File::open("../testdata/similarity.bin").unwrap());
+        let mut converted = Cursor::new(Vec::new());
+        quantizer()
+            .n_samples(20)
+            .convert::<PQ<f32>, _, _>(&mut reader, &mut converted)
+            .unwrap();
+        check_converted("../testdata/similarity.bin", converted.into_inner());
+    }
+
+    #[test]
+    fn convert_discards_norms() {
+        let mut reader = BufReader::new(File::open("../testdata/similarity.bin").unwrap());
+        let mut converted = Cursor::new(Vec::new());
+        quantizer()
+            .options(Word2VecOptions::default().norms(NormsPolicy::Discard))
+            .convert::<PQ<f32>, _, _>(&mut reader, &mut converted)
+            .unwrap();
+
+        converted.set_position(0);
+        let embeds: Embeddings<SimpleVocab, QuantizedArray> =
+            Embeddings::read_embeddings(&mut converted).unwrap();
+        assert_eq!(embeds.vocab().words_len(), 41);
+        assert!(embeds.norms().is_none());
+    }
+
+    #[test]
+    fn convert_reports_progress() {
+        let mut reader = BufReader::new(File::open("../testdata/similarity.bin").unwrap());
+        let mut reports = Vec::new();
+        quantizer()
+            .convert_with_progress::<PQ<f32>, _, _, _>(
+                &mut reader,
+                &mut Cursor::new(Vec::new()),
+                XorShiftRng::seed_from_u64(42),
+                &mut Warnings::new(),
+                |progress| reports.push(progress.clone()),
+            )
+            .unwrap();
+
+        let last = reports.last().unwrap();
+        assert_eq!(last.unit, ProgressUnit::Rows);
+        assert_eq!(last.done, 41);
+        assert_eq!(last.total, Some(41));
+        assert_eq!(last.chunk.as_deref(), Some("QuantizedArray"));
+    }
+
+    #[test]
+    fn read_unnormalized() {
+        let mut reader = BufReader::new(File::open("../testdata/similarity.bin").unwrap());
+        let check = Embeddings::read_word2vec_binary_raw(
+            &mut reader,
+            &Word2VecOptions::default(),
+            &mut Warnings::new(),
+        )
+        .unwrap();
+
+        let mut reader = BufReader::new(File::open("../testdata/similarity.bin").unwrap());
+        let options = Word2VecOptions::default().norms(NormsPolicy::Unnormalized);
+        let embeds = Embeddings::read_word2vec_binary_with_options(&mut reader, &options).unwrap();
+        assert!(embeds.norms().is_none());
+        assert_eq!(embeds.storage().view(), check.storage().view());
+    }
+
+    #[test]
+    fn convert_keeps_first_duplicate() {
+        let data = word2vec_bytes(&["a", "b", "a", "c"], b' ');
+        let mut converted = Cursor::new(Vec::new());
+        let mut warnings = Warnings::new();
+        Word2VecQuantizer::new(1, 1)
+            .n_iterations(1)
+            .options(Word2VecOptions::default().duplicates(DuplicatePolicy::KeepFirst))
+            .convert_using::<PQ<f32>, _, _, _>(
+                &mut Cursor::new(data),
+                &mut converted,
+                XorShiftRng::seed_from_u64(42),
+                &mut warnings,
+            )
+            .unwrap();
+
+        converted.set_position(0);
+        let embeds: Embeddings<SimpleVocab, QuantizedArray> =
+            Embeddings::read_embeddings(&mut converted).unwrap();
+        assert_eq!(embeds.vocab().words(), &["a", "b", "c"]);
+        assert_eq!(embeds.norms().unwrap()[1], 2f32.sqrt());
+        assert_eq!(
+            warnings.into_iter().collect::<Vec<_>>(),
+            vec![Warning::DuplicateWord {
+                word: "a".to_owned()
+            }]
+        );
+    }
+
+    #[test]
+    fn convert_fails_on_duplicates() {
+        for policy in &[
+            DuplicatePolicy::Error,
+            DuplicatePolicy::KeepLast,
+            DuplicatePolicy::Average,
+        ] {
+            let data = word2vec_bytes(&["a", "b", "a"], b' ');
+            assert!(Word2VecQuantizer::new(1, 1)
+                .options(Word2VecOptions::default().duplicates(*policy))
+                .convert::<PQ<f32>, _, _>(&mut Cursor::new(data), &mut Cursor::new(Vec::new()))
+                .is_err());
+        }
+    }
+
+    fn mmap_word2vec_file(name: &str, data: &[u8]) -> BufReader<File> {
+        let path = std::env::temp_dir().join(format!("{}-{}.bin", name, std::process::id()));
+        std::fs::write(&path, data).unwrap();
+        let reader = BufReader::new(File::open(&path).unwrap());
+        std::fs::remove_file(&path).unwrap();
+        reader
+    }
+
+    #[test]
+    fn mmap_matches_read() {
+        let options = Word2VecOptions::default().norms(NormsPolicy::Unnormalized);
+        let mut reader = BufReader::new(File::open("../testdata/similarity.bin").unwrap());
+        let check: Embeddings<SimpleVocab, NdArray> =
+            Embeddings::read_word2vec_binary_with_options(&mut reader, &options).unwrap();
+
+        let mut reader = BufReader::new(File::open("../testdata/similarity.bin").unwrap());
+        let embeds: Embeddings<SimpleVocab, MmapWord2VecArray> =
+            Embeddings::mmap_word2vec_binary(&mut reader).unwrap();
+
+        assert_eq!(embeds.vocab(), check.vocab());
+        assert!(embeds.norms().is_none());
+        assert_eq!(embeds.storage().shape(), check.storage().shape());
+
+        let mut out = Array1::zeros(embeds.dims());
+        for idx in 0..check.vocab().words_len() {
+            assert_eq!(
+                embeds.storage().embedding(idx),
+                check.storage().embedding(idx)
+            );
+            embeds.storage().embedding_into(idx, out.view_mut());
+            assert_eq!(out, check.storage().embedding(idx));
+        }
+    }
+
+    #[test]
+    fn mmap_handles_unaligned_embeddings() {
+        // The embeddings of "a" and "bbb" are at offsets 6 and 19.
+        let mut reader = mmap_word2vec_file("mmap-unaligned", &word2vec_bytes(&["a", "bbb"], b' '));
+        let embeds: Embeddings<SimpleVocab, MmapWord2VecArray> =
+            Embeddings::mmap_word2vec_binary(&mut reader).unwrap();
+        assert_eq!(embeds.embedding("a").unwrap(), array![0., 1.]);
+        assert_eq!(embeds.embedding("bbb").unwrap(), array![1., 1.]);
+    }
+
+    #[test]
+    fn mmap_resolves_duplicates() {
+        let data = word2vec_bytes(&["a", "b", "a"], b' ');
+
+        let mut reader = mmap_word2vec_file("mmap-duplicates", &data);
+        assert!(
+            Embeddings::<SimpleVocab, MmapWord2VecArray>::mmap_word2vec_binary(&mut reader)
+                .is_err()
+        );
+
+        let options = Word2VecOptions::default()
+            .norms(NormsPolicy::Unnormalized)
+            .duplicates(DuplicatePolicy::KeepLast);
+        let mut warnings = Warnings::new();
+        let mut reader = mmap_word2vec_file("mmap-duplicates", &data);
+        let embeds: Embeddings<SimpleVocab, MmapWord2VecArray> =
+            Embeddings::mmap_word2vec_binary_with_warnings(&mut reader, &options, &mut warnings)
+                .unwrap();
+        assert_eq!(embeds.vocab().words(), &["a", "b"]);
+        assert_eq!(embeds.embedding("a").unwrap(), array![2., 1.]);
+        assert_eq!(
+            warnings.into_iter().collect::<Vec<_>>(),
+            vec![Warning::DuplicateWord {
+                word: "a".to_owned()
+            }]
+        );
+    }
+
+    #[test]
+    fn mmap_rejects_unsupported_input() {
+        let mut reader = BufReader::new(File::open("../testdata/similarity.bin").unwrap());
+        assert!(
+            Embeddings::<SimpleVocab, MmapWord2VecArray>::mmap_word2vec_binary_with_options(
+                &mut reader,
+                &Word2VecOptions::default()
+            )
+            .is_err()
+        );
+
+        let mut reader = BufReader::new(File::open("../testdata/similarity.bin.gz").unwrap());
+        assert!(
+            Embeddings::<SimpleVocab, MmapWord2VecArray>::mmap_word2vec_binary(&mut reader)
+                .is_err()
+        );
+
+        let mut data = word2vec_bytes(&["a", "b"], b' ');
+        data.truncate(data.len() - 4);
+        let mut reader = mmap_word2vec_file("mmap-truncated", &data);
+        assert!(
+            Embeddings::<SimpleVocab, MmapWord2VecArray>::mmap_word2vec_binary(&mut reader)
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,73 @@
+//! Word counts chunk
+
+use std::mem::size_of;
+use std::ops::Deref;
+
+use super::memory::{MemoryFootprint, MemoryUsage};
+
+/// Chunk for storing word frequencies.
+///
+/// The counts chunk stores the frequency of each in-vocabulary word
+/// in the training corpus, in vocabulary order. Counts can be used
+/// downstream, e.g. for frequency-weighted pooling of embeddings or
+/// for subsampling frequent words.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct WordCounts {
+    inner: Vec<u64>,
+}
+
+impl WordCounts {
+    /// Construct new `WordCounts`.
+    pub fn new(counts: impl Into<Vec<u64>>) -> Self {
+        WordCounts {
+            inner: counts.into(),
+        }
+    }
+
+    /// Get the sum of the counts.
+    pub fn total(&self) -> u64 {
+        self.inner.iter().sum()
+    }
+
+    /// Check whether the counts are in descending order.
+    ///
+    /// Equal counts are allowed to be in any order.
+    pub fn is_descending(&self) -> bool {
+        self.inner.windows(2).all(|pair| pair[0] >= pair[1])
+    }
+
+    /// Append a count.
+    pub(crate) fn push(&mut self, count: u64) {
+        self.inner.push(count);
+    }
+
+    /// Reserve capacity for at least `additional` more counts.
+    pub(crate) fn reserve(&mut self, additional: usize) {
+        self.inner.reserve(additional);
+    }
+
+    /// Shrink the capacity of the counts as much as possible.
+    pub(crate) fn shrink_to_fit(&mut self) {
+        self.inner.shrink_to_fit();
+    }
+}
+
+impl Deref for WordCounts {
+    type Target = [u64];
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl From<Vec<u64>> for WordCounts {
+    fn from(counts: Vec<u64>) -> WordCounts {
+        WordCounts::new(counts)
+    }
+}
+
+impl MemoryUsage for WordCounts {
+    fn memory_usage(&self) -> MemoryFootprint {
+        MemoryFootprint::resident(self.inner.len() * size_of::<u64>())
+    }
+}
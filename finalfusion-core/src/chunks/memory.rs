@@ -0,0 +1,140 @@
+//! Memory usage of chunks
+
+use std::collections::HashMap;
+use std::iter::Sum;
+use std::mem::size_of;
+use std::ops::{Add, AddAssign};
+
+/// Memory footprint of a chunk.
+///
+/// The footprint distinguishes between two kinds of memory:
+///
+/// * *Resident* memory is allocated on the heap, such as an embedding
+///   matrix that is read into memory.
+/// * *Mapped* memory is memory-mapped from a file. The operating
+///   system pages in mapped memory on demand, so only a part of the
+///   mapped memory may actually be resident.
+///
+/// The footprint is an estimate: it accounts for the data held by a
+/// chunk, but not for allocator overhead.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct MemoryFootprint {
+    /// Heap-allocated bytes.
+    pub resident: usize,
+
+    /// Memory-mapped bytes.
+    pub mapped: usize,
+}
+
+impl MemoryFootprint {
+    /// Construct a footprint of resident memory.
+    pub fn resident(bytes: usize) -> Self {
+        MemoryFootprint {
+            resident: bytes,
+            mapped: 0,
+        }
+    }
+
+    /// Construct a footprint of mapped memory.
+    pub fn mapped(bytes: usize) -> Self {
+        MemoryFootprint {
+            resident: 0,
+            mapped: bytes,
+        }
+    }
+
+    /// Get the total number of bytes.
+    pub fn total(&self) -> usize {
+        self.resident + self.mapped
+    }
+}
+
+impl Add for MemoryFootprint {
+    type Output = MemoryFootprint;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        MemoryFootprint {
+            resident: self.resident + rhs.resident,
+            mapped: self.mapped + rhs.mapped,
+        }
+    }
+}
+
+impl AddAssign for MemoryFootprint {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl Sum for MemoryFootprint {
+    fn sum<I>(iter: I) -> Self
+    where
+        I: Iterator<Item = Self>,
+    {
+        iter.fold(MemoryFootprint::default(), Add::add)
+    }
+}
+
+/// Report the memory usage of a data structure.
+pub trait MemoryUsage {
+    /// Get the memory footprint.
+    fn memory_usage(&self) -> MemoryFootprint;
+}
+
+impl<T> MemoryUsage for &T
+where
+    T: MemoryUsage + ?Sized,
+{
+    fn memory_usage(&self) -> MemoryFootprint {
+        (**self).memory_usage()
+    }
+}
+
+impl<T> MemoryUsage for Option<T>
+where
+    T: MemoryUsage,
+{
+    fn memory_usage(&self) -> MemoryFootprint {
+        self.as_ref()
+            .map(MemoryUsage::memory_usage)
+            .unwrap_or_default()
+    }
+}
+
+/// Get the number of heap-allocated bytes of a vector of strings.
+pub(crate) fn strings_heap_size(strings: &Vec<String>) -> usize {
+    strings.capacity() * size_of::<String>() + strings.iter().map(String::capacity).sum::<usize>()
+}
+
+/// Get the number of heap-allocated bytes of a map with string keys.
+///
+/// This is an approximation that assumes that the table of the map
+/// uses one control byte per bucket.
+pub(crate) fn string_map_heap_size<V>(map: &HashMap<String, V>) -> usize {
+    map.capacity() * (size_of::<(String, V)>() + 1)
+        + map.keys().map(String::capacity).sum::<usize>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MemoryFootprint;
+
+    #[test]
+    fn memory_footprint_sum() {
+        let footprint: MemoryFootprint = vec![
+            MemoryFootprint::resident(10),
+            MemoryFootprint::mapped(20),
+            MemoryFootprint::resident(5),
+        ]
+        .into_iter()
+        .sum();
+        assert_eq!(
+            footprint,
+            MemoryFootprint {
+                resident: 15,
+                mapped: 20
+            }
+        );
+        assert_eq!(footprint.total(), 35);
+    }
+}
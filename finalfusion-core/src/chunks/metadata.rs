@@ -0,0 +1,40 @@
+//! Metadata chunks
+
+use std::ops::{Deref, DerefMut};
+
+use toml::Value;
+
+/// Embeddings metadata.
+///
+/// finalfusion metadata in TOML format.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Metadata {
+    inner: Value,
+}
+
+impl Metadata {
+    /// Construct new `Metadata`.
+    pub fn new(data: Value) -> Self {
+        Metadata { inner: data }
+    }
+}
+
+impl Deref for Metadata {
+    type Target = Value;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl DerefMut for Metadata {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.inner
+    }
+}
+
+impl From<Value> for Metadata {
+    fn from(value: Value) -> Self {
+        Metadata { inner: value }
+    }
+}
@@ -1,6 +1,8 @@
 //! finalfusion chunks
 
-pub(crate) mod io;
+pub mod counts;
+
+pub mod memory;
 
 pub mod metadata;
 
@@ -8,4 +10,6 @@ pub mod norms;
 
 pub mod storage;
 
+pub mod unknown;
+
 pub mod vocab;
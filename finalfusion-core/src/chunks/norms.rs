@@ -0,0 +1,80 @@
+//! Norms chunk
+
+use std::mem::{self, size_of};
+use std::ops::Deref;
+
+use ndarray::Array1;
+
+use super::memory::{MemoryFootprint, MemoryUsage};
+
+/// Chunk for storing embedding l2 norms.
+///
+/// Word embeddings are always l2-normalized in finalfusion. Sometimes
+/// it is useful to get the original unnormalized embeddings. The
+/// norms chunk is used for storing norms of in-vocabulary embeddings.
+/// The unnormalized embedding can be reconstructed by multiplying the
+/// normalized embedding by its orginal l2 norm.
+#[derive(Clone, Debug)]
+pub struct NdNorms {
+    inner: Array1<f32>,
+}
+
+impl NdNorms {
+    /// Construct new `NdNorms`.
+    pub fn new(norms: impl Into<Array1<f32>>) -> Self {
+        NdNorms {
+            inner: norms.into(),
+        }
+    }
+}
+
+impl NdNorms {
+    /// Append a norm.
+    pub(crate) fn push(&mut self, norm: f32) {
+        self.modify_norms(|norms| norms.push(norm));
+    }
+
+    /// Reserve capacity for at least `additional` more norms.
+    pub(crate) fn reserve(&mut self, additional: usize) {
+        self.modify_norms(|norms| norms.reserve(additional));
+    }
+
+    /// Shrink the capacity of the norms as much as possible.
+    pub(crate) fn shrink_to_fit(&mut self) {
+        self.modify_norms(Vec::shrink_to_fit);
+    }
+
+    fn modify_norms(&mut self, f: impl FnOnce(&mut Vec<f32>)) {
+        let inner = mem::replace(&mut self.inner, Array1::zeros(0));
+        let mut norms = if inner.is_standard_layout() {
+            inner.into_raw_vec()
+        } else {
+            inner.to_vec()
+        };
+        f(&mut norms);
+        self.inner = norms.into();
+    }
+}
+
+impl Deref for NdNorms {
+    type Target = Array1<f32>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl<V> From<V> for NdNorms
+where
+    V: Into<Array1<f32>>,
+{
+    fn from(array: V) -> NdNorms {
+        NdNorms::new(array)
+    }
+}
+
+impl MemoryUsage for NdNorms {
+    fn memory_usage(&self) -> MemoryFootprint {
+        MemoryFootprint::resident(self.inner.len() * size_of::<f32>())
+    }
+}
@@ -0,0 +1,200 @@
+use std::io;
+#[cfg(unix)]
+use std::mem::size_of_val;
+use std::ptr;
+
+use memmap::Mmap;
+
+use crate::error::{ErrorKind, Result};
+
+/// Expected access pattern of memory-mapped storage.
+///
+/// Access patterns are hints to the operating system, which can use
+/// them to tune read-ahead and page-cache eviction.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AccessPattern {
+    /// No specific access pattern (the default).
+    Normal,
+
+    /// Embeddings are accessed in random order, read-ahead is not
+    /// useful.
+    Random,
+
+    /// Embeddings are accessed in storage order, pages can be read
+    /// ahead aggressively and evicted soon after they are accessed.
+    Sequential,
+
+    /// The embeddings will be accessed soon, they can be paged in
+    /// ahead of time.
+    WillNeed,
+}
+
+/// Storage that accepts paging hints.
+///
+/// For memory-mapped storage, the hints are passed on to the operating
+/// system. The hints have no effect on storage that is read into
+/// memory. On platforms without `madvise`, `advise` does nothing.
+pub trait Advise {
+    /// Advise the operating system of the expected access pattern.
+    fn advise(&self, pattern: AccessPattern) -> Result<()>;
+
+    /// Prefault all pages of the storage.
+    ///
+    /// This reads one byte of every page, so that the storage is
+    /// resident in the page cache after this method returns and later
+    /// lookups do not incur page faults.
+    fn prefault(&self);
+}
+
+/// Storage that can be locked in memory.
+///
+/// Locked storage is not paged out, so lookups do not incur page
+/// faults or swapping. Memory-mapped storage is paged in when it is
+/// locked.
+///
+/// Locking fails when the process does not have permission to lock
+/// memory or would exceed its limit of locked memory (see
+/// `RLIMIT_MEMLOCK`). Storage remains usable when locking fails, so
+/// callers can fall back to unlocked storage. Locking is not supported
+/// on platforms without `mlock`.
+///
+/// Locks apply to whole pages. Unlocking heap-allocated storage can
+/// also unlock memory of other locked data that shares its first or
+/// last page.
+pub trait LockMemory {
+    /// Lock the storage in memory.
+    ///
+    /// Returns an error when the storage could not be locked.
+    fn lock_memory(&self) -> Result<()>;
+
+    /// Unlock the storage.
+    fn unlock_memory(&self) -> Result<()>;
+}
+
+/// Page size that is assumed when the page size cannot be determined.
+const FALLBACK_PAGE_SIZE: usize = 4096;
+
+#[cfg(unix)]
+fn page_size() -> usize {
+    // Safety: sysconf does not have any preconditions.
+    let size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    if size > 0 {
+        size as usize
+    } else {
+        FALLBACK_PAGE_SIZE
+    }
+}
+
+#[cfg(not(unix))]
+fn page_size() -> usize {
+    FALLBACK_PAGE_SIZE
+}
+
+/// Get the page-aligned region that contains the given data.
+#[cfg(unix)]
+fn page_region<T>(data: &[T]) -> (*mut libc::c_void, usize) {
+    // The data may start in the middle of a page, madvise and mlock
+    // require a page-aligned address.
+    let ptr = data.as_ptr() as usize;
+    let alignment = ptr % page_size();
+    (
+        (ptr - alignment) as *mut libc::c_void,
+        size_of_val(data) + alignment,
+    )
+}
+
+#[cfg(unix)]
+pub(crate) fn advise_mmap(map: &Mmap, pattern: AccessPattern) -> Result<()> {
+    if map.is_empty() {
+        return Ok(());
+    }
+
+    let advice = match pattern {
+        AccessPattern::Normal => libc::MADV_NORMAL,
+        AccessPattern::Random => libc::MADV_RANDOM,
+        AccessPattern::Sequential => libc::MADV_SEQUENTIAL,
+        AccessPattern::WillNeed => libc::MADV_WILLNEED,
+    };
+
+    // Safety: the aligned range is part of the mapping, since the
+    // mapping always starts at a page boundary.
+    let (addr, len) = page_region(map);
+    let r = unsafe { libc::madvise(addr, len, advice) };
+
+    if r != 0 {
+        return Err(ErrorKind::io_error(
+            "Cannot advise memory mapping",
+            io::Error::last_os_error(),
+        )
+        .into());
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub(crate) fn advise_mmap(_map: &Mmap, _pattern: AccessPattern) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(unix)]
+pub(crate) fn lock_slice<T>(data: &[T]) -> Result<()> {
+    if data.is_empty() {
+        return Ok(());
+    }
+
+    // Safety: mlock does not access the memory, it only has to be
+    // mapped.
+    let (addr, len) = page_region(data);
+    if unsafe { libc::mlock(addr, len) } != 0 {
+        return Err(ErrorKind::io_error(
+            "Cannot lock storage in memory",
+            io::Error::last_os_error(),
+        )
+        .into());
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+pub(crate) fn unlock_slice<T>(data: &[T]) -> Result<()> {
+    if data.is_empty() {
+        return Ok(());
+    }
+
+    // Safety: see lock_slice.
+    let (addr, len) = page_region(data);
+    if unsafe { libc::munlock(addr, len) } != 0 {
+        return Err(
+            ErrorKind::io_error("Cannot unlock storage", io::Error::last_os_error()).into(),
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub(crate) fn lock_slice<T>(_data: &[T]) -> Result<()> {
+    Err(ErrorKind::io_error(
+        "Cannot lock storage in memory",
+        io::Error::new(
+            io::ErrorKind::Other,
+            "Memory locking is not supported on this platform",
+        ),
+    )
+    .into())
+}
+
+#[cfg(not(unix))]
+pub(crate) fn unlock_slice<T>(_data: &[T]) -> Result<()> {
+    Ok(())
+}
+
+pub(crate) fn prefault_mmap(map: &Mmap) {
+    for offset in (0..map.len()).step_by(page_size()) {
+        // Safety: offset is within the mapping. The read is volatile,
+        // so that the access is not optimized away.
+        unsafe { ptr::read_volatile(map.as_ptr().add(offset)) };
+    }
+}
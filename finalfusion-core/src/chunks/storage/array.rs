@@ -0,0 +1,732 @@
+use std::fs::File;
+use std::io;
+#[cfg(not(any(unix, windows)))]
+use std::io::{Read, Seek, SeekFrom};
+use std::marker::PhantomData;
+use std::mem::{self, align_of, size_of};
+#[cfg(not(any(unix, windows)))]
+use std::sync::Mutex;
+
+use byteorder::{ByteOrder, LittleEndian};
+use memmap::Mmap;
+use ndarray::{
+    s, Array1, Array2, ArrayView1, ArrayView2, ArrayViewMut1, ArrayViewMut2, Axis, CowArray,
+    Dimension, Ix1, Ix2, ShapeBuilder,
+};
+
+use super::advice::{advise_mmap, lock_slice, prefault_mmap, unlock_slice};
+use super::{
+    AccessPattern, Advise, Element, LockMemory, MatrixLayout, SelectRows, Storage, StorageView,
+    StorageViewMut,
+};
+use crate::chunks::memory::{MemoryFootprint, MemoryUsage};
+use crate::error::{ErrorKind, Result};
+use crate::util::l2_normalize;
+
+/// Memory-mapped matrix.
+///
+/// The components of the matrix are of type `A`, which is `f32` by
+/// default.
+///
+/// Since the mapping is read-only, a truncated matrix is copied to
+/// memory when its embeddings are normalized. Retrieval and views use
+/// the normalized copy in that case.
+#[derive(Debug)]
+pub struct MmapArray<A = f32> {
+    map: Mmap,
+    shape: Ix2,
+    dims: usize,
+    normalized: Option<Array2<A>>,
+    _phantom: PhantomData<A>,
+}
+
+impl<A> MmapArray<A>
+where
+    A: Element,
+{
+    /// Construct a matrix from mapped data.
+    ///
+    /// `map` must contain a matrix of the given shape in little-endian
+    /// byte order. Only the first `dims` columns of the matrix are
+    /// used. Returns an error when the mapped data is not aligned to
+    /// the component type.
+    #[doc(hidden)]
+    pub fn from_mmap(map: Mmap, shape: Ix2, dims: usize) -> Result<Self> {
+        // Padding is relative to the start of the file, the matrix is
+        // misaligned when embeddings are stored at an unaligned offset
+        // in a larger file.
+        if map.as_ptr() as usize % align_of::<A>() != 0 {
+            return Err(ErrorKind::Format(format!(
+                "Embedding matrix is not aligned to {} bytes",
+                align_of::<A>()
+            ))
+            .into());
+        }
+
+        Ok(MmapArray {
+            map,
+            shape,
+            dims,
+            normalized: None,
+            _phantom: PhantomData,
+        })
+    }
+
+    /// Get the mapped data in file byte order.
+    #[doc(hidden)]
+    pub fn mapped_data(&self) -> &[u8] {
+        &self.map
+    }
+
+    /// Get the shape of the full (untruncated) matrix.
+    #[doc(hidden)]
+    pub fn full_shape(&self) -> Ix2 {
+        self.shape
+    }
+
+    /// Get the normalized copy of a truncated matrix.
+    ///
+    /// Returns `None` when no rows were normalized.
+    #[doc(hidden)]
+    pub fn normalized_view(&self) -> Option<ArrayView2<'_, A>> {
+        self.normalized.as_ref().map(Array2::view)
+    }
+
+    /// Normalize the first `n_rows` rows.
+    ///
+    /// The first `dims` columns of the matrix are copied to memory and
+    /// the first `n_rows` rows of the copy are normalized. Returns the
+    /// norms of the normalized rows.
+    #[doc(hidden)]
+    pub fn normalize_rows(&mut self, n_rows: usize) -> Array1<f32> {
+        let mut matrix = self.full_view().slice_move(s![.., ..self.dims]).to_owned();
+
+        #[cfg(target_endian = "big")]
+        A::from_le_slice(
+            matrix
+                .as_slice_mut()
+                .expect("Cannot borrow matrix as mutable slice"),
+        );
+
+        let norms = matrix
+            .outer_iter_mut()
+            .take(n_rows)
+            .map(A::l2_normalize)
+            .collect::<Vec<_>>();
+        self.normalized = Some(matrix);
+
+        norms.into()
+    }
+
+    /// Get a view of the full (untruncated) matrix.
+    ///
+    /// The returned view is in file byte order.
+    fn full_view(&self) -> ArrayView2<'_, A> {
+        // Alignment is ok, padding guarantees that the pointer is at
+        // a multiple of the component size.
+        #[allow(clippy::cast_ptr_alignment)]
+        unsafe {
+            ArrayView2::from_shape_ptr(self.shape, self.map.as_ptr() as *const A)
+        }
+    }
+
+    /// Get a view of the matrix with its original component type.
+    #[cfg(target_endian = "little")]
+    pub fn matrix_view(&self) -> ArrayView2<'_, A> {
+        match self.normalized_view() {
+            Some(matrix) => matrix,
+            None => self.full_view().slice_move(s![.., ..self.dims]),
+        }
+    }
+
+    /// Get a row of the matrix with its original component type.
+    ///
+    /// In contrast to `Storage::embedding`, the components are not
+    /// converted to `f32`.
+    pub fn matrix_row(&self, idx: usize) -> Array1<A> {
+        if let Some(matrix) = self.normalized_view() {
+            return matrix.row(idx).to_owned();
+        }
+
+        #[allow(unused_mut)]
+        let mut row = self.full_view().slice_move(s![idx, ..self.dims]).to_owned();
+
+        #[cfg(target_endian = "big")]
+        A::from_le_slice(
+            row.as_slice_mut()
+                .expect("Cannot borrow vector as mutable slice"),
+        );
+
+        row
+    }
+}
+
+impl<A> Storage for MmapArray<A>
+where
+    A: Element,
+{
+    fn embedding(&self, idx: usize) -> CowArray<'_, f32, Ix1> {
+        CowArray::from(A::into_f32_owned(self.matrix_row(idx)))
+    }
+
+    fn embedding_into(&self, idx: usize, mut out: ArrayViewMut1<f32>) {
+        #[cfg(target_endian = "little")]
+        out.assign(&A::as_f32(self.matrix_view().row(idx)));
+
+        #[cfg(target_endian = "big")]
+        out.assign(&self.embedding(idx));
+    }
+
+    #[cfg(target_endian = "little")]
+    fn embeddings(&self, indices: &[usize]) -> Array2<f32> {
+        A::into_f32(self.matrix_view().select(Axis(0), indices))
+    }
+
+    fn shape(&self) -> (usize, usize) {
+        (self.shape[0], self.dims)
+    }
+}
+
+impl<A> SelectRows for MmapArray<A>
+where
+    A: Element,
+{
+    type Output = NdArray<A>;
+
+    fn select_rows(&self, indices: &[usize]) -> NdArray<A> {
+        if let Some(matrix) = self.normalized_view() {
+            return NdArray::new(matrix.select(Axis(0), indices));
+        }
+
+        #[allow(unused_mut)]
+        let mut selected = self
+            .full_view()
+            .slice_move(s![.., ..self.dims])
+            .select(Axis(0), indices);
+
+        #[cfg(target_endian = "big")]
+        A::from_le_slice(
+            selected
+                .as_slice_mut()
+                .expect("Cannot borrow matrix as mutable slice"),
+        );
+
+        NdArray::new(selected)
+    }
+}
+
+#[cfg(target_endian = "little")]
+impl StorageView for MmapArray {
+    fn view(&self) -> ArrayView2<'_, f32> {
+        self.matrix_view()
+    }
+}
+
+impl StorageViewMut for NdArray {
+    fn view_mut(&mut self) -> ArrayViewMut2<'_, f32> {
+        self.inner.view_mut()
+    }
+}
+
+impl<A> MemoryUsage for MmapArray<A> {
+    fn memory_usage(&self) -> MemoryFootprint {
+        let normalized = self
+            .normalized
+            .as_ref()
+            .map(|matrix| matrix.len() * size_of::<A>())
+            .unwrap_or(0);
+        MemoryFootprint::mapped(self.map.len()) + MemoryFootprint::resident(normalized)
+    }
+}
+
+impl<A> Advise for MmapArray<A> {
+    fn advise(&self, pattern: AccessPattern) -> Result<()> {
+        advise_mmap(&self.map, pattern)
+    }
+
+    fn prefault(&self) {
+        prefault_mmap(&self.map)
+    }
+}
+
+impl<A> LockMemory for MmapArray<A> {
+    fn lock_memory(&self) -> Result<()> {
+        lock_slice(&self.map)
+    }
+
+    fn unlock_memory(&self) -> Result<()> {
+        unlock_slice(&self.map)
+    }
+}
+
+/// Matrix that is read from a file on demand.
+///
+/// In contrast to `MmapArray`, the embedding matrix is not memory
+/// mapped. Instead, every embedding is retrieved using a positioned
+/// read on the underlying file when it is requested. This is useful
+/// when memory mapping is undesirable or unavailable, e.g. on network
+/// file systems or when memory use should be accounted strictly.
+///
+/// Since `Storage::embedding` cannot fail, retrieving an embedding
+/// through `Storage` panics when the embedding cannot be read from the
+/// file. Use `try_embedding` or `try_embedding_into` to handle read
+/// errors.
+#[derive(Debug)]
+pub struct PreadArray {
+    file: File,
+    offset: u64,
+    shape: Ix2,
+    dims: usize,
+    normalized_rows: usize,
+
+    // Platforms without positioned reads fall back to seeking, which
+    // requires exclusive access to the file cursor.
+    #[cfg(not(any(unix, windows)))]
+    lock: Mutex<()>,
+}
+
+impl PreadArray {
+    /// Construct a matrix that is read from `file`.
+    ///
+    /// The matrix of the given shape is stored at `offset` in
+    /// little-endian byte order. Only the first `dims` columns of the
+    /// matrix are used.
+    #[doc(hidden)]
+    pub fn from_file(file: File, offset: u64, shape: Ix2, dims: usize) -> Self {
+        PreadArray {
+            file,
+            offset,
+            shape,
+            dims,
+            normalized_rows: 0,
+            #[cfg(not(any(unix, windows)))]
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Get the shape of the full (untruncated) matrix.
+    #[doc(hidden)]
+    pub fn full_shape(&self) -> Ix2 {
+        self.shape
+    }
+
+    /// Normalize the first `n_rows` rows on retrieval.
+    #[doc(hidden)]
+    pub fn set_normalized_rows(&mut self, n_rows: usize) {
+        self.normalized_rows = n_rows;
+    }
+
+    /// Read the embedding at the given index from the file.
+    ///
+    /// Returns an error when the embedding cannot be read.
+    pub fn try_embedding(&self, idx: usize) -> Result<Array1<f32>> {
+        let mut embedding = Array1::zeros(self.dims);
+        self.read_embedding_into(
+            idx,
+            embedding
+                .as_slice_mut()
+                .expect("Cannot borrow vector as mutable slice"),
+        )
+        .map_err(|e| ErrorKind::io_error("Cannot read embedding from file", e))?;
+        Ok(embedding)
+    }
+
+    /// Read the embedding at the given index from the file into `out`.
+    ///
+    /// Returns an error when the embedding cannot be read.
+    pub fn try_embedding_into(&self, idx: usize, mut out: ArrayViewMut1<f32>) -> Result<()> {
+        match out.as_slice_mut() {
+            Some(out) => self
+                .read_embedding_into(idx, out)
+                .map_err(|e| ErrorKind::io_error("Cannot read embedding from file", e).into()),
+            None => {
+                out.assign(&self.try_embedding(idx)?);
+                Ok(())
+            }
+        }
+    }
+
+    fn read_embedding_into(&self, idx: usize, embedding: &mut [f32]) -> io::Result<()> {
+        let (rows, cols) = self.shape.into_pattern();
+        assert!(
+            idx < rows,
+            "Embedding index out of bounds: {} >= {}",
+            idx,
+            rows
+        );
+        assert_eq!(
+            embedding.len(),
+            self.dims,
+            "Embeddings have {} dimensions, whereas target array has {}",
+            self.dims,
+            embedding.len()
+        );
+
+        // Only the first dims components of the row are read. The
+        // components are read directly into the embedding and then
+        // converted to the native byte order.
+        let row_len = cols * size_of::<f32>();
+        let buf = unsafe {
+            std::slice::from_raw_parts_mut(
+                embedding.as_mut_ptr() as *mut u8,
+                self.dims * size_of::<f32>(),
+            )
+        };
+        self.read_exact_at(buf, self.offset + (idx * row_len) as u64)?;
+        LittleEndian::from_slice_f32(embedding);
+
+        if idx < self.normalized_rows {
+            l2_normalize(ArrayViewMut1::from(embedding));
+        }
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        use std::os::unix::fs::FileExt;
+
+        self.file.read_exact_at(buf, offset)
+    }
+
+    #[cfg(windows)]
+    fn read_exact_at(&self, mut buf: &mut [u8], mut offset: u64) -> io::Result<()> {
+        use std::os::windows::fs::FileExt;
+
+        while !buf.is_empty() {
+            match self.file.seek_read(buf, offset) {
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(n) => {
+                    buf = &mut buf[n..];
+                    offset += n as u64;
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(())
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        let _guard = self.lock.lock().expect("Cannot acquire file lock");
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(buf)
+    }
+}
+
+impl Storage for PreadArray {
+    fn embedding(&self, idx: usize) -> CowArray<'_, f32, Ix1> {
+        CowArray::from(
+            self.try_embedding(idx)
+                .expect("Cannot read embedding from file"),
+        )
+    }
+
+    fn embedding_into(&self, idx: usize, out: ArrayViewMut1<f32>) {
+        self.try_embedding_into(idx, out)
+            .expect("Cannot read embedding from file")
+    }
+
+    fn shape(&self) -> (usize, usize) {
+        (self.shape[0], self.dims)
+    }
+}
+
+impl SelectRows for PreadArray {
+    type Output = NdArray;
+
+    fn select_rows(&self, indices: &[usize]) -> NdArray {
+        NdArray::new(self.embeddings(indices))
+    }
+}
+
+impl MemoryUsage for PreadArray {
+    /// Embeddings are read from the file on demand, so the matrix
+    /// does not use memory.
+    fn memory_usage(&self) -> MemoryFootprint {
+        MemoryFootprint::default()
+    }
+}
+
+/// In-memory `ndarray` matrix.
+///
+/// The components of the matrix are of type `A`, which is `f32` by
+/// default.
+#[derive(Clone, Debug)]
+pub struct NdArray<A = f32> {
+    inner: Array2<A>,
+}
+
+impl<A> NdArray<A> {
+    pub fn new(arr: Array2<A>) -> Self {
+        NdArray { inner: arr }
+    }
+
+    /// Get a view of the matrix with its original component type.
+    pub fn matrix_view(&self) -> ArrayView2<'_, A> {
+        self.inner.view()
+    }
+
+    /// Get a mutable view of the matrix with its original component type.
+    #[doc(hidden)]
+    pub fn matrix_view_mut(&mut self) -> ArrayViewMut2<'_, A> {
+        self.inner.view_mut()
+    }
+}
+
+impl<A> NdArray<A>
+where
+    A: Clone,
+{
+    /// Append rows to the matrix.
+    ///
+    /// The matrix storage is grown in place when possible, so that
+    /// appending rows one by one takes amortized linear time.
+    ///
+    /// Panics when `rows` does not have the same number of columns as
+    /// the matrix.
+    pub fn append_rows(&mut self, rows: ArrayView2<A>) {
+        let (n_rows, n_cols) = self.inner.dim();
+        assert_eq!(
+            n_cols,
+            rows.ncols(),
+            "Matrix has {} columns, whereas appended rows have {}",
+            n_cols,
+            rows.ncols()
+        );
+
+        let mut data = self.take_data();
+        data.extend(rows.iter().cloned());
+
+        self.inner = Array2::from_shape_vec((n_rows + rows.nrows(), n_cols), data)
+            .expect("Matrix data does not match its shape");
+    }
+
+    /// Reserve capacity for at least `additional` more rows.
+    ///
+    /// Reserving capacity avoids reallocations when rows are appended
+    /// incrementally. This has no effect on matrices in column-major
+    /// order, since appending rows converts them to row-major order.
+    pub fn reserve_rows(&mut self, additional: usize) {
+        if !self.inner.is_standard_layout() {
+            return;
+        }
+
+        let shape = self.inner.dim();
+        let mut data = self.take_data();
+        data.reserve(additional * shape.1);
+        self.inner =
+            Array2::from_shape_vec(shape, data).expect("Matrix data does not match its shape");
+    }
+
+    /// Shrink the capacity of the matrix storage as much as possible.
+    pub fn shrink_to_fit(&mut self) {
+        if !self.inner.is_standard_layout() {
+            return;
+        }
+
+        let shape = self.inner.dim();
+        let mut data = self.take_data();
+        data.shrink_to_fit();
+        self.inner =
+            Array2::from_shape_vec(shape, data).expect("Matrix data does not match its shape");
+    }
+
+    /// Take the data of the matrix in row-major order.
+    ///
+    /// The matrix is left empty, with the same number of columns.
+    fn take_data(&mut self) -> Vec<A> {
+        let empty = Array2::from_shape_vec((0, self.inner.ncols()), Vec::new())
+            .expect("Empty matrix does not match its shape");
+        let inner = mem::replace(&mut self.inner, empty);
+        if inner.is_standard_layout() {
+            inner.into_raw_vec()
+        } else {
+            inner.iter().cloned().collect()
+        }
+    }
+
+    /// Append a row to the matrix.
+    ///
+    /// Panics when `row` does not have the same number of columns as
+    /// the matrix.
+    pub fn push_row(&mut self, row: ArrayView1<A>) {
+        self.append_rows(row.insert_axis(Axis(0)));
+    }
+
+    /// Convert the matrix to column-major order.
+    ///
+    /// In column-major order, the components of a column are stored
+    /// contiguously. This makes batched operations over the whole
+    /// matrix, such as computing the similarities of all words to a
+    /// query, faster in some BLAS routines, at the cost of slower
+    /// lookups of single embeddings. The matrix is always written in
+    /// row-major order.
+    pub fn into_column_major(self) -> Self {
+        if MatrixLayout::of(self.inner.view()) == MatrixLayout::ColumnMajor {
+            return self;
+        }
+
+        let data = self.inner.t().iter().cloned().collect();
+        NdArray {
+            inner: Array2::from_shape_vec(self.inner.dim().f(), data)
+                .expect("Matrix data does not match its shape"),
+        }
+    }
+
+    /// Convert the matrix to row-major order.
+    ///
+    /// Matrices are in row-major order, unless they were converted to
+    /// column-major order using `into_column_major`.
+    pub fn into_row_major(self) -> Self {
+        if MatrixLayout::of(self.inner.view()) == MatrixLayout::RowMajor {
+            return self;
+        }
+
+        let data = self.inner.iter().cloned().collect();
+        NdArray {
+            inner: Array2::from_shape_vec(self.inner.dim(), data)
+                .expect("Matrix data does not match its shape"),
+        }
+    }
+}
+
+impl<A> LockMemory for NdArray<A> {
+    fn lock_memory(&self) -> Result<()> {
+        lock_slice(owned_slice(&self.inner))
+    }
+
+    fn unlock_memory(&self) -> Result<()> {
+        unlock_slice(owned_slice(&self.inner))
+    }
+}
+
+/// Get the memory of an owned matrix as a slice.
+fn owned_slice<A>(matrix: &Array2<A>) -> &[A] {
+    matrix
+        .as_slice_memory_order()
+        .expect("Owned matrix is not contiguous")
+}
+
+impl<A> SelectRows for NdArray<A>
+where
+    A: Copy,
+{
+    type Output = NdArray<A>;
+
+    fn select_rows(&self, indices: &[usize]) -> NdArray<A> {
+        NdArray::new(self.inner.select(Axis(0), indices))
+    }
+}
+
+impl<A> From<Array2<A>> for NdArray<A> {
+    fn from(arr: Array2<A>) -> Self {
+        NdArray::new(arr)
+    }
+}
+
+impl<A> Storage for NdArray<A>
+where
+    A: Element,
+{
+    fn embedding(&self, idx: usize) -> CowArray<'_, f32, Ix1> {
+        A::as_f32(self.inner.row(idx))
+    }
+
+    fn embeddings(&self, indices: &[usize]) -> Array2<f32> {
+        A::into_f32(self.inner.select(Axis(0), indices))
+    }
+
+    fn shape(&self) -> (usize, usize) {
+        self.inner.dim()
+    }
+}
+
+impl<A> MemoryUsage for NdArray<A> {
+    fn memory_usage(&self) -> MemoryFootprint {
+        MemoryFootprint::resident(self.inner.len() * size_of::<A>())
+    }
+}
+
+impl StorageView for NdArray {
+    fn view(&self) -> ArrayView2<'_, f32> {
+        self.inner.view()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{s, Array1, Array2};
+
+    use crate::storage::{MatrixLayout, NdArray, Storage, StorageView};
+
+    const N_ROWS: usize = 100;
+    const N_COLS: usize = 100;
+
+    fn test_ndarray() -> NdArray {
+        let test_data = Array2::from_shape_fn((N_ROWS, N_COLS), |(r, c)| {
+            r as f32 * N_COLS as f32 + c as f32
+        });
+
+        NdArray::new(test_data)
+    }
+
+    #[test]
+    fn ndarray_embedding_into() {
+        let arr = test_ndarray();
+        let mut out = Array1::zeros(N_COLS);
+        arr.embedding_into(42, out.view_mut());
+        assert_eq!(out, arr.embedding(42));
+    }
+
+    #[test]
+    fn ndarray_embeddings() {
+        let arr = test_ndarray();
+        let indices = [3, 1, 4, 1, 5];
+        let embeddings = arr.embeddings(&indices);
+        for (&idx, embedding) in indices.iter().zip(embeddings.outer_iter()) {
+            assert_eq!(embedding, arr.embedding(idx).view());
+        }
+    }
+
+    #[test]
+    fn ndarray_append_rows() {
+        let check_arr = test_ndarray();
+        let mut arr = NdArray::new(check_arr.view().slice(s![..10, ..]).to_owned());
+        arr.append_rows(check_arr.view().slice(s![10..50, ..]));
+        for row in check_arr.view().slice(s![50.., ..]).outer_iter() {
+            arr.push_row(row);
+        }
+        assert_eq!(arr.view(), check_arr.view());
+
+        // Matrices that are not in standard layout.
+        let mut arr = NdArray::new(check_arr.view().t().to_owned().reversed_axes());
+        arr.push_row(check_arr.embedding(0).view());
+        assert_eq!(arr.shape(), (N_ROWS + 1, N_COLS));
+        assert_eq!(arr.view().slice(s![..N_ROWS, ..]), check_arr.view());
+        assert_eq!(arr.embedding(N_ROWS), check_arr.embedding(0));
+    }
+
+    #[test]
+    fn ndarray_column_major() {
+        let check_arr = test_ndarray();
+        let arr = check_arr.clone().into_column_major();
+        assert_eq!(arr.layout(), MatrixLayout::ColumnMajor);
+        assert_eq!(arr.view(), check_arr.view());
+        assert_eq!(arr.embedding(3), check_arr.embedding(3));
+
+        let arr = arr.into_row_major();
+        assert_eq!(arr.layout(), MatrixLayout::RowMajor);
+        assert_eq!(arr.view(), check_arr.view());
+    }
+
+    #[test]
+    #[should_panic]
+    fn ndarray_append_rows_rejects_incorrect_dims() {
+        let mut arr = test_ndarray();
+        arr.push_row(Array1::zeros(N_COLS - 1).view());
+    }
+}
@@ -0,0 +1,166 @@
+use std::io;
+use std::mem::size_of;
+use std::sync::Arc;
+
+use ndarray::{Array2, ArrayBase, ArrayView2, Axis, CowArray, Data, Ix1, Ix2};
+
+use super::advice::{lock_slice, unlock_slice};
+use super::{LockMemory, NdArray, SelectRows, Storage, StorageView};
+use crate::chunks::memory::{MemoryFootprint, MemoryUsage};
+use crate::error::{ErrorKind, Result};
+
+/// Embedding matrix that borrows an externally owned matrix.
+///
+/// This storage type makes it possible to use a matrix that is owned
+/// by another library, such as an embedding trainer, as the storage
+/// of `Embeddings` without copying it. The borrowed matrix does not
+/// have to be contiguous.
+///
+/// Since the matrix is borrowed, it is not counted in the memory
+/// usage of the storage.
+#[derive(Clone, Debug)]
+pub struct BorrowedArray<'a> {
+    inner: ArrayView2<'a, f32>,
+}
+
+impl<'a> BorrowedArray<'a> {
+    /// Construct storage from a borrowed matrix.
+    pub fn new(view: ArrayView2<'a, f32>) -> Self {
+        BorrowedArray { inner: view }
+    }
+}
+
+impl<'a> From<ArrayView2<'a, f32>> for BorrowedArray<'a> {
+    fn from(view: ArrayView2<'a, f32>) -> Self {
+        BorrowedArray::new(view)
+    }
+}
+
+impl<'a> Storage for BorrowedArray<'a> {
+    fn embedding(&self, idx: usize) -> CowArray<'_, f32, Ix1> {
+        CowArray::from(self.inner.row(idx))
+    }
+
+    fn shape(&self) -> (usize, usize) {
+        self.inner.dim()
+    }
+}
+
+impl<'a> StorageView for BorrowedArray<'a> {
+    fn view(&self) -> ArrayView2<'_, f32> {
+        self.inner.view()
+    }
+}
+
+impl<'a> SelectRows for BorrowedArray<'a> {
+    type Output = NdArray;
+
+    fn select_rows(&self, indices: &[usize]) -> NdArray {
+        NdArray::new(self.inner.select(Axis(0), indices))
+    }
+}
+
+impl<'a> MemoryUsage for BorrowedArray<'a> {
+    fn memory_usage(&self) -> MemoryFootprint {
+        MemoryFootprint::default()
+    }
+}
+
+impl<'a> LockMemory for BorrowedArray<'a> {
+    /// Lock the borrowed matrix in memory.
+    ///
+    /// Fails when the borrowed matrix is not contiguous.
+    fn lock_memory(&self) -> Result<()> {
+        lock_slice(contiguous_slice(&self.inner)?)
+    }
+
+    fn unlock_memory(&self) -> Result<()> {
+        unlock_slice(contiguous_slice(&self.inner)?)
+    }
+}
+
+/// Embedding matrix that is shared through reference counting.
+///
+/// This storage type shares ownership of the matrix with other
+/// owners, such as an embedding trainer that keeps updating its copy.
+/// Since the matrix is shared, it is not copied when constructing
+/// `Embeddings`.
+#[derive(Clone, Debug)]
+pub struct SharedArray {
+    inner: Arc<Array2<f32>>,
+}
+
+impl SharedArray {
+    /// Construct storage from a shared matrix.
+    pub fn new(matrix: Arc<Array2<f32>>) -> Self {
+        SharedArray { inner: matrix }
+    }
+
+    /// Get the shared matrix.
+    pub fn matrix(&self) -> &Arc<Array2<f32>> {
+        &self.inner
+    }
+}
+
+impl From<Arc<Array2<f32>>> for SharedArray {
+    fn from(matrix: Arc<Array2<f32>>) -> Self {
+        SharedArray::new(matrix)
+    }
+}
+
+impl Storage for SharedArray {
+    fn embedding(&self, idx: usize) -> CowArray<'_, f32, Ix1> {
+        CowArray::from(self.inner.row(idx))
+    }
+
+    fn shape(&self) -> (usize, usize) {
+        self.inner.dim()
+    }
+}
+
+impl StorageView for SharedArray {
+    fn view(&self) -> ArrayView2<'_, f32> {
+        self.inner.view()
+    }
+}
+
+impl SelectRows for SharedArray {
+    type Output = NdArray;
+
+    fn select_rows(&self, indices: &[usize]) -> NdArray {
+        NdArray::new(self.inner.select(Axis(0), indices))
+    }
+}
+
+impl MemoryUsage for SharedArray {
+    /// Get the memory footprint.
+    ///
+    /// The shared matrix is counted in full, even when it is also
+    /// owned elsewhere.
+    fn memory_usage(&self) -> MemoryFootprint {
+        MemoryFootprint::resident(self.inner.len() * size_of::<f32>())
+    }
+}
+
+impl LockMemory for SharedArray {
+    fn lock_memory(&self) -> Result<()> {
+        lock_slice(contiguous_slice(&*self.inner)?)
+    }
+
+    fn unlock_memory(&self) -> Result<()> {
+        unlock_slice(contiguous_slice(&*self.inner)?)
+    }
+}
+
+fn contiguous_slice<S>(matrix: &ArrayBase<S, Ix2>) -> Result<&[f32]>
+where
+    S: Data<Elem = f32>,
+{
+    matrix.as_slice_memory_order().ok_or_else(|| {
+        ErrorKind::io_error(
+            "Cannot lock storage in memory",
+            io::Error::new(io::ErrorKind::InvalidInput, "Matrix is not contiguous"),
+        )
+        .into()
+    })
+}
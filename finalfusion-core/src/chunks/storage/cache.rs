@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+use std::mem::size_of;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use ndarray::{Array1, ArrayView1, ArrayViewMut1};
+
+/// Bounded cache of reconstructed embeddings.
+///
+/// When the cache is full, the least recently used embedding is
+/// evicted. The cache uses interior mutability, so that it can be
+/// updated during lookups through a shared reference.
+pub(crate) struct ReconstructionCache {
+    capacity: usize,
+    entries: Mutex<Entries>,
+}
+
+impl ReconstructionCache {
+    /// Construct a cache that holds up to `capacity` embeddings.
+    ///
+    /// Panics when `capacity` is zero.
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "Cache capacity must be at least 1");
+
+        ReconstructionCache {
+            capacity,
+            entries: Mutex::new(Entries::default()),
+        }
+    }
+
+    /// Get the maximum number of cached embeddings.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Copy the cached embedding of `idx` into `out`.
+    ///
+    /// Returns `false` if the embedding is not cached.
+    pub fn copy_into(&self, idx: usize, mut out: ArrayViewMut1<f32>) -> bool {
+        let mut entries = self.lock();
+        match entries.map.get(&idx).copied() {
+            Some(slot) => {
+                entries.promote(slot);
+                out.assign(&entries.slots[slot].embedding);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Add the embedding of `idx` to the cache.
+    pub fn insert(&self, idx: usize, embedding: ArrayView1<f32>) {
+        let mut entries = self.lock();
+        if let Some(&slot) = entries.map.get(&idx) {
+            entries.promote(slot);
+            return;
+        }
+
+        let slot = if entries.slots.len() < self.capacity {
+            entries.slots.push(Slot {
+                idx,
+                embedding: embedding.to_owned(),
+                prev: None,
+                next: None,
+            });
+            entries.slots.len() - 1
+        } else {
+            // Reuse the slot of the least recently used embedding.
+            let slot = entries.tail.expect("Full cache without entries");
+            entries.unlink(slot);
+            let evicted = entries.slots[slot].idx;
+            entries.map.remove(&evicted);
+            entries.slots[slot].idx = idx;
+            entries.slots[slot].embedding.assign(&embedding);
+            slot
+        };
+
+        entries.map.insert(idx, slot);
+        entries.push_front(slot);
+    }
+
+    /// Remove all embeddings from the cache.
+    pub fn clear(&self) {
+        *self.lock() = Entries::default();
+    }
+
+    /// Get the number of bytes used by the cached embeddings.
+    pub fn memory_usage(&self) -> usize {
+        self.lock()
+            .slots
+            .iter()
+            .map(|slot| slot.embedding.len() * size_of::<f32>())
+            .sum()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Entries> {
+        // The entries are consistent at the end of every critical
+        // section, so a poisoned lock can be recovered.
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Cache entries in a doubly-linked list, ordered by recency.
+#[derive(Default)]
+struct Entries {
+    map: HashMap<usize, usize>,
+    slots: Vec<Slot>,
+    head: Option<usize>,
+    tail: Option<usize>,
+}
+
+impl Entries {
+    fn promote(&mut self, slot: usize) {
+        if self.head != Some(slot) {
+            self.unlink(slot);
+            self.push_front(slot);
+        }
+    }
+
+    fn push_front(&mut self, slot: usize) {
+        self.slots[slot].prev = None;
+        self.slots[slot].next = self.head;
+        match self.head {
+            Some(head) => self.slots[head].prev = Some(slot),
+            None => self.tail = Some(slot),
+        }
+        self.head = Some(slot);
+    }
+
+    fn unlink(&mut self, slot: usize) {
+        let Slot { prev, next, .. } = self.slots[slot];
+        match prev {
+            Some(prev) => self.slots[prev].next = next,
+            None => self.head = next,
+        }
+        match next {
+            Some(next) => self.slots[next].prev = prev,
+            None => self.tail = prev,
+        }
+    }
+}
+
+struct Slot {
+    idx: usize,
+    embedding: Array1<f32>,
+    prev: Option<usize>,
+    next: Option<usize>,
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{array, Array1};
+
+    use super::ReconstructionCache;
+
+    fn cached(cache: &ReconstructionCache, idx: usize) -> Option<Array1<f32>> {
+        let mut out = Array1::zeros(2);
+        if cache.copy_into(idx, out.view_mut()) {
+            Some(out)
+        } else {
+            None
+        }
+    }
+
+    #[test]
+    fn cache_evicts_least_recently_used() {
+        let cache = ReconstructionCache::new(2);
+        cache.insert(1, array![1., 1.].view());
+        cache.insert(2, array![2., 2.].view());
+        assert_eq!(cached(&cache, 1), Some(array![1., 1.]));
+
+        // 2 is the least recently used embedding.
+        cache.insert(3, array![3., 3.].view());
+        assert_eq!(cached(&cache, 2), None);
+        assert_eq!(cached(&cache, 1), Some(array![1., 1.]));
+        assert_eq!(cached(&cache, 3), Some(array![3., 3.]));
+
+        cache.insert(4, array![4., 4.].view());
+        assert_eq!(cached(&cache, 1), None);
+        assert_eq!(cached(&cache, 3), Some(array![3., 3.]));
+        assert_eq!(cached(&cache, 4), Some(array![4., 4.]));
+        assert_eq!(cache.memory_usage(), 16);
+
+        cache.clear();
+        assert_eq!(cached(&cache, 4), None);
+        assert_eq!(cache.memory_usage(), 0);
+    }
+}
@@ -0,0 +1,253 @@
+use std::collections::HashMap;
+use std::mem::size_of;
+
+use ndarray::{Array2, ArrayView1, ArrayViewMut1, Axis, CowArray, Ix1};
+
+use super::advice::{lock_slice, unlock_slice};
+use super::{LockMemory, SelectRows, Storage};
+use crate::chunks::memory::{MemoryFootprint, MemoryUsage};
+use crate::error::Result;
+
+/// Embedding matrix with deduplicated rows.
+///
+/// This storage type stores every unique embedding once. A table maps
+/// the index of each embedding to a row of the matrix of unique
+/// embeddings. This reduces memory use for embedding matrices with
+/// many duplicate rows, such as matrices where many words share a
+/// placeholder embedding.
+///
+/// A `DedupArray` is typically constructed with `Prune::prune`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DedupArray {
+    embeddings: Array2<f32>,
+    indices: Vec<u32>,
+}
+
+impl DedupArray {
+    /// Construct a deduplicated matrix.
+    ///
+    /// `embeddings` contains the unique embeddings and `indices[i]`
+    /// is the row of the embedding with index *i* in `embeddings`.
+    ///
+    /// Panics when an index is not a row of `embeddings`.
+    pub fn new(embeddings: Array2<f32>, indices: Vec<u32>) -> Self {
+        assert!(
+            indices
+                .iter()
+                .all(|&idx| (idx as usize) < embeddings.nrows()),
+            "Indices must be rows of the embedding matrix"
+        );
+
+        DedupArray {
+            embeddings,
+            indices,
+        }
+    }
+
+    /// Get the matrix of unique embeddings.
+    pub fn unique_embeddings(&self) -> &Array2<f32> {
+        &self.embeddings
+    }
+
+    /// Get the rows of the embeddings in the matrix of unique embeddings.
+    pub fn indices(&self) -> &[u32] {
+        &self.indices
+    }
+}
+
+impl Storage for DedupArray {
+    fn embedding(&self, idx: usize) -> CowArray<'_, f32, Ix1> {
+        CowArray::from(self.embeddings.row(self.indices[idx] as usize))
+    }
+
+    fn embedding_into(&self, idx: usize, mut out: ArrayViewMut1<f32>) {
+        out.assign(&self.embeddings.row(self.indices[idx] as usize));
+    }
+
+    fn shape(&self) -> (usize, usize) {
+        (self.indices.len(), self.embeddings.ncols())
+    }
+}
+
+impl SelectRows for DedupArray {
+    type Output = DedupArray;
+
+    /// Select the embeddings at the given indices.
+    ///
+    /// Unique embeddings that are not used by the selected
+    /// embeddings are removed.
+    fn select_rows(&self, indices: &[usize]) -> DedupArray {
+        let mut unique_rows = Vec::new();
+        let mut remapped = HashMap::new();
+        let selected_indices = indices
+            .iter()
+            .map(|&idx| {
+                let unique_row = self.indices[idx];
+                *remapped.entry(unique_row).or_insert_with(|| {
+                    unique_rows.push(unique_row as usize);
+                    unique_rows.len() as u32 - 1
+                })
+            })
+            .collect();
+
+        DedupArray {
+            embeddings: self.embeddings.select(Axis(0), &unique_rows),
+            indices: selected_indices,
+        }
+    }
+}
+
+impl MemoryUsage for DedupArray {
+    fn memory_usage(&self) -> MemoryFootprint {
+        MemoryFootprint::resident(
+            self.embeddings.len() * size_of::<f32>() + self.indices.capacity() * size_of::<u32>(),
+        )
+    }
+}
+
+impl LockMemory for DedupArray {
+    fn lock_memory(&self) -> Result<()> {
+        lock_slice(
+            self.embeddings
+                .as_slice_memory_order()
+                .expect("Owned matrix is not contiguous"),
+        )?;
+        if let Err(err) = lock_slice(&self.indices) {
+            self.unlock_memory()?;
+            return Err(err);
+        }
+
+        Ok(())
+    }
+
+    fn unlock_memory(&self) -> Result<()> {
+        unlock_slice(
+            self.embeddings
+                .as_slice_memory_order()
+                .expect("Owned matrix is not contiguous"),
+        )?;
+        unlock_slice(&self.indices)
+    }
+}
+
+/// Embedding matrices that can be pruned by deduplicating rows.
+pub trait Prune {
+    /// Deduplicate the rows of the embedding matrix.
+    ///
+    /// Rows are duplicates when they are equal after rounding their
+    /// components to multiples of `tolerance`. Only identical rows are
+    /// deduplicated when `tolerance` is zero. Since rows are compared
+    /// after rounding, two rows whose components are within
+    /// `tolerance` of each other are not deduplicated when they are
+    /// rounded to different multiples. Of every set of duplicates, the
+    /// first row is stored.
+    ///
+    /// Panics when `tolerance` is negative or when there are more than
+    /// *2^32* unique rows.
+    fn prune(&self, tolerance: f32) -> DedupArray;
+}
+
+impl<S> Prune for S
+where
+    S: Storage,
+{
+    fn prune(&self, tolerance: f32) -> DedupArray {
+        assert!(tolerance >= 0., "Tolerance must not be negative");
+
+        let (rows, cols) = self.shape();
+        let mut unique = HashMap::new();
+        let mut data = Vec::new();
+        let mut indices = Vec::with_capacity(rows);
+
+        for idx in 0..rows {
+            let embedding = self.embedding(idx);
+            let n_unique = unique.len();
+            let unique_idx = *unique
+                .entry(dedup_key(embedding.view(), tolerance))
+                .or_insert_with(|| {
+                    data.extend(embedding.iter());
+                    n_unique
+                });
+
+            assert!(
+                unique_idx <= u32::MAX as usize,
+                "Too many unique embeddings"
+            );
+            indices.push(unique_idx as u32);
+        }
+
+        let embeddings = Array2::from_shape_vec((unique.len(), cols), data)
+            .expect("Unique embeddings do not match their shape");
+
+        DedupArray {
+            embeddings,
+            indices,
+        }
+    }
+}
+
+/// Get the key that is used to find duplicate embeddings.
+fn dedup_key(embedding: ArrayView1<f32>, tolerance: f32) -> Vec<i64> {
+    if tolerance == 0. {
+        // Adding zero maps -0 to 0.
+        embedding
+            .iter()
+            .map(|&v| (v + 0.).to_bits() as i64)
+            .collect()
+    } else {
+        embedding
+            .iter()
+            .map(|&v| (v / tolerance).round() as i64)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+
+    use super::Prune;
+    use crate::storage::{NdArray, SelectRows, Storage};
+
+    fn test_ndarray() -> NdArray {
+        NdArray::new(array![
+            [1., 2., 3.],
+            [0., 0., 0.],
+            [1., 2., 3.],
+            [0., 0., 0.],
+            [1., 2.001, 3.],
+            [4., 5., 6.]
+        ])
+    }
+
+    #[test]
+    fn prune_deduplicates_identical_rows() {
+        let arr = test_ndarray();
+        let pruned = arr.prune(0.);
+        assert_eq!(pruned.shape(), arr.shape());
+        assert_eq!(pruned.unique_embeddings().nrows(), 4);
+        assert_eq!(pruned.indices(), &[0, 1, 0, 1, 2, 3]);
+        for idx in 0..arr.shape().0 {
+            assert_eq!(pruned.embedding(idx), arr.embedding(idx));
+        }
+    }
+
+    #[test]
+    fn prune_deduplicates_near_identical_rows() {
+        let pruned = test_ndarray().prune(0.1);
+        assert_eq!(pruned.unique_embeddings().nrows(), 3);
+        assert_eq!(pruned.indices(), &[0, 1, 0, 1, 0, 2]);
+        assert_eq!(pruned.embedding(4), array![1., 2., 3.]);
+    }
+
+    #[test]
+    fn select_rows_removes_unused_embeddings() {
+        let pruned = test_ndarray().prune(0.);
+        let selected = pruned.select_rows(&[3, 5, 1]);
+        assert_eq!(
+            selected.unique_embeddings(),
+            &array![[0., 0., 0.], [4., 5., 6.]]
+        );
+        assert_eq!(selected.indices(), &[0, 1, 0]);
+    }
+}
@@ -0,0 +1,124 @@
+use std::mem::size_of;
+
+use ndarray::{Array2, ArrayViewMut1, CowArray, Ix1};
+
+use super::{NdArray, SelectRows, Storage, StorageWrap};
+use crate::chunks::memory::{MemoryFootprint, MemoryUsage};
+
+/// Embedding matrix that layers several embedding matrices.
+///
+/// This is the storage of `LayeredVocab`. The embedding of a word is
+/// retrieved from the layer that the word was taken from, the
+/// embeddings of subword units from the layer of the subword unit.
+/// The embeddings of the layers are not copied.
+pub struct LayeredStorage {
+    layers: Vec<StorageWrap>,
+    word_rows: Vec<(usize, usize)>,
+    subword_rows: Vec<(usize, usize)>,
+    shape: (usize, usize),
+}
+
+impl LayeredStorage {
+    /// Construct layered storage.
+    ///
+    /// `word_rows` contains the layer and the row in that layer of
+    /// every word. `subword_offsets` contains the first subword index
+    /// of every layer. The subword rows of a layer start at `words_len`
+    /// of the layer, which is given in `layer_words_len`.
+    pub(crate) fn new(
+        layers: Vec<StorageWrap>,
+        word_rows: Vec<(usize, usize)>,
+        subword_offsets: &[usize],
+        layer_words_len: &[usize],
+    ) -> Self {
+        assert!(!layers.is_empty(), "Layered storage requires a layer");
+        assert_eq!(layers.len(), subword_offsets.len());
+        assert_eq!(layers.len(), layer_words_len.len());
+
+        let dims = layers[0].shape().1;
+        assert!(
+            layers.iter().all(|layer| layer.shape().1 == dims),
+            "Layers do not have the same dimensionality"
+        );
+
+        let subwords_len = layers
+            .iter()
+            .zip(layer_words_len)
+            .map(|(layer, words_len)| layer.shape().0 - words_len)
+            .sum::<usize>();
+
+        LayeredStorage {
+            shape: (word_rows.len() + subwords_len, dims),
+            layers,
+            word_rows,
+            subword_rows: subword_offsets
+                .iter()
+                .cloned()
+                .zip(layer_words_len.iter().cloned())
+                .collect(),
+        }
+    }
+
+    /// Get the layers, in order of precedence.
+    pub fn layers(&self) -> &[StorageWrap] {
+        &self.layers
+    }
+
+    /// Get the layer and the row in that layer of an index.
+    fn layer_row(&self, idx: usize) -> (usize, usize) {
+        if let Some(&layer_row) = self.word_rows.get(idx) {
+            return layer_row;
+        }
+
+        // Layers without subword units have the same offset as the
+        // next layer, so the last layer with an offset up to the index
+        // is the layer of the subword unit.
+        let layer = self
+            .subword_rows
+            .partition_point(|&(offset, _)| offset <= idx)
+            - 1;
+        let (offset, words_len) = self.subword_rows[layer];
+        (layer, words_len + idx - offset)
+    }
+}
+
+impl Storage for LayeredStorage {
+    fn embedding(&self, idx: usize) -> CowArray<'_, f32, Ix1> {
+        let (layer, row) = self.layer_row(idx);
+        self.layers[layer].embedding(row)
+    }
+
+    fn embedding_into(&self, idx: usize, out: ArrayViewMut1<f32>) {
+        let (layer, row) = self.layer_row(idx);
+        self.layers[layer].embedding_into(row, out);
+    }
+
+    fn shape(&self) -> (usize, usize) {
+        self.shape
+    }
+}
+
+impl SelectRows for LayeredStorage {
+    type Output = NdArray;
+
+    fn select_rows(&self, indices: &[usize]) -> NdArray {
+        let mut matrix = Array2::zeros((indices.len(), self.shape.1));
+        for (&idx, embedding) in indices.iter().zip(matrix.outer_iter_mut()) {
+            self.embedding_into(idx, embedding);
+        }
+
+        NdArray::new(matrix)
+    }
+}
+
+impl MemoryUsage for LayeredStorage {
+    fn memory_usage(&self) -> MemoryFootprint {
+        self.layers
+            .iter()
+            .map(MemoryUsage::memory_usage)
+            .sum::<MemoryFootprint>()
+            + MemoryFootprint::resident(
+                (self.word_rows.len() + self.subword_rows.len()) * size_of::<(usize, usize)>(),
+            )
+    }
+}
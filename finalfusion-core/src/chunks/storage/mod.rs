@@ -0,0 +1,282 @@
+//! Embedding matrix representations.
+
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use byteorder::{ByteOrder, LittleEndian, ReadBytesExt, WriteBytesExt};
+use ndarray::{
+    Array1, Array2, ArrayView1, ArrayView2, ArrayViewMut1, ArrayViewMut2, CowArray, Ix1,
+};
+
+use crate::util::l2_normalize;
+
+mod advice;
+pub use self::advice::{AccessPattern, Advise, LockMemory};
+
+mod array;
+pub use self::array::{MmapArray, NdArray, PreadArray};
+
+mod borrowed;
+pub use self::borrowed::{BorrowedArray, SharedArray};
+
+mod cache;
+
+mod dedup;
+pub use self::dedup::{DedupArray, Prune};
+
+mod layered;
+pub use self::layered::LayeredStorage;
+
+mod quantized;
+#[doc(hidden)]
+pub use self::quantized::{
+    code_len, normalize_rows, packs_codes, quantize_rows, quantizer_size, sample_rows,
+    MAX_PACKED_CENTROIDS,
+};
+pub use self::quantized::{
+    train_pq_sample, MmapQuantizedArray, Quantize, QuantizedArray, TryQuantize,
+};
+
+mod residual;
+#[doc(hidden)]
+pub use self::residual::MAX_COARSE_CENTROIDS;
+pub use self::residual::{QuantizeResidual, ResidualQuantizedArray};
+
+mod wrappers;
+pub use self::wrappers::{StorageViewWrap, StorageWrap};
+
+/// Embedding matrix storage.
+///
+/// To allow for embeddings to be stored in different manners (e.g.
+/// regular *n x d* matrix or as quantized vectors), this trait
+/// abstracts over concrete storage types.
+///
+/// This crate only provides storage in host memory. Storage types
+/// for other devices, such as a GPU-resident matrix, can be
+/// implemented outside this crate. Such implementations should
+/// override `embeddings` to serve batched lookups with a single
+/// transfer from the device.
+pub trait Storage {
+    fn embedding(&self, idx: usize) -> CowArray<'_, f32, Ix1>;
+
+    /// Realize the embedding at the given index into `out`.
+    ///
+    /// This variant of `embedding` makes it possible to retrieve
+    /// embeddings without allocations, by reusing `out` across calls.
+    /// The default implementation copies the result of `embedding`,
+    /// storage types that have to construct embeddings (e.g. quantized
+    /// storage) provide implementations that write to `out` directly.
+    ///
+    /// Panics when `out` does not have the dimensionality of the
+    /// embeddings.
+    fn embedding_into(&self, idx: usize, mut out: ArrayViewMut1<f32>) {
+        out.assign(&self.embedding(idx));
+    }
+
+    /// Get the embeddings at the given indices.
+    ///
+    /// Returns a matrix in which row *i* is the embedding at
+    /// `indices[i]`. The default implementation retrieves the
+    /// embeddings one by one, storage types can provide a more
+    /// efficient implementation.
+    fn embeddings(&self, indices: &[usize]) -> Array2<f32> {
+        let mut embeddings = Array2::zeros((indices.len(), self.shape().1));
+        for (&idx, mut embedding) in indices.iter().zip(embeddings.outer_iter_mut()) {
+            embedding.assign(&self.embedding(idx));
+        }
+
+        embeddings
+    }
+
+    fn shape(&self) -> (usize, usize);
+}
+
+/// Component type of embedding matrices.
+///
+/// `NdArray` and `MmapArray` can store embedding matrices with `f32`
+/// or `f64` components. Since `Storage` retrieves embeddings as `f32`,
+/// `f64` components are converted when embeddings are retrieved. So,
+/// `f64` is only lossless on disk: lookups and queries through
+/// `Storage` use `f32` precision. The original components can be
+/// accessed through `NdArray::matrix_view`, `MmapArray::matrix_view`,
+/// and `MmapArray::matrix_row`.
+pub trait Element: Copy + Default + fmt::Debug + PartialEq + Send + Sync + 'static {
+    /// Identifier of the type in the finalfusion format.
+    const TYPE_ID: u32;
+
+    /// Read little-endian components into `dst`.
+    fn read_into<R>(read: &mut R, dst: &mut [Self]) -> io::Result<()>
+    where
+        R: Read;
+
+    /// Write a component in little-endian byte order.
+    fn write<W>(write: &mut W, value: Self) -> io::Result<()>
+    where
+        W: Write;
+
+    /// Convert little-endian components to the native byte order in place.
+    fn from_le_slice(data: &mut [Self]);
+
+    /// Convert a vector to `f32`, borrowing when no conversion is necessary.
+    fn as_f32(vector: ArrayView1<'_, Self>) -> CowArray<'_, f32, Ix1>;
+
+    /// Convert an owned vector to `f32`, without copying `f32` vectors.
+    fn into_f32_owned(vector: Array1<Self>) -> Array1<f32>;
+
+    /// Convert a matrix to `f32`.
+    fn into_f32(matrix: Array2<Self>) -> Array2<f32>;
+
+    /// Normalize a vector to unit length in place.
+    ///
+    /// Returns the norm of the vector. Vectors with norm zero are
+    /// left unchanged.
+    fn l2_normalize(vector: ArrayViewMut1<'_, Self>) -> f32;
+}
+
+impl Element for f32 {
+    const TYPE_ID: u32 = 10;
+
+    fn read_into<R>(read: &mut R, dst: &mut [Self]) -> io::Result<()>
+    where
+        R: Read,
+    {
+        read.read_f32_into::<LittleEndian>(dst)
+    }
+
+    fn write<W>(write: &mut W, value: Self) -> io::Result<()>
+    where
+        W: Write,
+    {
+        write.write_f32::<LittleEndian>(value)
+    }
+
+    fn from_le_slice(data: &mut [Self]) {
+        LittleEndian::from_slice_f32(data);
+    }
+
+    fn as_f32(vector: ArrayView1<'_, Self>) -> CowArray<'_, f32, Ix1> {
+        CowArray::from(vector)
+    }
+
+    fn into_f32_owned(vector: Array1<Self>) -> Array1<f32> {
+        vector
+    }
+
+    fn into_f32(matrix: Array2<Self>) -> Array2<f32> {
+        matrix
+    }
+
+    fn l2_normalize(vector: ArrayViewMut1<'_, Self>) -> f32 {
+        l2_normalize(vector)
+    }
+}
+
+impl Element for f64 {
+    const TYPE_ID: u32 = 11;
+
+    fn read_into<R>(read: &mut R, dst: &mut [Self]) -> io::Result<()>
+    where
+        R: Read,
+    {
+        read.read_f64_into::<LittleEndian>(dst)
+    }
+
+    fn write<W>(write: &mut W, value: Self) -> io::Result<()>
+    where
+        W: Write,
+    {
+        write.write_f64::<LittleEndian>(value)
+    }
+
+    fn from_le_slice(data: &mut [Self]) {
+        LittleEndian::from_slice_f64(data);
+    }
+
+    fn as_f32(vector: ArrayView1<'_, Self>) -> CowArray<'_, f32, Ix1> {
+        CowArray::from(vector.mapv(|v| v as f32))
+    }
+
+    fn into_f32_owned(vector: Array1<Self>) -> Array1<f32> {
+        vector.mapv(|v| v as f32)
+    }
+
+    fn into_f32(matrix: Array2<Self>) -> Array2<f32> {
+        matrix.mapv(|v| v as f32)
+    }
+
+    fn l2_normalize(mut vector: ArrayViewMut1<'_, Self>) -> f32 {
+        let norm = vector.dot(&vector).sqrt();
+
+        if norm != 0. {
+            vector /= norm;
+        }
+
+        norm as f32
+    }
+}
+
+/// Memory layout of an embedding matrix.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MatrixLayout {
+    /// The rows of the matrix are contiguous in memory.
+    RowMajor,
+
+    /// The columns of the matrix are contiguous in memory.
+    ColumnMajor,
+
+    /// Neither the rows nor the columns are contiguous in memory.
+    Strided,
+}
+
+impl MatrixLayout {
+    /// Get the layout of a matrix.
+    ///
+    /// Matrices that are both row-major and column-major, such as
+    /// matrices with a single row, are considered to be row-major.
+    pub fn of<A>(matrix: ArrayView2<A>) -> Self {
+        if matrix.is_standard_layout() {
+            MatrixLayout::RowMajor
+        } else if matrix.t().is_standard_layout() {
+            MatrixLayout::ColumnMajor
+        } else {
+            MatrixLayout::Strided
+        }
+    }
+}
+
+/// Storage that provide a view of the embedding matrix.
+pub trait StorageView: Storage {
+    /// Get a view of the embedding matrix.
+    fn view(&self) -> ArrayView2<'_, f32>;
+
+    /// Get the memory layout of the embedding matrix.
+    ///
+    /// Batched operations over the matrix, such as multiplication
+    /// with a matrix of queries, can use this to pick an efficient
+    /// routine for the layout.
+    fn layout(&self) -> MatrixLayout {
+        MatrixLayout::of(self.view())
+    }
+}
+
+/// Storage from which embeddings can be selected.
+pub trait SelectRows {
+    /// Storage type of the selected embeddings.
+    type Output;
+
+    /// Select the embeddings at the given indices.
+    ///
+    /// Row *i* of the selected storage is the embedding at
+    /// `indices[i]`. Quantized storage is selected without
+    /// reconstructing the embeddings.
+    ///
+    /// Panics when an index is out of bounds.
+    fn select_rows(&self, indices: &[usize]) -> Self::Output;
+}
+
+/// Storage that provide a mutable view of the embedding matrix.
+#[doc(hidden)]
+pub trait StorageViewMut: Storage {
+    /// Get a view of the embedding matrix.
+    fn view_mut(&mut self) -> ArrayViewMut2<'_, f32>;
+}
@@ -0,0 +1,1070 @@
+use std::mem::size_of;
+
+use half::f16;
+use memmap::Mmap;
+use ndarray::{
+    s, Array1, Array2, ArrayBase, ArrayView1, ArrayView2, ArrayViewMut1, ArrayViewMut2, Axis,
+    CowArray, Data, Ix1, Ix2,
+};
+use rand::{Rng, RngCore, SeedableRng};
+use rand_xorshift::XorShiftRng;
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+use reductive::pq::{QuantizeVector, ReconstructVector, TrainPQ, PQ};
+
+use super::advice::{advise_mmap, lock_slice, prefault_mmap, unlock_slice};
+use super::cache::ReconstructionCache;
+use super::{AccessPattern, Advise, LockMemory, NdArray, SelectRows, Storage, StorageView};
+use crate::chunks::memory::{MemoryFootprint, MemoryUsage};
+use crate::error::{ErrorKind, Result};
+
+/// Maximum number of centroids per subquantizer for packed codes.
+#[doc(hidden)]
+pub const MAX_PACKED_CENTROIDS: usize = 16;
+
+/// Quantized embedding matrix.
+///
+/// When a subquantizer has at most 16 centroids (4-bit codes), two
+/// codes are packed in one byte. The first code of a pair is stored
+/// in the lower 4 bits. If the number of subquantizers is odd, the
+/// upper 4 bits of the last byte of every embedding are unused.
+/// Files with packed codes require `Features::PACKED_CODES`, so that
+/// readers that do not support packed codes reject them.
+///
+/// Norms can be stored in half precision to reduce the size of the
+/// serialized matrix, see `set_half_norms`. Files with half-precision
+/// norms require `Features::HALF_NORMS`.
+///
+/// Reconstructed embeddings of frequently looked up words can be
+/// cached, see `set_cache_capacity`.
+pub struct QuantizedArray {
+    quantizer: PQ<f32>,
+    quantized_embeddings: Array2<u8>,
+    packed: bool,
+    norms: Option<Array1<f32>>,
+    half_norms: bool,
+    cache: Option<ReconstructionCache>,
+}
+
+impl QuantizedArray {
+    /// Construct a quantized matrix from its parts.
+    ///
+    /// If `packed` is `true`, `quantized_embeddings` contains packed
+    /// 4-bit codes.
+    #[doc(hidden)]
+    pub fn from_parts(
+        quantizer: PQ<f32>,
+        quantized_embeddings: Array2<u8>,
+        packed: bool,
+        norms: Option<Array1<f32>>,
+        half_norms: bool,
+    ) -> Self {
+        QuantizedArray {
+            quantizer,
+            quantized_embeddings,
+            packed,
+            norms,
+            half_norms,
+            cache: None,
+        }
+    }
+
+    /// Get the quantized embeddings.
+    #[doc(hidden)]
+    pub fn quantized_embeddings(&self) -> ArrayView2<'_, u8> {
+        self.quantized_embeddings.view()
+    }
+
+    /// Check whether the quantized embeddings are packed 4-bit codes.
+    #[doc(hidden)]
+    pub fn packed(&self) -> bool {
+        self.packed
+    }
+
+    /// Get the norms of the embeddings.
+    #[doc(hidden)]
+    pub fn norms(&self) -> Option<ArrayView1<'_, f32>> {
+        self.norms.as_ref().map(Array1::view)
+    }
+
+    /// Get the quantizer.
+    pub fn quantizer(&self) -> &PQ<f32> {
+        &self.quantizer
+    }
+
+    /// Check whether the norms are stored in half precision.
+    pub fn half_norms(&self) -> bool {
+        self.half_norms
+    }
+
+    /// Store the norms in half precision.
+    ///
+    /// Half-precision norms use 2 rather than 4 bytes per embedding in
+    /// the serialized matrix. Norms are always single precision in
+    /// memory. When half precision is enabled, the norms are rounded
+    /// to half precision, such that lookups give the same results
+    /// before and after serialization. Disabling half precision does
+    /// not restore the precision that was lost in rounding.
+    pub fn set_half_norms(&mut self, half_norms: bool) {
+        if half_norms {
+            if let Some(norms) = self.norms.as_mut() {
+                norms.mapv_inplace(|norm| f16::from_f32(norm).to_f32());
+            }
+        }
+
+        self.half_norms = half_norms;
+
+        // Cached embeddings were scaled by the old norms.
+        if let Some(cache) = &self.cache {
+            cache.clear();
+        }
+    }
+
+    /// Get the maximum number of cached reconstructed embeddings.
+    ///
+    /// Returns `0` when caching is disabled.
+    pub fn cache_capacity(&self) -> usize {
+        self.cache
+            .as_ref()
+            .map(ReconstructionCache::capacity)
+            .unwrap_or(0)
+    }
+
+    /// Cache up to `capacity` reconstructed embeddings.
+    ///
+    /// Lookups of single embeddings through `embedding` and
+    /// `embedding_into` use the cache. When the cache is full, the
+    /// least recently used embedding is evicted. The cache can be
+    /// used from multiple threads, but lookups contend for its lock.
+    /// Batch lookups through `embeddings` bypass the cache.
+    ///
+    /// Caching is disabled when `capacity` is `0`. Changing the
+    /// capacity clears the cache.
+    pub fn set_cache_capacity(&mut self, capacity: usize) {
+        self.cache = if capacity == 0 {
+            None
+        } else {
+            Some(ReconstructionCache::new(capacity))
+        };
+    }
+
+    /// Reconstruct the dense embedding matrix.
+    ///
+    /// The embeddings are reconstructed using the quantizer. If the
+    /// quantized array stores norms, the reconstructed embeddings are
+    /// scaled by their norms.
+    pub fn reconstruct(&self) -> NdArray {
+        reconstruct_matrix(
+            &self.quantizer,
+            self.quantized_embeddings.view(),
+            self.packed,
+            self.norms.as_ref().map(Array1::view),
+        )
+    }
+}
+
+impl Storage for QuantizedArray {
+    fn embedding(&self, idx: usize) -> CowArray<'_, f32, Ix1> {
+        let mut reconstructed = Array1::zeros(self.quantizer.reconstructed_len());
+        self.embedding_into(idx, reconstructed.view_mut());
+        CowArray::from(reconstructed)
+    }
+
+    fn embedding_into(&self, idx: usize, mut out: ArrayViewMut1<f32>) {
+        if let Some(cache) = &self.cache {
+            if cache.copy_into(idx, out.view_mut()) {
+                return;
+            }
+        }
+
+        reconstruct_into(
+            &self.quantizer,
+            self.quantized_embeddings.row(idx),
+            self.packed,
+            self.norms.as_ref().map(|norms| norms[idx]),
+            out.view_mut(),
+        );
+
+        if let Some(cache) = &self.cache {
+            cache.insert(idx, out.view());
+        }
+    }
+
+    fn embeddings(&self, indices: &[usize]) -> Array2<f32> {
+        let norms = self
+            .norms
+            .as_ref()
+            .map(|norms| norms.select(Axis(0), indices));
+        reconstruct_batch(
+            &self.quantizer,
+            self.quantized_embeddings.select(Axis(0), indices).view(),
+            self.packed,
+            norms.as_ref().map(Array1::view),
+        )
+    }
+
+    fn shape(&self) -> (usize, usize) {
+        (
+            self.quantized_embeddings.nrows(),
+            self.quantizer.reconstructed_len(),
+        )
+    }
+}
+
+impl SelectRows for QuantizedArray {
+    type Output = QuantizedArray;
+
+    /// Select the embeddings at the given indices.
+    ///
+    /// The selected storage has a cache with the same capacity, but
+    /// the cached embeddings are not retained.
+    fn select_rows(&self, indices: &[usize]) -> QuantizedArray {
+        QuantizedArray {
+            quantizer: self.quantizer.clone(),
+            quantized_embeddings: self.quantized_embeddings.select(Axis(0), indices),
+            packed: self.packed,
+            norms: self
+                .norms
+                .as_ref()
+                .map(|norms| norms.select(Axis(0), indices)),
+            half_norms: self.half_norms,
+            cache: self
+                .cache
+                .as_ref()
+                .map(|cache| ReconstructionCache::new(cache.capacity())),
+        }
+    }
+}
+
+impl MemoryUsage for QuantizedArray {
+    fn memory_usage(&self) -> MemoryFootprint {
+        MemoryFootprint::resident(
+            quantizer_size(&self.quantizer)
+                + self.quantized_embeddings.len()
+                + norms_size(self.norms.as_ref())
+                + self
+                    .cache
+                    .as_ref()
+                    .map(ReconstructionCache::memory_usage)
+                    .unwrap_or(0),
+        )
+    }
+}
+
+impl LockMemory for QuantizedArray {
+    /// Lock the quantized embeddings and norms in memory.
+    fn lock_memory(&self) -> Result<()> {
+        lock_slice(
+            self.quantized_embeddings
+                .as_slice_memory_order()
+                .expect("Quantized embeddings are not contiguous"),
+        )?;
+
+        if let Some(norms) = &self.norms {
+            let norms = norms
+                .as_slice_memory_order()
+                .expect("Norms are not contiguous");
+            if let Err(err) = lock_slice(norms) {
+                self.unlock_memory()?;
+                return Err(err);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn unlock_memory(&self) -> Result<()> {
+        unlock_slice(
+            self.quantized_embeddings
+                .as_slice_memory_order()
+                .expect("Quantized embeddings are not contiguous"),
+        )?;
+
+        if let Some(norms) = &self.norms {
+            unlock_slice(
+                norms
+                    .as_slice_memory_order()
+                    .expect("Norms are not contiguous"),
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Quantizable embedding matrix.
+///
+/// When the `rayon` feature is enabled, normalization and
+/// quantization of the embedding matrix are done in parallel.
+///
+/// The training backend is selected through the type parameter `T`
+/// of the quantization methods. reductive provides `PQ`, `OPQ`, and
+/// `GaussianOPQ`, which train on the CPU. Other backends, such as
+/// GPU-based trainers, can be used by implementing `TrainPQ`.
+pub trait Quantize {
+    /// Quantize the embedding matrix.
+    ///
+    /// This method trains a quantizer for the embedding matrix and
+    /// then quantizes the matrix using this quantizer.
+    ///
+    /// The xorshift PRNG is used for picking the initial quantizer
+    /// centroids. With `n_subquantizer_bits <= 4`, two quantized
+    /// components are packed in a byte.
+    fn quantize<T>(
+        &self,
+        n_subquantizers: usize,
+        n_subquantizer_bits: u32,
+        n_iterations: usize,
+        n_attempts: usize,
+        normalize: bool,
+    ) -> QuantizedArray
+    where
+        T: TrainPQ<f32>,
+    {
+        self.quantize_using::<T, _>(
+            n_subquantizers,
+            n_subquantizer_bits,
+            n_iterations,
+            n_attempts,
+            normalize,
+            XorShiftRng::from_entropy(),
+        )
+    }
+
+    /// Quantize the embedding matrix using the provided RNG.
+    ///
+    /// This method trains a quantizer for the embedding matrix and
+    /// then quantizes the matrix using this quantizer.
+    fn quantize_using<T, R>(
+        &self,
+        n_subquantizers: usize,
+        n_subquantizer_bits: u32,
+        n_iterations: usize,
+        n_attempts: usize,
+        normalize: bool,
+        rng: R,
+    ) -> QuantizedArray
+    where
+        T: TrainPQ<f32>,
+        R: RngCore + SeedableRng + Send;
+}
+
+/// Fallible quantization of embedding matrices.
+///
+/// This trait is implemented by storage wrappers, which can only be
+/// quantized when the wrapped storage type implements `StorageView`.
+/// The methods correspond to those of `Quantize`, but return an error
+/// when the wrapped storage cannot be viewed as an `f32` matrix.
+pub trait TryQuantize {
+    /// Quantize the embedding matrix.
+    ///
+    /// See `Quantize::quantize`.
+    fn try_quantize<T>(
+        &self,
+        n_subquantizers: usize,
+        n_subquantizer_bits: u32,
+        n_iterations: usize,
+        n_attempts: usize,
+        normalize: bool,
+    ) -> Result<QuantizedArray>
+    where
+        T: TrainPQ<f32>,
+    {
+        self.try_quantize_using::<T, _>(
+            n_subquantizers,
+            n_subquantizer_bits,
+            n_iterations,
+            n_attempts,
+            normalize,
+            XorShiftRng::from_entropy(),
+        )
+    }
+
+    /// Quantize the embedding matrix using the provided RNG.
+    ///
+    /// See `Quantize::quantize_using`.
+    fn try_quantize_using<T, R>(
+        &self,
+        n_subquantizers: usize,
+        n_subquantizer_bits: u32,
+        n_iterations: usize,
+        n_attempts: usize,
+        normalize: bool,
+        rng: R,
+    ) -> Result<QuantizedArray>
+    where
+        T: TrainPQ<f32>,
+        R: RngCore + SeedableRng + Send;
+}
+
+impl<S> Quantize for S
+where
+    S: StorageView,
+{
+    /// Quantize the embedding matrix.
+    ///
+    /// This method trains a quantizer for the embedding matrix and
+    /// then quantizes the matrix using this quantizer.
+    fn quantize_using<T, R>(
+        &self,
+        n_subquantizers: usize,
+        n_subquantizer_bits: u32,
+        n_iterations: usize,
+        n_attempts: usize,
+        normalize: bool,
+        rng: R,
+    ) -> QuantizedArray
+    where
+        T: TrainPQ<f32>,
+        R: RngCore + SeedableRng + Send,
+    {
+        let (embeds, norms) = if normalize {
+            let mut normalized = self.view().to_owned();
+            let norms = normalize_rows(normalized.view_mut());
+            (CowArray::from(normalized), Some(norms))
+        } else {
+            (CowArray::from(self.view()), None)
+        };
+
+        let quantizer = T::train_pq_using(
+            n_subquantizers,
+            n_subquantizer_bits,
+            n_iterations,
+            n_attempts,
+            embeds.view(),
+            rng,
+        );
+
+        let packed = packs_codes(&quantizer);
+        let quantized_embeddings = quantize_rows(&quantizer, embeds.view(), packed);
+
+        QuantizedArray {
+            quantizer,
+            quantized_embeddings,
+            packed,
+            norms,
+            half_norms: false,
+            cache: None,
+        }
+    }
+}
+
+/// Train a product quantizer on a random sample of rows.
+///
+/// This function is intended for matrices that do not fit in memory.
+/// A uniform sample of at most `n_samples` rows is drawn from `rows`
+/// using reservoir sampling, so that only the sample is kept in
+/// memory. The quantizer is then trained on the sample. If
+/// `normalize` is `true`, the sampled rows are l2-normalized before
+/// training, which should match the `normalize` argument of
+/// `QuantizedArrayWriter::new`.
+///
+/// Returns an error if `n_samples` is zero, `rows` is empty, or the
+/// rows do not have the same length.
+#[allow(clippy::too_many_arguments)]
+pub fn train_pq_sample<T, I, S, R>(
+    rows: I,
+    n_samples: usize,
+    n_subquantizers: usize,
+    n_subquantizer_bits: u32,
+    n_iterations: usize,
+    n_attempts: usize,
+    normalize: bool,
+    mut rng: R,
+) -> Result<PQ<f32>>
+where
+    T: TrainPQ<f32>,
+    I: IntoIterator<Item = ArrayBase<S, Ix1>>,
+    S: Data<Elem = f32>,
+    R: RngCore + SeedableRng + Send,
+{
+    if n_samples == 0 {
+        return Err(
+            ErrorKind::Format(String::from("Cannot train quantizer on zero samples")).into(),
+        );
+    }
+
+    let mut sample = sample_rows(rows, n_samples, &mut rng)?;
+
+    if normalize {
+        normalize_rows(sample.view_mut());
+    }
+
+    Ok(T::train_pq_using(
+        n_subquantizers,
+        n_subquantizer_bits,
+        n_iterations,
+        n_attempts,
+        sample.view(),
+        rng,
+    ))
+}
+
+/// Draw a uniform sample of at most `n_samples` rows.
+///
+/// Returns an error if `rows` is empty or the rows do not have the
+/// same length.
+#[doc(hidden)]
+pub fn sample_rows<I, S, R>(rows: I, n_samples: usize, rng: &mut R) -> Result<Array2<f32>>
+where
+    I: IntoIterator<Item = ArrayBase<S, Ix1>>,
+    S: Data<Elem = f32>,
+    R: Rng,
+{
+    let mut sample: Option<Array2<f32>> = None;
+    let mut n_rows = 0;
+
+    for row in rows {
+        let sample = sample.get_or_insert_with(|| Array2::zeros((n_samples, row.len())));
+        if row.len() != sample.ncols() {
+            return Err(ErrorKind::Format(format!(
+                "Incorrect row length, expected: {}, got: {}",
+                sample.ncols(),
+                row.len()
+            ))
+            .into());
+        }
+
+        let idx = if n_rows < n_samples {
+            Some(n_rows)
+        } else {
+            Some(rng.gen_range(0, n_rows + 1)).filter(|&idx| idx < n_samples)
+        };
+
+        if let Some(idx) = idx {
+            sample.row_mut(idx).assign(&row);
+        }
+
+        n_rows += 1;
+    }
+
+    let mut sample = sample
+        .ok_or_else(|| ErrorKind::Format(String::from("Cannot train quantizer without rows")))?;
+    if n_rows < n_samples {
+        sample = sample.slice_move(s![..n_rows, ..]);
+    }
+
+    Ok(sample)
+}
+
+/// Get the size of the quantizer in bytes.
+#[doc(hidden)]
+pub fn quantizer_size(quantizer: &PQ<f32>) -> usize {
+    (quantizer.projection().map(|p| p.len()).unwrap_or(0) + quantizer.subquantizers().len())
+        * size_of::<f32>()
+}
+
+/// Get the size of the norms in bytes.
+pub(crate) fn norms_size(norms: Option<&Array1<f32>>) -> usize {
+    norms.map(|norms| norms.len()).unwrap_or(0) * size_of::<f32>()
+}
+
+/// Check whether the codes of a quantizer are packed.
+#[doc(hidden)]
+pub fn packs_codes(quantizer: &PQ<f32>) -> bool {
+    quantizer.n_quantizer_centroids() <= MAX_PACKED_CENTROIDS
+}
+
+/// Get the number of bytes that stores the codes of an embedding.
+#[doc(hidden)]
+pub fn code_len(quantized_len: usize, packed: bool) -> usize {
+    if packed {
+        quantized_len.div_ceil(2)
+    } else {
+        quantized_len
+    }
+}
+
+/// Pack the quantized embeddings, storing two 4-bit codes per byte.
+fn pack_codes(codes: ArrayView2<u8>) -> Array2<u8> {
+    let quantized_len = codes.ncols();
+    Array2::from_shape_fn(
+        (codes.nrows(), code_len(quantized_len, true)),
+        |(row, col)| {
+            let low = codes[(row, 2 * col)];
+            let high = if 2 * col + 1 < quantized_len {
+                codes[(row, 2 * col + 1)]
+            } else {
+                0
+            };
+            low | (high << 4)
+        },
+    )
+}
+
+/// Get the codes of quantized embeddings, unpacking them if necessary.
+fn unpack_codes(
+    quantized_embeddings: ArrayView2<u8>,
+    quantized_len: usize,
+    packed: bool,
+) -> CowArray<u8, Ix2> {
+    if !packed {
+        return CowArray::from(quantized_embeddings);
+    }
+
+    CowArray::from(Array2::from_shape_fn(
+        (quantized_embeddings.nrows(), quantized_len),
+        |(row, col)| (quantized_embeddings[(row, col / 2)] >> (4 * (col % 2))) & 0x0f,
+    ))
+}
+
+fn reconstruct_matrix(
+    quantizer: &PQ<f32>,
+    quantized_embeddings: ArrayView2<u8>,
+    packed: bool,
+    norms: Option<ArrayView1<f32>>,
+) -> NdArray {
+    NdArray::new(reconstruct_batch(
+        quantizer,
+        quantized_embeddings,
+        packed,
+        norms,
+    ))
+}
+
+pub(crate) fn reconstruct_into(
+    quantizer: &PQ<f32>,
+    quantized_embedding: ArrayView1<u8>,
+    packed: bool,
+    norm: Option<f32>,
+    mut out: ArrayViewMut1<f32>,
+) {
+    quantizer.reconstruct_batch_into(
+        unpack_codes(
+            quantized_embedding.insert_axis(Axis(0)),
+            quantizer.quantized_len(),
+            packed,
+        )
+        .view(),
+        out.view_mut().insert_axis(Axis(0)),
+    );
+    if let Some(norm) = norm {
+        out *= norm;
+    }
+}
+
+pub(crate) fn reconstruct_batch(
+    quantizer: &PQ<f32>,
+    quantized_embeddings: ArrayView2<u8>,
+    packed: bool,
+    norms: Option<ArrayView1<f32>>,
+) -> Array2<f32> {
+    let mut reconstructed = quantizer.reconstruct_batch(
+        unpack_codes(quantized_embeddings, quantizer.quantized_len(), packed).view(),
+    );
+    if let Some(norms) = norms {
+        reconstructed *= &norms.insert_axis(Axis(1));
+    }
+
+    reconstructed
+}
+
+/// Number of rows that is processed per task in parallel quantization.
+#[cfg(feature = "rayon")]
+const PARALLEL_BATCH_SIZE: usize = 4096;
+
+/// Normalize the rows of a matrix, returning the norms.
+#[cfg(not(feature = "rayon"))]
+#[doc(hidden)]
+pub fn normalize_rows(mut embeds: ArrayViewMut2<f32>) -> Array1<f32> {
+    embeds
+        .outer_iter_mut()
+        .map(|mut embedding| {
+            let norm = embedding.dot(&embedding).sqrt();
+            embedding /= norm;
+            norm
+        })
+        .collect()
+}
+
+/// Normalize the rows of a matrix in parallel, returning the norms.
+#[cfg(feature = "rayon")]
+#[doc(hidden)]
+pub fn normalize_rows(mut embeds: ArrayViewMut2<f32>) -> Array1<f32> {
+    let mut norms = Array1::zeros(embeds.nrows());
+    embeds
+        .axis_chunks_iter_mut(Axis(0), PARALLEL_BATCH_SIZE)
+        .zip(norms.axis_chunks_iter_mut(Axis(0), PARALLEL_BATCH_SIZE))
+        .collect::<Vec<_>>()
+        .into_par_iter()
+        .for_each(|(mut batch, mut batch_norms)| {
+            for (mut embedding, norm) in batch.outer_iter_mut().zip(batch_norms.iter_mut()) {
+                *norm = embedding.dot(&embedding).sqrt();
+                embedding /= *norm;
+            }
+        });
+    norms
+}
+
+/// Quantize the rows of a matrix, packing the codes if `packed` is `true`.
+#[doc(hidden)]
+pub fn quantize_rows(quantizer: &PQ<f32>, embeds: ArrayView2<f32>, packed: bool) -> Array2<u8> {
+    let quantized = quantize_batch(quantizer, embeds);
+    if packed {
+        pack_codes(quantized.view())
+    } else {
+        quantized
+    }
+}
+
+/// Quantize the rows of a matrix.
+#[cfg(not(feature = "rayon"))]
+fn quantize_batch(quantizer: &PQ<f32>, embeds: ArrayView2<f32>) -> Array2<u8> {
+    quantizer.quantize_batch(embeds)
+}
+
+/// Quantize the rows of a matrix in parallel.
+#[cfg(feature = "rayon")]
+fn quantize_batch(quantizer: &PQ<f32>, embeds: ArrayView2<f32>) -> Array2<u8> {
+    let mut quantized = Array2::zeros((embeds.nrows(), quantizer.quantized_len()));
+    embeds
+        .axis_chunks_iter(Axis(0), PARALLEL_BATCH_SIZE)
+        .zip(quantized.axis_chunks_iter_mut(Axis(0), PARALLEL_BATCH_SIZE))
+        .collect::<Vec<_>>()
+        .into_par_iter()
+        .for_each(|(batch, batch_quantized)| quantizer.quantize_batch_into(batch, batch_quantized));
+    quantized
+}
+
+/// Memory-mapped quantized embedding matrix.
+///
+/// See `QuantizedArray` for the storage of 4-bit codes.
+pub struct MmapQuantizedArray {
+    quantizer: PQ<f32>,
+    quantized_embeddings: Mmap,
+    packed: bool,
+    norms: Option<Array1<f32>>,
+    half_norms: bool,
+}
+
+impl MmapQuantizedArray {
+    /// Construct a quantized matrix from its parts.
+    ///
+    /// `quantized_embeddings` maps the quantized embeddings. If
+    /// `packed` is `true`, the mapping contains packed 4-bit codes.
+    #[doc(hidden)]
+    pub fn from_mmap(
+        quantizer: PQ<f32>,
+        quantized_embeddings: Mmap,
+        packed: bool,
+        norms: Option<Array1<f32>>,
+        half_norms: bool,
+    ) -> Self {
+        MmapQuantizedArray {
+            quantizer,
+            quantized_embeddings,
+            packed,
+            norms,
+            half_norms,
+        }
+    }
+
+    /// Get the quantized embeddings.
+    #[doc(hidden)]
+    pub fn quantized_embeddings(&self) -> ArrayView2<'_, u8> {
+        let n_embeddings = self.shape().0;
+
+        // Safety: the memory map is valid for the lifetime of self.
+        unsafe {
+            ArrayView2::from_shape_ptr(
+                (n_embeddings, self.code_len()),
+                self.quantized_embeddings.as_ptr(),
+            )
+        }
+    }
+
+    /// Check whether the quantized embeddings are packed 4-bit codes.
+    #[doc(hidden)]
+    pub fn packed(&self) -> bool {
+        self.packed
+    }
+
+    /// Get the norms of the embeddings.
+    #[doc(hidden)]
+    pub fn norms(&self) -> Option<ArrayView1<'_, f32>> {
+        self.norms.as_ref().map(Array1::view)
+    }
+
+    fn code_len(&self) -> usize {
+        code_len(self.quantizer.quantized_len(), self.packed)
+    }
+
+    /// Check whether the norms are stored in half precision.
+    pub fn half_norms(&self) -> bool {
+        self.half_norms
+    }
+}
+
+impl MmapQuantizedArray {
+    /// Get the quantizer.
+    pub fn quantizer(&self) -> &PQ<f32> {
+        &self.quantizer
+    }
+
+    /// Reconstruct the dense embedding matrix.
+    ///
+    /// The embeddings are reconstructed using the quantizer. If the
+    /// quantized array stores norms, the reconstructed embeddings are
+    /// scaled by their norms.
+    pub fn reconstruct(&self) -> NdArray {
+        let quantized_embeddings = self.quantized_embeddings();
+        reconstruct_matrix(
+            &self.quantizer,
+            quantized_embeddings,
+            self.packed,
+            self.norms.as_ref().map(Array1::view),
+        )
+    }
+}
+
+impl Storage for MmapQuantizedArray {
+    fn embedding(&self, idx: usize) -> CowArray<'_, f32, Ix1> {
+        let mut reconstructed = Array1::zeros(self.quantizer.reconstructed_len());
+        self.embedding_into(idx, reconstructed.view_mut());
+        CowArray::from(reconstructed)
+    }
+
+    fn embedding_into(&self, idx: usize, out: ArrayViewMut1<f32>) {
+        let quantized = self.quantized_embeddings();
+        reconstruct_into(
+            &self.quantizer,
+            quantized.row(idx),
+            self.packed,
+            self.norms.as_ref().map(|norms| norms[idx]),
+            out,
+        );
+    }
+
+    fn embeddings(&self, indices: &[usize]) -> Array2<f32> {
+        let quantized = self.quantized_embeddings();
+        let norms = self
+            .norms
+            .as_ref()
+            .map(|norms| norms.select(Axis(0), indices));
+        reconstruct_batch(
+            &self.quantizer,
+            quantized.select(Axis(0), indices).view(),
+            self.packed,
+            norms.as_ref().map(Array1::view),
+        )
+    }
+
+    fn shape(&self) -> (usize, usize) {
+        (
+            self.quantized_embeddings.len() / self.code_len(),
+            self.quantizer.reconstructed_len(),
+        )
+    }
+}
+
+impl SelectRows for MmapQuantizedArray {
+    type Output = QuantizedArray;
+
+    fn select_rows(&self, indices: &[usize]) -> QuantizedArray {
+        let quantized_embeddings = self.quantized_embeddings();
+
+        QuantizedArray {
+            quantizer: self.quantizer.clone(),
+            quantized_embeddings: quantized_embeddings.select(Axis(0), indices),
+            packed: self.packed,
+            norms: self
+                .norms
+                .as_ref()
+                .map(|norms| norms.select(Axis(0), indices)),
+            half_norms: self.half_norms,
+            cache: None,
+        }
+    }
+}
+
+impl MemoryUsage for MmapQuantizedArray {
+    fn memory_usage(&self) -> MemoryFootprint {
+        MemoryFootprint::resident(quantizer_size(&self.quantizer) + norms_size(self.norms.as_ref()))
+            + MemoryFootprint::mapped(self.quantized_embeddings.len())
+    }
+}
+
+impl Advise for MmapQuantizedArray {
+    fn advise(&self, pattern: AccessPattern) -> Result<()> {
+        advise_mmap(&self.quantized_embeddings, pattern)
+    }
+
+    fn prefault(&self) {
+        prefault_mmap(&self.quantized_embeddings)
+    }
+}
+
+impl LockMemory for MmapQuantizedArray {
+    fn lock_memory(&self) -> Result<()> {
+        lock_slice(&self.quantized_embeddings)
+    }
+
+    fn unlock_memory(&self) -> Result<()> {
+        unlock_slice(&self.quantized_embeddings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{Array1, Array2};
+    use rand::SeedableRng;
+    use rand_xorshift::XorShiftRng;
+    use reductive::pq::{QuantizeVector, ReconstructVector, PQ};
+
+    use super::{pack_codes, unpack_codes};
+    use crate::chunks::memory::MemoryUsage;
+    use crate::storage::{
+        train_pq_sample, NdArray, Quantize, QuantizedArray, Storage, StorageView,
+    };
+
+    const N_ROWS: usize = 100;
+    const N_COLS: usize = 100;
+
+    fn test_ndarray() -> NdArray {
+        let test_data = Array2::from_shape_fn((N_ROWS, N_COLS), |(r, c)| {
+            r as f32 * N_COLS as f32 + c as f32
+        });
+
+        NdArray::new(test_data)
+    }
+
+    fn test_quantized_array(norms: bool) -> QuantizedArray {
+        let ndarray = test_ndarray();
+        ndarray.quantize::<PQ<f32>>(10, 4, 5, 1, norms)
+    }
+
+    fn storage_eq(arr: &impl Storage, check_arr: &impl Storage) {
+        assert_eq!(arr.shape(), check_arr.shape());
+        for idx in 0..check_arr.shape().0 {
+            assert_eq!(arr.embedding(idx).view(), check_arr.embedding(idx).view());
+        }
+    }
+
+    #[test]
+    fn quantized_array_cache() {
+        let mut arr = test_quantized_array(true);
+        let uncached_usage = arr.memory_usage().resident;
+        let check_arr = arr.reconstruct();
+        arr.set_cache_capacity(10);
+        assert_eq!(arr.cache_capacity(), 10);
+
+        // Look up embeddings twice, so that the second lookups are cached.
+        for _ in 0..2 {
+            for idx in (0..N_ROWS).chain(0..10) {
+                assert_eq!(arr.embedding(idx), check_arr.embedding(idx));
+            }
+        }
+        assert_eq!(
+            arr.memory_usage().resident,
+            uncached_usage + 10 * N_COLS * 4
+        );
+
+        // Cached embeddings must not use outdated norms.
+        arr.set_half_norms(true);
+        let check_arr = arr.reconstruct();
+        for idx in 0..10 {
+            assert_eq!(arr.embedding(idx), check_arr.embedding(idx));
+        }
+
+        arr.set_cache_capacity(0);
+        assert_eq!(arr.cache_capacity(), 0);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn quantize_rows_matches_quantize_batch() {
+        let arr = test_quantized_array(false);
+        let embeds = arr.reconstruct();
+        assert_eq!(
+            super::quantize_batch(arr.quantizer(), embeds.view()),
+            arr.quantizer().quantize_batch::<u8, _>(embeds.view())
+        );
+    }
+
+    #[test]
+    fn pack_unpack_codes_roundtrip() {
+        let codes = Array2::from_shape_fn((4, 5), |(r, c)| ((r * 5 + c) % 16) as u8);
+        let packed = pack_codes(codes.view());
+        assert_eq!(packed.dim(), (4, 3));
+        assert_eq!(packed[(0, 0)], 0x10);
+        assert_eq!(packed[(0, 2)], 0x04);
+        assert_eq!(unpack_codes(packed.view(), 5, true).view(), codes.view());
+    }
+
+    #[test]
+    fn quantized_array_packs_4bit_codes() {
+        let ndarray = test_ndarray();
+        let arr = test_quantized_array(false);
+        assert!(arr.packed);
+        assert_eq!(arr.quantized_embeddings.dim(), (N_ROWS, 5));
+
+        let quantizer = arr.quantizer();
+        let check = quantizer.reconstruct_batch(quantizer.quantize_batch::<u8, _>(ndarray.view()));
+        for (idx, check_embedding) in check.outer_iter().enumerate() {
+            assert_eq!(arr.embedding(idx), check_embedding);
+        }
+    }
+
+    #[test]
+    fn quantized_array_embeddings() {
+        let indices = [3, 1, 4, 1, 5];
+        for &norms in &[false, true] {
+            let arr = test_quantized_array(norms);
+            let embeddings = arr.embeddings(&indices);
+            for (&idx, embedding) in indices.iter().zip(embeddings.outer_iter()) {
+                assert_eq!(embedding, arr.embedding(idx).view());
+            }
+        }
+    }
+
+    #[test]
+    fn quantized_array_embedding_into() {
+        for &norms in &[false, true] {
+            let arr = test_quantized_array(norms);
+            let mut out = Array1::zeros(N_COLS);
+            for idx in 0..N_ROWS {
+                arr.embedding_into(idx, out.view_mut());
+                assert_eq!(out, arr.embedding(idx));
+            }
+        }
+    }
+
+    #[test]
+    fn quantized_array_reconstruct() {
+        for &norms in &[false, true] {
+            let check_arr = test_quantized_array(norms);
+            let arr = check_arr.reconstruct();
+            storage_eq(&arr, &check_arr);
+        }
+    }
+
+    #[test]
+    fn train_pq_sample_fails_without_rows() {
+        assert!(train_pq_sample::<PQ<f32>, _, _, _>(
+            Vec::<Array1<f32>>::new(),
+            50,
+            10,
+            4,
+            5,
+            1,
+            false,
+            XorShiftRng::seed_from_u64(42),
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn train_pq_sample_fails_without_samples() {
+        assert!(train_pq_sample::<PQ<f32>, _, _, _>(
+            test_ndarray().view().outer_iter(),
+            0,
+            10,
+            4,
+            5,
+            1,
+            false,
+            XorShiftRng::seed_from_u64(42),
+        )
+        .is_err());
+    }
+}
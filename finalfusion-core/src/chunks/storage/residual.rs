@@ -0,0 +1,462 @@
+use std::mem::size_of;
+
+use ndarray::{Array1, Array2, ArrayView1, ArrayView2, ArrayViewMut1, Axis, CowArray, Ix1};
+use rand::{RngCore, SeedableRng};
+use rand_xorshift::XorShiftRng;
+use reductive::kmeans::{KMeans, NIterationsCondition, RandomInstanceCentroids};
+use reductive::pq::{ReconstructVector, TrainPQ, PQ};
+
+use super::advice::{lock_slice, unlock_slice};
+use super::quantized::{
+    normalize_rows, norms_size, packs_codes, quantize_rows, quantizer_size, reconstruct_batch,
+    reconstruct_into,
+};
+use super::{LockMemory, NdArray, SelectRows, Storage, StorageView};
+use crate::chunks::memory::{MemoryFootprint, MemoryUsage};
+use crate::error::Result;
+
+/// Maximum number of coarse centroids.
+///
+/// Coarse codes are stored as `u8`.
+#[doc(hidden)]
+pub const MAX_COARSE_CENTROIDS: usize = 256;
+
+/// Number of rows for which coarse centroids are assigned at once.
+const ASSIGNMENT_BATCH_SIZE: usize = 4096;
+
+/// Embedding matrix quantized using residual quantization.
+///
+/// Residual quantization quantizes embeddings in two stages. First,
+/// every embedding is assigned to the nearest centroid of a coarse
+/// codebook. The residual of the embedding, its difference with the
+/// coarse centroid, is then quantized using product quantization.
+/// Embeddings are reconstructed by adding the reconstructed residual
+/// to the coarse centroid.
+///
+/// Since the product quantizer only has to quantize the residuals,
+/// the reconstruction error is typically lower than that of
+/// `QuantizedArray` with the same number of subquantizers. This comes
+/// at the cost of one additional byte per embedding, which stores the
+/// coarse code. Codes of the residuals are packed as in
+/// `QuantizedArray`.
+pub struct ResidualQuantizedArray {
+    coarse_centroids: Array2<f32>,
+    coarse_codes: Array1<u8>,
+    quantizer: PQ<f32>,
+    quantized_embeddings: Array2<u8>,
+    packed: bool,
+    norms: Option<Array1<f32>>,
+}
+
+impl ResidualQuantizedArray {
+    /// Construct a residual quantized matrix from its parts.
+    ///
+    /// If `packed` is `true`, `quantized_embeddings` contains packed
+    /// 4-bit codes of the residuals.
+    #[doc(hidden)]
+    pub fn from_parts(
+        coarse_centroids: Array2<f32>,
+        coarse_codes: Array1<u8>,
+        quantizer: PQ<f32>,
+        quantized_embeddings: Array2<u8>,
+        packed: bool,
+        norms: Option<Array1<f32>>,
+    ) -> Self {
+        ResidualQuantizedArray {
+            coarse_centroids,
+            coarse_codes,
+            quantizer,
+            quantized_embeddings,
+            packed,
+            norms,
+        }
+    }
+
+    /// Get the coarse codes of the embeddings.
+    #[doc(hidden)]
+    pub fn coarse_codes(&self) -> ArrayView1<'_, u8> {
+        self.coarse_codes.view()
+    }
+
+    /// Get the quantized residuals.
+    #[doc(hidden)]
+    pub fn quantized_embeddings(&self) -> ArrayView2<'_, u8> {
+        self.quantized_embeddings.view()
+    }
+
+    /// Check whether the quantized residuals are packed 4-bit codes.
+    #[doc(hidden)]
+    pub fn packed(&self) -> bool {
+        self.packed
+    }
+
+    /// Get the norms of the embeddings.
+    #[doc(hidden)]
+    pub fn norms(&self) -> Option<ArrayView1<'_, f32>> {
+        self.norms.as_ref().map(Array1::view)
+    }
+
+    /// Get the centroids of the coarse codebook.
+    pub fn coarse_centroids(&self) -> ArrayView2<'_, f32> {
+        self.coarse_centroids.view()
+    }
+
+    /// Get the quantizer of the residuals.
+    pub fn quantizer(&self) -> &PQ<f32> {
+        &self.quantizer
+    }
+
+    /// Reconstruct the dense embedding matrix.
+    ///
+    /// If the quantized array stores norms, the reconstructed
+    /// embeddings are scaled by their norms.
+    pub fn reconstruct(&self) -> NdArray {
+        let mut reconstructed = reconstruct_batch(
+            &self.quantizer,
+            self.quantized_embeddings.view(),
+            self.packed,
+            None,
+        );
+
+        for (mut embedding, &code) in reconstructed.outer_iter_mut().zip(&self.coarse_codes) {
+            embedding += &self.coarse_centroids.row(code as usize);
+        }
+
+        if let Some(norms) = &self.norms {
+            reconstructed *= &norms.view().insert_axis(Axis(1));
+        }
+
+        NdArray::new(reconstructed)
+    }
+}
+
+impl Storage for ResidualQuantizedArray {
+    fn embedding(&self, idx: usize) -> CowArray<'_, f32, Ix1> {
+        let mut reconstructed = Array1::zeros(self.quantizer.reconstructed_len());
+        self.embedding_into(idx, reconstructed.view_mut());
+        CowArray::from(reconstructed)
+    }
+
+    fn embedding_into(&self, idx: usize, mut out: ArrayViewMut1<f32>) {
+        reconstruct_into(
+            &self.quantizer,
+            self.quantized_embeddings.row(idx),
+            self.packed,
+            None,
+            out.view_mut(),
+        );
+        out += &self.coarse_centroids.row(self.coarse_codes[idx] as usize);
+        if let Some(norms) = &self.norms {
+            out *= norms[idx];
+        }
+    }
+
+    fn shape(&self) -> (usize, usize) {
+        (
+            self.quantized_embeddings.nrows(),
+            self.quantizer.reconstructed_len(),
+        )
+    }
+}
+
+impl SelectRows for ResidualQuantizedArray {
+    type Output = ResidualQuantizedArray;
+
+    fn select_rows(&self, indices: &[usize]) -> ResidualQuantizedArray {
+        ResidualQuantizedArray {
+            coarse_centroids: self.coarse_centroids.clone(),
+            coarse_codes: self.coarse_codes.select(Axis(0), indices),
+            quantizer: self.quantizer.clone(),
+            quantized_embeddings: self.quantized_embeddings.select(Axis(0), indices),
+            packed: self.packed,
+            norms: self
+                .norms
+                .as_ref()
+                .map(|norms| norms.select(Axis(0), indices)),
+        }
+    }
+}
+
+impl MemoryUsage for ResidualQuantizedArray {
+    fn memory_usage(&self) -> MemoryFootprint {
+        MemoryFootprint::resident(
+            self.coarse_centroids.len() * size_of::<f32>()
+                + self.coarse_codes.len()
+                + quantizer_size(&self.quantizer)
+                + self.quantized_embeddings.len()
+                + norms_size(self.norms.as_ref()),
+        )
+    }
+}
+
+impl LockMemory for ResidualQuantizedArray {
+    /// Lock the coarse and quantized codes and the norms in memory.
+    fn lock_memory(&self) -> Result<()> {
+        lock_slice(
+            self.quantized_embeddings
+                .as_slice_memory_order()
+                .expect("Quantized embeddings are not contiguous"),
+        )?;
+
+        let locked = lock_slice(
+            self.coarse_codes
+                .as_slice_memory_order()
+                .expect("Coarse codes are not contiguous"),
+        )
+        .and_then(|_| match &self.norms {
+            Some(norms) => lock_slice(
+                norms
+                    .as_slice_memory_order()
+                    .expect("Norms are not contiguous"),
+            ),
+            None => Ok(()),
+        });
+
+        if let Err(err) = locked {
+            self.unlock_memory()?;
+            return Err(err);
+        }
+
+        Ok(())
+    }
+
+    fn unlock_memory(&self) -> Result<()> {
+        unlock_slice(
+            self.quantized_embeddings
+                .as_slice_memory_order()
+                .expect("Quantized embeddings are not contiguous"),
+        )?;
+        unlock_slice(
+            self.coarse_codes
+                .as_slice_memory_order()
+                .expect("Coarse codes are not contiguous"),
+        )?;
+
+        if let Some(norms) = &self.norms {
+            unlock_slice(
+                norms
+                    .as_slice_memory_order()
+                    .expect("Norms are not contiguous"),
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Embedding matrices that can be quantized using residual quantization.
+///
+/// When the `rayon` feature is enabled, normalization and
+/// quantization of the residuals are done in parallel.
+pub trait QuantizeResidual {
+    /// Quantize the embedding matrix using residual quantization.
+    ///
+    /// This method trains a coarse codebook with `n_coarse_centroids`
+    /// centroids using k-means and then trains a product quantizer
+    /// for the residuals. See `Quantize::quantize` for a description
+    /// of the product quantization parameters.
+    ///
+    /// The xorshift PRNG is used for picking the initial centroids.
+    ///
+    /// Panics when `n_coarse_centroids` is zero, is larger than 256,
+    /// or is not smaller than the number of embeddings.
+    fn quantize_residual<T>(
+        &self,
+        n_coarse_centroids: usize,
+        n_subquantizers: usize,
+        n_subquantizer_bits: u32,
+        n_iterations: usize,
+        n_attempts: usize,
+        normalize: bool,
+    ) -> ResidualQuantizedArray
+    where
+        T: TrainPQ<f32>,
+    {
+        self.quantize_residual_using::<T, _>(
+            n_coarse_centroids,
+            n_subquantizers,
+            n_subquantizer_bits,
+            n_iterations,
+            n_attempts,
+            normalize,
+            XorShiftRng::from_entropy(),
+        )
+    }
+
+    /// Quantize the embedding matrix using residual quantization and
+    /// the provided RNG.
+    #[allow(clippy::too_many_arguments)]
+    fn quantize_residual_using<T, R>(
+        &self,
+        n_coarse_centroids: usize,
+        n_subquantizers: usize,
+        n_subquantizer_bits: u32,
+        n_iterations: usize,
+        n_attempts: usize,
+        normalize: bool,
+        rng: R,
+    ) -> ResidualQuantizedArray
+    where
+        T: TrainPQ<f32>,
+        R: RngCore + SeedableRng + Send;
+}
+
+impl<S> QuantizeResidual for S
+where
+    S: StorageView,
+{
+    #[allow(clippy::too_many_arguments)]
+    fn quantize_residual_using<T, R>(
+        &self,
+        n_coarse_centroids: usize,
+        n_subquantizers: usize,
+        n_subquantizer_bits: u32,
+        n_iterations: usize,
+        n_attempts: usize,
+        normalize: bool,
+        mut rng: R,
+    ) -> ResidualQuantizedArray
+    where
+        T: TrainPQ<f32>,
+        R: RngCore + SeedableRng + Send,
+    {
+        assert!(
+            n_coarse_centroids > 0 && n_coarse_centroids <= MAX_COARSE_CENTROIDS,
+            "The number of coarse centroids should be between 1 and {}",
+            MAX_COARSE_CENTROIDS
+        );
+
+        let (embeds, norms) = if normalize {
+            let mut normalized = self.view().to_owned();
+            let norms = normalize_rows(normalized.view_mut());
+            (CowArray::from(normalized), Some(norms))
+        } else {
+            (CowArray::from(self.view()), None)
+        };
+
+        let (coarse_centroids, _) = embeds.k_means(
+            Axis(0),
+            n_coarse_centroids,
+            RandomInstanceCentroids::new(&mut rng),
+            NIterationsCondition(n_iterations),
+        );
+
+        let coarse_codes = coarse_assignments(coarse_centroids.view(), embeds.view());
+        let mut residuals = embeds.into_owned();
+        for (mut residual, &code) in residuals.outer_iter_mut().zip(&coarse_codes) {
+            residual -= &coarse_centroids.row(code as usize);
+        }
+
+        let quantizer = T::train_pq_using(
+            n_subquantizers,
+            n_subquantizer_bits,
+            n_iterations,
+            n_attempts,
+            residuals.view(),
+            rng,
+        );
+
+        let packed = packs_codes(&quantizer);
+        let quantized_embeddings = quantize_rows(&quantizer, residuals.view(), packed);
+
+        ResidualQuantizedArray {
+            coarse_centroids,
+            coarse_codes,
+            quantizer,
+            quantized_embeddings,
+            packed,
+            norms,
+        }
+    }
+}
+
+/// Assign every embedding to its nearest coarse centroid.
+fn coarse_assignments(centroids: ArrayView2<f32>, embeds: ArrayView2<f32>) -> Array1<u8> {
+    // ||e - c||^2 = ||e||^2 - 2 e·c + ||c||^2, where ||e||^2 does not
+    // affect the nearest centroid.
+    let centroid_norms = centroids.map_axis(Axis(1), |centroid| centroid.dot(&centroid));
+
+    let mut codes = Vec::with_capacity(embeds.nrows());
+    for batch in embeds.axis_chunks_iter(Axis(0), ASSIGNMENT_BATCH_SIZE) {
+        let dots = batch.dot(&centroids.t());
+        codes.extend(dots.outer_iter().map(|embed_dots| {
+            embed_dots
+                .iter()
+                .zip(centroid_norms.iter())
+                .map(|(&dot, &norm)| norm - 2. * dot)
+                .enumerate()
+                .fold((0, f32::INFINITY), |(best, best_dist), (idx, dist)| {
+                    if dist < best_dist {
+                        (idx, dist)
+                    } else {
+                        (best, best_dist)
+                    }
+                })
+                .0 as u8
+        }));
+    }
+
+    Array1::from(codes)
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{Array2, ArrayView2};
+    use rand::SeedableRng;
+    use rand_xorshift::XorShiftRng;
+    use reductive::pq::PQ;
+
+    use super::{QuantizeResidual, ResidualQuantizedArray};
+    use crate::storage::{NdArray, Quantize, Storage, StorageView};
+
+    const N_ROWS: usize = 100;
+    const N_COLS: usize = 20;
+
+    fn test_ndarray() -> NdArray {
+        // Embeddings in four clusters.
+        let test_data = Array2::from_shape_fn((N_ROWS, N_COLS), |(r, c)| {
+            (r % 4 * 10) as f32 + ((r * N_COLS + c) % 7) as f32 / 7.
+        });
+
+        NdArray::new(test_data)
+    }
+
+    fn test_residual_quantized_array(norms: bool) -> ResidualQuantizedArray {
+        test_ndarray().quantize_residual_using::<PQ<f32>, _>(
+            4,
+            5,
+            4,
+            10,
+            1,
+            norms,
+            XorShiftRng::seed_from_u64(42),
+        )
+    }
+
+    fn squared_error(a: ArrayView2<f32>, b: ArrayView2<f32>) -> f32 {
+        (&a - &b).iter().map(|v| v * v).sum()
+    }
+
+    #[test]
+    fn residual_quantization_improves_reconstruction() {
+        let arr = test_ndarray();
+        let pq =
+            arr.quantize_using::<PQ<f32>, _>(5, 4, 10, 1, false, XorShiftRng::seed_from_u64(42));
+        let rq = test_residual_quantized_array(false);
+        assert!(
+            squared_error(rq.reconstruct().view(), arr.view())
+                < squared_error(pq.reconstruct().view(), arr.view())
+        );
+    }
+
+    #[test]
+    fn residual_quantized_array_embedding_matches_reconstruct() {
+        for &norms in &[false, true] {
+            let arr = test_residual_quantized_array(norms);
+            let reconstructed = arr.reconstruct();
+            for idx in 0..N_ROWS {
+                assert_eq!(arr.embedding(idx), reconstructed.embedding(idx));
+            }
+        }
+    }
+}
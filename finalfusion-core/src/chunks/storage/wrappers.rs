@@ -0,0 +1,423 @@
+use ndarray::{Array2, ArrayView2, ArrayViewMut1, CowArray, Ix1};
+use rand::{RngCore, SeedableRng};
+use reductive::pq::TrainPQ;
+
+use super::{
+    AccessPattern, Advise, DedupArray, LockMemory, MmapArray, MmapQuantizedArray, NdArray,
+    PreadArray, Quantize, QuantizedArray, ResidualQuantizedArray, SelectRows, Storage, StorageView,
+    TryQuantize,
+};
+use crate::chunks::memory::{MemoryFootprint, MemoryUsage};
+use crate::error::{ErrorKind, Result};
+
+/// Storage types wrapper.
+///
+/// This crate makes it possible to create fine-grained embedding
+/// types, such as `Embeddings<SimpleVocab, NdArray>` or
+/// `Embeddings<SubwordVocab, QuantizedArray>`. However, in some cases
+/// it is more pleasant to have a single type that covers all
+/// vocabulary and storage types. `VocabWrap` and `StorageWrap` wrap
+/// all the vocabularies and storage types known to this crate such
+/// that the type `Embeddings<VocabWrap, StorageWrap>` covers all
+/// variations.
+pub enum StorageWrap {
+    NdArray(NdArray),
+    NdArrayF64(NdArray<f64>),
+    // Boxed: clippy complains about large variant otherwise. Boxing
+    // does not seem to have a noticable impact on performance.
+    QuantizedArray(Box<QuantizedArray>),
+    MmapArray(MmapArray),
+    MmapArrayF64(MmapArray<f64>),
+    MmapQuantizedArray(MmapQuantizedArray),
+    PreadArray(PreadArray),
+    DedupArray(DedupArray),
+    ResidualQuantizedArray(Box<ResidualQuantizedArray>),
+}
+
+impl Storage for StorageWrap {
+    fn embedding(&self, idx: usize) -> CowArray<'_, f32, Ix1> {
+        match self {
+            StorageWrap::DedupArray(inner) => inner.embedding(idx),
+            StorageWrap::MmapArray(inner) => inner.embedding(idx),
+            StorageWrap::MmapArrayF64(inner) => inner.embedding(idx),
+            StorageWrap::MmapQuantizedArray(inner) => inner.embedding(idx),
+            StorageWrap::NdArray(inner) => inner.embedding(idx),
+            StorageWrap::NdArrayF64(inner) => inner.embedding(idx),
+            StorageWrap::PreadArray(inner) => inner.embedding(idx),
+            StorageWrap::QuantizedArray(inner) => inner.embedding(idx),
+            StorageWrap::ResidualQuantizedArray(inner) => inner.embedding(idx),
+        }
+    }
+
+    fn embedding_into(&self, idx: usize, out: ArrayViewMut1<f32>) {
+        match self {
+            StorageWrap::DedupArray(inner) => inner.embedding_into(idx, out),
+            StorageWrap::MmapArray(inner) => inner.embedding_into(idx, out),
+            StorageWrap::MmapArrayF64(inner) => inner.embedding_into(idx, out),
+            StorageWrap::MmapQuantizedArray(inner) => inner.embedding_into(idx, out),
+            StorageWrap::NdArray(inner) => inner.embedding_into(idx, out),
+            StorageWrap::NdArrayF64(inner) => inner.embedding_into(idx, out),
+            StorageWrap::PreadArray(inner) => inner.embedding_into(idx, out),
+            StorageWrap::QuantizedArray(inner) => inner.embedding_into(idx, out),
+            StorageWrap::ResidualQuantizedArray(inner) => inner.embedding_into(idx, out),
+        }
+    }
+
+    fn embeddings(&self, indices: &[usize]) -> Array2<f32> {
+        match self {
+            StorageWrap::DedupArray(inner) => inner.embeddings(indices),
+            StorageWrap::MmapArray(inner) => inner.embeddings(indices),
+            StorageWrap::MmapArrayF64(inner) => inner.embeddings(indices),
+            StorageWrap::MmapQuantizedArray(inner) => inner.embeddings(indices),
+            StorageWrap::NdArray(inner) => inner.embeddings(indices),
+            StorageWrap::NdArrayF64(inner) => inner.embeddings(indices),
+            StorageWrap::PreadArray(inner) => inner.embeddings(indices),
+            StorageWrap::QuantizedArray(inner) => inner.embeddings(indices),
+            StorageWrap::ResidualQuantizedArray(inner) => inner.embeddings(indices),
+        }
+    }
+
+    fn shape(&self) -> (usize, usize) {
+        match self {
+            StorageWrap::DedupArray(inner) => inner.shape(),
+            StorageWrap::MmapArray(inner) => inner.shape(),
+            StorageWrap::MmapArrayF64(inner) => inner.shape(),
+            StorageWrap::MmapQuantizedArray(inner) => inner.shape(),
+            StorageWrap::NdArray(inner) => inner.shape(),
+            StorageWrap::NdArrayF64(inner) => inner.shape(),
+            StorageWrap::PreadArray(inner) => inner.shape(),
+            StorageWrap::QuantizedArray(inner) => inner.shape(),
+            StorageWrap::ResidualQuantizedArray(inner) => inner.shape(),
+        }
+    }
+}
+
+impl Advise for StorageWrap {
+    fn advise(&self, pattern: AccessPattern) -> Result<()> {
+        match self {
+            StorageWrap::MmapArray(inner) => inner.advise(pattern),
+            StorageWrap::MmapArrayF64(inner) => inner.advise(pattern),
+            StorageWrap::MmapQuantizedArray(inner) => inner.advise(pattern),
+            StorageWrap::DedupArray(_)
+            | StorageWrap::NdArray(_)
+            | StorageWrap::NdArrayF64(_)
+            | StorageWrap::PreadArray(_)
+            | StorageWrap::QuantizedArray(_)
+            | StorageWrap::ResidualQuantizedArray(_) => Ok(()),
+        }
+    }
+
+    fn prefault(&self) {
+        match self {
+            StorageWrap::MmapArray(inner) => inner.prefault(),
+            StorageWrap::MmapArrayF64(inner) => inner.prefault(),
+            StorageWrap::MmapQuantizedArray(inner) => inner.prefault(),
+            StorageWrap::DedupArray(_)
+            | StorageWrap::NdArray(_)
+            | StorageWrap::NdArrayF64(_)
+            | StorageWrap::PreadArray(_)
+            | StorageWrap::QuantizedArray(_)
+            | StorageWrap::ResidualQuantizedArray(_) => (),
+        }
+    }
+}
+
+impl LockMemory for StorageWrap {
+    /// Lock the storage in memory.
+    ///
+    /// `PreadArray` reads embeddings from its file on demand, so there
+    /// is no memory to lock and locking always succeeds.
+    fn lock_memory(&self) -> Result<()> {
+        match self {
+            StorageWrap::DedupArray(inner) => inner.lock_memory(),
+            StorageWrap::MmapArray(inner) => inner.lock_memory(),
+            StorageWrap::MmapArrayF64(inner) => inner.lock_memory(),
+            StorageWrap::MmapQuantizedArray(inner) => inner.lock_memory(),
+            StorageWrap::NdArray(inner) => inner.lock_memory(),
+            StorageWrap::NdArrayF64(inner) => inner.lock_memory(),
+            StorageWrap::QuantizedArray(inner) => inner.lock_memory(),
+            StorageWrap::ResidualQuantizedArray(inner) => inner.lock_memory(),
+            StorageWrap::PreadArray(_) => Ok(()),
+        }
+    }
+
+    fn unlock_memory(&self) -> Result<()> {
+        match self {
+            StorageWrap::DedupArray(inner) => inner.unlock_memory(),
+            StorageWrap::MmapArray(inner) => inner.unlock_memory(),
+            StorageWrap::MmapArrayF64(inner) => inner.unlock_memory(),
+            StorageWrap::MmapQuantizedArray(inner) => inner.unlock_memory(),
+            StorageWrap::NdArray(inner) => inner.unlock_memory(),
+            StorageWrap::NdArrayF64(inner) => inner.unlock_memory(),
+            StorageWrap::QuantizedArray(inner) => inner.unlock_memory(),
+            StorageWrap::ResidualQuantizedArray(inner) => inner.unlock_memory(),
+            StorageWrap::PreadArray(_) => Ok(()),
+        }
+    }
+}
+
+impl MemoryUsage for StorageWrap {
+    fn memory_usage(&self) -> MemoryFootprint {
+        match self {
+            StorageWrap::DedupArray(inner) => inner.memory_usage(),
+            StorageWrap::MmapArray(inner) => inner.memory_usage(),
+            StorageWrap::MmapArrayF64(inner) => inner.memory_usage(),
+            StorageWrap::MmapQuantizedArray(inner) => inner.memory_usage(),
+            StorageWrap::NdArray(inner) => inner.memory_usage(),
+            StorageWrap::NdArrayF64(inner) => inner.memory_usage(),
+            StorageWrap::PreadArray(inner) => inner.memory_usage(),
+            StorageWrap::QuantizedArray(inner) => inner.memory_usage(),
+            StorageWrap::ResidualQuantizedArray(inner) => inner.memory_usage(),
+        }
+    }
+}
+
+impl From<DedupArray> for StorageWrap {
+    fn from(s: DedupArray) -> Self {
+        StorageWrap::DedupArray(s)
+    }
+}
+
+impl From<MmapArray> for StorageWrap {
+    fn from(s: MmapArray) -> Self {
+        StorageWrap::MmapArray(s)
+    }
+}
+
+impl From<MmapArray<f64>> for StorageWrap {
+    fn from(s: MmapArray<f64>) -> Self {
+        StorageWrap::MmapArrayF64(s)
+    }
+}
+
+impl From<MmapQuantizedArray> for StorageWrap {
+    fn from(s: MmapQuantizedArray) -> Self {
+        StorageWrap::MmapQuantizedArray(s)
+    }
+}
+
+impl From<NdArray> for StorageWrap {
+    fn from(s: NdArray) -> Self {
+        StorageWrap::NdArray(s)
+    }
+}
+
+impl From<NdArray<f64>> for StorageWrap {
+    fn from(s: NdArray<f64>) -> Self {
+        StorageWrap::NdArrayF64(s)
+    }
+}
+
+impl From<PreadArray> for StorageWrap {
+    fn from(s: PreadArray) -> Self {
+        StorageWrap::PreadArray(s)
+    }
+}
+
+impl From<QuantizedArray> for StorageWrap {
+    fn from(s: QuantizedArray) -> Self {
+        StorageWrap::QuantizedArray(Box::new(s))
+    }
+}
+
+impl From<ResidualQuantizedArray> for StorageWrap {
+    fn from(s: ResidualQuantizedArray) -> Self {
+        StorageWrap::ResidualQuantizedArray(Box::new(s))
+    }
+}
+
+impl SelectRows for StorageWrap {
+    type Output = StorageWrap;
+
+    /// Select the embeddings at the given indices.
+    ///
+    /// Memory-mapped and positioned-read storage is selected into
+    /// memory.
+    fn select_rows(&self, indices: &[usize]) -> StorageWrap {
+        match self {
+            StorageWrap::NdArray(inner) => inner.select_rows(indices).into(),
+            StorageWrap::NdArrayF64(inner) => inner.select_rows(indices).into(),
+            StorageWrap::QuantizedArray(inner) => inner.select_rows(indices).into(),
+            StorageWrap::MmapArray(inner) => inner.select_rows(indices).into(),
+            StorageWrap::MmapArrayF64(inner) => inner.select_rows(indices).into(),
+            StorageWrap::MmapQuantizedArray(inner) => inner.select_rows(indices).into(),
+            StorageWrap::PreadArray(inner) => inner.select_rows(indices).into(),
+            StorageWrap::DedupArray(inner) => inner.select_rows(indices).into(),
+            StorageWrap::ResidualQuantizedArray(inner) => inner.select_rows(indices).into(),
+        }
+    }
+}
+
+impl TryQuantize for StorageWrap {
+    /// Quantize the embedding matrix using the provided RNG.
+    ///
+    /// Only `NdArray` and `MmapArray` storage with `f32` components
+    /// can be quantized. Quantizing other storage types results in an
+    /// error.
+    fn try_quantize_using<T, R>(
+        &self,
+        n_subquantizers: usize,
+        n_subquantizer_bits: u32,
+        n_iterations: usize,
+        n_attempts: usize,
+        normalize: bool,
+        rng: R,
+    ) -> Result<QuantizedArray>
+    where
+        T: TrainPQ<f32>,
+        R: RngCore + SeedableRng + Send,
+    {
+        match self {
+            #[cfg(target_endian = "little")]
+            StorageWrap::MmapArray(inner) => Ok(inner.quantize_using::<T, R>(
+                n_subquantizers,
+                n_subquantizer_bits,
+                n_iterations,
+                n_attempts,
+                normalize,
+                rng,
+            )),
+            StorageWrap::NdArray(inner) => Ok(inner.quantize_using::<T, R>(
+                n_subquantizers,
+                n_subquantizer_bits,
+                n_iterations,
+                n_attempts,
+                normalize,
+                rng,
+            )),
+            _ => Err(ErrorKind::Format(
+                "Only storage that can be viewed as an f32 matrix can be quantized".to_string(),
+            )
+            .into()),
+        }
+    }
+}
+
+/// Wrapper for storage types that implement views.
+///
+/// This type covers the subset of storage types that implement
+/// `StorageView`. See the `StorageWrap` type for more information.
+pub enum StorageViewWrap {
+    #[cfg(target_endian = "little")]
+    MmapArray(MmapArray),
+    NdArray(NdArray),
+}
+
+impl Storage for StorageViewWrap {
+    fn embedding(&self, idx: usize) -> CowArray<'_, f32, Ix1> {
+        match self {
+            #[cfg(target_endian = "little")]
+            StorageViewWrap::MmapArray(inner) => inner.embedding(idx),
+            StorageViewWrap::NdArray(inner) => inner.embedding(idx),
+        }
+    }
+
+    fn embedding_into(&self, idx: usize, out: ArrayViewMut1<f32>) {
+        match self {
+            #[cfg(target_endian = "little")]
+            StorageViewWrap::MmapArray(inner) => inner.embedding_into(idx, out),
+            StorageViewWrap::NdArray(inner) => inner.embedding_into(idx, out),
+        }
+    }
+
+    fn embeddings(&self, indices: &[usize]) -> Array2<f32> {
+        match self {
+            #[cfg(target_endian = "little")]
+            StorageViewWrap::MmapArray(inner) => inner.embeddings(indices),
+            StorageViewWrap::NdArray(inner) => inner.embeddings(indices),
+        }
+    }
+
+    fn shape(&self) -> (usize, usize) {
+        match self {
+            #[cfg(target_endian = "little")]
+            StorageViewWrap::MmapArray(inner) => inner.shape(),
+            StorageViewWrap::NdArray(inner) => inner.shape(),
+        }
+    }
+}
+
+impl SelectRows for StorageViewWrap {
+    type Output = StorageViewWrap;
+
+    /// Select the embeddings at the given indices.
+    ///
+    /// Memory-mapped storage is selected into memory.
+    fn select_rows(&self, indices: &[usize]) -> StorageViewWrap {
+        match self {
+            #[cfg(target_endian = "little")]
+            StorageViewWrap::MmapArray(inner) => inner.select_rows(indices).into(),
+            StorageViewWrap::NdArray(inner) => inner.select_rows(indices).into(),
+        }
+    }
+}
+
+impl StorageView for StorageViewWrap {
+    fn view(&self) -> ArrayView2<'_, f32> {
+        match self {
+            #[cfg(target_endian = "little")]
+            StorageViewWrap::MmapArray(inner) => inner.view(),
+            StorageViewWrap::NdArray(inner) => inner.view(),
+        }
+    }
+}
+
+#[cfg(target_endian = "little")]
+impl Advise for StorageViewWrap {
+    fn advise(&self, pattern: AccessPattern) -> Result<()> {
+        match self {
+            #[cfg(target_endian = "little")]
+            StorageViewWrap::MmapArray(inner) => inner.advise(pattern),
+            StorageViewWrap::NdArray(_) => Ok(()),
+        }
+    }
+
+    fn prefault(&self) {
+        match self {
+            #[cfg(target_endian = "little")]
+            StorageViewWrap::MmapArray(inner) => inner.prefault(),
+            StorageViewWrap::NdArray(_) => (),
+        }
+    }
+}
+
+impl LockMemory for StorageViewWrap {
+    fn lock_memory(&self) -> Result<()> {
+        match self {
+            #[cfg(target_endian = "little")]
+            StorageViewWrap::MmapArray(inner) => inner.lock_memory(),
+            StorageViewWrap::NdArray(inner) => inner.lock_memory(),
+        }
+    }
+
+    fn unlock_memory(&self) -> Result<()> {
+        match self {
+            #[cfg(target_endian = "little")]
+            StorageViewWrap::MmapArray(inner) => inner.unlock_memory(),
+            StorageViewWrap::NdArray(inner) => inner.unlock_memory(),
+        }
+    }
+}
+
+impl MemoryUsage for StorageViewWrap {
+    fn memory_usage(&self) -> MemoryFootprint {
+        match self {
+            #[cfg(target_endian = "little")]
+            StorageViewWrap::MmapArray(inner) => inner.memory_usage(),
+            StorageViewWrap::NdArray(inner) => inner.memory_usage(),
+        }
+    }
+}
+
+#[cfg(target_endian = "little")]
+impl From<MmapArray> for StorageViewWrap {
+    fn from(s: MmapArray) -> Self {
+        StorageViewWrap::MmapArray(s)
+    }
+}
+
+impl From<NdArray> for StorageViewWrap {
+    fn from(s: NdArray) -> Self {
+        StorageViewWrap::NdArray(s)
+    }
+}
@@ -0,0 +1,55 @@
+//! Chunks that are unknown to this version of finalfusion
+
+use std::fmt;
+
+/// Chunk that is unknown to this version of finalfusion.
+///
+/// Chunks with identifiers that are unknown, as well as custom chunks
+/// without a registered handler, are retained as opaque data when
+/// embeddings are read. The chunks are written unchanged, so that
+/// reading and writing embeddings does not drop data.
+#[derive(Clone, Eq, PartialEq)]
+pub struct UnknownChunk {
+    identifier: u32,
+    data: Vec<u8>,
+    data_alignment: u64,
+}
+
+impl UnknownChunk {
+    /// Get the chunk identifier.
+    pub fn identifier(&self) -> u32 {
+        self.identifier
+    }
+
+    /// Get the chunk data.
+    ///
+    /// The data does not include the chunk identifier and length.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Construct an unknown chunk from its parts.
+    #[doc(hidden)]
+    pub fn from_parts(identifier: u32, data: Vec<u8>, data_alignment: u64) -> Self {
+        UnknownChunk {
+            identifier,
+            data,
+            data_alignment,
+        }
+    }
+
+    /// Get the alignment of the data in the file it was read from.
+    #[doc(hidden)]
+    pub fn data_alignment(&self) -> u64 {
+        self.data_alignment
+    }
+}
+
+impl fmt::Debug for UnknownChunk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UnknownChunk")
+            .field("identifier", &self.identifier)
+            .field("len", &self.data.len())
+            .finish()
+    }
+}
@@ -0,0 +1,330 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use crate::chunks::memory::{
+    string_map_heap_size, strings_heap_size, MemoryFootprint, MemoryUsage,
+};
+use crate::chunks::vocab::{RetainWords, Vocab, VocabIter, WordIndex};
+use crate::error::{Error, ErrorKind, Result};
+use crate::normalization::{collision_error, NormalizeVocab, WordNormalization};
+
+/// Vocabulary with aliases.
+///
+/// `AliasVocab` wraps another vocabulary and adds aliases that map
+/// surface forms to the index of a word of the wrapped vocabulary.
+/// Aliases share the embedding of their word, so that e.g. spelling
+/// variants do not need their own rows in the storage. Aliases are
+/// not words of the vocabulary: they are not returned by `words` and
+/// they do not count towards `words_len`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AliasVocab<V> {
+    inner: V,
+    aliases: Vec<String>,
+    indices: HashMap<String, usize>,
+}
+
+impl<V> AliasVocab<V>
+where
+    V: Vocab,
+{
+    /// Construct an alias vocabulary around a vocabulary.
+    ///
+    /// Each alias is paired with the index of the word of `inner`
+    /// that it is an alias of.
+    ///
+    /// Panics when an alias is a word of `inner`, when there are
+    /// duplicate aliases, or when an index is not a word index of
+    /// `inner`.
+    pub fn new(inner: V, aliases: impl IntoIterator<Item = (String, usize)>) -> Self {
+        Self::try_new(inner, aliases.into_iter().collect()).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    fn try_new(inner: V, aliases: Vec<(String, usize)>) -> std::result::Result<Self, String> {
+        let mut indices = HashMap::with_capacity(aliases.len());
+        let mut alias_words = Vec::with_capacity(aliases.len());
+        for (alias, idx) in aliases {
+            if let Some(WordIndex::Word(_)) = inner.idx(&alias) {
+                return Err(format!("Alias is a word of the vocabulary: {}", alias));
+            }
+
+            if idx >= inner.words_len() {
+                return Err(format!(
+                    "Index of alias {} is not a word index: {} (words: {})",
+                    alias,
+                    idx,
+                    inner.words_len()
+                ));
+            }
+
+            if indices.insert(alias.clone(), idx).is_some() {
+                return Err(format!("Duplicate alias: {}", alias));
+            }
+
+            alias_words.push(alias);
+        }
+
+        Ok(AliasVocab {
+            inner,
+            aliases: alias_words,
+            indices,
+        })
+    }
+}
+
+impl<V> AliasVocab<V> {
+    /// Get the wrapped vocabulary.
+    pub fn inner(&self) -> &V {
+        &self.inner
+    }
+
+    /// Unwrap the wrapped vocabulary.
+    pub fn into_inner(self) -> V {
+        self.inner
+    }
+
+    /// Get the aliases with the indices of their words.
+    ///
+    /// Aliases are returned in the order in which they were added.
+    pub fn aliases(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.aliases
+            .iter()
+            .map(move |alias| (alias.as_str(), self.indices[alias]))
+    }
+
+    /// Get the number of aliases.
+    pub fn aliases_len(&self) -> usize {
+        self.aliases.len()
+    }
+
+    /// Replace the wrapped vocabulary, keeping the aliases.
+    ///
+    /// The new vocabulary must have the same words.
+    pub(crate) fn map_inner<V2>(self, f: impl FnOnce(V) -> V2) -> AliasVocab<V2> {
+        AliasVocab {
+            inner: f(self.inner),
+            aliases: self.aliases,
+            indices: self.indices,
+        }
+    }
+}
+
+impl<V> Vocab for AliasVocab<V>
+where
+    V: Vocab,
+{
+    fn idx(&self, word: &str) -> Option<WordIndex> {
+        match self.indices.get(word) {
+            Some(&idx) => Some(WordIndex::Word(idx)),
+            None => self.inner.idx(word),
+        }
+    }
+
+    fn words_len(&self) -> usize {
+        self.inner.words_len()
+    }
+
+    fn vocab_len(&self) -> usize {
+        self.inner.vocab_len()
+    }
+
+    fn words(&self) -> &[String] {
+        self.inner.words()
+    }
+
+    fn word(&self, idx: usize) -> Option<Cow<'_, str>> {
+        self.inner.word(idx)
+    }
+
+    fn iter(&self) -> VocabIter<'_> {
+        self.inner.iter()
+    }
+}
+
+impl<V> RetainWords for AliasVocab<V>
+where
+    V: RetainWords,
+{
+    /// Construct a vocabulary with the words at the given indices.
+    ///
+    /// Aliases of words that are not retained are removed.
+    fn retain_indices(&self, indices: &[usize]) -> (Self, Vec<usize>) {
+        let (inner, rows) = self.inner.retain_indices(indices);
+
+        let mut aliases = Vec::new();
+        let mut alias_indices = HashMap::new();
+        for alias in &self.aliases {
+            if let Ok(idx) = indices.binary_search(&self.indices[alias]) {
+                aliases.push(alias.clone());
+                alias_indices.insert(alias.clone(), idx);
+            }
+        }
+
+        (
+            AliasVocab {
+                inner,
+                aliases,
+                indices: alias_indices,
+            },
+            rows,
+        )
+    }
+}
+
+impl<V> NormalizeVocab for AliasVocab<V>
+where
+    V: Clone + NormalizeVocab + Vocab,
+{
+    /// Normalize the words and the aliases.
+    ///
+    /// Aliases of a word that have the same normalized form as each
+    /// other or as the word are merged. Returns an error when an alias
+    /// has the same normalized form as another word or as an alias of
+    /// another word. The vocabulary is not modified in that case.
+    fn normalize_vocab(&mut self, normalization: &dyn WordNormalization) -> Result<()> {
+        let mut inner = self.inner.clone();
+        inner.normalize_vocab(normalization)?;
+
+        let mut aliases = Vec::with_capacity(self.aliases.len());
+        let mut originals = HashMap::with_capacity(self.aliases.len());
+        let mut indices = HashMap::with_capacity(self.aliases.len());
+        for alias in &self.aliases {
+            let idx = self.indices[alias];
+            let normalized = normalization.normalize(alias).into_owned();
+
+            if let Some(WordIndex::Word(word_idx)) = inner.idx(&normalized) {
+                if word_idx == idx {
+                    continue;
+                }
+
+                let word = self.inner.word(word_idx).expect("Invalid word index");
+                return Err(collision_error(alias, &word, &normalized));
+            }
+
+            match indices.get(&normalized) {
+                Some(&other_idx) if other_idx == idx => continue,
+                Some(_) => return Err(collision_error(alias, originals[&normalized], &normalized)),
+                None => (),
+            }
+
+            originals.insert(normalized.clone(), alias.as_str());
+            indices.insert(normalized.clone(), idx);
+            aliases.push(normalized);
+        }
+
+        self.inner = inner;
+        self.aliases = aliases;
+        self.indices = indices;
+
+        Ok(())
+    }
+}
+
+impl<V> MemoryUsage for AliasVocab<V>
+where
+    V: MemoryUsage,
+{
+    fn memory_usage(&self) -> MemoryFootprint {
+        self.inner.memory_usage()
+            + MemoryFootprint::resident(
+                strings_heap_size(&self.aliases) + string_map_heap_size(&self.indices),
+            )
+    }
+}
+
+impl<V> AliasVocab<V>
+where
+    V: Vocab,
+{
+    /// Construct an alias vocabulary, returning an error for invalid
+    /// aliases.
+    #[doc(hidden)]
+    pub fn from_aliases(inner: V, aliases: Vec<(String, usize)>) -> Result<Self> {
+        Self::try_new(inner, aliases).map_err(|e| Error::from(ErrorKind::Format(e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::AliasVocab;
+    use crate::chunks::vocab::{RetainWords, SimpleVocab, Vocab, WordIndex};
+    use crate::normalization::{CaseFolding, NormalizeVocab};
+
+    fn test_alias_vocab() -> AliasVocab<SimpleVocab> {
+        AliasVocab::new(
+            SimpleVocab::new(vec![
+                "color".to_owned(),
+                "center".to_owned(),
+                "is".to_owned(),
+            ]),
+            vec![("colour".to_owned(), 0), ("centre".to_owned(), 1)],
+        )
+    }
+
+    #[test]
+    fn alias_vocab_lookup() {
+        let vocab = test_alias_vocab();
+        assert_eq!(vocab.words_len(), 3);
+        assert_eq!(vocab.vocab_len(), 3);
+        assert_eq!(vocab.idx("colour"), Some(WordIndex::Word(0)));
+        assert_eq!(vocab.idx("color"), Some(WordIndex::Word(0)));
+        assert_eq!(vocab.idx("centre"), Some(WordIndex::Word(1)));
+        assert_eq!(vocab.idx("is"), Some(WordIndex::Word(2)));
+        assert_eq!(vocab.idx("colours"), None);
+        assert_eq!(
+            vocab.aliases().collect::<Vec<_>>(),
+            vec![("colour", 0), ("centre", 1)]
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn alias_vocab_rejects_word_aliases() {
+        AliasVocab::new(
+            SimpleVocab::new(vec!["color".to_owned(), "colour".to_owned()]),
+            vec![("colour".to_owned(), 0)],
+        );
+    }
+
+    #[test]
+    fn alias_vocab_retain_removes_aliases() {
+        let (vocab, rows) = test_alias_vocab().retain_indices(&[1, 2]);
+        assert_eq!(rows, vec![1, 2]);
+        assert_eq!(vocab.idx("centre"), Some(WordIndex::Word(0)));
+        assert_eq!(vocab.idx("colour"), None);
+        assert_eq!(vocab.aliases_len(), 1);
+    }
+
+    #[test]
+    fn alias_vocab_normalizes_aliases() {
+        let mut vocab = AliasVocab::new(
+            SimpleVocab::new(vec!["color".to_owned(), "Centre".to_owned()]),
+            vec![
+                ("Colour".to_owned(), 0),
+                ("COLOUR".to_owned(), 0),
+                ("centre".to_owned(), 1),
+            ],
+        );
+        vocab.normalize_vocab(&CaseFolding::default()).unwrap();
+        assert_eq!(vocab.idx("colour"), Some(WordIndex::Word(0)));
+        assert_eq!(vocab.idx("centre"), Some(WordIndex::Word(1)));
+        assert_eq!(vocab.aliases().collect::<Vec<_>>(), vec![("colour", 0)]);
+    }
+
+    #[test]
+    fn alias_vocab_normalization_rejects_collisions() {
+        let mut vocab = AliasVocab::new(
+            SimpleVocab::new(vec!["color".to_owned(), "Centre".to_owned()]),
+            vec![("Colour".to_owned(), 0), ("centre".to_owned(), 0)],
+        );
+        let check = vocab.clone();
+        assert!(vocab.normalize_vocab(&CaseFolding::default()).is_err());
+        assert_eq!(vocab, check);
+
+        let mut vocab = AliasVocab::new(
+            SimpleVocab::new(vec!["color".to_owned(), "center".to_owned()]),
+            vec![("Centre".to_owned(), 0), ("centre".to_owned(), 1)],
+        );
+        assert!(vocab.normalize_vocab(&CaseFolding::default()).is_err());
+    }
+}
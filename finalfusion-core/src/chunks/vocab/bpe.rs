@@ -0,0 +1,301 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use crate::chunks::memory::{
+    string_map_heap_size, strings_heap_size, MemoryFootprint, MemoryUsage,
+};
+use crate::chunks::vocab::{
+    check_retained_indices, create_indices, RetainWords, SubwordIndices, Vocab, WordIndex,
+};
+use crate::error::Result;
+use crate::normalization::{normalize_words, NormalizeVocab, WordNormalization};
+
+/// Vocabulary with byte pair encoding (BPE) units.
+///
+/// Unknown words are segmented into BPE units by splitting them into
+/// characters and then repeatedly applying the highest-priority merge
+/// of two adjacent units. The embedding of an unknown word is the
+/// average of the embeddings of its units. The embeddings of the units
+/// are stored after the embeddings of the words.
+///
+/// Some BPE implementations mark the end of a word by appending a
+/// marker such as `</w>` to the last character before merging, the
+/// marker can be set with the `end_of_word` argument of
+/// `BpeVocab::new`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BpeVocab {
+    indices: HashMap<String, usize>,
+    words: Vec<String>,
+    unit_indices: HashMap<String, usize>,
+    units: Vec<String>,
+    merge_ranks: HashMap<String, HashMap<String, usize>>,
+    end_of_word: String,
+}
+
+impl BpeVocab {
+    /// Construct a new BPE vocabulary.
+    ///
+    /// Words and units are assigned indices in the given order.
+    /// `merges` are the merges of the BPE model, from the highest to
+    /// the lowest priority. `end_of_word` is appended to the last
+    /// character of a word before it is segmented, an empty string
+    /// disables the end-of-word marker.
+    ///
+    /// Panics when there are duplicate words or units.
+    pub fn new(
+        words: impl Into<Vec<String>>,
+        units: impl Into<Vec<String>>,
+        merges: impl IntoIterator<Item = (String, String)>,
+        end_of_word: impl Into<String>,
+    ) -> Self {
+        let words = words.into();
+        let indices = create_indices(&words);
+        assert_eq!(
+            words.len(),
+            indices.len(),
+            "words contained duplicate entries."
+        );
+
+        let units = units.into();
+        let unit_indices = create_indices(&units);
+        assert_eq!(
+            units.len(),
+            unit_indices.len(),
+            "units contained duplicate entries."
+        );
+
+        let mut merge_ranks: HashMap<String, HashMap<String, usize>> = HashMap::new();
+        for (rank, (left, right)) in merges.into_iter().enumerate() {
+            // Only the first occurrence of a merge has an effect.
+            merge_ranks
+                .entry(left)
+                .or_default()
+                .entry(right)
+                .or_insert(rank);
+        }
+
+        BpeVocab {
+            indices,
+            words,
+            unit_indices,
+            units,
+            merge_ranks,
+            end_of_word: end_of_word.into(),
+        }
+    }
+
+    /// Get the end-of-word marker.
+    pub fn end_of_word(&self) -> &str {
+        &self.end_of_word
+    }
+
+    /// Get the BPE merges, from the highest to the lowest priority.
+    pub fn merges(&self) -> Vec<(&str, &str)> {
+        let mut merges = self
+            .merge_ranks
+            .iter()
+            .flat_map(|(left, rights)| {
+                rights
+                    .iter()
+                    .map(move |(right, &rank)| (rank, left.as_str(), right.as_str()))
+            })
+            .collect::<Vec<_>>();
+        merges.sort_unstable();
+        merges
+            .into_iter()
+            .map(|(_, left, right)| (left, right))
+            .collect()
+    }
+
+    /// Get the BPE units.
+    pub fn units(&self) -> &[String] {
+        &self.units
+    }
+
+    /// Segment a word into BPE units.
+    ///
+    /// The units are returned regardless of whether they are in the
+    /// vocabulary.
+    pub fn segment(&self, word: &str) -> Vec<String> {
+        let mut units = word.chars().map(String::from).collect::<Vec<_>>();
+        if let Some(last) = units.last_mut() {
+            last.push_str(&self.end_of_word);
+        }
+
+        loop {
+            let best_rank = units
+                .windows(2)
+                .filter_map(|pair| self.merge_rank(&pair[0], &pair[1]))
+                .min();
+            let best_rank = match best_rank {
+                Some(rank) => rank,
+                None => break,
+            };
+
+            // Apply the merge to all occurrences of the pair.
+            let mut merged = Vec::with_capacity(units.len());
+            let mut iter = units.into_iter().peekable();
+            while let Some(mut unit) = iter.next() {
+                if let Some(next) = iter.peek() {
+                    if self.merge_rank(&unit, next) == Some(best_rank) {
+                        unit.push_str(next);
+                        iter.next();
+                    }
+                }
+                merged.push(unit);
+            }
+            units = merged;
+        }
+
+        units
+    }
+
+    fn merge_rank(&self, left: &str, right: &str) -> Option<usize> {
+        self.merge_ranks.get(left)?.get(right).cloned()
+    }
+}
+
+impl Vocab for BpeVocab {
+    fn idx(&self, word: &str) -> Option<WordIndex> {
+        // If the word is known, return its index.
+        if let Some(idx) = self.indices.get(word).cloned() {
+            return Some(WordIndex::Word(idx));
+        }
+
+        // Otherwise, return the indices of its units.
+        self.subword_indices(word).map(WordIndex::Subword)
+    }
+
+    fn words_len(&self) -> usize {
+        self.indices.len()
+    }
+
+    fn vocab_len(&self) -> usize {
+        self.words_len() + self.units.len()
+    }
+
+    fn words(&self) -> &[String] {
+        &self.words
+    }
+
+    fn word(&self, idx: usize) -> Option<Cow<'_, str>> {
+        match idx.checked_sub(self.words_len()) {
+            None => Some(Cow::Borrowed(self.words[idx].as_str())),
+            Some(unit_idx) => self
+                .units
+                .get(unit_idx)
+                .map(|unit| Cow::Borrowed(unit.as_str())),
+        }
+    }
+}
+
+impl SubwordIndices for BpeVocab {
+    /// Return the indices of the BPE units of a word.
+    ///
+    /// Units that are not in the vocabulary are skipped.
+    fn subword_indices(&self, word: &str) -> Option<Vec<usize>> {
+        let indices = self
+            .segment(word)
+            .iter()
+            .filter_map(|unit| self.unit_indices.get(unit))
+            .map(|idx| idx + self.words_len())
+            .collect::<Vec<_>>();
+        if indices.is_empty() {
+            None
+        } else {
+            Some(indices)
+        }
+    }
+}
+
+impl RetainWords for BpeVocab {
+    /// Construct a vocabulary with the words at the given indices.
+    ///
+    /// All units and merges are retained.
+    fn retain_indices(&self, indices: &[usize]) -> (Self, Vec<usize>) {
+        check_retained_indices(indices, self.words_len());
+        let words = indices
+            .iter()
+            .map(|&idx| self.words[idx].clone())
+            .collect::<Vec<_>>();
+        let vocab = BpeVocab {
+            indices: create_indices(&words),
+            words,
+            unit_indices: self.unit_indices.clone(),
+            units: self.units.clone(),
+            merge_ranks: self.merge_ranks.clone(),
+            end_of_word: self.end_of_word.clone(),
+        };
+
+        let mut rows = indices.to_vec();
+        rows.extend(self.words_len()..self.vocab_len());
+
+        (vocab, rows)
+    }
+}
+
+impl NormalizeVocab for BpeVocab {
+    fn normalize_vocab(&mut self, normalization: &dyn WordNormalization) -> Result<()> {
+        normalize_words(&mut self.words, normalization)?;
+        self.indices = create_indices(&self.words);
+        Ok(())
+    }
+}
+
+impl MemoryUsage for BpeVocab {
+    fn memory_usage(&self) -> MemoryFootprint {
+        let merges_size = string_map_heap_size(&self.merge_ranks)
+            + self
+                .merge_ranks
+                .values()
+                .map(string_map_heap_size)
+                .sum::<usize>();
+
+        MemoryFootprint::resident(
+            string_map_heap_size(&self.indices)
+                + strings_heap_size(&self.words)
+                + string_map_heap_size(&self.unit_indices)
+                + strings_heap_size(&self.units)
+                + merges_size
+                + self.end_of_word.capacity(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::BpeVocab;
+    use crate::chunks::vocab::{Vocab, WordIndex};
+
+    fn strings(strs: &[&str]) -> Vec<String> {
+        strs.iter().map(|s| (*s).to_owned()).collect()
+    }
+
+    fn test_bpe_vocab() -> BpeVocab {
+        let merges = vec![("l", "o"), ("lo", "w"), ("e", "r</w>"), ("w", "e")];
+        BpeVocab::new(
+            strings(&["low", "lowest"]),
+            strings(&["low", "er</w>", "l", "o", "w", "e", "r</w>", "s", "t</w>"]),
+            merges
+                .into_iter()
+                .map(|(left, right)| (left.to_owned(), right.to_owned())),
+            "</w>",
+        )
+    }
+
+    #[test]
+    fn bpe_vocab_segments_unknown_words() {
+        let vocab = test_bpe_vocab();
+        assert_eq!(vocab.segment("lower"), strings(&["low", "er</w>"]));
+        assert_eq!(vocab.segment("slow"), strings(&["s", "lo", "w</w>"]));
+        assert_eq!(vocab.segment(""), Vec::<String>::new());
+
+        assert_eq!(vocab.idx("lowest"), Some(WordIndex::Word(1)));
+        assert_eq!(vocab.idx("lower"), Some(WordIndex::Subword(vec![2, 3])));
+        // lo and w</w> are not units.
+        assert_eq!(vocab.idx("slow"), Some(WordIndex::Subword(vec![9])));
+        assert_eq!(vocab.idx("x"), None);
+        assert_eq!(vocab.vocab_len(), 11);
+    }
+}
@@ -0,0 +1,151 @@
+use std::borrow::Cow;
+
+use crate::chunks::memory::{MemoryFootprint, MemoryUsage};
+use crate::chunks::vocab::{RetainWords, Vocab, VocabIter, WordIndex};
+use crate::error::Result;
+use crate::normalization::{NormalizeVocab, WordNormalization};
+
+/// Number of byte units of a byte-fallback vocabulary.
+pub const N_BYTE_UNITS: usize = 256;
+
+/// Vocabulary that falls back to byte units.
+///
+/// `ByteFallbackVocab` wraps another vocabulary. When the wrapped
+/// vocabulary has no index for a word, neither for the word itself
+/// nor for its subword units, the word is decomposed into its UTF-8
+/// bytes. Each byte value has its own embedding, which is stored after
+/// the rows of the wrapped vocabulary. Thus, every non-empty word has
+/// an embedding.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ByteFallbackVocab<V> {
+    inner: V,
+}
+
+impl<V> ByteFallbackVocab<V> {
+    /// Construct a byte-fallback vocabulary around a vocabulary.
+    ///
+    /// The storage of the vocabulary must have `N_BYTE_UNITS` rows
+    /// after the rows of `inner`, the row of byte *b* being
+    /// `inner.vocab_len() + b`.
+    pub fn new(inner: V) -> Self {
+        ByteFallbackVocab { inner }
+    }
+
+    /// Get the wrapped vocabulary.
+    pub fn inner(&self) -> &V {
+        &self.inner
+    }
+
+    /// Unwrap the wrapped vocabulary.
+    pub fn into_inner(self) -> V {
+        self.inner
+    }
+}
+
+impl<V> ByteFallbackVocab<V>
+where
+    V: Vocab,
+{
+    /// Get the storage index of a byte unit.
+    pub fn byte_idx(&self, byte: u8) -> usize {
+        self.inner.vocab_len() + byte as usize
+    }
+}
+
+impl<V> Vocab for ByteFallbackVocab<V>
+where
+    V: Vocab,
+{
+    /// Get the index of a word.
+    ///
+    /// Returns `None` only for the empty string, since it has
+    /// no bytes.
+    fn idx(&self, word: &str) -> Option<WordIndex> {
+        self.inner.idx(word).or_else(|| {
+            if word.is_empty() {
+                None
+            } else {
+                Some(WordIndex::Subword(
+                    word.bytes().map(|byte| self.byte_idx(byte)).collect(),
+                ))
+            }
+        })
+    }
+
+    fn words_len(&self) -> usize {
+        self.inner.words_len()
+    }
+
+    fn vocab_len(&self) -> usize {
+        self.inner.vocab_len() + N_BYTE_UNITS
+    }
+
+    fn words(&self) -> &[String] {
+        self.inner.words()
+    }
+
+    /// Get the word or subword unit at a storage index.
+    ///
+    /// Returns `None` for byte units, since a byte is generally not
+    /// a valid UTF-8 string.
+    fn word(&self, idx: usize) -> Option<Cow<'_, str>> {
+        self.inner.word(idx)
+    }
+
+    fn iter(&self) -> VocabIter<'_> {
+        self.inner.iter()
+    }
+}
+
+impl<V> RetainWords for ByteFallbackVocab<V>
+where
+    V: RetainWords,
+{
+    fn retain_indices(&self, indices: &[usize]) -> (Self, Vec<usize>) {
+        let (inner, mut rows) = self.inner.retain_indices(indices);
+        rows.extend((0..N_BYTE_UNITS).map(|byte| self.inner.vocab_len() + byte));
+        (ByteFallbackVocab { inner }, rows)
+    }
+}
+
+impl<V> NormalizeVocab for ByteFallbackVocab<V>
+where
+    V: NormalizeVocab,
+{
+    fn normalize_vocab(&mut self, normalization: &dyn WordNormalization) -> Result<()> {
+        self.inner.normalize_vocab(normalization)
+    }
+}
+
+impl<V> MemoryUsage for ByteFallbackVocab<V>
+where
+    V: MemoryUsage,
+{
+    fn memory_usage(&self) -> MemoryFootprint {
+        self.inner.memory_usage()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::ByteFallbackVocab;
+    use crate::chunks::vocab::{SimpleVocab, Vocab, WordIndex};
+
+    fn test_byte_fallback_vocab() -> ByteFallbackVocab<SimpleVocab> {
+        ByteFallbackVocab::new(SimpleVocab::new(vec!["this".to_owned(), "is".to_owned()]))
+    }
+
+    #[test]
+    fn byte_fallback_vocab_lookup() {
+        let vocab = test_byte_fallback_vocab();
+        assert_eq!(vocab.vocab_len(), 258);
+        assert_eq!(vocab.words_len(), 2);
+        assert_eq!(vocab.idx("is"), Some(WordIndex::Word(1)));
+        assert_eq!(
+            vocab.idx("aß"),
+            Some(WordIndex::Subword(vec![2 + 0x61, 2 + 0xc3, 2 + 0x9f]))
+        );
+        assert_eq!(vocab.idx(""), None);
+    }
+}
@@ -0,0 +1,80 @@
+use crate::chunks::memory::{MemoryFootprint, MemoryUsage};
+use crate::chunks::vocab::{RetainWords, SimpleVocab, Vocab, WordIndex};
+use crate::error::Result;
+use crate::normalization::{NormalizeVocab, WordNormalization};
+
+/// Vocabulary that is stored with front coding.
+///
+/// In memory, `FrontCodedVocab` behaves like `SimpleVocab`. The
+/// difference is in the chunk: `SimpleVocab` stores every word with a
+/// 32-bit length, whereas `FrontCodedVocab` sorts the words and stores
+/// each word as the length of the prefix that it shares with the
+/// preceding word and the remaining suffix. All lengths and the word
+/// indices are stored as variable-length integers. Since sorted words
+/// tend to share long prefixes, this considerably reduces the size of
+/// files with large vocabularies.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FrontCodedVocab {
+    inner: SimpleVocab,
+}
+
+impl FrontCodedVocab {
+    /// Construct a new front-coded vocabulary.
+    ///
+    /// Words are assigned indices in the given order.
+    ///
+    /// Panics when there are duplicate words.
+    pub fn new(words: impl Into<Vec<String>>) -> Self {
+        FrontCodedVocab {
+            inner: SimpleVocab::new(words),
+        }
+    }
+
+    /// Get the vocabulary as a `SimpleVocab`.
+    pub fn into_inner(self) -> SimpleVocab {
+        self.inner
+    }
+}
+
+impl From<SimpleVocab> for FrontCodedVocab {
+    fn from(inner: SimpleVocab) -> Self {
+        FrontCodedVocab { inner }
+    }
+}
+
+impl Vocab for FrontCodedVocab {
+    fn idx(&self, word: &str) -> Option<WordIndex> {
+        self.inner.idx(word)
+    }
+
+    fn words_len(&self) -> usize {
+        self.inner.words_len()
+    }
+
+    fn vocab_len(&self) -> usize {
+        self.inner.vocab_len()
+    }
+
+    fn words(&self) -> &[String] {
+        self.inner.words()
+    }
+}
+
+impl RetainWords for FrontCodedVocab {
+    fn retain_indices(&self, indices: &[usize]) -> (Self, Vec<usize>) {
+        let (inner, rows) = self.inner.retain_indices(indices);
+        (FrontCodedVocab { inner }, rows)
+    }
+}
+
+impl NormalizeVocab for FrontCodedVocab {
+    fn normalize_vocab(&mut self, normalization: &dyn WordNormalization) -> Result<()> {
+        self.inner.normalize_vocab(normalization)
+    }
+}
+
+impl MemoryUsage for FrontCodedVocab {
+    fn memory_usage(&self) -> MemoryFootprint {
+        self.inner.memory_usage()
+    }
+}
@@ -0,0 +1,690 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::mem::size_of;
+use std::ops::Range;
+use std::str;
+use std::sync::OnceLock;
+
+use crate::chunks::memory::{strings_heap_size, MemoryFootprint, MemoryUsage};
+use crate::chunks::vocab::{check_retained_indices, RetainWords, Vocab, VocabIter, WordIndex};
+use crate::error::{Error, ErrorKind, Result};
+use crate::normalization::{normalize_words, NormalizeVocab, WordNormalization};
+
+/// Vocabulary backed by a finite state transducer.
+///
+/// The words are stored in a minimal acyclic finite state transducer
+/// that maps every word to its rank in sorted order, together with a
+/// permutation of ranks to word indices. Words with common prefixes
+/// or suffixes share states in the transducer, so for large
+/// vocabularies `FstVocab` uses far less memory than the hash table
+/// of `SimpleVocab`. In return, lookups are somewhat slower, since a
+/// lookup walks the transducer byte by byte.
+///
+/// The word list returned by `Vocab::words` is reconstructed from the
+/// transducer when it is first requested and kept in memory
+/// afterwards. Lookups, `Vocab::word`, and `Vocab::iter` do not
+/// require the word list, they reconstruct words from the transducer
+/// on demand.
+///
+/// The transducer stores `u32` word indices, so `FstVocab` supports
+/// at most *2^32 - 2* words. `MmapVocab` can be used for larger
+/// vocabularies.
+#[derive(Clone, Debug)]
+pub struct FstVocab {
+    fst: Fst,
+    indices: Vec<u32>,
+    ranks: OnceLock<Vec<u32>>,
+    words: OnceLock<Vec<String>>,
+}
+
+impl FstVocab {
+    /// Construct a new transducer-backed vocabulary.
+    ///
+    /// Words are assigned indices in the given order.
+    ///
+    /// Panics when there are duplicate words or when there are more
+    /// than *2^32 - 2* words.
+    pub fn new(words: impl Into<Vec<String>>) -> Self {
+        let words = words.into();
+        assert!(
+            words.len() < u32::MAX as usize,
+            "FstVocab supports at most {} words",
+            u32::MAX - 1
+        );
+
+        let mut order = (0..words.len()).collect::<Vec<_>>();
+        order.sort_unstable_by(|&idx1, &idx2| words[idx1].cmp(&words[idx2]));
+        assert!(
+            order
+                .windows(2)
+                .all(|pair| words[pair[0]] != words[pair[1]]),
+            "words contained duplicate entries."
+        );
+
+        let mut builder = FstBuilder::new();
+        for &idx in &order {
+            builder.insert(words[idx].as_bytes());
+        }
+
+        FstVocab {
+            fst: builder.finish(),
+            indices: order.into_iter().map(|idx| idx as u32).collect(),
+            ranks: OnceLock::new(),
+            words: OnceLock::new(),
+        }
+    }
+
+    /// Construct a vocabulary from the transducer arrays and the
+    /// permutation of ranks to word indices.
+    ///
+    /// Returns an error when the transducer is not well-formed or the
+    /// indices are not a permutation.
+    #[doc(hidden)]
+    pub fn from_parts(
+        offsets: Vec<u32>,
+        finals: Vec<bool>,
+        labels: Vec<u8>,
+        targets: Vec<u32>,
+        outputs: Vec<u32>,
+        indices: Vec<u32>,
+    ) -> Result<Self> {
+        let fst = Fst {
+            offsets,
+            finals,
+            labels,
+            targets,
+            outputs,
+        };
+        fst.check(indices.len())?;
+        check_permutation(&indices)?;
+
+        Ok(FstVocab {
+            fst,
+            indices,
+            ranks: OnceLock::new(),
+            words: OnceLock::new(),
+        })
+    }
+
+    #[doc(hidden)]
+    pub fn offsets(&self) -> &[u32] {
+        &self.fst.offsets
+    }
+
+    #[doc(hidden)]
+    pub fn finals(&self) -> &[bool] {
+        &self.fst.finals
+    }
+
+    #[doc(hidden)]
+    pub fn labels(&self) -> &[u8] {
+        &self.fst.labels
+    }
+
+    #[doc(hidden)]
+    pub fn targets(&self) -> &[u32] {
+        &self.fst.targets
+    }
+
+    #[doc(hidden)]
+    pub fn outputs(&self) -> &[u32] {
+        &self.fst.outputs
+    }
+
+    #[doc(hidden)]
+    pub fn indices(&self) -> &[u32] {
+        &self.indices
+    }
+
+    /// Get the permutation of word indices to ranks.
+    fn ranks(&self) -> &[u32] {
+        self.ranks.get_or_init(|| {
+            let mut ranks = vec![0; self.indices.len()];
+            for (rank, &idx) in self.indices.iter().enumerate() {
+                ranks[idx as usize] = rank as u32;
+            }
+            ranks
+        })
+    }
+
+    fn rank_word(&self, rank: usize) -> String {
+        let word = self.fst.word(rank).expect("Invalid word rank");
+        String::from_utf8(word).expect("Transducer contains invalid UTF-8")
+    }
+
+    fn reconstruct_words(&self) -> Vec<String> {
+        let mut words = vec![String::new(); self.indices.len()];
+        self.fst
+            .for_each_word(|rank, word| {
+                words[self.indices[rank as usize] as usize] = str::from_utf8(word)
+                    .expect("Transducer contains invalid UTF-8")
+                    .to_owned();
+                Ok(())
+            })
+            .expect("Cannot enumerate transducer words");
+        words
+    }
+}
+
+impl PartialEq for FstVocab {
+    fn eq(&self, other: &Self) -> bool {
+        // The word list is a cache, so it is not compared.
+        self.fst == other.fst && self.indices == other.indices
+    }
+}
+
+impl Eq for FstVocab {}
+
+impl Vocab for FstVocab {
+    fn idx(&self, word: &str) -> Option<WordIndex> {
+        self.fst
+            .rank(word.as_bytes())
+            .map(|rank| WordIndex::Word(self.indices[rank] as usize))
+    }
+
+    fn words_len(&self) -> usize {
+        self.indices.len()
+    }
+
+    fn vocab_len(&self) -> usize {
+        self.words_len()
+    }
+
+    fn words(&self) -> &[String] {
+        self.words.get_or_init(|| self.reconstruct_words())
+    }
+
+    fn word(&self, idx: usize) -> Option<Cow<'_, str>> {
+        if let Some(words) = self.words.get() {
+            return words.get(idx).map(|word| Cow::Borrowed(word.as_str()));
+        }
+
+        let rank = *self.ranks().get(idx)?;
+        Some(Cow::Owned(self.rank_word(rank as usize)))
+    }
+
+    fn iter(&self) -> VocabIter<'_> {
+        if let Some(words) = self.words.get() {
+            return Box::new(
+                words
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, word)| (Cow::Borrowed(word.as_str()), idx)),
+            );
+        }
+
+        Box::new(
+            self.ranks()
+                .iter()
+                .enumerate()
+                .map(move |(idx, &rank)| (Cow::Owned(self.rank_word(rank as usize)), idx)),
+        )
+    }
+}
+
+impl RetainWords for FstVocab {
+    fn retain_indices(&self, indices: &[usize]) -> (Self, Vec<usize>) {
+        check_retained_indices(indices, self.words_len());
+        let words = indices
+            .iter()
+            .map(|&idx| self.word(idx).expect("Invalid word index").into_owned())
+            .collect::<Vec<_>>();
+
+        (FstVocab::new(words), indices.to_vec())
+    }
+}
+
+impl NormalizeVocab for FstVocab {
+    fn normalize_vocab(&mut self, normalization: &dyn WordNormalization) -> Result<()> {
+        let mut words = self
+            .iter()
+            .map(|(word, _)| word.into_owned())
+            .collect::<Vec<_>>();
+        normalize_words(&mut words, normalization)?;
+        *self = FstVocab::new(words);
+        Ok(())
+    }
+}
+
+impl MemoryUsage for FstVocab {
+    fn memory_usage(&self) -> MemoryFootprint {
+        let ranks_size = self.ranks.get().map(Vec::len).unwrap_or(0) * size_of::<u32>();
+        let words_size = self.words.get().map(strings_heap_size).unwrap_or(0);
+        MemoryFootprint::resident(
+            self.fst.heap_size() + self.indices.len() * size_of::<u32>() + ranks_size + words_size,
+        )
+    }
+}
+
+fn check_permutation(indices: &[u32]) -> Result<()> {
+    let mut seen = vec![false; indices.len()];
+    for &idx in indices {
+        match seen.get_mut(idx as usize) {
+            Some(seen @ false) => *seen = true,
+            _ => {
+                return Err(ErrorKind::Format(format!(
+                    "Word index {} is out of bounds or occurs more than once",
+                    idx
+                ))
+                .into())
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Minimal acyclic finite state transducer.
+///
+/// The transducer maps each accepted word to its rank among the
+/// accepted words in lexicographic byte order. The output of a
+/// transition is the number of accepted words that precede all words
+/// through the transition. The rank of a word is the sum of the
+/// outputs along its path.
+///
+/// Transitions are stored per state, sorted by label. Since states are
+/// added after all states that they transition to, the targets of a
+/// state's transitions have lower state numbers than the state itself.
+/// The start state is the last state.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Fst {
+    /// The transitions of state `s` are `offsets[s]..offsets[s + 1]`.
+    offsets: Vec<u32>,
+    finals: Vec<bool>,
+    labels: Vec<u8>,
+    targets: Vec<u32>,
+    outputs: Vec<u32>,
+}
+
+impl Fst {
+    fn start(&self) -> usize {
+        self.finals.len() - 1
+    }
+
+    fn transitions(&self, state: usize) -> Range<usize> {
+        self.offsets[state] as usize..self.offsets[state + 1] as usize
+    }
+
+    fn heap_size(&self) -> usize {
+        (self.offsets.len() + self.targets.len() + self.outputs.len()) * size_of::<u32>()
+            + self.finals.len()
+            + self.labels.len()
+    }
+
+    /// Get the rank of a word, `None` if the word is not accepted.
+    fn rank(&self, word: &[u8]) -> Option<usize> {
+        let mut state = self.start();
+        let mut rank = 0;
+        for label in word {
+            let transitions = self.transitions(state);
+            let transition =
+                transitions.start + self.labels[transitions.clone()].binary_search(label).ok()?;
+            rank += self.outputs[transition] as usize;
+            state = self.targets[transition] as usize;
+        }
+
+        if self.finals[state] {
+            Some(rank)
+        } else {
+            None
+        }
+    }
+
+    /// Get the bytes of the word with the given rank, `None` if there
+    /// is no such word.
+    fn word(&self, mut rank: usize) -> Option<Vec<u8>> {
+        let mut state = self.start();
+        let mut word = Vec::new();
+        loop {
+            if self.finals[state] && rank == 0 {
+                return Some(word);
+            }
+
+            // The outputs of a state's transitions increase with their
+            // labels. The word is reached through the last transition
+            // with an output that does not exceed the remaining rank.
+            let transitions = self.transitions(state);
+            let n_preceding = self.outputs[transitions.clone()]
+                .partition_point(|&output| output as usize <= rank);
+            if n_preceding == 0 {
+                return None;
+            }
+
+            let transition = transitions.start + n_preceding - 1;
+            rank -= self.outputs[transition] as usize;
+            word.push(self.labels[transition]);
+            state = self.targets[transition] as usize;
+        }
+    }
+
+    /// Call `f` with the output and the bytes of every accepted word.
+    ///
+    /// Words are enumerated in lexicographic byte order.
+    fn for_each_word<F>(&self, mut f: F) -> Result<()>
+    where
+        F: FnMut(u64, &[u8]) -> Result<()>,
+    {
+        let start = self.start();
+        let mut word = Vec::new();
+        if self.finals[start] {
+            f(0, &word)?;
+        }
+
+        // Depth-first search, the stack contains the state, its next
+        // transition, and the output of the path to the state.
+        let mut stack = vec![(start, self.offsets[start] as usize, 0u64)];
+        while let Some(top) = stack.last_mut() {
+            let (state, transition, output) = *top;
+            if transition == self.offsets[state + 1] as usize {
+                stack.pop();
+                word.pop();
+                continue;
+            }
+            top.1 += 1;
+
+            let target = self.targets[transition] as usize;
+            let output = output + u64::from(self.outputs[transition]);
+            word.push(self.labels[transition]);
+            if self.finals[target] {
+                f(output, &word)?;
+            }
+
+            stack.push((target, self.offsets[target] as usize, output));
+        }
+
+        Ok(())
+    }
+
+    /// Check that the transducer is well-formed and maps exactly
+    /// `n_words` valid UTF-8 words to their ranks.
+    fn check(&self, n_words: usize) -> Result<()> {
+        let n_states = self.finals.len();
+        if n_states == 0 {
+            return Err(ErrorKind::Format("Transducer does not have states".to_string()).into());
+        }
+
+        if self.offsets.len() != n_states + 1
+            || self.offsets[0] != 0
+            || self.offsets[n_states] as usize != self.labels.len()
+            || self.offsets.windows(2).any(|pair| pair[0] > pair[1])
+        {
+            return Err(ErrorKind::Format("Invalid transducer state offsets".to_string()).into());
+        }
+
+        for state in 0..n_states {
+            let transitions = self.transitions(state);
+
+            // Every state except the start state must lead to a word.
+            // Together with acyclicity, this bounds the enumeration below.
+            if transitions.is_empty() && !self.finals[state] && state != self.start() {
+                return Err(ErrorKind::Format(format!(
+                    "Transducer state {} does not accept any word",
+                    state
+                ))
+                .into());
+            }
+
+            if self.labels[transitions.clone()]
+                .windows(2)
+                .any(|pair| pair[0] >= pair[1])
+            {
+                return Err(ErrorKind::Format(format!(
+                    "Transitions of transducer state {} are not sorted",
+                    state
+                ))
+                .into());
+            }
+
+            if self.targets[transitions]
+                .iter()
+                .any(|&target| target as usize >= state)
+            {
+                return Err(ErrorKind::Format(format!(
+                    "Transducer state {} has a transition to a later state",
+                    state
+                ))
+                .into());
+            }
+        }
+
+        let mut n_accepted = 0;
+        self.for_each_word(|rank, word| {
+            if n_accepted == n_words {
+                return Err(ErrorKind::Format(format!(
+                    "Transducer accepts more than {} words",
+                    n_words
+                ))
+                .into());
+            }
+
+            if rank != n_accepted as u64 {
+                return Err(ErrorKind::Format(format!(
+                    "Transducer maps word {} to rank {}",
+                    n_accepted, rank
+                ))
+                .into());
+            }
+
+            str::from_utf8(word)
+                .map_err(|e| ErrorKind::Format(format!("Word contains invalid UTF-8: {}", e)))
+                .map_err(Error::from)?;
+
+            n_accepted += 1;
+
+            Ok(())
+        })?;
+
+        if n_accepted != n_words {
+            return Err(ErrorKind::Format(format!(
+                "Transducer accepts {} words, expected {}",
+                n_accepted, n_words
+            ))
+            .into());
+        }
+
+        Ok(())
+    }
+}
+
+/// State of which the transitions can still change.
+#[derive(Default)]
+struct UnfinishedState {
+    is_final: bool,
+
+    /// Transition labels and targets. The target of the last transition
+    /// is set when the state that it leads to is finished.
+    transitions: Vec<(u8, u32)>,
+}
+
+/// Builder for minimal acyclic transducers.
+///
+/// Words must be inserted in lexicographic byte order. When a word is
+/// inserted, the states on the path of the previous word that are not
+/// shared with the new word are finished. A finished state is replaced
+/// by an equivalent finished state if one exists, which makes the
+/// transducer minimal (Daciuk et al., 2000).
+struct FstBuilder {
+    fst: Fst,
+
+    /// The number of words accepted from each finished state.
+    counts: Vec<u32>,
+
+    /// Finished states, keyed by finality and transitions.
+    registry: HashMap<(bool, Vec<(u8, u32)>), u32>,
+
+    /// The path of the last inserted word.
+    unfinished: Vec<UnfinishedState>,
+
+    prev: Vec<u8>,
+}
+
+impl FstBuilder {
+    fn new() -> Self {
+        FstBuilder {
+            fst: Fst {
+                offsets: vec![0],
+                finals: Vec::new(),
+                labels: Vec::new(),
+                targets: Vec::new(),
+                outputs: Vec::new(),
+            },
+            counts: Vec::new(),
+            registry: HashMap::new(),
+            unfinished: vec![UnfinishedState::default()],
+            prev: Vec::new(),
+        }
+    }
+
+    fn insert(&mut self, word: &[u8]) {
+        let prefix_len = self
+            .prev
+            .iter()
+            .zip(word)
+            .take_while(|(prev, cur)| prev == cur)
+            .count();
+        self.finish_path(prefix_len + 1);
+
+        for &label in &word[prefix_len..] {
+            self.last_unfinished().transitions.push((label, 0));
+            self.unfinished.push(UnfinishedState::default());
+        }
+        self.last_unfinished().is_final = true;
+
+        self.prev.clear();
+        self.prev.extend_from_slice(word);
+    }
+
+    fn finish(mut self) -> Fst {
+        self.finish_path(1);
+
+        // The start state is added without looking up an equivalent
+        // state, so that it is always the last state.
+        let start = self.unfinished.pop().expect("Missing start state");
+        self.add_state(start.is_final, &start.transitions);
+
+        self.fst
+    }
+
+    /// Finish unfinished states until `depth` states remain.
+    fn finish_path(&mut self, depth: usize) {
+        while self.unfinished.len() > depth {
+            let state = self.unfinished.pop().expect("Missing unfinished state");
+            let id = self.finish_state(state);
+            self.last_unfinished()
+                .transitions
+                .last_mut()
+                .expect("Unfinished state without transitions")
+                .1 = id;
+        }
+    }
+
+    fn finish_state(&mut self, state: UnfinishedState) -> u32 {
+        let key = (state.is_final, state.transitions);
+        if let Some(&id) = self.registry.get(&key) {
+            return id;
+        }
+
+        let id = self.add_state(key.0, &key.1);
+        self.registry.insert(key, id);
+        id
+    }
+
+    fn add_state(&mut self, is_final: bool, transitions: &[(u8, u32)]) -> u32 {
+        let mut count = is_final as u32;
+        for &(label, target) in transitions {
+            self.fst.labels.push(label);
+            self.fst.targets.push(target);
+            self.fst.outputs.push(count);
+            count += self.counts[target as usize];
+        }
+
+        self.fst.finals.push(is_final);
+        self.fst.offsets.push(self.fst.labels.len() as u32);
+        self.counts.push(count);
+
+        (self.counts.len() - 1) as u32
+    }
+
+    fn last_unfinished(&mut self) -> &mut UnfinishedState {
+        self.unfinished
+            .last_mut()
+            .expect("Missing unfinished state")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::FstVocab;
+    use crate::chunks::vocab::{Vocab, WordIndex};
+
+    fn test_fst_vocab() -> FstVocab {
+        let words = vec![
+            "tops".to_owned(),
+            "tap".to_owned(),
+            "top".to_owned(),
+            "".to_owned(),
+            "taps".to_owned(),
+            "töpfe".to_owned(),
+        ];
+
+        FstVocab::new(words)
+    }
+
+    #[test]
+    fn fst_vocab_lookup() {
+        let vocab = test_fst_vocab();
+        for (idx, word) in ["tops", "tap", "top", "", "taps", "töpfe"]
+            .iter()
+            .enumerate()
+        {
+            assert_eq!(vocab.idx(word), Some(WordIndex::Word(idx)));
+        }
+
+        for word in &["t", "to", "tapss", "pots", "töpf"] {
+            assert_eq!(vocab.idx(word), None);
+        }
+
+        assert_eq!(vocab.words_len(), 6);
+        assert_eq!(
+            vocab.words(),
+            &["tops", "tap", "top", "", "taps", "töpfe"][..]
+        );
+    }
+
+    #[test]
+    fn fst_vocab_reconstructs_words_on_demand() {
+        let vocab = test_fst_vocab();
+        let words = ["tops", "tap", "top", "", "taps", "töpfe"];
+        for (idx, word) in words.iter().enumerate() {
+            assert_eq!(vocab.word(idx).as_deref(), Some(*word));
+        }
+        assert_eq!(vocab.word(6).as_deref(), None);
+
+        let iter_words = vocab.iter().collect::<Vec<_>>();
+        assert_eq!(iter_words.len(), words.len());
+        for ((word, idx), (check_idx, check_word)) in
+            iter_words.iter().zip(words.iter().enumerate())
+        {
+            assert_eq!(word, check_word);
+            assert_eq!(*idx, check_idx);
+        }
+
+        // The word list is not materialized.
+        assert!(vocab.words.get().is_none());
+    }
+
+    #[test]
+    fn fst_vocab_is_minimal() {
+        let vocab = FstVocab::new(vec![
+            "tap".to_owned(),
+            "taps".to_owned(),
+            "top".to_owned(),
+            "tops".to_owned(),
+        ]);
+
+        // t -> {a, o} -> p (final) -> s (final)
+        assert_eq!(vocab.fst.finals.len(), 5);
+        assert_eq!(vocab.fst.labels.len(), 5);
+    }
+}
@@ -0,0 +1,908 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::chunks::vocab::{
+    AliasVocab, BpeVocab, BucketSubwordVocab, ByteFallbackVocab, ExplicitSubwordVocab,
+    FastTextSubwordVocab, FrontCodedVocab, FstVocab, MmapVocab, NamespacedVocab, PieceType,
+    SentencePieceModel, SentencePieceVocab, SimpleVocab, SubwordVocab, Vocab, VocabWrap,
+    WordPieceVocab, NAMESPACE_SEPARATOR,
+};
+use crate::error::{Error, ErrorKind, Result};
+use crate::subword::{
+    BucketIndexer, ExplicitIndexer, FastTextIndexer, FinalfusionHashIndexer, Indexer,
+    StrWithCharLen,
+};
+
+/// JSON representation of vocabularies.
+///
+/// The JSON representation of a vocabulary is an object with the
+/// vocabulary type in the `type` field. Words and other units are
+/// stored as arrays in index order, so the index of a word is its
+/// position in the array. Subword vocabularies also store their
+/// n-gram configuration. For example:
+///
+/// ```
+/// use finalfusion_core::vocab::{SimpleVocab, VocabJson};
+///
+/// let vocab = SimpleVocab::new(vec!["hello".to_owned(), "world".to_owned()]);
+/// let json = vocab.to_json().unwrap();
+/// assert_eq!(json, r#"{"type":"SimpleVocab","words":["hello","world"]}"#);
+/// assert_eq!(SimpleVocab::from_json(&json).unwrap(), vocab);
+/// ```
+pub trait VocabJson
+where
+    Self: Sized,
+{
+    /// Serialize the vocabulary to JSON.
+    fn to_json(&self) -> Result<String>;
+
+    /// Deserialize a vocabulary from JSON.
+    ///
+    /// Returns an error when the JSON is not a vocabulary of this
+    /// type or when the vocabulary is invalid, e.g. because it has
+    /// duplicate words.
+    fn from_json(json: &str) -> Result<Self>;
+}
+
+// Variants are named after the vocabulary types.
+#[allow(clippy::enum_variant_names)]
+#[derive(Deserialize, Serialize)]
+#[serde(tag = "type")]
+enum JsonVocab {
+    SimpleVocab {
+        words: Vec<String>,
+    },
+    BucketSubwordVocab {
+        words: Vec<String>,
+        min_n: u32,
+        max_n: u32,
+        buckets_exp: u32,
+        #[serde(default = "default_bow", skip_serializing_if = "is_default_bow")]
+        bow: char,
+        #[serde(default = "default_eow", skip_serializing_if = "is_default_eow")]
+        eow: char,
+    },
+    FastTextSubwordVocab {
+        words: Vec<String>,
+        min_n: u32,
+        max_n: u32,
+        buckets: u32,
+        #[serde(default = "default_bow", skip_serializing_if = "is_default_bow")]
+        bow: char,
+        #[serde(default = "default_eow", skip_serializing_if = "is_default_eow")]
+        eow: char,
+    },
+    ExplicitSubwordVocab {
+        words: Vec<String>,
+        min_n: u32,
+        max_n: u32,
+        ngrams: Vec<JsonNGram>,
+        #[serde(default = "default_bow", skip_serializing_if = "is_default_bow")]
+        bow: char,
+        #[serde(default = "default_eow", skip_serializing_if = "is_default_eow")]
+        eow: char,
+    },
+    NamespacedVocab {
+        namespaces: Vec<JsonNamespace>,
+    },
+    FstVocab {
+        words: Vec<String>,
+    },
+    FrontCodedVocab {
+        words: Vec<String>,
+    },
+    MmapVocab {
+        words: Vec<String>,
+        #[serde(default)]
+        perfect_hash: bool,
+    },
+    BpeVocab {
+        words: Vec<String>,
+        units: Vec<String>,
+        merges: Vec<(String, String)>,
+        end_of_word: String,
+    },
+    WordPieceVocab {
+        words: Vec<String>,
+        pieces: Vec<String>,
+        continuation_prefix: String,
+    },
+    SentencePieceVocab {
+        model: String,
+        pieces: Vec<JsonPiece>,
+    },
+    ByteFallbackVocab {
+        inner: Box<JsonVocab>,
+    },
+    AliasVocab {
+        inner: Box<JsonVocab>,
+        aliases: Vec<JsonAlias>,
+    },
+}
+
+impl JsonVocab {
+    fn type_name(&self) -> &'static str {
+        use self::JsonVocab::*;
+
+        match self {
+            SimpleVocab { .. } => "SimpleVocab",
+            BucketSubwordVocab { .. } => "BucketSubwordVocab",
+            FastTextSubwordVocab { .. } => "FastTextSubwordVocab",
+            ExplicitSubwordVocab { .. } => "ExplicitSubwordVocab",
+            NamespacedVocab { .. } => "NamespacedVocab",
+            FstVocab { .. } => "FstVocab",
+            FrontCodedVocab { .. } => "FrontCodedVocab",
+            MmapVocab { .. } => "MmapVocab",
+            BpeVocab { .. } => "BpeVocab",
+            WordPieceVocab { .. } => "WordPieceVocab",
+            SentencePieceVocab { .. } => "SentencePieceVocab",
+            ByteFallbackVocab { .. } => "ByteFallbackVocab",
+            AliasVocab { .. } => "AliasVocab",
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+struct JsonNGram {
+    ngram: String,
+    index: u64,
+}
+
+#[derive(Deserialize, Serialize)]
+struct JsonAlias {
+    alias: String,
+    index: usize,
+}
+
+#[derive(Deserialize, Serialize)]
+struct JsonNamespace {
+    namespace: String,
+    words: Vec<String>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct JsonPiece {
+    piece: String,
+    score: f32,
+    #[serde(rename = "type")]
+    piece_type: String,
+}
+
+fn to_json_string(vocab: &JsonVocab) -> Result<String> {
+    serde_json::to_string(vocab).map_err(|e| {
+        ErrorKind::Format(format!("Cannot serialize vocabulary to JSON: {}", e)).into()
+    })
+}
+
+fn from_json_str(json: &str) -> Result<JsonVocab> {
+    serde_json::from_str(json)
+        .map_err(|e| ErrorKind::Format(format!("Cannot parse vocabulary JSON: {}", e)).into())
+}
+
+fn unexpected_type(expected: &str, vocab: &JsonVocab) -> Error {
+    ErrorKind::Format(format!(
+        "Expected vocabulary type {}, got: {}",
+        expected,
+        vocab.type_name()
+    ))
+    .into()
+}
+
+// Duplicates would result in a panic in the vocab constructors.
+fn check_unique<'a>(items: impl IntoIterator<Item = &'a String>, what: &str) -> Result<()> {
+    let mut seen = HashSet::new();
+    for item in items {
+        if !seen.insert(item) {
+            return Err(ErrorKind::Format(format!("Duplicate {}: {}", what, item)).into());
+        }
+    }
+
+    Ok(())
+}
+
+fn piece_type_name(piece_type: PieceType) -> &'static str {
+    use self::PieceType::*;
+
+    match piece_type {
+        Normal => "normal",
+        Unknown => "unknown",
+        Control => "control",
+        UserDefined => "user_defined",
+        Unused => "unused",
+        Byte => "byte",
+    }
+}
+
+fn piece_type_from_name(name: &str) -> Result<PieceType> {
+    use self::PieceType::*;
+
+    match name {
+        "normal" => Ok(Normal),
+        "unknown" => Ok(Unknown),
+        "control" => Ok(Control),
+        "user_defined" => Ok(UserDefined),
+        "unused" => Ok(Unused),
+        "byte" => Ok(Byte),
+        _ => Err(ErrorKind::Format(format!("Unknown piece type: {}", name)).into()),
+    }
+}
+
+fn default_bow() -> char {
+    BucketSubwordVocab::DEFAULT_BOW
+}
+
+fn default_eow() -> char {
+    BucketSubwordVocab::DEFAULT_EOW
+}
+
+fn is_default_bow(bow: &char) -> bool {
+    *bow == BucketSubwordVocab::DEFAULT_BOW
+}
+
+fn is_default_eow(eow: &char) -> bool {
+    *eow == BucketSubwordVocab::DEFAULT_EOW
+}
+
+impl From<&SimpleVocab> for JsonVocab {
+    fn from(vocab: &SimpleVocab) -> Self {
+        JsonVocab::SimpleVocab {
+            words: vocab.words().to_vec(),
+        }
+    }
+}
+
+impl From<&BucketSubwordVocab> for JsonVocab {
+    fn from(vocab: &BucketSubwordVocab) -> Self {
+        JsonVocab::BucketSubwordVocab {
+            words: vocab.words().to_vec(),
+            min_n: vocab.min_n(),
+            max_n: vocab.max_n(),
+            buckets_exp: vocab.indexer().buckets() as u32,
+            bow: vocab.bow(),
+            eow: vocab.eow(),
+        }
+    }
+}
+
+impl From<&FastTextSubwordVocab> for JsonVocab {
+    fn from(vocab: &FastTextSubwordVocab) -> Self {
+        JsonVocab::FastTextSubwordVocab {
+            words: vocab.words().to_vec(),
+            min_n: vocab.min_n(),
+            max_n: vocab.max_n(),
+            buckets: vocab.indexer().buckets() as u32,
+            bow: vocab.bow(),
+            eow: vocab.eow(),
+        }
+    }
+}
+
+impl From<&ExplicitSubwordVocab> for JsonVocab {
+    fn from(vocab: &ExplicitSubwordVocab) -> Self {
+        let indexer = vocab.indexer();
+        JsonVocab::ExplicitSubwordVocab {
+            words: vocab.words().to_vec(),
+            min_n: vocab.min_n(),
+            max_n: vocab.max_n(),
+            ngrams: indexer
+                .ngrams()
+                .iter()
+                .map(|ngram| JsonNGram {
+                    ngram: ngram.clone(),
+                    index: indexer
+                        .index_ngram(&StrWithCharLen::new(ngram))
+                        .expect("Indexer does not index its own n-gram"),
+                })
+                .collect(),
+            bow: vocab.bow(),
+            eow: vocab.eow(),
+        }
+    }
+}
+
+impl From<&NamespacedVocab> for JsonVocab {
+    fn from(vocab: &NamespacedVocab) -> Self {
+        let namespaces = vocab
+            .namespaces()
+            .iter()
+            .map(|namespace| {
+                let prefix_len = namespace.len() + NAMESPACE_SEPARATOR.len();
+                JsonNamespace {
+                    namespace: namespace.clone(),
+                    words: vocab
+                        .namespace_words(namespace)
+                        .expect("Unknown namespace")
+                        .iter()
+                        .map(|word| word[prefix_len..].to_owned())
+                        .collect(),
+                }
+            })
+            .collect();
+
+        JsonVocab::NamespacedVocab { namespaces }
+    }
+}
+
+impl From<&FstVocab> for JsonVocab {
+    fn from(vocab: &FstVocab) -> Self {
+        JsonVocab::FstVocab {
+            words: vocab.words().to_vec(),
+        }
+    }
+}
+
+impl From<&FrontCodedVocab> for JsonVocab {
+    fn from(vocab: &FrontCodedVocab) -> Self {
+        JsonVocab::FrontCodedVocab {
+            words: vocab.words().to_vec(),
+        }
+    }
+}
+
+impl From<&MmapVocab> for JsonVocab {
+    fn from(vocab: &MmapVocab) -> Self {
+        JsonVocab::MmapVocab {
+            words: vocab.iter().map(|(word, _)| word.into_owned()).collect(),
+            perfect_hash: vocab.has_perfect_hash(),
+        }
+    }
+}
+
+impl From<&BpeVocab> for JsonVocab {
+    fn from(vocab: &BpeVocab) -> Self {
+        JsonVocab::BpeVocab {
+            words: vocab.words().to_vec(),
+            units: vocab.units().to_vec(),
+            merges: vocab
+                .merges()
+                .into_iter()
+                .map(|(left, right)| (left.to_owned(), right.to_owned()))
+                .collect(),
+            end_of_word: vocab.end_of_word().to_owned(),
+        }
+    }
+}
+
+impl From<&WordPieceVocab> for JsonVocab {
+    fn from(vocab: &WordPieceVocab) -> Self {
+        JsonVocab::WordPieceVocab {
+            words: vocab.words().to_vec(),
+            pieces: vocab.pieces().to_vec(),
+            continuation_prefix: vocab.continuation_prefix().to_owned(),
+        }
+    }
+}
+
+impl From<&SentencePieceVocab> for JsonVocab {
+    fn from(vocab: &SentencePieceVocab) -> Self {
+        let model = match vocab.model() {
+            SentencePieceModel::Unigram => "unigram",
+            SentencePieceModel::Bpe => "bpe",
+        };
+
+        JsonVocab::SentencePieceVocab {
+            model: model.to_owned(),
+            pieces: vocab
+                .words()
+                .iter()
+                .zip(vocab.scores())
+                .zip(vocab.types())
+                .map(|((piece, &score), &piece_type)| JsonPiece {
+                    piece: piece.clone(),
+                    score,
+                    piece_type: piece_type_name(piece_type).to_owned(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl<V> From<&ByteFallbackVocab<V>> for JsonVocab
+where
+    for<'a> &'a V: Into<JsonVocab>,
+{
+    fn from(vocab: &ByteFallbackVocab<V>) -> Self {
+        JsonVocab::ByteFallbackVocab {
+            inner: Box::new(vocab.inner().into()),
+        }
+    }
+}
+
+impl<V> From<&AliasVocab<V>> for JsonVocab
+where
+    for<'a> &'a V: Into<JsonVocab>,
+{
+    fn from(vocab: &AliasVocab<V>) -> Self {
+        JsonVocab::AliasVocab {
+            inner: Box::new(vocab.inner().into()),
+            aliases: vocab
+                .aliases()
+                .map(|(alias, index)| JsonAlias {
+                    alias: alias.to_owned(),
+                    index,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<&VocabWrap> for JsonVocab {
+    fn from(vocab: &VocabWrap) -> Self {
+        match vocab {
+            VocabWrap::SimpleVocab(inner) => inner.into(),
+            VocabWrap::ExplicitSubwordVocab(inner) => inner.into(),
+            VocabWrap::FastTextSubwordVocab(inner) => inner.into(),
+            VocabWrap::BucketSubwordVocab(inner) => inner.into(),
+            VocabWrap::NamespacedVocab(inner) => inner.into(),
+            VocabWrap::FstVocab(inner) => inner.into(),
+            VocabWrap::FrontCodedVocab(inner) => inner.into(),
+            VocabWrap::MmapVocab(inner) => inner.into(),
+            VocabWrap::BpeVocab(inner) => inner.into(),
+            VocabWrap::WordPieceVocab(inner) => inner.into(),
+            VocabWrap::SentencePieceVocab(inner) => inner.into(),
+            VocabWrap::ByteFallbackVocab(inner) => inner.as_ref().into(),
+            VocabWrap::AliasVocab(inner) => inner.as_ref().into(),
+        }
+    }
+}
+
+/// Conversion from the JSON representation.
+trait TryFromJsonVocab: Sized {
+    fn try_from_json_vocab(vocab: JsonVocab) -> Result<Self>;
+}
+
+impl TryFromJsonVocab for VocabWrap {
+    fn try_from_json_vocab(vocab: JsonVocab) -> Result<Self> {
+        Ok(match vocab {
+            vocab @ JsonVocab::SimpleVocab { .. } => {
+                SimpleVocab::try_from_json_vocab(vocab)?.into()
+            }
+            vocab @ JsonVocab::BucketSubwordVocab { .. } => {
+                BucketSubwordVocab::try_from_json_vocab(vocab)?.into()
+            }
+            vocab @ JsonVocab::FastTextSubwordVocab { .. } => {
+                FastTextSubwordVocab::try_from_json_vocab(vocab)?.into()
+            }
+            vocab @ JsonVocab::ExplicitSubwordVocab { .. } => {
+                ExplicitSubwordVocab::try_from_json_vocab(vocab)?.into()
+            }
+            vocab @ JsonVocab::NamespacedVocab { .. } => {
+                NamespacedVocab::try_from_json_vocab(vocab)?.into()
+            }
+            vocab @ JsonVocab::FstVocab { .. } => FstVocab::try_from_json_vocab(vocab)?.into(),
+            vocab @ JsonVocab::FrontCodedVocab { .. } => {
+                FrontCodedVocab::try_from_json_vocab(vocab)?.into()
+            }
+            vocab @ JsonVocab::MmapVocab { .. } => MmapVocab::try_from_json_vocab(vocab)?.into(),
+            vocab @ JsonVocab::BpeVocab { .. } => BpeVocab::try_from_json_vocab(vocab)?.into(),
+            vocab @ JsonVocab::WordPieceVocab { .. } => {
+                WordPieceVocab::try_from_json_vocab(vocab)?.into()
+            }
+            vocab @ JsonVocab::SentencePieceVocab { .. } => {
+                SentencePieceVocab::try_from_json_vocab(vocab)?.into()
+            }
+            vocab @ JsonVocab::ByteFallbackVocab { .. } => {
+                ByteFallbackVocab::<VocabWrap>::try_from_json_vocab(vocab)?.into()
+            }
+            vocab @ JsonVocab::AliasVocab { .. } => {
+                AliasVocab::<VocabWrap>::try_from_json_vocab(vocab)?.into()
+            }
+        })
+    }
+}
+
+impl TryFromJsonVocab for SimpleVocab {
+    fn try_from_json_vocab(vocab: JsonVocab) -> Result<Self> {
+        match vocab {
+            JsonVocab::SimpleVocab { words } => {
+                check_unique(&words, "word")?;
+                Ok(SimpleVocab::new(words))
+            }
+            vocab => Err(unexpected_type("SimpleVocab", &vocab)),
+        }
+    }
+}
+
+impl TryFromJsonVocab for BucketSubwordVocab {
+    fn try_from_json_vocab(vocab: JsonVocab) -> Result<Self> {
+        match vocab {
+            JsonVocab::BucketSubwordVocab {
+                words,
+                min_n,
+                max_n,
+                buckets_exp,
+                bow,
+                eow,
+            } => {
+                check_unique(&words, "word")?;
+                if buckets_exp > 64 {
+                    return Err(ErrorKind::Format(format!(
+                        "The bucket exponent cannot be larger than 64, got: {}",
+                        buckets_exp
+                    ))
+                    .into());
+                }
+
+                Ok(SubwordVocab::new(
+                    words,
+                    min_n,
+                    max_n,
+                    FinalfusionHashIndexer::new(buckets_exp as usize),
+                )
+                .with_markers(bow, eow))
+            }
+            vocab => Err(unexpected_type("BucketSubwordVocab", &vocab)),
+        }
+    }
+}
+
+impl TryFromJsonVocab for FastTextSubwordVocab {
+    fn try_from_json_vocab(vocab: JsonVocab) -> Result<Self> {
+        match vocab {
+            JsonVocab::FastTextSubwordVocab {
+                words,
+                min_n,
+                max_n,
+                buckets,
+                bow,
+                eow,
+            } => {
+                check_unique(&words, "word")?;
+                let indexer = FastTextIndexer::new(buckets as usize);
+                Ok(SubwordVocab::new(words, min_n, max_n, indexer).with_markers(bow, eow))
+            }
+            vocab => Err(unexpected_type("FastTextSubwordVocab", &vocab)),
+        }
+    }
+}
+
+impl TryFromJsonVocab for ExplicitSubwordVocab {
+    fn try_from_json_vocab(vocab: JsonVocab) -> Result<Self> {
+        match vocab {
+            JsonVocab::ExplicitSubwordVocab {
+                words,
+                min_n,
+                max_n,
+                ngrams,
+                bow,
+                eow,
+            } => {
+                check_unique(&words, "word")?;
+                check_unique(ngrams.iter().map(|ngram| &ngram.ngram), "n-gram")?;
+                let indexer = ExplicitIndexer::new_with_indices(
+                    ngrams
+                        .into_iter()
+                        .map(|ngram| (ngram.ngram, ngram.index))
+                        .collect(),
+                );
+
+                Ok(SubwordVocab::new(words, min_n, max_n, indexer).with_markers(bow, eow))
+            }
+            vocab => Err(unexpected_type("ExplicitSubwordVocab", &vocab)),
+        }
+    }
+}
+
+impl TryFromJsonVocab for NamespacedVocab {
+    fn try_from_json_vocab(vocab: JsonVocab) -> Result<Self> {
+        match vocab {
+            JsonVocab::NamespacedVocab { namespaces } => {
+                check_unique(namespaces.iter().map(|ns| &ns.namespace), "namespace")?;
+                for namespace in &namespaces {
+                    if namespace.namespace.contains(NAMESPACE_SEPARATOR) {
+                        return Err(ErrorKind::Format(format!(
+                            "Namespace contains the namespace separator: {}",
+                            namespace.namespace
+                        ))
+                        .into());
+                    }
+                    check_unique(&namespace.words, "word")?;
+                }
+
+                Ok(NamespacedVocab::new(
+                    namespaces.into_iter().map(|ns| (ns.namespace, ns.words)),
+                ))
+            }
+            vocab => Err(unexpected_type("NamespacedVocab", &vocab)),
+        }
+    }
+}
+
+impl TryFromJsonVocab for FstVocab {
+    fn try_from_json_vocab(vocab: JsonVocab) -> Result<Self> {
+        match vocab {
+            JsonVocab::FstVocab { words } => {
+                check_unique(&words, "word")?;
+                if words.len() >= u32::MAX as usize {
+                    return Err(ErrorKind::Format(format!(
+                        "FstVocab supports at most {} words",
+                        u32::MAX - 1
+                    ))
+                    .into());
+                }
+
+                Ok(FstVocab::new(words))
+            }
+            vocab => Err(unexpected_type("FstVocab", &vocab)),
+        }
+    }
+}
+
+impl TryFromJsonVocab for FrontCodedVocab {
+    fn try_from_json_vocab(vocab: JsonVocab) -> Result<Self> {
+        match vocab {
+            JsonVocab::FrontCodedVocab { words } => {
+                check_unique(&words, "word")?;
+                Ok(FrontCodedVocab::new(words))
+            }
+            vocab => Err(unexpected_type("FrontCodedVocab", &vocab)),
+        }
+    }
+}
+
+impl TryFromJsonVocab for MmapVocab {
+    fn try_from_json_vocab(vocab: JsonVocab) -> Result<Self> {
+        match vocab {
+            JsonVocab::MmapVocab {
+                words,
+                perfect_hash,
+            } => {
+                check_unique(&words, "word")?;
+                if perfect_hash && words.len() > u32::MAX as usize {
+                    return Err(ErrorKind::Format(format!(
+                        "MmapVocab supports perfect hashing of at most {} words",
+                        u32::MAX
+                    ))
+                    .into());
+                }
+
+                if perfect_hash {
+                    Ok(MmapVocab::new_with_perfect_hash(words))
+                } else {
+                    Ok(MmapVocab::new(words))
+                }
+            }
+            vocab => Err(unexpected_type("MmapVocab", &vocab)),
+        }
+    }
+}
+
+impl TryFromJsonVocab for BpeVocab {
+    fn try_from_json_vocab(vocab: JsonVocab) -> Result<Self> {
+        match vocab {
+            JsonVocab::BpeVocab {
+                words,
+                units,
+                merges,
+                end_of_word,
+            } => {
+                check_unique(&words, "word")?;
+                check_unique(&units, "unit")?;
+                Ok(BpeVocab::new(words, units, merges, end_of_word))
+            }
+            vocab => Err(unexpected_type("BpeVocab", &vocab)),
+        }
+    }
+}
+
+impl TryFromJsonVocab for WordPieceVocab {
+    fn try_from_json_vocab(vocab: JsonVocab) -> Result<Self> {
+        match vocab {
+            JsonVocab::WordPieceVocab {
+                words,
+                pieces,
+                continuation_prefix,
+            } => {
+                check_unique(&words, "word")?;
+                check_unique(&pieces, "piece")?;
+                Ok(WordPieceVocab::new(words, pieces, continuation_prefix))
+            }
+            vocab => Err(unexpected_type("WordPieceVocab", &vocab)),
+        }
+    }
+}
+
+impl TryFromJsonVocab for SentencePieceVocab {
+    fn try_from_json_vocab(vocab: JsonVocab) -> Result<Self> {
+        match vocab {
+            JsonVocab::SentencePieceVocab { model, pieces } => {
+                let model = match model.as_str() {
+                    "unigram" => SentencePieceModel::Unigram,
+                    "bpe" => SentencePieceModel::Bpe,
+                    _ => {
+                        return Err(ErrorKind::Format(format!(
+                            "Unknown SentencePiece model type: {}",
+                            model
+                        ))
+                        .into())
+                    }
+                };
+                check_unique(pieces.iter().map(|piece| &piece.piece), "piece")?;
+
+                let types = pieces
+                    .iter()
+                    .map(|piece| piece_type_from_name(&piece.piece_type))
+                    .collect::<Result<Vec<_>>>()?;
+                let scores = pieces.iter().map(|piece| piece.score).collect::<Vec<_>>();
+                let pieces = pieces
+                    .into_iter()
+                    .map(|piece| piece.piece)
+                    .collect::<Vec<_>>();
+
+                Ok(SentencePieceVocab::new(pieces, scores, types, model))
+            }
+            vocab => Err(unexpected_type("SentencePieceVocab", &vocab)),
+        }
+    }
+}
+
+impl<V> TryFromJsonVocab for ByteFallbackVocab<V>
+where
+    V: TryFromJsonVocab,
+{
+    fn try_from_json_vocab(vocab: JsonVocab) -> Result<Self> {
+        match vocab {
+            JsonVocab::ByteFallbackVocab { inner } => {
+                Ok(ByteFallbackVocab::new(V::try_from_json_vocab(*inner)?))
+            }
+            vocab => Err(unexpected_type("ByteFallbackVocab", &vocab)),
+        }
+    }
+}
+
+impl<V> TryFromJsonVocab for AliasVocab<V>
+where
+    V: TryFromJsonVocab + Vocab,
+{
+    fn try_from_json_vocab(vocab: JsonVocab) -> Result<Self> {
+        match vocab {
+            JsonVocab::AliasVocab { inner, aliases } => AliasVocab::from_aliases(
+                V::try_from_json_vocab(*inner)?,
+                aliases
+                    .into_iter()
+                    .map(|alias| (alias.alias, alias.index))
+                    .collect(),
+            ),
+            vocab => Err(unexpected_type("AliasVocab", &vocab)),
+        }
+    }
+}
+
+macro_rules! impl_vocab_json {
+    ($vocab:ty) => {
+        impl VocabJson for $vocab {
+            fn to_json(&self) -> Result<String> {
+                to_json_string(&self.into())
+            }
+
+            fn from_json(json: &str) -> Result<Self> {
+                Self::try_from_json_vocab(from_json_str(json)?)
+            }
+        }
+    };
+}
+
+impl_vocab_json!(SimpleVocab);
+impl_vocab_json!(BucketSubwordVocab);
+impl_vocab_json!(FastTextSubwordVocab);
+impl_vocab_json!(ExplicitSubwordVocab);
+impl_vocab_json!(NamespacedVocab);
+impl_vocab_json!(FstVocab);
+impl_vocab_json!(FrontCodedVocab);
+impl_vocab_json!(MmapVocab);
+impl_vocab_json!(BpeVocab);
+impl_vocab_json!(WordPieceVocab);
+impl_vocab_json!(SentencePieceVocab);
+impl_vocab_json!(ByteFallbackVocab<SimpleVocab>);
+impl_vocab_json!(ByteFallbackVocab<BucketSubwordVocab>);
+impl_vocab_json!(ByteFallbackVocab<FastTextSubwordVocab>);
+impl_vocab_json!(ByteFallbackVocab<ExplicitSubwordVocab>);
+impl_vocab_json!(ByteFallbackVocab<VocabWrap>);
+impl_vocab_json!(AliasVocab<SimpleVocab>);
+impl_vocab_json!(AliasVocab<VocabWrap>);
+impl_vocab_json!(VocabWrap);
+
+#[cfg(test)]
+mod tests {
+    use super::VocabJson;
+    use crate::chunks::vocab::{
+        AliasVocab, BpeVocab, BucketSubwordVocab, ByteFallbackVocab, ExplicitSubwordVocab,
+        FastTextSubwordVocab, FrontCodedVocab, FstVocab, MmapVocab, NamespacedVocab, PieceType,
+        SentencePieceModel, SentencePieceVocab, SimpleVocab, SubwordVocab, VocabWrap,
+        WordPieceVocab,
+    };
+    use crate::subword::FastTextIndexer;
+    use crate::subword::{BucketIndexer, ExplicitIndexer, FinalfusionHashIndexer};
+
+    fn strings(strs: &[&str]) -> Vec<String> {
+        strs.iter().map(|s| (*s).to_owned()).collect()
+    }
+
+    fn test_vocabs() -> Vec<VocabWrap> {
+        let words = strings(&["this", "is", "a", "test"]);
+        vec![
+            SimpleVocab::new(words.clone()).into(),
+            BucketSubwordVocab::new(words.clone(), 3, 6, FinalfusionHashIndexer::new(10)).into(),
+            FastTextSubwordVocab::new(words.clone(), 1, 5, FastTextIndexer::new(20)).into(),
+            FastTextSubwordVocab::new(words.clone(), 1, 5, FastTextIndexer::new(20))
+                .with_markers('^', '$')
+                .into(),
+            ExplicitSubwordVocab::new(
+                words.clone(),
+                2,
+                3,
+                ExplicitIndexer::new_with_indices(vec![
+                    ("is>".to_owned(), 3),
+                    ("is".to_owned(), 5),
+                    ("<t".to_owned(), 3),
+                ]),
+            )
+            .into(),
+            NamespacedVocab::new(vec![
+                ("entity", strings(&["a", "b"])),
+                ("rel", strings(&["a"])),
+            ])
+            .into(),
+            FstVocab::new(words.clone()).into(),
+            FrontCodedVocab::new(words.clone()).into(),
+            MmapVocab::new(words.clone()).into(),
+            MmapVocab::new_with_perfect_hash(words.clone()).into(),
+            BpeVocab::new(
+                words.clone(),
+                strings(&["th", "is</w>"]),
+                vec![("t".to_owned(), "h".to_owned())],
+                "</w>",
+            )
+            .into(),
+            WordPieceVocab::new(
+                words.clone(),
+                strings(&["th", "##is"]),
+                WordPieceVocab::CONTINUATION_PREFIX,
+            )
+            .into(),
+            SentencePieceVocab::new(
+                strings(&["<unk>", "▁th", "is"]),
+                vec![0., -1., -2.5],
+                vec![PieceType::Unknown, PieceType::Normal, PieceType::Normal],
+                SentencePieceModel::Bpe,
+            )
+            .into(),
+            ByteFallbackVocab::new(SimpleVocab::new(words.clone())).into(),
+            AliasVocab::new(SimpleVocab::new(words), vec![("these".to_owned(), 0)]).into(),
+        ]
+    }
+
+    #[test]
+    fn vocab_json_roundtrip() {
+        for vocab in test_vocabs() {
+            let json = vocab.to_json().unwrap();
+            assert_eq!(VocabWrap::from_json(&json).unwrap(), vocab);
+        }
+    }
+
+    #[test]
+    fn vocab_json_format() {
+        let vocab: SubwordVocab<_> = SubwordVocab::new(
+            strings(&["a"]),
+            2,
+            3,
+            ExplicitIndexer::new(strings(&["<a", "a>"])),
+        );
+        assert_eq!(
+            vocab.to_json().unwrap(),
+            r#"{"type":"ExplicitSubwordVocab","words":["a"],"min_n":2,"max_n":3,"ngrams":[{"ngram":"<a","index":0},{"ngram":"a>","index":1}]}"#
+        );
+    }
+
+    #[test]
+    fn vocab_json_rejects_invalid_vocabs() {
+        assert!(SimpleVocab::from_json(r#"{"type":"SimpleVocab","words":["a","a"]}"#).is_err());
+        assert!(SimpleVocab::from_json(r#"{"type":"FstVocab","words":["a"]}"#).is_err());
+        assert!(VocabWrap::from_json(r#"{"type":"UnknownVocab","words":["a"]}"#).is_err());
+        assert!(NamespacedVocab::from_json(
+            r#"{"type":"NamespacedVocab","namespaces":[{"namespace":"a::b","words":[]}]}"#
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,335 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::mem::size_of;
+
+use crate::chunks::memory::{
+    string_map_heap_size, strings_heap_size, MemoryFootprint, MemoryUsage,
+};
+use crate::chunks::vocab::{RetainWords, Vocab, VocabIter, WordIndex};
+use crate::error::Result;
+use crate::normalization::{NormalizeVocab, WordNormalization};
+
+/// Format of language-tagged words.
+///
+/// A tagged word consists of the prefix, the language, the separator,
+/// and the word, in that order. The default format tags words as in
+/// `en:dog`. ConceptNet Numberbatch tags words as in `/c/en/dog`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LanguageTagFormat {
+    prefix: String,
+    separator: String,
+}
+
+impl LanguageTagFormat {
+    /// Construct a tag format from a prefix and a separator.
+    ///
+    /// Panics when the separator is empty.
+    pub fn new(prefix: impl Into<String>, separator: impl Into<String>) -> Self {
+        let separator = separator.into();
+        assert!(!separator.is_empty(), "Language separator is empty");
+
+        LanguageTagFormat {
+            prefix: prefix.into(),
+            separator,
+        }
+    }
+
+    /// Tag format of ConceptNet Numberbatch, e.g. `/c/en/dog`.
+    pub fn conceptnet() -> Self {
+        LanguageTagFormat::new("/c/", "/")
+    }
+
+    /// Get the prefix of tagged words.
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    /// Get the separator between the language and the word.
+    pub fn separator(&self) -> &str {
+        &self.separator
+    }
+
+    /// Tag a word with a language.
+    pub fn tag(&self, language: &str, word: &str) -> String {
+        let mut tagged = String::with_capacity(
+            self.prefix.len() + language.len() + self.separator.len() + word.len(),
+        );
+        tagged.push_str(&self.prefix);
+        tagged.push_str(language);
+        tagged.push_str(&self.separator);
+        tagged.push_str(word);
+        tagged
+    }
+
+    /// Split a tagged word in its language and the word.
+    ///
+    /// Returns `None` when the word is not tagged or the language is
+    /// empty.
+    pub fn split<'a>(&self, tagged: &'a str) -> Option<(&'a str, &'a str)> {
+        let rest = tagged.strip_prefix(self.prefix.as_str())?;
+        let sep_pos = rest.find(self.separator.as_str())?;
+        if sep_pos == 0 {
+            return None;
+        }
+
+        Some((&rest[..sep_pos], &rest[sep_pos + self.separator.len()..]))
+    }
+}
+
+impl Default for LanguageTagFormat {
+    fn default() -> Self {
+        LanguageTagFormat::new("", ":")
+    }
+}
+
+/// Vocabulary of language-tagged words.
+///
+/// `LanguageVocab` wraps a vocabulary of which the words are tagged
+/// with their language, as in multilingual embeddings where `en:dog`
+/// and `nl:hond` share one embedding space. Words can be looked up by
+/// language with `lookup` and the words of a language can be iterated
+/// with `language_words`. Words that are not tagged do not belong to
+/// any language, but can still be looked up with `Vocab::idx`.
+///
+/// `LanguageVocab` does not have its own chunk, it is serialized as
+/// the wrapped vocabulary. Reading a `LanguageVocab` uses the default
+/// tag format; vocabularies with another format can be wrapped with
+/// `LanguageVocab::with_format` after reading.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LanguageVocab<V> {
+    inner: V,
+    format: LanguageTagFormat,
+    languages: Vec<String>,
+    language_indices: HashMap<String, usize>,
+    word_indices: Vec<Vec<usize>>,
+}
+
+impl<V> LanguageVocab<V>
+where
+    V: Vocab,
+{
+    /// Construct a language vocabulary with the default tag format.
+    pub fn new(inner: V) -> Self {
+        Self::with_format(inner, LanguageTagFormat::default())
+    }
+
+    /// Construct a language vocabulary with the given tag format.
+    ///
+    /// Languages are ordered by their first occurrence in the
+    /// vocabulary.
+    pub fn with_format(inner: V, format: LanguageTagFormat) -> Self {
+        let mut vocab = LanguageVocab {
+            inner,
+            format,
+            languages: Vec::new(),
+            language_indices: HashMap::new(),
+            word_indices: Vec::new(),
+        };
+        vocab.index_languages();
+        vocab
+    }
+
+    fn index_languages(&mut self) {
+        let mut languages = Vec::new();
+        let mut language_indices = HashMap::new();
+        let mut word_indices: Vec<Vec<usize>> = Vec::new();
+        for (word, idx) in self.inner.iter() {
+            let language = match self.format.split(&word) {
+                Some((language, _)) => language,
+                None => continue,
+            };
+
+            let lang_idx = *language_indices
+                .entry(language.to_owned())
+                .or_insert_with(|| {
+                    languages.push(language.to_owned());
+                    word_indices.push(Vec::new());
+                    languages.len() - 1
+                });
+            word_indices[lang_idx].push(idx);
+        }
+
+        self.languages = languages;
+        self.language_indices = language_indices;
+        self.word_indices = word_indices;
+    }
+
+    /// Look up a word in a language.
+    pub fn lookup(&self, language: &str, word: &str) -> Option<WordIndex> {
+        self.inner.idx(&self.format.tag(language, word))
+    }
+
+    /// Get the words of a language with their indices.
+    ///
+    /// The words are returned without their language tags. Returns
+    /// `None` when the vocabulary has no words of the language.
+    pub fn language_words(&self, language: &str) -> Option<VocabIter<'_>> {
+        let lang_idx = *self.language_indices.get(language)?;
+        Some(Box::new(self.word_indices[lang_idx].iter().filter_map(
+            move |&idx| {
+                let word = match self.inner.word(idx)? {
+                    Cow::Borrowed(tagged) => Cow::Borrowed(self.format.split(tagged)?.1),
+                    Cow::Owned(tagged) => Cow::Owned(self.format.split(&tagged)?.1.to_owned()),
+                };
+                Some((word, idx))
+            },
+        )))
+    }
+
+    /// Get the number of words of a language.
+    pub fn language_len(&self, language: &str) -> usize {
+        self.language_indices
+            .get(language)
+            .map(|&lang_idx| self.word_indices[lang_idx].len())
+            .unwrap_or(0)
+    }
+}
+
+impl<V> LanguageVocab<V> {
+    /// Get the tag format.
+    pub fn format(&self) -> &LanguageTagFormat {
+        &self.format
+    }
+
+    /// Get the languages of the vocabulary.
+    pub fn languages(&self) -> &[String] {
+        &self.languages
+    }
+
+    /// Get the wrapped vocabulary.
+    pub fn inner(&self) -> &V {
+        &self.inner
+    }
+
+    /// Unwrap the wrapped vocabulary.
+    pub fn into_inner(self) -> V {
+        self.inner
+    }
+}
+
+impl<V> Vocab for LanguageVocab<V>
+where
+    V: Vocab,
+{
+    fn idx(&self, word: &str) -> Option<WordIndex> {
+        self.inner.idx(word)
+    }
+
+    fn words_len(&self) -> usize {
+        self.inner.words_len()
+    }
+
+    fn vocab_len(&self) -> usize {
+        self.inner.vocab_len()
+    }
+
+    fn words(&self) -> &[String] {
+        self.inner.words()
+    }
+
+    fn word(&self, idx: usize) -> Option<Cow<'_, str>> {
+        self.inner.word(idx)
+    }
+
+    fn iter(&self) -> VocabIter<'_> {
+        self.inner.iter()
+    }
+}
+
+impl<V> RetainWords for LanguageVocab<V>
+where
+    V: RetainWords,
+{
+    fn retain_indices(&self, indices: &[usize]) -> (Self, Vec<usize>) {
+        let (inner, rows) = self.inner.retain_indices(indices);
+        (LanguageVocab::with_format(inner, self.format.clone()), rows)
+    }
+}
+
+impl<V> NormalizeVocab for LanguageVocab<V>
+where
+    V: NormalizeVocab + Vocab,
+{
+    fn normalize_vocab(&mut self, normalization: &dyn WordNormalization) -> Result<()> {
+        self.inner.normalize_vocab(normalization)?;
+        self.index_languages();
+        Ok(())
+    }
+}
+
+impl<V> MemoryUsage for LanguageVocab<V>
+where
+    V: MemoryUsage,
+{
+    fn memory_usage(&self) -> MemoryFootprint {
+        let indices_size = self.word_indices.capacity() * size_of::<Vec<usize>>()
+            + self
+                .word_indices
+                .iter()
+                .map(|indices| indices.capacity() * size_of::<usize>())
+                .sum::<usize>();
+        self.inner.memory_usage()
+            + MemoryFootprint::resident(
+                strings_heap_size(&self.languages)
+                    + string_map_heap_size(&self.language_indices)
+                    + indices_size,
+            )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{LanguageTagFormat, LanguageVocab};
+    use crate::chunks::vocab::{RetainWords, SimpleVocab, Vocab, WordIndex};
+
+    fn test_language_vocab() -> LanguageVocab<SimpleVocab> {
+        LanguageVocab::new(SimpleVocab::new(
+            ["en:dog", "nl:hond", "en:cat", "<unk>", "nl:kat", "de:Hund"]
+                .iter()
+                .map(|&word| word.to_owned())
+                .collect::<Vec<_>>(),
+        ))
+    }
+
+    #[test]
+    fn language_tag_format_split() {
+        let format = LanguageTagFormat::default();
+        assert_eq!(format.split("en:dog"), Some(("en", "dog")));
+        assert_eq!(format.split("en:1:2"), Some(("en", "1:2")));
+        assert_eq!(format.split(":dog"), None);
+        assert_eq!(format.split("dog"), None);
+        assert_eq!(format.tag("en", "dog"), "en:dog");
+
+        let format = LanguageTagFormat::conceptnet();
+        assert_eq!(format.split("/c/en/hot_dog"), Some(("en", "hot_dog")));
+        assert_eq!(format.split("en/dog"), None);
+        assert_eq!(format.tag("nl", "hond"), "/c/nl/hond");
+    }
+
+    #[test]
+    fn language_vocab_lookup() {
+        let vocab = test_language_vocab();
+        assert_eq!(vocab.languages(), &["en", "nl", "de"]);
+        assert_eq!(vocab.lookup("nl", "kat"), Some(WordIndex::Word(4)));
+        assert_eq!(vocab.lookup("de", "kat"), None);
+        assert_eq!(vocab.idx("en:cat"), Some(WordIndex::Word(2)));
+        assert_eq!(vocab.idx("<unk>"), Some(WordIndex::Word(3)));
+        assert_eq!(vocab.language_len("nl"), 2);
+        assert_eq!(vocab.language_len("fr"), 0);
+        assert_eq!(
+            vocab.language_words("en").unwrap().collect::<Vec<_>>(),
+            vec![("dog".into(), 0), ("cat".into(), 2)]
+        );
+        assert!(vocab.language_words("fr").is_none());
+    }
+
+    #[test]
+    fn language_vocab_retain() {
+        let (vocab, rows) = test_language_vocab().retain_indices(&[1, 2, 3]);
+        assert_eq!(rows, vec![1, 2, 3]);
+        assert_eq!(vocab.languages(), &["nl", "en"]);
+        assert_eq!(vocab.lookup("en", "cat"), Some(WordIndex::Word(1)));
+        assert_eq!(vocab.language_len("nl"), 1);
+    }
+}
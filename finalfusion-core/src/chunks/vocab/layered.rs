@@ -0,0 +1,122 @@
+use crate::chunks::memory::{MemoryFootprint, MemoryUsage};
+use crate::chunks::vocab::{SimpleVocab, Vocab, VocabWrap, WordIndex};
+
+/// Vocabulary that layers several vocabularies.
+///
+/// The words of the vocabulary are the words of all layers. When a
+/// word is in more than one layer, the first layer that contains the
+/// word takes precedence. The words of the first layer come first,
+/// followed by the words of the second layer that are not in the
+/// first layer, and so on.
+///
+/// The subword indices of each layer follow the words. Words that are
+/// not in any layer get the subword indices of the first layer that
+/// provides them.
+///
+/// A layered vocabulary is constructed together with its storage by
+/// `Embeddings::layered`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LayeredVocab {
+    layers: Vec<VocabWrap>,
+    words: SimpleVocab,
+    subword_offsets: Vec<usize>,
+    vocab_len: usize,
+}
+
+impl LayeredVocab {
+    /// Construct a layered vocabulary.
+    ///
+    /// Returns the vocabulary, together with the layer and the index
+    /// in that layer of every word of the vocabulary.
+    pub(crate) fn new(layers: Vec<VocabWrap>) -> (Self, Vec<(usize, usize)>) {
+        let mut words = SimpleVocab::new(Vec::new());
+        let mut sources = Vec::new();
+        for (layer_idx, layer) in layers.iter().enumerate() {
+            for (word, idx) in layer.iter() {
+                if words.push(word).is_some() {
+                    sources.push((layer_idx, idx));
+                }
+            }
+        }
+        words.shrink_to_fit();
+
+        let mut subword_offsets = Vec::with_capacity(layers.len());
+        let mut vocab_len = words.words_len();
+        for layer in &layers {
+            subword_offsets.push(vocab_len);
+            vocab_len += layer.vocab_len() - layer.words_len();
+        }
+
+        (
+            LayeredVocab {
+                layers,
+                words,
+                subword_offsets,
+                vocab_len,
+            },
+            sources,
+        )
+    }
+
+    /// Get the layers, in order of precedence.
+    pub fn layers(&self) -> &[VocabWrap] {
+        &self.layers
+    }
+
+    /// Get the index of the first subword index of each layer.
+    pub(crate) fn subword_offsets(&self) -> &[usize] {
+        &self.subword_offsets
+    }
+}
+
+impl Vocab for LayeredVocab {
+    fn idx(&self, word: &str) -> Option<WordIndex> {
+        if let Some(idx) = self.words.idx(word) {
+            return Some(idx);
+        }
+
+        // The word is not in any layer, but it can still be an alias
+        // or be represented by subword units.
+        for (layer, &offset) in self.layers.iter().zip(&self.subword_offsets) {
+            match layer.idx(word) {
+                Some(WordIndex::Word(idx)) => {
+                    return layer.word(idx).and_then(|word| self.words.idx(&word))
+                }
+                Some(WordIndex::Subword(indices)) => {
+                    let words_len = layer.words_len();
+                    return Some(WordIndex::Subword(
+                        indices
+                            .into_iter()
+                            .map(|idx| offset + idx - words_len)
+                            .collect(),
+                    ));
+                }
+                None => (),
+            }
+        }
+
+        None
+    }
+
+    fn words_len(&self) -> usize {
+        self.words.words_len()
+    }
+
+    fn vocab_len(&self) -> usize {
+        self.vocab_len
+    }
+
+    fn words(&self) -> &[String] {
+        self.words.words()
+    }
+}
+
+impl MemoryUsage for LayeredVocab {
+    fn memory_usage(&self) -> MemoryFootprint {
+        self.layers
+            .iter()
+            .map(MemoryUsage::memory_usage)
+            .sum::<MemoryFootprint>()
+            + self.words.memory_usage()
+    }
+}
@@ -0,0 +1,817 @@
+use std::borrow::Cow;
+use std::cmp::{Ordering, Reverse};
+use std::hash::Hasher;
+use std::mem::size_of;
+use std::ops::Deref;
+use std::str;
+use std::sync::OnceLock;
+
+use byteorder::{ByteOrder, LittleEndian, WriteBytesExt};
+use fnv::FnvHasher;
+use memmap::Mmap;
+
+use crate::chunks::memory::{strings_heap_size, MemoryFootprint, MemoryUsage};
+use crate::chunks::vocab::{check_retained_indices, RetainWords, Vocab, VocabIter, WordIndex};
+use crate::error::{Error, ErrorKind, Result};
+use crate::normalization::{normalize_words, NormalizeVocab, WordNormalization};
+
+/// Average number of words per bucket of the perfect hash function.
+const PERFECT_HASH_BUCKET_SIZE: usize = 5;
+
+/// Vocabulary that can be memory mapped.
+///
+/// The words are stored in a string pool in byte-wise sorted order,
+/// together with a table of word offsets into the pool and
+/// permutations between sorted ranks and word indices. Lookups are
+/// binary searches over the pool and do not require a hash table or a
+/// `String` per word. When the vocabulary is memory mapped with
+/// `MmapChunk`, loading the vocabulary only validates the chunk, which
+/// is much faster than reading the words of a multi-million word
+/// vocabulary into memory.
+///
+/// Reading or memory mapping the vocabulary only checks the chunk
+/// header and the bounds of the string pool, which takes constant
+/// time. The words, index tables, and perfect hash function are
+/// validated by `ReadChunk::validate`, which is called when embeddings
+/// are read with `ReadEmbeddingsStrict`. Lookups in a corrupt
+/// vocabulary that was not validated may panic or return incorrect
+/// results.
+///
+/// Optionally, the chunk stores a minimal perfect hash function that
+/// maps every word to its rank, see `new_with_perfect_hash`. Lookups
+/// then hash the word once and compare it to a single word of the
+/// pool, rather than comparing it to *log2(n)* words.
+///
+/// The word list returned by `Vocab::words` is reconstructed from the
+/// string pool when it is first requested and kept in memory
+/// afterwards. `Vocab::iter` and `Vocab::word` do not require the
+/// word list.
+///
+/// The index and rank tables store `u32` values for vocabularies with
+/// at most *2^32 - 1* words and `u64` values for larger vocabularies.
+/// The perfect hash function is only supported for vocabularies with
+/// at most *2^32 - 1* words.
+#[derive(Debug)]
+pub struct MmapVocab {
+    data: VocabData,
+    words_len: usize,
+    seed: u64,
+    n_buckets: usize,
+    n_slots: usize,
+    words: OnceLock<Vec<String>>,
+}
+
+impl MmapVocab {
+    /// Construct a new vocabulary.
+    ///
+    /// Words are assigned indices in the given order.
+    ///
+    /// Panics when there are duplicate words.
+    pub fn new(words: impl Into<Vec<String>>) -> Self {
+        Self::new_with_options(&words.into(), false)
+    }
+
+    /// Construct a new vocabulary with a minimal perfect hash function.
+    ///
+    /// The perfect hash function is constructed using the
+    /// *hash, displace, and compress* algorithm (Belazzougui et al.,
+    /// 2009) and stored in the vocabulary chunk.
+    ///
+    /// Words are assigned indices in the given order.
+    ///
+    /// Panics when there are duplicate words or when there are more
+    /// than *2^32 - 1* words.
+    pub fn new_with_perfect_hash(words: impl Into<Vec<String>>) -> Self {
+        Self::new_with_options(&words.into(), true)
+    }
+
+    fn new_with_options(words: &[String], perfect_hash: bool) -> Self {
+        assert!(
+            !perfect_hash || words.len() <= u32::MAX as usize,
+            "MmapVocab supports perfect hashing of at most {} words",
+            u32::MAX
+        );
+
+        let mut order = (0..words.len()).collect::<Vec<_>>();
+        order.sort_unstable_by(|&idx1, &idx2| words[idx1].as_bytes().cmp(words[idx2].as_bytes()));
+        assert!(
+            order
+                .windows(2)
+                .all(|pair| words[pair[0]] != words[pair[1]]),
+            "words contained duplicate entries."
+        );
+
+        let mut ranks = vec![0; words.len()];
+        for (rank, &idx) in order.iter().enumerate() {
+            ranks[idx] = rank;
+        }
+
+        let perfect_hash = if perfect_hash && !words.is_empty() {
+            let sorted = order
+                .iter()
+                .map(|&idx| words[idx].as_bytes())
+                .collect::<Vec<_>>();
+            Some(PerfectHash::new(&sorted))
+        } else {
+            None
+        };
+        let (seed, displacements, slots) = match perfect_hash {
+            Some(hash) => (hash.seed, hash.displacements, hash.slots),
+            None => (0, Vec::new(), Vec::new()),
+        };
+
+        let pool_len = words.iter().map(String::len).sum::<usize>();
+        let mut data = Vec::with_capacity(
+            Self::data_len(words.len(), displacements.len(), slots.len(), pool_len)
+                .expect("Vocabulary data is too large"),
+        );
+        let mut offset = 0u64;
+        for &idx in &order {
+            offset += words[idx].len() as u64;
+            data.write_u64::<LittleEndian>(offset)
+                .expect("Cannot write to vector");
+        }
+        let wide = has_wide_indices(words.len());
+        for &value in order.iter().chain(&ranks) {
+            if wide {
+                data.write_u64::<LittleEndian>(value as u64)
+            } else {
+                data.write_u32::<LittleEndian>(value as u32)
+            }
+            .expect("Cannot write to vector");
+        }
+        let hash_tables = displacements
+            .iter()
+            .flat_map(|&(d1, d2)| vec![d1, d2])
+            .chain(slots.iter().cloned());
+        for value in hash_tables {
+            data.write_u32::<LittleEndian>(value)
+                .expect("Cannot write to vector");
+        }
+        for &idx in &order {
+            data.extend_from_slice(words[idx].as_bytes());
+        }
+
+        MmapVocab {
+            data: VocabData::Owned(data),
+            words_len: words.len(),
+            seed,
+            n_buckets: displacements.len(),
+            n_slots: slots.len(),
+            words: OnceLock::new(),
+        }
+    }
+
+    /// Check whether the vocabulary has a perfect hash function.
+    pub fn has_perfect_hash(&self) -> bool {
+        self.n_slots != 0
+    }
+
+    /// Check whether the vocabulary is memory mapped.
+    pub fn is_mapped(&self) -> bool {
+        matches!(self.data, VocabData::Mapped(_))
+    }
+
+    /// Get the start offset of the word with the given rank.
+    ///
+    /// Only the end offsets are stored, the start offset of a word is
+    /// the end offset of the preceding word.
+    fn offset(&self, rank: usize) -> usize {
+        match rank {
+            0 => 0,
+            rank => LittleEndian::read_u64(&self.data[(rank - 1) * size_of::<u64>()..]) as usize,
+        }
+    }
+
+    fn indices_start(&self) -> usize {
+        self.words_len * size_of::<u64>()
+    }
+
+    fn ranks_start(&self) -> usize {
+        self.indices_start() + self.words_len * index_size(self.words_len)
+    }
+
+    fn displacements_start(&self) -> usize {
+        self.ranks_start() + self.words_len * index_size(self.words_len)
+    }
+
+    fn slots_start(&self) -> usize {
+        self.displacements_start() + self.n_buckets * 2 * size_of::<u32>()
+    }
+
+    fn pool_start(&self) -> usize {
+        self.slots_start() + self.n_slots * size_of::<u32>()
+    }
+
+    fn read_u32(&self, start: usize, idx: usize) -> u32 {
+        LittleEndian::read_u32(&self.data[start + idx * size_of::<u32>()..])
+    }
+
+    /// Read a value of the index or rank table.
+    fn read_index(&self, start: usize, idx: usize) -> usize {
+        if has_wide_indices(self.words_len) {
+            LittleEndian::read_u64(&self.data[start + idx * size_of::<u64>()..]) as usize
+        } else {
+            self.read_u32(start, idx) as usize
+        }
+    }
+
+    fn rank_index(&self, rank: usize) -> usize {
+        self.read_index(self.indices_start(), rank)
+    }
+
+    fn index_rank(&self, idx: usize) -> usize {
+        self.read_index(self.ranks_start(), idx)
+    }
+
+    fn rank_bytes(&self, rank: usize) -> &[u8] {
+        let pool_start = self.pool_start();
+        &self.data[pool_start + self.offset(rank)..pool_start + self.offset(rank + 1)]
+    }
+
+    fn rank_word(&self, rank: usize) -> &str {
+        str::from_utf8(self.rank_bytes(rank)).expect("Vocabulary contains invalid UTF-8")
+    }
+
+    /// Get the rank of a word in sorted order.
+    fn rank(&self, word: &[u8]) -> Option<usize> {
+        if self.has_perfect_hash() {
+            // The perfect hash function maps words that are not in the
+            // vocabulary to arbitrary ranks.
+            let rank = self.hash_rank(word);
+            return if self.rank_bytes(rank) == word {
+                Some(rank)
+            } else {
+                None
+            };
+        }
+
+        let mut size = self.words_len;
+        let mut lower = 0;
+        while size > 0 {
+            let half = size / 2;
+            let mid = lower + half;
+            match self.rank_bytes(mid).cmp(word) {
+                Ordering::Less => {
+                    lower = mid + 1;
+                    size -= half + 1;
+                }
+                Ordering::Greater => size = half,
+                Ordering::Equal => return Some(mid),
+            }
+        }
+
+        None
+    }
+
+    /// Get the rank that the perfect hash function maps a word to.
+    fn hash_rank(&self, word: &[u8]) -> usize {
+        let hash = KeyHash::new(word, self.seed);
+        let bucket = hash.bucket(self.n_buckets);
+        let d1 = self.read_u32(self.displacements_start(), 2 * bucket);
+        let d2 = self.read_u32(self.displacements_start(), 2 * bucket + 1);
+        self.read_u32(self.slots_start(), hash.slot(d1, d2, self.n_slots)) as usize
+    }
+
+    /// Check the vocabulary header and the bounds of the string pool.
+    ///
+    /// These checks take constant time. The remaining checks are
+    /// performed by `check_words` and `check_perfect_hash`.
+    fn check_header(&self) -> Result<()> {
+        let pool_len = self.data.len() - self.pool_start();
+        if self.offset(self.words_len) != pool_len {
+            return Err(
+                ErrorKind::Format("Invalid vocabulary string pool offsets".to_string()).into(),
+            );
+        }
+
+        if self.n_slots == 0 {
+            return if self.n_buckets == 0 {
+                Ok(())
+            } else {
+                Err(ErrorKind::Format(
+                    "Vocabulary has perfect hash buckets without slots".to_string(),
+                )
+                .into())
+            };
+        }
+
+        if self.n_slots != self.words_len || self.n_buckets == 0 || has_wide_indices(self.words_len)
+        {
+            return Err(ErrorKind::Format(format!(
+                "Perfect hash function with {} buckets and {} slots cannot hash {} words",
+                self.n_buckets, self.n_slots, self.words_len
+            ))
+            .into());
+        }
+
+        Ok(())
+    }
+
+    /// Check that the words and the index tables are valid.
+    fn check_words(&self) -> Result<()> {
+        for rank in 0..self.words_len {
+            if self.offset(rank) > self.offset(rank + 1) {
+                return Err(ErrorKind::Format(format!(
+                    "Offsets of vocabulary word {} are not increasing",
+                    rank
+                ))
+                .into());
+            }
+        }
+
+        for rank in 0..self.words_len {
+            str::from_utf8(self.rank_bytes(rank))
+                .map_err(|e| ErrorKind::Format(format!("Word contains invalid UTF-8: {}", e)))
+                .map_err(Error::from)?;
+
+            if rank > 0 && self.rank_bytes(rank - 1) >= self.rank_bytes(rank) {
+                return Err(ErrorKind::Format(format!(
+                    "Vocabulary words are not sorted or unique at rank {}",
+                    rank
+                ))
+                .into());
+            }
+
+            // Since every rank maps to an index that maps back to the
+            // rank, both tables are permutations.
+            let idx = self.rank_index(rank);
+            if idx >= self.words_len || self.index_rank(idx) != rank {
+                return Err(ErrorKind::Format(format!(
+                    "Word index {} of rank {} is out of bounds or does not map to the rank",
+                    idx, rank
+                ))
+                .into());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check that the perfect hash function maps every word to its rank.
+    fn check_perfect_hash(&self) -> Result<()> {
+        if !self.has_perfect_hash() {
+            return Ok(());
+        }
+
+        for slot in 0..self.n_slots {
+            let rank = self.read_u32(self.slots_start(), slot) as usize;
+            if rank >= self.words_len {
+                return Err(ErrorKind::Format(format!(
+                    "Perfect hash slot {} contains out of bounds rank {}",
+                    slot, rank
+                ))
+                .into());
+            }
+        }
+
+        // Since every rank is hashed to a slot that contains the rank,
+        // the slots are a permutation of the ranks.
+        for rank in 0..self.words_len {
+            if self.hash_rank(self.rank_bytes(rank)) != rank {
+                return Err(ErrorKind::Format(format!(
+                    "Perfect hash function does not map word {} to its rank",
+                    rank
+                ))
+                .into());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn reconstruct_words(&self) -> Vec<String> {
+        (0..self.words_len)
+            .map(|idx| self.rank_word(self.index_rank(idx)).to_owned())
+            .collect()
+    }
+
+    /// Construct a vocabulary from chunk data that is read into memory.
+    ///
+    /// Only the header and the bounds of the string pool are checked.
+    #[doc(hidden)]
+    pub fn from_data(
+        data: Vec<u8>,
+        words_len: usize,
+        seed: u64,
+        n_buckets: usize,
+        n_slots: usize,
+    ) -> Result<Self> {
+        Self::from_vocab_data(VocabData::Owned(data), words_len, seed, n_buckets, n_slots)
+    }
+
+    /// Construct a vocabulary from memory-mapped chunk data.
+    ///
+    /// Only the header and the bounds of the string pool are checked.
+    #[doc(hidden)]
+    pub fn from_mmap(
+        map: Mmap,
+        words_len: usize,
+        seed: u64,
+        n_buckets: usize,
+        n_slots: usize,
+    ) -> Result<Self> {
+        Self::from_vocab_data(VocabData::Mapped(map), words_len, seed, n_buckets, n_slots)
+    }
+
+    fn from_vocab_data(
+        data: VocabData,
+        words_len: usize,
+        seed: u64,
+        n_buckets: usize,
+        n_slots: usize,
+    ) -> Result<Self> {
+        let vocab = MmapVocab {
+            data,
+            words_len,
+            seed,
+            n_buckets,
+            n_slots,
+            words: OnceLock::new(),
+        };
+        vocab.check_header()?;
+
+        Ok(vocab)
+    }
+
+    /// Check the words, the index tables, and the perfect hash function.
+    ///
+    /// These checks take linear time in the size of the vocabulary.
+    #[doc(hidden)]
+    pub fn check(&self) -> Result<()> {
+        self.check_words()?;
+        self.check_perfect_hash()
+    }
+
+    /// Get the chunk data.
+    #[doc(hidden)]
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Get the length of the string pool in bytes.
+    #[doc(hidden)]
+    pub fn pool_len(&self) -> usize {
+        self.data.len() - self.pool_start()
+    }
+
+    /// Get the seed of the perfect hash function.
+    #[doc(hidden)]
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Get the number of buckets of the perfect hash function.
+    #[doc(hidden)]
+    pub fn n_buckets(&self) -> usize {
+        self.n_buckets
+    }
+
+    /// Get the number of slots of the perfect hash function.
+    #[doc(hidden)]
+    pub fn n_slots(&self) -> usize {
+        self.n_slots
+    }
+
+    /// Get the length of the chunk data.
+    #[doc(hidden)]
+    pub fn data_len(
+        words_len: usize,
+        n_buckets: usize,
+        n_slots: usize,
+        pool_len: usize,
+    ) -> Option<usize> {
+        // End offsets (u64), indices, and ranks.
+        let tables_len = words_len.checked_mul(size_of::<u64>() + 2 * index_size(words_len))?;
+        let hash_len = n_buckets
+            .checked_mul(2 * size_of::<u32>())?
+            .checked_add(n_slots.checked_mul(size_of::<u32>())?)?;
+        tables_len.checked_add(hash_len)?.checked_add(pool_len)
+    }
+}
+
+impl Clone for MmapVocab {
+    /// Clone the vocabulary.
+    ///
+    /// The clone of a memory-mapped vocabulary is stored in memory.
+    fn clone(&self) -> Self {
+        MmapVocab {
+            data: VocabData::Owned(self.data.to_vec()),
+            words_len: self.words_len,
+            seed: self.seed,
+            n_buckets: self.n_buckets,
+            n_slots: self.n_slots,
+            words: self.words.clone(),
+        }
+    }
+}
+
+impl PartialEq for MmapVocab {
+    fn eq(&self, other: &Self) -> bool {
+        // The word list is a cache, so it is not compared.
+        self.words_len == other.words_len
+            && self.seed == other.seed
+            && self.n_buckets == other.n_buckets
+            && self.n_slots == other.n_slots
+            && *self.data == *other.data
+    }
+}
+
+impl Eq for MmapVocab {}
+
+impl Vocab for MmapVocab {
+    fn idx(&self, word: &str) -> Option<WordIndex> {
+        self.rank(word.as_bytes())
+            .map(|rank| WordIndex::Word(self.rank_index(rank)))
+    }
+
+    fn words_len(&self) -> usize {
+        self.words_len
+    }
+
+    fn vocab_len(&self) -> usize {
+        self.words_len
+    }
+
+    fn words(&self) -> &[String] {
+        self.words.get_or_init(|| self.reconstruct_words())
+    }
+
+    fn word(&self, idx: usize) -> Option<Cow<'_, str>> {
+        if idx < self.words_len {
+            Some(Cow::Borrowed(self.rank_word(self.index_rank(idx))))
+        } else {
+            None
+        }
+    }
+
+    fn iter(&self) -> VocabIter<'_> {
+        Box::new(
+            (0..self.words_len)
+                .map(move |idx| (Cow::Borrowed(self.rank_word(self.index_rank(idx))), idx)),
+        )
+    }
+}
+
+impl RetainWords for MmapVocab {
+    fn retain_indices(&self, indices: &[usize]) -> (Self, Vec<usize>) {
+        check_retained_indices(indices, self.words_len());
+        let words = indices
+            .iter()
+            .map(|&idx| self.rank_word(self.index_rank(idx)).to_owned())
+            .collect::<Vec<_>>();
+
+        (
+            MmapVocab::new_with_options(&words, self.has_perfect_hash()),
+            indices.to_vec(),
+        )
+    }
+}
+
+impl NormalizeVocab for MmapVocab {
+    fn normalize_vocab(&mut self, normalization: &dyn WordNormalization) -> Result<()> {
+        let mut words = self.reconstruct_words();
+        normalize_words(&mut words, normalization)?;
+        *self = MmapVocab::new_with_options(&words, self.has_perfect_hash());
+        Ok(())
+    }
+}
+
+impl MemoryUsage for MmapVocab {
+    fn memory_usage(&self) -> MemoryFootprint {
+        let words_size = self.words.get().map(strings_heap_size).unwrap_or(0);
+        let footprint = match &self.data {
+            VocabData::Owned(data) => MemoryFootprint::resident(data.len()),
+            VocabData::Mapped(map) => MemoryFootprint::mapped(map.len()),
+        };
+        footprint + MemoryFootprint::resident(words_size)
+    }
+}
+
+/// Check whether the index and rank tables of a vocabulary store
+/// `u64` values.
+fn has_wide_indices(words_len: usize) -> bool {
+    words_len as u64 > u32::MAX as u64
+}
+
+/// Get the size in bytes of values of the index and rank tables.
+fn index_size(words_len: usize) -> usize {
+    if has_wide_indices(words_len) {
+        size_of::<u64>()
+    } else {
+        size_of::<u32>()
+    }
+}
+
+/// Chunk data that is either owned or memory mapped.
+#[derive(Debug)]
+enum VocabData {
+    Owned(Vec<u8>),
+    Mapped(Mmap),
+}
+
+impl Deref for VocabData {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            VocabData::Owned(data) => data,
+            VocabData::Mapped(map) => map,
+        }
+    }
+}
+
+/// Hash of a key of the perfect hash function.
+struct KeyHash {
+    g: u32,
+    f1: u32,
+    f2: u32,
+}
+
+impl KeyHash {
+    fn new(key: &[u8], seed: u64) -> Self {
+        // Bytes are hashed explicitly, so that the hash does not depend
+        // on the platform.
+        let mut hasher = FnvHasher::default();
+        hasher.write(&seed.to_le_bytes());
+        hasher.write(key);
+
+        let hash = mix(hasher.finish());
+        KeyHash {
+            g: (hash >> 32) as u32,
+            f1: hash as u32,
+            f2: mix(hash) as u32,
+        }
+    }
+
+    fn bucket(&self, n_buckets: usize) -> usize {
+        self.g as usize % n_buckets
+    }
+
+    fn slot(&self, d1: u32, d2: u32, n_slots: usize) -> usize {
+        d2.wrapping_add(self.f1.wrapping_mul(d1))
+            .wrapping_add(self.f2) as usize
+            % n_slots
+    }
+}
+
+/// Finalizer of the SplitMix64 generator, to mix the bits of the FNV hash.
+fn mix(mut x: u64) -> u64 {
+    x ^= x >> 30;
+    x = x.wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x ^= x >> 27;
+    x = x.wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+/// Minimal perfect hash function.
+///
+/// Keys are distributed over buckets. Starting with the largest
+/// bucket, displacements are searched such that all keys of the bucket
+/// are placed in free slots.
+struct PerfectHash {
+    seed: u64,
+    displacements: Vec<(u32, u32)>,
+
+    /// The key of each slot.
+    slots: Vec<u32>,
+}
+
+impl PerfectHash {
+    fn new(keys: &[&[u8]]) -> Self {
+        let n_buckets = keys.len().div_ceil(PERFECT_HASH_BUCKET_SIZE);
+        (0..)
+            .find_map(|seed| Self::new_with_seed(keys, n_buckets, seed))
+            .expect("Cannot construct perfect hash function")
+    }
+
+    fn new_with_seed(keys: &[&[u8]], n_buckets: usize, seed: u64) -> Option<Self> {
+        let n_slots = keys.len();
+        let hashes = keys
+            .iter()
+            .map(|key| KeyHash::new(key, seed))
+            .collect::<Vec<_>>();
+
+        let mut buckets = vec![Vec::new(); n_buckets];
+        for (key, hash) in hashes.iter().enumerate() {
+            buckets[hash.bucket(n_buckets)].push(key);
+        }
+        let mut order = (0..n_buckets).collect::<Vec<_>>();
+        order.sort_by_key(|&bucket| Reverse(buckets[bucket].len()));
+
+        let mut displacements = vec![(0, 0); n_buckets];
+        let mut slots = vec![u32::MAX; n_slots];
+
+        // Slots that are taken by keys of the current bucket are marked
+        // with the current attempt, to avoid clearing the marks.
+        let mut attempts = vec![0u64; n_slots];
+        let mut attempt = 0u64;
+        let mut placed = Vec::new();
+
+        'buckets: for bucket in order {
+            let bucket_keys = &buckets[bucket];
+            if bucket_keys.is_empty() {
+                break;
+            }
+
+            for d1 in 0..n_slots as u32 {
+                'displacements: for d2 in 0..n_slots as u32 {
+                    attempt += 1;
+                    placed.clear();
+                    for &key in bucket_keys {
+                        let slot = hashes[key].slot(d1, d2, n_slots);
+                        if slots[slot] != u32::MAX || attempts[slot] == attempt {
+                            continue 'displacements;
+                        }
+                        attempts[slot] = attempt;
+                        placed.push((slot, key));
+                    }
+
+                    for &(slot, key) in &placed {
+                        slots[slot] = key as u32;
+                    }
+                    displacements[bucket] = (d1, d2);
+                    continue 'buckets;
+                }
+            }
+
+            return None;
+        }
+
+        Some(PerfectHash {
+            seed,
+            displacements,
+            slots,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{index_size, MmapVocab};
+    use crate::chunks::vocab::{Vocab, WordIndex};
+
+    fn test_words() -> Vec<String> {
+        vec![
+            "tops".to_owned(),
+            "tap".to_owned(),
+            "top".to_owned(),
+            "".to_owned(),
+            "taps".to_owned(),
+            "töpfe".to_owned(),
+        ]
+    }
+
+    fn test_mmap_vocab() -> MmapVocab {
+        MmapVocab::new(test_words())
+    }
+
+    fn check_lookups(vocab: &MmapVocab) {
+        let words = ["tops", "tap", "top", "", "taps", "töpfe"];
+        for (idx, word) in words.iter().enumerate() {
+            assert_eq!(vocab.idx(word), Some(WordIndex::Word(idx)));
+            assert_eq!(vocab.word(idx).as_deref(), Some(*word));
+        }
+        assert_eq!(vocab.word(words.len()), None);
+
+        for word in &["t", "to", "tapss", "pots", "töpf"] {
+            assert_eq!(vocab.idx(word), None);
+        }
+
+        assert_eq!(vocab.words_len(), 6);
+        assert_eq!(vocab.words(), &words[..]);
+        assert!(vocab.iter().map(|(word, _)| word).eq(words.iter().cloned()));
+    }
+
+    #[test]
+    fn mmap_vocab_lookup() {
+        let vocab = test_mmap_vocab();
+        assert!(!vocab.has_perfect_hash());
+        check_lookups(&vocab);
+    }
+
+    #[test]
+    fn mmap_vocab_perfect_hash_lookup() {
+        let vocab = MmapVocab::new_with_perfect_hash(test_words());
+        assert!(vocab.has_perfect_hash());
+        check_lookups(&vocab);
+
+        let words = (0..1000).map(|i| format!("word{}", i)).collect::<Vec<_>>();
+        let vocab = MmapVocab::new_with_perfect_hash(words.clone());
+        for (idx, word) in words.iter().enumerate() {
+            assert_eq!(vocab.idx(word), Some(WordIndex::Word(idx)));
+        }
+        assert_eq!(vocab.idx("word1000"), None);
+
+        assert!(!MmapVocab::new_with_perfect_hash(Vec::new()).has_perfect_hash());
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn mmap_vocab_uses_wide_indices_for_large_vocabularies() {
+        assert_eq!(index_size(0), 4);
+        assert_eq!(index_size(u32::MAX as usize), 4);
+        assert_eq!(index_size(u32::MAX as usize + 1), 8);
+    }
+}
@@ -0,0 +1,189 @@
+//! Embedding vocabularies
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+mod subword;
+pub use subword::{
+    BucketSubwordVocab, ExplicitSubwordVocab, FastTextSubwordVocab, NGramIndices, SubwordIndices,
+    SubwordVocab,
+};
+
+mod alias;
+pub use alias::AliasVocab;
+
+mod bpe;
+pub use bpe::BpeVocab;
+
+mod byte_fallback;
+pub use byte_fallback::{ByteFallbackVocab, N_BYTE_UNITS};
+
+mod front_coded;
+pub use front_coded::FrontCodedVocab;
+
+mod fst;
+pub use fst::FstVocab;
+
+mod json;
+pub use json::VocabJson;
+
+mod language;
+pub use language::{LanguageTagFormat, LanguageVocab};
+
+mod layered;
+pub use layered::LayeredVocab;
+
+mod mmap;
+pub use mmap::MmapVocab;
+
+mod namespaced;
+pub use namespaced::{NamespacedVocab, NAMESPACE_SEPARATOR};
+
+mod sentencepiece;
+pub use sentencepiece::{PieceType, SentencePieceModel, SentencePieceVocab, SENTENCEPIECE_SPACE};
+
+mod simple;
+pub use simple::SimpleVocab;
+
+mod wordpiece;
+pub use wordpiece::WordPieceVocab;
+
+mod wrappers;
+pub use wrappers::VocabWrap;
+
+/// Embedding vocabularies.
+#[allow(clippy::len_without_is_empty)]
+pub trait Vocab {
+    /// Get the index of a token.
+    fn idx(&self, word: &str) -> Option<WordIndex>;
+
+    /// Get the number of words in the vocabulary.
+    fn words_len(&self) -> usize;
+
+    /// Get the total length of this vocabulary, including possible subword indices.
+    fn vocab_len(&self) -> usize;
+
+    /// Get the words in the vocabulary.
+    fn words(&self) -> &[String];
+
+    /// Get the word or subword unit at a storage index.
+    ///
+    /// For indices of subword units, the unit is returned when the
+    /// vocabulary stores it. `None` is returned for indices that are
+    /// out of bounds and for subword indices that do not correspond
+    /// to a single string, such as hashed buckets. Vocabularies that
+    /// do not store their words as strings can override this method to
+    /// reconstruct the word without materializing `words`.
+    fn word(&self, idx: usize) -> Option<Cow<'_, str>> {
+        self.words()
+            .get(idx)
+            .map(|word| Cow::Borrowed(word.as_str()))
+    }
+
+    /// Get an iterator over the words in the vocabulary and their indices.
+    ///
+    /// The words are returned in index order. Vocabularies that do
+    /// not store their words as strings can override this method to
+    /// return the words without materializing them with `words`.
+    fn iter(&self) -> VocabIter<'_> {
+        Box::new(
+            self.words()
+                .iter()
+                .enumerate()
+                .map(|(idx, word)| (Cow::Borrowed(word.as_str()), idx)),
+        )
+    }
+
+    /// Get the frequency rank of a word.
+    ///
+    /// The rank of a word is its index, counting from zero. This is
+    /// only a frequency rank when the words are ordered by descending
+    /// frequency, as is conventional for word2vec, GloVe, and fastText
+    /// models and as can be verified with
+    /// `Embeddings::is_frequency_ranked`. Returns `None` when the word
+    /// is not in the vocabulary.
+    fn rank(&self, word: &str) -> Option<usize> {
+        self.idx(word)?.word()
+    }
+
+    /// Get the `n` words with the highest ranks and their indices.
+    ///
+    /// When the vocabulary is ordered by descending frequency, these
+    /// are the `n` most frequent words. Fewer words are returned when
+    /// the vocabulary has less than `n` words.
+    fn top_n(&self, n: usize) -> VocabIter<'_> {
+        Box::new(self.iter().take(n))
+    }
+}
+
+/// Iterator over the words of a vocabulary and their indices.
+pub type VocabIter<'a> = Box<dyn Iterator<Item = (Cow<'a, str>, usize)> + 'a>;
+
+/// Vocabularies from which words can be removed.
+pub trait RetainWords: Vocab + Sized {
+    /// Construct a vocabulary with the words at the given indices.
+    ///
+    /// The words keep their order, so `indices` must be strictly
+    /// increasing. Returns the vocabulary, together with the storage
+    /// rows that it uses. Row *i* of the storage of the new vocabulary
+    /// is row `rows[i]` of the storage of this vocabulary, including
+    /// the rows of subword units.
+    ///
+    /// Panics when the indices are not strictly increasing or are not
+    /// indices of words.
+    fn retain_indices(&self, indices: &[usize]) -> (Self, Vec<usize>);
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+/// Index of a vocabulary word.
+pub enum WordIndex {
+    /// The index of an in-vocabulary word.
+    Word(usize),
+
+    /// The subword indices of out-of-vocabulary words.
+    Subword(Vec<usize>),
+}
+
+impl WordIndex {
+    pub fn word(&self) -> Option<usize> {
+        use WordIndex::*;
+
+        match self {
+            Word(idx) => Some(*idx),
+            Subword(_) => None,
+        }
+    }
+
+    pub fn subword(&self) -> Option<&[usize]> {
+        use WordIndex::*;
+
+        match self {
+            Word(_) => None,
+            Subword(indices) => Some(indices),
+        }
+    }
+}
+
+/// Check that the indices of retained words are valid.
+#[doc(hidden)]
+pub fn check_retained_indices(indices: &[usize], words_len: usize) {
+    assert!(
+        indices.windows(2).all(|pair| pair[0] < pair[1]),
+        "Indices of retained words must be strictly increasing"
+    );
+    assert!(
+        indices.last().map(|&idx| idx < words_len).unwrap_or(true),
+        "Indices of retained words must be smaller than the number of words"
+    );
+}
+
+#[doc(hidden)]
+pub fn create_indices(words: &[String]) -> HashMap<String, usize> {
+    let mut indices = HashMap::new();
+
+    for (idx, word) in words.iter().enumerate() {
+        indices.insert(word.to_owned(), idx);
+    }
+
+    indices
+}
@@ -0,0 +1,284 @@
+use std::collections::HashMap;
+use std::mem::size_of;
+use std::ops::Range;
+
+use crate::chunks::memory::{
+    string_map_heap_size, strings_heap_size, MemoryFootprint, MemoryUsage,
+};
+use crate::chunks::vocab::{check_retained_indices, create_indices, RetainWords, Vocab, WordIndex};
+use crate::error::Result;
+use crate::normalization::{normalize_words, NormalizeVocab, WordNormalization};
+
+/// Separator between a namespace and a word in qualified words.
+pub const NAMESPACE_SEPARATOR: &str = "::";
+
+/// Vocabulary that is partitioned in namespaces.
+///
+/// Each namespace has its own vocabulary, so that the same word can
+/// occur in several namespaces without colliding. For instance,
+/// entity embeddings and word embeddings can be stored together,
+/// using the namespaces `entity` and `word`.
+///
+/// Words are qualified with their namespace, as in `entity::Berlin`.
+/// `Vocab::idx` looks up qualified words. Unqualified words are looked
+/// up in the namespaces in order, returning the first match. The
+/// indices of the words of a namespace are contiguous and namespaces
+/// are ordered as given at construction.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct NamespacedVocab {
+    namespaces: Vec<String>,
+    namespace_indices: HashMap<String, usize>,
+    offsets: Vec<usize>,
+    indices: Vec<HashMap<String, usize>>,
+    words: Vec<String>,
+}
+
+impl NamespacedVocab {
+    /// Construct a new namespaced vocabulary.
+    ///
+    /// The vocabulary is constructed from pairs of namespaces and
+    /// their words. Words are assigned indices in the given order.
+    ///
+    /// Panics when there are duplicate namespaces, a namespace
+    /// contains the namespace separator, or a namespace contains
+    /// duplicate words.
+    pub fn new<I, N, W>(namespaces: I) -> Self
+    where
+        I: IntoIterator<Item = (N, W)>,
+        N: Into<String>,
+        W: Into<Vec<String>>,
+    {
+        let mut vocab = NamespacedVocab {
+            namespaces: Vec::new(),
+            namespace_indices: HashMap::new(),
+            offsets: Vec::new(),
+            indices: Vec::new(),
+            words: Vec::new(),
+        };
+
+        for (namespace, words) in namespaces {
+            let namespace = namespace.into();
+            let words = words.into();
+
+            assert!(
+                !namespace.contains(NAMESPACE_SEPARATOR),
+                "namespace contains the namespace separator: {}",
+                namespace
+            );
+            assert!(
+                !vocab.namespace_indices.contains_key(&namespace),
+                "duplicate namespace: {}",
+                namespace
+            );
+
+            let indices = create_indices(&words);
+            assert_eq!(
+                words.len(),
+                indices.len(),
+                "words contained duplicate entries."
+            );
+
+            vocab
+                .namespace_indices
+                .insert(namespace.clone(), vocab.namespaces.len());
+            vocab.offsets.push(vocab.words.len());
+            vocab.words.extend(
+                words
+                    .iter()
+                    .map(|word| format!("{}{}{}", namespace, NAMESPACE_SEPARATOR, word)),
+            );
+            vocab.indices.push(indices);
+            vocab.namespaces.push(namespace);
+        }
+
+        vocab
+    }
+
+    /// Get the index of a word within a namespace.
+    pub fn idx_in(&self, namespace: &str, word: &str) -> Option<usize> {
+        let ns_idx = *self.namespace_indices.get(namespace)?;
+        self.indices[ns_idx]
+            .get(word)
+            .map(|&idx| self.offsets[ns_idx] + idx)
+    }
+
+    /// Get the indices of an unqualified word across all namespaces.
+    ///
+    /// Returns the namespaces that contain the word, together with
+    /// the index of the word in that namespace.
+    pub fn idx_all(&self, word: &str) -> Vec<(&str, usize)> {
+        self.namespaces
+            .iter()
+            .zip(&self.indices)
+            .zip(&self.offsets)
+            .filter_map(|((namespace, indices), offset)| {
+                indices
+                    .get(word)
+                    .map(|&idx| (namespace.as_str(), offset + idx))
+            })
+            .collect()
+    }
+
+    /// Get the namespaces.
+    pub fn namespaces(&self) -> &[String] {
+        &self.namespaces
+    }
+
+    /// Get the range of indices of the words in a namespace.
+    pub fn namespace_range(&self, namespace: &str) -> Option<Range<usize>> {
+        let ns_idx = *self.namespace_indices.get(namespace)?;
+        let start = self.offsets[ns_idx];
+        Some(start..start + self.indices[ns_idx].len())
+    }
+
+    /// Get the qualified words of a namespace.
+    pub fn namespace_words(&self, namespace: &str) -> Option<&[String]> {
+        self.namespace_range(namespace)
+            .map(|range| &self.words[range])
+    }
+}
+
+impl Vocab for NamespacedVocab {
+    fn idx(&self, word: &str) -> Option<WordIndex> {
+        if let Some(sep_pos) = word.find(NAMESPACE_SEPARATOR) {
+            let namespace = &word[..sep_pos];
+            let unqualified = &word[sep_pos + NAMESPACE_SEPARATOR.len()..];
+            if let Some(idx) = self.idx_in(namespace, unqualified) {
+                return Some(WordIndex::Word(idx));
+            }
+        }
+
+        self.indices
+            .iter()
+            .zip(&self.offsets)
+            .find_map(|(indices, offset)| indices.get(word).map(|&idx| offset + idx))
+            .map(WordIndex::Word)
+    }
+
+    fn words_len(&self) -> usize {
+        self.words.len()
+    }
+
+    fn vocab_len(&self) -> usize {
+        self.words_len()
+    }
+
+    fn words(&self) -> &[String] {
+        &self.words
+    }
+}
+
+impl RetainWords for NamespacedVocab {
+    /// Construct a vocabulary with the words at the given indices.
+    ///
+    /// All namespaces are retained, including namespaces of which no
+    /// words are retained.
+    fn retain_indices(&self, indices: &[usize]) -> (Self, Vec<usize>) {
+        check_retained_indices(indices, self.words_len());
+
+        let mut namespaces = self
+            .namespaces
+            .iter()
+            .map(|namespace| (namespace.clone(), Vec::new()))
+            .collect::<Vec<_>>();
+        for &idx in indices {
+            // Namespaces are ordered by their offsets.
+            let ns_idx = self.offsets.partition_point(|&offset| offset <= idx) - 1;
+            let (namespace, words) = &mut namespaces[ns_idx];
+            let prefix_len = namespace.len() + NAMESPACE_SEPARATOR.len();
+            words.push(self.words[idx][prefix_len..].to_owned());
+        }
+
+        (NamespacedVocab::new(namespaces), indices.to_vec())
+    }
+}
+
+impl NormalizeVocab for NamespacedVocab {
+    /// Normalize the words of all namespaces.
+    ///
+    /// Words are normalized without their namespace qualification,
+    /// so words only collide within a namespace. Namespaces are not
+    /// normalized.
+    fn normalize_vocab(&mut self, normalization: &dyn WordNormalization) -> Result<()> {
+        let namespaces = self
+            .namespaces
+            .iter()
+            .map(|namespace| {
+                let prefix_len = namespace.len() + NAMESPACE_SEPARATOR.len();
+                let mut words = self
+                    .namespace_words(namespace)
+                    .expect("Unknown namespace")
+                    .iter()
+                    .map(|word| word[prefix_len..].to_owned())
+                    .collect::<Vec<_>>();
+                normalize_words(&mut words, normalization)?;
+                Ok((namespace.clone(), words))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        *self = NamespacedVocab::new(namespaces);
+        Ok(())
+    }
+}
+
+impl MemoryUsage for NamespacedVocab {
+    fn memory_usage(&self) -> MemoryFootprint {
+        MemoryFootprint::resident(
+            strings_heap_size(&self.namespaces)
+                + string_map_heap_size(&self.namespace_indices)
+                + self.offsets.capacity() * size_of::<usize>()
+                + self.indices.capacity() * size_of::<HashMap<String, usize>>()
+                + self.indices.iter().map(string_map_heap_size).sum::<usize>()
+                + strings_heap_size(&self.words),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::NamespacedVocab;
+    use crate::chunks::vocab::{RetainWords, Vocab, WordIndex};
+
+    fn test_namespaced_vocab() -> NamespacedVocab {
+        NamespacedVocab::new(vec![
+            ("word", vec!["Berlin".to_owned(), "is".to_owned()]),
+            ("entity", vec!["Berlin".to_owned(), "Q64".to_owned()]),
+        ])
+    }
+
+    #[test]
+    fn namespaced_vocab_lookups() {
+        let vocab = test_namespaced_vocab();
+        assert_eq!(vocab.words_len(), 4);
+        assert_eq!(vocab.idx("word::Berlin"), Some(WordIndex::Word(0)));
+        assert_eq!(vocab.idx("entity::Berlin"), Some(WordIndex::Word(2)));
+        assert_eq!(vocab.idx("Berlin"), Some(WordIndex::Word(0)));
+        assert_eq!(vocab.idx("Q64"), Some(WordIndex::Word(3)));
+        assert_eq!(vocab.idx("word::Q64"), None);
+        assert_eq!(vocab.idx_in("entity", "Q64"), Some(3));
+        assert_eq!(vocab.idx_all("Berlin"), vec![("word", 0), ("entity", 2)]);
+        assert_eq!(vocab.namespace_range("entity"), Some(2..4));
+        assert_eq!(
+            vocab.namespace_words("entity").unwrap(),
+            &["entity::Berlin".to_owned(), "entity::Q64".to_owned()]
+        );
+    }
+
+    #[test]
+    fn namespaced_vocab_retain_indices() {
+        let (vocab, rows) = test_namespaced_vocab().retain_indices(&[1, 2]);
+        assert_eq!(rows, vec![1, 2]);
+        assert_eq!(
+            vocab.namespaces(),
+            &["word".to_owned(), "entity".to_owned()]
+        );
+        assert_eq!(vocab.idx("word::is"), Some(WordIndex::Word(0)));
+        assert_eq!(vocab.idx("Berlin"), Some(WordIndex::Word(1)));
+        assert_eq!(vocab.idx("Q64"), None);
+
+        let (vocab, rows) = test_namespaced_vocab().retain_indices(&[]);
+        assert!(rows.is_empty());
+        assert_eq!(vocab.words_len(), 0);
+    }
+}
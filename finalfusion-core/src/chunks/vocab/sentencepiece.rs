@@ -0,0 +1,448 @@
+use std::collections::HashMap;
+use std::mem::size_of;
+
+use crate::chunks::memory::{
+    string_map_heap_size, strings_heap_size, MemoryFootprint, MemoryUsage,
+};
+use crate::chunks::vocab::{
+    check_retained_indices, create_indices, RetainWords, SubwordIndices, Vocab, WordIndex,
+};
+use crate::error::Result;
+use crate::normalization::{normalize_words, NormalizeVocab, WordNormalization};
+
+/// Marker of the start of a word in SentencePiece pieces (U+2581).
+pub const SENTENCEPIECE_SPACE: char = '\u{2581}';
+
+/// SentencePiece segmentation algorithm.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SentencePieceModel {
+    /// Unigram language model, words are segmented into the pieces
+    /// with the highest total score.
+    Unigram,
+
+    /// Byte pair encoding, adjacent pieces are merged greedily in
+    /// the order of the scores of the merged pieces.
+    Bpe,
+}
+
+impl SentencePieceModel {
+    #[doc(hidden)]
+    pub fn from_u32(model: u32) -> Option<Self> {
+        match model {
+            1 => Some(SentencePieceModel::Unigram),
+            2 => Some(SentencePieceModel::Bpe),
+            _ => None,
+        }
+    }
+
+    #[doc(hidden)]
+    pub fn to_u32(self) -> u32 {
+        match self {
+            SentencePieceModel::Unigram => 1,
+            SentencePieceModel::Bpe => 2,
+        }
+    }
+}
+
+/// Type of a SentencePiece piece.
+///
+/// The types and their numeric values are those of SentencePiece.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PieceType {
+    /// Regular piece.
+    Normal,
+
+    /// Piece for unknown characters, such as `<unk>`.
+    Unknown,
+
+    /// Control symbol, such as `<s>`.
+    Control,
+
+    /// Piece that was added by the user.
+    UserDefined,
+
+    /// Piece that is not used in segmentation.
+    Unused,
+
+    /// Byte fallback piece, such as `<0x41>`.
+    Byte,
+}
+
+impl PieceType {
+    #[doc(hidden)]
+    pub fn from_u8(piece_type: u8) -> Option<Self> {
+        use self::PieceType::*;
+
+        match piece_type {
+            1 => Some(Normal),
+            2 => Some(Unknown),
+            3 => Some(Control),
+            4 => Some(UserDefined),
+            5 => Some(Unused),
+            6 => Some(Byte),
+            _ => None,
+        }
+    }
+
+    #[doc(hidden)]
+    pub fn to_u8(self) -> u8 {
+        use self::PieceType::*;
+
+        match self {
+            Normal => 1,
+            Unknown => 2,
+            Control => 3,
+            UserDefined => 4,
+            Unused => 5,
+            Byte => 6,
+        }
+    }
+
+    /// Returns `true` if the piece can be used in segmentation.
+    fn segments(self) -> bool {
+        matches!(self, PieceType::Normal | PieceType::UserDefined)
+    }
+}
+
+/// SentencePiece vocabulary.
+///
+/// The vocabulary consists of the pieces of a SentencePiece model,
+/// each piece has an embedding. SentencePiece marks the start of a
+/// word with `SENTENCEPIECE_SPACE`, so a word is looked up as
+/// follows:
+///
+/// 1. If the word is a piece, the index of the piece is returned.
+/// 2. If the word prefixed by `SENTENCEPIECE_SPACE` is a piece, the
+///    index of that piece is returned.
+/// 3. Otherwise, the prefixed word is segmented into pieces, whose
+///    embeddings are averaged.
+///
+/// Spaces in a word are replaced by `SENTENCEPIECE_SPACE`. The
+/// Unicode normalization of SentencePiece models is not applied, a
+/// normalization can be installed with `Embeddings::set_normalization`.
+#[derive(Clone, Debug)]
+pub struct SentencePieceVocab {
+    indices: HashMap<String, usize>,
+    pieces: Vec<String>,
+    scores: Vec<f32>,
+    types: Vec<PieceType>,
+    model: SentencePieceModel,
+    max_piece_chars: usize,
+}
+
+impl SentencePieceVocab {
+    /// Construct a new SentencePiece vocabulary.
+    ///
+    /// Pieces are assigned indices in the given order.
+    ///
+    /// Panics when there are duplicate pieces or when `pieces`,
+    /// `scores`, and `types` do not have the same length.
+    pub fn new(
+        pieces: impl Into<Vec<String>>,
+        scores: impl Into<Vec<f32>>,
+        types: impl Into<Vec<PieceType>>,
+        model: SentencePieceModel,
+    ) -> Self {
+        let pieces = pieces.into();
+        let scores = scores.into();
+        let types = types.into();
+        assert_eq!(
+            pieces.len(),
+            scores.len(),
+            "Pieces and scores do not have the same length"
+        );
+        assert_eq!(
+            pieces.len(),
+            types.len(),
+            "Pieces and types do not have the same length"
+        );
+
+        let indices = create_indices(&pieces);
+        assert_eq!(
+            pieces.len(),
+            indices.len(),
+            "words contained duplicate entries."
+        );
+
+        let max_piece_chars = pieces
+            .iter()
+            .zip(&types)
+            .filter(|(_, piece_type)| piece_type.segments())
+            .map(|(piece, _)| piece.chars().count())
+            .max()
+            .unwrap_or(0);
+
+        SentencePieceVocab {
+            indices,
+            pieces,
+            scores,
+            types,
+            model,
+            max_piece_chars,
+        }
+    }
+
+    /// Get the segmentation algorithm.
+    pub fn model(&self) -> SentencePieceModel {
+        self.model
+    }
+
+    /// Get the piece scores.
+    pub fn scores(&self) -> &[f32] {
+        &self.scores
+    }
+
+    /// Get the piece types.
+    pub fn types(&self) -> &[PieceType] {
+        &self.types
+    }
+
+    /// Segment a word into pieces.
+    ///
+    /// The word is prefixed by `SENTENCEPIECE_SPACE`. Returns the
+    /// indices of the pieces, characters that are not covered by any
+    /// piece are skipped.
+    pub fn segment(&self, word: &str) -> Vec<usize> {
+        let mut text = String::with_capacity(word.len() + 3);
+        text.push(SENTENCEPIECE_SPACE);
+        text.extend(
+            word.chars()
+                .map(|c| if c == ' ' { SENTENCEPIECE_SPACE } else { c }),
+        );
+
+        match self.model {
+            SentencePieceModel::Unigram => self.segment_unigram(&text),
+            SentencePieceModel::Bpe => self.segment_bpe(&text),
+        }
+    }
+
+    fn segment_piece(&self, piece: &str) -> Option<(usize, f32)> {
+        let idx = *self.indices.get(piece)?;
+        if self.types[idx].segments() {
+            Some((idx, self.scores[idx]))
+        } else {
+            None
+        }
+    }
+
+    /// Segment using the Viterbi algorithm.
+    fn segment_unigram(&self, text: &str) -> Vec<usize> {
+        let offsets = text
+            .char_indices()
+            .map(|(offset, _)| offset)
+            .chain(Some(text.len()))
+            .collect::<Vec<_>>();
+
+        // Unknown characters get a lower score than any piece.
+        let unknown_score = self.scores.iter().cloned().fold(0f32, f32::min) - 10.;
+
+        // Best segmentation of each prefix: score, start of the last
+        // segment, and the piece of the last segment.
+        let mut best: Vec<(f32, usize, Option<usize>)> = vec![(0., 0, None)];
+        for end in 1..offsets.len() {
+            let mut end_best = (
+                best[end - 1].0 + unknown_score,
+                end - 1,
+                Option::<usize>::None,
+            );
+
+            for start in end.saturating_sub(self.max_piece_chars)..end {
+                if let Some((idx, score)) = self.segment_piece(&text[offsets[start]..offsets[end]])
+                {
+                    let score = best[start].0 + score;
+                    if score > end_best.0 {
+                        end_best = (score, start, Some(idx));
+                    }
+                }
+            }
+
+            best.push(end_best);
+        }
+
+        let mut pieces = Vec::new();
+        let mut end = offsets.len() - 1;
+        while end > 0 {
+            let (_, start, piece) = best[end];
+            pieces.extend(piece);
+            end = start;
+        }
+        pieces.reverse();
+
+        pieces
+    }
+
+    /// Segment by merging adjacent pieces.
+    fn segment_bpe(&self, text: &str) -> Vec<usize> {
+        let mut symbols = text.chars().map(String::from).collect::<Vec<_>>();
+
+        loop {
+            let mut best: Option<(usize, f32)> = None;
+            for (pos, pair) in symbols.windows(2).enumerate() {
+                let merged = format!("{}{}", pair[0], pair[1]);
+                if let Some((_, score)) = self.segment_piece(&merged) {
+                    if best.map(|(_, best)| score > best).unwrap_or(true) {
+                        best = Some((pos, score));
+                    }
+                }
+            }
+
+            match best {
+                Some((pos, _)) => {
+                    let right = symbols.remove(pos + 1);
+                    symbols[pos].push_str(&right);
+                }
+                None => break,
+            }
+        }
+
+        symbols
+            .iter()
+            .filter_map(|symbol| self.segment_piece(symbol))
+            .map(|(idx, _)| idx)
+            .collect()
+    }
+}
+
+impl PartialEq for SentencePieceVocab {
+    fn eq(&self, other: &Self) -> bool {
+        // Scores are compared bitwise, so that equality is reflexive.
+        self.pieces == other.pieces
+            && self.types == other.types
+            && self.model == other.model
+            && self.scores.len() == other.scores.len()
+            && self
+                .scores
+                .iter()
+                .zip(&other.scores)
+                .all(|(score, other)| score.to_bits() == other.to_bits())
+    }
+}
+
+impl Eq for SentencePieceVocab {}
+
+impl Vocab for SentencePieceVocab {
+    fn idx(&self, word: &str) -> Option<WordIndex> {
+        if let Some(idx) = self.indices.get(word).cloned() {
+            return Some(WordIndex::Word(idx));
+        }
+
+        let mut prefixed = String::with_capacity(word.len() + 3);
+        prefixed.push(SENTENCEPIECE_SPACE);
+        prefixed.push_str(word);
+        if let Some(idx) = self.indices.get(&prefixed).cloned() {
+            return Some(WordIndex::Word(idx));
+        }
+
+        self.subword_indices(word).map(WordIndex::Subword)
+    }
+
+    fn words_len(&self) -> usize {
+        self.pieces.len()
+    }
+
+    fn vocab_len(&self) -> usize {
+        self.words_len()
+    }
+
+    /// Get the pieces.
+    fn words(&self) -> &[String] {
+        &self.pieces
+    }
+}
+
+impl SubwordIndices for SentencePieceVocab {
+    fn subword_indices(&self, word: &str) -> Option<Vec<usize>> {
+        let indices = self.segment(word);
+        if indices.is_empty() {
+            None
+        } else {
+            Some(indices)
+        }
+    }
+}
+
+impl RetainWords for SentencePieceVocab {
+    fn retain_indices(&self, indices: &[usize]) -> (Self, Vec<usize>) {
+        check_retained_indices(indices, self.words_len());
+        let vocab = SentencePieceVocab::new(
+            indices
+                .iter()
+                .map(|&idx| self.pieces[idx].clone())
+                .collect::<Vec<_>>(),
+            indices
+                .iter()
+                .map(|&idx| self.scores[idx])
+                .collect::<Vec<_>>(),
+            indices
+                .iter()
+                .map(|&idx| self.types[idx])
+                .collect::<Vec<_>>(),
+            self.model,
+        );
+
+        (vocab, indices.to_vec())
+    }
+}
+
+impl NormalizeVocab for SentencePieceVocab {
+    fn normalize_vocab(&mut self, normalization: &dyn WordNormalization) -> Result<()> {
+        normalize_words(&mut self.pieces, normalization)?;
+        self.indices = create_indices(&self.pieces);
+        Ok(())
+    }
+}
+
+impl MemoryUsage for SentencePieceVocab {
+    fn memory_usage(&self) -> MemoryFootprint {
+        MemoryFootprint::resident(
+            string_map_heap_size(&self.indices)
+                + strings_heap_size(&self.pieces)
+                + self.scores.len() * size_of::<f32>()
+                + self.types.len() * size_of::<PieceType>(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{PieceType, SentencePieceModel, SentencePieceVocab};
+    use crate::chunks::vocab::{Vocab, WordIndex};
+
+    fn test_sentencepiece_vocab(model: SentencePieceModel) -> SentencePieceVocab {
+        let pieces = [
+            "<unk>", "▁", "▁l", "ow", "▁low", "er", "e", "r", "o", "w", "l",
+        ];
+        let scores = [0., -1., -2., -3., -1.5, -2.5, -5., -5., -5., -5., -5.];
+        let mut types = vec![PieceType::Normal; pieces.len()];
+        types[0] = PieceType::Unknown;
+
+        SentencePieceVocab::new(
+            pieces.iter().map(|&p| p.to_owned()).collect::<Vec<_>>(),
+            scores.to_vec(),
+            types,
+            model,
+        )
+    }
+
+    #[test]
+    fn sentencepiece_vocab_segments_unigram() {
+        let vocab = test_sentencepiece_vocab(SentencePieceModel::Unigram);
+        assert_eq!(vocab.idx("low"), Some(WordIndex::Word(4)));
+        assert_eq!(vocab.idx("▁l"), Some(WordIndex::Word(2)));
+        assert_eq!(vocab.idx("lower"), Some(WordIndex::Subword(vec![4, 5])));
+        // x is not covered by any piece.
+        assert_eq!(vocab.segment("lowx"), vec![4]);
+        assert_eq!(vocab.idx("<unk>"), Some(WordIndex::Word(0)));
+        assert_eq!(vocab.segment("<unk>"), vec![1]);
+    }
+
+    #[test]
+    fn sentencepiece_vocab_segments_bpe() {
+        let vocab = test_sentencepiece_vocab(SentencePieceModel::Bpe);
+        // Merges in score order: ▁l, er, ow, ▁low.
+        assert_eq!(vocab.segment("lower"), vec![4, 5]);
+        // ▁lo is not a piece, so ▁l and o are not merged.
+        assert_eq!(vocab.segment("lo"), vec![2, 8]);
+    }
+}
@@ -0,0 +1,177 @@
+use std::collections::HashMap;
+
+use crate::chunks::memory::{
+    string_map_heap_size, strings_heap_size, MemoryFootprint, MemoryUsage,
+};
+use crate::chunks::vocab::{check_retained_indices, create_indices, RetainWords, Vocab, WordIndex};
+use crate::error::Result;
+use crate::normalization::{normalize_words, NormalizeVocab, WordNormalization};
+
+/// Vocabulary without subword units.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SimpleVocab {
+    indices: HashMap<String, usize>,
+    words: Vec<String>,
+}
+
+impl SimpleVocab {
+    /// Construct a new simple vocabulary.
+    ///
+    /// Words are assigned indices in the given order.
+    ///
+    /// Panics when there are duplicate words.
+    pub fn new(words: impl Into<Vec<String>>) -> Self {
+        let words = words.into();
+        let indices = create_indices(&words);
+        assert_eq!(
+            words.len(),
+            indices.len(),
+            "words contained duplicate entries."
+        );
+        SimpleVocab { words, indices }
+    }
+
+    /// Add a word to the vocabulary.
+    ///
+    /// Returns the index of the word. `None` is returned and the
+    /// vocabulary is not modified when the word is already in the
+    /// vocabulary.
+    pub fn push(&mut self, word: impl Into<String>) -> Option<usize> {
+        let word = word.into();
+        if self.indices.contains_key(&word) {
+            return None;
+        }
+
+        let idx = self.words.len();
+        self.indices.insert(word.clone(), idx);
+        self.words.push(word);
+
+        Some(idx)
+    }
+
+    /// Get the index of a word, adding the word when it is not in
+    /// the vocabulary.
+    ///
+    /// A word that is added gets the index `words_len()` before the
+    /// insertion.
+    pub fn insert(&mut self, word: &str) -> usize {
+        if let Some(&idx) = self.indices.get(word) {
+            return idx;
+        }
+
+        let idx = self.words.len();
+        self.indices.insert(word.to_owned(), idx);
+        self.words.push(word.to_owned());
+
+        idx
+    }
+
+    /// Reserve capacity for at least `additional` more words.
+    pub fn reserve(&mut self, additional: usize) {
+        self.indices.reserve(additional);
+        self.words.reserve(additional);
+    }
+
+    /// Shrink the capacity of the vocabulary as much as possible.
+    pub fn shrink_to_fit(&mut self) {
+        self.indices.shrink_to_fit();
+        self.words.shrink_to_fit();
+    }
+}
+
+impl Vocab for SimpleVocab {
+    fn idx(&self, word: &str) -> Option<WordIndex> {
+        self.indices.get(word).cloned().map(WordIndex::Word)
+    }
+
+    fn words_len(&self) -> usize {
+        self.indices.len()
+    }
+
+    fn vocab_len(&self) -> usize {
+        self.words_len()
+    }
+
+    fn words(&self) -> &[String] {
+        &self.words
+    }
+}
+
+impl RetainWords for SimpleVocab {
+    fn retain_indices(&self, indices: &[usize]) -> (Self, Vec<usize>) {
+        check_retained_indices(indices, self.words_len());
+        let words = indices
+            .iter()
+            .map(|&idx| self.words[idx].clone())
+            .collect::<Vec<_>>();
+
+        (SimpleVocab::new(words), indices.to_vec())
+    }
+}
+
+impl NormalizeVocab for SimpleVocab {
+    fn normalize_vocab(&mut self, normalization: &dyn WordNormalization) -> Result<()> {
+        normalize_words(&mut self.words, normalization)?;
+        self.indices = create_indices(&self.words);
+        Ok(())
+    }
+}
+
+impl MemoryUsage for SimpleVocab {
+    fn memory_usage(&self) -> MemoryFootprint {
+        MemoryFootprint::resident(
+            string_map_heap_size(&self.indices) + strings_heap_size(&self.words),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::SimpleVocab;
+    use crate::chunks::vocab::{Vocab, WordIndex};
+
+    fn test_simple_vocab() -> SimpleVocab {
+        let words = vec![
+            "this".to_owned(),
+            "is".to_owned(),
+            "a".to_owned(),
+            "test".to_owned(),
+        ];
+
+        SimpleVocab::new(words)
+    }
+
+    #[test]
+    fn simple_vocab_push() {
+        let mut vocab = test_simple_vocab();
+        assert_eq!(vocab.push("vocab"), Some(4));
+        assert_eq!(vocab.push("test"), None);
+        assert_eq!(vocab.words_len(), 5);
+        assert_eq!(vocab.idx("vocab"), Some(WordIndex::Word(4)));
+        assert_eq!(vocab.words()[4], "vocab");
+    }
+
+    #[test]
+    fn simple_vocab_insert() {
+        let mut vocab = test_simple_vocab();
+        vocab.reserve(10);
+        assert_eq!(vocab.insert("vocab"), 4);
+        assert_eq!(vocab.insert("test"), 3);
+        assert_eq!(vocab.insert("vocab"), 4);
+        vocab.shrink_to_fit();
+        assert_eq!(vocab.words_len(), 5);
+        assert_eq!(vocab.idx("vocab"), Some(WordIndex::Word(4)));
+    }
+
+    #[test]
+    fn simple_vocab_iter() {
+        let vocab = test_simple_vocab();
+        let words = vocab.iter().collect::<Vec<_>>();
+        assert_eq!(words.len(), vocab.words_len());
+        for (word, idx) in words {
+            assert_eq!(vocab.words()[idx], word);
+            assert_eq!(vocab.idx(&word), Some(WordIndex::Word(idx)));
+        }
+    }
+}
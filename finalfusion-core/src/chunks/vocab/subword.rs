@@ -0,0 +1,427 @@
+use std::borrow::Cow;
+use std::cmp;
+use std::collections::HashMap;
+
+use crate::chunks::memory::{
+    string_map_heap_size, strings_heap_size, MemoryFootprint, MemoryUsage,
+};
+use crate::chunks::vocab::{check_retained_indices, create_indices, RetainWords, Vocab, WordIndex};
+use crate::error::Result;
+use crate::normalization::{normalize_words, NormalizeVocab, WordNormalization};
+use crate::subword::{
+    ExplicitIndexer, FastTextIndexer, FinalfusionHashIndexer, Indexer,
+    SubwordIndices as StrSubwordIndices,
+};
+
+/// fastText vocabulary with hashed n-grams.
+pub type FastTextSubwordVocab = SubwordVocab<FastTextIndexer>;
+
+/// finalfusion vocabulary with hashed n-grams.
+pub type BucketSubwordVocab = SubwordVocab<FinalfusionHashIndexer>;
+
+/// finalfusion vocabulary with explicit n-grams.
+pub type ExplicitSubwordVocab = SubwordVocab<ExplicitIndexer>;
+
+/// Vocabulary with subword units.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SubwordVocab<I> {
+    indexer: I,
+    indices: HashMap<String, usize>,
+    words: Vec<String>,
+    min_n: u32,
+    max_n: u32,
+    bow: char,
+    eow: char,
+}
+
+impl<I> SubwordVocab<I> {
+    /// Default begin-of-word marker.
+    pub const DEFAULT_BOW: char = '<';
+
+    /// Default end-of-word marker.
+    pub const DEFAULT_EOW: char = '>';
+
+    /// Use the given begin-of-word and end-of-word markers.
+    ///
+    /// Words are surrounded by these markers before n-grams are
+    /// extracted. The markers should match the convention of the
+    /// model that the vocabulary was trained with, otherwise the
+    /// n-grams of unknown words do not map to the trained subword
+    /// embeddings.
+    pub fn with_markers(mut self, bow: char, eow: char) -> Self {
+        self.bow = bow;
+        self.eow = eow;
+        self
+    }
+
+    /// Get the begin-of-word marker.
+    pub fn bow(&self) -> char {
+        self.bow
+    }
+
+    /// Get the end-of-word marker.
+    pub fn eow(&self) -> char {
+        self.eow
+    }
+
+    /// Check whether the vocabulary uses the default markers.
+    #[doc(hidden)]
+    pub fn has_default_markers(&self) -> bool {
+        self.bow == Self::DEFAULT_BOW && self.eow == Self::DEFAULT_EOW
+    }
+}
+
+impl<I> SubwordVocab<I>
+where
+    I: Indexer,
+{
+    /// Construct a new `SubwordVocab`.
+    ///
+    /// Words are assigned indices in the given order. NGrams in range `(min_n..max_n)` are
+    /// considered. The `indexer` is used to look up indices for the NGrams produced by this
+    /// `SubwordVocab`. If `max_n` is zero, no n-grams are used, as in
+    /// fastText models that are trained without subwords.
+    ///
+    /// Panics when there are duplicate words.
+    pub fn new(words: impl Into<Vec<String>>, min_n: u32, max_n: u32, indexer: I) -> Self {
+        let words = words.into();
+        let indices = create_indices(&words);
+        assert_eq!(
+            words.len(),
+            indices.len(),
+            "words contained duplicate entries."
+        );
+
+        SubwordVocab {
+            indices,
+            words,
+            min_n,
+            max_n,
+            indexer,
+            bow: Self::DEFAULT_BOW,
+            eow: Self::DEFAULT_EOW,
+        }
+    }
+
+    /// Get the vocab's indexer.
+    pub fn indexer(&self) -> &I {
+        &self.indexer
+    }
+
+    /// Get the lower bound of the generated ngram lengths.
+    pub fn min_n(&self) -> u32 {
+        self.min_n
+    }
+
+    /// Get the upper bound of the generated ngram lengths.
+    pub fn max_n(&self) -> u32 {
+        self.max_n
+    }
+
+    /// Get the n-grams of a bracketed word with their indices.
+    ///
+    /// N-grams are only generated when `max_n` is at least one and
+    /// not smaller than `min_n`, so a vocabulary that was trained
+    /// without n-grams (`max_n` is zero) has no subword indices.
+    fn bracketed_ngram_indices<'a>(
+        &'a self,
+        bracketed: &'a str,
+    ) -> impl Iterator<Item = (&'a str, Option<u64>)> + 'a {
+        let min_n = cmp::max(self.min_n, 1) as usize;
+        let max_n = self.max_n as usize;
+
+        let ngrams = if min_n <= max_n {
+            Some(bracketed.subword_indices_with_ngrams(min_n, max_n, &self.indexer))
+        } else {
+            None
+        };
+
+        let bow_len = self.bow.len_utf8();
+        let eow_len = self.eow.len_utf8();
+        ngrams.into_iter().flatten().filter(move |(ngram, _)| {
+            // A marker is a unigram at the start or the end.
+            let offset = ngram.as_ptr() as usize - bracketed.as_ptr() as usize;
+            let is_bow = offset == 0 && ngram.len() == bow_len;
+            let is_eow = offset + ngram.len() == bracketed.len() && ngram.len() == eow_len;
+            I::BRACKET_UNIGRAMS || !(is_bow || is_eow)
+        })
+    }
+
+    fn bracket(&self, word: impl AsRef<str>) -> String {
+        let mut bracketed = String::new();
+        bracketed.push(self.bow);
+        bracketed.push_str(word.as_ref());
+        bracketed.push(self.eow);
+
+        bracketed
+    }
+}
+
+impl<I> Vocab for SubwordVocab<I>
+where
+    I: Indexer,
+{
+    fn idx(&self, word: &str) -> Option<WordIndex> {
+        // If the word is known, return its index.
+        if let Some(idx) = self.indices.get(word).cloned() {
+            return Some(WordIndex::Word(idx));
+        }
+
+        // Otherwise, return the subword indices.
+        self.subword_indices(word).map(WordIndex::Subword)
+    }
+
+    fn words_len(&self) -> usize {
+        self.indices.len()
+    }
+
+    fn vocab_len(&self) -> usize {
+        self.words_len() + self.indexer.upper_bound() as usize
+    }
+
+    fn words(&self) -> &[String] {
+        &self.words
+    }
+
+    fn word(&self, idx: usize) -> Option<Cow<'_, str>> {
+        match idx.checked_sub(self.words_len()) {
+            None => Some(Cow::Borrowed(self.words[idx].as_str())),
+            Some(ngram_idx) => self.indexer.ngram(ngram_idx as u64).map(Cow::Borrowed),
+        }
+    }
+}
+
+impl<I> RetainWords for SubwordVocab<I>
+where
+    I: Indexer + Clone,
+{
+    /// Construct a vocabulary with the words at the given indices.
+    ///
+    /// All subword units are retained.
+    fn retain_indices(&self, indices: &[usize]) -> (Self, Vec<usize>) {
+        check_retained_indices(indices, self.words_len());
+        let words = indices
+            .iter()
+            .map(|&idx| self.words[idx].clone())
+            .collect::<Vec<_>>();
+        let vocab = SubwordVocab::new(words, self.min_n, self.max_n, self.indexer.clone())
+            .with_markers(self.bow, self.eow);
+
+        let mut rows = indices.to_vec();
+        rows.extend(self.words_len()..self.vocab_len());
+
+        (vocab, rows)
+    }
+}
+
+impl<I> NormalizeVocab for SubwordVocab<I> {
+    fn normalize_vocab(&mut self, normalization: &dyn WordNormalization) -> Result<()> {
+        normalize_words(&mut self.words, normalization)?;
+        self.indices = create_indices(&self.words);
+        Ok(())
+    }
+}
+
+impl<I> MemoryUsage for SubwordVocab<I>
+where
+    I: MemoryUsage,
+{
+    fn memory_usage(&self) -> MemoryFootprint {
+        self.indexer.memory_usage()
+            + MemoryFootprint::resident(
+                string_map_heap_size(&self.indices) + strings_heap_size(&self.words),
+            )
+    }
+}
+
+/// Get subword indices.
+///
+/// Get the subword ngrams and their indices of a word in the
+/// subword vocabulary.
+pub trait NGramIndices {
+    /// Return the subword ngrams and their indices of a word,
+    /// in the subword vocabulary.
+    fn ngram_indices(&self, word: &str) -> Option<Vec<(String, Option<usize>)>>;
+}
+
+impl<I> NGramIndices for SubwordVocab<I>
+where
+    I: Indexer,
+{
+    fn ngram_indices(&self, word: &str) -> Option<Vec<(String, Option<usize>)>> {
+        let bracketed = self.bracket(word);
+        let indices = self
+            .bracketed_ngram_indices(&bracketed)
+            .map(|(ngram, idx)| {
+                (
+                    ngram.to_owned(),
+                    idx.map(|idx| idx as usize + self.words_len()),
+                )
+            })
+            .collect::<Vec<_>>();
+        if indices.is_empty() {
+            None
+        } else {
+            Some(indices)
+        }
+    }
+}
+
+/// Get subword indices.
+///
+/// Get the subword indices of a token in the subword vocabulary.
+pub trait SubwordIndices {
+    /// Return the subword indices of the subwords of a string,
+    /// according to the subword vocabulary.
+    fn subword_indices(&self, word: &str) -> Option<Vec<usize>>;
+}
+
+impl<I> SubwordIndices for SubwordVocab<I>
+where
+    I: Indexer,
+{
+    fn subword_indices(&self, word: &str) -> Option<Vec<usize>> {
+        let bracketed = self.bracket(word);
+        let indices = self
+            .bracketed_ngram_indices(&bracketed)
+            .filter_map(|(_, idx)| idx)
+            .map(|idx| idx as usize + self.words_len())
+            .collect::<Vec<_>>();
+        if indices.is_empty() {
+            None
+        } else {
+            Some(indices)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{BucketSubwordVocab, FastTextSubwordVocab, NGramIndices, SubwordVocab};
+    use crate::chunks::vocab::{ExplicitSubwordVocab, RetainWords, Vocab};
+    use crate::subword::FastTextIndexer;
+    use crate::subword::{BucketIndexer, ExplicitIndexer, FinalfusionHashIndexer};
+
+    fn test_subword_vocab() -> BucketSubwordVocab {
+        let words = vec![
+            "this".to_owned(),
+            "is".to_owned(),
+            "a".to_owned(),
+            "test".to_owned(),
+        ];
+        let indexer = FinalfusionHashIndexer::new(20);
+        SubwordVocab::new(words, 3, 6, indexer)
+    }
+
+    fn test_ngram_vocab() -> ExplicitSubwordVocab {
+        let words = vec![
+            "this".to_owned(),
+            "is".to_owned(),
+            "a".to_owned(),
+            "test".to_owned(),
+        ];
+
+        let ngrams = vec![
+            ("is>".to_owned(), 0),
+            ("is".to_owned(), 1),
+            ("<t".to_owned(), 2),
+        ];
+
+        ExplicitSubwordVocab::new(words, 2, 3, ExplicitIndexer::new_with_indices(ngrams))
+    }
+
+    #[test]
+    fn subword_vocab_uses_ngram_lengths() {
+        let words = vec!["this".to_owned()];
+        let ngrams = |vocab: &FastTextSubwordVocab, word| {
+            vocab.ngram_indices(word).map(|ngrams| {
+                let mut ngrams = ngrams
+                    .into_iter()
+                    .map(|(ngram, _)| ngram)
+                    .collect::<Vec<_>>();
+                ngrams.sort();
+                ngrams
+            })
+        };
+
+        let vocab = SubwordVocab::new(words.clone(), 1, 2, FastTextIndexer::new(20));
+        assert_eq!(
+            ngrams(&vocab, "ab"),
+            Some(vec![
+                "<a".to_owned(),
+                "a".to_owned(),
+                "ab".to_owned(),
+                "b".to_owned(),
+                "b>".to_owned()
+            ])
+        );
+
+        // fastText models without n-grams have a maximum length of zero.
+        let vocab = SubwordVocab::new(words.clone(), 0, 0, FastTextIndexer::new(20));
+        assert_eq!(ngrams(&vocab, "ab"), None);
+        assert_eq!(vocab.idx("ab"), None);
+
+        // The markers are n-grams in finalfusion vocabularies.
+        let vocab = SubwordVocab::new(words, 1, 1, FinalfusionHashIndexer::new(20));
+        assert_eq!(vocab.ngram_indices("a").unwrap().len(), 3);
+    }
+
+    #[test]
+    fn subword_vocab_uses_markers() {
+        let vocab = SubwordVocab::new(vec!["this".to_owned()], 2, 2, FastTextIndexer::new(20))
+            .with_markers('⟨', '⟩');
+        let ngrams = vocab
+            .ngram_indices("ab")
+            .unwrap()
+            .into_iter()
+            .map(|(ngram, _)| ngram)
+            .collect::<Vec<_>>();
+        assert_eq!(ngrams, vec!["⟨a", "ab", "b⟩"]);
+
+        // Multi-byte markers are n-grams in finalfusion vocabularies.
+        let vocab = SubwordVocab::new(
+            vec!["this".to_owned()],
+            1,
+            1,
+            FinalfusionHashIndexer::new(20),
+        )
+        .with_markers('⟨', '⟩');
+        assert_eq!(vocab.ngram_indices("a").unwrap().len(), 3);
+        let vocab = SubwordVocab::new(vec!["this".to_owned()], 1, 1, FastTextIndexer::new(20))
+            .with_markers('⟨', '⟩');
+        assert_eq!(vocab.ngram_indices("a").unwrap().len(), 1);
+
+        let (retained, _) = vocab.retain_indices(&[0]);
+        assert_eq!(retained.bow(), '⟨');
+        assert_eq!(retained.eow(), '⟩');
+    }
+
+    #[test]
+    fn subword_vocab_word_by_index() {
+        let vocab = test_ngram_vocab();
+        assert_eq!(vocab.word(1).as_deref(), Some("is"));
+        assert_eq!(vocab.word(4).as_deref(), Some("is>"));
+        assert_eq!(vocab.word(6).as_deref(), Some("<t"));
+        assert_eq!(vocab.word(7).as_deref(), None);
+
+        let ngrams = vec![
+            ("is>".to_owned(), 3),
+            ("is".to_owned(), 5),
+            ("<t".to_owned(), 3),
+        ];
+        let vocab = ExplicitSubwordVocab::new(
+            vec!["this".to_owned()],
+            2,
+            3,
+            ExplicitIndexer::new_with_indices(ngrams),
+        );
+        assert_eq!(vocab.word(1).as_deref(), Some("is>"));
+        assert_eq!(vocab.word(2).as_deref(), Some("is"));
+        assert_eq!(vocab.word(3).as_deref(), None);
+
+        let vocab = test_subword_vocab();
+        assert_eq!(vocab.word(3).as_deref(), Some("test"));
+        assert_eq!(vocab.word(4).as_deref(), None);
+    }
+}
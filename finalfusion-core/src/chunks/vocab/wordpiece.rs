@@ -0,0 +1,242 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use crate::chunks::memory::{
+    string_map_heap_size, strings_heap_size, MemoryFootprint, MemoryUsage,
+};
+use crate::chunks::vocab::{
+    check_retained_indices, create_indices, RetainWords, SubwordIndices, Vocab, WordIndex,
+};
+use crate::error::Result;
+use crate::normalization::{normalize_words, NormalizeVocab, WordNormalization};
+
+/// Vocabulary with WordPiece units.
+///
+/// Unknown words are segmented into pieces with greedy longest-match
+/// first segmentation, as in BERT. Pieces that do not start a word
+/// are prefixed with a continuation prefix, `##` by convention. The
+/// embedding of an unknown word is the average of the embeddings of
+/// its pieces. The embeddings of the pieces are stored after the
+/// embeddings of the words.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct WordPieceVocab {
+    indices: HashMap<String, usize>,
+    words: Vec<String>,
+    piece_indices: HashMap<String, usize>,
+    pieces: Vec<String>,
+    continuation_prefix: String,
+}
+
+impl WordPieceVocab {
+    /// The conventional prefix of continuation pieces.
+    pub const CONTINUATION_PREFIX: &'static str = "##";
+
+    /// Construct a new WordPiece vocabulary.
+    ///
+    /// Words and pieces are assigned indices in the given order.
+    /// Continuation pieces must start with `continuation_prefix`.
+    ///
+    /// Panics when there are duplicate words or pieces.
+    pub fn new(
+        words: impl Into<Vec<String>>,
+        pieces: impl Into<Vec<String>>,
+        continuation_prefix: impl Into<String>,
+    ) -> Self {
+        let words = words.into();
+        let indices = create_indices(&words);
+        assert_eq!(
+            words.len(),
+            indices.len(),
+            "words contained duplicate entries."
+        );
+
+        let pieces = pieces.into();
+        let piece_indices = create_indices(&pieces);
+        assert_eq!(
+            pieces.len(),
+            piece_indices.len(),
+            "pieces contained duplicate entries."
+        );
+
+        WordPieceVocab {
+            indices,
+            words,
+            piece_indices,
+            pieces,
+            continuation_prefix: continuation_prefix.into(),
+        }
+    }
+
+    /// Get the prefix of continuation pieces.
+    pub fn continuation_prefix(&self) -> &str {
+        &self.continuation_prefix
+    }
+
+    /// Get the pieces.
+    pub fn pieces(&self) -> &[String] {
+        &self.pieces
+    }
+
+    /// Segment a word into pieces.
+    ///
+    /// Returns the indices of the pieces among the pieces of the
+    /// vocabulary. `None` is returned when the word cannot be
+    /// segmented, i.e. when at some position no piece matches.
+    pub fn segment(&self, word: &str) -> Option<Vec<usize>> {
+        if word.is_empty() {
+            return None;
+        }
+
+        let mut pieces = Vec::new();
+        let mut candidate = String::new();
+        let mut start = 0;
+        while start < word.len() {
+            // Find the longest piece that matches at start.
+            let piece = word[start..]
+                .char_indices()
+                .map(|(offset, ch)| start + offset + ch.len_utf8())
+                .rev()
+                .find_map(|end| {
+                    candidate.clear();
+                    if start != 0 {
+                        candidate.push_str(&self.continuation_prefix);
+                    }
+                    candidate.push_str(&word[start..end]);
+                    self.piece_indices.get(&candidate).map(|&idx| (idx, end))
+                });
+
+            let (idx, end) = piece?;
+            pieces.push(idx);
+            start = end;
+        }
+
+        Some(pieces)
+    }
+}
+
+impl Vocab for WordPieceVocab {
+    fn idx(&self, word: &str) -> Option<WordIndex> {
+        // If the word is known, return its index.
+        if let Some(idx) = self.indices.get(word).cloned() {
+            return Some(WordIndex::Word(idx));
+        }
+
+        // Otherwise, return the indices of its pieces.
+        self.subword_indices(word).map(WordIndex::Subword)
+    }
+
+    fn words_len(&self) -> usize {
+        self.indices.len()
+    }
+
+    fn vocab_len(&self) -> usize {
+        self.words_len() + self.pieces.len()
+    }
+
+    fn words(&self) -> &[String] {
+        &self.words
+    }
+
+    fn word(&self, idx: usize) -> Option<Cow<'_, str>> {
+        match idx.checked_sub(self.words_len()) {
+            None => Some(Cow::Borrowed(self.words[idx].as_str())),
+            Some(piece_idx) => self
+                .pieces
+                .get(piece_idx)
+                .map(|piece| Cow::Borrowed(piece.as_str())),
+        }
+    }
+}
+
+impl SubwordIndices for WordPieceVocab {
+    /// Return the indices of the pieces of a word.
+    fn subword_indices(&self, word: &str) -> Option<Vec<usize>> {
+        self.segment(word).map(|pieces| {
+            pieces
+                .into_iter()
+                .map(|idx| idx + self.words_len())
+                .collect()
+        })
+    }
+}
+
+impl RetainWords for WordPieceVocab {
+    /// Construct a vocabulary with the words at the given indices.
+    ///
+    /// All pieces are retained.
+    fn retain_indices(&self, indices: &[usize]) -> (Self, Vec<usize>) {
+        check_retained_indices(indices, self.words_len());
+        let words = indices
+            .iter()
+            .map(|&idx| self.words[idx].clone())
+            .collect::<Vec<_>>();
+        let vocab =
+            WordPieceVocab::new(words, self.pieces.clone(), self.continuation_prefix.clone());
+
+        let mut rows = indices.to_vec();
+        rows.extend(self.words_len()..self.vocab_len());
+
+        (vocab, rows)
+    }
+}
+
+impl NormalizeVocab for WordPieceVocab {
+    fn normalize_vocab(&mut self, normalization: &dyn WordNormalization) -> Result<()> {
+        normalize_words(&mut self.words, normalization)?;
+        self.indices = create_indices(&self.words);
+        Ok(())
+    }
+}
+
+impl MemoryUsage for WordPieceVocab {
+    fn memory_usage(&self) -> MemoryFootprint {
+        MemoryFootprint::resident(
+            string_map_heap_size(&self.indices)
+                + strings_heap_size(&self.words)
+                + string_map_heap_size(&self.piece_indices)
+                + strings_heap_size(&self.pieces)
+                + self.continuation_prefix.capacity(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::WordPieceVocab;
+    use crate::chunks::vocab::{Vocab, WordIndex};
+
+    fn strings(strs: &[&str]) -> Vec<String> {
+        strs.iter().map(|s| (*s).to_owned()).collect()
+    }
+
+    fn test_wordpiece_vocab() -> WordPieceVocab {
+        WordPieceVocab::new(
+            strings(&["affable", "want"]),
+            strings(&["un", "u", "##n", "##aff", "##able", "##a", "##ff", "##ä"]),
+            WordPieceVocab::CONTINUATION_PREFIX,
+        )
+    }
+
+    #[test]
+    fn wordpiece_vocab_segments_greedily() {
+        let vocab = test_wordpiece_vocab();
+        assert_eq!(vocab.segment("unaffable"), Some(vec![0, 3, 4]));
+        assert_eq!(vocab.segment("unaffä"), Some(vec![0, 3, 7]));
+        assert_eq!(vocab.segment("uaff"), Some(vec![1, 3]));
+        assert_eq!(vocab.segment("unx"), None);
+        assert_eq!(vocab.segment("aff"), None);
+        assert_eq!(vocab.segment(""), None);
+
+        assert_eq!(vocab.idx("want"), Some(WordIndex::Word(1)));
+        assert_eq!(
+            vocab.idx("unaffable"),
+            Some(WordIndex::Subword(vec![2, 5, 6]))
+        );
+        assert_eq!(vocab.idx("wants"), None);
+        assert_eq!(vocab.vocab_len(), 10);
+        assert_eq!(vocab.word(1).as_deref(), Some("want"));
+        assert_eq!(vocab.word(5).as_deref(), Some("##aff"));
+        assert_eq!(vocab.word(10).as_deref(), None);
+    }
+}
@@ -0,0 +1,299 @@
+use std::borrow::Cow;
+
+use crate::chunks::memory::{MemoryFootprint, MemoryUsage};
+use crate::chunks::vocab::subword::{
+    BucketSubwordVocab, ExplicitSubwordVocab, FastTextSubwordVocab,
+};
+use crate::chunks::vocab::{
+    AliasVocab, BpeVocab, ByteFallbackVocab, FrontCodedVocab, FstVocab, MmapVocab, NamespacedVocab,
+    RetainWords, SentencePieceVocab, SimpleVocab, Vocab, VocabIter, WordIndex, WordPieceVocab,
+};
+use crate::error::Result;
+use crate::normalization::{NormalizeVocab, WordNormalization};
+
+/// Vocabulary types wrapper.
+///
+/// This crate makes it possible to create fine-grained embedding
+/// types, such as `Embeddings<SimpleVocab, NdArray>` or
+/// `Embeddings<SubwordVocab, QuantizedArray>`. However, in some cases
+/// it is more pleasant to have a single type that covers all
+/// vocabulary and storage types. `VocabWrap` and `StorageWrap` wrap
+/// all the vocabularies and storage types known to this crate such
+/// that the type `Embeddings<VocabWrap, StorageWrap>` covers all
+/// variations.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum VocabWrap {
+    SimpleVocab(SimpleVocab),
+    ExplicitSubwordVocab(ExplicitSubwordVocab),
+    FastTextSubwordVocab(FastTextSubwordVocab),
+    BucketSubwordVocab(BucketSubwordVocab),
+    NamespacedVocab(NamespacedVocab),
+    FstVocab(FstVocab),
+    FrontCodedVocab(FrontCodedVocab),
+    MmapVocab(MmapVocab),
+    BpeVocab(BpeVocab),
+    WordPieceVocab(WordPieceVocab),
+    SentencePieceVocab(SentencePieceVocab),
+    ByteFallbackVocab(Box<ByteFallbackVocab<VocabWrap>>),
+    AliasVocab(Box<AliasVocab<VocabWrap>>),
+}
+
+impl Vocab for VocabWrap {
+    fn idx(&self, word: &str) -> Option<WordIndex> {
+        match self {
+            VocabWrap::SimpleVocab(inner) => inner.idx(word),
+            VocabWrap::ExplicitSubwordVocab(inner) => inner.idx(word),
+            VocabWrap::FastTextSubwordVocab(inner) => inner.idx(word),
+            VocabWrap::BucketSubwordVocab(inner) => inner.idx(word),
+            VocabWrap::NamespacedVocab(inner) => inner.idx(word),
+            VocabWrap::FstVocab(inner) => inner.idx(word),
+            VocabWrap::FrontCodedVocab(inner) => inner.idx(word),
+            VocabWrap::MmapVocab(inner) => inner.idx(word),
+            VocabWrap::BpeVocab(inner) => inner.idx(word),
+            VocabWrap::WordPieceVocab(inner) => inner.idx(word),
+            VocabWrap::SentencePieceVocab(inner) => inner.idx(word),
+            VocabWrap::ByteFallbackVocab(inner) => inner.idx(word),
+            VocabWrap::AliasVocab(inner) => inner.idx(word),
+        }
+    }
+
+    /// Get the vocabulary size.
+    fn words_len(&self) -> usize {
+        match self {
+            VocabWrap::SimpleVocab(inner) => inner.words_len(),
+            VocabWrap::ExplicitSubwordVocab(inner) => inner.words_len(),
+            VocabWrap::FastTextSubwordVocab(inner) => inner.words_len(),
+            VocabWrap::BucketSubwordVocab(inner) => inner.words_len(),
+            VocabWrap::NamespacedVocab(inner) => inner.words_len(),
+            VocabWrap::FstVocab(inner) => inner.words_len(),
+            VocabWrap::FrontCodedVocab(inner) => inner.words_len(),
+            VocabWrap::MmapVocab(inner) => inner.words_len(),
+            VocabWrap::BpeVocab(inner) => inner.words_len(),
+            VocabWrap::WordPieceVocab(inner) => inner.words_len(),
+            VocabWrap::SentencePieceVocab(inner) => inner.words_len(),
+            VocabWrap::ByteFallbackVocab(inner) => inner.words_len(),
+            VocabWrap::AliasVocab(inner) => inner.words_len(),
+        }
+    }
+
+    fn vocab_len(&self) -> usize {
+        match self {
+            VocabWrap::SimpleVocab(inner) => inner.vocab_len(),
+            VocabWrap::ExplicitSubwordVocab(inner) => inner.vocab_len(),
+            VocabWrap::FastTextSubwordVocab(inner) => inner.vocab_len(),
+            VocabWrap::BucketSubwordVocab(inner) => inner.vocab_len(),
+            VocabWrap::NamespacedVocab(inner) => inner.vocab_len(),
+            VocabWrap::FstVocab(inner) => inner.vocab_len(),
+            VocabWrap::FrontCodedVocab(inner) => inner.vocab_len(),
+            VocabWrap::MmapVocab(inner) => inner.vocab_len(),
+            VocabWrap::BpeVocab(inner) => inner.vocab_len(),
+            VocabWrap::WordPieceVocab(inner) => inner.vocab_len(),
+            VocabWrap::SentencePieceVocab(inner) => inner.vocab_len(),
+            VocabWrap::ByteFallbackVocab(inner) => inner.vocab_len(),
+            VocabWrap::AliasVocab(inner) => inner.vocab_len(),
+        }
+    }
+
+    /// Get the words in the vocabulary.
+    fn words(&self) -> &[String] {
+        match self {
+            VocabWrap::SimpleVocab(inner) => inner.words(),
+            VocabWrap::ExplicitSubwordVocab(inner) => inner.words(),
+            VocabWrap::FastTextSubwordVocab(inner) => inner.words(),
+            VocabWrap::BucketSubwordVocab(inner) => inner.words(),
+            VocabWrap::NamespacedVocab(inner) => inner.words(),
+            VocabWrap::FstVocab(inner) => inner.words(),
+            VocabWrap::FrontCodedVocab(inner) => inner.words(),
+            VocabWrap::MmapVocab(inner) => inner.words(),
+            VocabWrap::BpeVocab(inner) => inner.words(),
+            VocabWrap::WordPieceVocab(inner) => inner.words(),
+            VocabWrap::SentencePieceVocab(inner) => inner.words(),
+            VocabWrap::ByteFallbackVocab(inner) => inner.words(),
+            VocabWrap::AliasVocab(inner) => inner.words(),
+        }
+    }
+
+    fn iter(&self) -> VocabIter<'_> {
+        match self {
+            VocabWrap::SimpleVocab(inner) => inner.iter(),
+            VocabWrap::ExplicitSubwordVocab(inner) => inner.iter(),
+            VocabWrap::FastTextSubwordVocab(inner) => inner.iter(),
+            VocabWrap::BucketSubwordVocab(inner) => inner.iter(),
+            VocabWrap::NamespacedVocab(inner) => inner.iter(),
+            VocabWrap::FstVocab(inner) => inner.iter(),
+            VocabWrap::FrontCodedVocab(inner) => inner.iter(),
+            VocabWrap::MmapVocab(inner) => inner.iter(),
+            VocabWrap::BpeVocab(inner) => inner.iter(),
+            VocabWrap::WordPieceVocab(inner) => inner.iter(),
+            VocabWrap::SentencePieceVocab(inner) => inner.iter(),
+            VocabWrap::ByteFallbackVocab(inner) => inner.iter(),
+            VocabWrap::AliasVocab(inner) => inner.iter(),
+        }
+    }
+
+    fn word(&self, idx: usize) -> Option<Cow<'_, str>> {
+        match self {
+            VocabWrap::SimpleVocab(inner) => inner.word(idx),
+            VocabWrap::ExplicitSubwordVocab(inner) => inner.word(idx),
+            VocabWrap::FastTextSubwordVocab(inner) => inner.word(idx),
+            VocabWrap::BucketSubwordVocab(inner) => inner.word(idx),
+            VocabWrap::NamespacedVocab(inner) => inner.word(idx),
+            VocabWrap::FstVocab(inner) => inner.word(idx),
+            VocabWrap::FrontCodedVocab(inner) => inner.word(idx),
+            VocabWrap::MmapVocab(inner) => inner.word(idx),
+            VocabWrap::BpeVocab(inner) => inner.word(idx),
+            VocabWrap::WordPieceVocab(inner) => inner.word(idx),
+            VocabWrap::SentencePieceVocab(inner) => inner.word(idx),
+            VocabWrap::ByteFallbackVocab(inner) => inner.word(idx),
+            VocabWrap::AliasVocab(inner) => inner.word(idx),
+        }
+    }
+}
+
+impl RetainWords for VocabWrap {
+    fn retain_indices(&self, indices: &[usize]) -> (Self, Vec<usize>) {
+        fn wrap<V: Into<VocabWrap>>((vocab, rows): (V, Vec<usize>)) -> (VocabWrap, Vec<usize>) {
+            (vocab.into(), rows)
+        }
+
+        match self {
+            VocabWrap::SimpleVocab(inner) => wrap(inner.retain_indices(indices)),
+            VocabWrap::ExplicitSubwordVocab(inner) => wrap(inner.retain_indices(indices)),
+            VocabWrap::FastTextSubwordVocab(inner) => wrap(inner.retain_indices(indices)),
+            VocabWrap::BucketSubwordVocab(inner) => wrap(inner.retain_indices(indices)),
+            VocabWrap::NamespacedVocab(inner) => wrap(inner.retain_indices(indices)),
+            VocabWrap::FstVocab(inner) => wrap(inner.retain_indices(indices)),
+            VocabWrap::FrontCodedVocab(inner) => wrap(inner.retain_indices(indices)),
+            VocabWrap::MmapVocab(inner) => wrap(inner.retain_indices(indices)),
+            VocabWrap::BpeVocab(inner) => wrap(inner.retain_indices(indices)),
+            VocabWrap::WordPieceVocab(inner) => wrap(inner.retain_indices(indices)),
+            VocabWrap::SentencePieceVocab(inner) => wrap(inner.retain_indices(indices)),
+            VocabWrap::ByteFallbackVocab(inner) => wrap(inner.retain_indices(indices)),
+            VocabWrap::AliasVocab(inner) => wrap(inner.retain_indices(indices)),
+        }
+    }
+}
+
+impl NormalizeVocab for VocabWrap {
+    fn normalize_vocab(&mut self, normalization: &dyn WordNormalization) -> Result<()> {
+        match self {
+            VocabWrap::SimpleVocab(inner) => inner.normalize_vocab(normalization),
+            VocabWrap::ExplicitSubwordVocab(inner) => inner.normalize_vocab(normalization),
+            VocabWrap::FastTextSubwordVocab(inner) => inner.normalize_vocab(normalization),
+            VocabWrap::BucketSubwordVocab(inner) => inner.normalize_vocab(normalization),
+            VocabWrap::NamespacedVocab(inner) => inner.normalize_vocab(normalization),
+            VocabWrap::FstVocab(inner) => inner.normalize_vocab(normalization),
+            VocabWrap::FrontCodedVocab(inner) => inner.normalize_vocab(normalization),
+            VocabWrap::MmapVocab(inner) => inner.normalize_vocab(normalization),
+            VocabWrap::BpeVocab(inner) => inner.normalize_vocab(normalization),
+            VocabWrap::WordPieceVocab(inner) => inner.normalize_vocab(normalization),
+            VocabWrap::SentencePieceVocab(inner) => inner.normalize_vocab(normalization),
+            VocabWrap::ByteFallbackVocab(inner) => inner.normalize_vocab(normalization),
+            VocabWrap::AliasVocab(inner) => inner.normalize_vocab(normalization),
+        }
+    }
+}
+
+impl MemoryUsage for VocabWrap {
+    fn memory_usage(&self) -> MemoryFootprint {
+        match self {
+            VocabWrap::SimpleVocab(inner) => inner.memory_usage(),
+            VocabWrap::ExplicitSubwordVocab(inner) => inner.memory_usage(),
+            VocabWrap::FastTextSubwordVocab(inner) => inner.memory_usage(),
+            VocabWrap::BucketSubwordVocab(inner) => inner.memory_usage(),
+            VocabWrap::NamespacedVocab(inner) => inner.memory_usage(),
+            VocabWrap::FstVocab(inner) => inner.memory_usage(),
+            VocabWrap::FrontCodedVocab(inner) => inner.memory_usage(),
+            VocabWrap::MmapVocab(inner) => inner.memory_usage(),
+            VocabWrap::BpeVocab(inner) => inner.memory_usage(),
+            VocabWrap::WordPieceVocab(inner) => inner.memory_usage(),
+            VocabWrap::SentencePieceVocab(inner) => inner.memory_usage(),
+            VocabWrap::ByteFallbackVocab(inner) => inner.memory_usage(),
+            VocabWrap::AliasVocab(inner) => inner.memory_usage(),
+        }
+    }
+}
+
+impl From<SimpleVocab> for VocabWrap {
+    fn from(v: SimpleVocab) -> Self {
+        VocabWrap::SimpleVocab(v)
+    }
+}
+
+impl From<FastTextSubwordVocab> for VocabWrap {
+    fn from(v: FastTextSubwordVocab) -> Self {
+        VocabWrap::FastTextSubwordVocab(v)
+    }
+}
+
+impl From<BucketSubwordVocab> for VocabWrap {
+    fn from(v: BucketSubwordVocab) -> Self {
+        VocabWrap::BucketSubwordVocab(v)
+    }
+}
+
+impl From<ExplicitSubwordVocab> for VocabWrap {
+    fn from(v: ExplicitSubwordVocab) -> Self {
+        VocabWrap::ExplicitSubwordVocab(v)
+    }
+}
+
+impl From<NamespacedVocab> for VocabWrap {
+    fn from(v: NamespacedVocab) -> Self {
+        VocabWrap::NamespacedVocab(v)
+    }
+}
+
+impl From<FstVocab> for VocabWrap {
+    fn from(v: FstVocab) -> Self {
+        VocabWrap::FstVocab(v)
+    }
+}
+
+impl From<FrontCodedVocab> for VocabWrap {
+    fn from(v: FrontCodedVocab) -> Self {
+        VocabWrap::FrontCodedVocab(v)
+    }
+}
+
+impl From<MmapVocab> for VocabWrap {
+    fn from(v: MmapVocab) -> Self {
+        VocabWrap::MmapVocab(v)
+    }
+}
+
+impl From<BpeVocab> for VocabWrap {
+    fn from(v: BpeVocab) -> Self {
+        VocabWrap::BpeVocab(v)
+    }
+}
+
+impl From<WordPieceVocab> for VocabWrap {
+    fn from(v: WordPieceVocab) -> Self {
+        VocabWrap::WordPieceVocab(v)
+    }
+}
+
+impl From<SentencePieceVocab> for VocabWrap {
+    fn from(v: SentencePieceVocab) -> Self {
+        VocabWrap::SentencePieceVocab(v)
+    }
+}
+
+impl<V> From<ByteFallbackVocab<V>> for VocabWrap
+where
+    V: Into<VocabWrap>,
+{
+    fn from(v: ByteFallbackVocab<V>) -> Self {
+        VocabWrap::ByteFallbackVocab(Box::new(ByteFallbackVocab::new(v.into_inner().into())))
+    }
+}
+
+impl<V> From<AliasVocab<V>> for VocabWrap
+where
+    V: Into<VocabWrap>,
+{
+    fn from(v: AliasVocab<V>) -> Self {
+        VocabWrap::AliasVocab(Box::new(v.map_inner(Into::into)))
+    }
+}
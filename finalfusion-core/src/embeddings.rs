@@ -0,0 +1,2210 @@
+//! Word embeddings.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::mem;
+use std::sync::Arc;
+
+use ndarray::{
+    s, Array1, Array2, ArrayView1, ArrayViewMut1, Axis, CowArray, ErrorKind as ShapeErrorKind, Ix1,
+    ShapeError,
+};
+use rand::{RngCore, SeedableRng};
+use rand_xorshift::XorShiftRng;
+use reductive::pq::TrainPQ;
+
+use crate::chunks::counts::WordCounts;
+use crate::chunks::memory::{MemoryFootprint, MemoryUsage};
+use crate::chunks::metadata::Metadata;
+use crate::chunks::norms::NdNorms;
+use crate::chunks::storage::{
+    AccessPattern, Advise, DedupArray, LayeredStorage, LockMemory, MmapArray, MmapQuantizedArray,
+    NdArray, PreadArray, Prune as PruneStorage, Quantize as QuantizeStorage,
+    QuantizeResidual as QuantizeResidualStorage, QuantizedArray, ResidualQuantizedArray,
+    SelectRows, Storage, StorageView, StorageViewWrap, StorageWrap,
+    TryQuantize as TryQuantizeStorage,
+};
+use crate::chunks::unknown::UnknownChunk;
+use crate::chunks::vocab::{
+    AliasVocab, BpeVocab, BucketSubwordVocab, ByteFallbackVocab, ExplicitSubwordVocab,
+    FastTextSubwordVocab, FrontCodedVocab, FstVocab, LanguageTagFormat, LanguageVocab,
+    LayeredVocab, MmapVocab, NamespacedVocab, RetainWords, SentencePieceVocab, SimpleVocab, Vocab,
+    VocabIter, VocabWrap, WordIndex, WordPieceVocab, N_BYTE_UNITS,
+};
+use crate::error::{Error, ErrorKind, Result};
+use crate::normalization::{NormalizeVocab, WordNormalization};
+use crate::transform::LookupTransform;
+use crate::util::l2_normalize;
+
+/// Word embeddings.
+///
+/// This data structure stores word embeddings (also known as *word vectors*)
+/// and provides some useful methods on the embeddings, such as similarity
+/// and analogy queries.
+#[derive(Clone, Debug)]
+pub struct Embeddings<V, S> {
+    metadata: Option<Metadata>,
+    storage: S,
+    vocab: V,
+    norms: Option<NdNorms>,
+    counts: Option<WordCounts>,
+    transform: Option<Arc<dyn LookupTransform>>,
+    normalization: Option<Arc<dyn WordNormalization>>,
+    unknown_chunks: Vec<UnknownChunk>,
+}
+
+impl<V, S> Embeddings<V, S>
+where
+    V: Vocab,
+    S: Storage,
+{
+    /// Construct an embeddings from a vocabulary, storage, and norms.
+    ///
+    /// The embeddings for known words **must** be
+    /// normalized. However, this is not verified due to the high
+    /// computational cost.
+    pub fn new(metadata: Option<Metadata>, vocab: V, storage: S, norms: NdNorms) -> Self {
+        assert_eq!(
+            vocab.words_len(),
+            norms.len(),
+            "Vocab and norms do not have the same length"
+        );
+        assert_eq!(
+            vocab.vocab_len(),
+            storage.shape().0,
+            "Max vocab index must match number of rows in the embedding matrix."
+        );
+
+        Embeddings {
+            metadata,
+            vocab,
+            storage,
+            norms: Some(norms),
+            counts: None,
+            transform: None,
+            normalization: None,
+            unknown_chunks: Vec::new(),
+        }
+    }
+}
+
+impl<V, S> Embeddings<V, S> {
+    /// Construct embeddings from all of their parts.
+    ///
+    /// The lookup transform and word normalization are not set.
+    #[doc(hidden)]
+    pub fn from_parts(
+        metadata: Option<Metadata>,
+        vocab: V,
+        storage: S,
+        norms: Option<NdNorms>,
+        counts: Option<WordCounts>,
+        unknown_chunks: Vec<UnknownChunk>,
+    ) -> Self {
+        Embeddings {
+            metadata,
+            vocab,
+            storage,
+            norms,
+            counts,
+            transform: None,
+            normalization: None,
+            unknown_chunks,
+        }
+    }
+
+    #[doc(hidden)]
+    pub fn new_without_norms(metadata: Option<Metadata>, vocab: V, storage: S) -> Self {
+        Embeddings {
+            metadata,
+            vocab,
+            storage,
+            norms: None,
+            counts: None,
+            transform: None,
+            normalization: None,
+            unknown_chunks: Vec::new(),
+        }
+    }
+
+    /// Decompose embeddings in its vocabulary, storage, and
+    /// optionally norms.
+    ///
+    /// The word counts, unknown chunks, lookup transform, and word
+    /// normalization, if any, are discarded.
+    pub fn into_parts(self) -> (Option<Metadata>, V, S, Option<NdNorms>) {
+        (self.metadata, self.vocab, self.storage, self.norms)
+    }
+
+    /// Get metadata.
+    pub fn metadata(&self) -> Option<&Metadata> {
+        self.metadata.as_ref()
+    }
+
+    /// Get metadata mutably.
+    pub fn metadata_mut(&mut self) -> Option<&mut Metadata> {
+        self.metadata.as_mut()
+    }
+
+    /// Get the chunks that are unknown to this version of finalfusion.
+    ///
+    /// Unknown chunks of a file are retained when embeddings are read
+    /// and written after the other chunks. Custom chunks are retained
+    /// as unknown chunks when they are read without a registry.
+    /// Unknown chunks are retained by conversions that preserve the
+    /// words and rows of the embeddings, such as quantization. Other
+    /// operations, such as pruning the vocabulary, drop unknown chunks,
+    /// since their data could no longer match the embeddings.
+    pub fn unknown_chunks(&self) -> &[UnknownChunk] {
+        &self.unknown_chunks
+    }
+
+    /// Set the unknown chunks.
+    ///
+    /// Returns the previously-stored unknown chunks.
+    pub fn set_unknown_chunks(&mut self, mut chunks: Vec<UnknownChunk>) -> Vec<UnknownChunk> {
+        mem::swap(&mut self.unknown_chunks, &mut chunks);
+        chunks
+    }
+
+    /// Get embedding norms.
+    pub fn norms(&self) -> Option<&NdNorms> {
+        self.norms.as_ref()
+    }
+
+    /// Get word counts.
+    pub fn counts(&self) -> Option<&WordCounts> {
+        self.counts.as_ref()
+    }
+
+    /// Set metadata.
+    ///
+    /// Returns the previously-stored metadata.
+    pub fn set_metadata(&mut self, mut metadata: Option<Metadata>) -> Option<Metadata> {
+        mem::swap(&mut self.metadata, &mut metadata);
+        metadata
+    }
+
+    /// Set the lookup transform.
+    ///
+    /// The transform is applied to embeddings that are retrieved
+    /// through lookups, iteration, and similarity queries. The
+    /// embedding storage itself is not modified, so the transform is
+    /// not persisted when the embeddings are written. Use `None` to
+    /// remove the transform.
+    ///
+    /// Returns the previously-installed transform.
+    pub fn set_transform(
+        &mut self,
+        mut transform: Option<Arc<dyn LookupTransform>>,
+    ) -> Option<Arc<dyn LookupTransform>> {
+        mem::swap(&mut self.transform, &mut transform);
+        transform
+    }
+
+    /// Get the lookup transform.
+    pub fn transform(&self) -> Option<&dyn LookupTransform> {
+        self.transform.as_deref()
+    }
+
+    /// Set the word normalization.
+    ///
+    /// The normalization is applied to query words before they are
+    /// looked up in the vocabulary. The vocabulary itself is not
+    /// modified, use `normalize_vocab` to normalize the vocabulary
+    /// as well. Use `None` to remove the normalization.
+    ///
+    /// Returns the previously-installed normalization.
+    pub fn set_normalization(
+        &mut self,
+        mut normalization: Option<Arc<dyn WordNormalization>>,
+    ) -> Option<Arc<dyn WordNormalization>> {
+        mem::swap(&mut self.normalization, &mut normalization);
+        normalization
+    }
+
+    /// Get the word normalization.
+    pub fn normalization(&self) -> Option<&dyn WordNormalization> {
+        self.normalization.as_deref()
+    }
+
+    /// Get the embedding storage.
+    ///
+    /// The storage contains the untransformed embeddings.
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+
+    /// Get the embedding storage mutably.
+    ///
+    /// The storage must keep the embeddings of the vocabulary.
+    #[doc(hidden)]
+    pub fn storage_mut(&mut self) -> &mut S {
+        &mut self.storage
+    }
+
+    /// Get the vocabulary.
+    pub fn vocab(&self) -> &V {
+        &self.vocab
+    }
+}
+
+impl<V, S> Embeddings<V, S>
+where
+    V: NormalizeVocab,
+{
+    /// Normalize the vocabulary with the installed word normalization.
+    ///
+    /// This is typically done directly after loading the embeddings,
+    /// so that the vocabulary and query words are normalized in the
+    /// same manner. Words keep their indices, so the storage and
+    /// norms are not modified. Does nothing when no normalization is
+    /// installed.
+    ///
+    /// Returns an error when words of the vocabulary have the same
+    /// normalized form, the vocabulary is not modified in that case.
+    /// Use `merge_normalized` to merge the embeddings of such words.
+    pub fn normalize_vocab(&mut self) -> Result<()> {
+        match self.normalization.as_deref() {
+            Some(normalization) => self.vocab.normalize_vocab(normalization),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<V, S> Embeddings<V, S>
+where
+    V: MemoryUsage,
+    S: MemoryUsage,
+{
+    /// Get the memory footprint of the embeddings.
+    ///
+    /// The footprint is the sum of the footprints of the vocabulary,
+    /// storage, norms, and counts. Metadata is not taken into account.
+    pub fn memory_usage(&self) -> MemoryFootprint {
+        self.vocab.memory_usage()
+            + self.storage.memory_usage()
+            + self.norms.memory_usage()
+            + self.counts.memory_usage()
+    }
+}
+
+impl<V, S> Embeddings<V, S>
+where
+    S: Advise,
+{
+    /// Advise the operating system of the access pattern of the storage.
+    ///
+    /// This only has an effect on memory-mapped storage.
+    pub fn advise(&self, pattern: AccessPattern) -> Result<()> {
+        self.storage.advise(pattern)
+    }
+
+    /// Prefault all pages of the storage.
+    ///
+    /// This only has an effect on memory-mapped storage.
+    pub fn prefault(&self) {
+        self.storage.prefault()
+    }
+}
+
+impl<V, S> Embeddings<V, S>
+where
+    S: LockMemory,
+{
+    /// Lock the storage in memory.
+    ///
+    /// Returns an error when the storage could not be locked, for
+    /// instance because the limit of locked memory would be exceeded.
+    /// The embeddings remain usable, but lookups may incur page
+    /// faults.
+    pub fn lock_storage(&self) -> Result<()> {
+        self.storage.lock_memory()
+    }
+
+    /// Unlock the storage.
+    pub fn unlock_storage(&self) -> Result<()> {
+        self.storage.unlock_memory()
+    }
+}
+
+#[allow(clippy::len_without_is_empty)]
+impl<V, S> Embeddings<V, S>
+where
+    V: Vocab,
+    S: Storage,
+{
+    /// Return the length (in vector components) of the word embeddings.
+    ///
+    /// If a lookup transform is installed, this is the length of the
+    /// transformed embeddings.
+    pub fn dims(&self) -> usize {
+        let dims = self.storage.shape().1;
+        match self.transform() {
+            Some(transform) => transform.dims(dims),
+            None => dims,
+        }
+    }
+
+    /// Convert the embeddings to embeddings with a dense storage.
+    ///
+    /// The embedding matrix is copied (or reconstructed, for quantized
+    /// storage) into an `NdArray`. The vocabulary, metadata, norms, and
+    /// lookup transform are retained.
+    pub fn into_dense(self) -> Embeddings<V, NdArray> {
+        let mut matrix = Array2::zeros(self.storage.shape());
+        for (idx, mut row) in matrix.outer_iter_mut().enumerate() {
+            row.assign(&self.storage.embedding(idx));
+        }
+
+        Embeddings {
+            metadata: self.metadata,
+            vocab: self.vocab,
+            storage: NdArray::new(matrix),
+            norms: self.norms,
+            counts: self.counts,
+            transform: self.transform,
+            normalization: self.normalization,
+            unknown_chunks: self.unknown_chunks,
+        }
+    }
+
+    /// Add byte units to the vocabulary.
+    ///
+    /// The embeddings are converted to embeddings with a
+    /// `ByteFallbackVocab`, which gives every non-empty word an
+    /// embedding. The embedding of a byte unit is the normalized
+    /// average of the embeddings of the words that contain the byte.
+    /// Bytes that do not occur in any word get the normalized average
+    /// of all word embeddings.
+    pub fn into_byte_fallback(self) -> Embeddings<ByteFallbackVocab<V>, NdArray> {
+        let (rows, dims) = self.storage.shape();
+        let mut matrix = Array2::zeros((rows + N_BYTE_UNITS, dims));
+        for (idx, mut row) in matrix.outer_iter_mut().take(rows).enumerate() {
+            row.assign(&self.storage.embedding(idx));
+        }
+
+        let mut byte_embeds = Array2::zeros((N_BYTE_UNITS, dims));
+        let mut byte_occurs = [false; N_BYTE_UNITS];
+        let mut centroid = Array1::zeros(dims);
+        for (word, idx) in self.vocab.iter() {
+            let embedding = matrix.row(idx);
+            centroid += &embedding;
+
+            let mut word_bytes = [false; N_BYTE_UNITS];
+            for byte in word.bytes() {
+                word_bytes[byte as usize] = true;
+            }
+
+            for byte in (0..N_BYTE_UNITS).filter(|&byte| word_bytes[byte]) {
+                let mut byte_embed = byte_embeds.row_mut(byte);
+                byte_embed += &embedding;
+                byte_occurs[byte] = true;
+            }
+        }
+
+        l2_normalize(centroid.view_mut());
+        for (mut byte_embed, &occurs) in byte_embeds.outer_iter_mut().zip(byte_occurs.iter()) {
+            if occurs {
+                l2_normalize(byte_embed.view_mut());
+            } else {
+                byte_embed.assign(&centroid);
+            }
+        }
+        matrix.slice_mut(s![rows.., ..]).assign(&byte_embeds);
+
+        Embeddings {
+            metadata: self.metadata,
+            vocab: ByteFallbackVocab::new(self.vocab),
+            storage: NdArray::new(matrix),
+            norms: self.norms,
+            counts: self.counts,
+            transform: self.transform,
+            normalization: self.normalization,
+            unknown_chunks: Vec::new(),
+        }
+    }
+
+    /// Set the word counts.
+    ///
+    /// Returns the previously-stored counts.
+    ///
+    /// Panics when the number of counts is not equal to the number of
+    /// words in the vocabulary.
+    pub fn set_counts(&mut self, mut counts: Option<WordCounts>) -> Option<WordCounts> {
+        if let Some(ref counts) = counts {
+            assert_eq!(
+                self.vocab.words_len(),
+                counts.len(),
+                "Vocab and counts do not have the same length"
+            );
+        }
+
+        mem::swap(&mut self.counts, &mut counts);
+        counts
+    }
+
+    /// Check whether the vocabulary is ordered by descending frequency.
+    ///
+    /// Returns `true` when the embeddings have word counts and the
+    /// counts are in descending order, so that `Vocab::rank` returns
+    /// frequency ranks. Embeddings without counts can be ordered by
+    /// frequency as well, but this cannot be verified.
+    pub fn is_frequency_ranked(&self) -> bool {
+        self.counts
+            .as_ref()
+            .map(WordCounts::is_descending)
+            .unwrap_or(false)
+    }
+
+    /// Get the corpus frequency of a word.
+    ///
+    /// Returns `None` when the embeddings do not have word counts or
+    /// the word is not in the vocabulary.
+    pub fn count(&self, word: &str) -> Option<u64> {
+        let counts = self.counts()?;
+        self.word_idx(word)?.word().map(|idx| counts[idx])
+    }
+
+    /// Get the embedding of a word.
+    pub fn embedding(&self, word: &str) -> Option<CowArray<'_, f32, Ix1>> {
+        self.untransformed_embedding(word)
+            .map(|embedding| self.apply_transform(embedding))
+    }
+
+    /// Get the embedding of a word, without applying the transform.
+    fn untransformed_embedding(&self, word: &str) -> Option<CowArray<'_, f32, Ix1>> {
+        match self.word_idx(word)? {
+            WordIndex::Word(idx) => Some(self.storage.embedding(idx)),
+            WordIndex::Subword(indices) => Some(CowArray::from(self.subword_embedding(&indices))),
+        }
+    }
+
+    /// Get the normalized sum of the embeddings of subword units.
+    fn subword_embedding(&self, indices: &[usize]) -> Array1<f32> {
+        let mut embed = Array1::zeros((self.storage.shape().1,));
+        for &idx in indices {
+            embed += &self.storage.embedding(idx).view();
+        }
+
+        l2_normalize(embed.view_mut());
+
+        embed
+    }
+
+    /// Realize the embedding of a word into the given vector.
+    ///
+    /// This variant of `embedding` realizes the embedding into the
+    /// given vector. This makes it possible to look up embeddings
+    /// without any additional allocations. This method returns
+    /// `false` and does not modify the vector if no embedding could
+    /// be found.
+    ///
+    /// Panics when then the vector does not have the same
+    /// dimensionality as the word embeddings.
+    ///
+    /// If a lookup transform is installed, the untransformed embedding
+    /// is realized into a temporary vector before it is transformed.
+    pub fn embedding_into(&self, word: &str, mut target: ArrayViewMut1<f32>) -> bool {
+        assert_eq!(
+            target.len(),
+            self.dims(),
+            "Embeddings have {} dimensions, whereas target array has {}",
+            self.dims(),
+            target.len()
+        );
+
+        if let Some(transform) = self.transform() {
+            return match self.untransformed_embedding(word) {
+                Some(embedding) => {
+                    target.assign(&transform.transform_embedding(embedding.view()));
+                    true
+                }
+                None => false,
+            };
+        }
+
+        let index = if let Some(idx) = self.word_idx(word) {
+            idx
+        } else {
+            return false;
+        };
+
+        match index {
+            WordIndex::Word(idx) => self.storage.embedding_into(idx, target),
+            WordIndex::Subword(indices) => {
+                target.fill(0.);
+
+                for idx in indices {
+                    target += &self.storage.embedding(idx).view();
+                }
+
+                l2_normalize(target.view_mut());
+            }
+        }
+
+        true
+    }
+
+    /// Get the embedding and original norm of a word.
+    ///
+    /// Returns for a word:
+    ///
+    /// * The word embedding.
+    /// * The norm of the embedding before normalization to a unit vector.
+    ///
+    /// The original embedding can be reconstructed by multiplying all
+    /// embedding components by the original norm.
+    ///
+    /// If the model does not have associated norms, *1* will be
+    /// returned as the norm for vocabulary words.
+    ///
+    /// If a lookup transform is installed, the embedding is
+    /// transformed, but the norm is that of the untransformed
+    /// embedding.
+    pub fn embedding_with_norm(&self, word: &str) -> Option<EmbeddingWithNorm<'_>> {
+        let embedding_with_norm = match self.word_idx(word)? {
+            WordIndex::Word(idx) => EmbeddingWithNorm {
+                embedding: self.storage.embedding(idx),
+                norm: self.norms().map(|n| n[idx]).unwrap_or(1.),
+            },
+            WordIndex::Subword(indices) => {
+                let mut embed = Array1::zeros((self.storage.shape().1,));
+                for idx in indices {
+                    embed += &self.storage.embedding(idx).view();
+                }
+
+                let norm = l2_normalize(embed.view_mut());
+
+                EmbeddingWithNorm {
+                    embedding: CowArray::from(embed),
+                    norm,
+                }
+            }
+        };
+
+        Some(EmbeddingWithNorm {
+            embedding: self.apply_transform(embedding_with_norm.embedding),
+            norm: embedding_with_norm.norm,
+        })
+    }
+
+    /// Get the embedding of a phrase.
+    ///
+    /// The phrase is split into tokens on whitespace. If the tokens
+    /// joined by `connector` form a word in the vocabulary, such as
+    /// `New_York` with the connector `_`, the embedding of that word
+    /// is returned. Otherwise, the embedding is the normalized average
+    /// of the embeddings of the tokens, where tokens without an
+    /// embedding are skipped. The returned `PhraseEmbedding` records
+    /// which of these strategies was used.
+    ///
+    /// Returns `None` when the phrase has no tokens or none of its
+    /// tokens has an embedding.
+    pub fn phrase_embedding(&self, phrase: &str, connector: &str) -> Option<PhraseEmbedding<'_>> {
+        let tokens = phrase.split_whitespace().collect::<Vec<_>>();
+        if tokens.is_empty() {
+            return None;
+        }
+
+        if let Some(WordIndex::Word(idx)) = self.word_idx(&tokens.join(connector)) {
+            return Some(PhraseEmbedding {
+                embedding: self.apply_transform(self.storage.embedding(idx)),
+                strategy: PhraseStrategy::Connected,
+            });
+        }
+
+        let mut embed = Array1::zeros((self.storage.shape().1,));
+        let mut n_found = 0;
+        for token in &tokens {
+            if let Some(token_embed) = self.untransformed_embedding(token) {
+                embed += &token_embed;
+                n_found += 1;
+            }
+        }
+
+        if n_found == 0 {
+            return None;
+        }
+
+        l2_normalize(embed.view_mut());
+
+        Some(PhraseEmbedding {
+            embedding: self.apply_transform(CowArray::from(embed)),
+            strategy: PhraseStrategy::Average {
+                n_found,
+                n_tokens: tokens.len(),
+            },
+        })
+    }
+
+    /// Get the embeddings of a batch of words.
+    ///
+    /// Returns a matrix with the embedding of the *i*-th word in the
+    /// *i*-th row and a vector that indicates for each word whether an
+    /// embedding was found. Rows of words without an embedding are
+    /// zero. If a lookup transform is installed, it is applied to the
+    /// whole batch at once.
+    ///
+    /// The embeddings of in-vocabulary words are retrieved from the
+    /// storage with a single `Storage::embeddings` call, embeddings
+    /// of unknown words are constructed from their subword units.
+    pub fn embedding_batch(&self, words: &[impl AsRef<str>]) -> (Array2<f32>, Vec<bool>) {
+        let mut found = vec![false; words.len()];
+        let mut embeddings = Array2::zeros((words.len(), self.storage.shape().1));
+        let mut word_rows = Vec::new();
+        let mut word_indices = Vec::new();
+        for (row, word) in words.iter().enumerate() {
+            match self.word_idx(word.as_ref()) {
+                Some(WordIndex::Word(idx)) => {
+                    word_rows.push(row);
+                    word_indices.push(idx);
+                }
+                Some(WordIndex::Subword(indices)) => {
+                    embeddings
+                        .row_mut(row)
+                        .assign(&self.subword_embedding(&indices));
+                }
+                None => continue,
+            }
+            found[row] = true;
+        }
+
+        let word_embeddings = self.storage.embeddings(&word_indices);
+        for (&row, embedding) in word_rows.iter().zip(word_embeddings.outer_iter()) {
+            embeddings.row_mut(row).assign(&embedding);
+        }
+
+        if let Some(transform) = self.transform() {
+            embeddings = transform.transform(embeddings.view());
+            for (mut embedding, &found) in embeddings.outer_iter_mut().zip(&found) {
+                if !found {
+                    embedding.fill(0.);
+                }
+            }
+        }
+
+        (embeddings, found)
+    }
+
+    /// Look up a word in the vocabulary, applying the normalization.
+    #[doc(hidden)]
+    pub fn word_idx(&self, word: &str) -> Option<WordIndex> {
+        match self.normalization() {
+            Some(normalization) => self.vocab.idx(&normalization.normalize(word)),
+            None => self.vocab.idx(word),
+        }
+    }
+
+    /// Apply the lookup transform to an embedding.
+    fn apply_transform<'a>(&self, embedding: CowArray<'a, f32, Ix1>) -> CowArray<'a, f32, Ix1> {
+        transform_embedding(self.transform(), embedding)
+    }
+
+    /// Get an iterator over pairs of words and the corresponding embeddings.
+    ///
+    /// The embeddings are transformed if a lookup transform is
+    /// installed.
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            storage: &self.storage,
+            transform: self.transform(),
+            inner: self.vocab.iter(),
+        }
+    }
+
+    /// Get an iterator over triples of words, embeddings, and norms.
+    ///
+    /// Returns an iterator that returns triples of:
+    ///
+    /// * A word.
+    /// * Its word embedding.
+    /// * The original norm of the embedding before normalization to a unit vector.
+    ///
+    /// The original embedding can be reconstructed by multiplying all
+    /// embedding components by the original norm.
+    ///
+    /// If the model does not have associated norms, the norm is
+    /// always *1*.
+    pub fn iter_with_norms(&self) -> IterWithNorms<'_> {
+        IterWithNorms {
+            storage: &self.storage,
+            norms: self.norms(),
+            transform: self.transform(),
+            inner: self.vocab.iter(),
+        }
+    }
+
+    /// Get an iterator over triples of words, embeddings, and norms
+    /// for the given words and their indices.
+    #[doc(hidden)]
+    pub fn iter_with_norms_from<'a>(&'a self, inner: VocabIter<'a>) -> IterWithNorms<'a> {
+        IterWithNorms {
+            storage: &self.storage,
+            norms: self.norms(),
+            transform: self.transform(),
+            inner,
+        }
+    }
+
+    /// Get the vocabulary size.
+    ///
+    /// The vocabulary size excludes subword units.
+    pub fn len(&self) -> usize {
+        self.vocab.words_len()
+    }
+}
+
+impl Embeddings<SimpleVocab, NdArray> {
+    /// Reserve capacity for at least `additional` more words.
+    ///
+    /// Capacity is reserved in the vocabulary, the storage, and the
+    /// norms and counts, if any. This avoids reallocations when
+    /// embeddings are assembled incrementally with `push`.
+    pub fn reserve(&mut self, additional: usize) {
+        self.vocab.reserve(additional);
+        self.storage.reserve_rows(additional);
+        if let Some(norms) = self.norms.as_mut() {
+            norms.reserve(additional);
+        }
+        if let Some(counts) = self.counts.as_mut() {
+            counts.reserve(additional);
+        }
+    }
+
+    /// Shrink the capacity of the embeddings as much as possible.
+    ///
+    /// This releases capacity that was reserved, but not used, after
+    /// assembling embeddings incrementally.
+    pub fn shrink_to_fit(&mut self) {
+        self.vocab.shrink_to_fit();
+        self.storage.shrink_to_fit();
+        if let Some(norms) = self.norms.as_mut() {
+            norms.shrink_to_fit();
+        }
+        if let Some(counts) = self.counts.as_mut() {
+            counts.shrink_to_fit();
+        }
+    }
+
+    /// Add a word and its embedding.
+    ///
+    /// The embedding is normalized before it is added. If the
+    /// embeddings have norms, the norm of the embedding is added as
+    /// well. If the embeddings have word counts, the word is added
+    /// with a count of zero. The embedding should be untransformed,
+    /// i.e. it has the dimensionality of the storage, regardless of
+    /// the lookup transform.
+    ///
+    /// Returns `false` and does not modify the embeddings when the
+    /// word is already in the vocabulary.
+    ///
+    /// Panics when the embedding does not have the same
+    /// dimensionality as the storage.
+    pub fn push(&mut self, word: impl Into<String>, embedding: ArrayView1<f32>) -> bool {
+        let dims = self.storage.shape().1;
+        assert_eq!(
+            embedding.len(),
+            dims,
+            "Storage has {} dimensions, whereas embedding has {}",
+            dims,
+            embedding.len()
+        );
+
+        if self.vocab.push(word).is_none() {
+            return false;
+        }
+
+        let mut embedding = embedding.to_owned();
+        let norm = l2_normalize(embedding.view_mut());
+        self.storage.push_row(embedding.view());
+        if let Some(norms) = self.norms.as_mut() {
+            norms.push(norm);
+        }
+        if let Some(counts) = self.counts.as_mut() {
+            counts.push(0);
+        }
+        self.unknown_chunks.clear();
+
+        true
+    }
+}
+
+impl<V, S> Embeddings<V, S>
+where
+    V: Vocab,
+{
+    /// Treat the words of the vocabulary as language-tagged words.
+    ///
+    /// This wraps the vocabulary in a `LanguageVocab`, which makes it
+    /// possible to look up words by language in multilingual
+    /// embeddings, such as `en:dog` with `format` set to the default
+    /// `LanguageTagFormat`.
+    pub fn into_language_vocab(self, format: LanguageTagFormat) -> Embeddings<LanguageVocab<V>, S> {
+        Embeddings {
+            metadata: self.metadata,
+            vocab: LanguageVocab::with_format(self.vocab, format),
+            storage: self.storage,
+            norms: self.norms,
+            counts: self.counts,
+            transform: self.transform,
+            normalization: self.normalization,
+            unknown_chunks: self.unknown_chunks,
+        }
+    }
+}
+
+impl<V, S> Embeddings<LanguageVocab<V>, S>
+where
+    V: Vocab,
+    S: Storage,
+{
+    /// Get the embedding of a word in a language.
+    pub fn language_embedding(&self, language: &str, word: &str) -> Option<CowArray<'_, f32, Ix1>> {
+        self.embedding(&self.vocab.format().tag(language, word))
+    }
+}
+
+impl<V, S> Embeddings<V, S>
+where
+    V: Vocab,
+    S: Storage,
+{
+    /// Merge two sets of embeddings.
+    ///
+    /// The vocabulary of the merged embeddings consists of the words
+    /// of `self`, followed by the words of `other` that are not in
+    /// `self`. The embedding of a word that is in both vocabularies is
+    /// chosen using `conflict`. Only in-vocabulary words are merged,
+    /// subword units are not. The merged embeddings are untransformed.
+    ///
+    /// The merged embeddings always have norms. A norm of *1* is
+    /// used for words of embeddings without norms. Word counts are
+    /// retained when both embeddings have counts, the counts of words
+    /// that are in both vocabularies are summed. The metadata of
+    /// `self` is retained.
+    ///
+    /// Returns an error when the embeddings do not have the same
+    /// dimensionality.
+    pub fn merge<V2, S2>(
+        &self,
+        other: &Embeddings<V2, S2>,
+        conflict: MergeConflict,
+    ) -> Result<Embeddings<SimpleVocab, NdArray>>
+    where
+        V2: Vocab,
+        S2: Storage,
+    {
+        let dims = self.storage.shape().1;
+        if other.storage.shape().1 != dims {
+            return Err(Error::Shape(ShapeError::from_kind(
+                ShapeErrorKind::IncompatibleShape,
+            )));
+        }
+
+        let mut words = Vec::with_capacity(self.len() + other.len());
+        let mut data = Vec::with_capacity((self.len() + other.len()) * dims);
+        let mut norms = Vec::with_capacity(self.len() + other.len());
+        let mut counts = match (self.counts(), other.counts()) {
+            (Some(_), Some(_)) => Some(Vec::with_capacity(self.len() + other.len())),
+            _ => None,
+        };
+
+        for (word, idx) in self.vocab.iter() {
+            let other_idx = other.vocab.idx(&word).and_then(|idx| idx.word());
+            let mut embedding = self.storage.embedding(idx).into_owned();
+            let mut norm = self.norms().map(|n| n[idx]).unwrap_or(1.);
+
+            if let Some(other_idx) = other_idx {
+                let other_norm = other.norms().map(|n| n[other_idx]).unwrap_or(1.);
+                match conflict {
+                    MergeConflict::First => (),
+                    MergeConflict::Last => {
+                        embedding = other.storage.embedding(other_idx).into_owned();
+                        norm = other_norm;
+                    }
+                    MergeConflict::Average => {
+                        embedding *= norm;
+                        embedding.scaled_add(other_norm, &other.storage.embedding(other_idx));
+                        embedding /= 2.;
+                        norm = l2_normalize(embedding.view_mut());
+                    }
+                }
+            }
+
+            if let (Some(merged), Some(self_counts), Some(other_counts)) =
+                (counts.as_mut(), self.counts(), other.counts())
+            {
+                merged.push(self_counts[idx] + other_idx.map(|idx| other_counts[idx]).unwrap_or(0));
+            }
+
+            words.push(word.into_owned());
+            data.extend(embedding.iter());
+            norms.push(norm);
+        }
+
+        for (word, idx) in other.vocab.iter() {
+            if self.vocab.idx(&word).and_then(|idx| idx.word()).is_some() {
+                continue;
+            }
+
+            if let (Some(merged), Some(other_counts)) = (counts.as_mut(), other.counts()) {
+                merged.push(other_counts[idx]);
+            }
+
+            words.push(word.into_owned());
+            data.extend(other.storage.embedding(idx).iter());
+            norms.push(other.norms().map(|n| n[idx]).unwrap_or(1.));
+        }
+
+        let matrix = Array2::from_shape_vec((words.len(), dims), data).map_err(Error::Shape)?;
+        let mut merged = Embeddings::new(
+            self.metadata.clone(),
+            SimpleVocab::new(words),
+            NdArray::new(matrix),
+            NdNorms::new(norms),
+        );
+        merged.set_counts(counts.map(WordCounts::new));
+
+        Ok(merged)
+    }
+}
+
+impl<V, S> Embeddings<V, S>
+where
+    V: Vocab,
+    S: Storage,
+{
+    /// Merge the words that have the same normalized form.
+    ///
+    /// Returns embeddings with the normalized words of the vocabulary,
+    /// in the order of their first occurrence. The embedding of a
+    /// normalized form that is shared by several words is chosen using
+    /// `conflict`, where `First` and `Last` refer to vocabulary order.
+    /// Only in-vocabulary words are merged, subword units are not. The
+    /// merged embeddings are untransformed. When no normalization is
+    /// installed, the words are not modified.
+    ///
+    /// The merged embeddings always have norms. A norm of *1* is used
+    /// when the embeddings do not have norms. Word counts are retained,
+    /// the counts of merged words are summed. The metadata and the word
+    /// normalization are retained.
+    pub fn merge_normalized(&self, conflict: MergeConflict) -> Embeddings<SimpleVocab, NdArray> {
+        let dims = self.storage.shape().1;
+
+        let mut words: Vec<String> = Vec::with_capacity(self.len());
+        let mut groups: Vec<Vec<usize>> = Vec::with_capacity(self.len());
+        let mut group_indices: HashMap<String, usize> = HashMap::with_capacity(self.len());
+        for (word, idx) in self.vocab.iter() {
+            let normalized = match self.normalization() {
+                Some(normalization) => normalization.normalize(&word).into_owned(),
+                None => word.into_owned(),
+            };
+
+            match group_indices.get(&normalized) {
+                Some(&group) => groups[group].push(idx),
+                None => {
+                    group_indices.insert(normalized.clone(), groups.len());
+                    words.push(normalized);
+                    groups.push(vec![idx]);
+                }
+            }
+        }
+
+        let norm = |idx: usize| self.norms().map(|n| n[idx]).unwrap_or(1.);
+
+        let mut matrix = Array2::zeros((words.len(), dims));
+        let mut norms = Vec::with_capacity(words.len());
+        for (group, mut embedding) in groups.iter().zip(matrix.outer_iter_mut()) {
+            match conflict {
+                MergeConflict::First => {
+                    embedding.assign(&self.storage.embedding(group[0]));
+                    norms.push(norm(group[0]));
+                }
+                MergeConflict::Last => {
+                    let idx = group[group.len() - 1];
+                    embedding.assign(&self.storage.embedding(idx));
+                    norms.push(norm(idx));
+                }
+                MergeConflict::Average if group.len() == 1 => {
+                    embedding.assign(&self.storage.embedding(group[0]));
+                    norms.push(norm(group[0]));
+                }
+                MergeConflict::Average => {
+                    for &idx in group {
+                        embedding.scaled_add(norm(idx), &self.storage.embedding(idx));
+                    }
+                    embedding /= group.len() as f32;
+                    norms.push(l2_normalize(embedding));
+                }
+            }
+        }
+
+        let counts = self.counts().map(|counts| {
+            WordCounts::new(
+                groups
+                    .iter()
+                    .map(|group| group.iter().map(|&idx| counts[idx]).sum())
+                    .collect::<Vec<_>>(),
+            )
+        });
+
+        let mut merged = Embeddings::new(
+            self.metadata.clone(),
+            SimpleVocab::new(words),
+            NdArray::new(matrix),
+            NdNorms::new(norms),
+        );
+        merged.set_counts(counts);
+        merged.set_normalization(self.normalization.clone());
+
+        merged
+    }
+}
+
+impl Embeddings<LayeredVocab, LayeredStorage> {
+    /// Layer embeddings.
+    ///
+    /// Returns embeddings with the words of all layers. When a word is
+    /// in more than one layer, the embedding of the first layer that
+    /// contains the word is used. So, a small domain-specific model can
+    /// be layered over a large general model by passing it first. In
+    /// contrast to `merge`, the embeddings of the layers are not copied,
+    /// lookups are delegated to the layers. Memory-mapped layers stay
+    /// memory mapped.
+    ///
+    /// The norms are retained when a layer has norms, words of layers
+    /// without norms get a norm of one. Counts are retained when all
+    /// layers have counts. The metadata of the first layer is retained.
+    /// The word normalizations and lookup transforms of the layers are
+    /// not applied.
+    ///
+    /// Returns an error when there are no layers or when the layers do
+    /// not have the same dimensionality.
+    pub fn layered(layers: Vec<Embeddings<VocabWrap, StorageWrap>>) -> Result<Self> {
+        let dims = match layers.first() {
+            Some(layer) => layer.dims(),
+            None => {
+                return Err(ErrorKind::Format(String::from(
+                    "Layered embeddings require at least one layer",
+                ))
+                .into())
+            }
+        };
+        if layers.iter().any(|layer| layer.dims() != dims) {
+            return Err(Error::Shape(ShapeError::from_kind(
+                ShapeErrorKind::IncompatibleShape,
+            )));
+        }
+
+        let has_norms = layers.iter().any(|layer| layer.norms.is_some());
+        let has_counts = layers.iter().all(|layer| layer.counts.is_some());
+        let metadata = layers[0].metadata.clone();
+
+        let mut vocabs = Vec::with_capacity(layers.len());
+        let mut storages = Vec::with_capacity(layers.len());
+        let mut layer_norms = Vec::with_capacity(layers.len());
+        let mut layer_counts = Vec::with_capacity(layers.len());
+        for layer in layers {
+            vocabs.push(layer.vocab);
+            storages.push(layer.storage);
+            layer_norms.push(layer.norms);
+            layer_counts.push(layer.counts);
+        }
+
+        let layer_words_len = vocabs.iter().map(Vocab::words_len).collect::<Vec<_>>();
+        let (vocab, word_rows) = LayeredVocab::new(vocabs);
+
+        let norms = if has_norms {
+            Some(NdNorms::new(
+                word_rows
+                    .iter()
+                    .map(|&(layer, idx)| layer_norms[layer].as_ref().map(|n| n[idx]).unwrap_or(1.))
+                    .collect::<Vec<_>>(),
+            ))
+        } else {
+            None
+        };
+        let counts = if has_counts {
+            Some(WordCounts::new(
+                word_rows
+                    .iter()
+                    .map(|&(layer, idx)| layer_counts[layer].as_ref().expect("Missing counts")[idx])
+                    .collect::<Vec<_>>(),
+            ))
+        } else {
+            None
+        };
+
+        let storage = LayeredStorage::new(
+            storages,
+            word_rows,
+            vocab.subword_offsets(),
+            &layer_words_len,
+        );
+
+        Ok(Embeddings {
+            metadata,
+            vocab,
+            storage,
+            norms,
+            counts,
+            transform: None,
+            normalization: None,
+            unknown_chunks: Vec::new(),
+        })
+    }
+}
+
+/// Resolution of conflicts when merging embeddings.
+///
+/// A conflict occurs when a word is in the vocabularies of both
+/// embeddings that are merged, or when several words have the same
+/// normalized form.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MergeConflict {
+    /// Use the embedding of the first embeddings or the first word.
+    First,
+
+    /// Use the embedding of the second embeddings or the last word.
+    Last,
+
+    /// Use the average of the unnormalized embeddings.
+    ///
+    /// The unnormalized embeddings are reconstructed using the norms
+    /// and the average is normalized again.
+    Average,
+}
+
+impl<V, S> Embeddings<V, S>
+where
+    V: RetainWords,
+    S: SelectRows + Storage,
+{
+    /// Retain the words for which the predicate holds.
+    ///
+    /// Returns embeddings with the words of the vocabulary for which
+    /// `predicate` returns `true`, in vocabulary order. The storage,
+    /// norms, and counts are compacted to the retained words. Subword
+    /// units are always retained. Metadata, the lookup transform, and
+    /// the word normalization are retained as well.
+    pub fn retain<F>(&self, mut predicate: F) -> Embeddings<V, S::Output>
+    where
+        F: FnMut(&str) -> bool,
+    {
+        let indices = self
+            .vocab
+            .iter()
+            .filter(|(word, _)| predicate(word))
+            .map(|(_, idx)| idx)
+            .collect::<Vec<_>>();
+
+        self.retain_indices(&indices)
+    }
+
+    /// Prune the vocabulary to the given words.
+    ///
+    /// Words are looked up in the vocabulary, applying the word
+    /// normalization, and words that are not in the vocabulary are
+    /// ignored. The retained words keep their vocabulary order. See
+    /// `retain` for more information.
+    pub fn prune_to(&self, words: &[impl AsRef<str>]) -> Embeddings<V, S::Output> {
+        let mut indices = words
+            .iter()
+            .filter_map(|word| self.word_idx(word.as_ref())?.word())
+            .collect::<Vec<_>>();
+        indices.sort_unstable();
+        indices.dedup();
+
+        self.retain_indices(&indices)
+    }
+
+    /// Restrict two sets of embeddings to their shared words.
+    ///
+    /// Returns the embeddings of `self` and `other`, restricted to the
+    /// words that are in both vocabularies. The shared words are in
+    /// the vocabulary order of `self` in both embeddings, so that row
+    /// *i* of both storages is the embedding of the same word. This
+    /// makes it possible to compare different embedding spaces on the
+    /// same words.
+    ///
+    /// The embeddings of `self` are restricted as in `retain`. The
+    /// embeddings of `other` get a simple vocabulary, since its
+    /// subword units cannot be reordered. Norms, counts, metadata, the
+    /// lookup transform, and the word normalization of `other` are
+    /// retained. Words are looked up without normalization.
+    pub fn intersect<V2, S2>(
+        &self,
+        other: &Embeddings<V2, S2>,
+    ) -> (
+        Embeddings<V, S::Output>,
+        Embeddings<SimpleVocab, S2::Output>,
+    )
+    where
+        V2: Vocab,
+        S2: SelectRows + Storage,
+    {
+        let mut indices = Vec::new();
+        let mut other_indices = Vec::new();
+        let mut words = Vec::new();
+        for (word, idx) in self.vocab.iter() {
+            if let Some(other_idx) = other.vocab.idx(&word).and_then(|idx| idx.word()) {
+                indices.push(idx);
+                other_indices.push(other_idx);
+                words.push(word.into_owned());
+            }
+        }
+
+        let other = Embeddings {
+            metadata: other.metadata.clone(),
+            vocab: SimpleVocab::new(words),
+            storage: other.storage.select_rows(&other_indices),
+            norms: other
+                .norms
+                .as_ref()
+                .map(|norms| NdNorms::new(norms.select(Axis(0), &other_indices))),
+            counts: other.counts.as_ref().map(|counts| {
+                WordCounts::new(
+                    other_indices
+                        .iter()
+                        .map(|&idx| counts[idx])
+                        .collect::<Vec<_>>(),
+                )
+            }),
+            transform: other.transform.clone(),
+            normalization: other.normalization.clone(),
+            unknown_chunks: Vec::new(),
+        };
+
+        (self.retain_indices(&indices), other)
+    }
+
+    /// Retain the words that are not in the vocabulary of `other`.
+    ///
+    /// Returns the embeddings of `self`, restricted to the words that
+    /// are not in the vocabulary of `other`, in vocabulary order. Words
+    /// are looked up without normalization. See `retain` for more
+    /// information.
+    pub fn difference<V2, S2>(&self, other: &Embeddings<V2, S2>) -> Embeddings<V, S::Output>
+    where
+        V2: Vocab,
+    {
+        self.retain(|word| other.vocab.idx(word).and_then(|idx| idx.word()).is_none())
+    }
+
+    /// Order the vocabulary by descending frequency.
+    ///
+    /// Returns embeddings of which the words are sorted by their
+    /// counts, such that `is_frequency_ranked` holds. Words with the
+    /// same count keep their vocabulary order. The embeddings get a
+    /// simple vocabulary, since subword units cannot be reordered.
+    /// Returns `None` when the embeddings do not have word counts.
+    pub fn sort_by_frequency(&self) -> Option<Embeddings<SimpleVocab, S::Output>> {
+        let counts = self.counts.as_ref()?;
+        let mut indices = (0..self.vocab.words_len()).collect::<Vec<_>>();
+        indices.sort_by(|&idx1, &idx2| counts[idx2].cmp(&counts[idx1]));
+
+        Some(Embeddings {
+            metadata: self.metadata.clone(),
+            vocab: SimpleVocab::new(
+                indices
+                    .iter()
+                    .map(|&idx| {
+                        self.vocab
+                            .word(idx)
+                            .expect("Invalid word index")
+                            .into_owned()
+                    })
+                    .collect::<Vec<_>>(),
+            ),
+            storage: self.storage.select_rows(&indices),
+            norms: self
+                .norms
+                .as_ref()
+                .map(|norms| NdNorms::new(norms.select(Axis(0), &indices))),
+            counts: Some(WordCounts::new(
+                indices.iter().map(|&idx| counts[idx]).collect::<Vec<_>>(),
+            )),
+            transform: self.transform.clone(),
+            normalization: self.normalization.clone(),
+            unknown_chunks: Vec::new(),
+        })
+    }
+
+    fn retain_indices(&self, indices: &[usize]) -> Embeddings<V, S::Output> {
+        let (vocab, rows) = self.vocab.retain_indices(indices);
+
+        Embeddings {
+            metadata: self.metadata.clone(),
+            vocab,
+            storage: self.storage.select_rows(&rows),
+            norms: self
+                .norms
+                .as_ref()
+                .map(|norms| NdNorms::new(norms.select(Axis(0), indices))),
+            counts: self.counts.as_ref().map(|counts| {
+                WordCounts::new(indices.iter().map(|&idx| counts[idx]).collect::<Vec<_>>())
+            }),
+            transform: self.transform.clone(),
+            normalization: self.normalization.clone(),
+            unknown_chunks: Vec::new(),
+        }
+    }
+}
+
+macro_rules! impl_embeddings_from(
+    ($vocab:ty, $storage:ty, $storage_wrap:ty) => {
+        impl From<Embeddings<$vocab, $storage>> for Embeddings<VocabWrap, $storage_wrap> {
+            fn from(from: Embeddings<$vocab, $storage>) -> Self {
+                Embeddings {
+                    metadata: from.metadata,
+                    vocab: from.vocab.into(),
+                    storage: from.storage.into(),
+                    norms: from.norms,
+                    counts: from.counts,
+                    transform: from.transform,
+                    normalization: from.normalization,
+                    unknown_chunks: from.unknown_chunks,
+                }
+            }
+        }
+    }
+);
+
+// Hmpf. We with the blanket From<T> for T implementation, we need
+// specialization to generalize this.
+impl_embeddings_from!(SimpleVocab, NdArray, StorageWrap);
+impl_embeddings_from!(SimpleVocab, NdArray, StorageViewWrap);
+impl_embeddings_from!(SimpleVocab, MmapArray, StorageWrap);
+impl_embeddings_from!(SimpleVocab, PreadArray, StorageWrap);
+#[cfg(target_endian = "little")]
+impl_embeddings_from!(SimpleVocab, MmapArray, StorageViewWrap);
+impl_embeddings_from!(SimpleVocab, QuantizedArray, StorageWrap);
+impl_embeddings_from!(SimpleVocab, MmapQuantizedArray, StorageWrap);
+impl_embeddings_from!(BucketSubwordVocab, NdArray, StorageWrap);
+impl_embeddings_from!(BucketSubwordVocab, NdArray, StorageViewWrap);
+impl_embeddings_from!(BucketSubwordVocab, MmapArray, StorageWrap);
+impl_embeddings_from!(BucketSubwordVocab, PreadArray, StorageWrap);
+#[cfg(target_endian = "little")]
+impl_embeddings_from!(BucketSubwordVocab, MmapArray, StorageViewWrap);
+impl_embeddings_from!(BucketSubwordVocab, QuantizedArray, StorageWrap);
+impl_embeddings_from!(BucketSubwordVocab, MmapQuantizedArray, StorageWrap);
+impl_embeddings_from!(FastTextSubwordVocab, NdArray, StorageWrap);
+impl_embeddings_from!(FastTextSubwordVocab, NdArray, StorageViewWrap);
+impl_embeddings_from!(FastTextSubwordVocab, MmapArray, StorageWrap);
+impl_embeddings_from!(FastTextSubwordVocab, PreadArray, StorageWrap);
+#[cfg(target_endian = "little")]
+impl_embeddings_from!(FastTextSubwordVocab, MmapArray, StorageViewWrap);
+impl_embeddings_from!(FastTextSubwordVocab, QuantizedArray, StorageWrap);
+impl_embeddings_from!(FastTextSubwordVocab, MmapQuantizedArray, StorageWrap);
+impl_embeddings_from!(ExplicitSubwordVocab, NdArray, StorageWrap);
+impl_embeddings_from!(ExplicitSubwordVocab, NdArray, StorageViewWrap);
+impl_embeddings_from!(ExplicitSubwordVocab, MmapArray, StorageWrap);
+impl_embeddings_from!(ExplicitSubwordVocab, PreadArray, StorageWrap);
+impl_embeddings_from!(ExplicitSubwordVocab, MmapQuantizedArray, StorageWrap);
+#[cfg(target_endian = "little")]
+impl_embeddings_from!(ExplicitSubwordVocab, MmapArray, StorageViewWrap);
+impl_embeddings_from!(ExplicitSubwordVocab, QuantizedArray, StorageWrap);
+impl_embeddings_from!(NamespacedVocab, NdArray, StorageWrap);
+impl_embeddings_from!(NamespacedVocab, NdArray, StorageViewWrap);
+impl_embeddings_from!(NamespacedVocab, MmapArray, StorageWrap);
+impl_embeddings_from!(NamespacedVocab, PreadArray, StorageWrap);
+#[cfg(target_endian = "little")]
+impl_embeddings_from!(NamespacedVocab, MmapArray, StorageViewWrap);
+impl_embeddings_from!(NamespacedVocab, QuantizedArray, StorageWrap);
+impl_embeddings_from!(NamespacedVocab, MmapQuantizedArray, StorageWrap);
+impl_embeddings_from!(FstVocab, NdArray, StorageWrap);
+impl_embeddings_from!(FrontCodedVocab, NdArray, StorageWrap);
+impl_embeddings_from!(MmapVocab, NdArray, StorageWrap);
+impl_embeddings_from!(FstVocab, NdArray, StorageViewWrap);
+impl_embeddings_from!(FrontCodedVocab, NdArray, StorageViewWrap);
+impl_embeddings_from!(MmapVocab, NdArray, StorageViewWrap);
+impl_embeddings_from!(FstVocab, MmapArray, StorageWrap);
+impl_embeddings_from!(FrontCodedVocab, MmapArray, StorageWrap);
+impl_embeddings_from!(MmapVocab, MmapArray, StorageWrap);
+impl_embeddings_from!(FstVocab, PreadArray, StorageWrap);
+impl_embeddings_from!(FrontCodedVocab, PreadArray, StorageWrap);
+impl_embeddings_from!(MmapVocab, PreadArray, StorageWrap);
+#[cfg(target_endian = "little")]
+impl_embeddings_from!(FstVocab, MmapArray, StorageViewWrap);
+#[cfg(target_endian = "little")]
+impl_embeddings_from!(FrontCodedVocab, MmapArray, StorageViewWrap);
+#[cfg(target_endian = "little")]
+impl_embeddings_from!(MmapVocab, MmapArray, StorageViewWrap);
+impl_embeddings_from!(FstVocab, QuantizedArray, StorageWrap);
+impl_embeddings_from!(FrontCodedVocab, QuantizedArray, StorageWrap);
+impl_embeddings_from!(MmapVocab, QuantizedArray, StorageWrap);
+impl_embeddings_from!(FstVocab, MmapQuantizedArray, StorageWrap);
+impl_embeddings_from!(FrontCodedVocab, MmapQuantizedArray, StorageWrap);
+impl_embeddings_from!(MmapVocab, MmapQuantizedArray, StorageWrap);
+impl_embeddings_from!(BpeVocab, NdArray, StorageWrap);
+impl_embeddings_from!(BpeVocab, NdArray, StorageViewWrap);
+impl_embeddings_from!(BpeVocab, MmapArray, StorageWrap);
+impl_embeddings_from!(BpeVocab, PreadArray, StorageWrap);
+#[cfg(target_endian = "little")]
+impl_embeddings_from!(BpeVocab, MmapArray, StorageViewWrap);
+impl_embeddings_from!(BpeVocab, QuantizedArray, StorageWrap);
+impl_embeddings_from!(BpeVocab, MmapQuantizedArray, StorageWrap);
+impl_embeddings_from!(WordPieceVocab, NdArray, StorageWrap);
+impl_embeddings_from!(SentencePieceVocab, NdArray, StorageWrap);
+impl_embeddings_from!(WordPieceVocab, NdArray, StorageViewWrap);
+impl_embeddings_from!(SentencePieceVocab, NdArray, StorageViewWrap);
+impl_embeddings_from!(WordPieceVocab, MmapArray, StorageWrap);
+impl_embeddings_from!(SentencePieceVocab, MmapArray, StorageWrap);
+impl_embeddings_from!(WordPieceVocab, PreadArray, StorageWrap);
+impl_embeddings_from!(SentencePieceVocab, PreadArray, StorageWrap);
+#[cfg(target_endian = "little")]
+impl_embeddings_from!(WordPieceVocab, MmapArray, StorageViewWrap);
+#[cfg(target_endian = "little")]
+impl_embeddings_from!(SentencePieceVocab, MmapArray, StorageViewWrap);
+impl_embeddings_from!(WordPieceVocab, QuantizedArray, StorageWrap);
+impl_embeddings_from!(SentencePieceVocab, QuantizedArray, StorageWrap);
+impl_embeddings_from!(WordPieceVocab, MmapQuantizedArray, StorageWrap);
+impl_embeddings_from!(SentencePieceVocab, MmapQuantizedArray, StorageWrap);
+impl_embeddings_from!(ByteFallbackVocab<SimpleVocab>, NdArray, StorageWrap);
+impl_embeddings_from!(ByteFallbackVocab<SimpleVocab>, NdArray, StorageViewWrap);
+impl_embeddings_from!(ByteFallbackVocab<BucketSubwordVocab>, NdArray, StorageWrap);
+impl_embeddings_from!(
+    ByteFallbackVocab<BucketSubwordVocab>,
+    NdArray,
+    StorageViewWrap
+);
+impl_embeddings_from!(
+    ByteFallbackVocab<FastTextSubwordVocab>,
+    NdArray,
+    StorageWrap
+);
+impl_embeddings_from!(
+    ByteFallbackVocab<FastTextSubwordVocab>,
+    NdArray,
+    StorageViewWrap
+);
+impl_embeddings_from!(
+    ByteFallbackVocab<ExplicitSubwordVocab>,
+    NdArray,
+    StorageWrap
+);
+impl_embeddings_from!(
+    ByteFallbackVocab<ExplicitSubwordVocab>,
+    NdArray,
+    StorageViewWrap
+);
+impl_embeddings_from!(ByteFallbackVocab<VocabWrap>, NdArray, StorageWrap);
+impl_embeddings_from!(ByteFallbackVocab<VocabWrap>, NdArray, StorageViewWrap);
+impl_embeddings_from!(AliasVocab<SimpleVocab>, NdArray, StorageWrap);
+impl_embeddings_from!(AliasVocab<SimpleVocab>, NdArray, StorageViewWrap);
+impl_embeddings_from!(AliasVocab<VocabWrap>, NdArray, StorageWrap);
+impl_embeddings_from!(AliasVocab<VocabWrap>, NdArray, StorageViewWrap);
+impl_embeddings_from!(VocabWrap, QuantizedArray, StorageWrap);
+impl_embeddings_from!(VocabWrap, MmapQuantizedArray, StorageWrap);
+impl_embeddings_from!(SimpleVocab, DedupArray, StorageWrap);
+impl_embeddings_from!(BucketSubwordVocab, DedupArray, StorageWrap);
+impl_embeddings_from!(FastTextSubwordVocab, DedupArray, StorageWrap);
+impl_embeddings_from!(ExplicitSubwordVocab, DedupArray, StorageWrap);
+impl_embeddings_from!(NamespacedVocab, DedupArray, StorageWrap);
+impl_embeddings_from!(FstVocab, DedupArray, StorageWrap);
+impl_embeddings_from!(FrontCodedVocab, DedupArray, StorageWrap);
+impl_embeddings_from!(MmapVocab, DedupArray, StorageWrap);
+impl_embeddings_from!(BpeVocab, DedupArray, StorageWrap);
+impl_embeddings_from!(WordPieceVocab, DedupArray, StorageWrap);
+impl_embeddings_from!(SentencePieceVocab, DedupArray, StorageWrap);
+impl_embeddings_from!(VocabWrap, DedupArray, StorageWrap);
+impl_embeddings_from!(SimpleVocab, ResidualQuantizedArray, StorageWrap);
+impl_embeddings_from!(BucketSubwordVocab, ResidualQuantizedArray, StorageWrap);
+impl_embeddings_from!(FastTextSubwordVocab, ResidualQuantizedArray, StorageWrap);
+impl_embeddings_from!(ExplicitSubwordVocab, ResidualQuantizedArray, StorageWrap);
+impl_embeddings_from!(NamespacedVocab, ResidualQuantizedArray, StorageWrap);
+impl_embeddings_from!(VocabWrap, ResidualQuantizedArray, StorageWrap);
+
+impl<'a, V, S> IntoIterator for &'a Embeddings<V, S>
+where
+    V: Vocab,
+    S: Storage,
+{
+    type Item = (Cow<'a, str>, CowArray<'a, f32, Ix1>);
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Quantizable embedding matrix.
+pub trait Quantize<V> {
+    /// Quantize the embedding matrix.
+    ///
+    /// This method trains a quantizer for the embedding matrix and
+    /// then quantizes the matrix using this quantizer.
+    ///
+    /// The xorshift PRNG is used for picking the initial quantizer
+    /// centroids.
+    fn quantize<T>(
+        &self,
+        n_subquantizers: usize,
+        n_subquantizer_bits: u32,
+        n_iterations: usize,
+        n_attempts: usize,
+        normalize: bool,
+    ) -> Embeddings<V, QuantizedArray>
+    where
+        T: TrainPQ<f32>,
+    {
+        self.quantize_using::<T, _>(
+            n_subquantizers,
+            n_subquantizer_bits,
+            n_iterations,
+            n_attempts,
+            normalize,
+            XorShiftRng::from_entropy(),
+        )
+    }
+
+    /// Quantize the embedding matrix using the provided RNG.
+    ///
+    /// This method trains a quantizer for the embedding matrix and
+    /// then quantizes the matrix using this quantizer.
+    fn quantize_using<T, R>(
+        &self,
+        n_subquantizers: usize,
+        n_subquantizer_bits: u32,
+        n_iterations: usize,
+        n_attempts: usize,
+        normalize: bool,
+        rng: R,
+    ) -> Embeddings<V, QuantizedArray>
+    where
+        T: TrainPQ<f32>,
+        R: RngCore + SeedableRng + Send;
+}
+
+impl<V, S> Quantize<V> for Embeddings<V, S>
+where
+    V: Vocab + Clone,
+    S: StorageView,
+{
+    fn quantize_using<T, R>(
+        &self,
+        n_subquantizers: usize,
+        n_subquantizer_bits: u32,
+        n_iterations: usize,
+        n_attempts: usize,
+        normalize: bool,
+        rng: R,
+    ) -> Embeddings<V, QuantizedArray>
+    where
+        T: TrainPQ<f32>,
+        R: RngCore + SeedableRng + Send,
+    {
+        let quantized_storage = self.storage().quantize_using::<T, R>(
+            n_subquantizers,
+            n_subquantizer_bits,
+            n_iterations,
+            n_attempts,
+            normalize,
+            rng,
+        );
+
+        Embeddings {
+            metadata: self.metadata().cloned(),
+            vocab: self.vocab.clone(),
+            storage: quantized_storage,
+            norms: self.norms().cloned(),
+            counts: self.counts.clone(),
+            transform: self.transform.clone(),
+            normalization: self.normalization.clone(),
+            unknown_chunks: self.unknown_chunks.clone(),
+        }
+    }
+}
+
+/// Fallibly quantizable embedding matrix.
+///
+/// This trait is implemented for embeddings with wrapped storage,
+/// such as `Embeddings<VocabWrap, StorageWrap>`. Quantization fails
+/// when the wrapped storage cannot be viewed as an `f32` matrix.
+pub trait TryQuantize<V> {
+    /// Quantize the embedding matrix.
+    ///
+    /// See `Quantize::quantize`.
+    fn try_quantize<T>(
+        &self,
+        n_subquantizers: usize,
+        n_subquantizer_bits: u32,
+        n_iterations: usize,
+        n_attempts: usize,
+        normalize: bool,
+    ) -> Result<Embeddings<V, QuantizedArray>>
+    where
+        T: TrainPQ<f32>,
+    {
+        self.try_quantize_using::<T, _>(
+            n_subquantizers,
+            n_subquantizer_bits,
+            n_iterations,
+            n_attempts,
+            normalize,
+            XorShiftRng::from_entropy(),
+        )
+    }
+
+    /// Quantize the embedding matrix using the provided RNG.
+    ///
+    /// See `Quantize::quantize_using`.
+    fn try_quantize_using<T, R>(
+        &self,
+        n_subquantizers: usize,
+        n_subquantizer_bits: u32,
+        n_iterations: usize,
+        n_attempts: usize,
+        normalize: bool,
+        rng: R,
+    ) -> Result<Embeddings<V, QuantizedArray>>
+    where
+        T: TrainPQ<f32>,
+        R: RngCore + SeedableRng + Send;
+}
+
+impl<V, S> TryQuantize<V> for Embeddings<V, S>
+where
+    V: Vocab + Clone,
+    S: TryQuantizeStorage,
+{
+    fn try_quantize_using<T, R>(
+        &self,
+        n_subquantizers: usize,
+        n_subquantizer_bits: u32,
+        n_iterations: usize,
+        n_attempts: usize,
+        normalize: bool,
+        rng: R,
+    ) -> Result<Embeddings<V, QuantizedArray>>
+    where
+        T: TrainPQ<f32>,
+        R: RngCore + SeedableRng + Send,
+    {
+        let quantized_storage = self.storage().try_quantize_using::<T, R>(
+            n_subquantizers,
+            n_subquantizer_bits,
+            n_iterations,
+            n_attempts,
+            normalize,
+            rng,
+        )?;
+
+        Ok(Embeddings {
+            metadata: self.metadata().cloned(),
+            vocab: self.vocab.clone(),
+            storage: quantized_storage,
+            norms: self.norms().cloned(),
+            counts: self.counts.clone(),
+            transform: self.transform.clone(),
+            normalization: self.normalization.clone(),
+            unknown_chunks: self.unknown_chunks.clone(),
+        })
+    }
+}
+
+/// Quantizable embedding matrix using residual quantization.
+pub trait QuantizeResidual<V> {
+    /// Quantize the embedding matrix using residual quantization.
+    ///
+    /// This method trains a coarse codebook with `n_coarse_centroids`
+    /// centroids and a product quantizer for the residuals, and then
+    /// quantizes the matrix. See `Quantize::quantize` for the
+    /// product quantization parameters.
+    ///
+    /// The xorshift PRNG is used for picking the initial centroids.
+    fn quantize_residual<T>(
+        &self,
+        n_coarse_centroids: usize,
+        n_subquantizers: usize,
+        n_subquantizer_bits: u32,
+        n_iterations: usize,
+        n_attempts: usize,
+        normalize: bool,
+    ) -> Embeddings<V, ResidualQuantizedArray>
+    where
+        T: TrainPQ<f32>,
+    {
+        self.quantize_residual_using::<T, _>(
+            n_coarse_centroids,
+            n_subquantizers,
+            n_subquantizer_bits,
+            n_iterations,
+            n_attempts,
+            normalize,
+            XorShiftRng::from_entropy(),
+        )
+    }
+
+    /// Quantize the embedding matrix using residual quantization and
+    /// the provided RNG.
+    #[allow(clippy::too_many_arguments)]
+    fn quantize_residual_using<T, R>(
+        &self,
+        n_coarse_centroids: usize,
+        n_subquantizers: usize,
+        n_subquantizer_bits: u32,
+        n_iterations: usize,
+        n_attempts: usize,
+        normalize: bool,
+        rng: R,
+    ) -> Embeddings<V, ResidualQuantizedArray>
+    where
+        T: TrainPQ<f32>,
+        R: RngCore + SeedableRng + Send;
+}
+
+impl<V, S> QuantizeResidual<V> for Embeddings<V, S>
+where
+    V: Vocab + Clone,
+    S: StorageView,
+{
+    #[allow(clippy::too_many_arguments)]
+    fn quantize_residual_using<T, R>(
+        &self,
+        n_coarse_centroids: usize,
+        n_subquantizers: usize,
+        n_subquantizer_bits: u32,
+        n_iterations: usize,
+        n_attempts: usize,
+        normalize: bool,
+        rng: R,
+    ) -> Embeddings<V, ResidualQuantizedArray>
+    where
+        T: TrainPQ<f32>,
+        R: RngCore + SeedableRng + Send,
+    {
+        let quantized_storage = self.storage().quantize_residual_using::<T, R>(
+            n_coarse_centroids,
+            n_subquantizers,
+            n_subquantizer_bits,
+            n_iterations,
+            n_attempts,
+            normalize,
+            rng,
+        );
+
+        Embeddings {
+            metadata: self.metadata().cloned(),
+            vocab: self.vocab.clone(),
+            storage: quantized_storage,
+            norms: self.norms().cloned(),
+            counts: self.counts.clone(),
+            transform: self.transform.clone(),
+            normalization: self.normalization.clone(),
+            unknown_chunks: self.unknown_chunks.clone(),
+        }
+    }
+}
+
+/// Embeddings pruning.
+pub trait Prune<V> {
+    /// Deduplicate the rows of the embedding matrix.
+    ///
+    /// Rows are duplicates when they are equal after rounding their
+    /// components to multiples of `tolerance`. Only identical rows
+    /// are deduplicated when `tolerance` is zero.
+    fn prune(&self, tolerance: f32) -> Embeddings<V, DedupArray>;
+}
+
+impl<V, S> Prune<V> for Embeddings<V, S>
+where
+    V: Vocab + Clone,
+    S: Storage,
+{
+    fn prune(&self, tolerance: f32) -> Embeddings<V, DedupArray> {
+        Embeddings {
+            metadata: self.metadata().cloned(),
+            vocab: self.vocab.clone(),
+            storage: self.storage().prune(tolerance),
+            norms: self.norms().cloned(),
+            counts: self.counts.clone(),
+            transform: self.transform.clone(),
+            normalization: self.normalization.clone(),
+            unknown_chunks: self.unknown_chunks.clone(),
+        }
+    }
+}
+
+/// An embedding with its (pre-normalization) l2 norm.
+pub struct EmbeddingWithNorm<'a> {
+    pub embedding: CowArray<'a, f32, Ix1>,
+    pub norm: f32,
+}
+
+impl<'a> EmbeddingWithNorm<'a> {
+    // Compute the unnormalized embedding.
+    pub fn into_unnormalized(self) -> Array1<f32> {
+        let mut unnormalized = self.embedding.into_owned();
+        unnormalized *= self.norm;
+        unnormalized
+    }
+}
+
+/// Strategy that was used to look up a phrase.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PhraseStrategy {
+    /// The connected phrase is a word in the vocabulary.
+    Connected,
+
+    /// The embeddings of the tokens of the phrase were averaged.
+    ///
+    /// `n_found` of the `n_tokens` tokens had an embedding.
+    Average { n_found: usize, n_tokens: usize },
+}
+
+/// The embedding of a phrase with its lookup strategy.
+pub struct PhraseEmbedding<'a> {
+    pub embedding: CowArray<'a, f32, Ix1>,
+    pub strategy: PhraseStrategy,
+}
+
+/// Iterator over embeddings.
+pub struct Iter<'a> {
+    storage: &'a dyn Storage,
+    transform: Option<&'a dyn LookupTransform>,
+    inner: VocabIter<'a>,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (Cow<'a, str>, CowArray<'a, f32, Ix1>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let transform = self.transform;
+        self.inner.next().map(|(word, idx)| {
+            (
+                word,
+                transform_embedding(transform, self.storage.embedding(idx)),
+            )
+        })
+    }
+}
+
+/// Iterator over embeddings.
+pub struct IterWithNorms<'a> {
+    storage: &'a dyn Storage,
+    norms: Option<&'a NdNorms>,
+    transform: Option<&'a dyn LookupTransform>,
+    inner: VocabIter<'a>,
+}
+
+impl<'a> Iterator for IterWithNorms<'a> {
+    type Item = (Cow<'a, str>, EmbeddingWithNorm<'a>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(word, idx)| {
+            (
+                word,
+                EmbeddingWithNorm {
+                    embedding: transform_embedding(self.transform, self.storage.embedding(idx)),
+                    norm: self.norms.map(|n| n[idx]).unwrap_or(1.),
+                },
+            )
+        })
+    }
+}
+
+fn transform_embedding<'a>(
+    transform: Option<&dyn LookupTransform>,
+    embedding: CowArray<'a, f32, Ix1>,
+) -> CowArray<'a, f32, Ix1> {
+    match transform {
+        Some(transform) => CowArray::from(transform.transform_embedding(embedding.view())),
+        None => embedding,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::sync::Arc;
+
+    use approx::AbsDiffEq;
+
+    use ndarray::{array, Array1, Array2, CowArray, Ix1};
+
+    use super::{Embeddings, MergeConflict, PhraseStrategy};
+    use crate::chunks::counts::WordCounts;
+
+    use crate::chunks::norms::NdNorms;
+    use crate::chunks::storage::{NdArray, Storage};
+    use crate::chunks::vocab::{LanguageTagFormat, SimpleVocab, Vocab};
+    use crate::normalization::DigitNormalization;
+
+    /// Storage that only serves batched lookups.
+    struct BatchStorage {
+        inner: NdArray,
+        n_batches: Cell<usize>,
+    }
+
+    impl Storage for BatchStorage {
+        fn embedding(&self, _idx: usize) -> CowArray<'_, f32, Ix1> {
+            panic!("Embeddings should be retrieved in a batch")
+        }
+
+        fn embeddings(&self, indices: &[usize]) -> Array2<f32> {
+            self.n_batches.set(self.n_batches.get() + 1);
+            self.inner.embeddings(indices)
+        }
+
+        fn shape(&self) -> (usize, usize) {
+            self.inner.shape()
+        }
+    }
+
+    #[test]
+    fn embedding_batch_retrieves_words_in_one_batch() {
+        let vocab = SimpleVocab::new(vec!["a".to_owned(), "b".to_owned(), "c".to_owned()]);
+        let storage = BatchStorage {
+            inner: NdArray::new(array![[1., 0.], [0., 1.], [-1., 0.]]),
+            n_batches: Cell::new(0),
+        };
+        let embeds = Embeddings::new(None, vocab, storage, NdNorms::new(Array1::ones(3)));
+
+        let (batch, found) = embeds.embedding_batch(&["c", "unknown", "a", "c"]);
+        assert_eq!(embeds.storage().n_batches.get(), 1);
+        assert_eq!(found, vec![true, false, true, true]);
+        assert_eq!(batch, array![[-1., 0.], [0., 0.], [1., 0.], [-1., 0.]]);
+    }
+
+    #[test]
+    fn phrase_embedding_strategies() {
+        let vocab = SimpleVocab::new(vec![
+            "New_York".to_owned(),
+            "New".to_owned(),
+            "York".to_owned(),
+        ]);
+        let storage = NdArray::new(array![[1., 0.], [0., 1.], [1., 0.]]);
+        let embeds = Embeddings::new(None, vocab, storage, NdNorms::new(Array1::ones(3)));
+
+        let phrase = embeds.phrase_embedding("New  York", "_").unwrap();
+        assert_eq!(phrase.strategy, PhraseStrategy::Connected);
+        assert_eq!(phrase.embedding, array![1., 0.]);
+
+        let phrase = embeds.phrase_embedding("New York City", "_").unwrap();
+        assert_eq!(
+            phrase.strategy,
+            PhraseStrategy::Average {
+                n_found: 2,
+                n_tokens: 3
+            }
+        );
+        let component = 0.5f32.sqrt();
+        assert!(phrase
+            .embedding
+            .abs_diff_eq(&array![component, component], 1e-6));
+
+        assert!(embeds.phrase_embedding("New York", "-").is_some());
+        assert!(embeds.phrase_embedding("Los Angeles", "_").is_none());
+        assert!(embeds.phrase_embedding(" ", "_").is_none());
+    }
+
+    fn merge_embeddings() -> (
+        Embeddings<SimpleVocab, NdArray>,
+        Embeddings<SimpleVocab, NdArray>,
+    ) {
+        let first = Embeddings::new(
+            None,
+            SimpleVocab::new(vec!["a".to_owned(), "b".to_owned()]),
+            NdArray::new(array![[1., 0.], [0., 1.]]),
+            NdNorms::new(array![2., 1.]),
+        );
+        let mut second = Embeddings::new(
+            None,
+            SimpleVocab::new(vec!["c".to_owned(), "a".to_owned()]),
+            NdArray::new(array![[0.6, 0.8], [0., 1.]]),
+            NdNorms::new(array![3., 2.]),
+        );
+        second.set_counts(Some(WordCounts::new(vec![5, 7])));
+
+        (first, second)
+    }
+
+    #[test]
+    fn merge_resolves_conflicts() {
+        let (mut first, second) = merge_embeddings();
+        let merged = first.merge(&second, MergeConflict::First).unwrap();
+        assert_eq!(merged.vocab().words(), &["a", "b", "c"]);
+        assert_eq!(merged.embedding("a").unwrap(), array![1., 0.]);
+        assert_eq!(merged.embedding("c").unwrap(), array![0.6, 0.8]);
+        assert_eq!(merged.norms().unwrap().view(), array![2., 1., 3.]);
+
+        // Counts are only merged when both embeddings have counts.
+        assert!(merged.counts().is_none());
+        first.set_counts(Some(WordCounts::new(vec![1, 2])));
+
+        let merged = first.merge(&second, MergeConflict::Last).unwrap();
+        assert_eq!(merged.embedding("a").unwrap(), array![0., 1.]);
+        assert_eq!(merged.counts().unwrap(), &WordCounts::new(vec![8, 2, 5]));
+
+        // The average of (2, 0) and (0, 2) is (1, 1).
+        let merged = first.merge(&second, MergeConflict::Average).unwrap();
+        let norm = 2f32.sqrt();
+        assert!(merged
+            .embedding("a")
+            .unwrap()
+            .abs_diff_eq(&array![1. / norm, 1. / norm], 1e-6));
+        assert!((merged.norms().unwrap()[0] - norm).abs() < 1e-6);
+    }
+
+    #[test]
+    fn merge_rejects_incompatible_dims() {
+        let (first, _) = merge_embeddings();
+        let other = Embeddings::new(
+            None,
+            SimpleVocab::new(vec!["a".to_owned()]),
+            NdArray::new(array![[1., 0., 0.]]),
+            NdNorms::new(array![1.]),
+        );
+        assert!(first.merge(&other, MergeConflict::First).is_err());
+    }
+
+    fn normalized_embeddings() -> Embeddings<SimpleVocab, NdArray> {
+        let mut embeds = Embeddings::new(
+            None,
+            SimpleVocab::new(vec!["2001".to_owned(), "a".to_owned(), "1999".to_owned()]),
+            NdArray::new(array![[1., 0.], [0.6, 0.8], [0., 1.]]),
+            NdNorms::new(array![2., 1., 2.]),
+        );
+        embeds.set_counts(Some(WordCounts::new(vec![3, 1, 4])));
+        embeds.set_normalization(Some(Arc::new(DigitNormalization::default())));
+        embeds
+    }
+
+    #[test]
+    fn normalize_vocab_rejects_collisions() {
+        let mut embeds = normalized_embeddings();
+        assert!(embeds.normalize_vocab().is_err());
+        assert_eq!(embeds.vocab().words(), &["2001", "a", "1999"]);
+    }
+
+    #[test]
+    fn merge_normalized_resolves_collisions() {
+        let embeds = normalized_embeddings();
+        let merged = embeds.merge_normalized(MergeConflict::First);
+        assert_eq!(merged.vocab().words(), &["0000", "a"]);
+        assert_eq!(merged.embedding("1984").unwrap(), array![1., 0.]);
+        assert_eq!(merged.counts().unwrap(), &WordCounts::new(vec![7, 1]));
+
+        let merged = embeds.merge_normalized(MergeConflict::Last);
+        assert_eq!(merged.embedding("1984").unwrap(), array![0., 1.]);
+
+        // The average of (2, 0) and (0, 2) is (1, 1).
+        let merged = embeds.merge_normalized(MergeConflict::Average);
+        let norm = 2f32.sqrt();
+        assert!(merged
+            .embedding("2001")
+            .unwrap()
+            .abs_diff_eq(&array![1. / norm, 1. / norm], 1e-6));
+        assert_eq!(merged.embedding("a").unwrap(), array![0.6, 0.8]);
+        assert!(merged
+            .norms()
+            .unwrap()
+            .view()
+            .abs_diff_eq(&array![norm, 1.], 1e-6));
+    }
+
+    #[test]
+    fn layered_prefers_first_layer() {
+        let (first, second) = merge_embeddings();
+        let layered = Embeddings::layered(vec![second.into(), first.into()]).unwrap();
+        assert_eq!(layered.vocab().words(), &["c", "a", "b"]);
+        assert_eq!(layered.embedding("a").unwrap(), array![0., 1.]);
+        assert_eq!(layered.embedding("b").unwrap(), array![0., 1.]);
+        assert_eq!(layered.embedding("c").unwrap(), array![0.6, 0.8]);
+        assert_eq!(layered.norms().unwrap().view(), array![3., 2., 1.]);
+        assert_eq!(layered.storage().shape(), (3, 2));
+
+        // Counts are only retained when all layers have counts.
+        assert!(layered.counts().is_none());
+    }
+
+    #[test]
+    fn layered_rejects_missing_layers() {
+        assert!(Embeddings::layered(Vec::new()).is_err());
+    }
+
+    #[test]
+    fn intersect_aligns_rows() {
+        let (first, second) = merge_embeddings();
+        let (first_shared, second_shared) = first.intersect(&second);
+        assert_eq!(first_shared.vocab().words(), &["a"]);
+        assert_eq!(second_shared.vocab().words(), &["a"]);
+        assert_eq!(first_shared.embedding("a").unwrap(), array![1., 0.]);
+        assert_eq!(second_shared.embedding("a").unwrap(), array![0., 1.]);
+        assert_eq!(second_shared.norms().unwrap().view(), array![2.]);
+        assert_eq!(second_shared.counts().unwrap(), &WordCounts::new(vec![7]));
+
+        // Rows of the second embeddings follow the order of the first.
+        let third = Embeddings::new(
+            None,
+            SimpleVocab::new(vec!["b".to_owned(), "c".to_owned(), "a".to_owned()]),
+            NdArray::new(array![[1., 0.], [0., 1.], [0.6, 0.8]]),
+            NdNorms::new(array![1., 1., 1.]),
+        );
+        let (first_shared, third_shared) = first.intersect(&third);
+        assert_eq!(first_shared.vocab().words(), &["a", "b"]);
+        assert_eq!(third_shared.vocab().words(), &["a", "b"]);
+        assert_eq!(third_shared.storage().embedding(0), array![0.6, 0.8]);
+        assert_eq!(third_shared.storage().embedding(1), array![1., 0.]);
+
+        let difference = second.difference(&first);
+        assert_eq!(difference.vocab().words(), &["c"]);
+        assert_eq!(difference.embedding("c").unwrap(), array![0.6, 0.8]);
+        assert_eq!(difference.counts().unwrap(), &WordCounts::new(vec![5]));
+    }
+
+    #[test]
+    fn language_embedding_lookup() {
+        let vocab = SimpleVocab::new(vec![
+            "/c/en/dog".to_owned(),
+            "/c/nl/hond".to_owned(),
+            "/c/nl/kat".to_owned(),
+        ]);
+        let storage = NdArray::new(array![[1., 0.], [0., 1.], [1., 0.]]);
+        let embeds = Embeddings::new(None, vocab, storage, NdNorms::new(Array1::ones(3)))
+            .into_language_vocab(LanguageTagFormat::conceptnet());
+
+        assert_eq!(
+            embeds.language_embedding("nl", "hond"),
+            embeds.embedding("/c/nl/hond")
+        );
+        assert!(embeds.language_embedding("en", "hond").is_none());
+        assert_eq!(
+            embeds
+                .vocab()
+                .language_words("nl")
+                .unwrap()
+                .map(|(word, _)| word)
+                .collect::<Vec<_>>(),
+            vec!["hond", "kat"]
+        );
+    }
+
+    #[test]
+    fn sort_by_frequency_ranks_words() {
+        let vocab = SimpleVocab::new(vec![
+            "the".to_owned(),
+            "rare".to_owned(),
+            "a".to_owned(),
+            "an".to_owned(),
+        ]);
+        let storage = NdArray::new(array![[1., 0.], [0., 1.], [1., 0.], [0., 1.]]);
+        let mut embeds = Embeddings::new(None, vocab, storage, NdNorms::new(Array1::ones(4)));
+        assert!(!embeds.is_frequency_ranked());
+        embeds.set_counts(Some(WordCounts::new(vec![10, 1, 8, 8])));
+        assert!(!embeds.is_frequency_ranked());
+
+        let sorted = embeds.sort_by_frequency().unwrap();
+        assert!(sorted.is_frequency_ranked());
+        assert_eq!(sorted.vocab().words(), &["the", "a", "an", "rare"]);
+        assert_eq!(sorted.vocab().rank("rare"), Some(3));
+        assert_eq!(sorted.count("rare"), Some(1));
+        assert_eq!(sorted.embedding("rare"), embeds.embedding("rare"));
+        assert_eq!(
+            sorted.vocab().top_n(2).collect::<Vec<_>>(),
+            vec![("the".into(), 0), ("a".into(), 1)]
+        );
+    }
+}
@@ -0,0 +1,80 @@
+//! Error types.
+//!
+//! This module provides the `Error`, `ErrorKind`, and `Result` types
+//! that are used for handling errors throughout finalfusion.
+
+use std::{fmt, io};
+
+use ndarray::ShapeError;
+
+/// `Result` type alias for operations that can lead to I/O errors.
+pub type Result<T> = ::std::result::Result<T, Error>;
+
+/// I/O errors in reading or writing embeddings.
+#[derive(Debug)]
+pub enum Error {
+    /// finalfusion errors.
+    FinalFusion(ErrorKind),
+
+    /// `ndarray` shape error.
+    Shape(ShapeError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::Error::*;
+        match *self {
+            FinalFusion(ref kind) => kind.fmt(f),
+            Shape(ref err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        use self::Error::*;
+
+        match *self {
+            FinalFusion(ErrorKind::Format(_)) => None,
+            FinalFusion(ErrorKind::Io { ref error, .. }) => Some(error),
+            Shape(ref err) => Some(err),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ErrorKind {
+    /// Invalid file format.
+    Format(String),
+
+    /// I/O error.
+    Io { desc: String, error: io::Error },
+}
+
+impl ErrorKind {
+    pub fn io_error(desc: impl Into<String>, error: io::Error) -> Self {
+        ErrorKind::Io {
+            desc: desc.into(),
+            error,
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::ErrorKind::*;
+        match *self {
+            Format(ref desc) => write!(f, "{}", desc),
+            Io {
+                ref desc,
+                ref error,
+            } => write!(f, "{}: {}", desc, error),
+        }
+    }
+}
+
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Error {
+        Error::FinalFusion(kind)
+    }
+}
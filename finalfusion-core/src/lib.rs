@@ -0,0 +1,32 @@
+//! Embedding lookup types of finalfusion.
+//!
+//! This crate provides the types for using word embeddings: the
+//! `Embeddings` type, the vocabulary and embedding matrix (storage)
+//! types, and similarity and analogy queries. It does not read or
+//! write embeddings, so it can be used by crates that construct
+//! embeddings in memory or provide their own storage.
+//!
+//! Embeddings in the finalfusion format are read and written with the
+//! `finalfusion-io` crate. The `finalfusion-compat` crate reads and
+//! writes the fastText, word2vec, and text formats. The `finalfusion`
+//! crate re-exports all three crates.
+
+mod chunks;
+pub use chunks::{counts, memory, metadata, norms, storage, unknown, vocab};
+
+pub mod embeddings;
+
+pub mod error;
+
+pub mod normalization;
+
+pub mod similarity;
+
+pub mod subword;
+
+pub mod transform;
+
+#[doc(hidden)]
+pub mod util;
+
+pub mod warnings;
@@ -0,0 +1,695 @@
+//! Normalization of words before vocabulary lookups.
+//!
+//! The same word can be encoded in Unicode in several ways. For
+//! instance, *é* can be encoded as the precomposed character U+00E9
+//! or as *e* followed by the combining acute accent U+0301. A
+//! vocabulary lookup only succeeds when the query word uses the same
+//! encoding as the vocabulary.
+//!
+//! A `WordNormalization` can be installed on `Embeddings` with
+//! `Embeddings::set_normalization`. Query words are then normalized
+//! before they are looked up in the vocabulary. The words of the
+//! vocabulary itself can be normalized with the same normalization
+//! using `Embeddings::normalize_vocab`, typically directly after
+//! loading the embeddings.
+//!
+//! `CaseFolding` implements Unicode full case folding, so that
+//! lookups are insensitive to the case of query words. Case folding
+//! differs from lowercasing for some characters, for instance *ß* is
+//! folded to *ss* and the final sigma *ς* to *σ*. Turkish and
+//! Azerbaijani distinguish a dotted and a dotless *i*; the Turkic
+//! case folding folds *I* to *ı* and *İ* to *i*.
+//!
+//! `DigitNormalization` replaces all ASCII digits by a canonical
+//! digit, for embeddings that were trained on corpora in which
+//! digits were normalized, so that e.g. *1984* and *2001* are both
+//! looked up as *0000*.
+//!
+//! `UnicodeNormalization` normalizes words to one of the Unicode
+//! normalization forms. NFC composes characters, so that *e* followed
+//! by a combining acute accent is looked up as *é*. NFKC additionally
+//! replaces compatibility characters, e.g. the ligature *ﬁ* by *fi*.
+//!
+//! ```
+//! use std::sync::Arc;
+//!
+//! use finalfusion_core::embeddings::Embeddings;
+//! use finalfusion_core::normalization::{NormalizationForm, UnicodeNormalization};
+//! use finalfusion_core::norms::NdNorms;
+//! use finalfusion_core::storage::NdArray;
+//! use finalfusion_core::vocab::SimpleVocab;
+//! use ndarray::array;
+//!
+//! let mut embeds = Embeddings::new(
+//!     None,
+//!     SimpleVocab::new(vec!["café".to_owned(), "Potsdam".to_owned()]),
+//!     NdArray::new(array![[0.6, 0.8], [0.8, -0.6]]),
+//!     NdNorms::new(array![5., 10.]),
+//! );
+//!
+//! let normalization = UnicodeNormalization::new(NormalizationForm::Nfc);
+//! embeds.set_normalization(Some(Arc::new(normalization)));
+//!
+//! assert_eq!(embeds.embedding("cafe\u{0301}"), embeds.embedding("café"));
+//! ```
+//!
+//! Other normalizations can be used by wrapping a function in
+//! `FnNormalization`.
+//!
+//! ```
+//! use std::borrow::Cow;
+//! use std::sync::Arc;
+//!
+//! use finalfusion_core::embeddings::Embeddings;
+//! use finalfusion_core::normalization::FnNormalization;
+//! use finalfusion_core::norms::NdNorms;
+//! use finalfusion_core::storage::NdArray;
+//! use finalfusion_core::vocab::SimpleVocab;
+//! use ndarray::array;
+//!
+//! let mut embeds = Embeddings::new(
+//!     None,
+//!     SimpleVocab::new(vec!["Berlin".to_owned(), "Potsdam".to_owned()]),
+//!     NdArray::new(array![[0.6, 0.8], [0.8, -0.6]]),
+//!     NdNorms::new(array![5., 10.]),
+//! );
+//!
+//! // Strip a trailing full stop from query words.
+//! let normalization = FnNormalization::new("strip_full_stop", |word: &str| {
+//!     Cow::Owned(word.trim_end_matches('.').to_owned())
+//! });
+//! embeds.set_normalization(Some(Arc::new(normalization)));
+//!
+//! assert_eq!(embeds.embedding("Berlin."), embeds.embedding("Berlin"));
+//! ```
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+
+use unicode_normalization::{is_nfc, is_nfd, is_nfkc, is_nfkd, UnicodeNormalization as _};
+
+use crate::error::{Error, ErrorKind, Result};
+
+/// Normalization of words.
+pub trait WordNormalization: fmt::Debug + Send + Sync {
+    /// Normalize a word.
+    ///
+    /// Implementations should return `Cow::Borrowed` when the word is
+    /// already normalized, to avoid allocations in lookups.
+    fn normalize<'a>(&self, word: &'a str) -> Cow<'a, str>;
+}
+
+/// Normalization using a function.
+///
+/// The name of the normalization is only used in its `Debug`
+/// representation.
+#[derive(Clone)]
+pub struct FnNormalization<F> {
+    name: String,
+    normalize: F,
+}
+
+impl<F> FnNormalization<F>
+where
+    F: for<'a> Fn(&'a str) -> Cow<'a, str> + Send + Sync,
+{
+    /// Construct a normalization from a name and a function.
+    pub fn new(name: impl Into<String>, normalize: F) -> Self {
+        FnNormalization {
+            name: name.into(),
+            normalize,
+        }
+    }
+
+    /// Get the name of the normalization.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl<F> fmt::Debug for FnNormalization<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FnNormalization")
+            .field("name", &self.name)
+            .finish()
+    }
+}
+
+impl<F> WordNormalization for FnNormalization<F>
+where
+    F: for<'a> Fn(&'a str) -> Cow<'a, str> + Send + Sync,
+{
+    fn normalize<'a>(&self, word: &'a str) -> Cow<'a, str> {
+        (self.normalize)(word)
+    }
+}
+
+/// Normalization of the words of a vocabulary.
+pub trait NormalizeVocab {
+    /// Replace the words of the vocabulary by their normalized forms.
+    ///
+    /// Words keep their indices. Returns an error when words have the
+    /// same normalized form, since the vocabulary would then contain
+    /// duplicate words. The vocabulary is not modified in that case.
+    /// `Embeddings::merge_normalized` resolves such collisions by
+    /// merging the embeddings of the colliding words.
+    fn normalize_vocab(&mut self, normalization: &dyn WordNormalization) -> Result<()>;
+}
+
+/// Normalize words in place.
+///
+/// Returns an error without modifying the words when two words have
+/// the same normalized form.
+pub(crate) fn normalize_words(
+    words: &mut [String],
+    normalization: &dyn WordNormalization,
+) -> Result<()> {
+    let normalized = words
+        .iter()
+        .map(|word| match normalization.normalize(word) {
+            Cow::Borrowed(_) => None,
+            Cow::Owned(normalized) => Some(normalized),
+        })
+        .collect::<Vec<_>>();
+
+    let mut seen: HashMap<&str, usize> = HashMap::with_capacity(words.len());
+    for (idx, (word, normalized)) in words.iter().zip(&normalized).enumerate() {
+        let form = normalized.as_deref().unwrap_or(word);
+        if let Some(other) = seen.insert(form, idx) {
+            return Err(collision_error(&words[other], word, form));
+        }
+    }
+
+    for (word, normalized) in words.iter_mut().zip(normalized) {
+        if let Some(normalized) = normalized {
+            *word = normalized;
+        }
+    }
+
+    Ok(())
+}
+
+pub(crate) fn collision_error(word: &str, other: &str, normalized: &str) -> Error {
+    ErrorKind::Format(format!(
+        "Words '{}' and '{}' have the same normalized form: '{}'",
+        word, other, normalized
+    ))
+    .into()
+}
+
+/// Unicode normalization form.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum NormalizationForm {
+    /// Canonical decomposition followed by canonical composition.
+    Nfc,
+
+    /// Canonical decomposition.
+    Nfd,
+
+    /// Compatibility decomposition followed by canonical composition.
+    Nfkc,
+
+    /// Compatibility decomposition.
+    Nfkd,
+}
+
+/// Unicode normalization.
+///
+/// Normalizes words to a Unicode normalization form, so that words
+/// that are canonically (NFC, NFD) or compatibility (NFKC, NFKD)
+/// equivalent are looked up as the same word.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct UnicodeNormalization {
+    form: NormalizationForm,
+}
+
+impl UnicodeNormalization {
+    /// Construct a normalization to the given normalization form.
+    pub fn new(form: NormalizationForm) -> Self {
+        UnicodeNormalization { form }
+    }
+
+    /// Get the normalization form.
+    pub fn form(self) -> NormalizationForm {
+        self.form
+    }
+}
+
+impl WordNormalization for UnicodeNormalization {
+    fn normalize<'a>(&self, word: &'a str) -> Cow<'a, str> {
+        let normalized = match self.form {
+            NormalizationForm::Nfc => is_nfc(word),
+            NormalizationForm::Nfd => is_nfd(word),
+            NormalizationForm::Nfkc => is_nfkc(word),
+            NormalizationForm::Nfkd => is_nfkd(word),
+        };
+
+        if normalized {
+            return Cow::Borrowed(word);
+        }
+
+        Cow::Owned(match self.form {
+            NormalizationForm::Nfc => word.nfc().collect(),
+            NormalizationForm::Nfd => word.nfd().collect(),
+            NormalizationForm::Nfkc => word.nfkc().collect(),
+            NormalizationForm::Nfkd => word.nfkd().collect(),
+        })
+    }
+}
+
+/// Normalization of digits.
+///
+/// Replaces every ASCII digit by the same character, `0` by default.
+/// Some embeddings were trained on corpora that normalized digits to
+/// another character, for instance the word2vec Google News
+/// embeddings use `#`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct DigitNormalization {
+    replacement: char,
+}
+
+impl DigitNormalization {
+    /// Construct a digit normalization with the given replacement.
+    pub fn new(replacement: char) -> Self {
+        DigitNormalization { replacement }
+    }
+
+    /// Get the character that digits are replaced by.
+    pub fn replacement(self) -> char {
+        self.replacement
+    }
+
+    fn is_normalized(self, c: char) -> bool {
+        !c.is_ascii_digit() || c == self.replacement
+    }
+}
+
+impl Default for DigitNormalization {
+    fn default() -> Self {
+        DigitNormalization::new('0')
+    }
+}
+
+impl WordNormalization for DigitNormalization {
+    fn normalize<'a>(&self, word: &'a str) -> Cow<'a, str> {
+        if word.chars().all(|c| self.is_normalized(c)) {
+            return Cow::Borrowed(word);
+        }
+
+        Cow::Owned(
+            word.chars()
+                .map(|c| {
+                    if c.is_ascii_digit() {
+                        self.replacement
+                    } else {
+                        c
+                    }
+                })
+                .collect(),
+        )
+    }
+}
+
+/// Locale of case folding.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum CaseFoldingLocale {
+    /// Case folding that is suitable for most languages.
+    #[default]
+    Default,
+
+    /// Case folding for Turkish and Azerbaijani.
+    ///
+    /// *I* is folded to the dotless *ı* and *İ* to *i*.
+    Turkic,
+}
+
+impl CaseFoldingLocale {
+    /// Get the case folding locale for a BCP 47 language tag.
+    ///
+    /// Returns the `Turkic` locale for Turkish (`tr`) and Azerbaijani
+    /// (`az`) and the `Default` locale for other languages.
+    pub fn for_language_tag(tag: &str) -> Self {
+        let language = tag.split(['-', '_']).next().unwrap_or("");
+        if language.eq_ignore_ascii_case("tr") || language.eq_ignore_ascii_case("az") {
+            CaseFoldingLocale::Turkic
+        } else {
+            CaseFoldingLocale::Default
+        }
+    }
+}
+
+/// Unicode full case folding.
+///
+/// Case folding maps words that only differ in case to the same
+/// form. In contrast to lowercasing, characters can be folded to
+/// multiple characters, e.g. *ß* is folded to *ss*.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct CaseFolding {
+    locale: CaseFoldingLocale,
+}
+
+impl CaseFolding {
+    /// Construct a case folding for the given locale.
+    pub fn new(locale: CaseFoldingLocale) -> Self {
+        CaseFolding { locale }
+    }
+
+    /// Construct a case folding for Turkish and Azerbaijani.
+    pub fn turkic() -> Self {
+        CaseFolding::new(CaseFoldingLocale::Turkic)
+    }
+
+    /// Get the locale of the case folding.
+    pub fn locale(self) -> CaseFoldingLocale {
+        self.locale
+    }
+
+    /// Fold a character, appending the folded form to `folded`.
+    fn fold_char(self, c: char, folded: &mut String) {
+        if self.locale == CaseFoldingLocale::Turkic {
+            match c {
+                'I' => return folded.push('\u{0131}'),
+                '\u{0130}' => return folded.push('i'),
+                _ => (),
+            }
+        }
+
+        match c as u32 {
+            // Cherokee folds to uppercase, since the uppercase letters
+            // were encoded first.
+            0x13a0..=0x13f5 => return folded.push(c),
+            0x13f8..=0x13fd => return push_code_point(c as u32 - 8, folded),
+            0xab70..=0xabbf => return push_code_point(c as u32 - 0xab70 + 0x13a0, folded),
+            _ => (),
+        }
+
+        match FOLDING_EXCEPTIONS.binary_search_by_key(&c, |&(from, _)| from) {
+            Ok(idx) => folded.push_str(FOLDING_EXCEPTIONS[idx].1),
+            Err(_) => folded.extend(c.to_lowercase()),
+        }
+    }
+
+    /// Check whether a character is unchanged by case folding.
+    fn is_folded(self, c: char) -> bool {
+        if c.is_ascii() {
+            return !c.is_ascii_uppercase();
+        }
+
+        let mut folded = String::new();
+        self.fold_char(c, &mut folded);
+        let mut chars = folded.chars();
+        chars.next() == Some(c) && chars.next().is_none()
+    }
+}
+
+impl WordNormalization for CaseFolding {
+    fn normalize<'a>(&self, word: &'a str) -> Cow<'a, str> {
+        let first_unfolded = match word.char_indices().find(|&(_, c)| !self.is_folded(c)) {
+            Some((idx, _)) => idx,
+            None => return Cow::Borrowed(word),
+        };
+
+        let mut folded = String::with_capacity(word.len());
+        folded.push_str(&word[..first_unfolded]);
+        for c in word[first_unfolded..].chars() {
+            self.fold_char(c, &mut folded);
+        }
+
+        Cow::Owned(folded)
+    }
+}
+
+fn push_code_point(code_point: u32, folded: &mut String) {
+    folded.push(std::char::from_u32(code_point).expect("Invalid code point"));
+}
+
+/// Characters for which full case folding differs from lowercasing.
+///
+/// Sorted by character, excluding Cherokee, which is handled by
+/// `CaseFolding::fold_char`.
+static FOLDING_EXCEPTIONS: &[(char, &str)] = &[
+    ('\u{00B5}', "\u{03BC}"),
+    ('\u{00DF}', "\u{0073}\u{0073}"),
+    ('\u{0149}', "\u{02BC}\u{006E}"),
+    ('\u{017F}', "\u{0073}"),
+    ('\u{01F0}', "\u{006A}\u{030C}"),
+    ('\u{0345}', "\u{03B9}"),
+    ('\u{0390}', "\u{03B9}\u{0308}\u{0301}"),
+    ('\u{03B0}', "\u{03C5}\u{0308}\u{0301}"),
+    ('\u{03C2}', "\u{03C3}"),
+    ('\u{03D0}', "\u{03B2}"),
+    ('\u{03D1}', "\u{03B8}"),
+    ('\u{03D5}', "\u{03C6}"),
+    ('\u{03D6}', "\u{03C0}"),
+    ('\u{03F0}', "\u{03BA}"),
+    ('\u{03F1}', "\u{03C1}"),
+    ('\u{03F5}', "\u{03B5}"),
+    ('\u{0587}', "\u{0565}\u{0582}"),
+    ('\u{1C80}', "\u{0432}"),
+    ('\u{1C81}', "\u{0434}"),
+    ('\u{1C82}', "\u{043E}"),
+    ('\u{1C83}', "\u{0441}"),
+    ('\u{1C84}', "\u{0442}"),
+    ('\u{1C85}', "\u{0442}"),
+    ('\u{1C86}', "\u{044A}"),
+    ('\u{1C87}', "\u{0463}"),
+    ('\u{1C88}', "\u{A64B}"),
+    ('\u{1E96}', "\u{0068}\u{0331}"),
+    ('\u{1E97}', "\u{0074}\u{0308}"),
+    ('\u{1E98}', "\u{0077}\u{030A}"),
+    ('\u{1E99}', "\u{0079}\u{030A}"),
+    ('\u{1E9A}', "\u{0061}\u{02BE}"),
+    ('\u{1E9B}', "\u{1E61}"),
+    ('\u{1E9E}', "\u{0073}\u{0073}"),
+    ('\u{1F50}', "\u{03C5}\u{0313}"),
+    ('\u{1F52}', "\u{03C5}\u{0313}\u{0300}"),
+    ('\u{1F54}', "\u{03C5}\u{0313}\u{0301}"),
+    ('\u{1F56}', "\u{03C5}\u{0313}\u{0342}"),
+    ('\u{1F80}', "\u{1F00}\u{03B9}"),
+    ('\u{1F81}', "\u{1F01}\u{03B9}"),
+    ('\u{1F82}', "\u{1F02}\u{03B9}"),
+    ('\u{1F83}', "\u{1F03}\u{03B9}"),
+    ('\u{1F84}', "\u{1F04}\u{03B9}"),
+    ('\u{1F85}', "\u{1F05}\u{03B9}"),
+    ('\u{1F86}', "\u{1F06}\u{03B9}"),
+    ('\u{1F87}', "\u{1F07}\u{03B9}"),
+    ('\u{1F88}', "\u{1F00}\u{03B9}"),
+    ('\u{1F89}', "\u{1F01}\u{03B9}"),
+    ('\u{1F8A}', "\u{1F02}\u{03B9}"),
+    ('\u{1F8B}', "\u{1F03}\u{03B9}"),
+    ('\u{1F8C}', "\u{1F04}\u{03B9}"),
+    ('\u{1F8D}', "\u{1F05}\u{03B9}"),
+    ('\u{1F8E}', "\u{1F06}\u{03B9}"),
+    ('\u{1F8F}', "\u{1F07}\u{03B9}"),
+    ('\u{1F90}', "\u{1F20}\u{03B9}"),
+    ('\u{1F91}', "\u{1F21}\u{03B9}"),
+    ('\u{1F92}', "\u{1F22}\u{03B9}"),
+    ('\u{1F93}', "\u{1F23}\u{03B9}"),
+    ('\u{1F94}', "\u{1F24}\u{03B9}"),
+    ('\u{1F95}', "\u{1F25}\u{03B9}"),
+    ('\u{1F96}', "\u{1F26}\u{03B9}"),
+    ('\u{1F97}', "\u{1F27}\u{03B9}"),
+    ('\u{1F98}', "\u{1F20}\u{03B9}"),
+    ('\u{1F99}', "\u{1F21}\u{03B9}"),
+    ('\u{1F9A}', "\u{1F22}\u{03B9}"),
+    ('\u{1F9B}', "\u{1F23}\u{03B9}"),
+    ('\u{1F9C}', "\u{1F24}\u{03B9}"),
+    ('\u{1F9D}', "\u{1F25}\u{03B9}"),
+    ('\u{1F9E}', "\u{1F26}\u{03B9}"),
+    ('\u{1F9F}', "\u{1F27}\u{03B9}"),
+    ('\u{1FA0}', "\u{1F60}\u{03B9}"),
+    ('\u{1FA1}', "\u{1F61}\u{03B9}"),
+    ('\u{1FA2}', "\u{1F62}\u{03B9}"),
+    ('\u{1FA3}', "\u{1F63}\u{03B9}"),
+    ('\u{1FA4}', "\u{1F64}\u{03B9}"),
+    ('\u{1FA5}', "\u{1F65}\u{03B9}"),
+    ('\u{1FA6}', "\u{1F66}\u{03B9}"),
+    ('\u{1FA7}', "\u{1F67}\u{03B9}"),
+    ('\u{1FA8}', "\u{1F60}\u{03B9}"),
+    ('\u{1FA9}', "\u{1F61}\u{03B9}"),
+    ('\u{1FAA}', "\u{1F62}\u{03B9}"),
+    ('\u{1FAB}', "\u{1F63}\u{03B9}"),
+    ('\u{1FAC}', "\u{1F64}\u{03B9}"),
+    ('\u{1FAD}', "\u{1F65}\u{03B9}"),
+    ('\u{1FAE}', "\u{1F66}\u{03B9}"),
+    ('\u{1FAF}', "\u{1F67}\u{03B9}"),
+    ('\u{1FB2}', "\u{1F70}\u{03B9}"),
+    ('\u{1FB3}', "\u{03B1}\u{03B9}"),
+    ('\u{1FB4}', "\u{03AC}\u{03B9}"),
+    ('\u{1FB6}', "\u{03B1}\u{0342}"),
+    ('\u{1FB7}', "\u{03B1}\u{0342}\u{03B9}"),
+    ('\u{1FBC}', "\u{03B1}\u{03B9}"),
+    ('\u{1FBE}', "\u{03B9}"),
+    ('\u{1FC2}', "\u{1F74}\u{03B9}"),
+    ('\u{1FC3}', "\u{03B7}\u{03B9}"),
+    ('\u{1FC4}', "\u{03AE}\u{03B9}"),
+    ('\u{1FC6}', "\u{03B7}\u{0342}"),
+    ('\u{1FC7}', "\u{03B7}\u{0342}\u{03B9}"),
+    ('\u{1FCC}', "\u{03B7}\u{03B9}"),
+    ('\u{1FD2}', "\u{03B9}\u{0308}\u{0300}"),
+    ('\u{1FD3}', "\u{03B9}\u{0308}\u{0301}"),
+    ('\u{1FD6}', "\u{03B9}\u{0342}"),
+    ('\u{1FD7}', "\u{03B9}\u{0308}\u{0342}"),
+    ('\u{1FE2}', "\u{03C5}\u{0308}\u{0300}"),
+    ('\u{1FE3}', "\u{03C5}\u{0308}\u{0301}"),
+    ('\u{1FE4}', "\u{03C1}\u{0313}"),
+    ('\u{1FE6}', "\u{03C5}\u{0342}"),
+    ('\u{1FE7}', "\u{03C5}\u{0308}\u{0342}"),
+    ('\u{1FF2}', "\u{1F7C}\u{03B9}"),
+    ('\u{1FF3}', "\u{03C9}\u{03B9}"),
+    ('\u{1FF4}', "\u{03CE}\u{03B9}"),
+    ('\u{1FF6}', "\u{03C9}\u{0342}"),
+    ('\u{1FF7}', "\u{03C9}\u{0342}\u{03B9}"),
+    ('\u{1FFC}', "\u{03C9}\u{03B9}"),
+    ('\u{FB00}', "\u{0066}\u{0066}"),
+    ('\u{FB01}', "\u{0066}\u{0069}"),
+    ('\u{FB02}', "\u{0066}\u{006C}"),
+    ('\u{FB03}', "\u{0066}\u{0066}\u{0069}"),
+    ('\u{FB04}', "\u{0066}\u{0066}\u{006C}"),
+    ('\u{FB05}', "\u{0073}\u{0074}"),
+    ('\u{FB06}', "\u{0073}\u{0074}"),
+    ('\u{FB13}', "\u{0574}\u{0576}"),
+    ('\u{FB14}', "\u{0574}\u{0565}"),
+    ('\u{FB15}', "\u{0574}\u{056B}"),
+    ('\u{FB16}', "\u{057E}\u{0576}"),
+    ('\u{FB17}', "\u{0574}\u{056D}"),
+];
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use super::{
+        normalize_words, CaseFolding, CaseFoldingLocale, DigitNormalization, FnNormalization,
+        NormalizationForm, UnicodeNormalization, WordNormalization,
+    };
+
+    fn lowercase() -> impl WordNormalization {
+        FnNormalization::new("lowercase", |word: &str| {
+            if word.chars().any(char::is_uppercase) {
+                Cow::Owned(word.to_lowercase())
+            } else {
+                Cow::Borrowed(word)
+            }
+        })
+    }
+
+    #[test]
+    fn normalize_words_rejects_collisions() {
+        let mut words: Vec<String> = vec!["Berlin", "Potsdam", "Hamburg"]
+            .into_iter()
+            .map(ToOwned::to_owned)
+            .collect();
+        normalize_words(&mut words, &lowercase()).unwrap();
+        assert_eq!(words, vec!["berlin", "potsdam", "hamburg"]);
+
+        let mut words: Vec<String> = vec!["Berlin", "Potsdam", "berlin"]
+            .into_iter()
+            .map(ToOwned::to_owned)
+            .collect();
+        assert!(normalize_words(&mut words, &lowercase()).is_err());
+        assert_eq!(words, vec!["Berlin", "Potsdam", "berlin"]);
+    }
+
+    #[test]
+    fn unicode_normalization_normalizes_forms() {
+        let nfc = UnicodeNormalization::new(NormalizationForm::Nfc);
+        assert_eq!(nfc.normalize("cafe\u{0301}"), "caf\u{00E9}");
+        assert_eq!(nfc.normalize("\u{FB01}le"), "\u{FB01}le");
+        assert!(matches!(nfc.normalize("caf\u{00E9}"), Cow::Borrowed(_)));
+
+        let nfd = UnicodeNormalization::new(NormalizationForm::Nfd);
+        assert_eq!(nfd.normalize("caf\u{00E9}"), "cafe\u{0301}");
+        assert!(matches!(nfd.normalize("cafe\u{0301}"), Cow::Borrowed(_)));
+
+        let nfkc = UnicodeNormalization::new(NormalizationForm::Nfkc);
+        assert_eq!(nfkc.normalize("\u{FB01}le"), "file");
+        assert_eq!(nfkc.normalize("cafe\u{0301}"), "caf\u{00E9}");
+        assert!(matches!(nfkc.normalize("file"), Cow::Borrowed(_)));
+
+        let nfkd = UnicodeNormalization::new(NormalizationForm::Nfkd);
+        assert_eq!(nfkd.normalize("\u{FB01}\u{00E9}"), "fie\u{0301}");
+    }
+
+    #[test]
+    fn digit_normalization_replaces_digits() {
+        let normalization = DigitNormalization::default();
+        assert_eq!(normalization.normalize("1984"), "0000");
+        assert_eq!(normalization.normalize("3.14€"), "0.00€");
+        assert_eq!(normalization.normalize("٣"), "٣");
+        assert!(matches!(normalization.normalize("a00"), Cow::Borrowed(_)));
+        assert!(matches!(normalization.normalize("a01"), Cow::Owned(_)));
+
+        let normalization = DigitNormalization::new('#');
+        assert_eq!(normalization.normalize("10km"), "##km");
+
+        let mut words: Vec<String> = vec!["1984", "10km", "00"]
+            .into_iter()
+            .map(ToOwned::to_owned)
+            .collect();
+        normalize_words(&mut words, &DigitNormalization::default()).unwrap();
+        assert_eq!(words, vec!["0000", "00km", "00"]);
+    }
+
+    #[test]
+    fn digit_normalization_rejects_collisions() {
+        let mut words: Vec<String> = vec!["2001", "1999"]
+            .into_iter()
+            .map(ToOwned::to_owned)
+            .collect();
+        assert!(normalize_words(&mut words, &DigitNormalization::default()).is_err());
+        assert_eq!(words, vec!["2001", "1999"]);
+    }
+
+    #[test]
+    fn case_folding_folds_full() {
+        let folding = CaseFolding::default();
+        assert_eq!(folding.normalize("Straße"), "strasse");
+        assert_eq!(folding.normalize("STRASSE"), "strasse");
+        assert_eq!(folding.normalize("ΟΔΟΣ"), "οδοσ");
+        assert_eq!(folding.normalize("οδος"), "οδοσ");
+        assert_eq!(folding.normalize("\u{FB01}le"), "file");
+        assert_eq!(folding.normalize("ᏣᎳᎩ"), "ᏣᎳᎩ");
+        assert_eq!(folding.normalize("ꮳꮃꭹ"), "ᏣᎳᎩ");
+        assert_eq!(folding.normalize("İstanbul"), "i\u{0307}stanbul");
+        assert_eq!(folding.normalize("Istanbul"), "istanbul");
+    }
+
+    #[test]
+    fn case_folding_borrows_folded() {
+        let folding = CaseFolding::default();
+        assert!(matches!(
+            folding.normalize("berlin"),
+            Cow::Borrowed("berlin")
+        ));
+        assert!(matches!(folding.normalize("σοφία"), Cow::Borrowed(_)));
+        assert!(matches!(folding.normalize("Berlin"), Cow::Owned(_)));
+        assert!(matches!(folding.normalize("straße"), Cow::Owned(_)));
+    }
+
+    #[test]
+    fn case_folding_turkic() {
+        let folding = CaseFolding::turkic();
+        assert_eq!(folding.normalize("İstanbul"), "istanbul");
+        assert_eq!(folding.normalize("IRMAK"), "\u{0131}rmak");
+        assert_eq!(folding.normalize("ırmak"), "ırmak");
+        assert_eq!(folding.normalize("Straße"), "strasse");
+    }
+
+    #[test]
+    fn case_folding_locale_for_language_tag() {
+        assert_eq!(
+            CaseFoldingLocale::for_language_tag("tr"),
+            CaseFoldingLocale::Turkic
+        );
+        assert_eq!(
+            CaseFoldingLocale::for_language_tag("AZ-Latn"),
+            CaseFoldingLocale::Turkic
+        );
+        assert_eq!(
+            CaseFoldingLocale::for_language_tag("de_DE"),
+            CaseFoldingLocale::Default
+        );
+    }
+}
@@ -0,0 +1,905 @@
+//! Traits and trait implementations for similarity queries.
+
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashSet};
+use std::error::Error;
+use std::{fmt, slice};
+
+use ndarray::linalg::general_mat_vec_mul;
+use ndarray::{s, Array1, Array2, ArrayView1, ArrayView2, ArrayViewMut1, Axis, CowArray, Ix1};
+use ordered_float::NotNan;
+
+use crate::chunks::storage::{Storage, StorageView};
+use crate::chunks::vocab::Vocab;
+use crate::embeddings::Embeddings;
+use crate::transform::LookupTransform;
+use crate::util::l2_normalize;
+
+/// A word with its similarity.
+///
+/// This data structure is used to store a pair consisting of a word and
+/// its similarity to a query word.
+#[derive(Debug, Eq, PartialEq)]
+pub struct WordSimilarityResult<'a> {
+    pub similarity: NotNan<f32>,
+    pub word: Cow<'a, str>,
+}
+
+impl<'a> Ord for WordSimilarityResult<'a> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match other.similarity.cmp(&self.similarity) {
+            Ordering::Equal => self.word.cmp(&other.word),
+            ordering => ordering,
+        }
+    }
+}
+
+impl<'a> PartialOrd for WordSimilarityResult<'a> {
+    fn partial_cmp(&self, other: &WordSimilarityResult) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Similarity metric for queries.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Metric {
+    /// Dot product.
+    ///
+    /// Since the embeddings are unit vectors, this is the cosine
+    /// similarity when the query embedding is also a unit vector.
+    Dot,
+
+    /// Cosine similarity.
+    ///
+    /// In contrast to `Dot`, the query embedding is normalized, so
+    /// that the similarity for unnormalized query embeddings is the
+    /// cosine similarity.
+    Cosine,
+
+    /// Negated euclidean distance.
+    Euclidean,
+}
+
+impl Metric {
+    fn similarity(self, embeds: ArrayView2<f32>, embed: ArrayView1<f32>) -> Array1<f32> {
+        let mut sims = Array1::zeros(embeds.nrows());
+        self.similarity_into(embeds, embed, sims.view_mut());
+        sims
+    }
+
+    fn similarity_into(
+        self,
+        embeds: ArrayView2<f32>,
+        embed: ArrayView1<f32>,
+        mut sims: ArrayViewMut1<f32>,
+    ) {
+        match self {
+            Metric::Dot => general_mat_vec_mul(1., &embeds, &embed, 0., &mut sims),
+            Metric::Cosine => {
+                let norm = embed.dot(&embed).sqrt();
+                general_mat_vec_mul(1., &embeds, &embed, 0., &mut sims);
+                if norm != 0. {
+                    sims /= norm;
+                }
+            }
+            Metric::Euclidean => {
+                for (e, sim) in embeds.outer_iter().zip(sims.iter_mut()) {
+                    *sim = -e
+                        .iter()
+                        .zip(embed.iter())
+                        .map(|(&a, &b)| (a - b) * (a - b))
+                        .sum::<f32>()
+                        .sqrt();
+                }
+            }
+        }
+    }
+}
+
+/// Input of a similarity query.
+#[derive(Clone, Debug)]
+pub enum QueryInput<'a> {
+    /// Find words that are similar to a word.
+    Word(&'a str),
+
+    /// Find words that are similar to an embedding.
+    Embedding(ArrayView1<'a, f32>),
+
+    /// Find words for the analogy `word1` is to `word2` as `word3` is
+    /// to `?`. This searches embeddings that are similar to:
+    ///
+    /// *embedding(word2) - embedding(word1) + embedding(word3)*
+    Analogy([&'a str; 3]),
+}
+
+impl<'a> QueryInput<'a> {
+    fn words(&self) -> &[&'a str] {
+        match self {
+            QueryInput::Word(word) => slice::from_ref(word),
+            QueryInput::Embedding(_) => &[],
+            QueryInput::Analogy(words) => words,
+        }
+    }
+}
+
+/// Options for similarity queries.
+#[derive(Clone, Debug)]
+pub struct QueryOptions<'a> {
+    /// The maximum number of results.
+    pub limit: usize,
+
+    /// The similarity metric.
+    pub metric: Metric,
+
+    /// Words that are excluded from the results.
+    pub skip: HashSet<&'a str>,
+
+    /// Query words that are excluded from the results.
+    ///
+    /// If `query_mask[i]` is `true`, the *i*-th query word cannot be
+    /// returned as a result. If `None`, all query words are excluded.
+    pub query_mask: Option<Vec<bool>>,
+}
+
+impl<'a> QueryOptions<'a> {
+    /// Construct query options with a limit on the number of results.
+    ///
+    /// The options use the dot product metric, skip no words, and
+    /// exclude all query words from the results.
+    pub fn new(limit: usize) -> Self {
+        QueryOptions {
+            limit,
+            metric: Metric::Dot,
+            skip: HashSet::new(),
+            query_mask: None,
+        }
+    }
+
+    /// Set the similarity metric.
+    pub fn metric(mut self, metric: Metric) -> Self {
+        self.metric = metric;
+        self
+    }
+
+    /// Set the words that are excluded from the results.
+    pub fn skip(mut self, skip: HashSet<&'a str>) -> Self {
+        self.skip = skip;
+        self
+    }
+
+    /// Set the query words that are excluded from the results.
+    pub fn query_mask(mut self, query_mask: impl Into<Vec<bool>>) -> Self {
+        self.query_mask = Some(query_mask.into());
+        self
+    }
+}
+
+/// Error of a similarity query.
+///
+/// This error is returned when no embedding could be computed for
+/// one or more of the query words.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct QueryError {
+    /// Indicates which of the query words were present.
+    pub present: Vec<bool>,
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Cannot compute embeddings for query words: {:?}",
+            self.present
+        )
+    }
+}
+
+impl Error for QueryError {}
+
+/// Reusable buffers for similarity queries.
+///
+/// A similarity query computes the similarity of the query to every
+/// word in the vocabulary. `Query::query_with` stores these
+/// similarities and the best candidates in a workspace, so that
+/// repeated queries do not need to allocate these buffers.
+pub struct QueryWorkspace<'a> {
+    sims: Array1<f32>,
+    results: BinaryHeap<WordSimilarityResult<'a>>,
+}
+
+impl<'a> QueryWorkspace<'a> {
+    /// Construct a workspace for queries on the given embeddings.
+    pub fn new<V, S>(embeddings: &'a Embeddings<V, S>) -> Self
+    where
+        V: Vocab,
+    {
+        QueryWorkspace {
+            sims: Array1::zeros(embeddings.vocab().words_len()),
+            results: BinaryHeap::new(),
+        }
+    }
+}
+
+/// Trait for similarity queries.
+///
+/// This trait covers all similarity queries: word similarity,
+/// embedding similarity, and analogy queries. The other query traits
+/// in this module are implemented in terms of this trait.
+pub trait Query {
+    /// Perform a similarity query.
+    ///
+    /// Returns at most `options.limit` words that are similar to
+    /// the query, using the metric in `options`.
+    fn query(
+        &self,
+        query: QueryInput<'_>,
+        options: &QueryOptions<'_>,
+    ) -> Result<Vec<WordSimilarityResult<'_>>, QueryError> {
+        let metric = options.metric;
+        self.query_by(query, options, |embeds, embed| {
+            metric.similarity(embeds, embed)
+        })
+    }
+
+    /// Perform a similarity query using the given similarity function.
+    ///
+    /// The similarity function should return, given the embeddings
+    /// matrix and the query vector a vector of similarity scores. The
+    /// metric in `options` is ignored. If the embeddings have a lookup
+    /// transform, the function is called for blocks of transformed
+    /// embeddings.
+    fn query_by<F>(
+        &self,
+        query: QueryInput<'_>,
+        options: &QueryOptions<'_>,
+        similarity: F,
+    ) -> Result<Vec<WordSimilarityResult<'_>>, QueryError>
+    where
+        F: FnMut(ArrayView2<f32>, ArrayView1<f32>) -> Array1<f32>;
+
+    /// Perform a similarity query using the given workspace.
+    ///
+    /// This method is equivalent to `query`, but stores intermediate
+    /// results in `workspace`, avoiding allocations proportional to
+    /// the vocabulary size.
+    fn query_with<'a>(
+        &'a self,
+        query: QueryInput<'_>,
+        options: &QueryOptions<'_>,
+        workspace: &mut QueryWorkspace<'a>,
+    ) -> Result<Vec<WordSimilarityResult<'a>>, QueryError>;
+}
+
+impl<V, S> Query for Embeddings<V, S>
+where
+    V: Vocab,
+    S: StorageView,
+{
+    fn query_by<F>(
+        &self,
+        query: QueryInput<'_>,
+        options: &QueryOptions<'_>,
+        similarity: F,
+    ) -> Result<Vec<WordSimilarityResult<'_>>, QueryError>
+    where
+        F: FnMut(ArrayView2<f32>, ArrayView1<f32>) -> Array1<f32>,
+    {
+        let (embedding, skip) = self.query_embedding_(&query, options)?;
+        Ok(self.similarity_(embedding.view(), &skip, options.limit, similarity))
+    }
+
+    fn query_with<'a>(
+        &'a self,
+        query: QueryInput<'_>,
+        options: &QueryOptions<'_>,
+        workspace: &mut QueryWorkspace<'a>,
+    ) -> Result<Vec<WordSimilarityResult<'a>>, QueryError> {
+        let (embedding, skip) = self.query_embedding_(&query, options)?;
+
+        let words_len = self.vocab().words_len();
+        if workspace.sims.len() != words_len {
+            workspace.sims = Array1::zeros(words_len);
+        }
+
+        let embeds = self.storage().view().slice_move(s![0..words_len, ..]);
+        match self.transform() {
+            Some(transform) => {
+                for (block, sims) in transformed_blocks(embeds, transform).zip(
+                    workspace
+                        .sims
+                        .axis_chunks_iter_mut(Axis(0), TRANSFORM_BLOCK_SIZE),
+                ) {
+                    options
+                        .metric
+                        .similarity_into(block.view(), embedding.view(), sims);
+                }
+            }
+            None => {
+                options
+                    .metric
+                    .similarity_into(embeds, embedding.view(), workspace.sims.view_mut())
+            }
+        }
+
+        Ok(top_k(
+            self.vocab(),
+            workspace.sims.view(),
+            &skip,
+            options.limit,
+            &mut workspace.results,
+        ))
+    }
+}
+
+impl<V, S> Embeddings<V, S>
+where
+    V: Vocab,
+    S: StorageView,
+{
+    /// Get the query embedding and the words to skip for a query.
+    fn query_embedding_<'a>(
+        &self,
+        query: &'a QueryInput<'_>,
+        options: &'a QueryOptions<'_>,
+    ) -> Result<(Array1<f32>, HashSet<&'a str>), QueryError> {
+        let embedding = match *query {
+            QueryInput::Word(word) => self
+                .embedding(word)
+                .ok_or_else(|| QueryError {
+                    present: vec![false],
+                })?
+                .into_owned(),
+            QueryInput::Embedding(ref embedding) => embedding.to_owned(),
+            QueryInput::Analogy(words) => {
+                let [embedding1, embedding2, embedding3] =
+                    lookup_words3(self, words).map_err(|present| QueryError {
+                        present: present.to_vec(),
+                    })?;
+
+                let mut embedding = (&embedding2.view() - &embedding1.view()) + embedding3.view();
+                l2_normalize(embedding.view_mut());
+                embedding
+            }
+        };
+
+        let mut skip = options.skip.clone();
+        for (idx, &word) in query.words().iter().enumerate() {
+            let exclude = options
+                .query_mask
+                .as_ref()
+                .map(|mask| mask.get(idx).cloned().unwrap_or(true))
+                .unwrap_or(true);
+            if exclude {
+                skip.insert(word);
+            }
+        }
+
+        Ok((embedding, skip))
+    }
+}
+
+/// Trait for analogy queries.
+pub trait Analogy {
+    /// Perform an analogy query.
+    ///
+    /// This method returns words that are close in vector space the analogy
+    /// query `word1` is to `word2` as `word3` is to `?`. More concretely,
+    /// it searches embeddings that are similar to:
+    ///
+    /// *embedding(word2) - embedding(word1) + embedding(word3)*
+    ///
+    /// At most, `limit` results are returned. `Result::Err` is returned
+    /// when no embedding could be computed for one or more of the tokens,
+    /// indicating which of the tokens were present.
+    fn analogy(
+        &self,
+        query: [&str; 3],
+        limit: usize,
+    ) -> Result<Vec<WordSimilarityResult<'_>>, [bool; 3]> {
+        self.analogy_masked(query, [true, true, true], limit)
+    }
+
+    /// Perform an analogy query.
+    ///
+    /// This method returns words that are close in vector space for the
+    /// analogy query `word1` is to `word2` as `word3` is to `?`. More
+    /// concretely, it searches embeddings that are similar to:
+    ///
+    /// *embedding(word2) - embedding(word1) + embedding(word3)*
+    ///
+    /// At most, `limit` results are returned.
+    ///
+    /// `remove` specifies which parts of the queries are excluded from the
+    /// output candidates. If `remove[0]` is `true`, `word1` cannot be
+    /// returned as an answer to the query.
+    ///
+    ///`Result::Err` is returned when no embedding could be computed
+    /// for one or more of the tokens, indicating which of the tokens
+    /// were present.
+    fn analogy_masked(
+        &self,
+        query: [&str; 3],
+        remove: [bool; 3],
+        limit: usize,
+    ) -> Result<Vec<WordSimilarityResult<'_>>, [bool; 3]>;
+}
+
+impl<V, S> Analogy for Embeddings<V, S>
+where
+    V: Vocab,
+    S: StorageView,
+{
+    fn analogy_masked(
+        &self,
+        query: [&str; 3],
+        remove: [bool; 3],
+        limit: usize,
+    ) -> Result<Vec<WordSimilarityResult<'_>>, [bool; 3]> {
+        self.analogy_by_masked(query, remove, limit, |embeds, embed| embeds.dot(&embed))
+    }
+}
+/// Trait for analogy queries with a custom similarity function.
+pub trait AnalogyBy {
+    /// Perform an analogy query using the given similarity function.
+    ///
+    /// This method returns words that are close in vector space the analogy
+    /// query `word1` is to `word2` as `word3` is to `?`. More concretely,
+    /// it searches embeddings that are similar to:
+    ///
+    /// *embedding(word2) - embedding(word1) + embedding(word3)*
+    ///
+    /// At most, `limit` results are returned.
+    ///
+    ///`Result::Err` is returned when no embedding could be computed
+    /// for one or more of the tokens, indicating which of the tokens
+    /// were present.
+    fn analogy_by<F>(
+        &self,
+        query: [&str; 3],
+        limit: usize,
+        similarity: F,
+    ) -> Result<Vec<WordSimilarityResult<'_>>, [bool; 3]>
+    where
+        F: FnMut(ArrayView2<f32>, ArrayView1<f32>) -> Array1<f32>,
+    {
+        self.analogy_by_masked(query, [true, true, true], limit, similarity)
+    }
+
+    /// Perform an analogy query using the given similarity function.
+    ///
+    /// This method returns words that are close in vector space the analogy
+    /// query `word1` is to `word2` as `word3` is to `?`. More concretely,
+    /// it searches embeddings that are similar to:
+    ///
+    /// *embedding(word2) - embedding(word1) + embedding(word3)*
+    ///
+    /// At most, `limit` results are returned.
+    ///
+    /// `remove` specifies which parts of the queries are excluded from the
+    /// output candidates. If `remove[0]` is `true`, `word1` cannot be
+    /// returned as an answer to the query.
+    ///
+    ///`Result::Err` is returned when no embedding could be computed
+    /// for one or more of the tokens, indicating which of the tokens
+    /// were present.
+    fn analogy_by_masked<F>(
+        &self,
+        query: [&str; 3],
+        remove: [bool; 3],
+        limit: usize,
+        similarity: F,
+    ) -> Result<Vec<WordSimilarityResult<'_>>, [bool; 3]>
+    where
+        F: FnMut(ArrayView2<f32>, ArrayView1<f32>) -> Array1<f32>;
+}
+
+impl<V, S> AnalogyBy for Embeddings<V, S>
+where
+    V: Vocab,
+    S: StorageView,
+{
+    fn analogy_by_masked<F>(
+        &self,
+        query: [&str; 3],
+        remove: [bool; 3],
+        limit: usize,
+        similarity: F,
+    ) -> Result<Vec<WordSimilarityResult<'_>>, [bool; 3]>
+    where
+        F: FnMut(ArrayView2<f32>, ArrayView1<f32>) -> Array1<f32>,
+    {
+        let options = QueryOptions::new(limit).query_mask(remove);
+        self.query_by(QueryInput::Analogy(query), &options, similarity)
+            .map_err(|err| [err.present[0], err.present[1], err.present[2]])
+    }
+}
+
+/// Trait for word similarity queries.
+pub trait WordSimilarity {
+    /// Find words that are similar to the query word.
+    ///
+    /// The similarity between two words is defined by the dot product of
+    /// the embeddings. If the vectors are unit vectors (e.g. by virtue of
+    /// calling `normalize`), this is the cosine similarity. At most, `limit`
+    /// results are returned.
+    fn word_similarity(&self, word: &str, limit: usize) -> Option<Vec<WordSimilarityResult<'_>>>;
+}
+
+/// Trait for word similarity queries with a custom similarity function.
+pub trait WordSimilarityBy {
+    /// Find words that are similar to the query word using the given similarity
+    /// function.
+    ///
+    /// The similarity function should return, given the embeddings matrix and
+    /// the word vector a vector of similarity scores. At most, `limit` results
+    /// are returned.
+    fn word_similarity_by<F>(
+        &self,
+        word: &str,
+        limit: usize,
+        similarity: F,
+    ) -> Option<Vec<WordSimilarityResult<'_>>>
+    where
+        F: FnMut(ArrayView2<f32>, ArrayView1<f32>) -> Array1<f32>;
+}
+
+impl<V, S> WordSimilarity for Embeddings<V, S>
+where
+    V: Vocab,
+    S: StorageView,
+{
+    fn word_similarity(&self, word: &str, limit: usize) -> Option<Vec<WordSimilarityResult<'_>>> {
+        self.word_similarity_by(word, limit, |embeds, embed| embeds.dot(&embed))
+    }
+}
+
+impl<V, S> WordSimilarityBy for Embeddings<V, S>
+where
+    V: Vocab,
+    S: StorageView,
+{
+    fn word_similarity_by<F>(
+        &self,
+        word: &str,
+        limit: usize,
+        similarity: F,
+    ) -> Option<Vec<WordSimilarityResult<'_>>>
+    where
+        F: FnMut(ArrayView2<f32>, ArrayView1<f32>) -> Array1<f32>,
+    {
+        self.query_by(
+            QueryInput::Word(word),
+            &QueryOptions::new(limit),
+            similarity,
+        )
+        .ok()
+    }
+}
+
+/// Trait for embedding similarity queries.
+pub trait EmbeddingSimilarity {
+    /// Find words that are similar to the query embedding.
+    ///
+    /// The similarity between the query embedding and other embeddings is
+    /// defined by the dot product of the embeddings. If the vectors are unit
+    /// vectors (e.g. by virtue of calling `normalize`), this is the cosine
+    /// similarity. At most, `limit` results are returned.
+    fn embedding_similarity(
+        &self,
+        query: ArrayView1<f32>,
+        limit: usize,
+    ) -> Option<Vec<WordSimilarityResult<'_>>> {
+        self.embedding_similarity_masked(query, limit, &HashSet::new())
+    }
+
+    /// Find words that are similar to the query embedding while skipping
+    /// certain words.
+    ///
+    /// The similarity between the query embedding and other embeddings is
+    /// defined by the dot product of the embeddings. If the vectors are unit
+    /// vectors (e.g. by virtue of calling `normalize`), this is the cosine
+    /// similarity. At most, `limit` results are returned.
+    fn embedding_similarity_masked(
+        &self,
+        query: ArrayView1<f32>,
+        limit: usize,
+        skips: &HashSet<&str>,
+    ) -> Option<Vec<WordSimilarityResult<'_>>>;
+}
+/// Trait for embedding similarity queries with a custom similarity function.
+pub trait EmbeddingSimilarityBy {
+    /// Find words that are similar to the query embedding using the given
+    /// similarity function.
+    ///
+    /// The similarity function should return, given the embeddings matrix and
+    /// the query vector a vector of similarity scores. At most, `limit` results
+    /// are returned.
+    fn embedding_similarity_by<F>(
+        &self,
+        query: ArrayView1<f32>,
+        limit: usize,
+        skip: &HashSet<&str>,
+        similarity: F,
+    ) -> Option<Vec<WordSimilarityResult<'_>>>
+    where
+        F: FnMut(ArrayView2<f32>, ArrayView1<f32>) -> Array1<f32>;
+}
+
+impl<V, S> EmbeddingSimilarity for Embeddings<V, S>
+where
+    V: Vocab,
+    S: StorageView,
+{
+    fn embedding_similarity_masked(
+        &self,
+        query: ArrayView1<f32>,
+        limit: usize,
+        skip: &HashSet<&str>,
+    ) -> Option<Vec<WordSimilarityResult<'_>>> {
+        self.embedding_similarity_by(query, limit, skip, |embeds, embed| embeds.dot(&embed))
+    }
+}
+
+impl<V, S> EmbeddingSimilarityBy for Embeddings<V, S>
+where
+    V: Vocab,
+    S: StorageView,
+{
+    fn embedding_similarity_by<F>(
+        &self,
+        query: ArrayView1<f32>,
+        limit: usize,
+        skip: &HashSet<&str>,
+        similarity: F,
+    ) -> Option<Vec<WordSimilarityResult<'_>>>
+    where
+        F: FnMut(ArrayView2<f32>, ArrayView1<f32>) -> Array1<f32>,
+    {
+        let options = QueryOptions::new(limit).skip(skip.clone());
+        self.query_by(QueryInput::Embedding(query), &options, similarity)
+            .ok()
+    }
+}
+
+trait SimilarityPrivate {
+    fn similarity_<F>(
+        &self,
+        embed: ArrayView1<f32>,
+        skip: &HashSet<&str>,
+        limit: usize,
+        similarity: F,
+    ) -> Vec<WordSimilarityResult<'_>>
+    where
+        F: FnMut(ArrayView2<f32>, ArrayView1<f32>) -> Array1<f32>;
+}
+
+impl<V, S> SimilarityPrivate for Embeddings<V, S>
+where
+    V: Vocab,
+    S: StorageView,
+{
+    fn similarity_<F>(
+        &self,
+        embed: ArrayView1<f32>,
+        skip: &HashSet<&str>,
+        limit: usize,
+        mut similarity: F,
+    ) -> Vec<WordSimilarityResult<'_>>
+    where
+        F: FnMut(ArrayView2<f32>, ArrayView1<f32>) -> Array1<f32>,
+    {
+        // ndarray#474
+        #[allow(clippy::deref_addrof)]
+        let embeds = self
+            .storage()
+            .view()
+            .slice_move(s![0..self.vocab().words_len(), ..]);
+        let sims = match self.transform() {
+            Some(transform) => {
+                let mut sims = Array1::zeros(embeds.nrows());
+                for (block, mut block_sims) in transformed_blocks(embeds, transform)
+                    .zip(sims.axis_chunks_iter_mut(Axis(0), TRANSFORM_BLOCK_SIZE))
+                {
+                    block_sims.assign(&similarity(block.view(), embed.view()));
+                }
+                sims
+            }
+            None => similarity(embeds, embed.view()),
+        };
+
+        top_k(
+            self.vocab(),
+            sims.view(),
+            skip,
+            limit,
+            &mut BinaryHeap::with_capacity(limit),
+        )
+    }
+}
+
+/// Number of embeddings that are transformed at once in similarity queries.
+const TRANSFORM_BLOCK_SIZE: usize = 4096;
+
+/// Transform an embedding matrix in blocks of `TRANSFORM_BLOCK_SIZE` rows.
+///
+/// This avoids materializing the complete transformed matrix.
+fn transformed_blocks<'a>(
+    embeds: ArrayView2<'a, f32>,
+    transform: &'a dyn LookupTransform,
+) -> impl Iterator<Item = Array2<f32>> + 'a {
+    (0..embeds.nrows())
+        .step_by(TRANSFORM_BLOCK_SIZE)
+        .map(move |start| {
+            let end = (start + TRANSFORM_BLOCK_SIZE).min(embeds.nrows());
+            transform.transform(embeds.slice(s![start..end, ..]))
+        })
+}
+
+/// Get the `limit` most similar words.
+///
+/// `sims` contains the similarities of the words of `vocab` in index
+/// order. Words are only retrieved from the vocabulary for candidate
+/// results. `results` is used as scratch space for the selection.
+fn top_k<'a>(
+    vocab: &'a dyn Vocab,
+    sims: ArrayView1<f32>,
+    skip: &HashSet<&str>,
+    limit: usize,
+    results: &mut BinaryHeap<WordSimilarityResult<'a>>,
+) -> Vec<WordSimilarityResult<'a>> {
+    results.clear();
+
+    let skip = skip
+        .iter()
+        .filter_map(|word| vocab.idx(word)?.word())
+        .collect::<HashSet<_>>();
+
+    for (idx, &sim) in sims.iter().enumerate() {
+        // Don't add words that we are explicitly asked to skip.
+        if skip.contains(&idx) {
+            continue;
+        }
+
+        let similarity = NotNan::new(sim).expect("Encountered NaN");
+
+        // Words that are less similar than the current results are not
+        // candidates.
+        if results.len() == limit {
+            match results.peek() {
+                Some(worst) if similarity >= worst.similarity => (),
+                _ => continue,
+            }
+        }
+
+        let word_similarity = WordSimilarityResult {
+            word: vocab.word(idx).expect("Invalid word index"),
+            similarity,
+        };
+
+        if results.len() < limit {
+            results.push(word_similarity);
+        } else {
+            let mut peek = results.peek_mut().expect("Cannot peek non-empty heap");
+            if word_similarity < *peek {
+                *peek = word_similarity
+            }
+        }
+    }
+
+    let mut sorted = results.drain().collect::<Vec<_>>();
+    sorted.sort();
+    sorted
+}
+
+/// Contribution of an embedding dimension to a similarity.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DimensionContribution {
+    /// The dimension.
+    pub dim: usize,
+
+    /// The product of the components of the embeddings in this dimension.
+    pub contribution: f32,
+}
+
+/// Explanation of the similarity of two words.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SimilarityExplanation {
+    /// The cosine similarity of the words.
+    pub similarity: f32,
+
+    /// The dimensions that contribute most to the similarity, sorted
+    /// by decreasing contribution.
+    pub contributions: Vec<DimensionContribution>,
+}
+
+/// Trait for explaining word similarities.
+pub trait ExplainSimilarity {
+    /// Explain the similarity of two words.
+    ///
+    /// The cosine similarity of two words is the sum of the elementwise
+    /// products of their normalized embeddings. This method returns the
+    /// similarity, together with the `limit` dimensions that have the
+    /// largest products. Dimensions with negative products decrease the
+    /// similarity, so they are only returned when fewer than `limit`
+    /// dimensions have a positive product.
+    ///
+    /// `Result::Err` is returned when a word is not known, indicating
+    /// which of the two words were present.
+    fn explain_similarity(
+        &self,
+        word1: &str,
+        word2: &str,
+        limit: usize,
+    ) -> Result<SimilarityExplanation, [bool; 2]>;
+}
+
+impl<V, S> ExplainSimilarity for Embeddings<V, S>
+where
+    V: Vocab,
+    S: Storage,
+{
+    fn explain_similarity(
+        &self,
+        word1: &str,
+        word2: &str,
+        limit: usize,
+    ) -> Result<SimilarityExplanation, [bool; 2]> {
+        let (embedding1, embedding2) = match (self.embedding(word1), self.embedding(word2)) {
+            (Some(embedding1), Some(embedding2)) => (embedding1, embedding2),
+            (embedding1, embedding2) => return Err([embedding1.is_some(), embedding2.is_some()]),
+        };
+
+        let mut embedding1 = embedding1.into_owned();
+        l2_normalize(embedding1.view_mut());
+        let mut embedding2 = embedding2.into_owned();
+        l2_normalize(embedding2.view_mut());
+
+        let products = embedding1 * embedding2;
+        let mut contributions = products
+            .iter()
+            .enumerate()
+            .map(|(dim, &contribution)| DimensionContribution { dim, contribution })
+            .collect::<Vec<_>>();
+        contributions.sort_by(|c1, c2| {
+            c2.contribution
+                .partial_cmp(&c1.contribution)
+                .unwrap_or(Ordering::Equal)
+        });
+        contributions.truncate(limit);
+
+        Ok(SimilarityExplanation {
+            similarity: products.sum(),
+            contributions,
+        })
+    }
+}
+
+fn lookup_words3<'a, V, S>(
+    embeddings: &'a Embeddings<V, S>,
+    query: [&str; 3],
+) -> Result<[CowArray<'a, f32, Ix1>; 3], [bool; 3]>
+where
+    V: Vocab,
+    S: Storage,
+{
+    let embedding1 = embeddings.embedding(query[0]);
+    let embedding2 = embeddings.embedding(query[1]);
+    let embedding3 = embeddings.embedding(query[2]);
+
+    let present = [
+        embedding1.is_some(),
+        embedding2.is_some(),
+        embedding3.is_some(),
+    ];
+
+    if !present.iter().all(|&present| present) {
+        return Err(present);
+    }
+
+    Ok([
+        embedding1.unwrap(),
+        embedding2.unwrap(),
+        embedding3.unwrap(),
+    ])
+}
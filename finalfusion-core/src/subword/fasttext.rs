@@ -1,5 +1,4 @@
-use std::i32;
-
+use crate::chunks::memory::{MemoryFootprint, MemoryUsage};
 use crate::subword::{BucketIndexer, Indexer, StrWithCharLen};
 
 /// fastText-compatible subword indexer.
@@ -40,7 +39,15 @@ impl BucketIndexer for FastTextIndexer {
     }
 }
 
+impl MemoryUsage for FastTextIndexer {
+    fn memory_usage(&self) -> MemoryFootprint {
+        MemoryFootprint::default()
+    }
+}
+
 impl Indexer for FastTextIndexer {
+    const BRACKET_UNIGRAMS: bool = false;
+
     fn index_ngram(&self, ngram: &StrWithCharLen) -> Option<u64> {
         Some(u64::from(fasttext_hash(ngram.as_str()) % self.buckets))
     }
@@ -59,7 +66,7 @@ impl Indexer for FastTextIndexer {
 ///
 /// This implementation 'emulates' the bug for compatibility
 /// with pretrained fastText embeddings.
-fn fasttext_hash(ngram: &str) -> u32 {
+pub fn fasttext_hash(ngram: &str) -> u32 {
     let mut h = 2_166_136_261;
 
     for byte in ngram.bytes() {
@@ -1,16 +1,23 @@
 //! Utilities for subword units.
 
-use std::cmp;
 use std::collections::{HashMap, VecDeque};
-use std::fmt;
 use std::hash::{Hash, Hasher};
 use std::marker::PhantomData;
 use std::ops::Deref;
+use std::{cmp, fmt};
 
 use fnv::FnvHasher;
 
+use crate::chunks::memory::{
+    string_map_heap_size, strings_heap_size, MemoryFootprint, MemoryUsage,
+};
 use crate::util::CollectWithCapacity;
 
+mod fasttext;
+#[doc(hidden)]
+pub use self::fasttext::fasttext_hash;
+pub use self::fasttext::FastTextIndexer;
+
 /// N-Gram indexer
 ///
 /// An indexer maps an n-gram to an index in the subword embedding
@@ -21,6 +28,20 @@ pub trait Indexer {
 
     /// Return the (exclusive) upper bound of this indexer.
     fn upper_bound(&self) -> u64;
+
+    /// Map an index to an n-gram.
+    ///
+    /// Returns `None` when the indexer does not store n-grams, as is
+    /// the case for hashing indexers.
+    fn ngram(&self, _idx: u64) -> Option<&str> {
+        None
+    }
+
+    /// Whether the begin- and end-of-word markers are n-grams.
+    ///
+    /// When this is `false`, the markers are not used as n-grams of
+    /// length 1, as in fastText.
+    const BRACKET_UNIGRAMS: bool = true;
 }
 
 /// N-Gram indexer with bucketing.
@@ -77,17 +98,13 @@ where
     }
 
     fn buckets(&self) -> usize {
-        self.buckets_exp as usize
+        self.buckets_exp
     }
 }
 
 impl<H> Clone for HashIndexer<H> {
     fn clone(&self) -> Self {
-        HashIndexer {
-            buckets_exp: self.buckets_exp,
-            mask: self.mask,
-            _phantom: PhantomData,
-        }
+        *self
     }
 }
 
@@ -118,6 +135,12 @@ where
     }
 }
 
+impl<H> MemoryUsage for HashIndexer<H> {
+    fn memory_usage(&self) -> MemoryFootprint {
+        MemoryFootprint::default()
+    }
+}
+
 impl<H> PartialEq for HashIndexer<H> {
     fn eq(&self, other: &Self) -> bool {
         self.mask.eq(&other.mask)
@@ -201,6 +224,14 @@ impl ExplicitIndexer {
     }
 }
 
+impl MemoryUsage for ExplicitIndexer {
+    fn memory_usage(&self) -> MemoryFootprint {
+        MemoryFootprint::resident(
+            strings_heap_size(&self.ngrams) + string_map_heap_size(&self.index),
+        )
+    }
+}
+
 impl Indexer for ExplicitIndexer {
     fn index_ngram(&self, ngram: &StrWithCharLen) -> Option<u64> {
         self.index.get(ngram.inner).cloned()
@@ -209,6 +240,26 @@ impl Indexer for ExplicitIndexer {
     fn upper_bound(&self) -> u64 {
         self.bound as u64
     }
+
+    /// Map an index to an n-gram.
+    ///
+    /// If multiple n-grams map to the index, the first of these
+    /// n-grams is returned.
+    fn ngram(&self, idx: u64) -> Option<&str> {
+        if (idx as usize) >= self.bound {
+            return None;
+        }
+
+        // With a one-to-one mapping, n-grams are stored in index order.
+        if self.bound == self.ngrams.len() {
+            return Some(self.ngrams[idx as usize].as_str());
+        }
+
+        self.ngrams
+            .iter()
+            .find(|ngram| self.index[ngram.as_str()] == idx)
+            .map(String::as_str)
+    }
 }
 
 /// A string reference with its length in characters.
@@ -620,8 +671,7 @@ mod tests {
                 .subword_indices_with_ngrams(3, 6, &indexer)
                 .collect::<Vec<_>>();
             ngrams_indices_test.sort_by_key(|ngrams_indices_pairs| ngrams_indices_pairs.1);
-            for (iter_check, iter_test) in ngrams_indices_check.into_iter().zip(ngrams_indices_test)
-            {
+            for (iter_check, iter_test) in ngrams_indices_check.iter().zip(ngrams_indices_test) {
                 assert_eq!(iter_check.0, iter_test.0);
             }
         }
@@ -0,0 +1,235 @@
+//! Transformations that are applied when embeddings are looked up.
+//!
+//! A `LookupTransform` can be installed on `Embeddings` with
+//! `Embeddings::set_transform`. The transform is then applied to all
+//! embeddings that are retrieved from the `Embeddings`, including
+//! lookups, iteration, and similarity queries. Since embeddings are
+//! transformed on demand, it is not necessary to store a transformed
+//! copy of the embedding matrix. Transforms are not stored when the
+//! embeddings are written in the finalfusion format.
+//!
+//! ```
+//! use std::sync::Arc;
+//!
+//! use finalfusion_core::embeddings::Embeddings;
+//! use finalfusion_core::norms::NdNorms;
+//! use finalfusion_core::storage::NdArray;
+//! use finalfusion_core::transform::{Clipping, L2Normalization, TransformPipeline};
+//! use finalfusion_core::vocab::SimpleVocab;
+//! use ndarray::array;
+//!
+//! let mut embeds = Embeddings::new(
+//!     None,
+//!     SimpleVocab::new(vec!["Berlin".to_owned(), "Potsdam".to_owned()]),
+//!     NdArray::new(array![[0.6, 0.8], [0.8, -0.6]]),
+//!     NdNorms::new(array![5., 10.]),
+//! );
+//!
+//! let pipeline = TransformPipeline::new()
+//!     .push(Clipping::new(-0.1, 0.1))
+//!     .push(L2Normalization);
+//! embeds.set_transform(Some(Arc::new(pipeline)));
+//!
+//! let embedding = embeds.embedding("Berlin").unwrap();
+//! assert!((embedding.dot(&embedding) - 1.).abs() < 1e-5);
+//! ```
+
+use std::fmt;
+
+use ndarray::{Array1, Array2, ArrayView1, ArrayView2, Axis};
+
+use crate::util::l2_normalize;
+
+/// Transformation of embeddings on lookup.
+pub trait LookupTransform: fmt::Debug + Send + Sync {
+    /// Get the dimensionality of transformed embeddings.
+    ///
+    /// `dims` is the dimensionality of the embeddings before the
+    /// transformation.
+    fn dims(&self, dims: usize) -> usize {
+        dims
+    }
+
+    /// Transform a batch of embeddings.
+    ///
+    /// Every row of `embeddings` is an embedding. The transformed
+    /// matrix must have the same number of rows and
+    /// `self.dims(embeddings.ncols())` columns.
+    fn transform(&self, embeddings: ArrayView2<f32>) -> Array2<f32>;
+
+    /// Transform a single embedding.
+    fn transform_embedding(&self, embedding: ArrayView1<f32>) -> Array1<f32> {
+        self.transform(embedding.insert_axis(Axis(0)))
+            .index_axis_move(Axis(0), 0)
+    }
+}
+
+/// Linear projection of embeddings.
+///
+/// Embeddings are multiplied by a *d x k* projection matrix, where
+/// *d* is the dimensionality of the embeddings and *k* the
+/// dimensionality of the projected embeddings.
+#[derive(Clone, Debug)]
+pub struct Projection {
+    matrix: Array2<f32>,
+}
+
+impl Projection {
+    /// Construct a projection from a projection matrix.
+    pub fn new(matrix: Array2<f32>) -> Self {
+        Projection { matrix }
+    }
+}
+
+impl LookupTransform for Projection {
+    fn dims(&self, _dims: usize) -> usize {
+        self.matrix.ncols()
+    }
+
+    fn transform(&self, embeddings: ArrayView2<f32>) -> Array2<f32> {
+        embeddings.dot(&self.matrix)
+    }
+}
+
+/// Centering of embeddings.
+///
+/// The given mean is subtracted from every embedding.
+#[derive(Clone, Debug)]
+pub struct Centering {
+    mean: Array1<f32>,
+}
+
+impl Centering {
+    /// Construct a centering transform from a mean embedding.
+    pub fn new(mean: Array1<f32>) -> Self {
+        Centering { mean }
+    }
+}
+
+impl LookupTransform for Centering {
+    fn transform(&self, embeddings: ArrayView2<f32>) -> Array2<f32> {
+        &embeddings - &self.mean
+    }
+}
+
+/// Clipping of embedding components to an interval.
+#[derive(Clone, Copy, Debug)]
+pub struct Clipping {
+    min: f32,
+    max: f32,
+}
+
+impl Clipping {
+    /// Construct a transform that clips components to *[min, max]*.
+    ///
+    /// Panics when `min` is larger than `max`.
+    pub fn new(min: f32, max: f32) -> Self {
+        assert!(
+            min <= max,
+            "Minimum ({}) is larger than maximum ({})",
+            min,
+            max
+        );
+        Clipping { min, max }
+    }
+}
+
+impl LookupTransform for Clipping {
+    fn transform(&self, embeddings: ArrayView2<f32>) -> Array2<f32> {
+        embeddings.mapv(|v| v.max(self.min).min(self.max))
+    }
+}
+
+/// l2 normalization of embeddings.
+///
+/// This transform is typically used after transforms that do not
+/// preserve vector lengths, so that the dot product of transformed
+/// embeddings is their cosine similarity.
+#[derive(Clone, Copy, Debug)]
+pub struct L2Normalization;
+
+impl LookupTransform for L2Normalization {
+    fn transform(&self, embeddings: ArrayView2<f32>) -> Array2<f32> {
+        let mut normalized = embeddings.to_owned();
+        for embedding in normalized.outer_iter_mut() {
+            l2_normalize(embedding);
+        }
+        normalized
+    }
+}
+
+/// Sequence of transforms.
+///
+/// The transforms are applied in the order in which they were added.
+#[derive(Debug, Default)]
+pub struct TransformPipeline {
+    transforms: Vec<Box<dyn LookupTransform>>,
+}
+
+impl TransformPipeline {
+    /// Construct an empty pipeline.
+    pub fn new() -> Self {
+        TransformPipeline::default()
+    }
+
+    /// Add a transform to the end of the pipeline.
+    pub fn push<T>(mut self, transform: T) -> Self
+    where
+        T: LookupTransform + 'static,
+    {
+        self.transforms.push(Box::new(transform));
+        self
+    }
+}
+
+impl LookupTransform for TransformPipeline {
+    fn dims(&self, dims: usize) -> usize {
+        self.transforms
+            .iter()
+            .fold(dims, |dims, transform| transform.dims(dims))
+    }
+
+    fn transform(&self, embeddings: ArrayView2<f32>) -> Array2<f32> {
+        let mut transformed = embeddings.to_owned();
+        for transform in &self.transforms {
+            transformed = transform.transform(transformed.view());
+        }
+        transformed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{array, Array2};
+
+    use super::{
+        Centering, Clipping, L2Normalization, LookupTransform, Projection, TransformPipeline,
+    };
+
+    #[test]
+    fn pipeline_applies_transforms_in_order() {
+        let pipeline = TransformPipeline::new()
+            .push(Centering::new(array![1., 1., 1.]))
+            .push(Projection::new(array![[1., 0.], [0., 1.], [0., 0.]]))
+            .push(Clipping::new(-2., 2.))
+            .push(L2Normalization);
+        assert_eq!(pipeline.dims(3), 2);
+
+        let transformed = pipeline.transform(array![[4., 1., 7.], [-5., 1., 0.]].view());
+        assert_eq!(transformed, array![[1., 0.], [-1., 0.]]);
+
+        assert_eq!(
+            pipeline.transform_embedding(array![1., 4., 0.].view()),
+            array![0., 1.]
+        );
+    }
+
+    #[test]
+    fn empty_pipeline_is_identity() {
+        let embeddings = Array2::from_shape_fn((3, 4), |(r, c)| (r * 4 + c) as f32);
+        assert_eq!(
+            TransformPipeline::new().transform(embeddings.view()),
+            embeddings
+        );
+    }
+}
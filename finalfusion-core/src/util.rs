@@ -1,10 +1,11 @@
 use std::collections::VecDeque;
-use std::io::BufRead;
+use std::io::{BufRead, Read};
 use std::mem::size_of;
 
-use crate::io::{Error, ErrorKind, Result};
 use ndarray::{Array1, ArrayViewMut1, ArrayViewMut2};
 
+use crate::error::{Error, ErrorKind, Result};
+
 /// Conversion from an `Iterator` into a collection with a given
 /// capacity.
 pub trait FromIteratorWithCapacity<T> {
@@ -20,7 +21,7 @@ impl<T> FromIteratorWithCapacity<T> for Vec<T> {
         I: IntoIterator<Item = T>,
     {
         let mut v = Vec::with_capacity(capacity);
-        v.extend(iter.into_iter());
+        v.extend(iter);
         v
     }
 }
@@ -31,7 +32,7 @@ impl<T> FromIteratorWithCapacity<T> for VecDeque<T> {
         I: IntoIterator<Item = T>,
     {
         let mut v = VecDeque::with_capacity(capacity);
-        v.extend(iter.into_iter());
+        v.extend(iter);
         v
     }
 }
@@ -99,18 +100,62 @@ pub fn read_number(reader: &mut dyn BufRead, delim: u8) -> Result<usize> {
 }
 
 pub fn read_string(reader: &mut dyn BufRead, delim: u8, lossy: bool) -> Result<String> {
+    read_string_checked(reader, delim, lossy).map(|(s, _)| s)
+}
+
+/// Read a string, also returns whether invalid UTF-8 was replaced.
+pub fn read_string_checked(
+    reader: &mut dyn BufRead,
+    delim: u8,
+    lossy: bool,
+) -> Result<(String, bool)> {
     let mut buf = Vec::new();
     reader
         .read_until(delim, &mut buf)
         .map_err(|e| ErrorKind::io_error("Cannot read string", e))?;
     buf.pop();
 
-    let s = if lossy {
-        String::from_utf8_lossy(&buf).into_owned()
-    } else {
-        String::from_utf8(buf)
-            .map_err(|e| ErrorKind::Format(format!("Token contains invalid UTF-8: {}", e)))?
-    };
+    decode_string(buf, lossy)
+}
+
+/// Decode a UTF-8 string, also returns whether invalid UTF-8 was replaced.
+pub fn decode_string(buf: Vec<u8>, lossy: bool) -> Result<(String, bool)> {
+    match String::from_utf8(buf) {
+        Ok(s) => Ok((s, false)),
+        Err(e) if lossy => Ok((String::from_utf8_lossy(e.as_bytes()).into_owned(), true)),
+        Err(e) => Err(ErrorKind::Format(format!("Token contains invalid UTF-8: {}", e)).into()),
+    }
+}
+
+/// Write an unsigned integer in the LEB128 variable-length encoding.
+pub fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
 
-    Ok(s)
+/// Read an unsigned integer in the LEB128 variable-length encoding.
+pub fn read_varint(reader: &mut dyn Read) -> Result<u64> {
+    let mut value = 0u64;
+    let mut shift = 0;
+    loop {
+        let mut byte = [0u8];
+        reader
+            .read_exact(&mut byte)
+            .map_err(|e| ErrorKind::io_error("Cannot read variable-length integer", e))?;
+        let payload = (byte[0] & 0x7f) as u64;
+        if shift == 63 && payload > 1 || shift > 63 {
+            return Err(ErrorKind::Format(String::from(
+                "Variable-length integer does not fit in 64 bits",
+            ))
+            .into());
+        }
+        value |= payload << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(value);
+        }
+        shift += 7;
+    }
 }
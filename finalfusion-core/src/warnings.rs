@@ -0,0 +1,251 @@
+//! Non-fatal issues encountered while reading embeddings.
+//!
+//! Readers of the non-finalfusion formats can recover from some
+//! irregularities in embedding files, such as tokens with invalid
+//! UTF-8 in lossy mode or embeddings that cannot be normalized. The
+//! `*_with_warnings` methods of these readers record such issues in
+//! `Warnings`, so that they can be logged or inspected by the caller.
+//!
+//! Warnings can also be handled as they occur using
+//! `Warnings::with_callback`. The number of words with replaced
+//! invalid UTF-8 and the number of skipped malformed lines are counted
+//! in both cases, see `Warnings::invalid_utf8_count` and
+//! `Warnings::malformed_line_count`.
+
+use std::borrow::Cow;
+use std::{fmt, slice, vec};
+
+use ndarray::ArrayView1;
+
+/// Non-fatal issue encountered while reading embeddings.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum Warning {
+    /// Invalid UTF-8 in a word was replaced by the replacement character.
+    InvalidUtf8 { word: String },
+
+    /// An empty line was skipped.
+    ///
+    /// Lines are numbered from 1. Readers that read namespaces from
+    /// separate files, such as the knowledge graph embeddings reader,
+    /// set `namespace` to the namespace of the file.
+    EmptyLine {
+        line: usize,
+        namespace: Option<String>,
+    },
+
+    /// The embedding of a word has norm zero and is not normalized.
+    ZeroNorm { word: String },
+
+    /// A word occurred more than once and was resolved using the
+    /// `DuplicatePolicy` of the reader.
+    DuplicateWord { word: String },
+
+    /// A malformed line was skipped.
+    ///
+    /// Lines are numbered from 1, `reason` describes the problem.
+    MalformedLine { line: usize, reason: String },
+
+    /// A word of a fastText model has no vector in the `.vec` file
+    /// that accompanies the model.
+    MissingVector { word: String },
+
+    /// A word in a `.vec` file is not in the vocabulary of the
+    /// fastText model that it accompanies.
+    UnexpectedVector { word: String },
+
+    /// The vector of a word in a `.vec` file differs from the
+    /// embedding of the word in the fastText model that it accompanies.
+    VectorMismatch { word: String },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use self::Warning::*;
+
+        match self {
+            InvalidUtf8 { word } => write!(f, "Replaced invalid UTF-8 in word: {}", word),
+            EmptyLine {
+                line,
+                namespace: Some(namespace),
+            } => write!(f, "Skipped empty line {} of namespace {}", line, namespace),
+            EmptyLine {
+                line,
+                namespace: None,
+            } => write!(f, "Skipped empty line {}", line),
+            ZeroNorm { word } => write!(f, "Embedding of '{}' has norm zero", word),
+            DuplicateWord { word } => write!(f, "Duplicate word: {}", word),
+            MalformedLine { line, reason } => {
+                write!(f, "Skipped malformed line {}: {}", line, reason)
+            }
+            MissingVector { word } => write!(f, "Missing vector of word: {}", word),
+            UnexpectedVector { word } => write!(f, "Vector of unknown word: {}", word),
+            VectorMismatch { word } => write!(f, "Vector of '{}' does not match model", word),
+        }
+    }
+}
+
+/// Collection of warnings.
+///
+/// By default, warnings are collected and can be inspected after
+/// reading. A `Warnings` instance that is constructed with
+/// `with_callback` passes warnings to the callback instead.
+#[derive(Default)]
+pub struct Warnings {
+    warnings: Vec<Warning>,
+    callback: Option<Box<dyn FnMut(Warning) + Send>>,
+    invalid_utf8: usize,
+    malformed_lines: usize,
+}
+
+impl Warnings {
+    /// Construct an empty collection of warnings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Construct a warnings channel that calls `callback` for every
+    /// warning.
+    ///
+    /// The warnings are not collected.
+    pub fn with_callback(callback: impl FnMut(Warning) + Send + 'static) -> Self {
+        Warnings {
+            warnings: Vec::new(),
+            callback: Some(Box::new(callback)),
+            invalid_utf8: 0,
+            malformed_lines: 0,
+        }
+    }
+
+    /// Check whether warnings were collected.
+    pub fn is_empty(&self) -> bool {
+        self.warnings.is_empty()
+    }
+
+    /// Get an iterator over the collected warnings.
+    pub fn iter(&self) -> slice::Iter<'_, Warning> {
+        self.warnings.iter()
+    }
+
+    /// Get the number of collected warnings.
+    pub fn len(&self) -> usize {
+        self.warnings.len()
+    }
+
+    /// Get the number of words in which invalid UTF-8 was replaced.
+    ///
+    /// This count includes warnings that were passed to a callback.
+    pub fn invalid_utf8_count(&self) -> usize {
+        self.invalid_utf8
+    }
+
+    /// Get the number of malformed lines that were skipped.
+    ///
+    /// This count includes warnings that were passed to a callback.
+    pub fn malformed_line_count(&self) -> usize {
+        self.malformed_lines
+    }
+
+    /// Add a warning.
+    pub fn push(&mut self, warning: Warning) {
+        match warning {
+            Warning::InvalidUtf8 { .. } => self.invalid_utf8 += 1,
+            Warning::MalformedLine { .. } => self.malformed_lines += 1,
+            _ => (),
+        }
+
+        match self.callback {
+            Some(ref mut callback) => callback(warning),
+            None => self.warnings.push(warning),
+        }
+    }
+
+    /// Add warnings for words with embeddings that have norm zero.
+    #[doc(hidden)]
+    pub fn push_zero_norms<'a>(
+        &mut self,
+        words: impl IntoIterator<Item = (Cow<'a, str>, usize)>,
+        norms: ArrayView1<f32>,
+    ) {
+        for (word, idx) in words {
+            if norms[idx] == 0. {
+                self.push(Warning::ZeroNorm {
+                    word: word.into_owned(),
+                })
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Warnings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Warnings")
+            .field("warnings", &self.warnings)
+            .field("callback", &self.callback.is_some())
+            .field("invalid_utf8", &self.invalid_utf8)
+            .field("malformed_lines", &self.malformed_lines)
+            .finish()
+    }
+}
+
+impl IntoIterator for Warnings {
+    type Item = Warning;
+    type IntoIter = vec::IntoIter<Warning>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.warnings.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Warnings {
+    type Item = &'a Warning;
+    type IntoIter = slice::Iter<'a, Warning>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use ndarray::array;
+
+    use super::{Warning, Warnings};
+
+    #[test]
+    fn warnings_are_collected() {
+        let mut warnings = Warnings::new();
+        warnings.push_zero_norms(
+            vec![("a".into(), 0), ("b".into(), 1), ("c".into(), 2)],
+            array![1., 0., 2.].view(),
+        );
+        assert_eq!(
+            warnings.into_iter().collect::<Vec<_>>(),
+            vec![Warning::ZeroNorm {
+                word: "b".to_owned()
+            }]
+        );
+    }
+
+    #[test]
+    fn warnings_are_passed_to_callback() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let callback_seen = seen.clone();
+        let mut warnings =
+            Warnings::with_callback(move |warning| callback_seen.lock().unwrap().push(warning));
+        let warning = Warning::EmptyLine {
+            line: 3,
+            namespace: None,
+        };
+        warnings.push(warning.clone());
+        let invalid_utf8 = Warning::InvalidUtf8 {
+            word: "z\u{fffd}e".to_owned(),
+        };
+        warnings.push(invalid_utf8.clone());
+        assert!(warnings.is_empty());
+        assert_eq!(warnings.invalid_utf8_count(), 1);
+        assert_eq!(*seen.lock().unwrap(), vec![warning, invalid_utf8]);
+    }
+}
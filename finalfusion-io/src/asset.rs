@@ -0,0 +1,457 @@
+//! Embeddings that are stored inside a larger file.
+//!
+//! Android packages (APKs) store assets in a zip archive. Assets that
+//! are stored uncompressed can be accessed directly at an offset in the
+//! package. `AssetReader` reads and memory maps finalfusion embeddings
+//! from such a file region, so that embeddings can be used without
+//! extracting them first.
+//!
+//! An `AssetReader` can be constructed from a file and the offset and
+//! length of the embeddings in that file, such as the file descriptor,
+//! offset, and length returned by Android's `AAsset_openFileDescriptor`.
+//! Alternatively, `AssetReader::open_zip_entry` looks up an
+//! uncompressed entry in a zip archive.
+//!
+//! The offset of the embeddings must be a multiple of 4, which is
+//! guaranteed for uncompressed assets that were aligned with
+//! `zipalign`. Embedding matrices with `f64` components require an
+//! offset that is a multiple of 8.
+
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use byteorder::{LittleEndian, ReadBytesExt};
+
+use crate::io::{ErrorKind, MmapEmbeddings, ReadEmbeddings, Result};
+
+/// Required alignment of the offset of embeddings in a file.
+const ASSET_ALIGNMENT: u64 = 4;
+
+const ZIP_LOCAL_HEADER_SIGNATURE: u32 = 0x0403_4b50;
+const ZIP_CENTRAL_HEADER_SIGNATURE: u32 = 0x0201_4b50;
+const ZIP_END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x0605_4b50;
+
+/// Size of the end of central directory record without comment.
+const ZIP_END_OF_CENTRAL_DIRECTORY_LEN: u64 = 22;
+
+/// Maximum length of a zip archive comment.
+const ZIP_MAX_COMMENT_LEN: u64 = u16::MAX as u64;
+
+/// Compression method of uncompressed zip entries.
+const ZIP_STORED: u16 = 0;
+
+/// Reader for embeddings in a region of a file.
+#[derive(Debug)]
+pub struct AssetReader {
+    file: File,
+    offset: u64,
+    len: u64,
+}
+
+impl AssetReader {
+    /// Construct a reader for the embeddings at `offset` in `file`.
+    ///
+    /// `len` is the length of the embeddings in bytes.
+    pub fn new(file: File, offset: u64, len: u64) -> Result<Self> {
+        if offset % ASSET_ALIGNMENT != 0 {
+            return Err(ErrorKind::Format(format!(
+                "Asset offset {} is not a multiple of {}",
+                offset, ASSET_ALIGNMENT
+            ))
+            .into());
+        }
+
+        let file_len = file
+            .metadata()
+            .map_err(|e| ErrorKind::io_error("Cannot get asset file metadata", e))?
+            .len();
+        if offset.checked_add(len).map(|end| end > file_len) != Some(false) {
+            return Err(ErrorKind::Format(format!(
+                "Asset at offset {} with length {} extends beyond file of length {}",
+                offset, len, file_len
+            ))
+            .into());
+        }
+
+        Ok(AssetReader { file, offset, len })
+    }
+
+    /// Construct a reader for an uncompressed entry of a zip archive.
+    ///
+    /// Returns an error when the archive does not contain the entry or
+    /// when the entry is compressed. ZIP64 archives are not supported.
+    pub fn open_zip_entry(path: impl AsRef<Path>, name: &str) -> Result<Self> {
+        let mut file =
+            File::open(path).map_err(|e| ErrorKind::io_error("Cannot open zip archive", e))?;
+        let (offset, len) = find_zip_entry(&mut file, name)?
+            .ok_or_else(|| ErrorKind::Format(format!("Zip archive does not contain '{}'", name)))?;
+        AssetReader::new(file, offset, len)
+    }
+
+    /// Get the length of the embeddings in bytes.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns `true` when the embeddings have length zero.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Get the offset of the embeddings in the file.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Memory map the embeddings.
+    ///
+    /// Memory mapping requires a reader of the complete file, so it is
+    /// not verified that the embeddings do not extend beyond the end of
+    /// the asset.
+    pub fn mmap_embeddings<E>(&self) -> Result<E>
+    where
+        E: MmapEmbeddings,
+    {
+        E::mmap_embeddings(&mut self.reader()?)
+    }
+
+    /// Read the embeddings.
+    ///
+    /// Reads are restricted to the asset.
+    pub fn read_embeddings<E>(&self) -> Result<E>
+    where
+        E: ReadEmbeddings,
+    {
+        let mut region = Region {
+            inner: self.reader()?,
+            start: self.offset,
+            end: self.offset + self.len,
+        };
+        E::read_embeddings(&mut region)
+    }
+
+    /// Get a reader that is positioned at the start of the embeddings.
+    ///
+    /// Readers of finalfusion chunks only seek relative to positions
+    /// that they obtained from the reader, so chunks can be read at
+    /// any (aligned) offset.
+    fn reader(&self) -> Result<BufReader<File>> {
+        let mut file = self
+            .file
+            .try_clone()
+            .map_err(|e| ErrorKind::io_error("Cannot duplicate asset file handle", e))?;
+        file.seek(SeekFrom::Start(self.offset))
+            .map_err(|e| ErrorKind::io_error("Cannot seek to asset", e))?;
+        Ok(BufReader::new(file))
+    }
+}
+
+/// Reader that is restricted to a region of a file.
+///
+/// Positions are absolute, so that the alignment of chunks is
+/// determined in the same way as for memory mapping.
+struct Region<R> {
+    inner: R,
+    start: u64,
+    end: u64,
+}
+
+impl<R> Read for Region<R>
+where
+    R: Read + Seek,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let pos = self.inner.stream_position()?;
+        let remaining = self.end.saturating_sub(pos).min(buf.len() as u64) as usize;
+        self.inner.read(&mut buf[..remaining])
+    }
+}
+
+impl<R> Seek for Region<R>
+where
+    R: Seek,
+{
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::End(offset) => SeekFrom::Start((self.end as i64 + offset) as u64),
+            pos => pos,
+        };
+
+        let new_pos = self.inner.seek(pos)?;
+        if new_pos < self.start {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Seek before the start of the asset",
+            ));
+        }
+
+        Ok(new_pos)
+    }
+}
+
+/// Find the data offset and length of an uncompressed zip entry.
+///
+/// Returns `None` when the archive does not contain the entry.
+#[doc(hidden)]
+pub fn find_zip_entry<R>(read: &mut R, name: &str) -> Result<Option<(u64, u64)>>
+where
+    R: Read + Seek,
+{
+    let (central_directory_offset, n_entries) = read_zip_end_of_central_directory(read)?;
+
+    read.seek(SeekFrom::Start(central_directory_offset))
+        .map_err(|e| ErrorKind::io_error("Cannot seek to zip central directory", e))?;
+
+    for _ in 0..n_entries {
+        let mut header = [0u8; 46];
+        read.read_exact(&mut header)
+            .map_err(|e| ErrorKind::io_error("Cannot read zip central directory header", e))?;
+        let mut header = &header[..];
+
+        if header.read_u32::<LittleEndian>().unwrap() != ZIP_CENTRAL_HEADER_SIGNATURE {
+            return Err(
+                ErrorKind::Format("Invalid zip central directory header".to_string()).into(),
+            );
+        }
+
+        // Skip versions and flags.
+        header = &header[6..];
+        let method = header.read_u16::<LittleEndian>().unwrap();
+        // Skip modification time, modification date, and CRC-32.
+        header = &header[8..];
+        let compressed_len = header.read_u32::<LittleEndian>().unwrap();
+        let _uncompressed_len = header.read_u32::<LittleEndian>().unwrap();
+        let name_len = header.read_u16::<LittleEndian>().unwrap();
+        let extra_len = header.read_u16::<LittleEndian>().unwrap();
+        let comment_len = header.read_u16::<LittleEndian>().unwrap();
+        // Skip disk number and file attributes.
+        header = &header[8..];
+        let local_header_offset = header.read_u32::<LittleEndian>().unwrap();
+
+        let mut entry_name = vec![0u8; name_len as usize];
+        read.read_exact(&mut entry_name)
+            .map_err(|e| ErrorKind::io_error("Cannot read zip entry name", e))?;
+        read.seek(SeekFrom::Current(extra_len as i64 + comment_len as i64))
+            .map_err(|e| ErrorKind::io_error("Cannot skip zip entry fields", e))?;
+
+        if entry_name != name.as_bytes() {
+            continue;
+        }
+
+        if method != ZIP_STORED {
+            return Err(ErrorKind::Format(format!("Zip entry '{}' is compressed", name)).into());
+        }
+
+        if compressed_len == u32::MAX || local_header_offset == u32::MAX {
+            return Err(ErrorKind::Format("ZIP64 archives are not supported".to_string()).into());
+        }
+
+        let offset = read_zip_local_header(read, local_header_offset as u64)?;
+        return Ok(Some((offset, compressed_len as u64)));
+    }
+
+    Ok(None)
+}
+
+/// Read the end of central directory record of a zip archive.
+///
+/// Returns the offset of the central directory and the number of
+/// entries.
+fn read_zip_end_of_central_directory<R>(read: &mut R) -> Result<(u64, u16)>
+where
+    R: Read + Seek,
+{
+    let file_len = read
+        .seek(SeekFrom::End(0))
+        .map_err(|e| ErrorKind::io_error("Cannot seek to end of zip archive", e))?;
+    if file_len < ZIP_END_OF_CENTRAL_DIRECTORY_LEN {
+        return Err(ErrorKind::Format("File is too short to be a zip archive".to_string()).into());
+    }
+
+    // The record is followed by a variable-length comment, so search
+    // the signature backwards.
+    let search_len = file_len.min(ZIP_END_OF_CENTRAL_DIRECTORY_LEN + ZIP_MAX_COMMENT_LEN);
+    read.seek(SeekFrom::Start(file_len - search_len))
+        .map_err(|e| ErrorKind::io_error("Cannot seek to end of zip archive", e))?;
+    let mut tail = vec![0u8; search_len as usize];
+    read.read_exact(&mut tail)
+        .map_err(|e| ErrorKind::io_error("Cannot read end of zip archive", e))?;
+
+    let signature = ZIP_END_OF_CENTRAL_DIRECTORY_SIGNATURE.to_le_bytes();
+    let record_start = (0..=tail.len() - ZIP_END_OF_CENTRAL_DIRECTORY_LEN as usize)
+        .rev()
+        .find(|&start| tail[start..start + 4] == signature)
+        .ok_or_else(|| {
+            ErrorKind::Format("Cannot find zip end of central directory record".to_string())
+        })?;
+
+    // Skip signature and disk numbers.
+    let mut record = &tail[record_start + 8..];
+    let _n_disk_entries = record.read_u16::<LittleEndian>().unwrap();
+    let n_entries = record.read_u16::<LittleEndian>().unwrap();
+    let _central_directory_len = record.read_u32::<LittleEndian>().unwrap();
+    let central_directory_offset = record.read_u32::<LittleEndian>().unwrap();
+
+    if n_entries == u16::MAX || central_directory_offset == u32::MAX {
+        return Err(ErrorKind::Format("ZIP64 archives are not supported".to_string()).into());
+    }
+
+    Ok((central_directory_offset as u64, n_entries))
+}
+
+/// Read a zip local file header, returning the offset of the entry data.
+fn read_zip_local_header<R>(read: &mut R, offset: u64) -> Result<u64>
+where
+    R: Read + Seek,
+{
+    read.seek(SeekFrom::Start(offset))
+        .map_err(|e| ErrorKind::io_error("Cannot seek to zip local file header", e))?;
+
+    let mut header = [0u8; 30];
+    read.read_exact(&mut header)
+        .map_err(|e| ErrorKind::io_error("Cannot read zip local file header", e))?;
+    let mut header = &header[..];
+
+    if header.read_u32::<LittleEndian>().unwrap() != ZIP_LOCAL_HEADER_SIGNATURE {
+        return Err(ErrorKind::Format("Invalid zip local file header".to_string()).into());
+    }
+
+    // The name and extra field lengths can differ from those in the
+    // central directory, e.g. zipalign pads the extra field.
+    header = &header[22..];
+    let name_len = header.read_u16::<LittleEndian>().unwrap();
+    let extra_len = header.read_u16::<LittleEndian>().unwrap();
+
+    Ok(offset + 30 + name_len as u64 + extra_len as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, File};
+    use std::io::{BufReader, Cursor, Write};
+    use std::path::PathBuf;
+
+    use byteorder::{LittleEndian, WriteBytesExt};
+
+    use super::AssetReader;
+    use crate::io::{ReadEmbeddings, WriteEmbeddings};
+    use finalfusion_core::embeddings::Embeddings;
+    use finalfusion_core::storage::StorageWrap;
+    use finalfusion_core::vocab::VocabWrap;
+
+    fn test_embeddings() -> Embeddings<VocabWrap, StorageWrap> {
+        let mut reader = BufReader::new(File::open("../testdata/similarity.fifu").unwrap());
+        Embeddings::read_embeddings(&mut reader).unwrap()
+    }
+
+    /// Write a zip archive with uncompressed entries.
+    ///
+    /// The extra field of local headers pads entry data to a multiple
+    /// of 4 bytes, similar to zipalign.
+    fn write_zip(path: &PathBuf, entries: &[(&str, &[u8])]) {
+        let mut data = Vec::new();
+        let mut central_directory = Vec::new();
+
+        for &(name, contents) in entries {
+            let offset = data.len();
+            let extra_len = (4 - (offset + 30 + name.len()) % 4) % 4;
+
+            data.write_u32::<LittleEndian>(0x0403_4b50).unwrap();
+            data.write_all(&[10, 0, 0, 0, 0, 0, 0, 0, 0, 0]).unwrap();
+            // CRC-32 is not verified.
+            data.write_u32::<LittleEndian>(0).unwrap();
+            data.write_u32::<LittleEndian>(contents.len() as u32)
+                .unwrap();
+            data.write_u32::<LittleEndian>(contents.len() as u32)
+                .unwrap();
+            data.write_u16::<LittleEndian>(name.len() as u16).unwrap();
+            data.write_u16::<LittleEndian>(extra_len as u16).unwrap();
+            data.write_all(name.as_bytes()).unwrap();
+            data.write_all(&vec![0; extra_len]).unwrap();
+            data.write_all(contents).unwrap();
+
+            central_directory
+                .write_u32::<LittleEndian>(0x0201_4b50)
+                .unwrap();
+            central_directory
+                .write_all(&[20, 0, 10, 0, 0, 0, 0, 0, 0, 0, 0, 0])
+                .unwrap();
+            central_directory.write_u32::<LittleEndian>(0).unwrap();
+            central_directory
+                .write_u32::<LittleEndian>(contents.len() as u32)
+                .unwrap();
+            central_directory
+                .write_u32::<LittleEndian>(contents.len() as u32)
+                .unwrap();
+            central_directory
+                .write_u16::<LittleEndian>(name.len() as u16)
+                .unwrap();
+            central_directory.write_all(&[0; 12]).unwrap();
+            central_directory
+                .write_u32::<LittleEndian>(offset as u32)
+                .unwrap();
+            central_directory.write_all(name.as_bytes()).unwrap();
+        }
+
+        let central_directory_offset = data.len();
+        data.write_all(&central_directory).unwrap();
+        data.write_u32::<LittleEndian>(0x0605_4b50).unwrap();
+        data.write_all(&[0; 4]).unwrap();
+        data.write_u16::<LittleEndian>(entries.len() as u16)
+            .unwrap();
+        data.write_u16::<LittleEndian>(entries.len() as u16)
+            .unwrap();
+        data.write_u32::<LittleEndian>(central_directory.len() as u32)
+            .unwrap();
+        data.write_u32::<LittleEndian>(central_directory_offset as u32)
+            .unwrap();
+        data.write_u16::<LittleEndian>(0).unwrap();
+
+        fs::write(path, data).unwrap();
+    }
+
+    #[test]
+    fn asset_reader_reads_zip_entry() {
+        let check_embeds = test_embeddings();
+        let mut embeds_data = Cursor::new(Vec::new());
+        check_embeds.write_embeddings(&mut embeds_data).unwrap();
+
+        let path = std::env::temp_dir().join(format!("asset-{}.apk", std::process::id()));
+        write_zip(
+            &path,
+            &[
+                ("AndroidManifest.xml", b"<manifest/>"),
+                ("assets/embeddings.fifu", embeds_data.get_ref()),
+            ],
+        );
+
+        let reader = AssetReader::open_zip_entry(&path, "assets/embeddings.fifu").unwrap();
+        assert_eq!(reader.len(), embeds_data.get_ref().len() as u64);
+
+        let embeds: Embeddings<VocabWrap, StorageWrap> = reader.mmap_embeddings().unwrap();
+        assert_eq!(embeds.vocab(), check_embeds.vocab());
+        assert_eq!(embeds.embedding("Berlin"), check_embeds.embedding("Berlin"));
+
+        let embeds: Embeddings<VocabWrap, StorageWrap> = reader.read_embeddings().unwrap();
+        assert_eq!(embeds.embedding("Berlin"), check_embeds.embedding("Berlin"));
+
+        assert!(AssetReader::open_zip_entry(&path, "assets/missing.fifu").is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn asset_reader_rejects_invalid_regions() {
+        let file = File::open("../testdata/similarity.fifu").unwrap();
+        let len = file.metadata().unwrap().len();
+        assert!(AssetReader::new(file.try_clone().unwrap(), 2, 10).is_err());
+        assert!(AssetReader::new(file.try_clone().unwrap(), 4, len).is_err());
+
+        // The embeddings extend beyond the region.
+        let reader = AssetReader::new(file, 0, 64).unwrap();
+        assert!(reader
+            .read_embeddings::<Embeddings<VocabWrap, StorageWrap>>()
+            .is_err());
+    }
+}
@@ -0,0 +1,107 @@
+//! Word counts chunk
+
+use std::io::{Read, Seek, Write};
+use std::mem::size_of;
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use finalfusion_core::counts::WordCounts;
+
+use crate::chunks::io::{ChunkIdentifier, ReadChunk, WriteChunk};
+use crate::io::{ErrorKind, Result};
+
+impl ReadChunk for WordCounts {
+    fn read_chunk<R>(read: &mut R) -> Result<Self>
+    where
+        R: Read + Seek,
+    {
+        ChunkIdentifier::ensure_chunk_type(read, ChunkIdentifier::WordCounts)?;
+
+        // Read and discard chunk length.
+        read.read_u64::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read counts chunk length", e))?;
+
+        let len = read
+            .read_u64::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read number of counts", e))?
+            as usize;
+
+        let mut data = vec![0u64; len];
+        read.read_u64_into::<LittleEndian>(&mut data)
+            .map_err(|e| ErrorKind::io_error("Cannot read counts", e))?;
+
+        Ok(WordCounts::new(data))
+    }
+}
+
+impl WriteChunk for WordCounts {
+    fn chunk_identifier(&self) -> ChunkIdentifier {
+        ChunkIdentifier::WordCounts
+    }
+
+    fn write_chunk<W>(&self, write: &mut W) -> Result<()>
+    where
+        W: Write + Seek,
+    {
+        // Chunk size: len (u64), counts.
+        let chunk_len = size_of::<u64>() + self.len() * size_of::<u64>();
+
+        write
+            .write_u32::<LittleEndian>(ChunkIdentifier::WordCounts as u32)
+            .map_err(|e| ErrorKind::io_error("Cannot write counts chunk identifier", e))?;
+        write
+            .write_u64::<LittleEndian>(chunk_len as u64)
+            .map_err(|e| ErrorKind::io_error("Cannot write counts chunk length", e))?;
+        write
+            .write_u64::<LittleEndian>(self.len() as u64)
+            .map_err(|e| ErrorKind::io_error("Cannot write number of counts", e))?;
+
+        for &count in self.iter() {
+            write
+                .write_u64::<LittleEndian>(count)
+                .map_err(|e| ErrorKind::io_error("Cannot write count", e))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read, Seek, SeekFrom};
+
+    use byteorder::{LittleEndian, ReadBytesExt};
+
+    use crate::chunks::io::{ReadChunk, WriteChunk};
+    use finalfusion_core::counts::WordCounts;
+
+    fn test_counts() -> WordCounts {
+        WordCounts::new((0..100).map(|count| count * 1000).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn counts_correct_chunk_size() {
+        let check_counts = test_counts();
+        let mut cursor = Cursor::new(Vec::new());
+        check_counts.write_chunk(&mut cursor).unwrap();
+        cursor.seek(SeekFrom::Start(0)).unwrap();
+
+        // Skip identifier.
+        cursor.read_u32::<LittleEndian>().unwrap();
+        let chunk_size = cursor.read_u64::<LittleEndian>().unwrap();
+        assert_eq!(
+            cursor.read_to_end(&mut Vec::new()).unwrap(),
+            chunk_size as usize
+        );
+    }
+
+    #[test]
+    fn counts_write_read_roundtrip() {
+        let check_counts = test_counts();
+        let mut cursor = Cursor::new(Vec::new());
+        check_counts.write_chunk(&mut cursor).unwrap();
+        cursor.seek(SeekFrom::Start(0)).unwrap();
+        let counts = WordCounts::read_chunk(&mut cursor).unwrap();
+        assert_eq!(counts, check_counts);
+        assert_eq!(counts.total(), 4_950_000);
+    }
+}
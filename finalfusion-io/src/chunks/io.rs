@@ -0,0 +1,1322 @@
+use std::any;
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::ops::BitOr;
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use finalfusion_core::unknown::UnknownChunk;
+use half::f16;
+use ndarray::Array1;
+
+use crate::io::{Error, ErrorKind, Result};
+
+/// Latest version of the finalfusion format.
+///
+/// Version 1 headers list the features that are required to read a
+/// file. Files that do not require any features are written with a
+/// version 0 header, so that they can be read by older readers.
+pub(crate) const MODEL_VERSION: u32 = 1;
+
+/// Format version of headers without features.
+const MODEL_VERSION_WITHOUT_FEATURES: u32 = 0;
+
+/// Features of the finalfusion format that are required to read a file.
+///
+/// Features are stored as bit flags in the file header. Reading a
+/// file fails with a descriptive error when it requires features that
+/// are not supported by this version of finalfusion.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Features {
+    bits: u64,
+}
+
+impl Features {
+    /// Chunk kinds that were added after version 0 of the format.
+    pub const EXTENDED_CHUNKS: Features = Features { bits: 1 };
+
+    /// Checksums of the chunk data.
+    pub const CHECKSUMS: Features = Features { bits: 1 << 1 };
+
+    /// Chunks that index the data of other chunks.
+    pub const INDEX_CHUNKS: Features = Features { bits: 1 << 2 };
+
+    /// Quantized matrices with two 4-bit codes per byte.
+    pub const PACKED_CODES: Features = Features { bits: 1 << 3 };
+
+    /// Quantized matrices with half-precision norms.
+    pub const HALF_NORMS: Features = Features { bits: 1 << 4 };
+
+    /// Features that are supported by this version of finalfusion.
+    pub const SUPPORTED: Features = Features {
+        bits: Features::EXTENDED_CHUNKS.bits
+            | Features::PACKED_CODES.bits
+            | Features::HALF_NORMS.bits,
+    };
+
+    const NAMED: &'static [(Features, &'static str)] = &[
+        (Features::EXTENDED_CHUNKS, "extended chunks"),
+        (Features::CHECKSUMS, "checksums"),
+        (Features::INDEX_CHUNKS, "index chunks"),
+        (Features::PACKED_CODES, "packed quantization codes"),
+        (Features::HALF_NORMS, "half-precision norms"),
+    ];
+
+    /// Construct an empty set of features.
+    pub const fn empty() -> Self {
+        Features { bits: 0 }
+    }
+
+    /// Construct features from their bit flags.
+    ///
+    /// Unknown bits are retained, so that they can be reported.
+    pub const fn from_bits(bits: u64) -> Self {
+        Features { bits }
+    }
+
+    /// Get the bit flags of the features.
+    pub const fn bits(self) -> u64 {
+        self.bits
+    }
+
+    /// Check whether all features of `other` are contained in `self`.
+    pub const fn contains(self, other: Features) -> bool {
+        self.bits & other.bits == other.bits
+    }
+
+    /// Check whether the set of features is empty.
+    pub const fn is_empty(self) -> bool {
+        self.bits == 0
+    }
+
+    /// Get the features that are in `self`, but not in `other`.
+    pub const fn difference(self, other: Features) -> Self {
+        Features {
+            bits: self.bits & !other.bits,
+        }
+    }
+
+    /// Get the features that are required for the given chunks.
+    fn for_chunks(identifiers: &[ChunkIdentifier]) -> Self {
+        if identifiers
+            .iter()
+            .any(|&identifier| identifier as u32 > ChunkIdentifier::ExplicitSubwordVocab as u32)
+        {
+            Features::EXTENDED_CHUNKS
+        } else {
+            Features::empty()
+        }
+    }
+}
+
+impl BitOr for Features {
+    type Output = Features;
+
+    fn bitor(self, rhs: Features) -> Features {
+        Features {
+            bits: self.bits | rhs.bits,
+        }
+    }
+}
+
+impl Display for Features {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut names = Features::NAMED
+            .iter()
+            .filter(|(feature, _)| self.contains(*feature))
+            .map(|&(_, name)| name.to_owned())
+            .collect::<Vec<_>>();
+
+        let unknown = Features::NAMED
+            .iter()
+            .fold(*self, |unknown, &(feature, _)| unknown.difference(feature));
+        if !unknown.is_empty() {
+            names.push(format!("unknown features {:#x}", unknown.bits));
+        }
+
+        if names.is_empty() {
+            write!(f, "none")
+        } else {
+            write!(f, "{}", names.join(", "))
+        }
+    }
+}
+
+const MAGIC: [u8; 4] = [b'F', b'i', b'F', b'u'];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum ChunkIdentifier {
+    Header = 0,
+    SimpleVocab = 1,
+    NdArray = 2,
+    BucketSubwordVocab = 3,
+    QuantizedArray = 4,
+    Metadata = 5,
+    NdNorms = 6,
+    FastTextSubwordVocab = 7,
+    ExplicitSubwordVocab = 8,
+    NamespacedVocab = 9,
+    DedupArray = 10,
+    ResidualQuantizedArray = 11,
+    WordCounts = 12,
+    FstVocab = 13,
+    ByteFallbackVocab = 14,
+    BpeVocab = 15,
+    WordPieceVocab = 16,
+    SentencePieceVocab = 17,
+    MmapVocab = 18,
+    AliasVocab = 19,
+    FrontCodedVocab = 20,
+}
+
+impl ChunkIdentifier {
+    pub fn try_from(identifier: u32) -> Option<Self> {
+        use self::ChunkIdentifier::*;
+
+        match identifier {
+            1 => Some(SimpleVocab),
+            2 => Some(NdArray),
+            3 => Some(BucketSubwordVocab),
+            4 => Some(QuantizedArray),
+            5 => Some(Metadata),
+            6 => Some(NdNorms),
+            7 => Some(FastTextSubwordVocab),
+            8 => Some(ExplicitSubwordVocab),
+            9 => Some(NamespacedVocab),
+            10 => Some(DedupArray),
+            11 => Some(ResidualQuantizedArray),
+            12 => Some(WordCounts),
+            13 => Some(FstVocab),
+            14 => Some(ByteFallbackVocab),
+            15 => Some(BpeVocab),
+            16 => Some(WordPieceVocab),
+            17 => Some(SentencePieceVocab),
+            18 => Some(MmapVocab),
+            19 => Some(AliasVocab),
+            20 => Some(FrontCodedVocab),
+            _ => None,
+        }
+    }
+
+    /// Read and ensure that the chunk has the given identifier.
+    pub fn ensure_chunk_type<R>(read: &mut R, identifier: ChunkIdentifier) -> Result<()>
+    where
+        R: Read,
+    {
+        let chunk_id = read
+            .read_u32::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read chunk identifier", e))?;
+        let chunk_id = ChunkIdentifier::try_from(chunk_id)
+            .ok_or_else(|| ErrorKind::Format(format!("Unknown chunk identifier: {}", chunk_id)))
+            .map_err(Error::from)?;
+        if chunk_id != identifier {
+            return Err(ErrorKind::Format(format!(
+                "Invalid chunk identifier, expected: {}, got: {}",
+                identifier, chunk_id
+            ))
+            .into());
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for ChunkIdentifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use self::ChunkIdentifier::*;
+
+        match self {
+            Header => write!(f, "Header"),
+            SimpleVocab => write!(f, "SimpleVocab"),
+            NdArray => write!(f, "NdArray"),
+            FastTextSubwordVocab => write!(f, "FastTextSubwordVocab"),
+            ExplicitSubwordVocab => write!(f, "ExplicitSubwordVocab"),
+            NamespacedVocab => write!(f, "NamespacedVocab"),
+            DedupArray => write!(f, "DedupArray"),
+            BucketSubwordVocab => write!(f, "BucketSubwordVocab"),
+            QuantizedArray => write!(f, "QuantizedArray"),
+            ResidualQuantizedArray => write!(f, "ResidualQuantizedArray"),
+            Metadata => write!(f, "Metadata"),
+            NdNorms => write!(f, "NdNorms"),
+            WordCounts => write!(f, "WordCounts"),
+            FstVocab => write!(f, "FstVocab"),
+            ByteFallbackVocab => write!(f, "ByteFallbackVocab"),
+            BpeVocab => write!(f, "BpeVocab"),
+            WordPieceVocab => write!(f, "WordPieceVocab"),
+            SentencePieceVocab => write!(f, "SentencePieceVocab"),
+            MmapVocab => write!(f, "MmapVocab"),
+            AliasVocab => write!(f, "AliasVocab"),
+            FrontCodedVocab => write!(f, "FrontCodedVocab"),
+        }
+    }
+}
+
+/// Trait defining identifiers for data types.
+pub trait TypeId {
+    /// Read and ensure that the data type is equal to `Self`.
+    fn ensure_data_type<R>(read: &mut R) -> Result<()>
+    where
+        R: Read;
+
+    /// Get this data type's identifier.
+    fn type_id() -> u32;
+}
+
+macro_rules! typeid_impl {
+    ($type:ty, $id:expr) => {
+        impl TypeId for $type {
+            fn ensure_data_type<R>(read: &mut R) -> Result<()>
+            where
+                R: Read,
+            {
+                let type_id = read
+                    .read_u32::<LittleEndian>()
+                    .map_err(|e| ErrorKind::io_error("Cannot read type identifier", e))?;
+                if type_id != Self::type_id() {
+                    return Err(ErrorKind::Format(format!(
+                        "Invalid type, expected: {}, got: {}",
+                        Self::type_id(),
+                        type_id
+                    ))
+                    .into());
+                }
+
+                Ok(())
+            }
+
+            fn type_id() -> u32 {
+                $id
+            }
+        }
+    };
+}
+
+/// Quantization codes of 4 bits, two of which are packed in a byte.
+pub(crate) struct PackedCodes;
+
+// floats starting at 10 to leave room for other integer types.
+typeid_impl!(f32, 10);
+typeid_impl!(f64, 11);
+typeid_impl!(f16, 12);
+typeid_impl!(u8, 1);
+typeid_impl!(PackedCodes, 2);
+
+pub trait ReadChunk
+where
+    Self: Sized,
+{
+    fn read_chunk<R>(read: &mut R) -> Result<Self>
+    where
+        R: Read + Seek;
+
+    /// Validate the chunk exhaustively.
+    ///
+    /// Some chunks only perform inexpensive checks when they are read,
+    /// because a full validation would take time linear in the size
+    /// of the chunk. This method performs the remaining checks. It is
+    /// called when embeddings are read with `ReadEmbeddingsStrict`.
+    fn validate(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Get the name of a chunk from its identifier.
+///
+/// Chunks that are unknown to this crate are named `Unknown(id)`.
+pub(crate) fn chunk_name(identifier: u32) -> String {
+    ChunkIdentifier::try_from(identifier)
+        .map(|identifier| identifier.to_string())
+        .unwrap_or_else(|| format!("Unknown({})", identifier))
+}
+
+/// Read a chunk, verifying that exactly its declared length is read.
+///
+/// Chunks declare the length of their data after the chunk
+/// identifier. Chunk readers do not always rely on the declared
+/// length, so a file with an incorrect length may be read without
+/// errors, or fail in a later chunk. This function fails with the
+/// offsets of the chunk when its declared length extends beyond the
+/// end of the data, or when `read_chunk` reads a different number of
+/// bytes than declared.
+///
+/// The reader must be positioned at the start of the chunk.
+pub(crate) fn read_chunk_strict<C, R>(
+    read: &mut R,
+    read_chunk: impl FnOnce(&mut R) -> Result<C>,
+) -> Result<C>
+where
+    R: Read + Seek,
+{
+    let start = read
+        .stream_position()
+        .map_err(|e| ErrorKind::io_error("Cannot get chunk position", e))?;
+    let identifier = read
+        .read_u32::<LittleEndian>()
+        .map_err(|e| ErrorKind::io_error("Cannot read chunk identifier", e))?;
+    let name = chunk_name(identifier);
+    let chunk_len = read
+        .read_u64::<LittleEndian>()
+        .map_err(|e| ErrorKind::io_error(format!("Cannot read length of chunk {}", name), e))?;
+
+    let data_start = start + 12;
+    let end = data_start.checked_add(chunk_len).ok_or_else(|| {
+        ErrorKind::Format(format!(
+            "Chunk {} at offset {} has invalid length {}",
+            name, start, chunk_len
+        ))
+    })?;
+    let data_end = read
+        .seek(SeekFrom::End(0))
+        .map_err(|e| ErrorKind::io_error("Cannot seek to end of data", e))?;
+    if end > data_end {
+        return Err(ErrorKind::Format(format!(
+            "Chunk {} at offset {} with length {} ends at offset {}, beyond the end of the data at offset {}",
+            name, start, chunk_len, end, data_end
+        ))
+        .into());
+    }
+
+    read.seek(SeekFrom::Start(start))
+        .map_err(|e| ErrorKind::io_error("Cannot seek to start of chunk", e))?;
+    let chunk = read_chunk(read)?;
+
+    let read_end = read
+        .stream_position()
+        .map_err(|e| ErrorKind::io_error("Cannot get chunk position", e))?;
+    if read_end != end {
+        return Err(ErrorKind::Format(format!(
+            "Chunk {} at offset {} with length {} should end at offset {}, but reading it ended at offset {}",
+            name, start, chunk_len, end, read_end
+        ))
+        .into());
+    }
+
+    Ok(chunk)
+}
+
+/// Memory-mappable chunks.
+pub trait MmapChunk
+where
+    Self: Sized,
+{
+    /// Memory map a chunk.
+    ///
+    /// The given `File` object should be positioned at the start of the chunk.
+    fn mmap_chunk(read: &mut BufReader<File>) -> Result<Self>;
+}
+
+/// Storage chunks that can be read with truncated embeddings.
+pub trait ReadChunkTruncated
+where
+    Self: Sized,
+{
+    /// Read a storage chunk, retaining the first `dims` components of
+    /// each embedding.
+    ///
+    /// The given `File` object should be positioned at the start of the chunk.
+    fn read_chunk_truncated(read: &mut BufReader<File>, dims: usize) -> Result<Self>;
+
+    /// Normalize the first `n_rows` truncated embeddings to unit length.
+    ///
+    /// Returns the norms of the truncated embeddings before
+    /// normalization.
+    fn normalize_rows(&mut self, n_rows: usize) -> Result<Array1<f32>>;
+}
+
+/// Storage chunks of which a subset of the rows can be read.
+pub trait ReadChunkRows
+where
+    Self: Sized,
+{
+    /// Read a storage chunk, retaining the given rows.
+    ///
+    /// Row *i* of the storage is row `rows[i]` of the stored matrix.
+    /// Other rows are skipped without reading them. After reading,
+    /// the given reader is positioned at the end of the chunk.
+    fn read_chunk_rows<R>(read: &mut R, rows: &[usize]) -> Result<Self>
+    where
+        R: Read + Seek;
+}
+
+/// Chunks that are read from a file on demand.
+pub trait PreadChunk
+where
+    Self: Sized,
+{
+    /// Open a chunk for positioned reads.
+    ///
+    /// The given `File` object should be positioned at the start of the chunk.
+    fn pread_chunk(read: &mut BufReader<File>) -> Result<Self>;
+}
+
+pub trait WriteChunk {
+    /// Get the identifier of a chunk.
+    fn chunk_identifier(&self) -> ChunkIdentifier;
+
+    fn write_chunk<W>(&self, write: &mut W) -> Result<()>
+    where
+        W: Write + Seek;
+
+    /// Get the features that are required to read the chunk.
+    ///
+    /// These features are added to the header of files that contain
+    /// the chunk, so that readers without support for them reject the
+    /// file.
+    fn required_features(&self) -> Features {
+        Features::empty()
+    }
+
+    /// Get the length of the chunk in bytes.
+    ///
+    /// The length depends on the padding of the chunk data, so the
+    /// length is computed for a chunk that starts at `offset`. The
+    /// chunk is serialized to compute its length, but the serialized
+    /// data is not stored.
+    fn chunk_len(&self, offset: u64) -> Result<u64> {
+        let mut counter = LengthCounter::new(offset);
+        self.write_chunk(&mut counter)?;
+        Ok(counter.end - offset)
+    }
+}
+
+/// Storage chunks of which a subset of the rows can be written.
+pub trait WriteChunkRows: WriteChunk {
+    /// Write a storage chunk, retaining the given rows.
+    ///
+    /// Row *i* of the written chunk is row `rows[i]` of the storage.
+    /// The chunk has the identifier and required features of the
+    /// storage. Panics when a row is out of bounds.
+    fn write_chunk_rows<W>(&self, rows: &[usize], write: &mut W) -> Result<()>
+    where
+        W: Write + Seek;
+}
+
+/// Writer that computes the length of the written data.
+///
+/// The written data is discarded. Seeking is supported, so that
+/// chunks that fill in data after writing can be measured.
+pub(crate) struct LengthCounter {
+    pos: u64,
+    end: u64,
+}
+
+impl LengthCounter {
+    /// Construct a counter that starts at stream position `offset`.
+    pub fn new(offset: u64) -> Self {
+        LengthCounter {
+            pos: offset,
+            end: offset,
+        }
+    }
+
+    /// Get the stream position after the written data.
+    pub fn end(&self) -> u64 {
+        self.end
+    }
+}
+
+impl Write for LengthCounter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pos += buf.len() as u64;
+        self.end = self.end.max(self.pos);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for LengthCounter {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(pos) => (pos, 0),
+            SeekFrom::Current(offset) => (self.pos, offset),
+            SeekFrom::End(offset) => (self.end, offset),
+        };
+
+        self.pos = checked_seek_offset(base, offset)?;
+        Ok(self.pos)
+    }
+}
+
+/// Writer that tracks the stream position of a non-seekable writer.
+///
+/// Chunks use the stream position to compute the padding of their
+/// data. This writer provides the stream position of writers that do
+/// not implement `Seek`. Seeking is only supported to the current
+/// position.
+pub(crate) struct PositionWriter<W> {
+    inner: W,
+    pos: u64,
+}
+
+impl<W> PositionWriter<W>
+where
+    W: Write,
+{
+    pub fn new(inner: W) -> Self {
+        PositionWriter { inner, pos: 0 }
+    }
+}
+
+impl<W> Write for PositionWriter<W>
+where
+    W: Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W> Seek for PositionWriter<W>
+where
+    W: Write,
+{
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(pos) => pos,
+            SeekFrom::Current(offset) | SeekFrom::End(offset) => {
+                checked_seek_offset(self.pos, offset)?
+            }
+        };
+
+        if target != self.pos {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Cannot seek in a non-seekable stream",
+            ));
+        }
+
+        Ok(self.pos)
+    }
+}
+
+fn checked_seek_offset(base: u64, offset: i64) -> io::Result<u64> {
+    if offset >= 0 {
+        base.checked_add(offset as u64)
+    } else {
+        base.checked_sub(offset.unsigned_abs())
+    }
+    .ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Invalid seek to a negative or overflowing position",
+        )
+    })
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct Header {
+    chunk_identifiers: Vec<ChunkIdentifier>,
+    unknown_identifiers: Vec<u32>,
+    custom_identifiers: Vec<u32>,
+    features: Features,
+}
+
+impl Header {
+    /// Construct a header for the given chunks.
+    ///
+    /// The required features are derived from the chunks.
+    pub fn new(chunk_identifiers: impl Into<Vec<ChunkIdentifier>>) -> Self {
+        let chunk_identifiers = chunk_identifiers.into();
+        let features = Features::for_chunks(&chunk_identifiers);
+        Header {
+            chunk_identifiers,
+            unknown_identifiers: Vec::new(),
+            custom_identifiers: Vec::new(),
+            features,
+        }
+    }
+
+    /// Add unknown chunks to the header.
+    ///
+    /// Unknown chunks follow the chunks of this crate. Unknown chunks
+    /// with custom chunk identifiers are added as custom chunks.
+    pub fn with_unknown_identifiers(mut self, identifiers: impl IntoIterator<Item = u32>) -> Self {
+        for identifier in identifiers {
+            if identifier >= CUSTOM_CHUNK_IDENTIFIER_START {
+                self.custom_identifiers.push(identifier);
+            } else {
+                self.unknown_identifiers.push(identifier);
+                self.features = self.features | Features::EXTENDED_CHUNKS;
+            }
+        }
+
+        self
+    }
+
+    /// Get the identifiers of the chunks that are unknown to this crate.
+    ///
+    /// Custom chunks are not included.
+    pub fn unknown_identifiers(&self) -> &[u32] {
+        &self.unknown_identifiers
+    }
+
+    /// Add required features to the header.
+    pub fn with_features(mut self, features: Features) -> Self {
+        self.features = self.features | features;
+        self
+    }
+
+    /// Add custom chunks to the header.
+    ///
+    /// Custom chunks follow the chunks of this crate.
+    pub fn with_custom_identifiers(
+        mut self,
+        custom_identifiers: impl IntoIterator<Item = u32>,
+    ) -> Self {
+        self.custom_identifiers.extend(custom_identifiers);
+        self
+    }
+
+    /// Add a metadata chunk to the header.
+    ///
+    /// The metadata chunk is always the first chunk, the header is
+    /// unchanged when it already contains a metadata chunk.
+    pub fn with_metadata(mut self) -> Self {
+        if self.chunk_identifiers.first() != Some(&ChunkIdentifier::Metadata) {
+            self.chunk_identifiers.insert(0, ChunkIdentifier::Metadata);
+        }
+
+        self
+    }
+
+    pub fn chunk_identifiers(&self) -> &[ChunkIdentifier] {
+        &self.chunk_identifiers
+    }
+
+    /// Get the identifiers of the custom chunks.
+    pub fn custom_identifiers(&self) -> &[u32] {
+        &self.custom_identifiers
+    }
+
+    /// Read a header, ensuring that its custom chunks are registered.
+    pub fn read_chunk_with_registry<R>(read: &mut R, registry: &ChunkRegistry) -> Result<Self>
+    where
+        R: Read + Seek,
+    {
+        Self::read_header(read, Some(registry))
+    }
+
+    fn read_header<R>(read: &mut R, registry: Option<&ChunkRegistry>) -> Result<Self>
+    where
+        R: Read + Seek,
+    {
+        // Magic and version ceremony.
+        let mut magic = [0u8; 4];
+        read.read_exact(&mut magic)
+            .map_err(|e| ErrorKind::io_error("Cannot read magic", e))?;
+
+        if magic != MAGIC {
+            return Err(ErrorKind::Format(format!(
+                "Expected 'FiFu' as magic, got: {}",
+                String::from_utf8_lossy(&magic).into_owned()
+            ))
+            .into());
+        }
+
+        let version = read
+            .read_u32::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read model version", e))?;
+        let features = match version {
+            MODEL_VERSION_WITHOUT_FEATURES => Features::empty(),
+            MODEL_VERSION => Features::from_bits(
+                read.read_u64::<LittleEndian>()
+                    .map_err(|e| ErrorKind::io_error("Cannot read required features", e))?,
+            ),
+            version => {
+                return Err(ErrorKind::Format(format!(
+                    "Unknown finalfusion version: {}, the latest supported version is {}",
+                    version, MODEL_VERSION
+                ))
+                .into())
+            }
+        };
+
+        let unsupported = features.difference(Features::SUPPORTED);
+        if !unsupported.is_empty() {
+            return Err(ErrorKind::Format(format!(
+                "The file requires features that are not supported by this version of \
+                 finalfusion: {}",
+                unsupported
+            ))
+            .into());
+        }
+
+        // Read chunk identifiers.
+        let chunk_identifiers_len = read
+            .read_u32::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read chunk identifiers length", e))?
+            as usize;
+        let mut chunk_identifiers = Vec::with_capacity(chunk_identifiers_len);
+        let mut unknown_identifiers = Vec::new();
+        let mut custom_identifiers = Vec::new();
+        for _ in 0..chunk_identifiers_len {
+            let identifier = read
+                .read_u32::<LittleEndian>()
+                .map_err(|e| ErrorKind::io_error("Cannot read chunk identifier", e))?;
+
+            // Readers without a registry skip custom chunks, since
+            // they follow the chunks of this crate.
+            if identifier >= CUSTOM_CHUNK_IDENTIFIER_START {
+                if let Some(registry) = registry {
+                    registry.handler(identifier)?;
+                }
+                custom_identifiers.push(identifier);
+                continue;
+            }
+
+            if !custom_identifiers.is_empty() {
+                return Err(ErrorKind::Format(format!(
+                    "Chunk {} follows custom chunks",
+                    ChunkIdentifier::try_from(identifier)
+                        .map(|identifier| identifier.to_string())
+                        .unwrap_or_else(|| identifier.to_string())
+                ))
+                .into());
+            }
+
+            // Unknown chunks are retained when they follow the known
+            // chunks, since they are not needed to read the known chunks.
+            let chunk_identifier = match ChunkIdentifier::try_from(identifier) {
+                Some(chunk_identifier) => chunk_identifier,
+                None => {
+                    unknown_identifiers.push(identifier);
+                    continue;
+                }
+            };
+            if let Some(unknown) = unknown_identifiers.first() {
+                return Err(ErrorKind::Format(format!(
+                    "Chunk {} follows unknown chunk {}, the file may require a newer \
+                     version of finalfusion",
+                    chunk_identifier, unknown
+                ))
+                .into());
+            }
+            chunk_identifiers.push(chunk_identifier);
+        }
+
+        Ok(Header {
+            chunk_identifiers,
+            unknown_identifiers,
+            custom_identifiers,
+            features,
+        })
+    }
+}
+
+impl WriteChunk for Header {
+    fn chunk_identifier(&self) -> ChunkIdentifier {
+        ChunkIdentifier::Header
+    }
+
+    fn write_chunk<W>(&self, write: &mut W) -> Result<()>
+    where
+        W: Write + Seek,
+    {
+        write
+            .write_all(&MAGIC)
+            .map_err(|e| ErrorKind::io_error("Cannot write magic", e))?;
+        if self.features.is_empty() {
+            write
+                .write_u32::<LittleEndian>(MODEL_VERSION_WITHOUT_FEATURES)
+                .map_err(|e| ErrorKind::io_error("Cannot write model version", e))?;
+        } else {
+            write
+                .write_u32::<LittleEndian>(MODEL_VERSION)
+                .map_err(|e| ErrorKind::io_error("Cannot write model version", e))?;
+            write
+                .write_u64::<LittleEndian>(self.features.bits())
+                .map_err(|e| ErrorKind::io_error("Cannot write required features", e))?;
+        }
+        write
+            .write_u32::<LittleEndian>(
+                (self.chunk_identifiers.len()
+                    + self.unknown_identifiers.len()
+                    + self.custom_identifiers.len()) as u32,
+            )
+            .map_err(|e| ErrorKind::io_error("Cannot write chunk identifiers length", e))?;
+
+        let identifiers = self
+            .chunk_identifiers
+            .iter()
+            .map(|&identifier| identifier as u32)
+            .chain(self.unknown_identifiers.iter().copied())
+            .chain(self.custom_identifiers.iter().copied());
+        for identifier in identifiers {
+            write
+                .write_u32::<LittleEndian>(identifier)
+                .map_err(|e| ErrorKind::io_error("Cannot write chunk identifier", e))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl ReadChunk for Header {
+    fn read_chunk<R>(read: &mut R) -> Result<Self>
+    where
+        R: Read + Seek,
+    {
+        Self::read_header(read, None)
+    }
+}
+
+/// First chunk identifier that can be used for custom chunks.
+///
+/// Identifiers below this value are reserved for the chunks of the
+/// finalfusion format.
+pub const CUSTOM_CHUNK_IDENTIFIER_START: u32 = 1 << 31;
+
+type ReadHandler = Box<dyn Fn(&[u8]) -> Result<Box<dyn any::Any + Send + Sync>> + Send + Sync>;
+
+type WriteHandler =
+    Box<dyn Fn(&(dyn any::Any + Send + Sync), &mut Vec<u8>) -> Result<()> + Send + Sync>;
+
+struct ChunkHandler {
+    name: String,
+    read: ReadHandler,
+    write: WriteHandler,
+}
+
+/// Registry of custom chunk types.
+///
+/// The finalfusion format can be extended with custom chunks, such as
+/// domain-specific metadata or indexes. A custom chunk type is
+/// registered with an identifier, a name, and handlers that read and
+/// write the chunk data. Embeddings with registered custom chunks can
+/// then be read with `ReadEmbeddingsWithRegistry` and written with
+/// `WriteEmbeddingsWithChunks`.
+///
+/// Custom chunks are stored like other chunks: the chunk identifier,
+/// the length of the chunk data as `u64`, and the chunk data. The
+/// handlers only read and write the chunk data. Custom chunks follow
+/// the chunks of the finalfusion format.
+///
+/// ```
+/// use finalfusion_io::io::{CUSTOM_CHUNK_IDENTIFIER_START, ChunkRegistry, CustomChunk};
+///
+/// let mut registry = ChunkRegistry::new();
+/// registry.register(
+///     CUSTOM_CHUNK_IDENTIFIER_START,
+///     "Comment",
+///     |data| Ok(String::from_utf8_lossy(data).into_owned()),
+///     |comment: &String, data| {
+///         data.extend_from_slice(comment.as_bytes());
+///         Ok(())
+///     },
+/// );
+///
+/// let chunk = CustomChunk::new(CUSTOM_CHUNK_IDENTIFIER_START, "trained on news".to_string());
+/// assert_eq!(chunk.downcast_ref::<String>().unwrap(), "trained on news");
+/// ```
+#[derive(Default)]
+pub struct ChunkRegistry {
+    handlers: HashMap<u32, ChunkHandler>,
+}
+
+impl ChunkRegistry {
+    /// Construct an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a custom chunk type.
+    ///
+    /// Chunks with the given `identifier` are read with `read`, which
+    /// receives the chunk data, and written with `write`, which
+    /// appends the chunk data to the given buffer. The values of the
+    /// chunks have type `T`.
+    ///
+    /// Panics when `identifier` is smaller than
+    /// `CUSTOM_CHUNK_IDENTIFIER_START` or is already registered.
+    pub fn register<T, R, W>(&mut self, identifier: u32, name: impl Into<String>, read: R, write: W)
+    where
+        T: any::Any + Send + Sync,
+        R: Fn(&[u8]) -> Result<T> + Send + Sync + 'static,
+        W: Fn(&T, &mut Vec<u8>) -> Result<()> + Send + Sync + 'static,
+    {
+        assert!(
+            identifier >= CUSTOM_CHUNK_IDENTIFIER_START,
+            "Custom chunk identifiers start at {}, got: {}",
+            CUSTOM_CHUNK_IDENTIFIER_START,
+            identifier
+        );
+        assert!(
+            !self.handlers.contains_key(&identifier),
+            "Chunk identifier {} is already registered",
+            identifier
+        );
+
+        let name = name.into();
+        let write_name = name.clone();
+        self.handlers.insert(
+            identifier,
+            ChunkHandler {
+                name,
+                read: Box::new(move |data| {
+                    read(data).map(|value| Box::new(value) as Box<dyn any::Any + Send + Sync>)
+                }),
+                write: Box::new(move |value, data| {
+                    let value = value.downcast_ref::<T>().ok_or_else(|| {
+                        ErrorKind::Format(format!(
+                            "Value has the wrong type for chunk {}",
+                            write_name
+                        ))
+                    })?;
+                    write(value, data)
+                }),
+            },
+        );
+    }
+
+    /// Check whether a chunk identifier is registered.
+    pub fn contains(&self, identifier: u32) -> bool {
+        self.handlers.contains_key(&identifier)
+    }
+
+    /// Get the name of a registered chunk type.
+    pub fn name(&self, identifier: u32) -> Option<&str> {
+        self.handlers
+            .get(&identifier)
+            .map(|handler| handler.name.as_str())
+    }
+
+    fn handler(&self, identifier: u32) -> Result<&ChunkHandler> {
+        self.handlers.get(&identifier).ok_or_else(|| {
+            ErrorKind::Format(format!("Unregistered chunk identifier: {}", identifier)).into()
+        })
+    }
+
+    /// Read a custom chunk.
+    pub fn read_chunk<R>(&self, read: &mut R) -> Result<CustomChunk>
+    where
+        R: Read,
+    {
+        let identifier = read
+            .read_u32::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read chunk identifier", e))?;
+        let handler = self.handler(identifier)?;
+
+        let chunk_len = read
+            .read_u64::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read custom chunk length", e))?;
+        let mut data = Vec::new();
+        read.take(chunk_len)
+            .read_to_end(&mut data)
+            .map_err(|e| ErrorKind::io_error("Cannot read custom chunk data", e))?;
+        if (data.len() as u64) < chunk_len {
+            return Err(ErrorKind::Format(format!(
+                "Custom chunk {} is truncated, expected {} bytes, got: {}",
+                handler.name,
+                chunk_len,
+                data.len()
+            ))
+            .into());
+        }
+
+        Ok(CustomChunk {
+            identifier,
+            value: (handler.read)(&data)?,
+        })
+    }
+
+    /// Write a custom chunk.
+    pub fn write_chunk<W>(&self, chunk: &CustomChunk, write: &mut W) -> Result<()>
+    where
+        W: Write,
+    {
+        let handler = self.handler(chunk.identifier)?;
+        let mut data = Vec::new();
+        (handler.write)(chunk.value.as_ref(), &mut data)?;
+
+        write
+            .write_u32::<LittleEndian>(chunk.identifier)
+            .map_err(|e| ErrorKind::io_error("Cannot write chunk identifier", e))?;
+        write
+            .write_u64::<LittleEndian>(data.len() as u64)
+            .map_err(|e| ErrorKind::io_error("Cannot write custom chunk length", e))?;
+        write
+            .write_all(&data)
+            .map_err(|e| ErrorKind::io_error("Cannot write custom chunk data", e))
+            .map_err(Error::from)
+    }
+}
+
+impl fmt::Debug for ChunkRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(
+                self.handlers
+                    .iter()
+                    .map(|(identifier, handler)| (identifier, &handler.name)),
+            )
+            .finish()
+    }
+}
+
+/// Custom chunk.
+///
+/// A custom chunk holds a value of the type that is registered for
+/// its identifier in a `ChunkRegistry`.
+pub struct CustomChunk {
+    identifier: u32,
+    value: Box<dyn any::Any + Send + Sync>,
+}
+
+impl CustomChunk {
+    /// Construct a custom chunk.
+    pub fn new<T>(identifier: u32, value: T) -> Self
+    where
+        T: any::Any + Send + Sync,
+    {
+        CustomChunk {
+            identifier,
+            value: Box::new(value),
+        }
+    }
+
+    /// Get the chunk identifier.
+    pub fn identifier(&self) -> u32 {
+        self.identifier
+    }
+
+    /// Get the value of the chunk.
+    ///
+    /// Returns `None` when the value is not of type `T`.
+    pub fn downcast_ref<T>(&self) -> Option<&T>
+    where
+        T: any::Any,
+    {
+        self.value.downcast_ref()
+    }
+}
+
+impl fmt::Debug for CustomChunk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CustomChunk")
+            .field("identifier", &self.identifier)
+            .finish()
+    }
+}
+
+/// Alignment of unknown chunk data that is preserved when writing.
+///
+/// Chunk data is padded using its absolute position in the file. This
+/// is the largest alignment of the data types of the format.
+const UNKNOWN_CHUNK_ALIGNMENT: u64 = 8;
+
+/// Read an unknown chunk with the given identifier.
+pub(crate) fn read_unknown_chunk<R>(read: &mut R, identifier: u32) -> Result<UnknownChunk>
+where
+    R: Read + Seek,
+{
+    let chunk_id = read
+        .read_u32::<LittleEndian>()
+        .map_err(|e| ErrorKind::io_error("Cannot read chunk identifier", e))?;
+    if chunk_id != identifier {
+        return Err(ErrorKind::Format(format!(
+            "Invalid chunk identifier, expected: {}, got: {}",
+            identifier, chunk_id
+        ))
+        .into());
+    }
+
+    let chunk_len = read
+        .read_u64::<LittleEndian>()
+        .map_err(|e| ErrorKind::io_error("Cannot read unknown chunk length", e))?;
+    let data_pos = read
+        .stream_position()
+        .map_err(|e| ErrorKind::io_error("Cannot get unknown chunk data position", e))?;
+
+    let mut data = Vec::new();
+    read.take(chunk_len)
+        .read_to_end(&mut data)
+        .map_err(|e| ErrorKind::io_error("Cannot read unknown chunk data", e))?;
+    if (data.len() as u64) < chunk_len {
+        return Err(ErrorKind::Format(format!(
+            "Unknown chunk {} is truncated, expected {} bytes, got: {}",
+            identifier,
+            chunk_len,
+            data.len()
+        ))
+        .into());
+    }
+
+    Ok(UnknownChunk::from_parts(
+        identifier,
+        data,
+        data_pos % UNKNOWN_CHUNK_ALIGNMENT,
+    ))
+}
+
+/// Write the unknown chunk.
+///
+/// Since the data of the chunk may be padded, writing fails when
+/// the data would not have the same alignment as in the file that
+/// the chunk was read from.
+pub(crate) fn write_unknown_chunk<W>(chunk: &UnknownChunk, write: &mut W) -> Result<()>
+where
+    W: Write + Seek,
+{
+    let data_pos = write
+        .stream_position()
+        .map_err(|e| ErrorKind::io_error("Cannot get unknown chunk position", e))?
+        + 12;
+    if data_pos % UNKNOWN_CHUNK_ALIGNMENT != chunk.data_alignment() {
+        return Err(ErrorKind::Format(format!(
+            "Cannot write unknown chunk {} with a different alignment than it was read with",
+            chunk.identifier()
+        ))
+        .into());
+    }
+
+    write
+        .write_u32::<LittleEndian>(chunk.identifier())
+        .map_err(|e| ErrorKind::io_error("Cannot write chunk identifier", e))?;
+    write
+        .write_u64::<LittleEndian>(chunk.data().len() as u64)
+        .map_err(|e| ErrorKind::io_error("Cannot write unknown chunk length", e))?;
+    write
+        .write_all(chunk.data())
+        .map_err(|e| ErrorKind::io_error("Cannot write unknown chunk data", e))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Seek, SeekFrom, Write};
+
+    use super::{
+        read_unknown_chunk, write_unknown_chunk, ChunkIdentifier, Features, Header, PositionWriter,
+        ReadChunk, WriteChunk,
+    };
+
+    #[test]
+    fn header_write_read_roundtrip() {
+        let check_header =
+            Header::new(vec![ChunkIdentifier::SimpleVocab, ChunkIdentifier::NdArray]);
+        let mut cursor = Cursor::new(Vec::new());
+        check_header.write_chunk(&mut cursor).unwrap();
+        cursor.seek(SeekFrom::Start(0)).unwrap();
+        let header = Header::read_chunk(&mut cursor).unwrap();
+        assert_eq!(header, check_header);
+    }
+
+    #[test]
+    fn header_without_features_is_version_0() {
+        let header = Header::new(vec![ChunkIdentifier::SimpleVocab, ChunkIdentifier::NdArray]);
+        let mut cursor = Cursor::new(Vec::new());
+        header.write_chunk(&mut cursor).unwrap();
+        assert_eq!(&cursor.get_ref()[4..8], &[0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn header_with_extended_chunks_roundtrip() {
+        let check_header = Header::new(vec![ChunkIdentifier::AliasVocab, ChunkIdentifier::NdArray]);
+        assert_eq!(check_header.features, Features::EXTENDED_CHUNKS);
+
+        let mut cursor = Cursor::new(Vec::new());
+        check_header.write_chunk(&mut cursor).unwrap();
+        assert_eq!(
+            &cursor.get_ref()[4..16],
+            &[1, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0]
+        );
+        cursor.seek(SeekFrom::Start(0)).unwrap();
+        let header = Header::read_chunk(&mut cursor).unwrap();
+        assert_eq!(header, check_header);
+    }
+
+    #[test]
+    fn header_with_unsupported_features_is_rejected() {
+        let header = Header::new(vec![ChunkIdentifier::SimpleVocab, ChunkIdentifier::NdArray])
+            .with_features(Features::CHECKSUMS | Features::from_bits(1 << 40));
+        let mut cursor = Cursor::new(Vec::new());
+        header.write_chunk(&mut cursor).unwrap();
+        cursor.seek(SeekFrom::Start(0)).unwrap();
+
+        let err = Header::read_chunk(&mut cursor).unwrap_err();
+        assert!(err
+            .to_string()
+            .ends_with("not supported by this version of finalfusion: checksums, unknown features 0x10000000000"));
+    }
+
+    #[test]
+    fn header_with_unknown_version_is_rejected() {
+        let mut data = b"FiFu".to_vec();
+        data.extend_from_slice(&[2, 0, 0, 0]);
+        let err = Header::read_chunk(&mut Cursor::new(data)).unwrap_err();
+        assert!(err.to_string().contains("latest supported version is 1"));
+    }
+
+    #[test]
+    fn header_rejects_chunks_after_unknown_chunks() {
+        let mut data = b"FiFu".to_vec();
+        for value in &[0u32, 2, 100, ChunkIdentifier::NdArray as u32] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        let err = Header::read_chunk(&mut Cursor::new(data)).unwrap_err();
+        assert!(err.to_string().contains("follows unknown chunk 100"));
+    }
+
+    #[test]
+    fn unknown_chunk_retains_alignment() {
+        let mut data = vec![100, 0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0];
+        data.extend_from_slice(b"abc");
+        let chunk = read_unknown_chunk(&mut Cursor::new(&data), 100).unwrap();
+        assert_eq!(chunk.data(), b"abc");
+
+        let mut cursor = Cursor::new(Vec::new());
+        write_unknown_chunk(&chunk, &mut cursor).unwrap();
+        assert_eq!(cursor.get_ref(), &data);
+
+        // The data would be moved to a different alignment.
+        assert!(write_unknown_chunk(&chunk, &mut cursor).is_err());
+
+        cursor.write_all(&[0]).unwrap();
+        write_unknown_chunk(&chunk, &mut cursor).unwrap();
+    }
+
+    #[test]
+    fn features_display() {
+        assert_eq!(Features::empty().to_string(), "none");
+        assert_eq!(
+            (Features::EXTENDED_CHUNKS | Features::INDEX_CHUNKS).to_string(),
+            "extended chunks, index chunks"
+        );
+    }
+
+    #[test]
+    fn chunk_len_is_written_length() {
+        let header = Header::new(vec![ChunkIdentifier::SimpleVocab, ChunkIdentifier::NdArray]);
+        let mut cursor = Cursor::new(vec![0; 3]);
+        cursor.seek(SeekFrom::End(0)).unwrap();
+        header.write_chunk(&mut cursor).unwrap();
+        assert_eq!(
+            header.chunk_len(3).unwrap(),
+            cursor.get_ref().len() as u64 - 3
+        );
+    }
+
+    #[test]
+    fn position_writer_tracks_position() {
+        let mut data = Vec::new();
+        let mut writer = PositionWriter::new(&mut data);
+        writer.write_all(b"FiFu").unwrap();
+        assert_eq!(writer.stream_position().unwrap(), 4);
+        assert_eq!(writer.seek(SeekFrom::Start(4)).unwrap(), 4);
+        assert!(writer.seek(SeekFrom::Start(0)).is_err());
+        assert!(writer.seek(SeekFrom::Current(1)).is_err());
+        assert_eq!(data, b"FiFu");
+    }
+}
@@ -0,0 +1,314 @@
+//! Metadata chunks
+
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use finalfusion_core::metadata::Metadata;
+use toml::Value;
+
+use crate::chunks::io::{ChunkIdentifier, Header, ReadChunk, WriteChunk};
+use crate::io::{Error, ErrorKind, ReadMetadata, Result, WriteMetadataInPlace};
+
+/// Granularity with which metadata is grown in place.
+///
+/// Moving chunks by a multiple of this size retains the alignment of
+/// their data. The remainder is left as padding for later edits.
+const METADATA_GROWTH: u64 = 4096;
+
+/// Size of the blocks in which chunks are moved.
+const MOVE_BLOCK_SIZE: u64 = 1 << 16;
+
+impl ReadChunk for Metadata {
+    fn read_chunk<R>(read: &mut R) -> Result<Self>
+    where
+        R: Read + Seek,
+    {
+        ChunkIdentifier::ensure_chunk_type(read, ChunkIdentifier::Metadata)?;
+
+        // Read chunk length.
+        let chunk_len = read
+            .read_u64::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read chunk length", e))?
+            as usize;
+
+        // Read TOML data.
+        let mut buf = vec![0; chunk_len];
+        read.read_exact(&mut buf)
+            .map_err(|e| ErrorKind::io_error("Cannot read TOML metadata", e))?;
+        let buf_str = String::from_utf8(buf)
+            .map_err(|e| ErrorKind::Format(format!("TOML metadata contains invalid UTF-8: {}", e)))
+            .map_err(Error::from)?;
+
+        Ok(Metadata::new(
+            buf_str
+                .parse::<Value>()
+                .map_err(|e| ErrorKind::Format(format!("Cannot deserialize TOML metadata: {}", e)))
+                .map_err(Error::from)?,
+        ))
+    }
+}
+
+impl WriteChunk for Metadata {
+    fn chunk_identifier(&self) -> ChunkIdentifier {
+        ChunkIdentifier::Metadata
+    }
+
+    fn write_chunk<W>(&self, write: &mut W) -> Result<()>
+    where
+        W: Write + Seek,
+    {
+        write_metadata_chunk(write, &self.to_string())
+    }
+}
+
+fn write_metadata_chunk<W>(write: &mut W, metadata_str: &str) -> Result<()>
+where
+    W: Write,
+{
+    write
+        .write_u32::<LittleEndian>(ChunkIdentifier::Metadata as u32)
+        .map_err(|e| ErrorKind::io_error("Cannot write metadata chunk identifier", e))?;
+    write
+        .write_u64::<LittleEndian>(metadata_str.len() as u64)
+        .map_err(|e| ErrorKind::io_error("Cannot write metadata length", e))?;
+    write
+        .write_all(metadata_str.as_bytes())
+        .map_err(|e| ErrorKind::io_error("Cannot write metadata", e))?;
+
+    Ok(())
+}
+
+impl ReadMetadata for Option<Metadata> {
+    fn read_metadata<R>(read: &mut R) -> Result<Self>
+    where
+        R: Read + Seek,
+    {
+        let header = Header::read_chunk(read)?;
+        let chunks = header.chunk_identifiers();
+
+        if chunks.is_empty() {
+            return Err(
+                ErrorKind::Format(String::from("Embedding file does not contain chunks")).into(),
+            );
+        }
+
+        if header.chunk_identifiers()[0] == ChunkIdentifier::Metadata {
+            Ok(Some(Metadata::read_chunk(read)?))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl WriteMetadataInPlace for Metadata {
+    fn write_metadata_in_place<F>(&self, file: &mut F) -> Result<()>
+    where
+        F: Read + Write + Seek,
+    {
+        file.seek(SeekFrom::Start(0))
+            .map_err(|e| ErrorKind::io_error("Cannot seek to header", e))?;
+        let header = Header::read_chunk(file)?;
+        if header.chunk_identifiers().is_empty() {
+            return Err(
+                ErrorKind::Format(String::from("Embedding file does not contain chunks")).into(),
+            );
+        }
+
+        // Find the end of the old metadata, the chunks that follow it
+        // are retained as-is.
+        let mut old_end = file
+            .stream_position()
+            .map_err(|e| ErrorKind::io_error("Cannot get end of header", e))?;
+        if header.chunk_identifiers()[0] == ChunkIdentifier::Metadata {
+            ChunkIdentifier::ensure_chunk_type(file, ChunkIdentifier::Metadata)?;
+            let chunk_len = file
+                .read_u64::<LittleEndian>()
+                .map_err(|e| ErrorKind::io_error("Cannot read chunk length", e))?;
+            old_end += 12 + chunk_len;
+        }
+
+        let mut prefix = Cursor::new(Vec::new());
+        header.with_metadata().write_chunk(&mut prefix)?;
+        let mut metadata_str = self.to_string();
+        let new_len = prefix.get_ref().len() as u64 + 12 + metadata_str.len() as u64;
+
+        // The metadata is padded with trailing newlines, which are
+        // ignored by TOML parsers.
+        let shift = if new_len <= old_end {
+            0
+        } else {
+            let growth = new_len - old_end;
+            growth.div_ceil(METADATA_GROWTH) * METADATA_GROWTH
+        };
+        let padding = old_end + shift - new_len;
+        metadata_str.extend((0..padding).map(|_| '\n'));
+        write_metadata_chunk(&mut prefix, &metadata_str)?;
+
+        if shift != 0 {
+            move_to_end(file, old_end, shift)?;
+        }
+
+        file.seek(SeekFrom::Start(0))
+            .map_err(|e| ErrorKind::io_error("Cannot seek to header", e))?;
+        file.write_all(prefix.get_ref())
+            .map_err(|e| ErrorKind::io_error("Cannot write metadata", e))?;
+        file.flush()
+            .map_err(|e| ErrorKind::io_error("Cannot flush metadata", e))?;
+
+        Ok(())
+    }
+}
+
+/// Move the data from `start` to the end of the file by `shift` bytes.
+///
+/// The data is copied back to front, so that data is not overwritten
+/// before it is moved.
+fn move_to_end<F>(file: &mut F, start: u64, shift: u64) -> Result<()>
+where
+    F: Read + Write + Seek,
+{
+    let mut end = file
+        .seek(SeekFrom::End(0))
+        .map_err(|e| ErrorKind::io_error("Cannot seek to end of file", e))?;
+
+    let mut buf = vec![0; MOVE_BLOCK_SIZE.min(end.saturating_sub(start)) as usize];
+    while end > start {
+        let block_len = MOVE_BLOCK_SIZE.min(end - start);
+        let block_start = end - block_len;
+        let block = &mut buf[..block_len as usize];
+
+        file.seek(SeekFrom::Start(block_start))
+            .map_err(|e| ErrorKind::io_error("Cannot seek to chunk data", e))?;
+        file.read_exact(block)
+            .map_err(|e| ErrorKind::io_error("Cannot read chunk data", e))?;
+        file.seek(SeekFrom::Start(block_start + shift))
+            .map_err(|e| ErrorKind::io_error("Cannot seek to chunk data", e))?;
+        file.write_all(block)
+            .map_err(|e| ErrorKind::io_error("Cannot write chunk data", e))?;
+
+        end = block_start;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::{Cursor, Read, Seek, SeekFrom};
+
+    use byteorder::{LittleEndian, ReadBytesExt};
+    use toml::{toml, Value};
+
+    use crate::chunks::io::{ReadChunk, WriteChunk};
+    use crate::io::{ReadEmbeddings, WriteMetadataInPlace};
+    use finalfusion_core::embeddings::Embeddings;
+    use finalfusion_core::metadata::Metadata;
+    use finalfusion_core::storage::{NdArray, StorageView};
+    use finalfusion_core::vocab::SimpleVocab;
+
+    fn read_chunk_size(read: &mut impl Read) -> u64 {
+        // Skip identifier.
+        read.read_u32::<LittleEndian>().unwrap();
+
+        // Return chunk length.
+        read.read_u64::<LittleEndian>().unwrap()
+    }
+
+    fn test_metadata() -> Metadata {
+        Metadata::new(toml! {
+            [hyperparameters]
+            dims = 300
+            ns = 5
+
+            [description]
+            description = "Test model"
+            language = "de"
+        })
+    }
+
+    #[test]
+    fn metadata_correct_chunk_size() {
+        let check_metadata = test_metadata();
+        let mut cursor = Cursor::new(Vec::new());
+        check_metadata.write_chunk(&mut cursor).unwrap();
+        cursor.seek(SeekFrom::Start(0)).unwrap();
+
+        let chunk_size = read_chunk_size(&mut cursor);
+        assert_eq!(
+            cursor.read_to_end(&mut Vec::new()).unwrap(),
+            chunk_size as usize
+        );
+    }
+
+    fn read_embeddings(data: &[u8]) -> Embeddings<SimpleVocab, NdArray> {
+        Embeddings::read_embeddings(&mut Cursor::new(data)).unwrap()
+    }
+
+    fn assert_same_embeddings(data: &[u8], check_data: &[u8], metadata: &Metadata) {
+        let embeddings = read_embeddings(data);
+        let check_embeddings = read_embeddings(check_data);
+        assert_eq!(embeddings.metadata(), Some(metadata));
+        assert_eq!(embeddings.vocab(), check_embeddings.vocab());
+        assert_eq!(
+            embeddings.storage().view(),
+            check_embeddings.storage().view()
+        );
+        assert_eq!(
+            embeddings.norms().map(|norms| norms.to_vec()),
+            check_embeddings.norms().map(|norms| norms.to_vec())
+        );
+    }
+
+    #[test]
+    fn metadata_in_place_insert_and_replace() {
+        let check_data = fs::read("../testdata/similarity.fifu").unwrap();
+        assert!(read_embeddings(&check_data).metadata().is_none());
+
+        // Insert metadata in a file without metadata.
+        let mut cursor = Cursor::new(check_data.clone());
+        let metadata = test_metadata();
+        metadata.write_metadata_in_place(&mut cursor).unwrap();
+        let inserted_len = cursor.get_ref().len();
+        assert_eq!((inserted_len - check_data.len()) % 4096, 0);
+        assert_same_embeddings(cursor.get_ref(), &check_data, &metadata);
+
+        // Smaller metadata is padded to the size of the old metadata.
+        let metadata = Metadata::new(toml! {
+            language = "nl"
+        });
+        metadata.write_metadata_in_place(&mut cursor).unwrap();
+        assert_eq!(cursor.get_ref().len(), inserted_len);
+        assert_same_embeddings(cursor.get_ref(), &check_data, &metadata);
+
+        // Larger metadata moves the chunks that follow the metadata.
+        let mut metadata = test_metadata();
+        metadata
+            .as_table_mut()
+            .unwrap()
+            .insert("notes".to_string(), Value::String("x".repeat(10_000)));
+        metadata.write_metadata_in_place(&mut cursor).unwrap();
+        assert!(cursor.get_ref().len() > inserted_len);
+        assert_eq!((cursor.get_ref().len() - check_data.len()) % 4096, 0);
+        assert_same_embeddings(cursor.get_ref(), &check_data, &metadata);
+    }
+
+    #[test]
+    fn metadata_in_place_rejects_invalid_file() {
+        let mut cursor = Cursor::new(b"FiFo\0\0\0\0".to_vec());
+        assert!(test_metadata()
+            .write_metadata_in_place(&mut cursor)
+            .is_err());
+        assert_eq!(cursor.get_ref(), b"FiFo\0\0\0\0");
+    }
+
+    #[test]
+    fn metadata_write_read_roundtrip() {
+        let check_metadata = test_metadata();
+        let mut cursor = Cursor::new(Vec::new());
+        check_metadata.write_chunk(&mut cursor).unwrap();
+        cursor.seek(SeekFrom::Start(0)).unwrap();
+        let metadata = Metadata::read_chunk(&mut cursor).unwrap();
+        assert_eq!(metadata, check_metadata);
+    }
+}
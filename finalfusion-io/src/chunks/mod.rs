@@ -0,0 +1,13 @@
+//! finalfusion chunks
+
+mod counts;
+
+pub(crate) mod io;
+
+mod metadata;
+
+mod norms;
+
+pub mod storage;
+
+mod vocab;
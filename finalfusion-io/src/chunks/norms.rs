@@ -2,52 +2,13 @@
 
 use std::io::{Read, Seek, SeekFrom, Write};
 use std::mem::size_of;
-use std::ops::Deref;
 
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
-use ndarray::Array1;
+use finalfusion_core::norms::NdNorms;
+use finalfusion_core::util::padding;
 
-use super::io::{ChunkIdentifier, ReadChunk, TypeId, WriteChunk};
+use crate::chunks::io::{ChunkIdentifier, ReadChunk, TypeId, WriteChunk};
 use crate::io::{ErrorKind, Result};
-use crate::util::padding;
-
-/// Chunk for storing embedding l2 norms.
-///
-/// Word embeddings are always l2-normalized in finalfusion. Sometimes
-/// it is useful to get the original unnormalized embeddings. The
-/// norms chunk is used for storing norms of in-vocabulary embeddings.
-/// The unnormalized embedding can be reconstructed by multiplying the
-/// normalized embedding by its orginal l2 norm.
-#[derive(Clone, Debug)]
-pub struct NdNorms {
-    inner: Array1<f32>,
-}
-
-impl NdNorms {
-    /// Construct new `NdNorms`.
-    pub fn new(norms: impl Into<Array1<f32>>) -> Self {
-        NdNorms {
-            inner: norms.into(),
-        }
-    }
-}
-
-impl Deref for NdNorms {
-    type Target = Array1<f32>;
-
-    fn deref(&self) -> &Self::Target {
-        &self.inner
-    }
-}
-
-impl<V> From<V> for NdNorms
-where
-    V: Into<Array1<f32>>,
-{
-    fn from(array: V) -> NdNorms {
-        NdNorms::new(array)
-    }
-}
 
 impl ReadChunk for NdNorms {
     fn read_chunk<R>(read: &mut R) -> Result<Self>
@@ -67,7 +28,7 @@ impl ReadChunk for NdNorms {
 
         f32::ensure_data_type(read)?;
 
-        let n_padding = padding::<f32>(read.seek(SeekFrom::Current(0)).map_err(|e| {
+        let n_padding = padding::<f32>(read.stream_position().map_err(|e| {
             ErrorKind::io_error("Cannot get file position for computing padding", e)
         })?);
         read.seek(SeekFrom::Current(n_padding as i64))
@@ -93,7 +54,7 @@ impl WriteChunk for NdNorms {
         write
             .write_u32::<LittleEndian>(ChunkIdentifier::NdNorms as u32)
             .map_err(|e| ErrorKind::io_error("Cannot write norms chunk identifier", e))?;
-        let n_padding = padding::<f32>(write.seek(SeekFrom::Current(0)).map_err(|e| {
+        let n_padding = padding::<f32>(write.stream_position().map_err(|e| {
             ErrorKind::io_error("Cannot get file position for computing padding", e)
         })?);
 
@@ -134,8 +95,8 @@ mod tests {
     use byteorder::{LittleEndian, ReadBytesExt};
     use ndarray::Array1;
 
-    use super::NdNorms;
     use crate::chunks::io::{ReadChunk, WriteChunk};
+    use finalfusion_core::norms::NdNorms;
 
     const LEN: usize = 100;
 
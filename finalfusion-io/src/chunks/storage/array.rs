@@ -0,0 +1,888 @@
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom, Write};
+use std::mem::size_of;
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use finalfusion_core::storage::{Element, MmapArray, NdArray, PreadArray, Storage};
+use finalfusion_core::util::{l2_normalize, padding};
+use memmap::MmapOptions;
+use ndarray::{s, Array1, Array2, ArrayView2, Dimension, Ix2};
+
+use crate::chunks::io::{
+    ChunkIdentifier, MmapChunk, PreadChunk, ReadChunk, ReadChunkRows, ReadChunkTruncated,
+    WriteChunk, WriteChunkRows,
+};
+use crate::io::{Error, ErrorKind, Result};
+
+/// Get the component type identifier of an embedding matrix chunk.
+///
+/// The position of the reader is restored after reading the
+/// identifier.
+pub(crate) fn ndarray_type_id<R>(read: &mut R) -> Result<u32>
+where
+    R: Read + Seek,
+{
+    let start = read
+        .stream_position()
+        .map_err(|e| ErrorKind::io_error("Cannot get embedding matrix chunk start position", e))?;
+
+    ChunkIdentifier::ensure_chunk_type(read, ChunkIdentifier::NdArray)?;
+
+    // Skip chunk length, number of rows, and number of columns.
+    read.seek(SeekFrom::Current(
+        (size_of::<u64>() + size_of::<u64>() + size_of::<u32>()) as i64,
+    ))
+    .map_err(|e| ErrorKind::io_error("Cannot skip embedding matrix shape", e))?;
+    let type_id = read
+        .read_u32::<LittleEndian>()
+        .map_err(|e| ErrorKind::io_error("Cannot read type identifier", e))?;
+
+    read.seek(SeekFrom::Start(start))
+        .map_err(|e| ErrorKind::io_error("Cannot seek to embedding matrix chunk start", e))?;
+
+    Ok(type_id)
+}
+
+/// Read the header of an embedding matrix chunk.
+///
+/// Returns the shape of the matrix. After reading the header, the
+/// reader is positioned at the start of the matrix data.
+fn read_ndarray_header<A, R>(read: &mut R) -> Result<Ix2>
+where
+    A: Element,
+    R: Read + Seek,
+{
+    ChunkIdentifier::ensure_chunk_type(read, ChunkIdentifier::NdArray)?;
+
+    // Read and discard chunk length.
+    read.read_u64::<LittleEndian>()
+        .map_err(|e| ErrorKind::io_error("Cannot read embedding matrix chunk length", e))?;
+
+    let rows = read
+        .read_u64::<LittleEndian>()
+        .map_err(|e| ErrorKind::io_error("Cannot read number of rows of the embedding matrix", e))?
+        as usize;
+    let cols = read.read_u32::<LittleEndian>().map_err(|e| {
+        ErrorKind::io_error("Cannot read number of columns of the embedding matrix", e)
+    })? as usize;
+
+    // The components of the embedding matrix should be of type A.
+    let type_id = read
+        .read_u32::<LittleEndian>()
+        .map_err(|e| ErrorKind::io_error("Cannot read type identifier", e))?;
+    if type_id != A::TYPE_ID {
+        return Err(ErrorKind::Format(format!(
+            "Invalid type, expected: {}, got: {}",
+            A::TYPE_ID,
+            type_id
+        ))
+        .into());
+    }
+
+    let n_padding =
+        padding::<A>(read.stream_position().map_err(|e| {
+            ErrorKind::io_error("Cannot get file position for computing padding", e)
+        })?);
+    read.seek(SeekFrom::Current(n_padding as i64))
+        .map_err(|e| ErrorKind::io_error("Cannot skip padding", e))?;
+
+    Ok(Ix2(rows, cols))
+}
+
+/// Write the header of an embedding matrix chunk.
+///
+/// After writing the header, `rows * cols` components of type `A`
+/// should be written in row-major order.
+fn write_ndarray_header<A, W>(rows: usize, cols: usize, write: &mut W) -> Result<()>
+where
+    A: Element,
+    W: Write + Seek,
+{
+    write
+        .write_u32::<LittleEndian>(ChunkIdentifier::NdArray as u32)
+        .map_err(|e| ErrorKind::io_error("Cannot write embedding matrix chunk identifier", e))?;
+    let n_padding =
+        padding::<A>(write.stream_position().map_err(|e| {
+            ErrorKind::io_error("Cannot get file position for computing padding", e)
+        })?);
+    // Chunk size: rows (u64), columns (u32), type id (u32),
+    //             padding ([0, size_of::<A>()) bytes), matrix.
+    let chunk_len = size_of::<u64>()
+        + size_of::<u32>()
+        + size_of::<u32>()
+        + n_padding as usize
+        + (rows * cols * size_of::<A>());
+    write
+        .write_u64::<LittleEndian>(chunk_len as u64)
+        .map_err(|e| ErrorKind::io_error("Cannot write embedding matrix chunk length", e))?;
+    write.write_u64::<LittleEndian>(rows as u64).map_err(|e| {
+        ErrorKind::io_error("Cannot write number of rows of the embedding matrix", e)
+    })?;
+    write.write_u32::<LittleEndian>(cols as u32).map_err(|e| {
+        ErrorKind::io_error("Cannot write number of columns of the embedding matrix", e)
+    })?;
+    write
+        .write_u32::<LittleEndian>(A::TYPE_ID)
+        .map_err(|e| ErrorKind::io_error("Cannot write embedding matrix type identifier", e))?;
+
+    // Write padding, such that the embedding matrix starts on at
+    // a multiple of the size of A (4 bytes for f32, 8 bytes for
+    // f64). This is necessary for memory mapping a matrix.
+    // Interpreting the raw u8 data as a proper f32 array requires
+    // that the data is aligned in memory. However, we cannot always
+    // memory map the starting offset of the matrix directly, since
+    // mmap(2) requires a file offset that is page-aligned. Since the
+    // page size is always a larger power of 2 (e.g. 2^12), which is
+    // divisible by the component size, the offset of the matrix with
+    // regards to the page boundary is also a multiple of the
+    // component size.
+
+    let padding = vec![0; n_padding as usize];
+    write
+        .write_all(&padding)
+        .map_err(|e| ErrorKind::io_error("Cannot write padding", e))?;
+
+    Ok(())
+}
+
+/// Get the number of dimensions to retain for a matrix of the given shape.
+///
+/// Returns an error if the embeddings cannot be truncated to `dims`
+/// dimensions. If `dims` is `None`, all dimensions are retained.
+fn truncated_dims(shape: Ix2, dims: Option<usize>) -> Result<usize> {
+    let cols = shape[1];
+    match dims {
+        Some(0) => Err(ErrorKind::Format(String::from(
+            "Cannot truncate embeddings to 0 dimensions",
+        ))
+        .into()),
+        Some(dims) if dims > cols => Err(ErrorKind::Format(format!(
+            "Cannot truncate embeddings to {} dimensions, the embedding matrix has {} dimensions",
+            dims, cols
+        ))
+        .into()),
+        Some(dims) => Ok(dims),
+        None => Ok(cols),
+    }
+}
+
+/// Check that the first `n_rows` rows of a matrix can be normalized.
+fn check_normalized_rows(n_rows: usize, rows: usize) -> Result<()> {
+    if n_rows > rows {
+        return Err(ErrorKind::Format(format!(
+            "Cannot normalize {} embeddings, the embedding matrix has {} rows",
+            n_rows, rows
+        ))
+        .into());
+    }
+
+    Ok(())
+}
+
+/// Block size used when writing a memory-mapped matrix.
+const MMAP_WRITE_BLOCK_SIZE: usize = 1 << 20;
+
+/// Memory map an embedding matrix chunk.
+///
+/// If `dims` is `Some`, only the first `dims` columns of the matrix
+/// are used.
+fn mmap_array<A>(read: &mut BufReader<File>, dims: Option<usize>) -> Result<MmapArray<A>>
+where
+    A: Element,
+{
+    let shape = read_ndarray_header::<A, _>(read)?;
+    let dims = truncated_dims(shape, dims)?;
+
+    // Set up memory mapping.
+    let matrix_len = shape.size() * size_of::<A>();
+    let offset = read.stream_position().map_err(|e| {
+        ErrorKind::io_error(
+            "Cannot get file position for memory mapping embedding matrix",
+            e,
+        )
+    })?;
+    let mut mmap_opts = MmapOptions::new();
+    let map = unsafe {
+        mmap_opts
+            .offset(offset)
+            .len(matrix_len)
+            .map(read.get_ref())
+            .map_err(|e| ErrorKind::io_error("Cannot memory map embedding matrix", e))?
+    };
+    let array = MmapArray::from_mmap(map, shape, dims)?;
+
+    // Position the reader after the matrix.
+    read.seek(SeekFrom::Current(matrix_len as i64))
+        .map_err(|e| ErrorKind::io_error("Cannot skip embedding matrix", e))?;
+
+    Ok(array)
+}
+
+/// Open an embedding matrix chunk for positioned reads.
+///
+/// If `dims` is `Some`, only the first `dims` columns of the matrix
+/// are used. Returns an error when the matrix does not have `f32`
+/// components or when the file is too short to contain the matrix.
+fn pread_array(read: &mut BufReader<File>, dims: Option<usize>) -> Result<PreadArray> {
+    let type_id = ndarray_type_id(read)?;
+    if type_id != f32::TYPE_ID {
+        return Err(ErrorKind::Format(format!(
+            "Positioned reads require an embedding matrix with f32 components (type {}), got type {}",
+            f32::TYPE_ID,
+            type_id
+        ))
+        .into());
+    }
+
+    let shape = read_ndarray_header::<f32, _>(read)?;
+    let dims = truncated_dims(shape, dims)?;
+
+    let matrix_len = shape.size() * size_of::<f32>();
+    let offset = read
+        .stream_position()
+        .map_err(|e| ErrorKind::io_error("Cannot get file position of embedding matrix", e))?;
+    let file_len = read
+        .get_ref()
+        .metadata()
+        .map_err(|e| ErrorKind::io_error("Cannot get length of embedding matrix file", e))?
+        .len();
+    if offset + matrix_len as u64 > file_len {
+        return Err(ErrorKind::Format(format!(
+            "Embedding matrix is truncated, expected {} bytes, got {}",
+            matrix_len,
+            file_len.saturating_sub(offset)
+        ))
+        .into());
+    }
+    let file = read
+        .get_ref()
+        .try_clone()
+        .map_err(|e| ErrorKind::io_error("Cannot duplicate embedding matrix file handle", e))?;
+
+    // Position the reader after the matrix.
+    read.seek(SeekFrom::Current(matrix_len as i64))
+        .map_err(|e| ErrorKind::io_error("Cannot skip embedding matrix", e))?;
+
+    Ok(PreadArray::from_file(file, offset, shape, dims))
+}
+
+/// Write an embedding matrix chunk.
+///
+/// The matrix is always written in row-major order.
+pub(crate) fn write_ndarray_chunk<A, W>(data: ArrayView2<A>, write: &mut W) -> Result<()>
+where
+    A: Element,
+    W: Write + Seek,
+{
+    write_ndarray_header::<A, _>(data.nrows(), data.ncols(), write)?;
+
+    for row in data.outer_iter() {
+        for &col in row.iter() {
+            A::write(write, col)
+                .map_err(|e| ErrorKind::io_error("Cannot write embedding matrix component", e))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Write the given rows of a matrix as an embedding matrix chunk.
+///
+/// Row *i* of the written matrix is row `rows[i]` of `data`.
+pub(crate) fn write_ndarray_rows_chunk<A, W>(
+    data: ArrayView2<A>,
+    rows: &[usize],
+    write: &mut W,
+) -> Result<()>
+where
+    A: Element,
+    W: Write + Seek,
+{
+    write_ndarray_header::<A, _>(rows.len(), data.ncols(), write)?;
+
+    for &row in rows {
+        for &col in data.row(row).iter() {
+            A::write(write, col)
+                .map_err(|e| ErrorKind::io_error("Cannot write embedding matrix component", e))?;
+        }
+    }
+
+    Ok(())
+}
+
+impl<A> MmapChunk for MmapArray<A>
+where
+    A: Element,
+{
+    fn mmap_chunk(read: &mut BufReader<File>) -> Result<Self> {
+        mmap_array(read, None)
+    }
+}
+
+impl<A> ReadChunkTruncated for MmapArray<A>
+where
+    A: Element,
+{
+    fn read_chunk_truncated(read: &mut BufReader<File>, dims: usize) -> Result<Self> {
+        mmap_array(read, Some(dims))
+    }
+
+    /// Normalize the first `n_rows` truncated embeddings.
+    ///
+    /// The mapped matrix is not modified, the truncated matrix is
+    /// copied to memory and normalized there.
+    fn normalize_rows(&mut self, n_rows: usize) -> Result<Array1<f32>> {
+        check_normalized_rows(n_rows, self.full_shape()[0])?;
+        Ok(MmapArray::normalize_rows(self, n_rows))
+    }
+}
+
+impl<A> WriteChunk for MmapArray<A>
+where
+    A: Element,
+{
+    fn chunk_identifier(&self) -> ChunkIdentifier {
+        ChunkIdentifier::NdArray
+    }
+
+    /// Write the memory-mapped matrix.
+    ///
+    /// The mapped data is copied to the writer in blocks of
+    /// `MMAP_WRITE_BLOCK_SIZE` bytes, so that the matrix is never
+    /// materialized in memory. Since the mapped data is in the byte
+    /// order of the file format, no conversion is necessary. Normalized
+    /// truncated matrices are written from their copy in memory.
+    fn write_chunk<W>(&self, write: &mut W) -> Result<()>
+    where
+        W: Write + Seek,
+    {
+        if let Some(matrix) = self.normalized_view() {
+            return write_ndarray_chunk(matrix, write);
+        }
+
+        let (rows, dims) = self.shape();
+        write_ndarray_header::<A, _>(rows, dims, write)?;
+
+        let cols = self.full_shape()[1];
+        if dims == cols {
+            for block in self.mapped_data().chunks(MMAP_WRITE_BLOCK_SIZE) {
+                write
+                    .write_all(block)
+                    .map_err(|e| ErrorKind::io_error("Cannot write embedding matrix", e))?;
+            }
+        } else {
+            // Rows are not contiguous in truncated matrices, copy the
+            // retained part of each row.
+            let row_len = cols * size_of::<A>();
+            let truncated_row_len = dims * size_of::<A>();
+            for row in self.mapped_data().chunks_exact(row_len) {
+                write
+                    .write_all(&row[..truncated_row_len])
+                    .map_err(|e| ErrorKind::io_error("Cannot write embedding matrix", e))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<A> WriteChunkRows for MmapArray<A>
+where
+    A: Element,
+{
+    /// Write the given rows of the memory-mapped matrix.
+    ///
+    /// The selected rows are gathered in blocks of
+    /// `MMAP_WRITE_BLOCK_SIZE` bytes before they are written, so that
+    /// only a single block is held in memory.
+    fn write_chunk_rows<W>(&self, rows: &[usize], write: &mut W) -> Result<()>
+    where
+        W: Write + Seek,
+    {
+        if let Some(matrix) = self.normalized_view() {
+            return write_ndarray_rows_chunk(matrix, rows, write);
+        }
+
+        let dims = self.shape().1;
+        write_ndarray_header::<A, _>(rows.len(), dims, write)?;
+
+        let row_len = self.full_shape()[1] * size_of::<A>();
+        let truncated_row_len = dims * size_of::<A>();
+        let block_size = MMAP_WRITE_BLOCK_SIZE.max(truncated_row_len);
+        let data = self.mapped_data();
+        let mut block = Vec::with_capacity(block_size);
+        for &row in rows {
+            if block.len() + truncated_row_len > block_size {
+                write
+                    .write_all(&block)
+                    .map_err(|e| ErrorKind::io_error("Cannot write embedding matrix", e))?;
+                block.clear();
+            }
+
+            let start = row * row_len;
+            block.extend_from_slice(&data[start..start + truncated_row_len]);
+        }
+        write
+            .write_all(&block)
+            .map_err(|e| ErrorKind::io_error("Cannot write embedding matrix", e))?;
+
+        Ok(())
+    }
+}
+
+impl PreadChunk for PreadArray {
+    fn pread_chunk(read: &mut BufReader<File>) -> Result<Self> {
+        pread_array(read, None)
+    }
+}
+
+impl ReadChunkTruncated for PreadArray {
+    fn read_chunk_truncated(read: &mut BufReader<File>, dims: usize) -> Result<Self> {
+        pread_array(read, Some(dims))
+    }
+
+    /// Normalize the first `n_rows` truncated embeddings.
+    ///
+    /// The embeddings are read once to compute their norms, afterwards
+    /// they are normalized when they are retrieved.
+    fn normalize_rows(&mut self, n_rows: usize) -> Result<Array1<f32>> {
+        check_normalized_rows(n_rows, self.full_shape()[0])?;
+
+        let mut norms = Vec::with_capacity(n_rows);
+        for idx in 0..n_rows {
+            let mut embedding = self.try_embedding(idx)?;
+            norms.push(l2_normalize(embedding.view_mut()));
+        }
+        self.set_normalized_rows(n_rows);
+
+        Ok(norms.into())
+    }
+}
+
+impl WriteChunk for PreadArray {
+    fn chunk_identifier(&self) -> ChunkIdentifier {
+        ChunkIdentifier::NdArray
+    }
+
+    fn write_chunk<W>(&self, write: &mut W) -> Result<()>
+    where
+        W: Write + Seek,
+    {
+        let (rows, cols) = self.shape();
+        write_ndarray_header::<f32, _>(rows, cols, write)?;
+
+        // Copy the matrix row by row, to avoid loading it in memory.
+        for idx in 0..rows {
+            let embedding = self.try_embedding(idx)?;
+            for &col in embedding.iter() {
+                write.write_f32::<LittleEndian>(col).map_err(|e| {
+                    ErrorKind::io_error("Cannot write embedding matrix component", e)
+                })?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl WriteChunkRows for PreadArray {
+    fn write_chunk_rows<W>(&self, rows: &[usize], write: &mut W) -> Result<()>
+    where
+        W: Write + Seek,
+    {
+        write_ndarray_header::<f32, _>(rows.len(), self.shape().1, write)?;
+
+        // Copy the selected rows one by one, to avoid loading them in memory.
+        for &row in rows {
+            let embedding = self.try_embedding(row)?;
+            for &col in embedding.iter() {
+                write.write_f32::<LittleEndian>(col).map_err(|e| {
+                    ErrorKind::io_error("Cannot write embedding matrix component", e)
+                })?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<A> ReadChunk for NdArray<A>
+where
+    A: Element,
+{
+    fn read_chunk<R>(read: &mut R) -> Result<Self>
+    where
+        R: Read + Seek,
+    {
+        let (rows, cols) = read_ndarray_header::<A, _>(read)?.into_pattern();
+
+        let mut data = vec![A::default(); rows * cols];
+        A::read_into(read, &mut data)
+            .map_err(|e| ErrorKind::io_error("Cannot read embedding matrix", e))?;
+
+        Ok(NdArray::new(
+            Array2::from_shape_vec((rows, cols), data).map_err(Error::Shape)?,
+        ))
+    }
+}
+
+impl<A> ReadChunkTruncated for NdArray<A>
+where
+    A: Element,
+{
+    fn read_chunk_truncated(read: &mut BufReader<File>, dims: usize) -> Result<Self> {
+        let shape = read_ndarray_header::<A, _>(read)?;
+        let dims = truncated_dims(shape, Some(dims))?;
+        let (rows, cols) = shape.into_pattern();
+
+        // Read the matrix row by row, so that only the retained
+        // components are stored.
+        let mut data = vec![A::default(); rows * dims];
+        let mut discard = vec![0u8; (cols - dims) * size_of::<A>()];
+        for row in data.chunks_exact_mut(dims) {
+            A::read_into(read, row)
+                .map_err(|e| ErrorKind::io_error("Cannot read embedding matrix", e))?;
+            read.read_exact(&mut discard)
+                .map_err(|e| ErrorKind::io_error("Cannot read embedding matrix", e))?;
+        }
+
+        Ok(NdArray::new(
+            Array2::from_shape_vec((rows, dims), data).map_err(Error::Shape)?,
+        ))
+    }
+
+    fn normalize_rows(&mut self, n_rows: usize) -> Result<Array1<f32>> {
+        check_normalized_rows(n_rows, self.matrix_view().nrows())?;
+
+        let mut rows = self.matrix_view_mut().slice_move(s![..n_rows, ..]);
+        Ok(rows
+            .outer_iter_mut()
+            .map(A::l2_normalize)
+            .collect::<Vec<_>>()
+            .into())
+    }
+}
+
+impl<A> ReadChunkRows for NdArray<A>
+where
+    A: Element,
+{
+    fn read_chunk_rows<R>(read: &mut R, rows: &[usize]) -> Result<Self>
+    where
+        R: Read + Seek,
+    {
+        let (n_rows, cols) = read_ndarray_header::<A, _>(read)?.into_pattern();
+        let data_start = read
+            .stream_position()
+            .map_err(|e| ErrorKind::io_error("Cannot get embedding matrix position", e))?;
+        let row_size = (cols * size_of::<A>()) as u64;
+
+        let mut data = vec![A::default(); rows.len() * cols];
+        let mut next_row = 0;
+        for (&row, embedding) in rows.iter().zip(data.chunks_exact_mut(cols.max(1))) {
+            if row >= n_rows {
+                return Err(ErrorKind::Format(format!(
+                    "Embedding matrix has {} rows, cannot read row {}",
+                    n_rows, row
+                ))
+                .into());
+            }
+
+            // Consecutive rows are read without seeking.
+            if row != next_row {
+                read.seek(SeekFrom::Start(data_start + row as u64 * row_size))
+                    .map_err(|e| ErrorKind::io_error("Cannot seek to embedding", e))?;
+            }
+            A::read_into(read, &mut embedding[..cols])
+                .map_err(|e| ErrorKind::io_error("Cannot read embedding matrix", e))?;
+            next_row = row + 1;
+        }
+
+        read.seek(SeekFrom::Start(data_start + n_rows as u64 * row_size))
+            .map_err(|e| ErrorKind::io_error("Cannot skip to the end of embedding matrix", e))?;
+
+        Ok(NdArray::new(
+            Array2::from_shape_vec((rows.len(), cols), data).map_err(Error::Shape)?,
+        ))
+    }
+}
+
+impl<A> WriteChunk for NdArray<A>
+where
+    A: Element,
+{
+    fn chunk_identifier(&self) -> ChunkIdentifier {
+        ChunkIdentifier::NdArray
+    }
+
+    fn write_chunk<W>(&self, write: &mut W) -> Result<()>
+    where
+        W: Write + Seek,
+    {
+        write_ndarray_chunk(self.matrix_view(), write)
+    }
+}
+
+impl<A> WriteChunkRows for NdArray<A>
+where
+    A: Element,
+{
+    fn write_chunk_rows<W>(&self, rows: &[usize], write: &mut W) -> Result<()>
+    where
+        W: Write + Seek,
+    {
+        write_ndarray_rows_chunk(self.matrix_view(), rows, write)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, File};
+    use std::io::{BufReader, Cursor, Read, Seek, SeekFrom};
+
+    use byteorder::{LittleEndian, ReadBytesExt};
+    use finalfusion_core::storage::{
+        MatrixLayout, MmapArray, NdArray, PreadArray, Storage, StorageView, StorageWrap,
+    };
+    use ndarray::{s, Array1, Array2, Axis};
+
+    use crate::chunks::io::{
+        MmapChunk, PreadChunk, ReadChunk, ReadChunkTruncated, WriteChunk, WriteChunkRows,
+    };
+
+    const N_ROWS: usize = 100;
+    const N_COLS: usize = 100;
+
+    fn test_ndarray() -> NdArray {
+        let test_data = Array2::from_shape_fn((N_ROWS, N_COLS), |(r, c)| {
+            r as f32 * N_COLS as f32 + c as f32
+        });
+
+        NdArray::new(test_data)
+    }
+
+    fn test_ndarray_f64() -> NdArray<f64> {
+        // Components that cannot be represented exactly as f32.
+        NdArray::new(Array2::from_shape_fn((N_ROWS, N_COLS), |(r, c)| {
+            1. + (r * N_COLS + c) as f64 * 1e-12
+        }))
+    }
+
+    fn read_chunk_size(read: &mut impl Read) -> u64 {
+        // Skip identifier.
+        read.read_u32::<LittleEndian>().unwrap();
+
+        // Return chunk length.
+        read.read_u64::<LittleEndian>().unwrap()
+    }
+
+    #[test]
+    fn ndarray_correct_chunk_size() {
+        let check_arr = test_ndarray();
+        let mut cursor = Cursor::new(Vec::new());
+        check_arr.write_chunk(&mut cursor).unwrap();
+        cursor.seek(SeekFrom::Start(0)).unwrap();
+
+        let chunk_size = read_chunk_size(&mut cursor);
+        assert_eq!(
+            cursor.read_to_end(&mut Vec::new()).unwrap(),
+            chunk_size as usize
+        );
+    }
+
+    #[test]
+    fn ndarray_column_major_written_row_major() {
+        let check_arr = test_ndarray();
+        let arr = check_arr.clone().into_column_major();
+        let mut cursor = Cursor::new(Vec::new());
+        arr.write_chunk(&mut cursor).unwrap();
+        cursor.seek(SeekFrom::Start(0)).unwrap();
+        let read_arr = NdArray::read_chunk(&mut cursor).unwrap();
+        assert_eq!(read_arr.layout(), MatrixLayout::RowMajor);
+        assert_eq!(read_arr.view(), check_arr.view());
+    }
+
+    #[test]
+    fn ndarray_write_read_roundtrip() {
+        let check_arr = test_ndarray();
+        let mut cursor = Cursor::new(Vec::new());
+        check_arr.write_chunk(&mut cursor).unwrap();
+        cursor.seek(SeekFrom::Start(0)).unwrap();
+        let arr = NdArray::read_chunk(&mut cursor).unwrap();
+        assert_eq!(arr.view(), check_arr.view());
+    }
+
+    #[test]
+    fn ndarray_f64_write_read_roundtrip() {
+        let check_arr = test_ndarray_f64();
+        let mut cursor = Cursor::new(Vec::new());
+        check_arr.write_chunk(&mut cursor).unwrap();
+        cursor.seek(SeekFrom::Start(0)).unwrap();
+        let arr = NdArray::<f64>::read_chunk(&mut cursor).unwrap();
+        assert_eq!(arr.matrix_view(), check_arr.matrix_view());
+        assert_eq!(
+            arr.embedding(1),
+            check_arr.matrix_view().row(1).mapv(|v| v as f32)
+        );
+    }
+
+    #[test]
+    fn ndarray_f64_correct_chunk_size() {
+        let check_arr = test_ndarray_f64();
+        let mut cursor = Cursor::new(Vec::new());
+        check_arr.write_chunk(&mut cursor).unwrap();
+        cursor.seek(SeekFrom::Start(0)).unwrap();
+
+        let chunk_size = read_chunk_size(&mut cursor);
+        assert_eq!(
+            cursor.read_to_end(&mut Vec::new()).unwrap(),
+            chunk_size as usize
+        );
+    }
+
+    #[test]
+    fn ndarray_type_mismatch_fails() {
+        let mut cursor = Cursor::new(Vec::new());
+        test_ndarray_f64().write_chunk(&mut cursor).unwrap();
+        cursor.seek(SeekFrom::Start(0)).unwrap();
+        assert!(NdArray::<f32>::read_chunk(&mut cursor).is_err());
+    }
+
+    #[test]
+    fn storage_wrap_reads_f64() {
+        let check_arr = test_ndarray_f64();
+        let mut cursor = Cursor::new(Vec::new());
+        check_arr.write_chunk(&mut cursor).unwrap();
+        cursor.seek(SeekFrom::Start(0)).unwrap();
+        match StorageWrap::read_chunk(&mut cursor).unwrap() {
+            StorageWrap::NdArrayF64(arr) => assert_eq!(arr.matrix_view(), check_arr.matrix_view()),
+            _ => panic!("Expected an f64 matrix"),
+        }
+    }
+
+    #[test]
+    fn mmap_array_f64() {
+        let check_arr = test_ndarray_f64();
+        let path = std::env::temp_dir().join(format!("mmap-f64-{}.fifu", std::process::id()));
+        {
+            // Write at an offset that is not a multiple of 8.
+            let mut data = Cursor::new(vec![0u8; 4]);
+            data.seek(SeekFrom::End(0)).unwrap();
+            check_arr.write_chunk(&mut data).unwrap();
+            fs::write(&path, data.into_inner()).unwrap();
+        }
+
+        let mut reader = BufReader::new(File::open(&path).unwrap());
+        reader.seek(SeekFrom::Start(4)).unwrap();
+        let arr = MmapArray::<f64>::mmap_chunk(&mut reader).unwrap();
+        assert_eq!(arr.shape(), (N_ROWS, N_COLS));
+        #[cfg(target_endian = "little")]
+        assert_eq!(arr.matrix_view(), check_arr.matrix_view());
+        assert_eq!(arr.matrix_row(42), check_arr.matrix_view().row(42));
+        assert_eq!(arr.embedding(42), check_arr.embedding(42));
+        assert_eq!(arr.embeddings(&[3, 1]), check_arr.embeddings(&[3, 1]));
+
+        let mut out = Array1::zeros(N_COLS);
+        arr.embedding_into(42, out.view_mut());
+        assert_eq!(out, check_arr.embedding(42));
+
+        drop(arr);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn mmap_array_write_chunk_rows() {
+        let check_arr = test_ndarray();
+        let rows = [42, 3, 99, 3];
+        let path = std::env::temp_dir().join(format!("mmap-rows-{}.fifu", std::process::id()));
+        {
+            let mut data = Cursor::new(Vec::new());
+            check_arr.write_chunk(&mut data).unwrap();
+            fs::write(&path, data.into_inner()).unwrap();
+        }
+
+        let mut reader = BufReader::new(File::open(&path).unwrap());
+        let arr = MmapArray::<f32>::mmap_chunk(&mut reader).unwrap();
+        let mut cursor = Cursor::new(Vec::new());
+        arr.write_chunk_rows(&rows, &mut cursor).unwrap();
+        cursor.seek(SeekFrom::Start(0)).unwrap();
+        let selected = NdArray::read_chunk(&mut cursor).unwrap();
+        assert_eq!(selected.view(), check_arr.view().select(Axis(0), &rows));
+
+        // Truncated embeddings only retain the first components of the rows.
+        reader.seek(SeekFrom::Start(0)).unwrap();
+        let arr = MmapArray::<f32>::read_chunk_truncated(&mut reader, 10).unwrap();
+        let mut cursor = Cursor::new(Vec::new());
+        arr.write_chunk_rows(&rows, &mut cursor).unwrap();
+        cursor.seek(SeekFrom::Start(0)).unwrap();
+        let chunk_size = read_chunk_size(&mut cursor);
+        assert_eq!(
+            cursor.read_to_end(&mut Vec::new()).unwrap(),
+            chunk_size as usize
+        );
+        cursor.seek(SeekFrom::Start(0)).unwrap();
+        let selected = NdArray::read_chunk(&mut cursor).unwrap();
+        assert_eq!(
+            selected.view(),
+            check_arr.view().select(Axis(0), &rows).slice(s![.., ..10])
+        );
+
+        drop(arr);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn pread_array_rejects_f64() {
+        let path = std::env::temp_dir().join(format!("pread-f64-{}.fifu", std::process::id()));
+        {
+            let mut data = Cursor::new(Vec::new());
+            test_ndarray_f64().write_chunk(&mut data).unwrap();
+            fs::write(&path, data.into_inner()).unwrap();
+        }
+
+        let mut reader = BufReader::new(File::open(&path).unwrap());
+        assert!(PreadArray::pread_chunk(&mut reader).is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn pread_array_rejects_truncated_file() {
+        let path =
+            std::env::temp_dir().join(format!("pread-truncated-{}.fifu", std::process::id()));
+        {
+            let mut data = Cursor::new(Vec::new());
+            test_ndarray().write_chunk(&mut data).unwrap();
+            let mut data = data.into_inner();
+            data.truncate(data.len() - 4);
+            fs::write(&path, data).unwrap();
+        }
+
+        let mut reader = BufReader::new(File::open(&path).unwrap());
+        assert!(PreadArray::pread_chunk(&mut reader).is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn pread_array_try_embedding() {
+        let check_arr = test_ndarray();
+        let path = std::env::temp_dir().join(format!("pread-{}.fifu", std::process::id()));
+        {
+            let mut data = Cursor::new(Vec::new());
+            check_arr.write_chunk(&mut data).unwrap();
+            fs::write(&path, data.into_inner()).unwrap();
+        }
+
+        let mut reader = BufReader::new(File::open(&path).unwrap());
+        let arr = PreadArray::pread_chunk(&mut reader).unwrap();
+        assert_eq!(arr.try_embedding(42).unwrap(), check_arr.embedding(42));
+
+        let mut out = Array1::zeros(N_COLS);
+        arr.try_embedding_into(42, out.view_mut()).unwrap();
+        assert_eq!(out, check_arr.embedding(42));
+
+        drop(arr);
+        fs::remove_file(&path).unwrap();
+    }
+}
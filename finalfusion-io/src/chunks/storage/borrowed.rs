@@ -0,0 +1,95 @@
+use std::io::{Seek, Write};
+
+use finalfusion_core::storage::{BorrowedArray, SharedArray, StorageView};
+
+use super::array::{write_ndarray_chunk, write_ndarray_rows_chunk};
+use crate::chunks::io::{ChunkIdentifier, WriteChunk, WriteChunkRows};
+use crate::io::Result;
+
+impl<'a> WriteChunk for BorrowedArray<'a> {
+    fn chunk_identifier(&self) -> ChunkIdentifier {
+        ChunkIdentifier::NdArray
+    }
+
+    fn write_chunk<W>(&self, write: &mut W) -> Result<()>
+    where
+        W: Write + Seek,
+    {
+        write_ndarray_chunk(self.view(), write)
+    }
+}
+
+impl<'a> WriteChunkRows for BorrowedArray<'a> {
+    fn write_chunk_rows<W>(&self, rows: &[usize], write: &mut W) -> Result<()>
+    where
+        W: Write + Seek,
+    {
+        write_ndarray_rows_chunk(self.view(), rows, write)
+    }
+}
+
+impl WriteChunk for SharedArray {
+    fn chunk_identifier(&self) -> ChunkIdentifier {
+        ChunkIdentifier::NdArray
+    }
+
+    fn write_chunk<W>(&self, write: &mut W) -> Result<()>
+    where
+        W: Write + Seek,
+    {
+        write_ndarray_chunk(self.view(), write)
+    }
+}
+
+impl WriteChunkRows for SharedArray {
+    fn write_chunk_rows<W>(&self, rows: &[usize], write: &mut W) -> Result<()>
+    where
+        W: Write + Seek,
+    {
+        write_ndarray_rows_chunk(self.view(), rows, write)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Seek, SeekFrom};
+    use std::sync::Arc;
+
+    use finalfusion_core::storage::{BorrowedArray, NdArray, SharedArray, Storage, StorageView};
+    use ndarray::{s, Array2};
+
+    use crate::chunks::io::{ReadChunk, WriteChunk};
+
+    fn test_matrix() -> Array2<f32> {
+        Array2::from_shape_fn((10, 6), |(r, c)| (r * 6 + c) as f32)
+    }
+
+    fn check_roundtrip(storage: &(impl Storage + WriteChunk), check: &Array2<f32>) {
+        let mut cursor = Cursor::new(Vec::new());
+        storage.write_chunk(&mut cursor).unwrap();
+        cursor.seek(SeekFrom::Start(0)).unwrap();
+        let arr = NdArray::read_chunk(&mut cursor).unwrap();
+        assert_eq!(arr.view(), check.view());
+        for idx in 0..check.nrows() {
+            assert_eq!(storage.embedding(idx).view(), check.row(idx));
+        }
+    }
+
+    #[test]
+    fn borrowed_array_write_read_roundtrip() {
+        let matrix = test_matrix();
+        check_roundtrip(&BorrowedArray::new(matrix.view()), &matrix);
+
+        // Views do not have to be contiguous.
+        let columns = matrix.slice(s![.., 1..4]);
+        check_roundtrip(&BorrowedArray::new(columns), &columns.to_owned());
+    }
+
+    #[test]
+    fn shared_array_does_not_copy() {
+        let matrix = Arc::new(test_matrix());
+        let shared = SharedArray::new(matrix.clone());
+        assert!(Arc::ptr_eq(shared.matrix(), &matrix));
+        check_roundtrip(&shared, &matrix);
+    }
+}
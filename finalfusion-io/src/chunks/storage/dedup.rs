@@ -0,0 +1,203 @@
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::mem::{size_of, size_of_val};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use finalfusion_core::storage::{DedupArray, SelectRows};
+use finalfusion_core::util::padding;
+use ndarray::Array2;
+
+use crate::chunks::io::{ChunkIdentifier, ReadChunk, TypeId, WriteChunk, WriteChunkRows};
+use crate::io::{Error, ErrorKind, Result};
+
+impl ReadChunk for DedupArray {
+    fn read_chunk<R>(read: &mut R) -> Result<Self>
+    where
+        R: Read + Seek,
+    {
+        ChunkIdentifier::ensure_chunk_type(read, ChunkIdentifier::DedupArray)?;
+
+        // Read and discard chunk length.
+        read.read_u64::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read deduplicated matrix chunk length", e))?;
+
+        let n_embeddings = read
+            .read_u64::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read number of embeddings", e))?
+            as usize;
+        let n_unique = read
+            .read_u64::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read number of unique embeddings", e))?
+            as usize;
+        let cols = read.read_u32::<LittleEndian>().map_err(|e| {
+            ErrorKind::io_error("Cannot read number of columns of the embedding matrix", e)
+        })? as usize;
+
+        f32::ensure_data_type(read)?;
+
+        let mut indices = vec![0u32; n_embeddings];
+        read.read_u32_into::<LittleEndian>(&mut indices)
+            .map_err(|e| ErrorKind::io_error("Cannot read embedding indices", e))?;
+        if let Some(&idx) = indices.iter().find(|&&idx| idx as usize >= n_unique) {
+            return Err(ErrorKind::Format(format!(
+                "Embedding index {} is out of bounds for {} unique embeddings",
+                idx, n_unique
+            ))
+            .into());
+        }
+
+        let n_padding = padding::<f32>(read.stream_position().map_err(|e| {
+            ErrorKind::io_error("Cannot get file position for computing padding", e)
+        })?);
+        read.seek(SeekFrom::Current(n_padding as i64))
+            .map_err(|e| ErrorKind::io_error("Cannot skip padding", e))?;
+
+        let mut data = vec![0f32; n_unique * cols];
+        read.read_f32_into::<LittleEndian>(&mut data)
+            .map_err(|e| ErrorKind::io_error("Cannot read unique embeddings", e))?;
+
+        Ok(DedupArray::new(
+            Array2::from_shape_vec((n_unique, cols), data).map_err(Error::Shape)?,
+            indices,
+        ))
+    }
+}
+
+impl WriteChunk for DedupArray {
+    fn chunk_identifier(&self) -> ChunkIdentifier {
+        ChunkIdentifier::DedupArray
+    }
+
+    fn write_chunk<W>(&self, write: &mut W) -> Result<()>
+    where
+        W: Write + Seek,
+    {
+        write
+            .write_u32::<LittleEndian>(ChunkIdentifier::DedupArray as u32)
+            .map_err(|e| {
+                ErrorKind::io_error("Cannot write deduplicated matrix chunk identifier", e)
+            })?;
+
+        // The fields before the padding have a combined length that is
+        // a multiple of 4, so the padding can be computed here.
+        let n_padding = padding::<f32>(write.stream_position().map_err(|e| {
+            ErrorKind::io_error("Cannot get file position for computing padding", e)
+        })?);
+
+        // Chunk size: number of embeddings (u64), number of unique
+        // embeddings (u64), columns (u32), type id (u32), indices,
+        // padding, unique embeddings.
+        let chunk_len = size_of::<u64>()
+            + size_of::<u64>()
+            + size_of::<u32>()
+            + size_of::<u32>()
+            + size_of_val(self.indices())
+            + n_padding as usize
+            + self.unique_embeddings().len() * size_of::<f32>();
+        write
+            .write_u64::<LittleEndian>(chunk_len as u64)
+            .map_err(|e| ErrorKind::io_error("Cannot write deduplicated matrix chunk length", e))?;
+        write
+            .write_u64::<LittleEndian>(self.indices().len() as u64)
+            .map_err(|e| ErrorKind::io_error("Cannot write number of embeddings", e))?;
+        write
+            .write_u64::<LittleEndian>(self.unique_embeddings().nrows() as u64)
+            .map_err(|e| ErrorKind::io_error("Cannot write number of unique embeddings", e))?;
+        write
+            .write_u32::<LittleEndian>(self.unique_embeddings().ncols() as u32)
+            .map_err(|e| {
+                ErrorKind::io_error("Cannot write number of columns of the embedding matrix", e)
+            })?;
+        write
+            .write_u32::<LittleEndian>(f32::type_id())
+            .map_err(|e| ErrorKind::io_error("Cannot write embedding matrix type identifier", e))?;
+
+        for &idx in self.indices() {
+            write
+                .write_u32::<LittleEndian>(idx)
+                .map_err(|e| ErrorKind::io_error("Cannot write embedding index", e))?;
+        }
+
+        let padding = vec![0; n_padding as usize];
+        write
+            .write_all(&padding)
+            .map_err(|e| ErrorKind::io_error("Cannot write padding", e))?;
+
+        for &component in self.unique_embeddings().iter() {
+            write
+                .write_f32::<LittleEndian>(component)
+                .map_err(|e| ErrorKind::io_error("Cannot write embedding matrix component", e))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl WriteChunkRows for DedupArray {
+    /// Write the given rows of the deduplicated matrix.
+    ///
+    /// Only the unique embeddings that are used by the selected rows
+    /// are copied.
+    fn write_chunk_rows<W>(&self, rows: &[usize], write: &mut W) -> Result<()>
+    where
+        W: Write + Seek,
+    {
+        self.select_rows(rows).write_chunk(write)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read, Seek, SeekFrom};
+
+    use finalfusion_core::storage::{DedupArray, NdArray, Prune};
+    use ndarray::array;
+
+    use crate::chunks::io::{ReadChunk, WriteChunk};
+
+    fn test_ndarray() -> NdArray {
+        NdArray::new(array![
+            [1., 2., 3.],
+            [0., 0., 0.],
+            [1., 2., 3.],
+            [0., 0., 0.],
+            [1., 2.001, 3.],
+            [4., 5., 6.]
+        ])
+    }
+
+    #[test]
+    fn dedup_array_write_read_roundtrip() {
+        let check_arr = test_ndarray().prune(0.);
+        let mut cursor = Cursor::new(Vec::new());
+        check_arr.write_chunk(&mut cursor).unwrap();
+        cursor.seek(SeekFrom::Start(0)).unwrap();
+        let arr = DedupArray::read_chunk(&mut cursor).unwrap();
+        assert_eq!(arr, check_arr);
+    }
+
+    #[test]
+    fn dedup_array_correct_chunk_size() {
+        let check_arr = test_ndarray().prune(0.);
+        let mut cursor = Cursor::new(Vec::new());
+        check_arr.write_chunk(&mut cursor).unwrap();
+        cursor.seek(SeekFrom::Start(4)).unwrap();
+        let mut chunk_size = [0u8; 8];
+        cursor.read_exact(&mut chunk_size).unwrap();
+        let chunk_size = u64::from_le_bytes(chunk_size);
+        assert_eq!(
+            cursor.read_to_end(&mut Vec::new()).unwrap(),
+            chunk_size as usize
+        );
+    }
+
+    #[test]
+    fn dedup_array_rejects_invalid_indices() {
+        let mut cursor = Cursor::new(Vec::new());
+        test_ndarray().prune(0.).write_chunk(&mut cursor).unwrap();
+
+        // Replace the first index by the number of unique embeddings.
+        let mut data = cursor.into_inner();
+        data[36..40].copy_from_slice(&4u32.to_le_bytes());
+        assert!(DedupArray::read_chunk(&mut Cursor::new(data)).is_err());
+    }
+}
@@ -0,0 +1,19 @@
+//! Serialization of embedding matrices.
+
+mod array;
+
+mod borrowed;
+
+mod dedup;
+
+mod quantized;
+#[doc(hidden)]
+pub use self::quantized::codes_features;
+pub use self::quantized::QuantizedArrayWriter;
+
+mod remap;
+pub use self::remap::{MmapArrayEpoch, RemappableMmapArray};
+
+mod residual;
+
+mod wrappers;
@@ -0,0 +1,1000 @@
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom, Write};
+use std::mem::size_of;
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use finalfusion_core::storage::{
+    code_len, normalize_rows, packs_codes, quantize_rows, MmapQuantizedArray, QuantizedArray,
+    SelectRows, MAX_PACKED_CENTROIDS,
+};
+use finalfusion_core::util::padding;
+use half::f16;
+use memmap::{Mmap, MmapOptions};
+use ndarray::{
+    Array, Array1, Array2, ArrayBase, ArrayView1, ArrayView2, Axis, Data, Dimension, IntoDimension,
+    Ix1, Ix2,
+};
+use reductive::pq::{QuantizeVector, ReconstructVector, PQ};
+
+use crate::chunks::io::{
+    ChunkIdentifier, Features, MmapChunk, PackedCodes, ReadChunk, TypeId, WriteChunk,
+    WriteChunkRows,
+};
+use crate::io::{Error, ErrorKind, Result};
+
+/// Storage type of the norms in a quantized matrix chunk.
+///
+/// The type is stored in the `use_norms` field of the chunk header.
+/// For compatibility with earlier versions of the format, `1` is
+/// used for single-precision norms. Half-precision norms use the type
+/// identifier of `f16` and require `Features::HALF_NORMS`, since
+/// earlier readers interpret any non-zero value as single-precision
+/// norms.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum NormsType {
+    Absent,
+    F32,
+    F16,
+}
+
+impl NormsType {
+    fn new(use_norms: bool, half_norms: bool) -> Self {
+        match (use_norms, half_norms) {
+            (false, _) => NormsType::Absent,
+            (true, false) => NormsType::F32,
+            (true, true) => NormsType::F16,
+        }
+    }
+
+    fn from_header(value: u32) -> Result<Self> {
+        match value {
+            0 => Ok(NormsType::Absent),
+            1 => Ok(NormsType::F32),
+            value if value == f16::type_id() => Ok(NormsType::F16),
+            value => Err(ErrorKind::Format(format!(
+                "Invalid norms type, expected: 0, 1, or {}, got: {}",
+                f16::type_id(),
+                value
+            ))
+            .into()),
+        }
+    }
+
+    fn header_value(self) -> u32 {
+        match self {
+            NormsType::Absent => 0,
+            NormsType::F32 => 1,
+            NormsType::F16 => f16::type_id(),
+        }
+    }
+
+    /// Get the features that are required to read the norms.
+    fn required_features(self) -> Features {
+        match self {
+            NormsType::F16 => Features::HALF_NORMS,
+            NormsType::Absent | NormsType::F32 => Features::empty(),
+        }
+    }
+
+    /// Size of a norm in bytes.
+    fn size(self) -> usize {
+        match self {
+            NormsType::Absent => 0,
+            NormsType::F32 => size_of::<f32>(),
+            NormsType::F16 => size_of::<f16>(),
+        }
+    }
+}
+
+struct PQRead {
+    n_embeddings: usize,
+    quantizer: PQ<f32>,
+    packed: bool,
+    norms_type: NormsType,
+}
+
+fn read_norms<R>(
+    read: &mut R,
+    n_embeddings: usize,
+    norms_type: NormsType,
+) -> Result<Option<Array1<f32>>>
+where
+    R: Read,
+{
+    let norms = match norms_type {
+        NormsType::Absent => return Ok(None),
+        NormsType::F32 => {
+            let mut norms = vec![0f32; n_embeddings];
+            read.read_f32_into::<LittleEndian>(&mut norms)
+                .map_err(|e| ErrorKind::io_error("Cannot read norms", e))?;
+            norms
+        }
+        NormsType::F16 => {
+            let mut norms = vec![0u16; n_embeddings];
+            read.read_u16_into::<LittleEndian>(&mut norms)
+                .map_err(|e| ErrorKind::io_error("Cannot read norms", e))?;
+            norms
+                .into_iter()
+                .map(|norm| f16::from_bits(norm).to_f32())
+                .collect()
+        }
+    };
+
+    Ok(Some(Array1::from(norms)))
+}
+
+fn write_norms<W>(write: &mut W, norms: ArrayView1<f32>, norms_type: NormsType) -> Result<()>
+where
+    W: Write,
+{
+    for &norm in norms {
+        match norms_type {
+            NormsType::Absent => return Ok(()),
+            NormsType::F32 => write.write_f32::<LittleEndian>(norm),
+            NormsType::F16 => write.write_u16::<LittleEndian>(f16::from_f32(norm).to_bits()),
+        }
+        .map_err(|e| ErrorKind::io_error("Cannot write norm vector component", e))?;
+    }
+
+    Ok(())
+}
+
+pub(crate) fn write_quantized<W>(write: &mut W, quantized: ArrayView2<u8>) -> Result<()>
+where
+    W: Write,
+{
+    for row in quantized.outer_iter() {
+        for &col in row {
+            write.write_u8(col).map_err(|e| {
+                ErrorKind::io_error("Cannot write quantized embedding matrix component", e)
+            })?;
+        }
+    }
+
+    Ok(())
+}
+
+impl ReadChunk for QuantizedArray {
+    fn read_chunk<R>(read: &mut R) -> Result<Self>
+    where
+        R: Read + Seek,
+    {
+        ChunkIdentifier::ensure_chunk_type(read, ChunkIdentifier::QuantizedArray)?;
+
+        // Read and discard chunk length.
+        read.read_u64::<LittleEndian>().map_err(|e| {
+            ErrorKind::io_error("Cannot read quantized embedding matrix chunk length", e)
+        })?;
+
+        let PQRead {
+            n_embeddings,
+            quantizer,
+            packed,
+            norms_type,
+        } = read_product_quantizer(read)?;
+
+        let norms = read_norms(read, n_embeddings, norms_type)?;
+
+        let code_len = code_len(quantizer.quantized_len(), packed);
+        let mut quantized_embeddings_vec = vec![0u8; n_embeddings * code_len];
+        read.read_exact(&mut quantized_embeddings_vec)
+            .map_err(|e| ErrorKind::io_error("Cannot read quantized embeddings", e))?;
+        let quantized_embeddings =
+            Array2::from_shape_vec((n_embeddings, code_len), quantized_embeddings_vec)
+                .map_err(Error::Shape)?;
+
+        Ok(QuantizedArray::from_parts(
+            quantizer,
+            quantized_embeddings,
+            packed,
+            norms,
+            norms_type == NormsType::F16,
+        ))
+    }
+}
+
+impl WriteChunk for QuantizedArray {
+    fn chunk_identifier(&self) -> ChunkIdentifier {
+        ChunkIdentifier::QuantizedArray
+    }
+
+    fn write_chunk<W>(&self, write: &mut W) -> Result<()>
+    where
+        W: Write + Seek,
+    {
+        write_quantized_chunk(
+            write,
+            self.quantizer(),
+            self.quantized_embeddings(),
+            self.packed(),
+            self.norms(),
+            self.half_norms(),
+        )
+    }
+
+    fn required_features(&self) -> Features {
+        let norms_type = NormsType::new(self.norms().is_some(), self.half_norms());
+        codes_features(self.packed()) | norms_type.required_features()
+    }
+}
+
+impl WriteChunkRows for QuantizedArray {
+    /// Write the given rows of the quantized matrix.
+    ///
+    /// The codes of the selected rows are copied, the embeddings are
+    /// not reconstructed.
+    fn write_chunk_rows<W>(&self, rows: &[usize], write: &mut W) -> Result<()>
+    where
+        W: Write + Seek,
+    {
+        self.select_rows(rows).write_chunk(write)
+    }
+}
+
+/// Writer that quantizes an embedding matrix in a streaming fashion.
+///
+/// The writer writes a quantized embedding matrix chunk incrementally.
+/// Rows are quantized as they are written, so that the embedding
+/// matrix never has to be in memory. Only the norms are kept in
+/// memory until the writer is finished. The quantizer can be trained
+/// on a sample of the matrix using `train_pq_sample`.
+///
+/// Since the chunk header contains the number of rows, the number of
+/// rows must be known up front. The chunk is complete after all rows
+/// are written and `finish` is called.
+pub struct QuantizedArrayWriter<'a, W>
+where
+    W: Write + Seek,
+{
+    write: &'a mut W,
+    quantizer: PQ<f32>,
+    packed: bool,
+    norms: Option<Vec<f32>>,
+    norms_offset: u64,
+    n_rows: usize,
+    n_written: usize,
+}
+
+impl<'a, W> QuantizedArrayWriter<'a, W>
+where
+    W: Write + Seek,
+{
+    /// Start writing a quantized embedding matrix of `n_rows` rows.
+    ///
+    /// If `normalize` is `true`, rows are l2-normalized before
+    /// quantization and their norms are stored in the chunk.
+    pub fn new(
+        write: &'a mut W,
+        quantizer: PQ<f32>,
+        n_rows: usize,
+        normalize: bool,
+    ) -> Result<Self> {
+        let packed = packs_codes(&quantizer);
+        let norms_type = NormsType::new(normalize, false);
+        write_chunk_header(write, &quantizer, n_rows, packed, norms_type)?;
+
+        let norms_offset = write
+            .stream_position()
+            .map_err(|e| ErrorKind::io_error("Cannot get file position of norms", e))?;
+
+        // Reserve space for the norms, they are written when finishing.
+        if normalize {
+            write_norms(write, Array1::zeros(n_rows).view(), norms_type)?;
+        }
+
+        Ok(QuantizedArrayWriter {
+            write,
+            quantizer,
+            packed,
+            norms: if normalize {
+                Some(Vec::with_capacity(n_rows))
+            } else {
+                None
+            },
+            norms_offset,
+            n_rows,
+            n_written: 0,
+        })
+    }
+
+    /// Quantize and write a row.
+    pub fn write_row<S>(&mut self, row: ArrayBase<S, Ix1>) -> Result<()>
+    where
+        S: Data<Elem = f32>,
+    {
+        self.write_rows(row.view().insert_axis(Axis(0)))
+    }
+
+    /// Quantize and write a batch of rows.
+    ///
+    /// Writing rows in batches is more efficient than writing them
+    /// one by one, especially when the `rayon` feature is enabled.
+    pub fn write_rows<S>(&mut self, rows: ArrayBase<S, Ix2>) -> Result<()>
+    where
+        S: Data<Elem = f32>,
+    {
+        if rows.ncols() != self.quantizer.reconstructed_len() {
+            return Err(ErrorKind::Format(format!(
+                "Incorrect row length, expected: {}, got: {}",
+                self.quantizer.reconstructed_len(),
+                rows.ncols()
+            ))
+            .into());
+        }
+
+        if self.n_written + rows.nrows() > self.n_rows {
+            return Err(
+                ErrorKind::Format(format!("Cannot write more than {} rows", self.n_rows)).into(),
+            );
+        }
+
+        let quantized = match self.norms {
+            Some(ref mut norms) => {
+                let mut normalized = rows.to_owned();
+                norms.extend(normalize_rows(normalized.view_mut()).iter());
+                quantize_rows(&self.quantizer, normalized.view(), self.packed)
+            }
+            None => quantize_rows(&self.quantizer, rows.view(), self.packed),
+        };
+
+        write_quantized(self.write, quantized.view())?;
+        self.n_written += rows.nrows();
+
+        Ok(())
+    }
+
+    /// Finish writing the chunk.
+    ///
+    /// Returns an error if fewer rows were written than specified
+    /// in the construction of the writer.
+    pub fn finish(self) -> Result<()> {
+        if self.n_written != self.n_rows {
+            return Err(ErrorKind::Format(format!(
+                "Expected {} rows, {} rows were written",
+                self.n_rows, self.n_written
+            ))
+            .into());
+        }
+
+        if let Some(norms) = self.norms {
+            let end = self
+                .write
+                .stream_position()
+                .map_err(|e| ErrorKind::io_error("Cannot get end of quantized matrix chunk", e))?;
+            self.write
+                .seek(SeekFrom::Start(self.norms_offset))
+                .map_err(|e| ErrorKind::io_error("Cannot seek to norms", e))?;
+            write_norms(self.write, ArrayView1::from(&norms), NormsType::F32)?;
+            self.write.seek(SeekFrom::Start(end)).map_err(|e| {
+                ErrorKind::io_error("Cannot seek to end of quantized matrix chunk", e)
+            })?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Get the features that are required to read codes.
+pub fn codes_features(packed: bool) -> Features {
+    if packed {
+        Features::PACKED_CODES
+    } else {
+        Features::empty()
+    }
+}
+
+impl MmapChunk for MmapQuantizedArray {
+    fn mmap_chunk(read: &mut BufReader<File>) -> Result<Self> {
+        ChunkIdentifier::ensure_chunk_type(read, ChunkIdentifier::QuantizedArray)?;
+
+        // Read and discard chunk length.
+        read.read_u64::<LittleEndian>().map_err(|e| {
+            ErrorKind::io_error("Cannot read quantized embedding matrix chunk length", e)
+        })?;
+
+        let PQRead {
+            n_embeddings,
+            quantizer,
+            packed,
+            norms_type,
+        } = read_product_quantizer(read)?;
+
+        let norms = read_norms(read, n_embeddings, norms_type)?;
+
+        let quantized_embeddings = mmap_quantized_embeddings(
+            read,
+            n_embeddings,
+            code_len(quantizer.quantized_len(), packed),
+        )?;
+
+        Ok(MmapQuantizedArray::from_mmap(
+            quantizer,
+            quantized_embeddings,
+            packed,
+            norms,
+            norms_type == NormsType::F16,
+        ))
+    }
+}
+
+impl WriteChunk for MmapQuantizedArray {
+    fn chunk_identifier(&self) -> ChunkIdentifier {
+        ChunkIdentifier::QuantizedArray
+    }
+
+    fn write_chunk<W>(&self, write: &mut W) -> Result<()>
+    where
+        W: Write + Seek,
+    {
+        write_quantized_chunk(
+            write,
+            self.quantizer(),
+            self.quantized_embeddings(),
+            self.packed(),
+            self.norms(),
+            self.half_norms(),
+        )
+    }
+
+    fn required_features(&self) -> Features {
+        let norms_type = NormsType::new(self.norms().is_some(), self.half_norms());
+        codes_features(self.packed()) | norms_type.required_features()
+    }
+}
+
+impl WriteChunkRows for MmapQuantizedArray {
+    /// Write the given rows of the quantized matrix.
+    ///
+    /// The codes of the selected rows are copied, the embeddings are
+    /// not reconstructed.
+    fn write_chunk_rows<W>(&self, rows: &[usize], write: &mut W) -> Result<()>
+    where
+        W: Write + Seek,
+    {
+        self.select_rows(rows).write_chunk(write)
+    }
+}
+
+fn check_quantizer_invariants(quantized_len: usize, reconstructed_len: usize) -> Result<()> {
+    if reconstructed_len % quantized_len != 0 {
+        return Err(ErrorKind::Format(format!("Reconstructed embedding length ({}) not a multiple of the quantized embedding length: ({})", quantized_len, reconstructed_len)).into());
+    }
+
+    Ok(())
+}
+
+fn read_product_quantizer<R>(read: &mut R) -> Result<PQRead>
+where
+    R: Read + Seek,
+{
+    let projection = read
+        .read_u32::<LittleEndian>()
+        .map_err(|e| ErrorKind::io_error("Cannot read quantized embedding matrix projection", e))?
+        != 0;
+    let norms_type =
+        NormsType::from_header(read.read_u32::<LittleEndian>().map_err(|e| {
+            ErrorKind::io_error("Cannot read quantized embedding matrix norms", e)
+        })?)?;
+    let quantized_len = read
+        .read_u32::<LittleEndian>()
+        .map_err(|e| ErrorKind::io_error("Cannot read quantized embedding length", e))?
+        as usize;
+    let reconstructed_len = read
+        .read_u32::<LittleEndian>()
+        .map_err(|e| ErrorKind::io_error("Cannot read reconstructed embedding length", e))?
+        as usize;
+    let n_centroids = read
+        .read_u32::<LittleEndian>()
+        .map_err(|e| ErrorKind::io_error("Cannot read number of subquantizers", e))?
+        as usize;
+    let n_embeddings = read
+        .read_u64::<LittleEndian>()
+        .map_err(|e| ErrorKind::io_error("Cannot read number of quantized embeddings", e))?
+        as usize;
+
+    check_quantizer_invariants(quantized_len, reconstructed_len)?;
+
+    // Quantized storage type.
+    let quantized_type_id = read
+        .read_u32::<LittleEndian>()
+        .map_err(|e| ErrorKind::io_error("Cannot read type identifier", e))?;
+    let packed = match quantized_type_id {
+        id if id == u8::type_id() => false,
+        id if id == PackedCodes::type_id() && n_centroids <= MAX_PACKED_CENTROIDS => true,
+        id if id == PackedCodes::type_id() => {
+            return Err(ErrorKind::Format(format!(
+                "Packed 4-bit codes cannot represent {} centroids",
+                n_centroids
+            ))
+            .into())
+        }
+        id => {
+            return Err(ErrorKind::Format(format!(
+                "Invalid type, expected: {} or {}, got: {}",
+                u8::type_id(),
+                PackedCodes::type_id(),
+                id
+            ))
+            .into())
+        }
+    };
+
+    // Reconstructed embedding type.
+    f32::ensure_data_type(read)?;
+
+    let n_padding =
+        padding::<f32>(read.stream_position().map_err(|e| {
+            ErrorKind::io_error("Cannot get file position for computing padding", e)
+        })?);
+    read.seek(SeekFrom::Current(n_padding as i64))
+        .map_err(|e| ErrorKind::io_error("Cannot skip padding", e))?;
+
+    let projection = if projection {
+        let mut projection_vec = vec![0f32; reconstructed_len * reconstructed_len];
+        read.read_f32_into::<LittleEndian>(&mut projection_vec)
+            .map_err(|e| ErrorKind::io_error("Cannot read projection matrix", e))?;
+        Some(
+            Array2::from_shape_vec((reconstructed_len, reconstructed_len), projection_vec)
+                .map_err(Error::Shape)?,
+        )
+    } else {
+        None
+    };
+
+    let quantizer_shape = (
+        quantized_len,
+        n_centroids,
+        reconstructed_len / quantized_len,
+    )
+        .into_dimension();
+    let mut quantizers = vec![0f32; quantizer_shape.size()];
+    read.read_f32_into::<LittleEndian>(&mut quantizers)
+        .map_err(|e| ErrorKind::io_error("Cannot read subquantizer", e))?;
+
+    Ok(PQRead {
+        n_embeddings,
+        packed,
+        quantizer: PQ::new(
+            projection,
+            Array::from_shape_vec(quantizer_shape, quantizers).expect("Incorrect quantizer shape"),
+        ),
+        norms_type,
+    })
+}
+
+fn write_quantized_chunk<W>(
+    write: &mut W,
+    quantizer: &PQ<f32>,
+    quantized: ArrayView2<u8>,
+    packed: bool,
+    norms: Option<ArrayView1<f32>>,
+    half_norms: bool,
+) -> Result<()>
+where
+    W: Write + Seek,
+{
+    let norms_type = NormsType::new(norms.is_some(), half_norms);
+    write_chunk_header(write, quantizer, quantized.nrows(), packed, norms_type)?;
+
+    // Write norms.
+    if let Some(ref norms) = norms {
+        write_norms(write, norms.view(), norms_type)?;
+    }
+
+    // Write quantized embedding matrix.
+    write_quantized(write, quantized)
+}
+
+/// Write the chunk header and the quantizer.
+///
+/// After writing the header, the writer is positioned where the
+/// norms (if any) and the quantized embeddings of `n_rows` rows
+/// should be written. If `packed` is `true`, the quantized
+/// embeddings should be written as packed 4-bit codes.
+fn write_chunk_header<W>(
+    write: &mut W,
+    quantizer: &PQ<f32>,
+    n_rows: usize,
+    packed: bool,
+    norms_type: NormsType,
+) -> Result<()>
+where
+    W: Write + Seek,
+{
+    write
+        .write_u32::<LittleEndian>(ChunkIdentifier::QuantizedArray as u32)
+        .map_err(|e| {
+            ErrorKind::io_error(
+                "Cannot write quantized embedding matrix chunk identifier",
+                e,
+            )
+        })?;
+
+    // projection (u32), use_norms (u32), quantized_len (u32),
+    // reconstructed_len (u32), n_centroids (u32), rows (u64),
+    // types (2 x u32 bytes), padding, projection matrix,
+    // centroids, norms, quantized data.
+    let n_padding =
+        padding::<f32>(write.stream_position().map_err(|e| {
+            ErrorKind::io_error("Cannot get file position for computing padding", e)
+        })?);
+    let chunk_size = size_of::<u32>()
+        + size_of::<u32>()
+        + size_of::<u32>()
+        + size_of::<u32>()
+        + size_of::<u32>()
+        + size_of::<u64>()
+        + 2 * size_of::<u32>()
+        + n_padding as usize
+        + quantizer.projection().is_some() as usize
+            * quantizer.reconstructed_len()
+            * quantizer.reconstructed_len()
+            * size_of::<f32>()
+        + quantizer.quantized_len()
+            * quantizer.n_quantizer_centroids()
+            * (quantizer.reconstructed_len() / quantizer.quantized_len())
+            * size_of::<f32>()
+        + n_rows * norms_type.size()
+        + n_rows * code_len(quantizer.quantized_len(), packed);
+
+    write
+        .write_u64::<LittleEndian>(chunk_size as u64)
+        .map_err(|e| {
+            ErrorKind::io_error("Cannot write quantized embedding matrix chunk length", e)
+        })?;
+
+    write
+        .write_u32::<LittleEndian>(quantizer.projection().is_some() as u32)
+        .map_err(|e| {
+            ErrorKind::io_error("Cannot write quantized embedding matrix projection", e)
+        })?;
+    write
+        .write_u32::<LittleEndian>(norms_type.header_value())
+        .map_err(|e| ErrorKind::io_error("Cannot write quantized embedding matrix norms", e))?;
+    write
+        .write_u32::<LittleEndian>(quantizer.quantized_len() as u32)
+        .map_err(|e| ErrorKind::io_error("Cannot write quantized embedding length", e))?;
+    write
+        .write_u32::<LittleEndian>(quantizer.reconstructed_len() as u32)
+        .map_err(|e| ErrorKind::io_error("Cannot write reconstructed embedding length", e))?;
+    write
+        .write_u32::<LittleEndian>(quantizer.n_quantizer_centroids() as u32)
+        .map_err(|e| ErrorKind::io_error("Cannot write number of subquantizers", e))?;
+    write
+        .write_u64::<LittleEndian>(n_rows as u64)
+        .map_err(|e| ErrorKind::io_error("Cannot write number of quantized embeddings", e))?;
+
+    // Quantized and reconstruction types.
+    write
+        .write_u32::<LittleEndian>(if packed {
+            PackedCodes::type_id()
+        } else {
+            u8::type_id()
+        })
+        .map_err(|e| ErrorKind::io_error("Cannot write quantized embedding type identifier", e))?;
+    write
+        .write_u32::<LittleEndian>(f32::type_id())
+        .map_err(|e| {
+            ErrorKind::io_error("Cannot write reconstructed embedding type identifier", e)
+        })?;
+
+    let padding = vec![0u8; n_padding as usize];
+    write
+        .write_all(&padding)
+        .map_err(|e| ErrorKind::io_error("Cannot write padding", e))?;
+
+    // Write projection matrix.
+    if let Some(projection) = quantizer.projection() {
+        for row in projection.outer_iter() {
+            for &col in row {
+                write.write_f32::<LittleEndian>(col).map_err(|e| {
+                    ErrorKind::io_error("Cannot write projection matrix component", e)
+                })?;
+            }
+        }
+    }
+
+    // Write subquantizers.
+    for subquantizer in quantizer.subquantizers().outer_iter() {
+        for row in subquantizer.outer_iter() {
+            for &col in row {
+                write
+                    .write_f32::<LittleEndian>(col)
+                    .map_err(|e| ErrorKind::io_error("Cannot write subquantizer component", e))?;
+            }
+        }
+    }
+
+    Ok(())
+}
+fn mmap_quantized_embeddings(
+    read: &mut BufReader<File>,
+    n_embeddings: usize,
+    code_len: usize,
+) -> Result<Mmap> {
+    let offset = read.stream_position().map_err(|e| {
+        ErrorKind::io_error(
+            "Cannot get file position for memory mapping embedding matrix",
+            e,
+        )
+    })?;
+    let matrix_len = n_embeddings * code_len;
+    let mut mmap_opts = MmapOptions::new();
+    let quantized = unsafe {
+        mmap_opts
+            .offset(offset)
+            .len(matrix_len)
+            .map(read.get_ref())
+            .map_err(|e| ErrorKind::io_error("Cannot memory map quantized embedding matrix", e))?
+    };
+
+    // Position the reader after the matrix.
+    read.seek(SeekFrom::Current(matrix_len as i64))
+        .map_err(|e| ErrorKind::io_error("Cannot skip quantized embedding matrix", e))?;
+
+    Ok(quantized)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::{BufReader, Cursor, Read, Seek, SeekFrom};
+
+    use byteorder::{LittleEndian, ReadBytesExt};
+    use finalfusion_core::storage::{
+        normalize_rows, quantize_rows, train_pq_sample, MmapQuantizedArray, NdArray, Quantize,
+        QuantizedArray, Storage, StorageView,
+    };
+    use ndarray::{Array1, Array2, Axis};
+    use rand::SeedableRng;
+    use rand_xorshift::XorShiftRng;
+    use reductive::pq::PQ;
+
+    use super::QuantizedArrayWriter;
+    use crate::chunks::io::{Features, MmapChunk, ReadChunk, WriteChunk};
+
+    const N_ROWS: usize = 100;
+    const N_COLS: usize = 100;
+
+    fn test_ndarray() -> NdArray {
+        let test_data = Array2::from_shape_fn((N_ROWS, N_COLS), |(r, c)| {
+            r as f32 * N_COLS as f32 + c as f32
+        });
+
+        NdArray::new(test_data)
+    }
+
+    fn test_quantized_array(norms: bool) -> QuantizedArray {
+        let ndarray = test_ndarray();
+        ndarray.quantize::<PQ<f32>>(10, 4, 5, 1, norms)
+    }
+
+    fn read_chunk_size(read: &mut impl Read) -> u64 {
+        // Skip identifier.
+        read.read_u32::<LittleEndian>().unwrap();
+
+        // Return chunk length.
+        read.read_u64::<LittleEndian>().unwrap()
+    }
+
+    fn storage_eq(arr: &impl Storage, check_arr: &impl Storage) {
+        assert_eq!(arr.shape(), check_arr.shape());
+        for idx in 0..check_arr.shape().0 {
+            assert_eq!(arr.embedding(idx).view(), check_arr.embedding(idx).view());
+        }
+    }
+
+    #[test]
+    fn quantized_array_correct_chunk_size() {
+        let check_arr = test_quantized_array(false);
+        let mut cursor = Cursor::new(Vec::new());
+        check_arr.write_chunk(&mut cursor).unwrap();
+        cursor.seek(SeekFrom::Start(0)).unwrap();
+
+        let chunk_size = read_chunk_size(&mut cursor);
+        assert_eq!(
+            cursor.read_to_end(&mut Vec::new()).unwrap(),
+            chunk_size as usize
+        );
+    }
+
+    #[test]
+    fn quantized_array_norms_correct_chunk_size() {
+        let check_arr = test_quantized_array(true);
+        let mut cursor = Cursor::new(Vec::new());
+        check_arr.write_chunk(&mut cursor).unwrap();
+        cursor.seek(SeekFrom::Start(0)).unwrap();
+
+        let chunk_size = read_chunk_size(&mut cursor);
+        assert_eq!(
+            cursor.read_to_end(&mut Vec::new()).unwrap(),
+            chunk_size as usize
+        );
+    }
+
+    #[test]
+    fn quantized_array_half_norms_read_write_roundtrip() {
+        let mut check_arr = test_quantized_array(true);
+        let mut cursor = Cursor::new(Vec::new());
+        check_arr.write_chunk(&mut cursor).unwrap();
+        let f32_norms_len = cursor.get_ref().len();
+
+        check_arr.set_half_norms(true);
+        let mut cursor = Cursor::new(Vec::new());
+        check_arr.write_chunk(&mut cursor).unwrap();
+        assert_eq!(cursor.get_ref().len(), f32_norms_len - 2 * N_ROWS);
+
+        cursor.seek(SeekFrom::Start(0)).unwrap();
+        let chunk_size = read_chunk_size(&mut cursor);
+        assert_eq!(
+            cursor.read_to_end(&mut Vec::new()).unwrap(),
+            chunk_size as usize
+        );
+
+        cursor.seek(SeekFrom::Start(0)).unwrap();
+        let arr = QuantizedArray::read_chunk(&mut cursor).unwrap();
+        assert!(arr.half_norms());
+        assert_eq!(arr.norms(), check_arr.norms());
+        storage_eq(&arr, &check_arr);
+    }
+
+    #[test]
+    fn quantized_array_required_features() {
+        let mut arr = test_quantized_array(true);
+        assert_eq!(arr.required_features(), Features::PACKED_CODES);
+        arr.set_half_norms(true);
+        assert_eq!(
+            arr.required_features(),
+            Features::PACKED_CODES | Features::HALF_NORMS
+        );
+
+        // Half precision does not apply without norms.
+        let mut arr = test_quantized_array(false);
+        arr.set_half_norms(true);
+        assert_eq!(arr.required_features(), Features::PACKED_CODES);
+    }
+
+    #[test]
+    fn quantized_array_read_write_roundtrip() {
+        let check_arr = test_quantized_array(true);
+        let mut cursor = Cursor::new(Vec::new());
+        check_arr.write_chunk(&mut cursor).unwrap();
+        cursor.seek(SeekFrom::Start(0)).unwrap();
+        let arr = QuantizedArray::read_chunk(&mut cursor).unwrap();
+        assert_eq!(arr.quantizer(), check_arr.quantizer());
+        assert_eq!(arr.quantized_embeddings(), check_arr.quantized_embeddings());
+    }
+
+    #[test]
+    fn unpacked_quantized_array_roundtrip() {
+        let data = std::fs::read("../testdata/quantized_storage.bin").unwrap();
+        let arr = QuantizedArray::read_chunk(&mut Cursor::new(&data)).unwrap();
+        assert!(!arr.packed());
+
+        let mut cursor = Cursor::new(Vec::new());
+        arr.write_chunk(&mut cursor).unwrap();
+        assert_eq!(cursor.into_inner(), data);
+    }
+
+    #[test]
+    fn mmap_packed_quantized_array() {
+        let check_arr = test_quantized_array(true);
+        let path = std::env::temp_dir().join(format!("packed-pq-{}.fifu", std::process::id()));
+        let mut data = Cursor::new(Vec::new());
+        check_arr.write_chunk(&mut data).unwrap();
+        std::fs::write(&path, data.into_inner()).unwrap();
+
+        let mut reader = BufReader::new(File::open(&path).unwrap());
+        let arr = MmapQuantizedArray::mmap_chunk(&mut reader).unwrap();
+        assert!(arr.packed());
+        storage_eq(&arr, &check_arr);
+        assert_eq!(arr.embeddings(&[3, 1]), check_arr.embeddings(&[3, 1]));
+
+        drop(arr);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn mmap_quantized_array_reconstruct() {
+        let mut storage_read =
+            BufReader::new(File::open("../testdata/quantized_storage.bin").unwrap());
+        let check_arr = QuantizedArray::read_chunk(&mut storage_read).unwrap();
+
+        storage_read.seek(SeekFrom::Start(0)).unwrap();
+        let arr = MmapQuantizedArray::mmap_chunk(&mut storage_read).unwrap();
+        storage_eq(&arr.reconstruct(), &check_arr);
+    }
+
+    #[test]
+    fn mmap_quantized_array() {
+        let mut storage_read =
+            BufReader::new(File::open("../testdata/quantized_storage.bin").unwrap());
+        let check_arr = QuantizedArray::read_chunk(&mut storage_read).unwrap();
+
+        // Memory map matrix.
+        storage_read.seek(SeekFrom::Start(0)).unwrap();
+        let arr = MmapQuantizedArray::mmap_chunk(&mut storage_read).unwrap();
+
+        // Check
+        storage_eq(&arr, &check_arr);
+    }
+
+    #[test]
+    fn write_mmap_quantized_array() {
+        // Memory map matrix.
+        let mut storage_read =
+            BufReader::new(File::open("../testdata/quantized_storage.bin").unwrap());
+        let check_arr = MmapQuantizedArray::mmap_chunk(&mut storage_read).unwrap();
+
+        // Write matrix
+        let mut cursor = Cursor::new(Vec::new());
+        check_arr.write_chunk(&mut cursor).unwrap();
+
+        // Read using non-mmap'ed reader.
+        cursor.seek(SeekFrom::Start(0)).unwrap();
+        let arr = QuantizedArray::read_chunk(&mut cursor).unwrap();
+
+        // Check
+        storage_eq(&arr, &check_arr);
+    }
+
+    #[test]
+    fn quantized_array_writer() {
+        let ndarray = test_ndarray();
+
+        for &normalize in &[false, true] {
+            let quantizer = train_pq_sample::<PQ<f32>, _, _, _>(
+                ndarray.view().outer_iter(),
+                50,
+                10,
+                4,
+                5,
+                1,
+                normalize,
+                XorShiftRng::seed_from_u64(42),
+            )
+            .unwrap();
+
+            let mut embeds = ndarray.view().to_owned();
+            let norms = if normalize {
+                Some(normalize_rows(embeds.view_mut()))
+            } else {
+                None
+            };
+            let check_arr = QuantizedArray::from_parts(
+                quantizer.clone(),
+                quantize_rows(&quantizer, embeds.view(), true),
+                true,
+                norms,
+                false,
+            );
+            let mut check_cursor = Cursor::new(Vec::new());
+            check_arr.write_chunk(&mut check_cursor).unwrap();
+
+            let mut cursor = Cursor::new(Vec::new());
+            let mut writer =
+                QuantizedArrayWriter::new(&mut cursor, quantizer, N_ROWS, normalize).unwrap();
+            for batch in ndarray.view().axis_chunks_iter(Axis(0), 30) {
+                writer.write_rows(batch).unwrap();
+            }
+            writer.finish().unwrap();
+
+            assert_eq!(cursor.into_inner(), check_cursor.into_inner());
+        }
+    }
+
+    #[test]
+    fn quantized_array_writer_checks_rows() {
+        let ndarray = test_ndarray();
+        let quantizer = test_quantized_array(false).quantizer().clone();
+
+        let mut cursor = Cursor::new(Vec::new());
+        let mut writer = QuantizedArrayWriter::new(&mut cursor, quantizer, 2, false).unwrap();
+        assert!(writer.write_row(Array1::zeros(N_COLS + 1)).is_err());
+        writer.write_row(ndarray.view().row(0)).unwrap();
+        assert!(writer
+            .write_rows(ndarray.view().slice(ndarray::s![..2, ..]))
+            .is_err());
+        assert!(writer.finish().is_err());
+    }
+}
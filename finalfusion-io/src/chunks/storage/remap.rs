@@ -0,0 +1,459 @@
+use std::fs::File;
+use std::io::{BufReader, Seek, SeekFrom};
+use std::ops::Deref;
+use std::sync::Arc;
+
+use byteorder::{LittleEndian, ReadBytesExt};
+use finalfusion_core::memory::{MemoryFootprint, MemoryUsage};
+#[cfg(target_endian = "little")]
+use finalfusion_core::storage::StorageView;
+use finalfusion_core::storage::{
+    AccessPattern, Advise, LockMemory, MmapArray, NdArray, SelectRows, Storage,
+};
+#[cfg(target_endian = "little")]
+use ndarray::ArrayView2;
+use ndarray::{Array2, ArrayViewMut1, CowArray, Ix1};
+
+use crate::chunks::io::{ChunkIdentifier, Header, MmapChunk, ReadChunk};
+use crate::io::{ErrorKind, Result};
+
+/// Memory-mapped matrix that can be remapped when its file grows.
+///
+/// This storage supports workflows where rows are appended to an
+/// embedding matrix while it is being served. Every mapping of the
+/// matrix is an *epoch*. `remap` re-reads the matrix header and maps
+/// the matrix again when its shape has changed, starting a new epoch.
+///
+/// The embedding matrix must be the last chunk of the file, so that
+/// growing the matrix does not overwrite other chunks. This is the
+/// case for storage-only files and for embeddings that are written
+/// without norms, counts, and unknown chunks. `mmap_chunk` returns an
+/// error for other files. Since the vocabulary is not remapped, the
+/// rows that are appended to embeddings are only accessible through
+/// the storage.
+///
+/// Readers never observe torn rows as long as the writer only appends
+/// to the matrix and updates the number of rows in the chunk header
+/// after the new rows are written. The number of columns cannot
+/// change and the file must not be truncated while it is mapped.
+///
+/// All accessors use the current epoch. Since `remap` requires a
+/// mutable reference, the epoch cannot change while the storage is
+/// borrowed. Served embeddings can be remapped behind a lock with
+/// `RemapEmbeddings`, readers that should not block remapping can
+/// take a `snapshot` of the current epoch, which remains valid and
+/// unchanged until it is dropped.
+#[derive(Debug)]
+pub struct RemappableMmapArray {
+    file: BufReader<File>,
+    offset: u64,
+    current: MmapArrayEpoch,
+}
+
+impl RemappableMmapArray {
+    /// Get the number of the current epoch.
+    ///
+    /// The first mapping of the matrix is epoch 0.
+    pub fn epoch(&self) -> u64 {
+        self.current.epoch
+    }
+
+    /// Remap the matrix when its shape has changed.
+    ///
+    /// Returns `true` when the matrix was remapped. Snapshots of
+    /// earlier epochs remain valid.
+    pub fn remap(&mut self) -> Result<bool> {
+        self.file
+            .seek(SeekFrom::Start(self.offset))
+            .map_err(|e| ErrorKind::io_error("Cannot seek to embedding matrix chunk", e))?;
+
+        let array = MmapArray::mmap_chunk(&mut self.file)?;
+        let (rows, cols) = array.shape();
+
+        let (cur_rows, cur_cols) = self.current.shape();
+        if cols != cur_cols {
+            return Err(ErrorKind::Format(format!(
+                "Number of columns changed from {} to {}",
+                cur_cols, cols
+            ))
+            .into());
+        }
+        if rows < cur_rows {
+            return Err(ErrorKind::Format(format!(
+                "Number of rows decreased from {} to {}",
+                cur_rows, rows
+            ))
+            .into());
+        }
+        if rows == cur_rows {
+            return Ok(false);
+        }
+
+        self.current = MmapArrayEpoch {
+            epoch: self.current.epoch + 1,
+            array: Arc::new(array),
+        };
+
+        Ok(true)
+    }
+
+    /// Get a snapshot of the current epoch.
+    pub fn snapshot(&self) -> MmapArrayEpoch {
+        self.current.clone()
+    }
+}
+
+impl MmapChunk for RemappableMmapArray {
+    fn mmap_chunk(read: &mut BufReader<File>) -> Result<Self> {
+        let offset = read
+            .stream_position()
+            .map_err(|e| ErrorKind::io_error("Cannot get file position of embedding matrix", e))?;
+        check_last_chunk(read, offset)?;
+
+        let file = read
+            .get_ref()
+            .try_clone()
+            .map_err(|e| ErrorKind::io_error("Cannot duplicate embedding matrix file handle", e))?;
+
+        let array = MmapArray::mmap_chunk(read)?;
+
+        Ok(RemappableMmapArray {
+            file: BufReader::new(file),
+            offset,
+            current: MmapArrayEpoch {
+                epoch: 0,
+                array: Arc::new(array),
+            },
+        })
+    }
+}
+
+/// Check that the embedding matrix at `offset` is the last chunk.
+///
+/// The reader is positioned at `offset` afterwards.
+fn check_last_chunk(read: &mut BufReader<File>, offset: u64) -> Result<()> {
+    read.seek(SeekFrom::Start(0))
+        .map_err(|e| ErrorKind::io_error("Cannot seek to header", e))?;
+    let header = Header::read_chunk(read)?;
+
+    let chunks = header.chunk_identifiers();
+    if chunks.last() != Some(&ChunkIdentifier::NdArray)
+        || !header.unknown_identifiers().is_empty()
+        || !header.custom_identifiers().is_empty()
+    {
+        return Err(ErrorKind::Format(
+            "Remappable matrices require a file with the embedding matrix as its last chunk"
+                .to_string(),
+        )
+        .into());
+    }
+
+    // Skip the chunks before the embedding matrix.
+    for _ in 1..chunks.len() {
+        read.read_u32::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read chunk identifier", e))?;
+        let chunk_len = read
+            .read_u64::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read chunk length", e))?;
+        read.seek(SeekFrom::Current(chunk_len as i64))
+            .map_err(|e| ErrorKind::io_error("Cannot skip chunk", e))?;
+    }
+
+    let matrix_offset = read
+        .stream_position()
+        .map_err(|e| ErrorKind::io_error("Cannot get file position of last chunk", e))?;
+    if matrix_offset != offset {
+        return Err(ErrorKind::Format(format!(
+            "Embedding matrix chunk at offset {} is not the last chunk at offset {}",
+            offset, matrix_offset
+        ))
+        .into());
+    }
+
+    Ok(())
+}
+
+impl Storage for RemappableMmapArray {
+    fn embedding(&self, idx: usize) -> CowArray<'_, f32, Ix1> {
+        self.current.embedding(idx)
+    }
+
+    fn embedding_into(&self, idx: usize, out: ArrayViewMut1<f32>) {
+        self.current.embedding_into(idx, out)
+    }
+
+    fn embeddings(&self, indices: &[usize]) -> Array2<f32> {
+        self.current.embeddings(indices)
+    }
+
+    fn shape(&self) -> (usize, usize) {
+        self.current.shape()
+    }
+}
+
+impl Advise for RemappableMmapArray {
+    /// Advise the operating system of the access pattern of the
+    /// current epoch.
+    fn advise(&self, pattern: AccessPattern) -> Result<()> {
+        self.current.advise(pattern)
+    }
+
+    /// Prefault the pages of the current epoch.
+    fn prefault(&self) {
+        self.current.prefault()
+    }
+}
+
+impl LockMemory for RemappableMmapArray {
+    /// Lock the current epoch in memory.
+    ///
+    /// Epochs that are mapped later are not locked.
+    fn lock_memory(&self) -> Result<()> {
+        self.current.lock_memory()
+    }
+
+    /// Unlock the current epoch.
+    fn unlock_memory(&self) -> Result<()> {
+        self.current.unlock_memory()
+    }
+}
+
+impl SelectRows for RemappableMmapArray {
+    type Output = NdArray;
+
+    /// Select the embeddings at the given indices.
+    ///
+    /// The embeddings are selected from the current epoch.
+    fn select_rows(&self, indices: &[usize]) -> NdArray {
+        NdArray::new(self.embeddings(indices))
+    }
+}
+
+impl MemoryUsage for RemappableMmapArray {
+    /// Get the memory usage of the current epoch.
+    ///
+    /// Mappings of earlier epochs that are still referenced by
+    /// snapshots are not included.
+    fn memory_usage(&self) -> MemoryFootprint {
+        self.current.memory_usage()
+    }
+}
+
+#[cfg(target_endian = "little")]
+impl StorageView for RemappableMmapArray {
+    fn view(&self) -> ArrayView2<'_, f32> {
+        self.current.view()
+    }
+}
+
+/// Snapshot of an epoch of a `RemappableMmapArray`.
+#[derive(Clone, Debug)]
+pub struct MmapArrayEpoch {
+    epoch: u64,
+    array: Arc<MmapArray>,
+}
+
+impl MmapArrayEpoch {
+    /// Get the number of the epoch.
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+}
+
+impl Deref for MmapArrayEpoch {
+    type Target = MmapArray;
+
+    fn deref(&self) -> &Self::Target {
+        &self.array
+    }
+}
+
+impl Storage for MmapArrayEpoch {
+    fn embedding(&self, idx: usize) -> CowArray<'_, f32, Ix1> {
+        self.array.embedding(idx)
+    }
+
+    fn embedding_into(&self, idx: usize, out: ArrayViewMut1<f32>) {
+        self.array.embedding_into(idx, out)
+    }
+
+    fn embeddings(&self, indices: &[usize]) -> Array2<f32> {
+        self.array.embeddings(indices)
+    }
+
+    fn shape(&self) -> (usize, usize) {
+        self.array.shape()
+    }
+}
+
+impl Advise for MmapArrayEpoch {
+    fn advise(&self, pattern: AccessPattern) -> Result<()> {
+        self.array.advise(pattern)
+    }
+
+    fn prefault(&self) {
+        self.array.prefault()
+    }
+}
+
+impl LockMemory for MmapArrayEpoch {
+    fn lock_memory(&self) -> Result<()> {
+        self.array.lock_memory()
+    }
+
+    fn unlock_memory(&self) -> Result<()> {
+        self.array.unlock_memory()
+    }
+}
+
+impl MemoryUsage for MmapArrayEpoch {
+    fn memory_usage(&self) -> MemoryFootprint {
+        self.array.memory_usage()
+    }
+}
+
+#[cfg(target_endian = "little")]
+impl StorageView for MmapArrayEpoch {
+    fn view(&self) -> ArrayView2<'_, f32> {
+        self.array.view()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, File, OpenOptions};
+    use std::io::{BufReader, Cursor, Write};
+    use std::path::PathBuf;
+
+    use finalfusion_core::embeddings::Embeddings;
+    use finalfusion_core::norms::NdNorms;
+    use finalfusion_core::storage::{NdArray, Storage};
+    use finalfusion_core::vocab::{SimpleVocab, Vocab};
+    use ndarray::{Array1, Array2};
+
+    use super::RemappableMmapArray;
+    use crate::chunks::io::{ChunkIdentifier, Header, MmapChunk, ReadChunk, WriteChunk};
+    use crate::io::{MmapEmbeddings, RemapEmbeddings, WriteEmbeddings};
+
+    fn test_ndarray(rows: usize) -> NdArray {
+        NdArray::new(Array2::from_shape_fn((rows, 10), |(r, c)| {
+            r as f32 * 10. + c as f32
+        }))
+    }
+
+    fn overwrite(path: &PathBuf, data: &[u8]) {
+        // Overwrite in place, the file must not be truncated while mapped.
+        let mut file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(false)
+            .open(path)
+            .unwrap();
+        file.write_all(data).unwrap();
+    }
+
+    fn write_ndarray(path: &PathBuf, rows: usize) {
+        let mut data = Vec::new();
+        let mut cursor = Cursor::new(&mut data);
+        Header::new(vec![ChunkIdentifier::NdArray])
+            .write_chunk(&mut cursor)
+            .unwrap();
+        test_ndarray(rows).write_chunk(&mut cursor).unwrap();
+        overwrite(path, &data);
+    }
+
+    /// Write embeddings of which the matrix has `rows` rows, of which
+    /// the first two are in the vocabulary.
+    fn write_embeddings(path: &PathBuf, rows: usize) {
+        let mut data = Vec::new();
+        let mut cursor = Cursor::new(&mut data);
+        Header::new(vec![ChunkIdentifier::SimpleVocab, ChunkIdentifier::NdArray])
+            .write_chunk(&mut cursor)
+            .unwrap();
+        SimpleVocab::new(vec!["a".to_string(), "b".to_string()])
+            .write_chunk(&mut cursor)
+            .unwrap();
+        test_ndarray(rows).write_chunk(&mut cursor).unwrap();
+        overwrite(path, &data);
+    }
+
+    fn mmap_remappable(path: &PathBuf) -> crate::io::Result<RemappableMmapArray> {
+        let mut reader = BufReader::new(File::open(path).unwrap());
+        Header::read_chunk(&mut reader)?;
+        RemappableMmapArray::mmap_chunk(&mut reader)
+    }
+
+    #[test]
+    fn remap_on_growth() {
+        let path = std::env::temp_dir().join(format!("remap-{}.fifu", std::process::id()));
+        let _ = fs::remove_file(&path);
+        write_ndarray(&path, 5);
+
+        let mut arr = mmap_remappable(&path).unwrap();
+        assert_eq!(arr.epoch(), 0);
+        assert_eq!(arr.shape(), (5, 10));
+        assert!(!arr.remap().unwrap());
+
+        let old = arr.snapshot();
+        write_ndarray(&path, 8);
+        assert!(arr.remap().unwrap());
+        assert_eq!(arr.epoch(), 1);
+        assert_eq!(arr.shape(), (8, 10));
+        assert_eq!(arr.embedding(7), test_ndarray(8).embedding(7));
+
+        // Old snapshots are unaffected.
+        assert_eq!(old.epoch(), 0);
+        assert_eq!(old.shape(), (5, 10));
+        assert_eq!(old.embedding(4), test_ndarray(5).embedding(4));
+
+        drop(old);
+        drop(arr);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn remap_embeddings_on_growth() {
+        let path = std::env::temp_dir().join(format!("remap-embeds-{}.fifu", std::process::id()));
+        let _ = fs::remove_file(&path);
+        write_embeddings(&path, 2);
+
+        let mut reader = BufReader::new(File::open(&path).unwrap());
+        let mut embeds: Embeddings<SimpleVocab, RemappableMmapArray> =
+            Embeddings::mmap_embeddings(&mut reader).unwrap();
+        assert_eq!(embeds.vocab().words_len(), 2);
+        assert_eq!(embeds.storage().shape(), (2, 10));
+
+        write_embeddings(&path, 4);
+        assert!(embeds.remap().unwrap());
+        assert_eq!(embeds.storage().epoch(), 1);
+        assert_eq!(embeds.storage().shape(), (4, 10));
+        assert_eq!(embeds.storage().embedding(3), test_ndarray(4).embedding(3));
+        assert_eq!(embeds.embedding("b").unwrap(), test_ndarray(4).embedding(1));
+
+        drop(embeds);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rejects_files_with_chunks_after_the_matrix() {
+        let path = std::env::temp_dir().join(format!("remap-norms-{}.fifu", std::process::id()));
+        let embeds = Embeddings::new(
+            None,
+            SimpleVocab::new(vec!["a".to_string(), "b".to_string()]),
+            test_ndarray(2),
+            NdNorms::new(Array1::ones(2)),
+        );
+        let mut data = Vec::new();
+        embeds
+            .write_embeddings(&mut Cursor::new(&mut data))
+            .unwrap();
+        fs::write(&path, &data).unwrap();
+
+        let mut reader = BufReader::new(File::open(&path).unwrap());
+        assert!(
+            Embeddings::<SimpleVocab, RemappableMmapArray>::mmap_embeddings(&mut reader).is_err()
+        );
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,389 @@
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::mem::size_of;
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use finalfusion_core::storage::{
+    code_len, quantizer_size, ResidualQuantizedArray, SelectRows, MAX_COARSE_CENTROIDS,
+    MAX_PACKED_CENTROIDS,
+};
+use finalfusion_core::util::padding;
+use ndarray::{Array, Array1, Array2};
+use reductive::pq::{QuantizeVector, ReconstructVector, PQ};
+
+use super::quantized::{codes_features, write_quantized};
+use crate::chunks::io::{
+    ChunkIdentifier, Features, PackedCodes, ReadChunk, TypeId, WriteChunk, WriteChunkRows,
+};
+use crate::io::{Error, ErrorKind, Result};
+
+impl ReadChunk for ResidualQuantizedArray {
+    fn read_chunk<R>(read: &mut R) -> Result<Self>
+    where
+        R: Read + Seek,
+    {
+        ChunkIdentifier::ensure_chunk_type(read, ChunkIdentifier::ResidualQuantizedArray)?;
+
+        // Read and discard chunk length.
+        read.read_u64::<LittleEndian>().map_err(|e| {
+            ErrorKind::io_error("Cannot read residual quantized matrix chunk length", e)
+        })?;
+
+        let n_embeddings = read
+            .read_u64::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read number of quantized embeddings", e))?
+            as usize;
+        let n_coarse_centroids = read
+            .read_u32::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read number of coarse centroids", e))?
+            as usize;
+        let projection = read.read_u32::<LittleEndian>().map_err(|e| {
+            ErrorKind::io_error("Cannot read quantized embedding matrix projection", e)
+        })? != 0;
+        let read_norms = read
+            .read_u32::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read quantized embedding matrix norms", e))?
+            != 0;
+        let quantized_len = read
+            .read_u32::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read quantized embedding length", e))?
+            as usize;
+        let reconstructed_len = read
+            .read_u32::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read reconstructed embedding length", e))?
+            as usize;
+        let n_centroids = read
+            .read_u32::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read number of subquantizers", e))?
+            as usize;
+
+        if quantized_len == 0 || reconstructed_len % quantized_len != 0 {
+            return Err(ErrorKind::Format(format!(
+                "Reconstructed embedding length ({}) not a multiple of the quantized embedding length: ({})",
+                reconstructed_len, quantized_len
+            ))
+            .into());
+        }
+
+        if n_coarse_centroids == 0 || n_coarse_centroids > MAX_COARSE_CENTROIDS {
+            return Err(ErrorKind::Format(format!(
+                "Number of coarse centroids should be between 1 and {}, got: {}",
+                MAX_COARSE_CENTROIDS, n_coarse_centroids
+            ))
+            .into());
+        }
+
+        let quantized_type_id = read
+            .read_u32::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read type identifier", e))?;
+        let packed = match quantized_type_id {
+            id if id == u8::type_id() => false,
+            id if id == PackedCodes::type_id() && n_centroids <= MAX_PACKED_CENTROIDS => true,
+            id => {
+                return Err(ErrorKind::Format(format!(
+                    "Invalid type for {} centroids, expected: {} or {}, got: {}",
+                    n_centroids,
+                    u8::type_id(),
+                    PackedCodes::type_id(),
+                    id
+                ))
+                .into())
+            }
+        };
+
+        f32::ensure_data_type(read)?;
+
+        let n_padding = padding::<f32>(read.stream_position().map_err(|e| {
+            ErrorKind::io_error("Cannot get file position for computing padding", e)
+        })?);
+        read.seek(SeekFrom::Current(n_padding as i64))
+            .map_err(|e| ErrorKind::io_error("Cannot skip padding", e))?;
+
+        let coarse_centroids = read_f32_matrix(
+            read,
+            (n_coarse_centroids, reconstructed_len),
+            "Cannot read coarse centroids",
+        )?;
+
+        let projection = if projection {
+            Some(read_f32_matrix(
+                read,
+                (reconstructed_len, reconstructed_len),
+                "Cannot read projection matrix",
+            )?)
+        } else {
+            None
+        };
+
+        let mut subquantizers =
+            vec![0f32; quantized_len * n_centroids * (reconstructed_len / quantized_len)];
+        read.read_f32_into::<LittleEndian>(&mut subquantizers)
+            .map_err(|e| ErrorKind::io_error("Cannot read subquantizer", e))?;
+        let subquantizers = Array::from_shape_vec(
+            (
+                quantized_len,
+                n_centroids,
+                reconstructed_len / quantized_len,
+            ),
+            subquantizers,
+        )
+        .map_err(Error::Shape)?;
+
+        let norms = if read_norms {
+            let mut norms = vec![0f32; n_embeddings];
+            read.read_f32_into::<LittleEndian>(&mut norms)
+                .map_err(|e| ErrorKind::io_error("Cannot read norms", e))?;
+            Some(Array1::from(norms))
+        } else {
+            None
+        };
+
+        let mut coarse_codes = vec![0u8; n_embeddings];
+        read.read_exact(&mut coarse_codes)
+            .map_err(|e| ErrorKind::io_error("Cannot read coarse codes", e))?;
+        if let Some(&code) = coarse_codes
+            .iter()
+            .find(|&&code| code as usize >= n_coarse_centroids)
+        {
+            return Err(ErrorKind::Format(format!(
+                "Coarse code {} is out of bounds for {} coarse centroids",
+                code, n_coarse_centroids
+            ))
+            .into());
+        }
+
+        let code_len = code_len(quantized_len, packed);
+        let mut quantized_embeddings = vec![0u8; n_embeddings * code_len];
+        read.read_exact(&mut quantized_embeddings)
+            .map_err(|e| ErrorKind::io_error("Cannot read quantized embeddings", e))?;
+
+        Ok(ResidualQuantizedArray::from_parts(
+            coarse_centroids,
+            Array1::from(coarse_codes),
+            PQ::new(projection, subquantizers),
+            Array2::from_shape_vec((n_embeddings, code_len), quantized_embeddings)
+                .map_err(Error::Shape)?,
+            packed,
+            norms,
+        ))
+    }
+}
+
+impl WriteChunk for ResidualQuantizedArray {
+    fn chunk_identifier(&self) -> ChunkIdentifier {
+        ChunkIdentifier::ResidualQuantizedArray
+    }
+
+    fn write_chunk<W>(&self, write: &mut W) -> Result<()>
+    where
+        W: Write + Seek,
+    {
+        write
+            .write_u32::<LittleEndian>(ChunkIdentifier::ResidualQuantizedArray as u32)
+            .map_err(|e| {
+                ErrorKind::io_error("Cannot write residual quantized matrix chunk identifier", e)
+            })?;
+
+        let n_padding = padding::<f32>(write.stream_position().map_err(|e| {
+            ErrorKind::io_error("Cannot get file position for computing padding", e)
+        })?);
+
+        // Chunk size: rows (u64), n_coarse_centroids (u32),
+        // projection (u32), use_norms (u32), quantized_len (u32),
+        // reconstructed_len (u32), n_centroids (u32), types (2 x u32),
+        // padding, coarse centroids, projection matrix, centroids,
+        // norms, coarse codes, quantized data.
+        let n_rows = self.quantized_embeddings().nrows();
+        let chunk_len = size_of::<u64>()
+            + 6 * size_of::<u32>()
+            + 2 * size_of::<u32>()
+            + n_padding as usize
+            + self.coarse_centroids().len() * size_of::<f32>()
+            + quantizer_size(self.quantizer())
+            + self
+                .norms()
+                .map_or(0, |norms| norms.len() * size_of::<f32>())
+            + n_rows
+            + self.quantized_embeddings().len();
+
+        write
+            .write_u64::<LittleEndian>(chunk_len as u64)
+            .map_err(|e| {
+                ErrorKind::io_error("Cannot write residual quantized matrix chunk length", e)
+            })?;
+        write
+            .write_u64::<LittleEndian>(n_rows as u64)
+            .map_err(|e| ErrorKind::io_error("Cannot write number of quantized embeddings", e))?;
+        write
+            .write_u32::<LittleEndian>(self.coarse_centroids().nrows() as u32)
+            .map_err(|e| ErrorKind::io_error("Cannot write number of coarse centroids", e))?;
+        write
+            .write_u32::<LittleEndian>(self.quantizer().projection().is_some() as u32)
+            .map_err(|e| {
+                ErrorKind::io_error("Cannot write quantized embedding matrix projection", e)
+            })?;
+        write
+            .write_u32::<LittleEndian>(self.norms().is_some() as u32)
+            .map_err(|e| ErrorKind::io_error("Cannot write quantized embedding matrix norms", e))?;
+        write
+            .write_u32::<LittleEndian>(self.quantizer().quantized_len() as u32)
+            .map_err(|e| ErrorKind::io_error("Cannot write quantized embedding length", e))?;
+        write
+            .write_u32::<LittleEndian>(self.quantizer().reconstructed_len() as u32)
+            .map_err(|e| ErrorKind::io_error("Cannot write reconstructed embedding length", e))?;
+        write
+            .write_u32::<LittleEndian>(self.quantizer().n_quantizer_centroids() as u32)
+            .map_err(|e| ErrorKind::io_error("Cannot write number of subquantizers", e))?;
+        write
+            .write_u32::<LittleEndian>(if self.packed() {
+                PackedCodes::type_id()
+            } else {
+                u8::type_id()
+            })
+            .map_err(|e| {
+                ErrorKind::io_error("Cannot write quantized embedding type identifier", e)
+            })?;
+        write
+            .write_u32::<LittleEndian>(f32::type_id())
+            .map_err(|e| {
+                ErrorKind::io_error("Cannot write reconstructed embedding type identifier", e)
+            })?;
+
+        let padding = vec![0u8; n_padding as usize];
+        write
+            .write_all(&padding)
+            .map_err(|e| ErrorKind::io_error("Cannot write padding", e))?;
+
+        for &component in self.coarse_centroids().iter() {
+            write
+                .write_f32::<LittleEndian>(component)
+                .map_err(|e| ErrorKind::io_error("Cannot write coarse centroid component", e))?;
+        }
+
+        if let Some(projection) = self.quantizer().projection() {
+            for &component in projection.iter() {
+                write.write_f32::<LittleEndian>(component).map_err(|e| {
+                    ErrorKind::io_error("Cannot write projection matrix component", e)
+                })?;
+            }
+        }
+
+        for &component in self.quantizer().subquantizers().iter() {
+            write
+                .write_f32::<LittleEndian>(component)
+                .map_err(|e| ErrorKind::io_error("Cannot write subquantizer component", e))?;
+        }
+
+        if let Some(norms) = self.norms() {
+            for &norm in norms {
+                write
+                    .write_f32::<LittleEndian>(norm)
+                    .map_err(|e| ErrorKind::io_error("Cannot write norm vector component", e))?;
+            }
+        }
+
+        write
+            .write_all(
+                self.coarse_codes()
+                    .as_slice()
+                    .expect("Coarse codes are not contiguous"),
+            )
+            .map_err(|e| ErrorKind::io_error("Cannot write coarse codes", e))?;
+
+        write_quantized(write, self.quantized_embeddings())
+    }
+
+    fn required_features(&self) -> Features {
+        codes_features(self.packed())
+    }
+}
+
+impl WriteChunkRows for ResidualQuantizedArray {
+    /// Write the given rows of the quantized matrix.
+    ///
+    /// The coarse and residual codes of the selected rows are copied,
+    /// the embeddings are not reconstructed.
+    fn write_chunk_rows<W>(&self, rows: &[usize], write: &mut W) -> Result<()>
+    where
+        W: Write + Seek,
+    {
+        self.select_rows(rows).write_chunk(write)
+    }
+}
+
+fn read_f32_matrix<R>(read: &mut R, shape: (usize, usize), error: &str) -> Result<Array2<f32>>
+where
+    R: Read,
+{
+    let mut data = vec![0f32; shape.0 * shape.1];
+    read.read_f32_into::<LittleEndian>(&mut data)
+        .map_err(|e| ErrorKind::io_error(error, e))?;
+    Array2::from_shape_vec(shape, data).map_err(Error::Shape)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read, Seek, SeekFrom};
+
+    use byteorder::{LittleEndian, ReadBytesExt};
+    use finalfusion_core::storage::{NdArray, QuantizeResidual, ResidualQuantizedArray};
+    use ndarray::Array2;
+    use rand::SeedableRng;
+    use rand_xorshift::XorShiftRng;
+    use reductive::pq::PQ;
+
+    use crate::chunks::io::{ReadChunk, WriteChunk};
+
+    const N_ROWS: usize = 100;
+    const N_COLS: usize = 20;
+
+    fn test_ndarray() -> NdArray {
+        // Embeddings in four clusters.
+        let test_data = Array2::from_shape_fn((N_ROWS, N_COLS), |(r, c)| {
+            (r % 4 * 10) as f32 + ((r * N_COLS + c) % 7) as f32 / 7.
+        });
+
+        NdArray::new(test_data)
+    }
+
+    fn test_residual_quantized_array(norms: bool) -> ResidualQuantizedArray {
+        test_ndarray().quantize_residual_using::<PQ<f32>, _>(
+            4,
+            5,
+            4,
+            10,
+            1,
+            norms,
+            XorShiftRng::seed_from_u64(42),
+        )
+    }
+
+    #[test]
+    fn residual_quantized_array_correct_chunk_size() {
+        let check_arr = test_residual_quantized_array(true);
+        let mut cursor = Cursor::new(Vec::new());
+        check_arr.write_chunk(&mut cursor).unwrap();
+        cursor.seek(SeekFrom::Start(4)).unwrap();
+
+        let chunk_size = cursor.read_u64::<LittleEndian>().unwrap();
+        assert_eq!(
+            cursor.read_to_end(&mut Vec::new()).unwrap(),
+            chunk_size as usize
+        );
+    }
+
+    #[test]
+    fn residual_quantized_array_read_write_roundtrip() {
+        for &norms in &[false, true] {
+            let check_arr = test_residual_quantized_array(norms);
+            let mut cursor = Cursor::new(Vec::new());
+            check_arr.write_chunk(&mut cursor).unwrap();
+            cursor.seek(SeekFrom::Start(0)).unwrap();
+            let arr = ResidualQuantizedArray::read_chunk(&mut cursor).unwrap();
+            assert_eq!(arr.coarse_centroids(), check_arr.coarse_centroids());
+            assert_eq!(arr.coarse_codes(), check_arr.coarse_codes());
+            assert_eq!(arr.quantizer(), check_arr.quantizer());
+            assert_eq!(arr.quantized_embeddings(), check_arr.quantized_embeddings());
+            assert_eq!(arr.norms(), check_arr.norms());
+        }
+    }
+}
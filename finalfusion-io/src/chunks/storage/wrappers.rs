@@ -0,0 +1,317 @@
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom, Write};
+
+use byteorder::{LittleEndian, ReadBytesExt};
+use finalfusion_core::storage::{
+    DedupArray, Element, MmapArray, MmapQuantizedArray, NdArray, PreadArray, QuantizedArray,
+    ResidualQuantizedArray, StorageViewWrap, StorageWrap,
+};
+
+use super::array::ndarray_type_id;
+use crate::chunks::io::{
+    ChunkIdentifier, Features, MmapChunk, PreadChunk, ReadChunk, WriteChunk, WriteChunkRows,
+};
+use crate::io::{Error, ErrorKind, Result};
+
+impl ReadChunk for StorageWrap {
+    fn read_chunk<R>(read: &mut R) -> Result<Self>
+    where
+        R: Read + Seek,
+    {
+        let chunk_start_pos = read
+            .stream_position()
+            .map_err(|e| ErrorKind::io_error("Cannot get storage chunk start position", e))?;
+
+        let chunk_id = read
+            .read_u32::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read storage chunk identifier", e))?;
+        let chunk_id = ChunkIdentifier::try_from(chunk_id)
+            .ok_or_else(|| ErrorKind::Format(format!("Unknown chunk identifier: {}", chunk_id)))
+            .map_err(Error::from)?;
+
+        read.seek(SeekFrom::Start(chunk_start_pos))
+            .map_err(|e| ErrorKind::io_error("Cannot seek to storage chunk start position", e))?;
+
+        match chunk_id {
+            ChunkIdentifier::NdArray => match ndarray_type_id(read)? {
+                f64::TYPE_ID => NdArray::read_chunk(read).map(StorageWrap::NdArrayF64),
+                _ => NdArray::read_chunk(read).map(StorageWrap::NdArray),
+            },
+            ChunkIdentifier::QuantizedArray => QuantizedArray::read_chunk(read)
+                .map(Box::new)
+                .map(StorageWrap::QuantizedArray),
+            ChunkIdentifier::DedupArray => {
+                DedupArray::read_chunk(read).map(StorageWrap::DedupArray)
+            }
+            ChunkIdentifier::ResidualQuantizedArray => ResidualQuantizedArray::read_chunk(read)
+                .map(Box::new)
+                .map(StorageWrap::ResidualQuantizedArray),
+            _ => Err(ErrorKind::Format(format!(
+                "Invalid chunk identifier, expected one of: {}, {}, {}, or {}, got: {}",
+                ChunkIdentifier::NdArray,
+                ChunkIdentifier::QuantizedArray,
+                ChunkIdentifier::DedupArray,
+                ChunkIdentifier::ResidualQuantizedArray,
+                chunk_id
+            ))
+            .into()),
+        }
+    }
+}
+
+impl MmapChunk for StorageWrap {
+    /// Memory map a storage chunk.
+    ///
+    /// Deduplicated and residual quantized matrices are read into
+    /// memory.
+    fn mmap_chunk(read: &mut BufReader<File>) -> Result<Self> {
+        let chunk_start_pos = read
+            .stream_position()
+            .map_err(|e| ErrorKind::io_error("Cannot get storage chunk start position", e))?;
+
+        let chunk_id = read
+            .read_u32::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read storage chunk identifier", e))?;
+        let chunk_id = ChunkIdentifier::try_from(chunk_id)
+            .ok_or_else(|| ErrorKind::Format(format!("Unknown chunk identifier: {}", chunk_id)))
+            .map_err(Error::from)?;
+
+        read.seek(SeekFrom::Start(chunk_start_pos))
+            .map_err(|e| ErrorKind::io_error("Cannot seek to storage chunk start position", e))?;
+
+        match chunk_id {
+            ChunkIdentifier::NdArray => match ndarray_type_id(read)? {
+                f64::TYPE_ID => MmapArray::mmap_chunk(read).map(StorageWrap::MmapArrayF64),
+                _ => MmapArray::mmap_chunk(read).map(StorageWrap::MmapArray),
+            },
+            ChunkIdentifier::QuantizedArray => {
+                MmapQuantizedArray::mmap_chunk(read).map(StorageWrap::MmapQuantizedArray)
+            }
+            ChunkIdentifier::DedupArray => {
+                DedupArray::read_chunk(read).map(StorageWrap::DedupArray)
+            }
+            ChunkIdentifier::ResidualQuantizedArray => ResidualQuantizedArray::read_chunk(read)
+                .map(Box::new)
+                .map(StorageWrap::ResidualQuantizedArray),
+            _ => Err(ErrorKind::Format(format!(
+                "Invalid chunk identifier, expected one of: {}, {}, {}, or {}, got: {}",
+                ChunkIdentifier::NdArray,
+                ChunkIdentifier::QuantizedArray,
+                ChunkIdentifier::DedupArray,
+                ChunkIdentifier::ResidualQuantizedArray,
+                chunk_id
+            ))
+            .into()),
+        }
+    }
+}
+
+impl PreadChunk for StorageWrap {
+    /// Open a storage chunk for positioned reads.
+    ///
+    /// Quantized matrices are small enough to be read into memory,
+    /// so they are read as a `QuantizedArray` or
+    /// `ResidualQuantizedArray`. Positioned reads only
+    /// support `f32` matrices, `f64` matrices and deduplicated
+    /// matrices are read into memory.
+    fn pread_chunk(read: &mut BufReader<File>) -> Result<Self> {
+        let chunk_start_pos = read
+            .stream_position()
+            .map_err(|e| ErrorKind::io_error("Cannot get storage chunk start position", e))?;
+
+        let chunk_id = read
+            .read_u32::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read storage chunk identifier", e))?;
+        let chunk_id = ChunkIdentifier::try_from(chunk_id)
+            .ok_or_else(|| ErrorKind::Format(format!("Unknown chunk identifier: {}", chunk_id)))
+            .map_err(Error::from)?;
+
+        read.seek(SeekFrom::Start(chunk_start_pos))
+            .map_err(|e| ErrorKind::io_error("Cannot seek to storage chunk start position", e))?;
+
+        match chunk_id {
+            ChunkIdentifier::NdArray => match ndarray_type_id(read)? {
+                f64::TYPE_ID => NdArray::read_chunk(read).map(StorageWrap::NdArrayF64),
+                _ => PreadArray::pread_chunk(read).map(StorageWrap::PreadArray),
+            },
+            ChunkIdentifier::QuantizedArray => QuantizedArray::read_chunk(read)
+                .map(Box::new)
+                .map(StorageWrap::QuantizedArray),
+            ChunkIdentifier::DedupArray => {
+                DedupArray::read_chunk(read).map(StorageWrap::DedupArray)
+            }
+            ChunkIdentifier::ResidualQuantizedArray => ResidualQuantizedArray::read_chunk(read)
+                .map(Box::new)
+                .map(StorageWrap::ResidualQuantizedArray),
+            _ => Err(ErrorKind::Format(format!(
+                "Invalid chunk identifier, expected one of: {}, {}, {}, or {}, got: {}",
+                ChunkIdentifier::NdArray,
+                ChunkIdentifier::QuantizedArray,
+                ChunkIdentifier::DedupArray,
+                ChunkIdentifier::ResidualQuantizedArray,
+                chunk_id
+            ))
+            .into()),
+        }
+    }
+}
+
+impl WriteChunk for StorageWrap {
+    fn chunk_identifier(&self) -> ChunkIdentifier {
+        match self {
+            StorageWrap::DedupArray(inner) => inner.chunk_identifier(),
+            StorageWrap::MmapArray(inner) => inner.chunk_identifier(),
+            StorageWrap::MmapArrayF64(inner) => inner.chunk_identifier(),
+            StorageWrap::MmapQuantizedArray(inner) => inner.chunk_identifier(),
+            StorageWrap::NdArray(inner) => inner.chunk_identifier(),
+            StorageWrap::NdArrayF64(inner) => inner.chunk_identifier(),
+            StorageWrap::PreadArray(inner) => inner.chunk_identifier(),
+            StorageWrap::QuantizedArray(inner) => inner.chunk_identifier(),
+            StorageWrap::ResidualQuantizedArray(inner) => inner.chunk_identifier(),
+        }
+    }
+
+    fn write_chunk<W>(&self, write: &mut W) -> Result<()>
+    where
+        W: Write + Seek,
+    {
+        match self {
+            StorageWrap::DedupArray(inner) => inner.write_chunk(write),
+            StorageWrap::MmapArray(inner) => inner.write_chunk(write),
+            StorageWrap::MmapArrayF64(inner) => inner.write_chunk(write),
+            StorageWrap::MmapQuantizedArray(inner) => inner.write_chunk(write),
+            StorageWrap::NdArray(inner) => inner.write_chunk(write),
+            StorageWrap::NdArrayF64(inner) => inner.write_chunk(write),
+            StorageWrap::PreadArray(inner) => inner.write_chunk(write),
+            StorageWrap::QuantizedArray(inner) => inner.write_chunk(write),
+            StorageWrap::ResidualQuantizedArray(inner) => inner.write_chunk(write),
+        }
+    }
+
+    fn required_features(&self) -> Features {
+        match self {
+            StorageWrap::DedupArray(inner) => inner.required_features(),
+            StorageWrap::MmapArray(inner) => inner.required_features(),
+            StorageWrap::MmapArrayF64(inner) => inner.required_features(),
+            StorageWrap::MmapQuantizedArray(inner) => inner.required_features(),
+            StorageWrap::NdArray(inner) => inner.required_features(),
+            StorageWrap::NdArrayF64(inner) => inner.required_features(),
+            StorageWrap::PreadArray(inner) => inner.required_features(),
+            StorageWrap::QuantizedArray(inner) => inner.required_features(),
+            StorageWrap::ResidualQuantizedArray(inner) => inner.required_features(),
+        }
+    }
+}
+
+impl WriteChunkRows for StorageWrap {
+    fn write_chunk_rows<W>(&self, rows: &[usize], write: &mut W) -> Result<()>
+    where
+        W: Write + Seek,
+    {
+        match self {
+            StorageWrap::DedupArray(inner) => inner.write_chunk_rows(rows, write),
+            StorageWrap::MmapArray(inner) => inner.write_chunk_rows(rows, write),
+            StorageWrap::MmapArrayF64(inner) => inner.write_chunk_rows(rows, write),
+            StorageWrap::MmapQuantizedArray(inner) => inner.write_chunk_rows(rows, write),
+            StorageWrap::NdArray(inner) => inner.write_chunk_rows(rows, write),
+            StorageWrap::NdArrayF64(inner) => inner.write_chunk_rows(rows, write),
+            StorageWrap::PreadArray(inner) => inner.write_chunk_rows(rows, write),
+            StorageWrap::QuantizedArray(inner) => inner.write_chunk_rows(rows, write),
+            StorageWrap::ResidualQuantizedArray(inner) => inner.write_chunk_rows(rows, write),
+        }
+    }
+}
+
+impl ReadChunk for StorageViewWrap {
+    fn read_chunk<R>(read: &mut R) -> Result<Self>
+    where
+        R: Read + Seek,
+    {
+        let chunk_start_pos = read
+            .stream_position()
+            .map_err(|e| ErrorKind::io_error("Cannot get storage chunk start position", e))?;
+
+        let chunk_id = read
+            .read_u32::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read storage chunk identifier", e))?;
+        let chunk_id = ChunkIdentifier::try_from(chunk_id)
+            .ok_or_else(|| ErrorKind::Format(format!("Unknown chunk identifier: {}", chunk_id)))
+            .map_err(Error::from)?;
+
+        read.seek(SeekFrom::Start(chunk_start_pos))
+            .map_err(|e| ErrorKind::io_error("Cannot seek to storage chunk start position", e))?;
+
+        match chunk_id {
+            ChunkIdentifier::NdArray => NdArray::read_chunk(read).map(StorageViewWrap::NdArray),
+            _ => Err(ErrorKind::Format(format!(
+                "Invalid chunk identifier, expected: {}, got: {}",
+                ChunkIdentifier::NdArray,
+                chunk_id
+            ))
+            .into()),
+        }
+    }
+}
+
+impl WriteChunk for StorageViewWrap {
+    fn chunk_identifier(&self) -> ChunkIdentifier {
+        match self {
+            #[cfg(target_endian = "little")]
+            StorageViewWrap::MmapArray(inner) => inner.chunk_identifier(),
+            StorageViewWrap::NdArray(inner) => inner.chunk_identifier(),
+        }
+    }
+
+    fn write_chunk<W>(&self, write: &mut W) -> Result<()>
+    where
+        W: Write + Seek,
+    {
+        match self {
+            #[cfg(target_endian = "little")]
+            StorageViewWrap::MmapArray(inner) => inner.write_chunk(write),
+            StorageViewWrap::NdArray(inner) => inner.write_chunk(write),
+        }
+    }
+}
+
+impl WriteChunkRows for StorageViewWrap {
+    fn write_chunk_rows<W>(&self, rows: &[usize], write: &mut W) -> Result<()>
+    where
+        W: Write + Seek,
+    {
+        match self {
+            #[cfg(target_endian = "little")]
+            StorageViewWrap::MmapArray(inner) => inner.write_chunk_rows(rows, write),
+            StorageViewWrap::NdArray(inner) => inner.write_chunk_rows(rows, write),
+        }
+    }
+}
+
+impl MmapChunk for StorageViewWrap {
+    fn mmap_chunk(read: &mut BufReader<File>) -> Result<Self> {
+        let chunk_start_pos = read
+            .stream_position()
+            .map_err(|e| ErrorKind::io_error("Cannot get storage chunk start position", e))?;
+
+        let chunk_id = read
+            .read_u32::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read storage chunk identifier", e))?;
+        let chunk_id = ChunkIdentifier::try_from(chunk_id)
+            .ok_or_else(|| ErrorKind::Format(format!("Unknown chunk identifier: {}", chunk_id)))
+            .map_err(Error::from)?;
+
+        read.seek(SeekFrom::Start(chunk_start_pos))
+            .map_err(|e| ErrorKind::io_error("Cannot seek to storage chunk start position", e))?;
+
+        match chunk_id {
+            #[cfg(target_endian = "little")]
+            ChunkIdentifier::NdArray => MmapArray::mmap_chunk(read).map(StorageViewWrap::MmapArray),
+            _ => Err(ErrorKind::Format(format!(
+                "Invalid chunk identifier, expected: {}, got: {}",
+                ChunkIdentifier::NdArray,
+                chunk_id
+            ))
+            .into()),
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, Write};
+use std::mem::size_of;
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use finalfusion_core::vocab::{AliasVocab, Vocab};
+
+use crate::chunks::io::{ChunkIdentifier, LengthCounter, MmapChunk, ReadChunk, WriteChunk};
+use crate::chunks::vocab::{read_vocab_items, write_vocab_items};
+use crate::io::{ErrorKind, Result};
+
+impl<V> ReadChunk for AliasVocab<V>
+where
+    V: ReadChunk + Vocab,
+{
+    fn read_chunk<R>(read: &mut R) -> Result<Self>
+    where
+        R: Read + Seek,
+    {
+        let aliases = read_aliases(read)?;
+        Self::from_aliases(V::read_chunk(read)?, aliases)
+    }
+
+    fn validate(&self) -> Result<()> {
+        self.inner().validate()
+    }
+}
+
+impl<V> MmapChunk for AliasVocab<V>
+where
+    V: MmapChunk + Vocab,
+{
+    fn mmap_chunk(read: &mut BufReader<File>) -> Result<Self> {
+        let aliases = read_aliases(read)?;
+        Self::from_aliases(V::mmap_chunk(read)?, aliases)
+    }
+}
+
+impl<V> WriteChunk for AliasVocab<V>
+where
+    V: WriteChunk,
+{
+    fn chunk_identifier(&self) -> ChunkIdentifier {
+        ChunkIdentifier::AliasVocab
+    }
+
+    fn write_chunk<W>(&self, write: &mut W) -> Result<()>
+    where
+        W: Write + Seek,
+    {
+        write
+            .write_u32::<LittleEndian>(ChunkIdentifier::AliasVocab as u32)
+            .map_err(|e| ErrorKind::io_error("Cannot write vocabulary chunk identifier", e))?;
+
+        // The chunk length includes the length of the wrapped
+        // vocabulary chunk, so the length is computed by writing the
+        // chunk data to a counter first.
+        let len_pos = write
+            .stream_position()
+            .map_err(|e| ErrorKind::io_error("Cannot get vocabulary chunk length position", e))?;
+        let data_pos = len_pos + size_of::<u64>() as u64;
+        let mut counter = LengthCounter::new(data_pos);
+        write_data(self, &mut counter)?;
+        write
+            .write_u64::<LittleEndian>(counter.end() - data_pos)
+            .map_err(|e| ErrorKind::io_error("Cannot write vocabulary chunk length", e))?;
+
+        write_data(self, write)
+    }
+}
+
+/// Read the aliases of an alias vocabulary chunk.
+fn read_aliases<R>(read: &mut R) -> Result<Vec<(String, usize)>>
+where
+    R: Read + Seek,
+{
+    ChunkIdentifier::ensure_chunk_type(read, ChunkIdentifier::AliasVocab)?;
+
+    // Read and discard chunk length.
+    read.read_u64::<LittleEndian>()
+        .map_err(|e| ErrorKind::io_error("Cannot read vocabulary chunk length", e))?;
+
+    let aliases_len =
+        read.read_u64::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read number of aliases", e))? as usize;
+    let aliases = read_vocab_items(read, aliases_len)?;
+    let mut indices = Vec::with_capacity(aliases_len);
+    for _ in 0..aliases_len {
+        indices.push(
+            read.read_u64::<LittleEndian>()
+                .map_err(|e| ErrorKind::io_error("Cannot read alias index", e))?
+                as usize,
+        );
+    }
+
+    Ok(aliases.into_iter().zip(indices).collect())
+}
+
+/// Write the chunk data, the aliases followed by the wrapped
+/// vocabulary chunk.
+fn write_data<V, W>(vocab: &AliasVocab<V>, write: &mut W) -> Result<()>
+where
+    V: WriteChunk,
+    W: Write + Seek,
+{
+    write
+        .write_u64::<LittleEndian>(vocab.aliases_len() as u64)
+        .map_err(|e| ErrorKind::io_error("Cannot write number of aliases", e))?;
+    write_vocab_items(write, vocab.aliases().map(|(alias, _)| alias))?;
+    for (_, idx) in vocab.aliases() {
+        write
+            .write_u64::<LittleEndian>(idx as u64)
+            .map_err(|e| ErrorKind::io_error("Cannot write alias index", e))?;
+    }
+
+    vocab.inner().write_chunk(write)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read, Seek, SeekFrom};
+
+    use finalfusion_core::vocab::{AliasVocab, SimpleVocab, VocabWrap};
+
+    use crate::chunks::io::{ReadChunk, WriteChunk};
+    use crate::chunks::vocab::read_chunk_size;
+
+    fn test_alias_vocab() -> AliasVocab<SimpleVocab> {
+        AliasVocab::new(
+            SimpleVocab::new(vec![
+                "color".to_owned(),
+                "center".to_owned(),
+                "is".to_owned(),
+            ]),
+            vec![("colour".to_owned(), 0), ("centre".to_owned(), 1)],
+        )
+    }
+
+    #[test]
+    fn alias_vocab_write_read_roundtrip() {
+        let check_vocab = test_alias_vocab();
+        let mut cursor = Cursor::new(Vec::new());
+        check_vocab.write_chunk(&mut cursor).unwrap();
+        cursor.seek(SeekFrom::Start(0)).unwrap();
+        let vocab = AliasVocab::<SimpleVocab>::read_chunk(&mut cursor).unwrap();
+        assert_eq!(vocab, check_vocab);
+
+        cursor.seek(SeekFrom::Start(0)).unwrap();
+        let vocab = VocabWrap::read_chunk(&mut cursor).unwrap();
+        assert_eq!(vocab, check_vocab.into());
+    }
+
+    #[test]
+    fn alias_vocab_correct_chunk_size() {
+        let check_vocab = test_alias_vocab();
+        let mut cursor = Cursor::new(Vec::new());
+        check_vocab.write_chunk(&mut cursor).unwrap();
+        cursor.seek(SeekFrom::Start(0)).unwrap();
+
+        let chunk_size = read_chunk_size(&mut cursor);
+        assert_eq!(
+            cursor.read_to_end(&mut Vec::new()).unwrap(),
+            chunk_size as usize
+        );
+    }
+}
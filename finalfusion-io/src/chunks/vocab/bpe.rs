@@ -0,0 +1,158 @@
+use std::io::{Read, Seek, Write};
+use std::iter;
+use std::mem::size_of;
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use finalfusion_core::vocab::{BpeVocab, Vocab};
+
+use crate::chunks::io::{ChunkIdentifier, ReadChunk, WriteChunk};
+use crate::chunks::vocab::{read_vocab_items, write_vocab_items};
+use crate::io::{ErrorKind, Result};
+
+impl ReadChunk for BpeVocab {
+    fn read_chunk<R>(read: &mut R) -> Result<Self>
+    where
+        R: Read + Seek,
+    {
+        ChunkIdentifier::ensure_chunk_type(read, ChunkIdentifier::BpeVocab)?;
+
+        // Read and discard chunk length.
+        read.read_u64::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read vocabulary chunk length", e))?;
+
+        let vocab_len = read
+            .read_u64::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read vocabulary length", e))?
+            as usize;
+        let n_units = read
+            .read_u64::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read number of BPE units", e))?
+            as usize;
+        let n_merges = read
+            .read_u64::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read number of BPE merges", e))?
+            as usize;
+
+        let end_of_word = read_vocab_items(read, 1)?.remove(0);
+        let words = read_vocab_items(read, vocab_len)?;
+        let units = read_vocab_items(read, n_units)?;
+        let merges = read_vocab_items(read, 2 * n_merges)?;
+        let mut merges = merges.into_iter();
+        let merges = (0..n_merges).map(|_| {
+            (
+                merges.next().expect("Missing left unit of merge"),
+                merges.next().expect("Missing right unit of merge"),
+            )
+        });
+
+        Ok(BpeVocab::new(words, units, merges, end_of_word))
+    }
+}
+
+impl WriteChunk for BpeVocab {
+    fn chunk_identifier(&self) -> ChunkIdentifier {
+        ChunkIdentifier::BpeVocab
+    }
+
+    fn write_chunk<W>(&self, write: &mut W) -> Result<()>
+    where
+        W: Write + Seek,
+    {
+        let merges = self.merges();
+
+        // Chunk size: vocabulary size (u64), number of units (u64),
+        // number of merges (u64), end-of-word marker, words, units,
+        // and the two units of each merge. Each string is stored as
+        // its length in bytes (u32) and its bytes (variable-length).
+        let string_len = |s: &str| s.len() + size_of::<u32>();
+        let chunk_len = 3 * size_of::<u64>()
+            + string_len(self.end_of_word())
+            + self.words().iter().map(|w| string_len(w)).sum::<usize>()
+            + self.units().iter().map(|u| string_len(u)).sum::<usize>()
+            + merges
+                .iter()
+                .map(|(left, right)| string_len(left) + string_len(right))
+                .sum::<usize>();
+
+        write
+            .write_u32::<LittleEndian>(ChunkIdentifier::BpeVocab as u32)
+            .map_err(|e| ErrorKind::io_error("Cannot write vocabulary chunk identifier", e))?;
+        write
+            .write_u64::<LittleEndian>(chunk_len as u64)
+            .map_err(|e| ErrorKind::io_error("Cannot write vocabulary chunk length", e))?;
+        write
+            .write_u64::<LittleEndian>(self.words().len() as u64)
+            .map_err(|e| ErrorKind::io_error("Cannot write vocabulary length", e))?;
+        write
+            .write_u64::<LittleEndian>(self.units().len() as u64)
+            .map_err(|e| ErrorKind::io_error("Cannot write number of BPE units", e))?;
+        write
+            .write_u64::<LittleEndian>(merges.len() as u64)
+            .map_err(|e| ErrorKind::io_error("Cannot write number of BPE merges", e))?;
+
+        write_vocab_items(write, iter::once(self.end_of_word()))?;
+        write_vocab_items(write, self.words())?;
+        write_vocab_items(write, self.units())?;
+        let merges = merges
+            .into_iter()
+            .flat_map(|(left, right)| vec![left.to_owned(), right.to_owned()])
+            .collect::<Vec<_>>();
+        write_vocab_items(write, &merges)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read, Seek, SeekFrom};
+
+    use finalfusion_core::vocab::BpeVocab;
+
+    use crate::chunks::io::{ReadChunk, WriteChunk};
+    use crate::chunks::vocab::read_chunk_size;
+
+    fn strings(strs: &[&str]) -> Vec<String> {
+        strs.iter().map(|s| (*s).to_owned()).collect()
+    }
+
+    fn test_bpe_vocab() -> BpeVocab {
+        let merges = vec![("l", "o"), ("lo", "w"), ("e", "r</w>"), ("w", "e")];
+        BpeVocab::new(
+            strings(&["low", "lowest"]),
+            strings(&["low", "er</w>", "l", "o", "w", "e", "r</w>", "s", "t</w>"]),
+            merges
+                .into_iter()
+                .map(|(left, right)| (left.to_owned(), right.to_owned())),
+            "</w>",
+        )
+    }
+
+    #[test]
+    fn bpe_vocab_write_read_roundtrip() {
+        let check_vocab = test_bpe_vocab();
+        let mut cursor = Cursor::new(Vec::new());
+        check_vocab.write_chunk(&mut cursor).unwrap();
+        cursor.seek(SeekFrom::Start(0)).unwrap();
+        let vocab = BpeVocab::read_chunk(&mut cursor).unwrap();
+        assert_eq!(vocab, check_vocab);
+        assert_eq!(
+            vocab.merges(),
+            vec![("l", "o"), ("lo", "w"), ("e", "r</w>"), ("w", "e")]
+        );
+    }
+
+    #[test]
+    fn bpe_vocab_correct_chunk_size() {
+        let check_vocab = test_bpe_vocab();
+        let mut cursor = Cursor::new(Vec::new());
+        check_vocab.write_chunk(&mut cursor).unwrap();
+        cursor.seek(SeekFrom::Start(0)).unwrap();
+
+        let chunk_size = read_chunk_size(&mut cursor);
+        assert_eq!(
+            cursor.read_to_end(&mut Vec::new()).unwrap(),
+            chunk_size as usize
+        );
+    }
+}
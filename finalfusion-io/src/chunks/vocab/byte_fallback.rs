@@ -0,0 +1,115 @@
+use std::io::{Read, Seek, Write};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use finalfusion_core::vocab::ByteFallbackVocab;
+
+use crate::chunks::io::{ChunkIdentifier, ReadChunk, WriteChunk};
+use crate::io::{ErrorKind, Result};
+
+impl<V> ReadChunk for ByteFallbackVocab<V>
+where
+    V: ReadChunk,
+{
+    fn read_chunk<R>(read: &mut R) -> Result<Self>
+    where
+        R: Read + Seek,
+    {
+        ChunkIdentifier::ensure_chunk_type(read, ChunkIdentifier::ByteFallbackVocab)?;
+
+        // Read and discard chunk length.
+        read.read_u64::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read vocabulary chunk length", e))?;
+
+        Ok(ByteFallbackVocab::new(V::read_chunk(read)?))
+    }
+
+    fn validate(&self) -> Result<()> {
+        self.inner().validate()
+    }
+}
+
+impl<V> WriteChunk for ByteFallbackVocab<V>
+where
+    V: WriteChunk,
+{
+    fn chunk_identifier(&self) -> ChunkIdentifier {
+        ChunkIdentifier::ByteFallbackVocab
+    }
+
+    fn write_chunk<W>(&self, write: &mut W) -> Result<()>
+    where
+        W: Write + Seek,
+    {
+        write
+            .write_u32::<LittleEndian>(ChunkIdentifier::ByteFallbackVocab as u32)
+            .map_err(|e| ErrorKind::io_error("Cannot write vocabulary chunk identifier", e))?;
+
+        // The chunk length is the length of the wrapped vocabulary
+        // chunk, which is computed before writing, so that no seeking
+        // is needed.
+        let len_pos = write
+            .stream_position()
+            .map_err(|e| ErrorKind::io_error("Cannot get vocabulary chunk length position", e))?;
+        let chunk_len = self.inner().chunk_len(len_pos + 8)?;
+        write
+            .write_u64::<LittleEndian>(chunk_len)
+            .map_err(|e| ErrorKind::io_error("Cannot write vocabulary chunk length", e))?;
+
+        self.inner().write_chunk(write)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read, Seek, SeekFrom};
+
+    use finalfusion_core::vocab::{ByteFallbackVocab, SimpleVocab, VocabWrap};
+
+    use crate::chunks::io::{PositionWriter, ReadChunk, WriteChunk};
+    use crate::chunks::vocab::read_chunk_size;
+
+    fn test_byte_fallback_vocab() -> ByteFallbackVocab<SimpleVocab> {
+        ByteFallbackVocab::new(SimpleVocab::new(vec!["this".to_owned(), "is".to_owned()]))
+    }
+
+    #[test]
+    fn byte_fallback_vocab_write_read_roundtrip() {
+        let check_vocab = test_byte_fallback_vocab();
+        let mut cursor = Cursor::new(Vec::new());
+        check_vocab.write_chunk(&mut cursor).unwrap();
+        cursor.seek(SeekFrom::Start(0)).unwrap();
+        let vocab = ByteFallbackVocab::<SimpleVocab>::read_chunk(&mut cursor).unwrap();
+        assert_eq!(vocab, check_vocab);
+
+        cursor.seek(SeekFrom::Start(0)).unwrap();
+        let vocab = VocabWrap::read_chunk(&mut cursor).unwrap();
+        assert_eq!(vocab, check_vocab.into());
+    }
+
+    #[test]
+    fn byte_fallback_vocab_write_without_seek() {
+        let vocab = test_byte_fallback_vocab();
+        let mut cursor = Cursor::new(Vec::new());
+        vocab.write_chunk(&mut cursor).unwrap();
+
+        let mut data = Vec::new();
+        vocab
+            .write_chunk(&mut PositionWriter::new(&mut data))
+            .unwrap();
+        assert_eq!(data, cursor.into_inner());
+    }
+
+    #[test]
+    fn byte_fallback_vocab_correct_chunk_size() {
+        let check_vocab = test_byte_fallback_vocab();
+        let mut cursor = Cursor::new(Vec::new());
+        check_vocab.write_chunk(&mut cursor).unwrap();
+        cursor.seek(SeekFrom::Start(0)).unwrap();
+
+        let chunk_size = read_chunk_size(&mut cursor);
+        assert_eq!(
+            cursor.read_to_end(&mut Vec::new()).unwrap(),
+            chunk_size as usize
+        );
+    }
+}
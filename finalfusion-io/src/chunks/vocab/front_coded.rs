@@ -0,0 +1,259 @@
+use std::io::{Cursor, Read, Seek, Write};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use finalfusion_core::util::{read_varint, write_varint};
+use finalfusion_core::vocab::{FrontCodedVocab, Vocab};
+
+use crate::chunks::io::{ChunkIdentifier, ReadChunk, WriteChunk};
+use crate::io::{Error, ErrorKind, Result};
+
+impl ReadChunk for FrontCodedVocab {
+    fn read_chunk<R>(read: &mut R) -> Result<Self>
+    where
+        R: Read + Seek,
+    {
+        ChunkIdentifier::ensure_chunk_type(read, ChunkIdentifier::FrontCodedVocab)?;
+
+        let chunk_len = read
+            .read_u64::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read vocabulary chunk length", e))?;
+
+        // The chunk consists of variable-length data, so it is read as
+        // a whole and decoded from memory.
+        let mut data = Vec::new();
+        read.by_ref()
+            .take(chunk_len)
+            .read_to_end(&mut data)
+            .map_err(|e| ErrorKind::io_error("Cannot read vocabulary chunk", e))?;
+        if data.len() as u64 != chunk_len {
+            return Err(ErrorKind::Format(format!(
+                "Vocabulary chunk is truncated, expected {} bytes, got {}",
+                chunk_len,
+                data.len()
+            ))
+            .into());
+        }
+
+        let mut data = Cursor::new(data);
+        let words = read_front_coded_words(&mut data)?;
+        if data.position() != chunk_len {
+            return Err(ErrorKind::Format(format!(
+                "Vocabulary chunk has {} trailing bytes",
+                chunk_len - data.position()
+            ))
+            .into());
+        }
+
+        Ok(FrontCodedVocab::new(words))
+    }
+}
+
+impl WriteChunk for FrontCodedVocab {
+    fn chunk_identifier(&self) -> ChunkIdentifier {
+        ChunkIdentifier::FrontCodedVocab
+    }
+
+    fn write_chunk<W>(&self, write: &mut W) -> Result<()>
+    where
+        W: Write + Seek,
+    {
+        let words = self.words();
+
+        let mut order = (0..words.len()).collect::<Vec<_>>();
+        order.sort_unstable_by(|&idx1, &idx2| words[idx1].cmp(&words[idx2]));
+
+        // Chunk: vocabulary length, for each word in sorted order: the
+        // length of the prefix shared with the preceding word and the
+        // length of the suffix, followed by the suffix, for each word
+        // in sorted order: the word index. All integers are varints.
+        let mut data = Vec::new();
+        write_varint(&mut data, words.len() as u64);
+
+        let mut prev: &[u8] = &[];
+        for &idx in &order {
+            let word = words[idx].as_bytes();
+            let prefix_len = prev
+                .iter()
+                .zip(word)
+                .take_while(|(prev, cur)| prev == cur)
+                .count();
+            let suffix = &word[prefix_len..];
+            write_varint(&mut data, prefix_len as u64);
+            write_varint(&mut data, suffix.len() as u64);
+            data.extend_from_slice(suffix);
+            prev = word;
+        }
+
+        for &idx in &order {
+            write_varint(&mut data, idx as u64);
+        }
+
+        write
+            .write_u32::<LittleEndian>(ChunkIdentifier::FrontCodedVocab as u32)
+            .map_err(|e| ErrorKind::io_error("Cannot write vocabulary chunk identifier", e))?;
+        write
+            .write_u64::<LittleEndian>(data.len() as u64)
+            .map_err(|e| ErrorKind::io_error("Cannot write vocabulary chunk length", e))?;
+        write
+            .write_all(&data)
+            .map_err(|e| ErrorKind::io_error("Cannot write vocabulary", e))?;
+
+        Ok(())
+    }
+}
+
+/// Decode the front-coded words, returning them in index order.
+fn read_front_coded_words(data: &mut Cursor<Vec<u8>>) -> Result<Vec<String>> {
+    let vocab_len = read_varint(data)? as usize;
+
+    // Every word takes at least two bytes, which bounds the number of
+    // words in a valid chunk.
+    let max_len = data.get_ref().len() / 2;
+    if vocab_len > max_len {
+        return Err(ErrorKind::Format(format!(
+            "Vocabulary length {} exceeds the chunk size",
+            vocab_len
+        ))
+        .into());
+    }
+
+    let mut sorted = Vec::with_capacity(vocab_len);
+    let mut word = Vec::new();
+    for _ in 0..vocab_len {
+        let prefix_len = read_varint(data)? as usize;
+        if prefix_len > word.len() {
+            return Err(ErrorKind::Format(format!(
+                "Shared prefix length {} exceeds the length of the preceding word",
+                prefix_len
+            ))
+            .into());
+        }
+
+        let suffix_len = read_varint(data)? as usize;
+        let remaining = data.get_ref().len() - data.position() as usize;
+        if suffix_len > remaining {
+            return Err(ErrorKind::Format(format!(
+                "Suffix length {} exceeds the chunk size",
+                suffix_len
+            ))
+            .into());
+        }
+
+        word.truncate(prefix_len);
+        let start = word.len();
+        word.resize(start + suffix_len, 0);
+        data.read_exact(&mut word[start..])
+            .map_err(|e| ErrorKind::io_error("Cannot read word suffix", e))?;
+
+        // Sorted order also guarantees that there are no duplicates.
+        if let Some(prev) = sorted.last() {
+            if String::as_bytes(prev) >= &word[..] {
+                return Err(ErrorKind::Format(String::from(
+                    "Words are not sorted or contain duplicates",
+                ))
+                .into());
+            }
+        }
+
+        let decoded = String::from_utf8(word.clone())
+            .map_err(|e| ErrorKind::Format(format!("Word contains invalid UTF-8: {}", e)))
+            .map_err(Error::from)?;
+        sorted.push(decoded);
+    }
+
+    let mut words = vec![None; vocab_len];
+    for word in sorted {
+        let idx = read_varint(data)? as usize;
+        match words.get_mut(idx) {
+            Some(slot @ None) => *slot = Some(word),
+            _ => {
+                return Err(ErrorKind::Format(format!(
+                    "Word index {} is out of bounds or occurs more than once",
+                    idx
+                ))
+                .into())
+            }
+        }
+    }
+
+    Ok(words
+        .into_iter()
+        .map(|word| word.expect("Missing word"))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read, Seek, SeekFrom};
+
+    use finalfusion_core::vocab::{FrontCodedVocab, SimpleVocab, Vocab, WordIndex};
+
+    use crate::chunks::io::{ReadChunk, WriteChunk};
+    use crate::chunks::vocab::read_chunk_size;
+
+    fn test_words() -> Vec<String> {
+        ["tops", "tap", "top", "", "taps", "töpfe", "töpfer"]
+            .iter()
+            .map(|&word| word.to_owned())
+            .collect()
+    }
+
+    fn write_vocab(vocab: &impl WriteChunk) -> Vec<u8> {
+        let mut cursor = Cursor::new(Vec::new());
+        vocab.write_chunk(&mut cursor).unwrap();
+        cursor.into_inner()
+    }
+
+    #[test]
+    fn front_coded_vocab_write_read_roundtrip() {
+        let check_vocab = FrontCodedVocab::new(test_words());
+        let mut cursor = Cursor::new(write_vocab(&check_vocab));
+        let vocab = FrontCodedVocab::read_chunk(&mut cursor).unwrap();
+        assert_eq!(vocab, check_vocab);
+        assert_eq!(vocab.words(), &test_words()[..]);
+        assert_eq!(vocab.idx("töpfer"), Some(WordIndex::Word(6)));
+    }
+
+    #[test]
+    fn front_coded_vocab_correct_chunk_size() {
+        let mut cursor = Cursor::new(write_vocab(&FrontCodedVocab::new(test_words())));
+        cursor.seek(SeekFrom::Start(0)).unwrap();
+
+        let chunk_size = read_chunk_size(&mut cursor);
+        assert_eq!(
+            cursor.read_to_end(&mut Vec::new()).unwrap(),
+            chunk_size as usize
+        );
+    }
+
+    #[test]
+    fn front_coded_vocab_rejects_invalid_data() {
+        let data = write_vocab(&FrontCodedVocab::new(test_words()));
+
+        // Duplicate the first word index in the last position.
+        let mut invalid = data.clone();
+        let len = invalid.len();
+        invalid[len - 1] = invalid[len - 7];
+        assert!(FrontCodedVocab::read_chunk(&mut Cursor::new(invalid)).is_err());
+
+        // Truncate the chunk.
+        let invalid = data[..data.len() - 1].to_vec();
+        assert!(FrontCodedVocab::read_chunk(&mut Cursor::new(invalid)).is_err());
+
+        // Set the shared prefix of the second word beyond the length
+        // of its predecessor, the empty word.
+        let mut invalid = data;
+        invalid[15] = 1;
+        assert!(FrontCodedVocab::read_chunk(&mut Cursor::new(invalid)).is_err());
+    }
+
+    #[test]
+    fn front_coded_vocab_is_smaller() {
+        let words = (0..1000)
+            .map(|idx| format!("prefix{:04}", idx))
+            .collect::<Vec<_>>();
+        let simple = write_vocab(&SimpleVocab::new(words.clone()));
+        let front_coded = write_vocab(&FrontCodedVocab::new(words));
+        assert!(front_coded.len() * 2 < simple.len());
+    }
+}
@@ -0,0 +1,218 @@
+use std::io::{Read, Seek, Write};
+use std::mem::{size_of, size_of_val};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use finalfusion_core::vocab::FstVocab;
+
+use crate::chunks::io::{ChunkIdentifier, ReadChunk, WriteChunk};
+use crate::io::{ErrorKind, Result};
+
+impl ReadChunk for FstVocab {
+    fn read_chunk<R>(read: &mut R) -> Result<Self>
+    where
+        R: Read + Seek,
+    {
+        ChunkIdentifier::ensure_chunk_type(read, ChunkIdentifier::FstVocab)?;
+
+        // Read and discard chunk length.
+        read.read_u64::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read vocabulary chunk length", e))?;
+
+        let vocab_len = read
+            .read_u64::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read vocabulary length", e))?
+            as usize;
+        let n_states = read
+            .read_u64::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read number of transducer states", e))?
+            as usize;
+        let n_transitions = read
+            .read_u64::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read number of transducer transitions", e))?
+            as usize;
+
+        // The offset of the first state is not stored, it is always 0.
+        let mut offsets = vec![0u32; n_states + 1];
+        read.read_u32_into::<LittleEndian>(&mut offsets[1..])
+            .map_err(|e| ErrorKind::io_error("Cannot read transducer state offsets", e))?;
+
+        let mut finals = vec![0u8; n_states];
+        read.read_exact(&mut finals)
+            .map_err(|e| ErrorKind::io_error("Cannot read transducer final states", e))?;
+        let finals = finals
+            .into_iter()
+            .map(|is_final| match is_final {
+                0 => Ok(false),
+                1 => Ok(true),
+                _ => Err(ErrorKind::Format(format!(
+                    "Invalid transducer final state marker: {}",
+                    is_final
+                ))),
+            })
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        let mut labels = vec![0u8; n_transitions];
+        read.read_exact(&mut labels)
+            .map_err(|e| ErrorKind::io_error("Cannot read transducer transition labels", e))?;
+
+        let mut targets = vec![0u32; n_transitions];
+        read.read_u32_into::<LittleEndian>(&mut targets)
+            .map_err(|e| ErrorKind::io_error("Cannot read transducer transition targets", e))?;
+
+        let mut outputs = vec![0u32; n_transitions];
+        read.read_u32_into::<LittleEndian>(&mut outputs)
+            .map_err(|e| ErrorKind::io_error("Cannot read transducer transition outputs", e))?;
+
+        let mut indices = vec![0u32; vocab_len];
+        read.read_u32_into::<LittleEndian>(&mut indices)
+            .map_err(|e| ErrorKind::io_error("Cannot read word indices", e))?;
+
+        FstVocab::from_parts(offsets, finals, labels, targets, outputs, indices)
+    }
+}
+
+impl WriteChunk for FstVocab {
+    fn chunk_identifier(&self) -> ChunkIdentifier {
+        ChunkIdentifier::FstVocab
+    }
+
+    fn write_chunk<W>(&self, write: &mut W) -> Result<()>
+    where
+        W: Write + Seek,
+    {
+        let n_states = self.finals().len();
+        let n_transitions = self.labels().len();
+        // Chunk size: vocabulary size (u64), number of states (u64),
+        // number of transitions (u64), state end offsets (u32), final
+        // state markers (u8), for each transition: label (u8), target
+        // (u32), output (u32), word indices (u32).
+        let chunk_len = 3 * size_of::<u64>()
+            + n_states * size_of::<u32>()
+            + n_states
+            + n_transitions * (1 + 2 * size_of::<u32>())
+            + size_of_val(self.indices());
+
+        write
+            .write_u32::<LittleEndian>(ChunkIdentifier::FstVocab as u32)
+            .map_err(|e| ErrorKind::io_error("Cannot write vocabulary chunk identifier", e))?;
+        write
+            .write_u64::<LittleEndian>(chunk_len as u64)
+            .map_err(|e| ErrorKind::io_error("Cannot write vocabulary chunk length", e))?;
+        write
+            .write_u64::<LittleEndian>(self.indices().len() as u64)
+            .map_err(|e| ErrorKind::io_error("Cannot write vocabulary length", e))?;
+        write
+            .write_u64::<LittleEndian>(n_states as u64)
+            .map_err(|e| ErrorKind::io_error("Cannot write number of transducer states", e))?;
+        write
+            .write_u64::<LittleEndian>(n_transitions as u64)
+            .map_err(|e| ErrorKind::io_error("Cannot write number of transducer transitions", e))?;
+
+        write_u32s(
+            write,
+            &self.offsets()[1..],
+            "Cannot write transducer state offsets",
+        )?;
+        let finals = self
+            .finals()
+            .iter()
+            .map(|&is_final| is_final as u8)
+            .collect::<Vec<_>>();
+        write
+            .write_all(&finals)
+            .map_err(|e| ErrorKind::io_error("Cannot write transducer final states", e))?;
+        write
+            .write_all(self.labels())
+            .map_err(|e| ErrorKind::io_error("Cannot write transducer transition labels", e))?;
+        write_u32s(
+            write,
+            self.targets(),
+            "Cannot write transducer transition targets",
+        )?;
+        write_u32s(
+            write,
+            self.outputs(),
+            "Cannot write transducer transition outputs",
+        )?;
+        write_u32s(write, self.indices(), "Cannot write word indices")?;
+
+        Ok(())
+    }
+}
+
+fn write_u32s<W>(write: &mut W, values: &[u32], error: &str) -> Result<()>
+where
+    W: Write,
+{
+    for &value in values {
+        write
+            .write_u32::<LittleEndian>(value)
+            .map_err(|e| ErrorKind::io_error(error, e))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read, Seek, SeekFrom};
+
+    use finalfusion_core::vocab::{FstVocab, Vocab};
+
+    use crate::chunks::io::{ReadChunk, WriteChunk};
+    use crate::chunks::vocab::read_chunk_size;
+
+    fn test_fst_vocab() -> FstVocab {
+        let words = vec![
+            "tops".to_owned(),
+            "tap".to_owned(),
+            "top".to_owned(),
+            "".to_owned(),
+            "taps".to_owned(),
+            "töpfe".to_owned(),
+        ];
+
+        FstVocab::new(words)
+    }
+
+    #[test]
+    fn fst_vocab_write_read_roundtrip() {
+        let check_vocab = test_fst_vocab();
+        let mut cursor = Cursor::new(Vec::new());
+        check_vocab.write_chunk(&mut cursor).unwrap();
+        cursor.seek(SeekFrom::Start(0)).unwrap();
+        let vocab = FstVocab::read_chunk(&mut cursor).unwrap();
+        assert_eq!(vocab, check_vocab);
+        assert_eq!(vocab.words(), check_vocab.words());
+    }
+
+    #[test]
+    fn fst_vocab_correct_chunk_size() {
+        let check_vocab = test_fst_vocab();
+        let mut cursor = Cursor::new(Vec::new());
+        check_vocab.write_chunk(&mut cursor).unwrap();
+        cursor.seek(SeekFrom::Start(0)).unwrap();
+
+        let chunk_size = read_chunk_size(&mut cursor);
+        assert_eq!(
+            cursor.read_to_end(&mut Vec::new()).unwrap(),
+            chunk_size as usize
+        );
+    }
+
+    #[test]
+    fn fst_vocab_rejects_invalid_indices() {
+        let check_vocab = test_fst_vocab();
+        let mut data = Vec::new();
+        check_vocab
+            .write_chunk(&mut Cursor::new(&mut data))
+            .unwrap();
+
+        // Duplicate the first word index in the last position.
+        let len = data.len();
+        let first = data[len - 24..len - 20].to_vec();
+        data[len - 4..].copy_from_slice(&first);
+
+        assert!(FstVocab::read_chunk(&mut Cursor::new(data)).is_err());
+    }
+}
@@ -0,0 +1,80 @@
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, Write};
+
+use finalfusion_core::vocab::{LanguageVocab, Vocab};
+
+use crate::chunks::io::{ChunkIdentifier, MmapChunk, ReadChunk, WriteChunk};
+use crate::io::Result;
+
+impl<V> ReadChunk for LanguageVocab<V>
+where
+    V: ReadChunk + Vocab,
+{
+    fn read_chunk<R>(read: &mut R) -> Result<Self>
+    where
+        R: Read + Seek,
+    {
+        V::read_chunk(read).map(LanguageVocab::new)
+    }
+
+    fn validate(&self) -> Result<()> {
+        self.inner().validate()
+    }
+}
+
+impl<V> MmapChunk for LanguageVocab<V>
+where
+    V: MmapChunk + Vocab,
+{
+    fn mmap_chunk(read: &mut BufReader<File>) -> Result<Self> {
+        V::mmap_chunk(read).map(LanguageVocab::new)
+    }
+}
+
+impl<V> WriteChunk for LanguageVocab<V>
+where
+    V: WriteChunk,
+{
+    fn chunk_identifier(&self) -> ChunkIdentifier {
+        self.inner().chunk_identifier()
+    }
+
+    fn write_chunk<W>(&self, write: &mut W) -> Result<()>
+    where
+        W: Write + Seek,
+    {
+        self.inner().write_chunk(write)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Seek, SeekFrom};
+
+    use finalfusion_core::vocab::{LanguageVocab, SimpleVocab};
+
+    use crate::chunks::io::{ReadChunk, WriteChunk};
+
+    fn test_language_vocab() -> LanguageVocab<SimpleVocab> {
+        LanguageVocab::new(SimpleVocab::new(
+            ["en:dog", "nl:hond", "en:cat", "<unk>", "nl:kat", "de:Hund"]
+                .iter()
+                .map(|&word| word.to_owned())
+                .collect::<Vec<_>>(),
+        ))
+    }
+
+    #[test]
+    fn language_vocab_write_read_roundtrip() {
+        let check_vocab = test_language_vocab();
+        let mut cursor = Cursor::new(Vec::new());
+        check_vocab.write_chunk(&mut cursor).unwrap();
+        cursor.seek(SeekFrom::Start(0)).unwrap();
+        let vocab = SimpleVocab::read_chunk(&mut cursor).unwrap();
+        assert_eq!(&vocab, check_vocab.inner());
+
+        cursor.seek(SeekFrom::Start(0)).unwrap();
+        let vocab = LanguageVocab::<SimpleVocab>::read_chunk(&mut cursor).unwrap();
+        assert_eq!(vocab, check_vocab);
+    }
+}
@@ -0,0 +1,316 @@
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom, Write};
+use std::mem::size_of;
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use finalfusion_core::vocab::{MmapVocab, Vocab};
+use memmap::MmapOptions;
+
+use crate::chunks::io::{ChunkIdentifier, MmapChunk, ReadChunk, WriteChunk};
+use crate::io::{ErrorKind, Result};
+
+impl ReadChunk for MmapVocab {
+    fn read_chunk<R>(read: &mut R) -> Result<Self>
+    where
+        R: Read + Seek,
+    {
+        let header = read_chunk_header(read)?;
+
+        let mut data = vec![0u8; header.data_len];
+        read.read_exact(&mut data)
+            .map_err(|e| ErrorKind::io_error("Cannot read vocabulary data", e))?;
+
+        MmapVocab::from_data(
+            data,
+            header.words_len,
+            header.seed,
+            header.n_buckets,
+            header.n_slots,
+        )
+    }
+
+    fn validate(&self) -> Result<()> {
+        self.check()
+    }
+}
+
+impl MmapChunk for MmapVocab {
+    fn mmap_chunk(read: &mut BufReader<File>) -> Result<Self> {
+        let header = read_chunk_header(read)?;
+
+        let offset = read.stream_position().map_err(|e| {
+            ErrorKind::io_error("Cannot get file position for memory mapping vocabulary", e)
+        })?;
+        let mut mmap_opts = MmapOptions::new();
+        let map = unsafe {
+            mmap_opts
+                .offset(offset)
+                .len(header.data_len)
+                .map(read.get_ref())
+                .map_err(|e| ErrorKind::io_error("Cannot memory map vocabulary", e))?
+        };
+
+        // Position the reader after the vocabulary.
+        read.seek(SeekFrom::Current(header.data_len as i64))
+            .map_err(|e| ErrorKind::io_error("Cannot skip vocabulary data", e))?;
+
+        MmapVocab::from_mmap(
+            map,
+            header.words_len,
+            header.seed,
+            header.n_buckets,
+            header.n_slots,
+        )
+    }
+}
+
+impl WriteChunk for MmapVocab {
+    fn chunk_identifier(&self) -> ChunkIdentifier {
+        ChunkIdentifier::MmapVocab
+    }
+
+    fn write_chunk<W>(&self, write: &mut W) -> Result<()>
+    where
+        W: Write + Seek,
+    {
+        // Chunk size: vocabulary size (u64), string pool size (u64),
+        // perfect hash seed (u64), number of buckets (u64), number of
+        // slots (u64), for each word: end offset (u64), index (u32 or
+        // u64), rank (u32 or u64), for each bucket: displacements (2x
+        // u32), for each slot: rank (u32), and the string pool.
+        let chunk_len = 5 * size_of::<u64>() + self.data().len();
+
+        write
+            .write_u32::<LittleEndian>(ChunkIdentifier::MmapVocab as u32)
+            .map_err(|e| ErrorKind::io_error("Cannot write vocabulary chunk identifier", e))?;
+        write
+            .write_u64::<LittleEndian>(chunk_len as u64)
+            .map_err(|e| ErrorKind::io_error("Cannot write vocabulary chunk length", e))?;
+        write
+            .write_u64::<LittleEndian>(self.words_len() as u64)
+            .map_err(|e| ErrorKind::io_error("Cannot write vocabulary length", e))?;
+        write
+            .write_u64::<LittleEndian>(self.pool_len() as u64)
+            .map_err(|e| ErrorKind::io_error("Cannot write vocabulary string pool length", e))?;
+        write
+            .write_u64::<LittleEndian>(self.seed())
+            .map_err(|e| ErrorKind::io_error("Cannot write perfect hash seed", e))?;
+        write
+            .write_u64::<LittleEndian>(self.n_buckets() as u64)
+            .map_err(|e| ErrorKind::io_error("Cannot write number of perfect hash buckets", e))?;
+        write
+            .write_u64::<LittleEndian>(self.n_slots() as u64)
+            .map_err(|e| ErrorKind::io_error("Cannot write number of perfect hash slots", e))?;
+        write
+            .write_all(self.data())
+            .map_err(|e| ErrorKind::io_error("Cannot write vocabulary data", e))?;
+
+        Ok(())
+    }
+}
+
+/// Read the chunk header.
+fn read_chunk_header<R>(read: &mut R) -> Result<ChunkHeader>
+where
+    R: Read,
+{
+    ChunkIdentifier::ensure_chunk_type(read, ChunkIdentifier::MmapVocab)?;
+
+    // Read and discard chunk length.
+    read.read_u64::<LittleEndian>()
+        .map_err(|e| ErrorKind::io_error("Cannot read vocabulary chunk length", e))?;
+
+    let words_len =
+        read.read_u64::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read vocabulary length", e))? as usize;
+
+    let pool_len = read
+        .read_u64::<LittleEndian>()
+        .map_err(|e| ErrorKind::io_error("Cannot read vocabulary string pool length", e))?
+        as usize;
+    let seed = read
+        .read_u64::<LittleEndian>()
+        .map_err(|e| ErrorKind::io_error("Cannot read perfect hash seed", e))?;
+    let n_buckets = read
+        .read_u64::<LittleEndian>()
+        .map_err(|e| ErrorKind::io_error("Cannot read number of perfect hash buckets", e))?
+        as usize;
+    let n_slots = read
+        .read_u64::<LittleEndian>()
+        .map_err(|e| ErrorKind::io_error("Cannot read number of perfect hash slots", e))?
+        as usize;
+
+    let data_len =
+        MmapVocab::data_len(words_len, n_buckets, n_slots, pool_len).ok_or_else(|| {
+            ErrorKind::Format(format!(
+                "Vocabulary with {} words, {} buckets, {} slots, and {} bytes is too large",
+                words_len, n_buckets, n_slots, pool_len
+            ))
+        })?;
+
+    Ok(ChunkHeader {
+        words_len,
+        seed,
+        n_buckets,
+        n_slots,
+        data_len,
+    })
+}
+
+/// Fields of the chunk header that determine the chunk layout.
+struct ChunkHeader {
+    words_len: usize,
+    seed: u64,
+    n_buckets: usize,
+    n_slots: usize,
+    data_len: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::{BufReader, Cursor, Read, Seek, SeekFrom};
+
+    use finalfusion_core::vocab::{MmapVocab, Vocab, WordIndex};
+
+    use crate::chunks::io::{MmapChunk, ReadChunk, WriteChunk};
+    use crate::chunks::vocab::read_chunk_size;
+
+    fn test_words() -> Vec<String> {
+        vec![
+            "tops".to_owned(),
+            "tap".to_owned(),
+            "top".to_owned(),
+            "".to_owned(),
+            "taps".to_owned(),
+            "töpfe".to_owned(),
+        ]
+    }
+
+    fn test_mmap_vocab() -> MmapVocab {
+        MmapVocab::new(test_words())
+    }
+
+    fn check_lookups(vocab: &MmapVocab) {
+        let words = ["tops", "tap", "top", "", "taps", "töpfe"];
+        for (idx, word) in words.iter().enumerate() {
+            assert_eq!(vocab.idx(word), Some(WordIndex::Word(idx)));
+            assert_eq!(vocab.word(idx).as_deref(), Some(*word));
+        }
+        assert_eq!(vocab.word(words.len()), None);
+
+        for word in &["t", "to", "tapss", "pots", "töpf"] {
+            assert_eq!(vocab.idx(word), None);
+        }
+
+        assert_eq!(vocab.words_len(), 6);
+        assert_eq!(vocab.words(), &words[..]);
+        assert!(vocab.iter().map(|(word, _)| word).eq(words.iter().cloned()));
+    }
+
+    #[test]
+    fn mmap_vocab_write_read_roundtrip() {
+        for check_vocab in &[
+            test_mmap_vocab(),
+            MmapVocab::new_with_perfect_hash(test_words()),
+        ] {
+            let mut cursor = Cursor::new(Vec::new());
+            check_vocab.write_chunk(&mut cursor).unwrap();
+            cursor.seek(SeekFrom::Start(0)).unwrap();
+            let vocab = MmapVocab::read_chunk(&mut cursor).unwrap();
+            assert_eq!(&vocab, check_vocab);
+            assert!(!vocab.is_mapped());
+            vocab.validate().unwrap();
+        }
+    }
+
+    #[test]
+    fn mmap_vocab_write_mmap_roundtrip() {
+        let check_vocab = MmapVocab::new_with_perfect_hash(test_words());
+        let path = std::env::temp_dir().join(format!("mmap-vocab-{}.fifu", std::process::id()));
+        {
+            let mut file = File::create(&path).unwrap();
+            // Write at an unaligned offset.
+            std::io::Write::write_all(&mut file, &[0; 3]).unwrap();
+            check_vocab.write_chunk(&mut file).unwrap();
+        }
+
+        let mut reader = BufReader::new(File::open(&path).unwrap());
+        reader.seek(SeekFrom::Start(3)).unwrap();
+        let vocab = MmapVocab::mmap_chunk(&mut reader).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(vocab.is_mapped());
+        assert_eq!(vocab, check_vocab);
+        check_lookups(&vocab);
+        assert!(!vocab.clone().is_mapped());
+    }
+
+    #[test]
+    fn mmap_vocab_correct_chunk_size() {
+        for check_vocab in &[
+            test_mmap_vocab(),
+            MmapVocab::new_with_perfect_hash(test_words()),
+        ] {
+            let mut cursor = Cursor::new(Vec::new());
+            check_vocab.write_chunk(&mut cursor).unwrap();
+            cursor.seek(SeekFrom::Start(0)).unwrap();
+
+            let chunk_size = read_chunk_size(&mut cursor);
+            assert_eq!(
+                cursor.read_to_end(&mut Vec::new()).unwrap(),
+                chunk_size as usize
+            );
+        }
+    }
+
+    #[test]
+    fn mmap_vocab_rejects_unsorted_words() {
+        let check_vocab = MmapVocab::new(vec!["a".to_owned(), "b".to_owned()]);
+        let mut data = Vec::new();
+        check_vocab
+            .write_chunk(&mut Cursor::new(&mut data))
+            .unwrap();
+
+        // Swap the words in the string pool.
+        let len = data.len();
+        data.swap(len - 2, len - 1);
+
+        // Reading only performs constant-time checks.
+        let vocab = MmapVocab::read_chunk(&mut Cursor::new(data)).unwrap();
+        assert!(vocab.validate().is_err());
+    }
+
+    #[test]
+    fn mmap_vocab_rejects_invalid_perfect_hash() {
+        let check_vocab = MmapVocab::new_with_perfect_hash(vec!["a".to_owned(), "b".to_owned()]);
+        let mut data = Vec::new();
+        check_vocab
+            .write_chunk(&mut Cursor::new(&mut data))
+            .unwrap();
+
+        // Swap the ranks of the two slots, which precede the pool.
+        let slots = data.len() - 2 - 8;
+        let (first, second) = data[slots..slots + 8].split_at_mut(4);
+        first.swap_with_slice(second);
+
+        let vocab = MmapVocab::read_chunk(&mut Cursor::new(data)).unwrap();
+        assert!(vocab.validate().is_err());
+    }
+
+    #[test]
+    fn mmap_vocab_rejects_invalid_pool_length() {
+        let check_vocab = MmapVocab::new(vec!["a".to_owned(), "b".to_owned()]);
+        let mut data = Vec::new();
+        check_vocab
+            .write_chunk(&mut Cursor::new(&mut data))
+            .unwrap();
+
+        // Change the end offset of the last word, which is the last
+        // offset before the index tables.
+        let last_offset = data.len() - 2 - 2 * 2 * 4 - 8;
+        data[last_offset] = 3;
+
+        assert!(MmapVocab::read_chunk(&mut Cursor::new(data)).is_err());
+    }
+}
@@ -0,0 +1,114 @@
+//! Embedding vocabularies
+
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, Write};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use finalfusion_core::vocab::{
+    BpeVocab, BucketSubwordVocab, ByteFallbackVocab, ExplicitSubwordVocab, FastTextSubwordVocab,
+    FrontCodedVocab, FstVocab, NamespacedVocab, SentencePieceVocab, SimpleVocab, WordPieceVocab,
+};
+
+use crate::chunks::io::{MmapChunk, ReadChunk};
+use crate::io::{Error, ErrorKind, Result};
+
+mod alias;
+mod bpe;
+mod byte_fallback;
+mod front_coded;
+mod fst;
+mod language;
+mod mmap;
+mod namespaced;
+mod sentencepiece;
+mod simple;
+mod subword;
+mod wordpiece;
+mod wrappers;
+
+/// Implement `MmapChunk` for vocabularies that are read into memory.
+///
+/// Embeddings are memory mapped with `MmapEmbeddings` by memory mapping
+/// the vocabulary and the storage. Vocabularies that cannot be memory
+/// mapped are read into memory.
+macro_rules! impl_mmap_chunk_read {
+    ($($vocab:ty),*) => {
+        $(
+            impl MmapChunk for $vocab {
+                fn mmap_chunk(read: &mut BufReader<File>) -> Result<Self> {
+                    Self::read_chunk(read)
+                }
+            }
+        )*
+    };
+}
+
+impl_mmap_chunk_read!(
+    SimpleVocab,
+    BucketSubwordVocab,
+    FastTextSubwordVocab,
+    ExplicitSubwordVocab,
+    NamespacedVocab,
+    FstVocab,
+    FrontCodedVocab,
+    BpeVocab,
+    WordPieceVocab,
+    SentencePieceVocab
+);
+
+impl<V> MmapChunk for ByteFallbackVocab<V>
+where
+    V: ReadChunk,
+{
+    fn mmap_chunk(read: &mut BufReader<File>) -> Result<Self> {
+        Self::read_chunk(read)
+    }
+}
+
+pub(crate) fn read_vocab_items<R>(read: &mut R, len: usize) -> Result<Vec<String>>
+where
+    R: Read + Seek,
+{
+    let mut items = Vec::with_capacity(len);
+    for _ in 0..len {
+        let item_len = read
+            .read_u32::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read item length", e))?
+            as usize;
+        let mut bytes = vec![0; item_len];
+        read.read_exact(&mut bytes)
+            .map_err(|e| ErrorKind::io_error("Cannot read item", e))?;
+        let item = String::from_utf8(bytes)
+            .map_err(|e| ErrorKind::Format(format!("Item contains invalid UTF-8: {}", e)))
+            .map_err(Error::from)?;
+        items.push(item);
+    }
+    Ok(items)
+}
+
+pub(crate) fn write_vocab_items<W, I>(write: &mut W, items: I) -> Result<()>
+where
+    W: Write + Seek,
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+{
+    for word in items {
+        let word = word.as_ref();
+        write
+            .write_u32::<LittleEndian>(word.len() as u32)
+            .map_err(|e| ErrorKind::io_error("Cannot write token length", e))?;
+        write
+            .write_all(word.as_bytes())
+            .map_err(|e| ErrorKind::io_error("Cannot write token", e))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) fn read_chunk_size(read: &mut impl Read) -> u64 {
+    // Skip identifier.
+    read.read_u32::<LittleEndian>().unwrap();
+
+    // Return chunk length.
+    read.read_u64::<LittleEndian>().unwrap()
+}
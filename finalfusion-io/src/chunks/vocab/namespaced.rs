@@ -0,0 +1,178 @@
+use std::io::{Read, Seek, Write};
+use std::iter;
+use std::mem::size_of;
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use finalfusion_core::vocab::{create_indices, NamespacedVocab, NAMESPACE_SEPARATOR};
+
+use crate::chunks::io::{ChunkIdentifier, ReadChunk, WriteChunk};
+use crate::chunks::vocab::{read_vocab_items, write_vocab_items};
+use crate::io::{ErrorKind, Result};
+
+impl ReadChunk for NamespacedVocab {
+    fn read_chunk<R>(read: &mut R) -> Result<Self>
+    where
+        R: Read + Seek,
+    {
+        ChunkIdentifier::ensure_chunk_type(read, ChunkIdentifier::NamespacedVocab)?;
+
+        // Read and discard chunk length.
+        read.read_u64::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read vocabulary chunk length", e))?;
+
+        let n_namespaces = read
+            .read_u64::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read number of namespaces", e))?
+            as usize;
+
+        // The number of namespaces is not trusted for preallocation,
+        // since it is read from the file.
+        let mut namespaces = Vec::new();
+        for _ in 0..n_namespaces {
+            let namespace = read_vocab_items(read, 1)?.remove(0);
+            let vocab_len = read
+                .read_u64::<LittleEndian>()
+                .map_err(|e| ErrorKind::io_error("Cannot read namespace vocabulary length", e))?
+                as usize;
+            let words = read_vocab_items(read, vocab_len)?;
+            namespaces.push((namespace, words));
+        }
+
+        // Check the invariants that the constructor checks with assertions.
+        for (idx, (namespace, words)) in namespaces.iter().enumerate() {
+            if namespace.contains(NAMESPACE_SEPARATOR)
+                || namespaces[..idx]
+                    .iter()
+                    .any(|(other, _)| other == namespace)
+            {
+                return Err(ErrorKind::Format(format!("Invalid namespace: {}", namespace)).into());
+            }
+
+            if create_indices(words).len() != words.len() {
+                return Err(ErrorKind::Format(format!(
+                    "Namespace contains duplicate words: {}",
+                    namespace
+                ))
+                .into());
+            }
+        }
+
+        Ok(NamespacedVocab::new(namespaces))
+    }
+}
+
+impl WriteChunk for NamespacedVocab {
+    fn chunk_identifier(&self) -> ChunkIdentifier {
+        ChunkIdentifier::NamespacedVocab
+    }
+
+    fn write_chunk<W>(&self, write: &mut W) -> Result<()>
+    where
+        W: Write + Seek,
+    {
+        let namespace_words = self
+            .namespaces()
+            .iter()
+            .map(|namespace| {
+                let prefix_len = namespace.len() + NAMESPACE_SEPARATOR.len();
+                let words = self
+                    .namespace_words(namespace)
+                    .expect("Namespace of the vocabulary is unknown")
+                    .iter()
+                    .map(|word| word[prefix_len..].to_owned())
+                    .collect::<Vec<_>>();
+                (namespace, words)
+            })
+            .collect::<Vec<_>>();
+
+        // Chunk size: number of namespaces (u64), for each namespace:
+        // namespace length in bytes (4 bytes), namespace bytes
+        // (variable-length), vocabulary size (u64), for each word:
+        // word length in bytes (4 bytes), word bytes (variable-length).
+        let chunk_len = size_of::<u64>()
+            + namespace_words
+                .iter()
+                .map(|(namespace, words)| {
+                    size_of::<u32>()
+                        + namespace.len()
+                        + size_of::<u64>()
+                        + words
+                            .iter()
+                            .map(|w| w.len() + size_of::<u32>())
+                            .sum::<usize>()
+                })
+                .sum::<usize>();
+
+        write
+            .write_u32::<LittleEndian>(ChunkIdentifier::NamespacedVocab as u32)
+            .map_err(|e| ErrorKind::io_error("Cannot write vocabulary chunk identifier", e))?;
+        write
+            .write_u64::<LittleEndian>(chunk_len as u64)
+            .map_err(|e| ErrorKind::io_error("Cannot write vocabulary chunk length", e))?;
+        write
+            .write_u64::<LittleEndian>(self.namespaces().len() as u64)
+            .map_err(|e| ErrorKind::io_error("Cannot write number of namespaces", e))?;
+
+        for (namespace, words) in namespace_words {
+            write_vocab_items(write, iter::once(namespace))?;
+            write
+                .write_u64::<LittleEndian>(words.len() as u64)
+                .map_err(|e| ErrorKind::io_error("Cannot write namespace vocabulary length", e))?;
+            write_vocab_items(write, &words)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read, Seek, SeekFrom};
+
+    use finalfusion_core::vocab::NamespacedVocab;
+
+    use crate::chunks::io::{ReadChunk, WriteChunk};
+    use crate::chunks::vocab::read_chunk_size;
+
+    fn test_namespaced_vocab() -> NamespacedVocab {
+        NamespacedVocab::new(vec![
+            ("word", vec!["Berlin".to_owned(), "is".to_owned()]),
+            ("entity", vec!["Berlin".to_owned(), "Q64".to_owned()]),
+        ])
+    }
+
+    #[test]
+    fn namespaced_vocab_write_read_roundtrip() {
+        let check_vocab = test_namespaced_vocab();
+        let mut cursor = Cursor::new(Vec::new());
+        check_vocab.write_chunk(&mut cursor).unwrap();
+        cursor.seek(SeekFrom::Start(0)).unwrap();
+        let vocab = NamespacedVocab::read_chunk(&mut cursor).unwrap();
+        assert_eq!(vocab, check_vocab);
+    }
+
+    #[test]
+    fn namespaced_vocab_rejects_corrupt_namespace_count() {
+        let mut cursor = Cursor::new(Vec::new());
+        test_namespaced_vocab().write_chunk(&mut cursor).unwrap();
+
+        // Overwrite the number of namespaces.
+        let mut data = cursor.into_inner();
+        data[12..20].copy_from_slice(&[0xff; 8]);
+        assert!(NamespacedVocab::read_chunk(&mut Cursor::new(data)).is_err());
+    }
+
+    #[test]
+    fn namespaced_vocab_correct_chunk_size() {
+        let check_vocab = test_namespaced_vocab();
+        let mut cursor = Cursor::new(Vec::new());
+        check_vocab.write_chunk(&mut cursor).unwrap();
+        cursor.seek(SeekFrom::Start(0)).unwrap();
+
+        let chunk_size = read_chunk_size(&mut cursor);
+        assert_eq!(
+            cursor.read_to_end(&mut Vec::new()).unwrap(),
+            chunk_size as usize
+        );
+    }
+}
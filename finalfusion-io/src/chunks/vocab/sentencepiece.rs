@@ -0,0 +1,159 @@
+use std::io::{Read, Seek, Write};
+use std::mem::size_of;
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use finalfusion_core::vocab::{PieceType, SentencePieceModel, SentencePieceVocab, Vocab};
+
+use crate::chunks::io::{ChunkIdentifier, ReadChunk, WriteChunk};
+use crate::chunks::vocab::{read_vocab_items, write_vocab_items};
+use crate::io::{Error, ErrorKind, Result};
+
+impl ReadChunk for SentencePieceVocab {
+    fn read_chunk<R>(read: &mut R) -> Result<Self>
+    where
+        R: Read + Seek,
+    {
+        ChunkIdentifier::ensure_chunk_type(read, ChunkIdentifier::SentencePieceVocab)?;
+
+        // Read and discard chunk length.
+        read.read_u64::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read vocabulary chunk length", e))?;
+
+        let vocab_len = read
+            .read_u64::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read vocabulary length", e))?
+            as usize;
+        let model = read
+            .read_u32::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read SentencePiece model type", e))?;
+        let model = SentencePieceModel::from_u32(model)
+            .ok_or_else(|| {
+                ErrorKind::Format(format!("Unknown SentencePiece model type: {}", model))
+            })
+            .map_err(Error::from)?;
+
+        let pieces = read_vocab_items(read, vocab_len)?;
+
+        let mut scores = vec![0f32; vocab_len];
+        read.read_f32_into::<LittleEndian>(&mut scores)
+            .map_err(|e| ErrorKind::io_error("Cannot read piece scores", e))?;
+
+        let mut types = vec![0u8; vocab_len];
+        read.read_exact(&mut types)
+            .map_err(|e| ErrorKind::io_error("Cannot read piece types", e))?;
+        let types = types
+            .into_iter()
+            .map(|piece_type| {
+                PieceType::from_u8(piece_type).ok_or_else(|| {
+                    ErrorKind::Format(format!("Unknown piece type: {}", piece_type)).into()
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(SentencePieceVocab::new(pieces, scores, types, model))
+    }
+}
+
+impl WriteChunk for SentencePieceVocab {
+    fn chunk_identifier(&self) -> ChunkIdentifier {
+        ChunkIdentifier::SentencePieceVocab
+    }
+
+    fn write_chunk<W>(&self, write: &mut W) -> Result<()>
+    where
+        W: Write + Seek,
+    {
+        // Chunk size: vocabulary size (u64), model type (u32), for
+        // each piece: piece length in bytes (u32), piece bytes
+        // (variable-length), score (f32), type (u8).
+        let chunk_len = size_of::<u64>()
+            + size_of::<u32>()
+            + self
+                .words()
+                .iter()
+                .map(|piece| piece.len() + size_of::<u32>() + size_of::<f32>() + 1)
+                .sum::<usize>();
+
+        write
+            .write_u32::<LittleEndian>(ChunkIdentifier::SentencePieceVocab as u32)
+            .map_err(|e| ErrorKind::io_error("Cannot write vocabulary chunk identifier", e))?;
+        write
+            .write_u64::<LittleEndian>(chunk_len as u64)
+            .map_err(|e| ErrorKind::io_error("Cannot write vocabulary chunk length", e))?;
+        write
+            .write_u64::<LittleEndian>(self.words().len() as u64)
+            .map_err(|e| ErrorKind::io_error("Cannot write vocabulary length", e))?;
+        write
+            .write_u32::<LittleEndian>(self.model().to_u32())
+            .map_err(|e| ErrorKind::io_error("Cannot write SentencePiece model type", e))?;
+
+        write_vocab_items(write, self.words())?;
+
+        for &score in self.scores() {
+            write
+                .write_f32::<LittleEndian>(score)
+                .map_err(|e| ErrorKind::io_error("Cannot write piece score", e))?;
+        }
+
+        let types = self
+            .types()
+            .iter()
+            .map(|piece_type| piece_type.to_u8())
+            .collect::<Vec<_>>();
+        write
+            .write_all(&types)
+            .map_err(|e| ErrorKind::io_error("Cannot write piece types", e))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read, Seek, SeekFrom};
+
+    use finalfusion_core::vocab::{PieceType, SentencePieceModel, SentencePieceVocab};
+
+    use crate::chunks::io::{ReadChunk, WriteChunk};
+    use crate::chunks::vocab::read_chunk_size;
+
+    fn test_sentencepiece_vocab(model: SentencePieceModel) -> SentencePieceVocab {
+        let pieces = [
+            "<unk>", "▁", "▁l", "ow", "▁low", "er", "e", "r", "o", "w", "l",
+        ];
+        let scores = [0., -1., -2., -3., -1.5, -2.5, -5., -5., -5., -5., -5.];
+        let mut types = vec![PieceType::Normal; pieces.len()];
+        types[0] = PieceType::Unknown;
+
+        SentencePieceVocab::new(
+            pieces.iter().map(|&p| p.to_owned()).collect::<Vec<_>>(),
+            scores.to_vec(),
+            types,
+            model,
+        )
+    }
+
+    #[test]
+    fn sentencepiece_vocab_write_read_roundtrip() {
+        let check_vocab = test_sentencepiece_vocab(SentencePieceModel::Bpe);
+        let mut cursor = Cursor::new(Vec::new());
+        check_vocab.write_chunk(&mut cursor).unwrap();
+        cursor.seek(SeekFrom::Start(0)).unwrap();
+        let vocab = SentencePieceVocab::read_chunk(&mut cursor).unwrap();
+        assert_eq!(vocab, check_vocab);
+    }
+
+    #[test]
+    fn sentencepiece_vocab_correct_chunk_size() {
+        let check_vocab = test_sentencepiece_vocab(SentencePieceModel::Unigram);
+        let mut cursor = Cursor::new(Vec::new());
+        check_vocab.write_chunk(&mut cursor).unwrap();
+        cursor.seek(SeekFrom::Start(0)).unwrap();
+
+        let chunk_size = read_chunk_size(&mut cursor);
+        assert_eq!(
+            cursor.read_to_end(&mut Vec::new()).unwrap(),
+            chunk_size as usize
+        );
+    }
+}
@@ -1,56 +1,13 @@
-use std::collections::HashMap;
 use std::io::{Read, Seek, Write};
 use std::mem::size_of;
 
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use finalfusion_core::vocab::{SimpleVocab, Vocab};
 
 use crate::chunks::io::{ChunkIdentifier, ReadChunk, WriteChunk};
-use crate::chunks::vocab::{create_indices, read_vocab_items, write_vocab_items, Vocab, WordIndex};
+use crate::chunks::vocab::{read_vocab_items, write_vocab_items};
 use crate::io::{ErrorKind, Result};
 
-/// Vocabulary without subword units.
-#[derive(Clone, Debug, Eq, PartialEq)]
-pub struct SimpleVocab {
-    indices: HashMap<String, usize>,
-    words: Vec<String>,
-}
-
-impl SimpleVocab {
-    /// Construct a new simple vocabulary.
-    ///
-    /// Words are assigned indices in the given order.
-    ///
-    /// Panics when there are duplicate words.
-    pub fn new(words: impl Into<Vec<String>>) -> Self {
-        let words = words.into();
-        let indices = create_indices(&words);
-        assert_eq!(
-            words.len(),
-            indices.len(),
-            "words contained duplicate entries."
-        );
-        SimpleVocab { words, indices }
-    }
-}
-
-impl Vocab for SimpleVocab {
-    fn idx(&self, word: &str) -> Option<WordIndex> {
-        self.indices.get(word).cloned().map(WordIndex::Word)
-    }
-
-    fn words_len(&self) -> usize {
-        self.indices.len()
-    }
-
-    fn vocab_len(&self) -> usize {
-        self.words_len()
-    }
-
-    fn words(&self) -> &[String] {
-        &self.words
-    }
-}
-
 impl ReadChunk for SimpleVocab {
     fn read_chunk<R>(read: &mut R) -> Result<Self>
     where
@@ -86,7 +43,7 @@ impl WriteChunk for SimpleVocab {
         // word length in bytes (4 bytes), word bytes (variable-length).
         let chunk_len = size_of::<u64>()
             + self
-                .words
+                .words()
                 .iter()
                 .map(|w| w.len() + size_of::<u32>())
                 .sum::<usize>();
@@ -98,7 +55,7 @@ impl WriteChunk for SimpleVocab {
             .write_u64::<LittleEndian>(chunk_len as u64)
             .map_err(|e| ErrorKind::io_error("Cannot write vocabulary chunk length", e))?;
         write
-            .write_u64::<LittleEndian>(self.words.len() as u64)
+            .write_u64::<LittleEndian>(self.words().len() as u64)
             .map_err(|e| ErrorKind::io_error("Cannot write vocabulary length", e))?;
 
         write_vocab_items(write, self.words())?;
@@ -111,7 +68,8 @@ impl WriteChunk for SimpleVocab {
 mod tests {
     use std::io::{Cursor, Read, Seek, SeekFrom};
 
-    use super::SimpleVocab;
+    use finalfusion_core::vocab::SimpleVocab;
+
     use crate::chunks::io::{ReadChunk, WriteChunk};
     use crate::chunks::vocab::read_chunk_size;
 
@@ -0,0 +1,531 @@
+use std::io::{self, Read, Seek, Write};
+use std::mem::size_of;
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use finalfusion_core::subword::{BucketIndexer, ExplicitIndexer, Indexer};
+use finalfusion_core::vocab::{
+    BucketSubwordVocab, ExplicitSubwordVocab, FastTextSubwordVocab, SubwordVocab, Vocab,
+};
+
+use crate::chunks::io::{ChunkIdentifier, ReadChunk, WriteChunk};
+use crate::chunks::vocab::{read_vocab_items, write_vocab_items};
+use crate::io::{Error, ErrorKind, Result};
+
+impl ReadChunk for FastTextSubwordVocab {
+    fn read_chunk<R>(read: &mut R) -> Result<Self>
+    where
+        R: Read + Seek,
+    {
+        read_bucketed_chunk(read, ChunkIdentifier::FastTextSubwordVocab)
+    }
+}
+
+impl ReadChunk for BucketSubwordVocab {
+    fn read_chunk<R>(read: &mut R) -> Result<Self>
+    where
+        R: Read + Seek,
+    {
+        read_bucketed_chunk(read, ChunkIdentifier::BucketSubwordVocab)
+    }
+}
+
+impl ReadChunk for ExplicitSubwordVocab {
+    fn read_chunk<R>(read: &mut R) -> Result<Self>
+    where
+        R: Read + Seek,
+    {
+        read_ngram_chunk(read, ChunkIdentifier::ExplicitSubwordVocab)
+    }
+}
+
+impl WriteChunk for FastTextSubwordVocab {
+    fn chunk_identifier(&self) -> ChunkIdentifier {
+        ChunkIdentifier::FastTextSubwordVocab
+    }
+
+    fn write_chunk<W>(&self, write: &mut W) -> Result<()>
+    where
+        W: Write + Seek,
+    {
+        write_bucketed_chunk(self, write, self.chunk_identifier())
+    }
+}
+
+impl WriteChunk for BucketSubwordVocab {
+    fn chunk_identifier(&self) -> ChunkIdentifier {
+        ChunkIdentifier::BucketSubwordVocab
+    }
+
+    fn write_chunk<W>(&self, write: &mut W) -> Result<()>
+    where
+        W: Write + Seek,
+    {
+        write_bucketed_chunk(self, write, self.chunk_identifier())
+    }
+}
+
+impl WriteChunk for ExplicitSubwordVocab {
+    fn chunk_identifier(&self) -> ChunkIdentifier {
+        ChunkIdentifier::ExplicitSubwordVocab
+    }
+
+    fn write_chunk<W>(&self, write: &mut W) -> Result<()>
+    where
+        W: Write + Seek,
+    {
+        write_ngram_chunk(self, write, self.chunk_identifier())
+    }
+}
+
+fn read_bucketed_chunk<I, R>(
+    read: &mut R,
+    chunk_identifier: ChunkIdentifier,
+) -> Result<SubwordVocab<I>>
+where
+    I: BucketIndexer,
+    R: Read + Seek,
+{
+    ChunkIdentifier::ensure_chunk_type(read, chunk_identifier)?;
+
+    let chunk_len = read
+        .read_u64::<LittleEndian>()
+        .map_err(|e| ErrorKind::io_error("Cannot read vocabulary chunk length", e))?;
+
+    let vocab_len =
+        read.read_u64::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read vocabulary length", e))? as usize;
+    let min_n = read
+        .read_u32::<LittleEndian>()
+        .map_err(|e| ErrorKind::io_error("Cannot read minimum n-gram length", e))?;
+    let max_n = read
+        .read_u32::<LittleEndian>()
+        .map_err(|e| ErrorKind::io_error("Cannot read maximum n-gram length", e))?;
+    let buckets = read
+        .read_u32::<LittleEndian>()
+        .map_err(|e| ErrorKind::io_error("Cannot read number of buckets", e))?;
+
+    let words = read_vocab_items(read, vocab_len)?;
+
+    let data_len = size_of::<u64>() + 3 * size_of::<u32>() + items_len(&words);
+    let (bow, eow) = read_markers::<I, _>(read, remaining_len(chunk_len, data_len)?)?;
+
+    Ok(SubwordVocab::new(words, min_n, max_n, I::new(buckets as usize)).with_markers(bow, eow))
+}
+
+fn write_bucketed_chunk<I, W>(
+    vocab: &SubwordVocab<I>,
+    write: &mut W,
+    chunk_identifier: ChunkIdentifier,
+) -> Result<()>
+where
+    I: BucketIndexer,
+    W: Write + Seek,
+{
+    // Chunk size: vocab size (u64), minimum n-gram length (u32),
+    // maximum n-gram length (u32), bucket exponent (u32), for
+    // each word: word length in bytes (u32), word bytes
+    // (variable-length), optionally followed by the begin-of-word
+    // and end-of-word markers (u32).
+    let chunk_len = size_of::<u64>()
+        + size_of::<u32>()
+        + size_of::<u32>()
+        + size_of::<u32>()
+        + items_len(vocab.words())
+        + markers_len(vocab);
+
+    write
+        .write_u32::<LittleEndian>(chunk_identifier as u32)
+        .map_err(|e| ErrorKind::io_error("Cannot write subword vocabulary chunk identifier", e))?;
+    write
+        .write_u64::<LittleEndian>(chunk_len as u64)
+        .map_err(|e| ErrorKind::io_error("Cannot write subword vocabulary chunk length", e))?;
+    write
+        .write_u64::<LittleEndian>(vocab.words().len() as u64)
+        .map_err(|e| ErrorKind::io_error("Cannot write vocabulary length", e))?;
+    write
+        .write_u32::<LittleEndian>(vocab.min_n())
+        .map_err(|e| ErrorKind::io_error("Cannot write minimum n-gram length", e))?;
+    write
+        .write_u32::<LittleEndian>(vocab.max_n())
+        .map_err(|e| ErrorKind::io_error("Cannot write maximum n-gram length", e))?;
+    write
+        .write_u32::<LittleEndian>(vocab.indexer().buckets() as u32)
+        .map_err(|e| ErrorKind::io_error("Cannot write number of buckets", e))?;
+
+    write_vocab_items(write, vocab.words())?;
+    write_markers(vocab, write)?;
+
+    Ok(())
+}
+
+fn read_ngram_chunk<R>(
+    read: &mut R,
+    chunk_identifier: ChunkIdentifier,
+) -> Result<ExplicitSubwordVocab>
+where
+    R: Read + Seek,
+{
+    ChunkIdentifier::ensure_chunk_type(read, chunk_identifier)?;
+    let chunk_len = read
+        .read_u64::<LittleEndian>()
+        .map_err(|e| ErrorKind::io_error("Cannot read vocabulary chunk length", e))?;
+    let words_len = read
+        .read_u64::<LittleEndian>()
+        .map_err(|e| ErrorKind::io_error("Cannot read number of words", e))?;
+    let ngrams_len = read
+        .read_u64::<LittleEndian>()
+        .map_err(|e| ErrorKind::io_error("Cannot read number of ngrams", e))?;
+    let min_n = read
+        .read_u32::<LittleEndian>()
+        .map_err(|e| ErrorKind::io_error("Cannot read minimum n-gram length", e))?;
+    let max_n = read
+        .read_u32::<LittleEndian>()
+        .map_err(|e| ErrorKind::io_error("Cannot read maximum n-gram length", e))?;
+
+    let words = read_vocab_items(read, words_len as usize)?;
+    let ngrams = read_ngrams_with_indices(read, ngrams_len as usize)?;
+
+    let data_len = 2 * size_of::<u64>()
+        + 2 * size_of::<u32>()
+        + items_len(&words)
+        + ngrams
+            .iter()
+            .map(|(ngram, _)| ngram.len() + size_of::<u32>() + size_of::<u64>())
+            .sum::<usize>();
+    let (bow, eow) = read_markers::<ExplicitIndexer, _>(read, remaining_len(chunk_len, data_len)?)?;
+
+    let indexer = ExplicitIndexer::new_with_indices(ngrams);
+    Ok(SubwordVocab::new(words, min_n, max_n, indexer).with_markers(bow, eow))
+}
+
+fn write_ngram_chunk<W>(
+    vocab: &ExplicitSubwordVocab,
+    write: &mut W,
+    chunk_identifier: ChunkIdentifier,
+) -> Result<()>
+where
+    W: Write + Seek,
+{
+    // Chunk size: word vocab size (u64), ngram vocab size (u64)
+    // minimum n-gram length (u32), maximum n-gram length (u32),
+    // for each word and ngram:
+    // length in bytes (u32), number of bytes (variable-length).
+    // each ngram is followed by its index (u64), optionally followed
+    // by the begin-of-word and end-of-word markers (u32).
+    let chunk_len = size_of::<u64>()
+        + size_of::<u64>()
+        + size_of::<u32>()
+        + size_of::<u32>()
+        + items_len(vocab.words())
+        + vocab
+            .indexer()
+            .ngrams()
+            .iter()
+            .map(|ngram| ngram.len() + size_of::<u32>() + size_of::<u64>())
+            .sum::<usize>()
+        + markers_len(vocab);
+
+    write
+        .write_u32::<LittleEndian>(chunk_identifier as u32)
+        .map_err(|e| ErrorKind::io_error("Cannot write subword vocabulary chunk identifier", e))?;
+    write
+        .write_u64::<LittleEndian>(chunk_len as u64)
+        .map_err(|e| ErrorKind::io_error("Cannot write subword vocabulary chunk length", e))?;
+    write
+        .write_u64::<LittleEndian>(vocab.words().len() as u64)
+        .map_err(|e| ErrorKind::io_error("Cannot write vocabulary length", e))?;
+    write
+        .write_u64::<LittleEndian>(vocab.indexer().ngrams().len() as u64)
+        .map_err(|e| ErrorKind::io_error("Cannot write ngram length", e))?;
+    write
+        .write_u32::<LittleEndian>(vocab.min_n())
+        .map_err(|e| ErrorKind::io_error("Cannot write minimum n-gram length", e))?;
+    write
+        .write_u32::<LittleEndian>(vocab.max_n())
+        .map_err(|e| ErrorKind::io_error("Cannot write maximum n-gram length", e))?;
+
+    write_vocab_items(write, vocab.words())?;
+    write_ngrams_with_indices(write, vocab.indexer())?;
+    write_markers(vocab, write)?;
+
+    Ok(())
+}
+
+/// Length of the marker trailer in the vocabulary chunk.
+///
+/// The markers are only stored when they differ from the
+/// defaults, so that chunks with default markers can be read
+/// by older versions.
+fn markers_len<I>(vocab: &SubwordVocab<I>) -> usize {
+    if vocab.has_default_markers() {
+        0
+    } else {
+        2 * size_of::<u32>()
+    }
+}
+
+fn write_markers<I, W>(vocab: &SubwordVocab<I>, write: &mut W) -> Result<()>
+where
+    W: Write,
+{
+    if vocab.has_default_markers() {
+        return Ok(());
+    }
+
+    write
+        .write_u32::<LittleEndian>(vocab.bow() as u32)
+        .map_err(|e| ErrorKind::io_error("Cannot write begin-of-word marker", e))?;
+    write
+        .write_u32::<LittleEndian>(vocab.eow() as u32)
+        .map_err(|e| ErrorKind::io_error("Cannot write end-of-word marker", e))?;
+
+    Ok(())
+}
+
+/// Read the marker trailer, `remaining` is the number of chunk
+/// bytes that were not read yet.
+fn read_markers<I, R>(read: &mut R, remaining: u64) -> Result<(char, char)>
+where
+    R: Read,
+{
+    match remaining {
+        0 => Ok((
+            SubwordVocab::<I>::DEFAULT_BOW,
+            SubwordVocab::<I>::DEFAULT_EOW,
+        )),
+        8 => {
+            let bow = read_marker(read, "begin-of-word")?;
+            let eow = read_marker(read, "end-of-word")?;
+            Ok((bow, eow))
+        }
+        _ => Err(ErrorKind::Format(format!(
+            "Subword vocabulary chunk has {} trailing bytes",
+            remaining
+        ))
+        .into()),
+    }
+}
+
+fn read_marker<R>(read: &mut R, name: &str) -> Result<char>
+where
+    R: Read,
+{
+    let code = read
+        .read_u32::<LittleEndian>()
+        .map_err(|e| ErrorKind::io_error(format!("Cannot read {} marker", name), e))?;
+    char::from_u32(code)
+        .ok_or_else(|| ErrorKind::Format(format!("Invalid {} marker: {:#x}", name, code)).into())
+}
+
+/// Serialized length of vocabulary items.
+fn items_len(items: &[String]) -> usize {
+    items.iter().map(|w| w.len() + size_of::<u32>()).sum()
+}
+
+/// Number of chunk bytes after the first `data_len` bytes.
+fn remaining_len(chunk_len: u64, data_len: usize) -> Result<u64> {
+    chunk_len.checked_sub(data_len as u64).ok_or_else(|| {
+        ErrorKind::Format(format!(
+            "Subword vocabulary chunk length {} is smaller than its data ({} bytes)",
+            chunk_len, data_len
+        ))
+        .into()
+    })
+}
+
+fn read_ngrams_with_indices<R>(read: &mut R, len: usize) -> Result<Vec<(String, u64)>>
+where
+    R: Read + Seek,
+{
+    let mut ngrams = Vec::with_capacity(len);
+    for _ in 0..len {
+        let ngram_len = read
+            .read_u32::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read item length", e))?
+            as usize;
+        let mut bytes = vec![0; ngram_len];
+        read.read_exact(&mut bytes)
+            .map_err(|e| ErrorKind::io_error("Cannot read item", e))?;
+        let item = String::from_utf8(bytes)
+            .map_err(|e| ErrorKind::Format(format!("Item contains invalid UTF-8: {}", e)))
+            .map_err(Error::from)?;
+        let idx = read
+            .read_u64::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read ngram index.", e))?;
+        ngrams.push((item, idx));
+    }
+    Ok(ngrams)
+}
+
+fn write_ngrams_with_indices<W>(write: &mut W, indexer: &ExplicitIndexer) -> Result<()>
+where
+    W: Write + Seek,
+{
+    for ngram in indexer.ngrams() {
+        let idx = indexer.index_ngram(&ngram.as_str().into()).ok_or_else(|| {
+            ErrorKind::io_error(
+                format!(
+                    "Indexer could not index n-gram during serialization: {}",
+                    ngram
+                ),
+                io::ErrorKind::Other.into(),
+            )
+        })?;
+        write
+            .write_u32::<LittleEndian>(ngram.len() as u32)
+            .map_err(|e| ErrorKind::io_error("Cannot write ngram length", e))?;
+        write
+            .write_all(ngram.as_bytes())
+            .map_err(|e| ErrorKind::io_error("Cannot write ngram", e))?;
+        write
+            .write_u64::<LittleEndian>(idx)
+            .map_err(|e| ErrorKind::io_error("Cannot write ngram idx", e))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read, Seek, SeekFrom};
+
+    use finalfusion_core::subword::{
+        BucketIndexer, ExplicitIndexer, FastTextIndexer, FinalfusionHashIndexer,
+    };
+    use finalfusion_core::vocab::{
+        BucketSubwordVocab, ExplicitSubwordVocab, FastTextSubwordVocab, SubwordVocab,
+    };
+
+    use crate::chunks::io::{ReadChunk, WriteChunk};
+    use crate::chunks::vocab::read_chunk_size;
+
+    fn test_fasttext_subword_vocab() -> FastTextSubwordVocab {
+        let words = vec![
+            "this".to_owned(),
+            "is".to_owned(),
+            "a".to_owned(),
+            "test".to_owned(),
+        ];
+        let indexer = FastTextIndexer::new(20);
+        SubwordVocab::new(words, 3, 6, indexer)
+    }
+
+    fn test_subword_vocab() -> BucketSubwordVocab {
+        let words = vec![
+            "this".to_owned(),
+            "is".to_owned(),
+            "a".to_owned(),
+            "test".to_owned(),
+        ];
+        let indexer = FinalfusionHashIndexer::new(20);
+        SubwordVocab::new(words, 3, 6, indexer)
+    }
+
+    fn test_ngram_vocab() -> ExplicitSubwordVocab {
+        let words = vec![
+            "this".to_owned(),
+            "is".to_owned(),
+            "a".to_owned(),
+            "test".to_owned(),
+        ];
+
+        let ngrams = vec![
+            ("is>".to_owned(), 0),
+            ("is".to_owned(), 1),
+            ("<t".to_owned(), 2),
+        ];
+
+        ExplicitSubwordVocab::new(words, 2, 3, ExplicitIndexer::new_with_indices(ngrams))
+    }
+
+    #[test]
+    fn fasttext_subword_vocab_write_read_roundtrip() {
+        let check_vocab = test_fasttext_subword_vocab();
+        let mut cursor = Cursor::new(Vec::new());
+        check_vocab.write_chunk(&mut cursor).unwrap();
+        cursor.seek(SeekFrom::Start(0)).unwrap();
+        let vocab = SubwordVocab::read_chunk(&mut cursor).unwrap();
+        assert_eq!(vocab, check_vocab);
+    }
+
+    #[test]
+    fn subword_vocab_write_read_roundtrip() {
+        let check_vocab = test_subword_vocab();
+        let mut cursor = Cursor::new(Vec::new());
+        check_vocab.write_chunk(&mut cursor).unwrap();
+        cursor.seek(SeekFrom::Start(0)).unwrap();
+        let vocab = SubwordVocab::read_chunk(&mut cursor).unwrap();
+        assert_eq!(vocab, check_vocab);
+    }
+
+    #[test]
+    fn subword_vocab_markers_write_read_roundtrip() {
+        let check_vocab = test_subword_vocab().with_markers('^', '$');
+        let mut cursor = Cursor::new(Vec::new());
+        check_vocab.write_chunk(&mut cursor).unwrap();
+        cursor.seek(SeekFrom::Start(0)).unwrap();
+        let vocab = BucketSubwordVocab::read_chunk(&mut cursor).unwrap();
+        assert_eq!(vocab.bow(), '^');
+        assert_eq!(vocab.eow(), '$');
+        assert_eq!(vocab, check_vocab);
+
+        let check_vocab = test_ngram_vocab().with_markers('⟨', '⟩');
+        let mut cursor = Cursor::new(Vec::new());
+        check_vocab.write_chunk(&mut cursor).unwrap();
+        cursor.seek(SeekFrom::Start(0)).unwrap();
+        let vocab = ExplicitSubwordVocab::read_chunk(&mut cursor).unwrap();
+        assert_eq!(vocab, check_vocab);
+    }
+
+    #[test]
+    fn subword_vocab_default_markers_are_not_stored() {
+        let mut default_data = Vec::new();
+        test_subword_vocab()
+            .write_chunk(&mut Cursor::new(&mut default_data))
+            .unwrap();
+        let mut marker_data = Vec::new();
+        test_subword_vocab()
+            .with_markers('^', '$')
+            .write_chunk(&mut Cursor::new(&mut marker_data))
+            .unwrap();
+        assert_eq!(marker_data.len(), default_data.len() + 8);
+        assert_eq!(&marker_data[12..marker_data.len() - 8], &default_data[12..]);
+    }
+
+    #[test]
+    fn subword_vocab_correct_chunk_size() {
+        for check_vocab in &[
+            test_subword_vocab(),
+            test_subword_vocab().with_markers('^', '$'),
+        ] {
+            let mut cursor = Cursor::new(Vec::new());
+            check_vocab.write_chunk(&mut cursor).unwrap();
+            cursor.seek(SeekFrom::Start(0)).unwrap();
+
+            let chunk_size = read_chunk_size(&mut cursor);
+            assert_eq!(
+                cursor.read_to_end(&mut Vec::new()).unwrap(),
+                chunk_size as usize
+            );
+        }
+    }
+
+    #[test]
+    fn ngram_vocab_write_read_roundtrip() {
+        let check_vocab = test_ngram_vocab();
+        let mut cursor = Cursor::new(Vec::new());
+        check_vocab.write_chunk(&mut cursor).unwrap();
+        cursor.seek(SeekFrom::Start(0)).unwrap();
+        let vocab = ExplicitSubwordVocab::read_chunk(&mut cursor).unwrap();
+        assert_eq!(vocab, check_vocab);
+    }
+
+    #[test]
+    fn ngram_vocab_correct_chunk_size() {
+        let check_vocab = test_ngram_vocab();
+        let mut cursor = Cursor::new(Vec::new());
+        check_vocab.write_chunk(&mut cursor).unwrap();
+        cursor.seek(SeekFrom::Start(0)).unwrap();
+        let vocab = SubwordVocab::read_chunk(&mut cursor).unwrap();
+        assert_eq!(vocab, check_vocab);
+    }
+}
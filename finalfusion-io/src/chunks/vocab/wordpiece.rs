@@ -0,0 +1,124 @@
+use std::io::{Read, Seek, Write};
+use std::iter;
+use std::mem::size_of;
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use finalfusion_core::vocab::{Vocab, WordPieceVocab};
+
+use crate::chunks::io::{ChunkIdentifier, ReadChunk, WriteChunk};
+use crate::chunks::vocab::{read_vocab_items, write_vocab_items};
+use crate::io::{ErrorKind, Result};
+
+impl ReadChunk for WordPieceVocab {
+    fn read_chunk<R>(read: &mut R) -> Result<Self>
+    where
+        R: Read + Seek,
+    {
+        ChunkIdentifier::ensure_chunk_type(read, ChunkIdentifier::WordPieceVocab)?;
+
+        // Read and discard chunk length.
+        read.read_u64::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read vocabulary chunk length", e))?;
+
+        let vocab_len = read
+            .read_u64::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read vocabulary length", e))?
+            as usize;
+        let n_pieces = read
+            .read_u64::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read number of pieces", e))?
+            as usize;
+
+        let continuation_prefix = read_vocab_items(read, 1)?.remove(0);
+        let words = read_vocab_items(read, vocab_len)?;
+        let pieces = read_vocab_items(read, n_pieces)?;
+
+        Ok(WordPieceVocab::new(words, pieces, continuation_prefix))
+    }
+}
+
+impl WriteChunk for WordPieceVocab {
+    fn chunk_identifier(&self) -> ChunkIdentifier {
+        ChunkIdentifier::WordPieceVocab
+    }
+
+    fn write_chunk<W>(&self, write: &mut W) -> Result<()>
+    where
+        W: Write + Seek,
+    {
+        // Chunk size: vocabulary size (u64), number of pieces (u64),
+        // continuation prefix, words, and pieces. Each string is
+        // stored as its length in bytes (u32) and its bytes
+        // (variable-length).
+        let string_len = |s: &str| s.len() + size_of::<u32>();
+        let chunk_len = 2 * size_of::<u64>()
+            + string_len(self.continuation_prefix())
+            + self.words().iter().map(|w| string_len(w)).sum::<usize>()
+            + self.pieces().iter().map(|p| string_len(p)).sum::<usize>();
+
+        write
+            .write_u32::<LittleEndian>(ChunkIdentifier::WordPieceVocab as u32)
+            .map_err(|e| ErrorKind::io_error("Cannot write vocabulary chunk identifier", e))?;
+        write
+            .write_u64::<LittleEndian>(chunk_len as u64)
+            .map_err(|e| ErrorKind::io_error("Cannot write vocabulary chunk length", e))?;
+        write
+            .write_u64::<LittleEndian>(self.words().len() as u64)
+            .map_err(|e| ErrorKind::io_error("Cannot write vocabulary length", e))?;
+        write
+            .write_u64::<LittleEndian>(self.pieces().len() as u64)
+            .map_err(|e| ErrorKind::io_error("Cannot write number of pieces", e))?;
+
+        write_vocab_items(write, iter::once(self.continuation_prefix()))?;
+        write_vocab_items(write, self.words())?;
+        write_vocab_items(write, self.pieces())?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read, Seek, SeekFrom};
+
+    use finalfusion_core::vocab::WordPieceVocab;
+
+    use crate::chunks::io::{ReadChunk, WriteChunk};
+    use crate::chunks::vocab::read_chunk_size;
+
+    fn strings(strs: &[&str]) -> Vec<String> {
+        strs.iter().map(|s| (*s).to_owned()).collect()
+    }
+
+    fn test_wordpiece_vocab() -> WordPieceVocab {
+        WordPieceVocab::new(
+            strings(&["affable", "want"]),
+            strings(&["un", "u", "##n", "##aff", "##able", "##a", "##ff", "##ä"]),
+            WordPieceVocab::CONTINUATION_PREFIX,
+        )
+    }
+
+    #[test]
+    fn wordpiece_vocab_write_read_roundtrip() {
+        let check_vocab = test_wordpiece_vocab();
+        let mut cursor = Cursor::new(Vec::new());
+        check_vocab.write_chunk(&mut cursor).unwrap();
+        cursor.seek(SeekFrom::Start(0)).unwrap();
+        let vocab = WordPieceVocab::read_chunk(&mut cursor).unwrap();
+        assert_eq!(vocab, check_vocab);
+    }
+
+    #[test]
+    fn wordpiece_vocab_correct_chunk_size() {
+        let check_vocab = test_wordpiece_vocab();
+        let mut cursor = Cursor::new(Vec::new());
+        check_vocab.write_chunk(&mut cursor).unwrap();
+        cursor.seek(SeekFrom::Start(0)).unwrap();
+
+        let chunk_size = read_chunk_size(&mut cursor);
+        assert_eq!(
+            cursor.read_to_end(&mut Vec::new()).unwrap(),
+            chunk_size as usize
+        );
+    }
+}
@@ -0,0 +1,162 @@
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom, Write};
+
+use byteorder::{LittleEndian, ReadBytesExt};
+use finalfusion_core::vocab::{
+    AliasVocab, BpeVocab, ByteFallbackVocab, FrontCodedVocab, FstVocab, MmapVocab, NamespacedVocab,
+    SentencePieceVocab, SimpleVocab, SubwordVocab, VocabWrap, WordPieceVocab,
+};
+
+use crate::chunks::io::{ChunkIdentifier, MmapChunk, ReadChunk, WriteChunk};
+use crate::io::{Error, ErrorKind, Result};
+
+impl ReadChunk for VocabWrap {
+    fn read_chunk<R>(read: &mut R) -> Result<Self>
+    where
+        R: Read + Seek,
+    {
+        let chunk_id = peek_chunk_identifier(read)?;
+
+        match chunk_id {
+            ChunkIdentifier::SimpleVocab => {
+                SimpleVocab::read_chunk(read).map(VocabWrap::SimpleVocab)
+            }
+            ChunkIdentifier::FastTextSubwordVocab => {
+                SubwordVocab::read_chunk(read).map(VocabWrap::FastTextSubwordVocab)
+            }
+            ChunkIdentifier::BucketSubwordVocab => {
+                SubwordVocab::read_chunk(read).map(VocabWrap::BucketSubwordVocab)
+            }
+            ChunkIdentifier::ExplicitSubwordVocab => {
+                SubwordVocab::read_chunk(read).map(VocabWrap::ExplicitSubwordVocab)
+            }
+            ChunkIdentifier::NamespacedVocab => {
+                NamespacedVocab::read_chunk(read).map(VocabWrap::NamespacedVocab)
+            }
+            ChunkIdentifier::FstVocab => FstVocab::read_chunk(read).map(VocabWrap::FstVocab),
+            ChunkIdentifier::FrontCodedVocab => {
+                FrontCodedVocab::read_chunk(read).map(VocabWrap::FrontCodedVocab)
+            }
+            ChunkIdentifier::MmapVocab => MmapVocab::read_chunk(read).map(VocabWrap::MmapVocab),
+            ChunkIdentifier::BpeVocab => BpeVocab::read_chunk(read).map(VocabWrap::BpeVocab),
+            ChunkIdentifier::WordPieceVocab => {
+                WordPieceVocab::read_chunk(read).map(VocabWrap::WordPieceVocab)
+            }
+            ChunkIdentifier::SentencePieceVocab => {
+                SentencePieceVocab::read_chunk(read).map(VocabWrap::SentencePieceVocab)
+            }
+            ChunkIdentifier::ByteFallbackVocab => {
+                ByteFallbackVocab::<VocabWrap>::read_chunk(read).map(Into::into)
+            }
+            ChunkIdentifier::AliasVocab => {
+                AliasVocab::<VocabWrap>::read_chunk(read).map(Into::into)
+            }
+            _ => Err(ErrorKind::Format(format!(
+                "Invalid chunk identifier, expected one of: {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {} or {}, got: {}",
+                ChunkIdentifier::SimpleVocab,
+                ChunkIdentifier::ExplicitSubwordVocab,
+                ChunkIdentifier::FastTextSubwordVocab,
+                ChunkIdentifier::BucketSubwordVocab,
+                ChunkIdentifier::NamespacedVocab,
+                ChunkIdentifier::FstVocab,
+                ChunkIdentifier::FrontCodedVocab,
+                ChunkIdentifier::MmapVocab,
+                ChunkIdentifier::ByteFallbackVocab,
+                ChunkIdentifier::AliasVocab,
+                ChunkIdentifier::BpeVocab,
+                ChunkIdentifier::WordPieceVocab,
+                ChunkIdentifier::SentencePieceVocab,
+                chunk_id
+            ))
+            .into()),
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        match self {
+            VocabWrap::MmapVocab(inner) => inner.validate(),
+            VocabWrap::ByteFallbackVocab(inner) => inner.validate(),
+            VocabWrap::AliasVocab(inner) => inner.validate(),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl MmapChunk for VocabWrap {
+    /// Memory map a vocabulary chunk.
+    ///
+    /// Only `MmapVocab` chunks, including those wrapped by an
+    /// `AliasVocab`, are memory mapped. Other vocabularies are read
+    /// into memory.
+    fn mmap_chunk(read: &mut BufReader<File>) -> Result<Self> {
+        match peek_chunk_identifier(read)? {
+            ChunkIdentifier::MmapVocab => MmapVocab::mmap_chunk(read).map(VocabWrap::MmapVocab),
+            ChunkIdentifier::AliasVocab => {
+                AliasVocab::<VocabWrap>::mmap_chunk(read).map(Into::into)
+            }
+            _ => Self::read_chunk(read),
+        }
+    }
+}
+
+impl WriteChunk for VocabWrap {
+    fn chunk_identifier(&self) -> ChunkIdentifier {
+        match self {
+            VocabWrap::SimpleVocab(inner) => inner.chunk_identifier(),
+            VocabWrap::ExplicitSubwordVocab(inner) => inner.chunk_identifier(),
+            VocabWrap::FastTextSubwordVocab(inner) => inner.chunk_identifier(),
+            VocabWrap::BucketSubwordVocab(inner) => inner.chunk_identifier(),
+            VocabWrap::NamespacedVocab(inner) => inner.chunk_identifier(),
+            VocabWrap::FstVocab(inner) => inner.chunk_identifier(),
+            VocabWrap::FrontCodedVocab(inner) => inner.chunk_identifier(),
+            VocabWrap::MmapVocab(inner) => inner.chunk_identifier(),
+            VocabWrap::BpeVocab(inner) => inner.chunk_identifier(),
+            VocabWrap::WordPieceVocab(inner) => inner.chunk_identifier(),
+            VocabWrap::SentencePieceVocab(inner) => inner.chunk_identifier(),
+            VocabWrap::ByteFallbackVocab(inner) => inner.chunk_identifier(),
+            VocabWrap::AliasVocab(inner) => inner.chunk_identifier(),
+        }
+    }
+
+    fn write_chunk<W>(&self, write: &mut W) -> Result<()>
+    where
+        W: Write + Seek,
+    {
+        match self {
+            VocabWrap::SimpleVocab(inner) => inner.write_chunk(write),
+            VocabWrap::ExplicitSubwordVocab(inner) => inner.write_chunk(write),
+            VocabWrap::FastTextSubwordVocab(inner) => inner.write_chunk(write),
+            VocabWrap::BucketSubwordVocab(inner) => inner.write_chunk(write),
+            VocabWrap::NamespacedVocab(inner) => inner.write_chunk(write),
+            VocabWrap::FstVocab(inner) => inner.write_chunk(write),
+            VocabWrap::FrontCodedVocab(inner) => inner.write_chunk(write),
+            VocabWrap::MmapVocab(inner) => inner.write_chunk(write),
+            VocabWrap::BpeVocab(inner) => inner.write_chunk(write),
+            VocabWrap::WordPieceVocab(inner) => inner.write_chunk(write),
+            VocabWrap::SentencePieceVocab(inner) => inner.write_chunk(write),
+            VocabWrap::ByteFallbackVocab(inner) => inner.write_chunk(write),
+            VocabWrap::AliasVocab(inner) => inner.write_chunk(write),
+        }
+    }
+}
+
+/// Read the identifier of the next chunk without consuming it.
+fn peek_chunk_identifier<R>(read: &mut R) -> Result<ChunkIdentifier>
+where
+    R: Read + Seek,
+{
+    let chunk_start_pos = read
+        .stream_position()
+        .map_err(|e| ErrorKind::io_error("Cannot get vocabulary chunk start position", e))?;
+    let chunk_id = read
+        .read_u32::<LittleEndian>()
+        .map_err(|e| ErrorKind::io_error("Cannot read vocabulary chunk identifier", e))?;
+    let chunk_id = ChunkIdentifier::try_from(chunk_id)
+        .ok_or_else(|| ErrorKind::Format(format!("Unknown chunk identifier: {}", chunk_id)))
+        .map_err(Error::from)?;
+
+    read.seek(SeekFrom::Start(chunk_start_pos))
+        .map_err(|e| ErrorKind::io_error("Cannot seek to vocabulary chunk start position", e))?;
+
+    Ok(chunk_id)
+}
@@ -0,0 +1,497 @@
+//! Comparison of finalfusion files.
+//!
+//! Conversions and migrations of embeddings should usually retain
+//! the vocabulary and embeddings, or change them only within some
+//! tolerance. `diff` reads two finalfusion files and reports the
+//! differences per chunk:
+//!
+//! * The chunks that are only in one of the files.
+//! * The metadata keys that were added, removed, or changed.
+//! * The words that were added to or removed from the vocabulary.
+//! * For every word in both vocabularies, the largest difference
+//!   between the components of its embeddings and the difference
+//!   between its norms.
+//!
+//! ```
+//! use std::fs::File;
+//! use std::io::BufReader;
+//!
+//! use finalfusion_io::diff::diff;
+//!
+//! let mut read1 = BufReader::new(File::open("../testdata/similarity.fifu").unwrap());
+//! let mut read2 = BufReader::new(File::open("../testdata/similarity.fifu").unwrap());
+//!
+//! let diff = diff(&mut read1, &mut read2).unwrap();
+//! assert!(diff.is_empty());
+//! ```
+
+use std::collections::HashSet;
+use std::io::{Read, Seek, SeekFrom};
+
+use finalfusion_core::embeddings::Embeddings;
+use finalfusion_core::storage::{Storage, StorageWrap};
+use finalfusion_core::vocab::{Vocab, VocabWrap, WordIndex};
+use toml::Value;
+
+use crate::chunks::io::{ChunkIdentifier, Header, ReadChunk};
+use crate::io::{ErrorKind, ReadEmbeddings, Result};
+
+/// Differences between two finalfusion files.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EmbeddingsDiff {
+    /// Differences in the chunks of the files.
+    pub chunks: ChunksDiff,
+
+    /// Metadata keys that differ.
+    ///
+    /// Keys of nested tables are joined by periods, e.g.
+    /// `training.epochs`.
+    pub metadata: Vec<MetadataChange>,
+
+    /// Differences in the vocabularies.
+    pub vocab: VocabDiff,
+
+    /// Differences in the embeddings.
+    pub storage: StorageDiff,
+}
+
+impl EmbeddingsDiff {
+    /// Check whether the files have the same chunks and data.
+    pub fn is_empty(&self) -> bool {
+        self.chunks.removed.is_empty()
+            && self.chunks.added.is_empty()
+            && self.metadata.is_empty()
+            && self.vocab.removed.is_empty()
+            && self.vocab.added.is_empty()
+            && self.storage.dims.0 == self.storage.dims.1
+            && self.storage.max_delta() == 0.
+            && self.storage.max_norm_delta() == 0.
+    }
+}
+
+/// Differences in the chunks of two files.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ChunksDiff {
+    /// Names of the chunks that are only in the first file.
+    pub removed: Vec<String>,
+
+    /// Names of the chunks that are only in the second file.
+    pub added: Vec<String>,
+}
+
+/// A metadata key that differs between two files.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MetadataChange {
+    /// The key.
+    pub key: String,
+
+    /// The value in the first file, `None` if the key is absent.
+    pub old: Option<Value>,
+
+    /// The value in the second file, `None` if the key is absent.
+    pub new: Option<Value>,
+}
+
+/// Differences in the vocabularies of two files.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct VocabDiff {
+    /// Words that are only in the first vocabulary.
+    pub removed: Vec<String>,
+
+    /// Words that are only in the second vocabulary.
+    pub added: Vec<String>,
+}
+
+/// Differences in the embeddings of two files.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StorageDiff {
+    /// Dimensionality of the embeddings in the first and second file.
+    pub dims: (usize, usize),
+
+    /// Differences of the words that are in both vocabularies.
+    ///
+    /// The rows are in the vocabulary order of the first file. Rows
+    /// are only compared when the embeddings have the same
+    /// dimensionality.
+    pub rows: Vec<RowDiff>,
+}
+
+impl StorageDiff {
+    /// Get the largest component difference of all rows.
+    ///
+    /// A NaN difference is reported as infinity.
+    pub fn max_delta(&self) -> f32 {
+        max_delta(self.rows.iter().map(|row| row.max_delta))
+    }
+
+    /// Get the largest norm difference of all rows.
+    ///
+    /// A NaN difference is reported as infinity.
+    pub fn max_norm_delta(&self) -> f32 {
+        max_delta(self.rows.iter().filter_map(|row| row.norm_delta))
+    }
+}
+
+/// Differences between the embeddings of a word.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RowDiff {
+    /// The word.
+    pub word: String,
+
+    /// The largest absolute difference between the embedding components.
+    ///
+    /// A component that is NaN in only one of the files, or that is
+    /// a different NaN, differs by infinity.
+    pub max_delta: f32,
+
+    /// The absolute difference between the norms.
+    ///
+    /// `None` when one of the files does not have norms. NaN norms
+    /// are compared as embedding components.
+    pub norm_delta: Option<f32>,
+}
+
+/// Compare two finalfusion files.
+///
+/// The embeddings are compared as stored. Lookup transforms and
+/// normalizations are not applied, and only the embeddings of words
+/// are compared, not those of subword units.
+pub fn diff<R1, R2>(read1: &mut R1, read2: &mut R2) -> Result<EmbeddingsDiff>
+where
+    R1: Read + Seek,
+    R2: Read + Seek,
+{
+    let chunks1 = read_chunk_names(read1)?;
+    let chunks2 = read_chunk_names(read2)?;
+
+    let embeds1: Embeddings<VocabWrap, StorageWrap> = Embeddings::read_embeddings(read1)?;
+    let embeds2: Embeddings<VocabWrap, StorageWrap> = Embeddings::read_embeddings(read2)?;
+
+    let mut metadata = Vec::new();
+    diff_metadata(
+        "",
+        embeds1.metadata().map(|m| &**m),
+        embeds2.metadata().map(|m| &**m),
+        &mut metadata,
+    );
+
+    Ok(EmbeddingsDiff {
+        chunks: ChunksDiff {
+            removed: difference(&chunks1, &chunks2),
+            added: difference(&chunks2, &chunks1),
+        },
+        metadata,
+        vocab: VocabDiff {
+            removed: vocab_difference(embeds1.vocab(), embeds2.vocab()),
+            added: vocab_difference(embeds2.vocab(), embeds1.vocab()),
+        },
+        storage: diff_storage(&embeds1, &embeds2),
+    })
+}
+
+/// Read the names of the chunks of a file.
+///
+/// The reader is positioned at the start of the file afterwards.
+fn read_chunk_names<R>(read: &mut R) -> Result<Vec<String>>
+where
+    R: Read + Seek,
+{
+    let start = read
+        .stream_position()
+        .map_err(|e| ErrorKind::io_error("Cannot get file position", e))?;
+    let header = Header::read_chunk(read)?;
+    read.seek(SeekFrom::Start(start))
+        .map_err(|e| ErrorKind::io_error("Cannot seek to start of embeddings", e))?;
+
+    Ok(header
+        .chunk_identifiers()
+        .iter()
+        .map(ChunkIdentifier::to_string)
+        .chain(
+            header
+                .unknown_identifiers()
+                .iter()
+                .chain(header.custom_identifiers())
+                .map(|identifier| format!("Unknown({})", identifier)),
+        )
+        .collect())
+}
+
+/// Get the items of `items` that are not in `other`.
+fn difference(items: &[String], other: &[String]) -> Vec<String> {
+    let other = other.iter().collect::<HashSet<_>>();
+    items
+        .iter()
+        .filter(|item| !other.contains(item))
+        .cloned()
+        .collect()
+}
+
+/// Get the words of `vocab` that are not in `other`.
+fn vocab_difference(vocab: &dyn Vocab, other: &dyn Vocab) -> Vec<String> {
+    vocab
+        .iter()
+        .filter(|(word, _)| other.idx(word).and_then(|idx| idx.word()).is_none())
+        .map(|(word, _)| word.into_owned())
+        .collect()
+}
+
+fn diff_metadata(
+    prefix: &str,
+    old: Option<&Value>,
+    new: Option<&Value>,
+    changes: &mut Vec<MetadataChange>,
+) {
+    match (old, new) {
+        (Some(Value::Table(old)), Some(Value::Table(new))) => {
+            for (key, old_value) in old {
+                diff_metadata(
+                    &metadata_key(prefix, key),
+                    Some(old_value),
+                    new.get(key),
+                    changes,
+                );
+            }
+
+            for (key, new_value) in new {
+                if !old.contains_key(key) {
+                    diff_metadata(&metadata_key(prefix, key), None, Some(new_value), changes);
+                }
+            }
+        }
+        (old, new) if old != new => changes.push(MetadataChange {
+            key: prefix.to_owned(),
+            old: old.cloned(),
+            new: new.cloned(),
+        }),
+        _ => (),
+    }
+}
+
+fn metadata_key(prefix: &str, key: &str) -> String {
+    if prefix.is_empty() {
+        key.to_owned()
+    } else {
+        format!("{}.{}", prefix, key)
+    }
+}
+
+/// Get the absolute difference between two values.
+///
+/// Values with the same bit pattern do not differ, other differences
+/// that are NaN are mapped to infinity. This ensures that NaN values
+/// are never ignored when differences are compared.
+fn abs_delta(v1: f32, v2: f32) -> f32 {
+    if v1.to_bits() == v2.to_bits() {
+        return 0.;
+    }
+
+    let delta = (v1 - v2).abs();
+    if delta.is_nan() {
+        f32::INFINITY
+    } else {
+        delta
+    }
+}
+
+/// Get the largest of the given differences.
+///
+/// Returns infinity if one of the differences is NaN.
+fn max_delta(deltas: impl Iterator<Item = f32>) -> f32 {
+    deltas.fold(0., |max, delta| {
+        if delta.is_nan() {
+            f32::INFINITY
+        } else {
+            max.max(delta)
+        }
+    })
+}
+
+fn diff_storage<V, S>(embeds1: &Embeddings<V, S>, embeds2: &Embeddings<V, S>) -> StorageDiff
+where
+    V: Vocab,
+    S: Storage,
+{
+    let dims = (embeds1.dims(), embeds2.dims());
+    if dims.0 != dims.1 {
+        return StorageDiff {
+            dims,
+            rows: Vec::new(),
+        };
+    }
+
+    let rows = embeds1
+        .vocab()
+        .iter()
+        .filter_map(|(word, idx1)| {
+            let idx2 = match embeds2.vocab().idx(&word)? {
+                WordIndex::Word(idx2) => idx2,
+                WordIndex::Subword(_) => return None,
+            };
+
+            let max_delta = max_delta(
+                embeds1
+                    .storage()
+                    .embedding(idx1)
+                    .iter()
+                    .zip(embeds2.storage().embedding(idx2).iter())
+                    .map(|(&v1, &v2)| abs_delta(v1, v2)),
+            );
+
+            let norm_delta = match (embeds1.norms(), embeds2.norms()) {
+                (Some(norms1), Some(norms2)) => Some(abs_delta(norms1[idx1], norms2[idx2])),
+                _ => None,
+            };
+
+            Some(RowDiff {
+                word: word.into_owned(),
+                max_delta,
+                norm_delta,
+            })
+        })
+        .collect();
+
+    StorageDiff { dims, rows }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::{BufReader, Cursor, Read};
+
+    use ndarray::{arr1, array, Array2};
+    use toml::toml;
+
+    use crate::io::WriteEmbeddings;
+    use finalfusion_core::embeddings::Embeddings;
+    use finalfusion_core::metadata::Metadata;
+    use finalfusion_core::norms::NdNorms;
+    use finalfusion_core::storage::NdArray;
+    use finalfusion_core::vocab::SimpleVocab;
+
+    use super::{diff, MetadataChange};
+
+    fn write(embeds: &Embeddings<SimpleVocab, NdArray>) -> Cursor<Vec<u8>> {
+        let mut data = Cursor::new(Vec::new());
+        embeds.write_embeddings(&mut data).unwrap();
+        data.set_position(0);
+        data
+    }
+
+    fn test_embeddings(words: &[&str], norms: &[f32]) -> Embeddings<SimpleVocab, NdArray> {
+        let vocab = SimpleVocab::new(words.iter().map(|&w| w.to_owned()).collect::<Vec<_>>());
+        let storage = NdArray::new(Array2::from_shape_fn((words.len(), 2), |(row, col)| {
+            (row + col) as f32
+        }));
+        Embeddings::new(None, vocab, storage, NdNorms::new(arr1(norms)))
+    }
+
+    #[test]
+    fn diff_identical_files() {
+        let mut data = Vec::new();
+        File::open("../testdata/similarity.fifu")
+            .unwrap()
+            .read_to_end(&mut data)
+            .unwrap();
+
+        let mut read1 = BufReader::new(File::open("../testdata/similarity.fifu").unwrap());
+        let diff = diff(&mut read1, &mut Cursor::new(data)).unwrap();
+        assert!(diff.is_empty());
+        assert_eq!(diff.storage.rows.len(), 41);
+    }
+
+    #[test]
+    fn diff_reports_differences_per_chunk() {
+        let mut embeds1 = test_embeddings(&["a", "b", "c"], &[1., 2., 3.]);
+        embeds1.set_metadata(Some(Metadata::new(toml! {
+            name = "test"
+            [training]
+            epochs = 5
+        })));
+
+        let mut embeds2 = test_embeddings(&["b", "a", "d"], &[1., 2.5, 1.]);
+        embeds2.set_metadata(Some(Metadata::new(toml! {
+            [training]
+            epochs = 10
+            dims = 2
+        })));
+
+        let diff = diff(&mut write(&embeds1), &mut write(&embeds2)).unwrap();
+        assert!(!diff.is_empty());
+        assert!(diff.chunks.removed.is_empty());
+        assert!(diff.chunks.added.is_empty());
+
+        assert_eq!(diff.vocab.removed, vec!["c"]);
+        assert_eq!(diff.vocab.added, vec!["d"]);
+
+        assert_eq!(
+            diff.metadata,
+            vec![
+                MetadataChange {
+                    key: "name".to_owned(),
+                    old: Some("test".into()),
+                    new: None,
+                },
+                MetadataChange {
+                    key: "training.epochs".to_owned(),
+                    old: Some(5.into()),
+                    new: Some(10.into()),
+                },
+                MetadataChange {
+                    key: "training.dims".to_owned(),
+                    old: None,
+                    new: Some(2.into()),
+                },
+            ]
+        );
+
+        let rows = diff
+            .storage
+            .rows
+            .iter()
+            .map(|row| (row.word.as_str(), row.max_delta, row.norm_delta))
+            .collect::<Vec<_>>();
+        assert_eq!(rows, vec![("a", 1., Some(1.5)), ("b", 1., Some(1.))]);
+        assert_eq!(diff.storage.max_delta(), 1.);
+        assert_eq!(diff.storage.max_norm_delta(), 1.5);
+    }
+
+    #[test]
+    fn diff_reports_chunks_and_dims() {
+        let embeds1 = test_embeddings(&["a"], &[1.]);
+        let embeds2 = Embeddings::new_without_norms(
+            None,
+            SimpleVocab::new(vec!["a".to_owned()]),
+            NdArray::new(Array2::zeros((1, 3))),
+        );
+
+        let diff = diff(&mut write(&embeds1), &mut write(&embeds2)).unwrap();
+        assert_eq!(diff.chunks.removed, vec!["NdNorms"]);
+        assert!(diff.chunks.added.is_empty());
+        assert_eq!(diff.storage.dims, (2, 3));
+        assert!(diff.storage.rows.is_empty());
+    }
+
+    #[test]
+    fn diff_reports_nan_differences() {
+        let embeds1 = test_embeddings(&["a", "b"], &[1., f32::NAN]);
+        let embeds2 = Embeddings::new(
+            None,
+            SimpleVocab::new(vec!["a".to_owned(), "b".to_owned()]),
+            NdArray::new(array![[0., f32::NAN], [1., 2.]]),
+            NdNorms::new(arr1(&[1., 1.])),
+        );
+
+        let nan_diff = diff(&mut write(&embeds1), &mut write(&embeds2)).unwrap();
+        assert!(!nan_diff.is_empty());
+        assert_eq!(nan_diff.storage.rows[0].max_delta, f32::INFINITY);
+        assert_eq!(nan_diff.storage.rows[1].norm_delta, Some(f32::INFINITY));
+        assert_eq!(nan_diff.storage.max_delta(), f32::INFINITY);
+        assert_eq!(nan_diff.storage.max_norm_delta(), f32::INFINITY);
+
+        // Identical NaN values are not a difference.
+        assert!(diff(&mut write(&embeds1), &mut write(&embeds1))
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,882 @@
+//! Serialization of embeddings.
+
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, Write};
+
+use finalfusion_core::counts::WordCounts;
+use finalfusion_core::embeddings::Embeddings;
+use finalfusion_core::metadata::Metadata;
+use finalfusion_core::norms::NdNorms;
+use finalfusion_core::storage::{LayeredStorage, Storage};
+use finalfusion_core::unknown::UnknownChunk;
+use finalfusion_core::vocab::{LayeredVocab, RetainWords, Vocab};
+use ndarray::Axis;
+
+use crate::chunks::storage::RemappableMmapArray;
+
+use crate::chunks::io::{
+    read_chunk_strict, read_unknown_chunk, write_unknown_chunk, ChunkIdentifier, Header, MmapChunk,
+    PreadChunk, ReadChunk, ReadChunkRows, ReadChunkTruncated, WriteChunk, WriteChunkRows,
+};
+use crate::io::{
+    ChunkRegistry, CustomChunk, ErrorKind, MmapEmbeddings, MmapLayeredEmbeddings, PreadEmbeddings,
+    ReadEmbeddings, ReadEmbeddingsStrict, ReadEmbeddingsSubset, ReadEmbeddingsTruncated,
+    ReadEmbeddingsWithRegistry, RemapEmbeddings, Result, WriteEmbeddings, WriteEmbeddingsFiltered,
+    WriteEmbeddingsWithChunks,
+};
+
+/// Read the optional norms and counts chunks.
+///
+/// The chunks are read when they are listed in the header. They
+/// cannot be probed for, since custom chunks can follow.
+fn read_optional_chunks<R>(
+    read: &mut R,
+    chunks: &[ChunkIdentifier],
+) -> Result<(Option<NdNorms>, Option<WordCounts>)>
+where
+    R: Read + Seek,
+{
+    let norms = if chunks.contains(&ChunkIdentifier::NdNorms) {
+        Some(NdNorms::read_chunk(read)?)
+    } else {
+        None
+    };
+
+    let counts = if chunks.contains(&ChunkIdentifier::WordCounts) {
+        Some(WordCounts::read_chunk(read)?)
+    } else {
+        None
+    };
+
+    Ok((norms, counts))
+}
+
+/// Read chunks that are unknown to this crate as opaque data.
+fn read_unknown_chunks<'a, R>(
+    read: &mut R,
+    identifiers: impl IntoIterator<Item = &'a u32>,
+) -> Result<Vec<UnknownChunk>>
+where
+    R: Read + Seek,
+{
+    identifiers
+        .into_iter()
+        .map(|&identifier| read_unknown_chunk(read, identifier))
+        .collect()
+}
+
+impl<V, S> MmapEmbeddings for Embeddings<V, S>
+where
+    Self: Sized,
+    V: MmapChunk,
+    S: MmapChunk,
+{
+    fn mmap_embeddings(read: &mut BufReader<File>) -> Result<Self> {
+        let header = Header::read_chunk(read)?;
+        let chunks = header.chunk_identifiers();
+        if chunks.is_empty() {
+            return Err(
+                ErrorKind::Format(String::from("Embedding file does not contain chunks")).into(),
+            );
+        }
+
+        let metadata = if header.chunk_identifiers()[0] == ChunkIdentifier::Metadata {
+            Some(Metadata::read_chunk(read)?)
+        } else {
+            None
+        };
+
+        let vocab = V::mmap_chunk(read)?;
+        let storage = S::mmap_chunk(read)?;
+        let (norms, counts) = read_optional_chunks(read, chunks)?;
+        let unknown_chunks = read_unknown_chunks(
+            read,
+            header
+                .unknown_identifiers()
+                .iter()
+                .chain(header.custom_identifiers()),
+        )?;
+
+        Ok(Embeddings::from_parts(
+            metadata,
+            vocab,
+            storage,
+            norms,
+            counts,
+            unknown_chunks,
+        ))
+    }
+}
+
+impl MmapLayeredEmbeddings for Embeddings<LayeredVocab, LayeredStorage> {
+    fn mmap_layered(reads: &mut [BufReader<File>]) -> Result<Self> {
+        let layers = reads
+            .iter_mut()
+            .map(Embeddings::mmap_embeddings)
+            .collect::<Result<Vec<_>>>()?;
+
+        Embeddings::layered(layers)
+    }
+}
+
+impl<V> RemapEmbeddings for Embeddings<V, RemappableMmapArray> {
+    fn remap(&mut self) -> Result<bool> {
+        self.storage_mut().remap()
+    }
+}
+
+impl<V, S> PreadEmbeddings for Embeddings<V, S>
+where
+    Self: Sized,
+    V: ReadChunk,
+    S: PreadChunk,
+{
+    fn pread_embeddings(read: &mut BufReader<File>) -> Result<Self> {
+        let header = Header::read_chunk(read)?;
+        let chunks = header.chunk_identifiers();
+        if chunks.is_empty() {
+            return Err(
+                ErrorKind::Format(String::from("Embedding file does not contain chunks")).into(),
+            );
+        }
+
+        let metadata = if header.chunk_identifiers()[0] == ChunkIdentifier::Metadata {
+            Some(Metadata::read_chunk(read)?)
+        } else {
+            None
+        };
+
+        let vocab = V::read_chunk(read)?;
+        let storage = S::pread_chunk(read)?;
+        let (norms, counts) = read_optional_chunks(read, chunks)?;
+        let unknown_chunks = read_unknown_chunks(
+            read,
+            header
+                .unknown_identifiers()
+                .iter()
+                .chain(header.custom_identifiers()),
+        )?;
+
+        Ok(Embeddings::from_parts(
+            metadata,
+            vocab,
+            storage,
+            norms,
+            counts,
+            unknown_chunks,
+        ))
+    }
+}
+
+impl<V, S> ReadEmbeddingsTruncated for Embeddings<V, S>
+where
+    V: ReadChunk + Vocab,
+    S: ReadChunkTruncated,
+{
+    fn read_embeddings_truncated(read: &mut BufReader<File>, dims: usize) -> Result<Self> {
+        let header = Header::read_chunk(read)?;
+        let chunks = header.chunk_identifiers();
+        if chunks.is_empty() {
+            return Err(
+                ErrorKind::Format(String::from("Embedding file does not contain chunks")).into(),
+            );
+        }
+
+        let metadata = if header.chunk_identifiers()[0] == ChunkIdentifier::Metadata {
+            Some(Metadata::read_chunk(read)?)
+        } else {
+            None
+        };
+
+        let vocab = V::read_chunk(read)?;
+        let mut storage = S::read_chunk_truncated(read, dims)?;
+        let (norms, counts) = read_optional_chunks(read, chunks)?;
+
+        // Truncated embeddings are not unit vectors. Normalize them and
+        // fold their norms into the norms of the full embeddings, so
+        // that the truncated embeddings can still be unnormalized.
+        let mut truncated_norms = storage.normalize_rows(vocab.words_len())?;
+        if let Some(norms) = norms {
+            if norms.len() != truncated_norms.len() {
+                return Err(ErrorKind::Format(format!(
+                    "Number of norms ({}) does not match the vocabulary size ({})",
+                    norms.len(),
+                    truncated_norms.len()
+                ))
+                .into());
+            }
+            truncated_norms *= &norms.view();
+        }
+        let norms = Some(NdNorms::new(truncated_norms));
+
+        Ok(Embeddings::from_parts(
+            metadata,
+            vocab,
+            storage,
+            norms,
+            counts,
+            Vec::new(),
+        ))
+    }
+}
+
+impl<V, S> ReadEmbeddingsSubset for Embeddings<V, S>
+where
+    V: ReadChunk + RetainWords,
+    S: ReadChunkRows,
+{
+    fn read_subset<R>(read: &mut R, words: &[impl AsRef<str>]) -> Result<Self>
+    where
+        R: Read + Seek,
+    {
+        let header = Header::read_chunk(read)?;
+        let chunks = header.chunk_identifiers();
+        if chunks.is_empty() {
+            return Err(
+                ErrorKind::Format(String::from("Embedding file does not contain chunks")).into(),
+            );
+        }
+
+        let metadata = if header.chunk_identifiers()[0] == ChunkIdentifier::Metadata {
+            Some(Metadata::read_chunk(read)?)
+        } else {
+            None
+        };
+
+        let vocab = V::read_chunk(read)?;
+        let mut indices = words
+            .iter()
+            .filter_map(|word| vocab.idx(word.as_ref())?.word())
+            .collect::<Vec<_>>();
+        indices.sort_unstable();
+        indices.dedup();
+        let (vocab, rows) = vocab.retain_indices(&indices);
+
+        let storage = S::read_chunk_rows(read, &rows)?;
+        let (norms, counts) = read_optional_chunks(read, chunks)?;
+
+        Ok(Embeddings::from_parts(
+            metadata,
+            vocab,
+            storage,
+            norms.map(|norms| NdNorms::new(norms.select(Axis(0), &indices))),
+            counts.map(|counts| {
+                WordCounts::new(indices.iter().map(|&idx| counts[idx]).collect::<Vec<_>>())
+            }),
+            Vec::new(),
+        ))
+    }
+}
+
+impl<V, S> ReadEmbeddings for Embeddings<V, S>
+where
+    V: ReadChunk,
+    S: ReadChunk,
+{
+    fn read_embeddings<R>(read: &mut R) -> Result<Self>
+    where
+        R: Read + Seek,
+    {
+        let header = Header::read_chunk(read)?;
+        let chunks = header.chunk_identifiers();
+        if chunks.is_empty() {
+            return Err(
+                ErrorKind::Format(String::from("Embedding file does not contain chunks")).into(),
+            );
+        }
+
+        let metadata = if header.chunk_identifiers()[0] == ChunkIdentifier::Metadata {
+            Some(Metadata::read_chunk(read)?)
+        } else {
+            None
+        };
+
+        let vocab = V::read_chunk(read)?;
+        let storage = S::read_chunk(read)?;
+        let (norms, counts) = read_optional_chunks(read, chunks)?;
+        let unknown_chunks = read_unknown_chunks(
+            read,
+            header
+                .unknown_identifiers()
+                .iter()
+                .chain(header.custom_identifiers()),
+        )?;
+
+        Ok(Embeddings::from_parts(
+            metadata,
+            vocab,
+            storage,
+            norms,
+            counts,
+            unknown_chunks,
+        ))
+    }
+}
+
+impl<V, S> ReadEmbeddingsStrict for Embeddings<V, S>
+where
+    V: ReadChunk,
+    S: ReadChunk,
+{
+    fn read_embeddings_strict<R>(read: &mut R) -> Result<Self>
+    where
+        R: Read + Seek,
+    {
+        let header = Header::read_chunk(read)?;
+        let chunks = header.chunk_identifiers();
+        if chunks.is_empty() {
+            return Err(
+                ErrorKind::Format(String::from("Embedding file does not contain chunks")).into(),
+            );
+        }
+
+        let metadata = if header.chunk_identifiers()[0] == ChunkIdentifier::Metadata {
+            Some(read_chunk_strict(read, Metadata::read_chunk)?)
+        } else {
+            None
+        };
+
+        let vocab = read_chunk_strict(read, V::read_chunk)?;
+        vocab.validate()?;
+        let storage = read_chunk_strict(read, S::read_chunk)?;
+        storage.validate()?;
+
+        let norms = if chunks.contains(&ChunkIdentifier::NdNorms) {
+            Some(read_chunk_strict(read, NdNorms::read_chunk)?)
+        } else {
+            None
+        };
+        let counts = if chunks.contains(&ChunkIdentifier::WordCounts) {
+            Some(read_chunk_strict(read, WordCounts::read_chunk)?)
+        } else {
+            None
+        };
+
+        let unknown_chunks = header
+            .unknown_identifiers()
+            .iter()
+            .chain(header.custom_identifiers())
+            .map(|&identifier| read_chunk_strict(read, |read| read_unknown_chunk(read, identifier)))
+            .collect::<Result<_>>()?;
+
+        Ok(Embeddings::from_parts(
+            metadata,
+            vocab,
+            storage,
+            norms,
+            counts,
+            unknown_chunks,
+        ))
+    }
+}
+
+impl<V, S> ReadEmbeddingsWithRegistry for Embeddings<V, S>
+where
+    V: ReadChunk,
+    S: ReadChunk,
+{
+    fn read_embeddings_with_registry<R>(
+        read: &mut R,
+        registry: &ChunkRegistry,
+    ) -> Result<(Self, Vec<CustomChunk>)>
+    where
+        R: Read + Seek,
+    {
+        let header = Header::read_chunk_with_registry(read, registry)?;
+        let chunks = header.chunk_identifiers();
+        if chunks.is_empty() {
+            return Err(
+                ErrorKind::Format(String::from("Embedding file does not contain chunks")).into(),
+            );
+        }
+
+        let metadata = if chunks[0] == ChunkIdentifier::Metadata {
+            Some(Metadata::read_chunk(read)?)
+        } else {
+            None
+        };
+
+        let vocab = V::read_chunk(read)?;
+        let storage = S::read_chunk(read)?;
+
+        let (norms, counts) = read_optional_chunks(read, chunks)?;
+        let unknown_chunks = read_unknown_chunks(read, header.unknown_identifiers())?;
+
+        let mut custom_chunks = Vec::with_capacity(header.custom_identifiers().len());
+        for &identifier in header.custom_identifiers() {
+            let chunk = registry.read_chunk(read)?;
+            if chunk.identifier() != identifier {
+                return Err(ErrorKind::Format(format!(
+                    "Invalid chunk identifier, expected: {}, got: {}",
+                    identifier,
+                    chunk.identifier()
+                ))
+                .into());
+            }
+            custom_chunks.push(chunk);
+        }
+
+        Ok((
+            Embeddings::from_parts(metadata, vocab, storage, norms, counts, unknown_chunks),
+            custom_chunks,
+        ))
+    }
+}
+
+impl<V, S> WriteEmbeddings for Embeddings<V, S>
+where
+    V: WriteChunk,
+    S: WriteChunk,
+{
+    fn write_embeddings<W>(&self, write: &mut W) -> Result<()>
+    where
+        W: Write + Seek,
+    {
+        self.write_embeddings_with_chunks(write, &ChunkRegistry::new(), &[])
+    }
+}
+
+impl<V, S> WriteEmbeddingsWithChunks for Embeddings<V, S>
+where
+    V: WriteChunk,
+    S: WriteChunk,
+{
+    fn write_embeddings_with_chunks<W>(
+        &self,
+        write: &mut W,
+        registry: &ChunkRegistry,
+        custom_chunks: &[CustomChunk],
+    ) -> Result<()>
+    where
+        W: Write + Seek,
+    {
+        let mut chunks = match self.metadata() {
+            Some(metadata) => vec![metadata.chunk_identifier()],
+            None => vec![],
+        };
+
+        chunks.extend_from_slice(&[
+            self.vocab().chunk_identifier(),
+            self.storage().chunk_identifier(),
+        ]);
+
+        if let Some(norms) = self.norms() {
+            chunks.push(norms.chunk_identifier());
+        }
+
+        if let Some(counts) = self.counts() {
+            chunks.push(counts.chunk_identifier());
+        }
+
+        let custom_identifiers = custom_chunks
+            .iter()
+            .map(CustomChunk::identifier)
+            .collect::<Vec<_>>();
+        Header::new(chunks)
+            .with_features(self.vocab().required_features() | self.storage().required_features())
+            .with_unknown_identifiers(self.unknown_chunks().iter().map(UnknownChunk::identifier))
+            .with_custom_identifiers(custom_identifiers)
+            .write_chunk(write)?;
+        if let Some(metadata) = self.metadata() {
+            metadata.write_chunk(write)?;
+        }
+
+        self.vocab().write_chunk(write)?;
+        self.storage().write_chunk(write)?;
+
+        if let Some(norms) = self.norms() {
+            norms.write_chunk(write)?;
+        }
+
+        if let Some(counts) = self.counts() {
+            counts.write_chunk(write)?;
+        }
+
+        for chunk in self.unknown_chunks() {
+            write_unknown_chunk(chunk, write)?;
+        }
+
+        for chunk in custom_chunks {
+            registry.write_chunk(chunk, write)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<V, S> WriteEmbeddingsFiltered for Embeddings<V, S>
+where
+    V: RetainWords + WriteChunk,
+    S: Storage + WriteChunkRows,
+{
+    fn write_embeddings_allowed<W>(&self, write: &mut W, words: &[impl AsRef<str>]) -> Result<()>
+    where
+        W: Write + Seek,
+    {
+        let mut indices = words
+            .iter()
+            .filter_map(|word| self.word_idx(word.as_ref())?.word())
+            .collect::<Vec<_>>();
+        indices.sort_unstable();
+        indices.dedup();
+
+        write_embeddings_indices(self, write, &indices)
+    }
+
+    fn write_embeddings_denied<W>(&self, write: &mut W, words: &[impl AsRef<str>]) -> Result<()>
+    where
+        W: Write + Seek,
+    {
+        let mut denied = vec![false; self.vocab().words_len()];
+        for idx in words
+            .iter()
+            .filter_map(|word| self.word_idx(word.as_ref())?.word())
+        {
+            denied[idx] = true;
+        }
+
+        let indices = (0..denied.len())
+            .filter(|&idx| !denied[idx])
+            .collect::<Vec<_>>();
+
+        write_embeddings_indices(self, write, &indices)
+    }
+
+    fn write_embeddings_retained<W, F>(&self, write: &mut W, mut predicate: F) -> Result<()>
+    where
+        W: Write + Seek,
+        F: FnMut(&str) -> bool,
+    {
+        let indices = self
+            .vocab()
+            .iter()
+            .filter(|(word, _)| predicate(word))
+            .map(|(_, idx)| idx)
+            .collect::<Vec<_>>();
+
+        write_embeddings_indices(self, write, &indices)
+    }
+}
+
+/// Write the embeddings of the words at the given indices.
+///
+/// The indices must be strictly increasing.
+fn write_embeddings_indices<V, S, W>(
+    embeddings: &Embeddings<V, S>,
+    write: &mut W,
+    indices: &[usize],
+) -> Result<()>
+where
+    V: RetainWords + WriteChunk,
+    S: Storage + WriteChunkRows,
+    W: Write + Seek,
+{
+    let (vocab, rows) = embeddings.vocab().retain_indices(indices);
+    let storage = embeddings.storage();
+    let norms = embeddings
+        .norms()
+        .map(|norms| NdNorms::new(norms.select(Axis(0), indices)));
+    let counts = embeddings
+        .counts()
+        .map(|counts| WordCounts::new(indices.iter().map(|&idx| counts[idx]).collect::<Vec<_>>()));
+
+    let mut chunks = match embeddings.metadata() {
+        Some(metadata) => vec![metadata.chunk_identifier()],
+        None => vec![],
+    };
+    chunks.extend_from_slice(&[vocab.chunk_identifier(), storage.chunk_identifier()]);
+    if let Some(ref norms) = norms {
+        chunks.push(norms.chunk_identifier());
+    }
+    if let Some(ref counts) = counts {
+        chunks.push(counts.chunk_identifier());
+    }
+
+    Header::new(chunks)
+        .with_features(vocab.required_features() | storage.required_features())
+        .write_chunk(write)?;
+    if let Some(metadata) = embeddings.metadata() {
+        metadata.write_chunk(write)?;
+    }
+    vocab.write_chunk(write)?;
+    storage.write_chunk_rows(&rows, write)?;
+    if let Some(norms) = norms {
+        norms.write_chunk(write)?;
+    }
+    if let Some(counts) = counts {
+        counts.write_chunk(write)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::fs::File;
+    use std::io::{BufReader, Cursor, Seek, SeekFrom, Write};
+
+    use approx::AbsDiffEq;
+    use byteorder::{LittleEndian, WriteBytesExt};
+
+    use ndarray::{array, Array1};
+    use reductive::pq::PQ;
+
+    use finalfusion_core::embeddings::{Embeddings, Quantize, TryQuantize};
+
+    use crate::chunks::io::{ChunkIdentifier, Header, ReadChunk, WriteChunk};
+
+    use crate::io::{
+        ChunkRegistry, CustomChunk, MmapEmbeddings, ReadEmbeddings, ReadEmbeddingsStrict,
+        ReadEmbeddingsSubset, ReadEmbeddingsTruncated, ReadEmbeddingsWithRegistry, WriteEmbeddings,
+        WriteEmbeddingsWithChunks, CUSTOM_CHUNK_IDENTIFIER_START,
+    };
+    use finalfusion_core::norms::NdNorms;
+    use finalfusion_core::storage::{MmapArray, NdArray, Storage, StorageView, StorageWrap};
+    use finalfusion_core::vocab::{SimpleVocab, Vocab, VocabWrap};
+
+    fn test_embeddings() -> Embeddings<SimpleVocab, NdArray> {
+        let mut reader = BufReader::new(File::open("../testdata/similarity.fifu").unwrap());
+        Embeddings::read_embeddings(&mut reader).unwrap()
+    }
+
+    #[test]
+    fn try_quantize_wrapped_storage() {
+        let mut reader = BufReader::new(File::open("../testdata/similarity.fifu").unwrap());
+        let embeds: Embeddings<VocabWrap, StorageWrap> =
+            Embeddings::mmap_embeddings(&mut reader).unwrap();
+        let quantized = embeds.try_quantize::<PQ<f32>>(10, 4, 5, 1, true).unwrap();
+        assert_eq!(quantized.vocab().words(), embeds.vocab().words());
+        assert_eq!(quantized.storage().shape(), embeds.storage().shape());
+
+        let quantized: Embeddings<VocabWrap, StorageWrap> = quantized.into();
+        assert!(quantized
+            .try_quantize::<PQ<f32>>(10, 4, 5, 1, true)
+            .is_err());
+    }
+
+    #[test]
+    #[cfg(target_endian = "little")]
+    fn truncated_mmap_can_be_viewed() {
+        let mut reader = BufReader::new(File::open("../testdata/similarity.fifu").unwrap());
+        let check: Embeddings<SimpleVocab, NdArray> =
+            Embeddings::read_embeddings_truncated(&mut reader, 20).unwrap();
+
+        let mut reader = BufReader::new(File::open("../testdata/similarity.fifu").unwrap());
+        let embeds: Embeddings<SimpleVocab, MmapArray> =
+            Embeddings::read_embeddings_truncated(&mut reader, 20).unwrap();
+        assert_eq!(embeds.storage().view(), check.storage().view());
+    }
+
+    #[test]
+    fn read_subset() {
+        let words = ["Potsdam", "Berlin", "notinvocab", "Berlin"];
+
+        let mut reader = BufReader::new(File::open("../testdata/similarity.fifu").unwrap());
+        let embeds: Embeddings<VocabWrap, NdArray> =
+            Embeddings::read_embeddings(&mut reader).unwrap();
+        let check = embeds.prune_to(&words);
+
+        let mut reader = BufReader::new(File::open("../testdata/similarity.fifu").unwrap());
+        let subset: Embeddings<VocabWrap, NdArray> =
+            Embeddings::read_subset(&mut reader, &words).unwrap();
+
+        assert_eq!(subset.vocab().words(), &["Berlin", "Potsdam"]);
+        assert_eq!(subset.vocab().words(), check.vocab().words());
+        assert_eq!(subset.storage().view(), check.storage().view());
+        assert_eq!(
+            subset.norms().map(|norms| norms.to_vec()),
+            check.norms().map(|norms| norms.to_vec())
+        );
+    }
+
+    #[test]
+    fn norms() {
+        let vocab = SimpleVocab::new(vec!["norms".to_string(), "test".to_string()]);
+        let storage = NdArray::new(array![[1f32], [-1f32]]);
+        let norms = NdNorms::new(array![2f32, 3f32]);
+        let check = Embeddings::new(None, vocab, storage, norms);
+
+        let mut serialized = Cursor::new(Vec::new());
+        check.write_embeddings(&mut serialized).unwrap();
+        serialized.seek(SeekFrom::Start(0)).unwrap();
+
+        let embeddings: Embeddings<SimpleVocab, NdArray> =
+            Embeddings::read_embeddings(&mut serialized).unwrap();
+
+        assert!(check
+            .norms()
+            .unwrap()
+            .view()
+            .abs_diff_eq(&embeddings.norms().unwrap().view(), 1e-8),);
+    }
+
+    fn embeddings_with_unknown_chunks() -> Vec<u8> {
+        let embeds = test_embeddings();
+        let mut cursor = Cursor::new(Vec::new());
+        Header::new(vec![ChunkIdentifier::SimpleVocab, ChunkIdentifier::NdArray])
+            .with_unknown_identifiers(vec![100, CUSTOM_CHUNK_IDENTIFIER_START + 1])
+            .write_chunk(&mut cursor)
+            .unwrap();
+        embeds.vocab().write_chunk(&mut cursor).unwrap();
+        embeds.storage().write_chunk(&mut cursor).unwrap();
+        for &(identifier, data) in &[
+            (100, &b"unknown"[..]),
+            (CUSTOM_CHUNK_IDENTIFIER_START + 1, &b"custom"[..]),
+        ] {
+            cursor.write_u32::<LittleEndian>(identifier).unwrap();
+            cursor.write_u64::<LittleEndian>(data.len() as u64).unwrap();
+            cursor.write_all(data).unwrap();
+        }
+
+        cursor.into_inner()
+    }
+
+    #[test]
+    fn write_read_unknown_chunks_roundtrip() {
+        let data = embeddings_with_unknown_chunks();
+        let embeds: Embeddings<SimpleVocab, NdArray> =
+            Embeddings::read_embeddings(&mut Cursor::new(&data)).unwrap();
+        assert_eq!(
+            embeds
+                .unknown_chunks()
+                .iter()
+                .map(|chunk| (chunk.identifier(), chunk.data()))
+                .collect::<Vec<_>>(),
+            vec![
+                (100, &b"unknown"[..]),
+                (CUSTOM_CHUNK_IDENTIFIER_START + 1, &b"custom"[..])
+            ]
+        );
+
+        let mut cursor = Cursor::new(Vec::new());
+        embeds.write_embeddings(&mut cursor).unwrap();
+        assert_eq!(cursor.into_inner(), data);
+
+        // Quantization retains rows, so the unknown chunks are kept.
+        let quantized = embeds.quantize::<PQ<f32>>(10, 4, 5, 1, true);
+        assert_eq!(quantized.unknown_chunks().len(), 2);
+    }
+
+    #[test]
+    fn read_embeddings_strict_accepts_unknown_chunks() {
+        let data = embeddings_with_unknown_chunks();
+        let embeds: Embeddings<VocabWrap, StorageWrap> =
+            Embeddings::read_embeddings_strict(&mut Cursor::new(&data)).unwrap();
+        let check: Embeddings<VocabWrap, StorageWrap> =
+            Embeddings::read_embeddings(&mut Cursor::new(&data)).unwrap();
+        assert_eq!(embeds.vocab(), check.vocab());
+        assert_eq!(embeds.unknown_chunks(), check.unknown_chunks());
+    }
+
+    #[test]
+    fn read_embeddings_strict_rejects_incorrect_lengths() {
+        let data = std::fs::read("../testdata/similarity.fifu").unwrap();
+        let mut cursor = Cursor::new(&data);
+        Header::read_chunk(&mut cursor).unwrap();
+        let vocab_start = cursor.position() as usize;
+
+        // Declare a vocabulary chunk that is 8 bytes longer.
+        let mut longer = data.clone();
+        let mut len = [0u8; 8];
+        len.copy_from_slice(&longer[vocab_start + 4..vocab_start + 12]);
+        let len = u64::from_le_bytes(len) + 8;
+        longer[vocab_start + 4..vocab_start + 12].copy_from_slice(&len.to_le_bytes());
+        let err =
+            Embeddings::<VocabWrap, StorageWrap>::read_embeddings_strict(&mut Cursor::new(&longer))
+                .err()
+                .unwrap();
+        assert!(err
+            .to_string()
+            .contains(&format!("at offset {} with length {}", vocab_start, len)));
+
+        let mut truncated = data;
+        truncated.truncate(truncated.len() - 16);
+        let err = Embeddings::<VocabWrap, StorageWrap>::read_embeddings_strict(&mut Cursor::new(
+            &truncated,
+        ))
+        .err()
+        .unwrap();
+        assert!(err.to_string().contains("beyond the end of the data"));
+    }
+
+    #[test]
+    fn unknown_chunks_are_dropped_on_vocab_changes() {
+        let data = embeddings_with_unknown_chunks();
+        let mut embeds: Embeddings<SimpleVocab, NdArray> =
+            Embeddings::read_embeddings(&mut Cursor::new(&data)).unwrap();
+        let pruned = embeds.retain(|word| word.starts_with('B'));
+        assert!(pruned.unknown_chunks().is_empty());
+
+        embeds.push("Zwickau", Array1::ones(embeds.dims()).view());
+        assert!(embeds.unknown_chunks().is_empty());
+    }
+
+    fn test_registry() -> ChunkRegistry {
+        let mut registry = ChunkRegistry::new();
+        registry.register(
+            CUSTOM_CHUNK_IDENTIFIER_START,
+            "WordClasses",
+            |data| Ok(data.to_vec()),
+            |classes: &Vec<u8>, data| {
+                data.extend_from_slice(classes);
+                Ok(())
+            },
+        );
+        registry
+    }
+
+    #[test]
+    fn write_read_custom_chunks_roundtrip() {
+        let mut reader = BufReader::new(File::open("../testdata/similarity.fifu").unwrap());
+        let check_embeds: Embeddings<SimpleVocab, NdArray> =
+            Embeddings::read_embeddings(&mut reader).unwrap();
+        let registry = test_registry();
+        let classes = vec![1u8, 2, 3];
+
+        let mut cursor = Cursor::new(Vec::new());
+        check_embeds
+            .write_embeddings_with_chunks(
+                &mut cursor,
+                &registry,
+                &[CustomChunk::new(
+                    CUSTOM_CHUNK_IDENTIFIER_START,
+                    classes.clone(),
+                )],
+            )
+            .unwrap();
+
+        cursor.seek(SeekFrom::Start(0)).unwrap();
+        let (embeds, chunks): (Embeddings<SimpleVocab, NdArray>, _) =
+            Embeddings::read_embeddings_with_registry(&mut cursor, &registry).unwrap();
+        assert_eq!(embeds.vocab(), check_embeds.vocab());
+        assert_eq!(embeds.storage().view(), check_embeds.storage().view());
+        assert_eq!(
+            embeds.norms().map(|n| n.view()),
+            check_embeds.norms().map(|n| n.view())
+        );
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].downcast_ref::<Vec<u8>>(), Some(&classes));
+
+        // Readers without a registry retain custom chunks as unknown chunks.
+        cursor.seek(SeekFrom::Start(0)).unwrap();
+        let embeds: Embeddings<SimpleVocab, NdArray> =
+            Embeddings::read_embeddings(&mut cursor).unwrap();
+        assert_eq!(
+            embeds.norms().map(|n| n.view()),
+            check_embeds.norms().map(|n| n.view())
+        );
+        assert_eq!(embeds.unknown_chunks().len(), 1);
+        assert_eq!(embeds.unknown_chunks()[0].data(), &classes[..]);
+
+        // Custom chunks must be registered.
+        cursor.seek(SeekFrom::Start(0)).unwrap();
+        assert!(
+            Embeddings::<SimpleVocab, NdArray>::read_embeddings_with_registry(
+                &mut cursor,
+                &ChunkRegistry::new()
+            )
+            .is_err()
+        );
+    }
+}
@@ -0,0 +1,1199 @@
+//! Description of the finalfusion binary format.
+//!
+//! This module describes the binary layout of each finalfusion chunk,
+//! so that the layout can be inspected programmatically and rendered
+//! as documentation, e.g. for implementing readers in other languages.
+//! A finalfusion file starts with a header chunk, followed by the
+//! chunks that are listed in the header. Files that require features
+//! of the format, such as chunks that were added after version 0,
+//! start with a version 1 header (`HeaderV1`), which lists the
+//! required features. Other files start with a version 0 header.
+//!
+//! All numbers are stored in little-endian byte order. Every chunk
+//! except for the header starts with the chunk identifier (`u32`)
+//! and the length of the remainder of the chunk in bytes (`u64`).
+//!
+//! The layout of a chunk is also rendered by `ChunkLayout::render`,
+//! and all layouts by `render`:
+//!
+//! ```
+//! use finalfusion_io::format::{layout, render};
+//!
+//! let ndarray = layout("NdArray").unwrap();
+//! assert_eq!(ndarray.identifier, Some(2));
+//! assert!(render().contains(&ndarray.render()));
+//! ```
+
+use std::fmt::{self, Display, Write};
+
+/// Version of the finalfusion format that is described.
+pub const FORMAT_VERSION: u32 = crate::chunks::io::MODEL_VERSION;
+
+/// Type of a field in a chunk.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FieldType {
+    /// Byte array with a fixed length.
+    Bytes(usize),
+
+    /// Unsigned 8-bit integer.
+    U8,
+
+    /// Unsigned 32-bit integer.
+    U32,
+
+    /// Unsigned 64-bit integer.
+    U64,
+
+    /// 32-bit IEEE 754 floating point number.
+    F32,
+
+    /// Unsigned integer in the LEB128 variable-length encoding.
+    ///
+    /// Each byte stores seven bits of the integer, starting with the
+    /// least significant bits. The high bit is set in all bytes except
+    /// for the last.
+    Varint,
+
+    /// UTF-8 string, prefixed by its length in bytes (`u32`).
+    String,
+
+    /// Zero bytes up to the next multiple of the given alignment.
+    ///
+    /// The padding is computed from the absolute position in the
+    /// file, as `alignment - (position % alignment)`. So, there is
+    /// at least one byte of padding, even when the position is
+    /// already aligned.
+    Padding(usize),
+
+    /// Array of values of the given type.
+    ///
+    /// The length of the array is the product of the values of the
+    /// named fields that precede the array.
+    Array(&'static FieldType, &'static [&'static str]),
+
+    /// Repeated group of fields.
+    ///
+    /// The number of repetitions is the product of the values of the
+    /// named fields that precede the group.
+    Repeated(&'static [Field], &'static [&'static str]),
+
+    /// Nested chunk, including its chunk identifier and length.
+    Chunk,
+}
+
+impl FieldType {
+    /// Get the size of a field of this type in bytes.
+    ///
+    /// Returns `None` if the size is not fixed.
+    pub fn size(&self) -> Option<usize> {
+        use self::FieldType::*;
+
+        match *self {
+            Bytes(len) => Some(len),
+            U8 => Some(1),
+            U32 => Some(4),
+            U64 => Some(8),
+            F32 => Some(4),
+            Varint | String | Padding(_) | Array(_, _) | Repeated(_, _) | Chunk => None,
+        }
+    }
+}
+
+impl Display for FieldType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use self::FieldType::*;
+
+        match self {
+            Bytes(len) => write!(f, "[u8; {}]", len),
+            U8 => write!(f, "u8"),
+            U32 => write!(f, "u32"),
+            U64 => write!(f, "u64"),
+            F32 => write!(f, "f32"),
+            Varint => write!(f, "varint"),
+            String => write!(f, "string"),
+            Padding(alignment) => write!(f, "padding({})", alignment),
+            Array(elem, len) => write!(f, "[{}; {}]", elem, len.join(" * ")),
+            Repeated(_, len) => write!(f, "repeated({})", len.join(" * ")),
+            Chunk => write!(f, "chunk"),
+        }
+    }
+}
+
+/// Field of a chunk.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Field {
+    /// Name of the field.
+    pub name: &'static str,
+
+    /// Type of the field.
+    pub field_type: FieldType,
+
+    /// Description of the field.
+    pub description: &'static str,
+}
+
+const fn field(name: &'static str, field_type: FieldType, description: &'static str) -> Field {
+    Field {
+        name,
+        field_type,
+        description,
+    }
+}
+
+/// Binary layout of a chunk.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ChunkLayout {
+    /// Name of the chunk.
+    pub name: &'static str,
+
+    /// Chunk identifier.
+    ///
+    /// The header does not have a chunk identifier.
+    pub identifier: Option<u32>,
+
+    /// Description of the chunk.
+    pub description: &'static str,
+
+    /// Fields of the chunk, in the order in which they are stored.
+    pub fields: &'static [Field],
+}
+
+impl ChunkLayout {
+    /// Render the layout as a Markdown table.
+    ///
+    /// Offsets are relative to the start of the chunk and are only
+    /// given until the first field that does not have a fixed size.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        match self.identifier {
+            Some(identifier) => writeln!(out, "## {} (identifier: {})", self.name, identifier),
+            None => writeln!(out, "## {}", self.name),
+        }
+        .expect("Cannot write to string");
+        writeln!(out, "\n{}\n", self.description).expect("Cannot write to string");
+        writeln!(out, "| Offset | Field | Type | Description |").expect("Cannot write to string");
+        writeln!(out, "|--------|-------|------|-------------|").expect("Cannot write to string");
+        render_fields(&mut out, self.fields, "", &mut Some(0));
+
+        out
+    }
+}
+
+fn render_fields(out: &mut String, fields: &[Field], prefix: &str, offset: &mut Option<usize>) {
+    for field in fields {
+        let offset_str = offset.map(|o| o.to_string()).unwrap_or_else(|| "-".into());
+        writeln!(
+            out,
+            "| {} | {}{} | {} | {} |",
+            offset_str, prefix, field.name, field.field_type, field.description
+        )
+        .expect("Cannot write to string");
+
+        *offset = match (*offset, field.field_type.size()) {
+            (Some(offset), Some(size)) => Some(offset + size),
+            _ => None,
+        };
+
+        if let FieldType::Repeated(group, _) = field.field_type {
+            render_fields(
+                out,
+                group,
+                &format!("{}{}[].", prefix, field.name),
+                &mut None,
+            );
+        }
+    }
+}
+
+const CHUNK_IDENTIFIER: Field = field("identifier", FieldType::U32, "Chunk identifier");
+
+const CHUNK_LEN: Field = field(
+    "chunk_len",
+    FieldType::U64,
+    "Length of the remainder of the chunk in bytes",
+);
+
+const LAYOUTS: &[ChunkLayout] = &[
+    ChunkLayout {
+        name: "Header",
+        identifier: None,
+        description: "File header of version 0, lists the chunks that follow the header.",
+        fields: &[
+            field("magic", FieldType::Bytes(4), "Magic: `FiFu`"),
+            field("version", FieldType::U32, "Format version: 0"),
+            field("n_chunks", FieldType::U32, "Number of chunks"),
+            field(
+                "chunk_identifiers",
+                FieldType::Array(&FieldType::U32, &["n_chunks"]),
+                "Identifiers of the chunks, in file order. Identifiers from 2^31 are \
+                 custom chunks, which follow all other chunks",
+            ),
+        ],
+    },
+    ChunkLayout {
+        name: "HeaderV1",
+        identifier: None,
+        description: "File header of version 1, lists the required features and the chunks \
+                      that follow the header.",
+        fields: &[
+            field("magic", FieldType::Bytes(4), "Magic: `FiFu`"),
+            field("version", FieldType::U32, "Format version: 1"),
+            field(
+                "features",
+                FieldType::U64,
+                "Required features as bit flags: 1 for chunks that were added after version 0, \
+                 2 for checksums, 4 for index chunks, 8 for packed quantization codes, \
+                 16 for half-precision norms",
+            ),
+            field("n_chunks", FieldType::U32, "Number of chunks"),
+            field(
+                "chunk_identifiers",
+                FieldType::Array(&FieldType::U32, &["n_chunks"]),
+                "Identifiers of the chunks, in file order. Identifiers from 2^31 are \
+                 custom chunks, which follow all other chunks",
+            ),
+        ],
+    },
+    ChunkLayout {
+        name: "SimpleVocab",
+        identifier: Some(1),
+        description: "Vocabulary without subword units.",
+        fields: &[
+            CHUNK_IDENTIFIER,
+            CHUNK_LEN,
+            field("vocab_len", FieldType::U64, "Number of words"),
+            field(
+                "words",
+                FieldType::Array(&FieldType::String, &["vocab_len"]),
+                "Words, in index order",
+            ),
+        ],
+    },
+    ChunkLayout {
+        name: "NdArray",
+        identifier: Some(2),
+        description: "Embedding matrix.",
+        fields: &[
+            CHUNK_IDENTIFIER,
+            CHUNK_LEN,
+            field("rows", FieldType::U64, "Number of rows"),
+            field("cols", FieldType::U32, "Number of columns"),
+            field(
+                "type_id",
+                FieldType::U32,
+                "Component type: `10` (f32) or `11` (f64)",
+            ),
+            field(
+                "padding",
+                FieldType::Padding(4),
+                "Alignment of the matrix, 8 bytes for f64 components",
+            ),
+            field(
+                "matrix",
+                FieldType::Array(&FieldType::F32, &["rows", "cols"]),
+                "Matrix in row-major order, components of type `type_id`",
+            ),
+        ],
+    },
+    ChunkLayout {
+        name: "BucketSubwordVocab",
+        identifier: Some(3),
+        description: "Subword vocabulary with n-grams hashed using FNV-1a. Words are \
+                      bracketed with `<` and `>`, unless the chunk ends with two additional \
+                      `u32` fields that store the code points of the begin-of-word and \
+                      end-of-word markers.",
+        fields: &[
+            CHUNK_IDENTIFIER,
+            CHUNK_LEN,
+            field("vocab_len", FieldType::U64, "Number of words"),
+            field("min_n", FieldType::U32, "Minimum n-gram length"),
+            field("max_n", FieldType::U32, "Maximum n-gram length"),
+            field(
+                "buckets_exp",
+                FieldType::U32,
+                "Number of buckets (as power of 2)",
+            ),
+            field(
+                "words",
+                FieldType::Array(&FieldType::String, &["vocab_len"]),
+                "Words, in index order",
+            ),
+        ],
+    },
+    ChunkLayout {
+        name: "QuantizedArray",
+        identifier: Some(4),
+        description: "Embedding matrix quantized using product quantization.",
+        fields: &[
+            CHUNK_IDENTIFIER,
+            CHUNK_LEN,
+            field(
+                "projection",
+                FieldType::U32,
+                "Projection matrix present (0 or 1)",
+            ),
+            field(
+                "use_norms",
+                FieldType::U32,
+                "Norms: `0` (absent), `1` (f32), or `12` (f16)",
+            ),
+            field("quantized_len", FieldType::U32, "Number of subquantizers"),
+            field(
+                "reconstructed_len",
+                FieldType::U32,
+                "Length of reconstructed embeddings",
+            ),
+            field(
+                "n_centroids",
+                FieldType::U32,
+                "Number of centroids per subquantizer",
+            ),
+            field("n_embeddings", FieldType::U64, "Number of embeddings"),
+            field(
+                "quantized_type_id",
+                FieldType::U32,
+                "Quantized type: `1` (u8) or `2` (two 4-bit codes per u8)",
+            ),
+            field(
+                "reconstructed_type_id",
+                FieldType::U32,
+                "Reconstructed type: `10` (f32)",
+            ),
+            field(
+                "padding",
+                FieldType::Padding(4),
+                "Alignment of the matrices",
+            ),
+            field(
+                "projection_matrix",
+                FieldType::Array(
+                    &FieldType::F32,
+                    &["projection", "reconstructed_len", "reconstructed_len"],
+                ),
+                "Projection matrix in row-major order",
+            ),
+            field(
+                "subquantizers",
+                FieldType::Array(&FieldType::F32, &["n_centroids", "reconstructed_len"]),
+                "Centroids, per subquantizer *n_centroids x (reconstructed_len / quantized_len)*",
+            ),
+            field(
+                "norms",
+                FieldType::Array(&FieldType::U8, &["n_embeddings", "norm_len"]),
+                "Embedding norms, *norm_len* is `0` without norms, `4` for f32 norms, \
+                 and `2` for f16 norms",
+            ),
+            field(
+                "quantized",
+                FieldType::Array(&FieldType::U8, &["n_embeddings", "code_len"]),
+                "Quantized embeddings in row-major order, *code_len* is *quantized_len* \
+                 for type `1` and *ceil(quantized_len / 2)* for type `2`. Type `2` \
+                 stores the first code of a pair in the lower 4 bits",
+            ),
+        ],
+    },
+    ChunkLayout {
+        name: "Metadata",
+        identifier: Some(5),
+        description: "Metadata in TOML format.",
+        fields: &[
+            CHUNK_IDENTIFIER,
+            CHUNK_LEN,
+            field(
+                "metadata",
+                FieldType::Array(&FieldType::U8, &["chunk_len"]),
+                "UTF-8 encoded TOML",
+            ),
+        ],
+    },
+    ChunkLayout {
+        name: "NdNorms",
+        identifier: Some(6),
+        description: "Norms of the embeddings before normalization.",
+        fields: &[
+            CHUNK_IDENTIFIER,
+            CHUNK_LEN,
+            field("len", FieldType::U64, "Number of norms"),
+            field("type_id", FieldType::U32, "Component type: `10` (f32)"),
+            field("padding", FieldType::Padding(4), "Alignment of the norms"),
+            field(
+                "norms",
+                FieldType::Array(&FieldType::F32, &["len"]),
+                "Norms, in vocabulary order",
+            ),
+        ],
+    },
+    ChunkLayout {
+        name: "FastTextSubwordVocab",
+        identifier: Some(7),
+        description: "Subword vocabulary with n-grams hashed as in fastText. Words are \
+                      bracketed with `<` and `>`, unless the chunk ends with two additional \
+                      `u32` fields that store the code points of the begin-of-word and \
+                      end-of-word markers.",
+        fields: &[
+            CHUNK_IDENTIFIER,
+            CHUNK_LEN,
+            field("vocab_len", FieldType::U64, "Number of words"),
+            field("min_n", FieldType::U32, "Minimum n-gram length"),
+            field("max_n", FieldType::U32, "Maximum n-gram length"),
+            field("buckets", FieldType::U32, "Number of buckets"),
+            field(
+                "words",
+                FieldType::Array(&FieldType::String, &["vocab_len"]),
+                "Words, in index order",
+            ),
+        ],
+    },
+    ChunkLayout {
+        name: "ExplicitSubwordVocab",
+        identifier: Some(8),
+        description: "Subword vocabulary with explicitly stored n-grams. Words are bracketed \
+                      with `<` and `>`, unless the chunk ends with two additional `u32` \
+                      fields that store the code points of the begin-of-word and end-of-word \
+                      markers.",
+        fields: &[
+            CHUNK_IDENTIFIER,
+            CHUNK_LEN,
+            field("words_len", FieldType::U64, "Number of words"),
+            field("ngrams_len", FieldType::U64, "Number of n-grams"),
+            field("min_n", FieldType::U32, "Minimum n-gram length"),
+            field("max_n", FieldType::U32, "Maximum n-gram length"),
+            field(
+                "words",
+                FieldType::Array(&FieldType::String, &["words_len"]),
+                "Words, in index order",
+            ),
+            field(
+                "ngrams",
+                FieldType::Repeated(
+                    &[
+                        field("ngram", FieldType::String, "N-gram"),
+                        field("index", FieldType::U64, "N-gram index"),
+                    ],
+                    &["ngrams_len"],
+                ),
+                "N-grams and their indices",
+            ),
+        ],
+    },
+    ChunkLayout {
+        name: "NamespacedVocab",
+        identifier: Some(9),
+        description: "Vocabulary that is partitioned in namespaces.",
+        fields: &[
+            CHUNK_IDENTIFIER,
+            CHUNK_LEN,
+            field("n_namespaces", FieldType::U64, "Number of namespaces"),
+            field(
+                "namespaces",
+                FieldType::Repeated(
+                    &[
+                        field("namespace", FieldType::String, "Namespace"),
+                        field("vocab_len", FieldType::U64, "Number of words"),
+                        field(
+                            "words",
+                            FieldType::Array(&FieldType::String, &["vocab_len"]),
+                            "Words, in index order",
+                        ),
+                    ],
+                    &["n_namespaces"],
+                ),
+                "Namespaces, in index order",
+            ),
+        ],
+    },
+    ChunkLayout {
+        name: "DedupArray",
+        identifier: Some(10),
+        description: "Embedding matrix that stores duplicate embeddings once.",
+        fields: &[
+            CHUNK_IDENTIFIER,
+            CHUNK_LEN,
+            field("n_embeddings", FieldType::U64, "Number of embeddings"),
+            field("n_unique", FieldType::U64, "Number of unique embeddings"),
+            field("cols", FieldType::U32, "Number of columns"),
+            field("type_id", FieldType::U32, "Component type: `10` (f32)"),
+            field(
+                "indices",
+                FieldType::Array(&FieldType::U32, &["n_embeddings"]),
+                "Row of each embedding in the matrix of unique embeddings",
+            ),
+            field("padding", FieldType::Padding(4), "Alignment of the matrix"),
+            field(
+                "matrix",
+                FieldType::Array(&FieldType::F32, &["n_unique", "cols"]),
+                "Unique embeddings in row-major order",
+            ),
+        ],
+    },
+    ChunkLayout {
+        name: "ResidualQuantizedArray",
+        identifier: Some(11),
+        description: "Embedding matrix quantized using a coarse codebook and product \
+                      quantization of the residuals.",
+        fields: &[
+            CHUNK_IDENTIFIER,
+            CHUNK_LEN,
+            field("n_embeddings", FieldType::U64, "Number of embeddings"),
+            field(
+                "n_coarse_centroids",
+                FieldType::U32,
+                "Number of coarse centroids (at most 256)",
+            ),
+            field(
+                "projection",
+                FieldType::U32,
+                "Projection matrix present (0 or 1)",
+            ),
+            field("use_norms", FieldType::U32, "Norms present (0 or 1)"),
+            field("quantized_len", FieldType::U32, "Number of subquantizers"),
+            field(
+                "reconstructed_len",
+                FieldType::U32,
+                "Length of reconstructed embeddings",
+            ),
+            field(
+                "n_centroids",
+                FieldType::U32,
+                "Number of centroids per subquantizer",
+            ),
+            field(
+                "quantized_type_id",
+                FieldType::U32,
+                "Quantized type: `1` (u8) or `2` (two 4-bit codes per u8)",
+            ),
+            field(
+                "reconstructed_type_id",
+                FieldType::U32,
+                "Reconstructed type: `10` (f32)",
+            ),
+            field(
+                "padding",
+                FieldType::Padding(4),
+                "Alignment of the matrices",
+            ),
+            field(
+                "coarse_centroids",
+                FieldType::Array(
+                    &FieldType::F32,
+                    &["n_coarse_centroids", "reconstructed_len"],
+                ),
+                "Coarse centroids in row-major order",
+            ),
+            field(
+                "projection_matrix",
+                FieldType::Array(
+                    &FieldType::F32,
+                    &["projection", "reconstructed_len", "reconstructed_len"],
+                ),
+                "Projection matrix of the residuals in row-major order",
+            ),
+            field(
+                "subquantizers",
+                FieldType::Array(&FieldType::F32, &["n_centroids", "reconstructed_len"]),
+                "Centroids, per subquantizer *n_centroids x (reconstructed_len / quantized_len)*",
+            ),
+            field(
+                "norms",
+                FieldType::Array(&FieldType::F32, &["use_norms", "n_embeddings"]),
+                "Embedding norms",
+            ),
+            field(
+                "coarse_codes",
+                FieldType::Array(&FieldType::U8, &["n_embeddings"]),
+                "Coarse centroid of each embedding",
+            ),
+            field(
+                "quantized",
+                FieldType::Array(&FieldType::U8, &["n_embeddings", "code_len"]),
+                "Quantized residuals in row-major order, as in `QuantizedArray`",
+            ),
+        ],
+    },
+    ChunkLayout {
+        name: "WordCounts",
+        identifier: Some(12),
+        description: "Corpus frequencies of the vocabulary words.",
+        fields: &[
+            CHUNK_IDENTIFIER,
+            CHUNK_LEN,
+            field("len", FieldType::U64, "Number of counts"),
+            field(
+                "counts",
+                FieldType::Array(&FieldType::U64, &["len"]),
+                "Counts, in vocabulary order",
+            ),
+        ],
+    },
+    ChunkLayout {
+        name: "FstVocab",
+        identifier: Some(13),
+        description: "Vocabulary stored as a minimal acyclic finite state transducer that \
+                      maps each word to its rank in byte-wise sorted order. The last state \
+                      is the start state.",
+        fields: &[
+            CHUNK_IDENTIFIER,
+            CHUNK_LEN,
+            field("vocab_len", FieldType::U64, "Number of words"),
+            field("n_states", FieldType::U64, "Number of states"),
+            field("n_transitions", FieldType::U64, "Number of transitions"),
+            field(
+                "state_ends",
+                FieldType::Array(&FieldType::U32, &["n_states"]),
+                "End offset of the transitions of each state, the transitions of \
+                 a state start at the end offset of the previous state",
+            ),
+            field(
+                "finals",
+                FieldType::Array(&FieldType::U8, &["n_states"]),
+                "`1` if a state is final, `0` otherwise",
+            ),
+            field(
+                "labels",
+                FieldType::Array(&FieldType::U8, &["n_transitions"]),
+                "Transition labels, sorted per state",
+            ),
+            field(
+                "targets",
+                FieldType::Array(&FieldType::U32, &["n_transitions"]),
+                "Transition target states",
+            ),
+            field(
+                "outputs",
+                FieldType::Array(&FieldType::U32, &["n_transitions"]),
+                "Transition outputs, the rank of a word is the sum of the outputs \
+                 on its path",
+            ),
+            field(
+                "indices",
+                FieldType::Array(&FieldType::U32, &["vocab_len"]),
+                "Word index of each rank",
+            ),
+        ],
+    },
+    ChunkLayout {
+        name: "ByteFallbackVocab",
+        identifier: Some(14),
+        description: "Vocabulary that falls back to the bytes of a word when the wrapped \
+                      vocabulary does not have an index for the word. The embedding of byte \
+                      *b* is stored in row *vocab_len + b*, where *vocab_len* is the number \
+                      of rows of the wrapped vocabulary.",
+        fields: &[
+            CHUNK_IDENTIFIER,
+            CHUNK_LEN,
+            field("vocab", FieldType::Chunk, "Wrapped vocabulary chunk"),
+        ],
+    },
+    ChunkLayout {
+        name: "BpeVocab",
+        identifier: Some(15),
+        description: "Vocabulary with byte pair encoding units. The embeddings of the units \
+                      follow the embeddings of the words.",
+        fields: &[
+            CHUNK_IDENTIFIER,
+            CHUNK_LEN,
+            field("vocab_len", FieldType::U64, "Number of words"),
+            field("n_units", FieldType::U64, "Number of units"),
+            field("n_merges", FieldType::U64, "Number of merges"),
+            field(
+                "end_of_word",
+                FieldType::String,
+                "Marker that is appended to the last character of a word, may be empty",
+            ),
+            field(
+                "words",
+                FieldType::Array(&FieldType::String, &["vocab_len"]),
+                "Words, in index order",
+            ),
+            field(
+                "units",
+                FieldType::Array(&FieldType::String, &["n_units"]),
+                "Units, in index order",
+            ),
+            field(
+                "merges",
+                FieldType::Repeated(
+                    &[
+                        field("left", FieldType::String, "Left unit"),
+                        field("right", FieldType::String, "Right unit"),
+                    ],
+                    &["n_merges"],
+                ),
+                "Merges, from the highest to the lowest priority",
+            ),
+        ],
+    },
+    ChunkLayout {
+        name: "WordPieceVocab",
+        identifier: Some(16),
+        description: "Vocabulary with WordPiece units. The embeddings of the pieces follow \
+                      the embeddings of the words.",
+        fields: &[
+            CHUNK_IDENTIFIER,
+            CHUNK_LEN,
+            field("vocab_len", FieldType::U64, "Number of words"),
+            field("n_pieces", FieldType::U64, "Number of pieces"),
+            field(
+                "continuation_prefix",
+                FieldType::String,
+                "Prefix of pieces that continue a word, usually `##`",
+            ),
+            field(
+                "words",
+                FieldType::Array(&FieldType::String, &["vocab_len"]),
+                "Words, in index order",
+            ),
+            field(
+                "pieces",
+                FieldType::Array(&FieldType::String, &["n_pieces"]),
+                "Pieces, in index order",
+            ),
+        ],
+    },
+    ChunkLayout {
+        name: "SentencePieceVocab",
+        identifier: Some(17),
+        description: "Vocabulary of SentencePiece pieces.",
+        fields: &[
+            CHUNK_IDENTIFIER,
+            CHUNK_LEN,
+            field("vocab_len", FieldType::U64, "Number of pieces"),
+            field(
+                "model_type",
+                FieldType::U32,
+                "Segmentation algorithm: 1 (unigram) or 2 (byte pair encoding)",
+            ),
+            field(
+                "pieces",
+                FieldType::Array(&FieldType::String, &["vocab_len"]),
+                "Pieces, in index order",
+            ),
+            field(
+                "scores",
+                FieldType::Array(&FieldType::F32, &["vocab_len"]),
+                "Piece scores",
+            ),
+            field(
+                "types",
+                FieldType::Array(&FieldType::U8, &["vocab_len"]),
+                "Piece types, as in SentencePiece: 1 (normal), 2 (unknown), 3 (control), \
+                 4 (user-defined), 5 (unused), or 6 (byte)",
+            ),
+        ],
+    },
+    ChunkLayout {
+        name: "MmapVocab",
+        identifier: Some(18),
+        description: "Vocabulary that can be memory mapped. The words are stored in a \
+                      string pool in byte-wise sorted order. The optional minimal perfect \
+                      hash function maps a word to slot *(d2 + f1 * d1 + f2) mod n_slots*, \
+                      using wrapping `u32` arithmetic. Here, *m* is the FNV-1a hash of \
+                      the seed (`u64`) and the word, mixed with the SplitMix64 finalizer. \
+                      The word is in bucket *(m >> 32) mod n_buckets* with displacements \
+                      *d1* and *d2*, *f1* is the lower half of *m*, and *f2* is the lower \
+                      half of *m* mixed again. The perfect hash function requires a \
+                      vocabulary of at most *2^32 - 1* words.",
+        fields: &[
+            CHUNK_IDENTIFIER,
+            CHUNK_LEN,
+            field("vocab_len", FieldType::U64, "Number of words"),
+            field(
+                "pool_len",
+                FieldType::U64,
+                "Length of the string pool in bytes",
+            ),
+            field("seed", FieldType::U64, "Seed of the perfect hash function"),
+            field(
+                "n_buckets",
+                FieldType::U64,
+                "Number of buckets of the perfect hash function, `0` without perfect hash \
+                 function",
+            ),
+            field(
+                "n_slots",
+                FieldType::U64,
+                "Number of slots of the perfect hash function, `vocab_len` with and `0` \
+                 without perfect hash function",
+            ),
+            field(
+                "ends",
+                FieldType::Array(&FieldType::U64, &["vocab_len"]),
+                "End offset of each word in the string pool, in sorted order, a word \
+                 starts at the end offset of the previous word",
+            ),
+            field(
+                "indices",
+                FieldType::Array(&FieldType::U32, &["vocab_len"]),
+                "Word index of each rank, `u64` when `vocab_len` exceeds *2^32 - 1*",
+            ),
+            field(
+                "ranks",
+                FieldType::Array(&FieldType::U32, &["vocab_len"]),
+                "Rank of each word index, `u64` when `vocab_len` exceeds *2^32 - 1*",
+            ),
+            field(
+                "displacements",
+                FieldType::Repeated(
+                    &[
+                        field("d1", FieldType::U32, "First displacement"),
+                        field("d2", FieldType::U32, "Second displacement"),
+                    ],
+                    &["n_buckets"],
+                ),
+                "Displacements of each bucket",
+            ),
+            field(
+                "slots",
+                FieldType::Array(&FieldType::U32, &["n_slots"]),
+                "Rank of the word in each slot",
+            ),
+            field(
+                "pool",
+                FieldType::Array(&FieldType::U8, &["pool_len"]),
+                "UTF-8 encoded words, in sorted order",
+            ),
+        ],
+    },
+    ChunkLayout {
+        name: "AliasVocab",
+        identifier: Some(19),
+        description: "Vocabulary with aliases that share the embedding of a word of the \
+                      wrapped vocabulary.",
+        fields: &[
+            CHUNK_IDENTIFIER,
+            CHUNK_LEN,
+            field("aliases_len", FieldType::U64, "Number of aliases"),
+            field(
+                "aliases",
+                FieldType::Array(&FieldType::String, &["aliases_len"]),
+                "Aliases",
+            ),
+            field(
+                "indices",
+                FieldType::Array(&FieldType::U64, &["aliases_len"]),
+                "Word indices of the aliases",
+            ),
+            field("vocab", FieldType::Chunk, "Wrapped vocabulary chunk"),
+        ],
+    },
+    ChunkLayout {
+        name: "FrontCodedVocab",
+        identifier: Some(20),
+        description: "Vocabulary without subword units, stored with front coding. The \
+                      words are stored in byte-wise sorted order, each word as the length \
+                      of the prefix that it shares with the preceding word and the \
+                      remaining suffix.",
+        fields: &[
+            CHUNK_IDENTIFIER,
+            CHUNK_LEN,
+            field("vocab_len", FieldType::Varint, "Number of words"),
+            field(
+                "words",
+                FieldType::Repeated(
+                    &[
+                        field(
+                            "prefix_len",
+                            FieldType::Varint,
+                            "Length of the prefix shared with the preceding word in \
+                             bytes, `0` for the first word",
+                        ),
+                        field(
+                            "suffix_len",
+                            FieldType::Varint,
+                            "Length of the suffix in bytes",
+                        ),
+                        field(
+                            "suffix",
+                            FieldType::Array(&FieldType::U8, &["suffix_len"]),
+                            "UTF-8 encoded suffix",
+                        ),
+                    ],
+                    &["vocab_len"],
+                ),
+                "Words, in sorted order",
+            ),
+            field(
+                "indices",
+                FieldType::Array(&FieldType::Varint, &["vocab_len"]),
+                "Word index of each word, in sorted order",
+            ),
+        ],
+    },
+];
+
+/// Get the layouts of all chunks.
+pub fn layouts() -> &'static [ChunkLayout] {
+    LAYOUTS
+}
+
+/// Get the layout of a chunk by its name.
+pub fn layout(name: &str) -> Option<&'static ChunkLayout> {
+    LAYOUTS.iter().find(|layout| layout.name == name)
+}
+
+/// Render the layouts of all chunks as Markdown.
+pub fn render() -> String {
+    let mut out = format!("# finalfusion format version {}\n", FORMAT_VERSION);
+    for layout in LAYOUTS {
+        out.push('\n');
+        out.push_str(&layout.render());
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::fs::File;
+    use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+
+    use byteorder::{LittleEndian, ReadBytesExt};
+    use ndarray::Array2;
+    use reductive::pq::PQ;
+    use toml::toml;
+
+    use super::{layout, render, Field, FieldType};
+    use crate::chunks::io::{ChunkIdentifier, Header, ReadChunk, WriteChunk};
+    use finalfusion_core::counts::WordCounts;
+    use finalfusion_core::metadata::Metadata;
+    use finalfusion_core::norms::NdNorms;
+    use finalfusion_core::storage::{NdArray, Prune, Quantize, QuantizeResidual, QuantizedArray};
+    use finalfusion_core::subword::FastTextIndexer;
+    use finalfusion_core::subword::{BucketIndexer, ExplicitIndexer, FinalfusionHashIndexer};
+    use finalfusion_core::util::read_varint;
+    use finalfusion_core::vocab::{
+        AliasVocab, BpeVocab, BucketSubwordVocab, ByteFallbackVocab, ExplicitSubwordVocab,
+        FastTextSubwordVocab, FrontCodedVocab, FstVocab, MmapVocab, NamespacedVocab, PieceType,
+        SentencePieceModel, SentencePieceVocab, SimpleVocab, WordPieceVocab,
+    };
+
+    fn words() -> Vec<String> {
+        vec![
+            "this".to_owned(),
+            "is".to_owned(),
+            "a".to_owned(),
+            "test".to_owned(),
+        ]
+    }
+
+    fn count(values: &HashMap<&str, u64>, len: &[&str]) -> u64 {
+        len.iter()
+            .map(|name| *values.get(name).expect("Unknown length field"))
+            .product()
+    }
+
+    // Read a field of the given type, returns the value of integer fields.
+    fn read_type<R>(
+        read: &mut R,
+        field_type: &FieldType,
+        values: &mut HashMap<&str, u64>,
+    ) -> Option<u64>
+    where
+        R: Read + Seek,
+    {
+        match *field_type {
+            FieldType::Bytes(len) => {
+                read.read_exact(&mut vec![0; len]).unwrap();
+                None
+            }
+            FieldType::U8 => Some(read.read_u8().unwrap() as u64),
+            FieldType::U32 => Some(read.read_u32::<LittleEndian>().unwrap() as u64),
+            FieldType::U64 => Some(read.read_u64::<LittleEndian>().unwrap()),
+            FieldType::Varint => Some(read_varint(read).unwrap()),
+            FieldType::F32 => {
+                read.read_f32::<LittleEndian>().unwrap();
+                None
+            }
+            FieldType::String => {
+                let len = read.read_u32::<LittleEndian>().unwrap() as usize;
+                let mut bytes = vec![0; len];
+                read.read_exact(&mut bytes).unwrap();
+                String::from_utf8(bytes).unwrap();
+                None
+            }
+            FieldType::Padding(alignment) => {
+                let alignment = alignment as u64;
+                let pos = read.stream_position().unwrap();
+                let mut padding = vec![0; (alignment - pos % alignment) as usize];
+                read.read_exact(&mut padding).unwrap();
+                assert!(padding.iter().all(|&b| b == 0));
+                None
+            }
+            FieldType::Array(elem, len) => {
+                for _ in 0..count(values, len) {
+                    read_type(read, elem, values);
+                }
+                None
+            }
+            FieldType::Repeated(group, len) => {
+                for _ in 0..count(values, len) {
+                    read_fields(read, group, values);
+                }
+                None
+            }
+            FieldType::Chunk => {
+                read.read_u32::<LittleEndian>().unwrap();
+                let len = read.read_u64::<LittleEndian>().unwrap() as usize;
+                read.read_exact(&mut vec![0; len]).unwrap();
+                None
+            }
+        }
+    }
+
+    fn read_fields<'a, R>(read: &mut R, fields: &'a [Field], values: &mut HashMap<&'a str, u64>)
+    where
+        R: Read + Seek,
+    {
+        for field in fields {
+            if let Some(value) = read_type(read, &field.field_type, values) {
+                values.insert(field.name, value);
+            }
+
+            // Lengths that are derived from other fields.
+            if field.name == "use_norms" {
+                let norm_len = match values["use_norms"] {
+                    0 => 0,
+                    1 => 4,
+                    12 => 2,
+                    id => panic!("Unknown norms type: {}", id),
+                };
+                values.insert("norm_len", norm_len);
+            }
+            if field.name == "quantized_type_id" {
+                let len = values["quantized_len"];
+                let code_len = if values["quantized_type_id"] == 2 {
+                    len.div_ceil(2)
+                } else {
+                    len
+                };
+                values.insert("code_len", code_len);
+            }
+        }
+    }
+
+    /// Check that the layout describes a chunk as written.
+    fn check_layout(chunk: &impl WriteChunk) {
+        check_named_layout(chunk, &chunk.chunk_identifier().to_string());
+    }
+
+    /// Check that the layout with the given name describes a chunk as written.
+    fn check_named_layout(chunk: &impl WriteChunk, name: &str) {
+        let layout = layout(name).unwrap();
+
+        // Write at an unaligned offset to verify padding.
+        let mut cursor = Cursor::new(Vec::new());
+        cursor.write_all(&[0; 3]).unwrap();
+        chunk.write_chunk(&mut cursor).unwrap();
+        let end = cursor.position();
+        cursor.seek(SeekFrom::Start(3)).unwrap();
+
+        let mut values = HashMap::new();
+        read_fields(&mut cursor, layout.fields, &mut values);
+        assert_eq!(
+            cursor.position(),
+            end,
+            "Layout of {} does not cover the chunk",
+            name
+        );
+
+        if let Some(identifier) = layout.identifier {
+            assert_eq!(values["identifier"], identifier as u64);
+            assert_eq!(values["chunk_len"], end - 3 - 12);
+        }
+    }
+
+    #[test]
+    fn layouts_describe_written_chunks() {
+        check_layout(&Header::new(vec![
+            ChunkIdentifier::SimpleVocab,
+            ChunkIdentifier::NdArray,
+        ]));
+        check_named_layout(
+            &Header::new(vec![ChunkIdentifier::FstVocab, ChunkIdentifier::NdArray]),
+            "HeaderV1",
+        );
+        check_layout(&SimpleVocab::new(words()));
+        check_layout(&BucketSubwordVocab::new(
+            words(),
+            3,
+            6,
+            FinalfusionHashIndexer::new(10),
+        ));
+        check_layout(&FastTextSubwordVocab::new(
+            words(),
+            3,
+            6,
+            FastTextIndexer::new(100),
+        ));
+        check_layout(&ExplicitSubwordVocab::new(
+            words(),
+            3,
+            6,
+            ExplicitIndexer::new(vec!["<th".to_owned(), "his".to_owned()]),
+        ));
+        check_layout(&NamespacedVocab::new(vec![
+            ("word", words()),
+            ("entity", vec!["Q64".to_owned()]),
+        ]));
+
+        let matrix = NdArray::new(Array2::from_shape_fn((16, 8), |(r, c)| (r * 8 + c) as f32));
+        check_layout(&matrix);
+        check_layout(&matrix.quantize::<PQ<f32>>(2, 2, 5, 1, false));
+        check_layout(&matrix.quantize::<PQ<f32>>(2, 2, 5, 1, true));
+        let mut half_norms = matrix.quantize::<PQ<f32>>(2, 2, 5, 1, true);
+        half_norms.set_half_norms(true);
+        check_layout(&half_norms);
+        check_layout(&matrix.quantize::<PQ<f32>>(1, 2, 5, 1, false));
+        check_layout(
+            &QuantizedArray::read_chunk(
+                &mut File::open("../testdata/quantized_storage.bin").unwrap(),
+            )
+            .unwrap(),
+        );
+        check_layout(
+            &NdArray::new(Array2::from_shape_fn((5, 3), |(r, c)| (r % 2 + c) as f32)).prune(0.),
+        );
+        check_layout(&matrix.quantize_residual::<PQ<f32>>(2, 2, 2, 5, 1, false));
+        check_layout(&matrix.quantize_residual::<PQ<f32>>(2, 2, 3, 5, 1, true));
+        check_layout(&NdNorms::new(vec![1f32, 2., 3.]));
+        check_layout(&WordCounts::new(vec![5, 3, 0]));
+        check_layout(&FstVocab::new(words()));
+        check_layout(&FrontCodedVocab::new(words()));
+        check_layout(&MmapVocab::new(words()));
+        check_layout(&MmapVocab::new_with_perfect_hash(words()));
+        check_layout(&ByteFallbackVocab::new(SimpleVocab::new(words())));
+        check_layout(&AliasVocab::new(
+            SimpleVocab::new(words()),
+            vec![("alias".to_owned(), 1)],
+        ));
+        check_layout(&BpeVocab::new(
+            words(),
+            vec!["th".to_owned(), "is".to_owned()],
+            vec![("t".to_owned(), "h".to_owned())],
+            "</w>",
+        ));
+        check_layout(&WordPieceVocab::new(
+            words(),
+            vec!["th".to_owned(), "##is".to_owned()],
+            WordPieceVocab::CONTINUATION_PREFIX,
+        ));
+        check_layout(&SentencePieceVocab::new(
+            vec!["▁th".to_owned(), "is".to_owned()],
+            vec![-1., -2.],
+            vec![PieceType::Normal; 2],
+            SentencePieceModel::Unigram,
+        ));
+        check_layout(&Metadata::new(toml! {
+            [hyperparameters]
+            dims = 300
+        }));
+    }
+
+    // testdata/format.md must be updated when a layout is changed.
+    #[test]
+    fn render_matches_golden_file() {
+        assert_eq!(render(), include_str!("../../testdata/format.md"));
+    }
+}
@@ -0,0 +1,471 @@
+//! Traits and error types for I/O.
+//!
+//! This module provides traits for reading embeddings
+//! (`ReadEmbeddings`), memory mapping embeddings (`MmapEmbeddings`),
+//! and writing embeddings (`WriteEmbeddings`), also to non-seekable
+//! writers (`WriteEmbeddingsStream`). Moreover, the module
+//! re-exports the `Error`, `ErrorKind`, and `Result` types that are
+//! used for handling I/O errors.
+
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, Write};
+
+pub use finalfusion_core::error::{Error, ErrorKind, Result};
+pub use finalfusion_core::unknown::UnknownChunk;
+
+use crate::chunks::io::PositionWriter;
+pub use crate::chunks::io::{ChunkRegistry, CustomChunk, Features, CUSTOM_CHUNK_IDENTIFIER_START};
+
+/// Read finalfusion embeddings.
+///
+/// This trait is used to read embeddings in the finalfusion format.
+/// Implementations are provided for the vocabulary and storage types
+/// in this crate.
+///
+/// ```
+/// use std::fs::File;
+///
+/// use finalfusion_core::embeddings::Embeddings;
+/// use finalfusion_core::storage::StorageWrap;
+/// use finalfusion_core::vocab::VocabWrap;
+/// use finalfusion_io::io::ReadEmbeddings;
+///
+/// let mut f = File::open("../testdata/similarity.fifu").unwrap();
+/// let embeddings: Embeddings<VocabWrap, StorageWrap> =
+///     Embeddings::read_embeddings(&mut f).unwrap();
+/// ```
+///
+/// This crate does not provide encryption. Embeddings that are
+/// encrypted at rest can be read from any reader that decrypts them,
+/// provided that it implements `Seek`, e.g. a `Cursor` over the
+/// decrypted data. Likewise, `WriteEmbeddings` can write to a writer
+/// that encrypts the data.
+pub trait ReadEmbeddings
+where
+    Self: Sized,
+{
+    /// Read the embeddings.
+    fn read_embeddings<R>(read: &mut R) -> Result<Self>
+    where
+        R: Read + Seek;
+}
+
+/// Read finalfusion embeddings with strict validation of chunk lengths.
+///
+/// Every chunk of a finalfusion file declares the length of its data.
+/// `ReadEmbeddings` does not verify all declared lengths, so a file
+/// that was truncated or written incorrectly may be read without
+/// errors or fail with an unclear error in a later chunk. This trait
+/// verifies that each chunk lies within the file and that reading it
+/// consumes exactly its declared length. Errors state the offsets of
+/// the offending chunk. The vocabulary and storage chunks are also
+/// validated exhaustively with `ReadChunk::validate`.
+///
+/// ```
+/// use std::fs::File;
+///
+/// use finalfusion_core::embeddings::Embeddings;
+/// use finalfusion_core::storage::StorageWrap;
+/// use finalfusion_core::vocab::VocabWrap;
+/// use finalfusion_io::io::ReadEmbeddingsStrict;
+///
+/// let mut f = File::open("../testdata/similarity.fifu").unwrap();
+/// let embeddings: Embeddings<VocabWrap, StorageWrap> =
+///     Embeddings::read_embeddings_strict(&mut f).unwrap();
+/// ```
+pub trait ReadEmbeddingsStrict
+where
+    Self: Sized,
+{
+    /// Read the embeddings, validating the length of every chunk.
+    fn read_embeddings_strict<R>(read: &mut R) -> Result<Self>
+    where
+        R: Read + Seek;
+}
+
+/// Read finalfusion embeddings metadata.
+///
+/// This trait is used to read the metadata of embeddings in the
+/// finalfusion format. This is typically faster than
+/// `ReadEmbeddings::read_embeddings`.
+///
+/// ```
+/// use std::fs::File;
+///
+/// use finalfusion_core::metadata::Metadata;
+/// use finalfusion_io::io::ReadMetadata;
+///
+/// let mut f = File::open("../testdata/similarity.fifu").unwrap();
+/// let metadata: Option<Metadata> =
+///     ReadMetadata::read_metadata(&mut f).unwrap();
+/// ```
+pub trait ReadMetadata
+where
+    Self: Sized,
+{
+    /// Read the metadata.
+    fn read_metadata<R>(read: &mut R) -> Result<Self>
+    where
+        R: Read + Seek;
+}
+
+/// Replace the metadata of finalfusion embeddings in place.
+///
+/// This trait is used to add or replace the metadata chunk of an
+/// existing finalfusion file, without reading or rewriting the other
+/// chunks. If the new metadata does not fit in the space of the old
+/// metadata, the chunks following the metadata are moved towards the
+/// end of the file. The metadata is then padded, such that subsequent
+/// edits with slightly larger metadata do not require moving the
+/// chunks again.
+///
+/// ```
+/// use std::fs::File;
+/// use std::io::{BufReader, Cursor};
+///
+/// use finalfusion_core::metadata::Metadata;
+/// use finalfusion_io::io::{ReadMetadata, WriteMetadataInPlace};
+/// use toml::toml;
+///
+/// let mut data = Cursor::new(std::fs::read("../testdata/similarity.fifu").unwrap());
+/// let metadata = Metadata::new(toml! {
+///     [description]
+///     language = "de"
+/// });
+/// metadata.write_metadata_in_place(&mut data).unwrap();
+///
+/// data.set_position(0);
+/// let read_metadata: Option<Metadata> =
+///     ReadMetadata::read_metadata(&mut data).unwrap();
+/// assert_eq!(read_metadata, Some(metadata));
+/// ```
+pub trait WriteMetadataInPlace {
+    /// Write the metadata to an existing finalfusion file.
+    fn write_metadata_in_place<F>(&self, file: &mut F) -> Result<()>
+    where
+        F: Read + Write + Seek;
+}
+
+/// Memory-map finalfusion embeddings.
+///
+/// This trait is used to read finalfusion embeddings while [memory
+/// mapping](https://en.wikipedia.org/wiki/Mmap) the embedding matrix.
+/// This leads to considerable memory savings, since the operating
+/// system will load the relevant pages from disk on demand. The
+/// vocabulary is also memory mapped when it is stored as an
+/// `MmapVocab`.
+pub trait MmapEmbeddings
+where
+    Self: Sized,
+{
+    fn mmap_embeddings(read: &mut BufReader<File>) -> Result<Self>;
+}
+
+/// Memory-map several finalfusion files as layered embeddings.
+///
+/// The files are memory mapped as in `MmapEmbeddings` and layered with
+/// `Embeddings::layered`, so that they can be queried as one set of
+/// embeddings. The files are given in order of precedence: when a word
+/// is in more than one file, the embedding of the first file that
+/// contains the word is used.
+///
+/// ```
+/// use std::fs::File;
+/// use std::io::BufReader;
+///
+/// use finalfusion_core::embeddings::Embeddings;
+/// use finalfusion_core::storage::LayeredStorage;
+/// use finalfusion_core::vocab::LayeredVocab;
+/// use finalfusion_io::io::MmapLayeredEmbeddings;
+///
+/// let mut readers = vec![
+///     BufReader::new(File::open("../testdata/similarity.fifu").unwrap()),
+///     BufReader::new(File::open("../testdata/similarity.fifu").unwrap()),
+/// ];
+/// let embeddings: Embeddings<LayeredVocab, LayeredStorage> =
+///     Embeddings::mmap_layered(&mut readers).unwrap();
+///
+/// // Look up an embedding.
+/// let embedding = embeddings.embedding("Berlin");
+/// ```
+pub trait MmapLayeredEmbeddings
+where
+    Self: Sized,
+{
+    fn mmap_layered(reads: &mut [BufReader<File>]) -> Result<Self>;
+}
+
+/// Remap the embedding matrix of memory-mapped embeddings.
+///
+/// This trait is implemented for embeddings with a
+/// `RemappableMmapArray` storage, which are memory mapped with
+/// `MmapEmbeddings`. Since remapping requires a mutable reference,
+/// served embeddings are typically put behind a `RwLock`: lookups
+/// take a read lock and see a single epoch of the matrix, remapping
+/// takes the write lock for the short time that is needed to map the
+/// grown matrix.
+pub trait RemapEmbeddings {
+    /// Remap the embedding matrix when its file has grown.
+    ///
+    /// Returns `true` when the matrix was remapped.
+    fn remap(&mut self) -> Result<bool>;
+}
+
+/// Read finalfusion embeddings with truncated embeddings.
+///
+/// This trait is used to read finalfusion embeddings, retaining only
+/// the first `dims` components of each embedding. This is useful for
+/// embeddings that are trained to be truncatable. The truncation is
+/// done while reading, so the full embedding matrix is never held in
+/// memory.
+///
+/// The storage type determines how the embedding matrix is read:
+/// `NdArray` reads the truncated matrix into memory, `MmapArray`
+/// memory maps the matrix, and `PreadArray` reads embeddings on
+/// demand.
+///
+/// Since truncated embeddings are not unit vectors, the embeddings of
+/// the words are normalized after truncation. The norms of the
+/// truncated embeddings are multiplied into the stored norms, so that
+/// the truncated embeddings can still be unnormalized. The mapping of
+/// `MmapArray` cannot be modified, so the truncated matrix is copied
+/// to memory to normalize it. `PreadArray` normalizes embeddings when
+/// they are retrieved.
+///
+/// ```
+/// use std::fs::File;
+/// use std::io::BufReader;
+///
+/// use finalfusion_core::embeddings::Embeddings;
+/// use finalfusion_core::storage::NdArray;
+/// use finalfusion_core::vocab::SimpleVocab;
+/// use finalfusion_io::io::ReadEmbeddingsTruncated;
+///
+/// let mut reader = BufReader::new(File::open("../testdata/similarity.fifu").unwrap());
+/// let embeddings: Embeddings<SimpleVocab, NdArray> =
+///     Embeddings::read_embeddings_truncated(&mut reader, 50).unwrap();
+/// assert_eq!(embeddings.dims(), 50);
+/// ```
+pub trait ReadEmbeddingsTruncated
+where
+    Self: Sized,
+{
+    fn read_embeddings_truncated(read: &mut BufReader<File>, dims: usize) -> Result<Self>;
+}
+
+/// Read finalfusion embeddings restricted to a set of words.
+///
+/// This trait is used to read finalfusion embeddings, retaining only
+/// the given words. This is useful for applications with a known,
+/// closed vocabulary. Only the rows of the embedding matrix that are
+/// retained are read, so the full embedding matrix is never held in
+/// memory.
+///
+/// The words are looked up in the vocabulary and words that are not
+/// in the vocabulary are ignored. The retained words keep their
+/// vocabulary order and subword units are always retained, as in
+/// `Embeddings::prune_to`. Norms and counts are restricted to the
+/// retained words.
+///
+/// ```
+/// use std::fs::File;
+/// use std::io::BufReader;
+///
+/// use finalfusion_core::embeddings::Embeddings;
+/// use finalfusion_core::storage::NdArray;
+/// use finalfusion_core::vocab::VocabWrap;
+/// use finalfusion_io::io::ReadEmbeddingsSubset;
+///
+/// let mut reader = BufReader::new(File::open("../testdata/similarity.fifu").unwrap());
+/// let embeddings: Embeddings<VocabWrap, NdArray> =
+///     Embeddings::read_subset(&mut reader, &["Berlin", "Potsdam"]).unwrap();
+/// assert_eq!(embeddings.len(), 2);
+/// ```
+pub trait ReadEmbeddingsSubset
+where
+    Self: Sized,
+{
+    fn read_subset<R>(read: &mut R, words: &[impl AsRef<str>]) -> Result<Self>
+    where
+        R: Read + Seek;
+}
+
+/// Read finalfusion embeddings using positioned reads.
+///
+/// This trait is used to read finalfusion embeddings without loading
+/// or memory mapping the embedding matrix. Embeddings are read from
+/// the file on demand. This keeps memory use low in environments where
+/// memory mapping is undesirable, at the cost of a read per lookup.
+///
+/// ```
+/// use std::fs::File;
+/// use std::io::BufReader;
+///
+/// use finalfusion_core::embeddings::Embeddings;
+/// use finalfusion_core::storage::StorageWrap;
+/// use finalfusion_core::vocab::VocabWrap;
+/// use finalfusion_io::io::{PreadEmbeddings, ReadEmbeddings};
+///
+/// let mut reader = BufReader::new(File::open("../testdata/similarity.fifu").unwrap());
+/// let embeddings: Embeddings<VocabWrap, StorageWrap> =
+///     Embeddings::pread_embeddings(&mut reader).unwrap();
+/// ```
+pub trait PreadEmbeddings
+where
+    Self: Sized,
+{
+    fn pread_embeddings(read: &mut BufReader<File>) -> Result<Self>;
+}
+
+/// Read finalfusion embeddings with custom chunks.
+///
+/// This trait is used to read embeddings that contain custom chunks
+/// of the types in a `ChunkRegistry`. The custom chunks are returned
+/// in file order. Files with custom chunks that are not in the
+/// registry cannot be read.
+///
+/// `ReadEmbeddings` retains custom chunks as unknown chunks, other
+/// readers skip custom chunks.
+pub trait ReadEmbeddingsWithRegistry
+where
+    Self: Sized,
+{
+    /// Read the embeddings and their custom chunks.
+    fn read_embeddings_with_registry<R>(
+        read: &mut R,
+        registry: &ChunkRegistry,
+    ) -> Result<(Self, Vec<CustomChunk>)>
+    where
+        R: Read + Seek;
+}
+
+/// Write finalfusion embeddings with custom chunks.
+///
+/// The custom chunks are written after the chunks of the embeddings,
+/// using the handlers in the registry.
+pub trait WriteEmbeddingsWithChunks {
+    fn write_embeddings_with_chunks<W>(
+        &self,
+        write: &mut W,
+        registry: &ChunkRegistry,
+        chunks: &[CustomChunk],
+    ) -> Result<()>
+    where
+        W: Write + Seek;
+}
+
+/// Write embeddings in finalfusion format.
+///
+/// This trait is used to write embeddings in finalfusion
+/// format. Writing in finalfusion format is supported regardless of
+/// the original format of the embeddings.
+pub trait WriteEmbeddings {
+    fn write_embeddings<W>(&self, write: &mut W) -> Result<()>
+    where
+        W: Write + Seek;
+}
+
+/// Write finalfusion embeddings restricted to a set of words.
+///
+/// This trait is used to write slimmed embeddings, for instance for
+/// deploying embeddings with an application that has a known
+/// vocabulary. The selected rows are streamed from the storage to
+/// the writer, so the restricted embedding matrix is never
+/// constructed in memory and it is not necessary to use
+/// `Embeddings::retain` first. Memory-mapped storage is copied in
+/// blocks, without reading the other rows. Quantized storage only
+/// copies the codes of the selected rows, without reconstructing the
+/// embeddings.
+///
+/// Words are looked up in the vocabulary as in `Embeddings::prune_to`,
+/// applying the word normalization. The written words keep their
+/// vocabulary order and subword units are always written. Norms and
+/// counts are restricted to the written words, unknown chunks are not
+/// written.
+///
+/// ```
+/// use std::fs::File;
+/// use std::io::{BufReader, Cursor};
+///
+/// use finalfusion_core::embeddings::Embeddings;
+/// use finalfusion_core::storage::StorageWrap;
+/// use finalfusion_core::vocab::VocabWrap;
+/// use finalfusion_io::io::{MmapEmbeddings, ReadEmbeddings, WriteEmbeddingsFiltered};
+///
+/// let mut reader = BufReader::new(File::open("../testdata/similarity.fifu").unwrap());
+/// let embeddings: Embeddings<VocabWrap, StorageWrap> =
+///     Embeddings::mmap_embeddings(&mut reader).unwrap();
+///
+/// let mut cursor = Cursor::new(Vec::new());
+/// embeddings
+///     .write_embeddings_allowed(&mut cursor, &["Berlin", "Potsdam"])
+///     .unwrap();
+///
+/// cursor.set_position(0);
+/// let slimmed: Embeddings<VocabWrap, StorageWrap> =
+///     Embeddings::read_embeddings(&mut cursor).unwrap();
+/// assert_eq!(slimmed.len(), 2);
+/// ```
+pub trait WriteEmbeddingsFiltered {
+    /// Write the embeddings of the given words.
+    ///
+    /// Words that are not in the vocabulary are ignored.
+    fn write_embeddings_allowed<W>(&self, write: &mut W, words: &[impl AsRef<str>]) -> Result<()>
+    where
+        W: Write + Seek;
+
+    /// Write the embeddings of all words, except for the given words.
+    fn write_embeddings_denied<W>(&self, write: &mut W, words: &[impl AsRef<str>]) -> Result<()>
+    where
+        W: Write + Seek;
+
+    /// Write the embeddings of the words for which the predicate holds.
+    fn write_embeddings_retained<W, F>(&self, write: &mut W, predicate: F) -> Result<()>
+    where
+        W: Write + Seek,
+        F: FnMut(&str) -> bool;
+}
+
+/// Write embeddings in finalfusion format to a non-seekable writer.
+///
+/// Chunks use the stream position to align their data, which is
+/// why `WriteEmbeddings` requires a seekable writer. This trait
+/// tracks the stream position while writing instead, so that
+/// embeddings can be written to any writer, such as a socket,
+/// standard output, or a compressor. The written data is identical
+/// to the data written by `WriteEmbeddings`.
+///
+/// ```
+/// use std::fs::File;
+/// use std::io::BufReader;
+///
+/// use finalfusion_core::embeddings::Embeddings;
+/// use finalfusion_core::storage::StorageWrap;
+/// use finalfusion_core::vocab::VocabWrap;
+/// use finalfusion_io::io::{ReadEmbeddings, WriteEmbeddingsStream};
+///
+/// let mut reader = BufReader::new(File::open("../testdata/similarity.fifu").unwrap());
+/// let embeddings: Embeddings<VocabWrap, StorageWrap> =
+///     Embeddings::read_embeddings(&mut reader).unwrap();
+///
+/// // Write to a vector, which does not implement `Seek`.
+/// let mut data = Vec::new();
+/// embeddings.write_embeddings_stream(&mut data).unwrap();
+/// ```
+pub trait WriteEmbeddingsStream {
+    fn write_embeddings_stream<W>(&self, write: &mut W) -> Result<()>
+    where
+        W: Write;
+}
+
+impl<T> WriteEmbeddingsStream for T
+where
+    T: WriteEmbeddings,
+{
+    fn write_embeddings_stream<W>(&self, write: &mut W) -> Result<()>
+    where
+        W: Write,
+    {
+        self.write_embeddings(&mut PositionWriter::new(write))
+    }
+}
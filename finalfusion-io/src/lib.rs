@@ -0,0 +1,31 @@
+//! Serialization of embeddings in the finalfusion format.
+//!
+//! This crate reads, memory maps, and writes the `Embeddings` type
+//! of the `finalfusion-core` crate in the finalfusion format. The
+//! traits for reading and writing embeddings are in the `io` module.
+//! The `finalfusion` crate re-exports this crate.
+
+mod chunks;
+pub use chunks::storage;
+
+#[doc(hidden)]
+pub mod chunk {
+    //! The chunk layer of the finalfusion format.
+    //!
+    //! This module is used by the `finalfusion-compat` crate and is
+    //! not part of the public API.
+
+    pub use crate::chunks::io::{ChunkIdentifier, Header, WriteChunk};
+}
+
+pub mod asset;
+
+pub mod diff;
+
+mod embeddings;
+
+pub mod format;
+
+pub mod io;
+
+pub mod progress;
@@ -0,0 +1,412 @@
+//! Progress reporting for long-running I/O.
+//!
+//! Reading, writing, or converting large embeddings can take several
+//! minutes. `ProgressReader` and `ProgressWriter` wrap a reader or
+//! writer and call a callback with the `Progress` of the data that
+//! was read or written. Since they only wrap the underlying reader or
+//! writer, they can be used with all entry points that read from
+//! `Read + Seek` or write to `Write + Seek`:
+//!
+//! ```
+//! use std::fs::File;
+//! use std::io::BufReader;
+//!
+//! use finalfusion_core::embeddings::Embeddings;
+//! use finalfusion_core::storage::StorageWrap;
+//! use finalfusion_core::vocab::VocabWrap;
+//! use finalfusion_io::io::ReadEmbeddings;
+//! use finalfusion_io::progress::ProgressReader;
+//!
+//! let f = BufReader::new(File::open("../testdata/similarity.fifu").unwrap());
+//! let mut reader = ProgressReader::new(f, |progress| {
+//!     eprintln!(
+//!         "Read {} of {:?} bytes, chunk: {:?}",
+//!         progress.done, progress.total, progress.chunk
+//!     )
+//! })
+//! .unwrap();
+//! let embeddings: Embeddings<VocabWrap, StorageWrap> =
+//!     Embeddings::read_embeddings(&mut reader).unwrap();
+//! ```
+//!
+//! When a finalfusion file is read, the progress also contains the
+//! chunk that is being read. The reader should be buffered before it
+//! is wrapped, as in the example above. Otherwise, the buffer reads
+//! ahead of the chunk that is being read. Conversions that process embeddings row
+//! by row, such as `Word2VecQuantizer::convert_with_progress`, report
+//! progress in rows.
+
+use std::io::{self, BufRead, Read, Seek, SeekFrom, Write};
+
+use byteorder::{LittleEndian, ReadBytesExt};
+
+use crate::chunks::io::{chunk_name, Header, ReadChunk};
+use crate::io::{ErrorKind, Result};
+
+/// Minimum number of bytes between progress reports.
+const REPORT_INTERVAL: u64 = 1 << 20;
+
+/// Unit of progress.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ProgressUnit {
+    /// Bytes that were read or written.
+    Bytes,
+
+    /// Embedding matrix rows that were processed.
+    Rows,
+}
+
+/// Progress of a long-running operation.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct Progress {
+    /// The unit of `done` and `total`.
+    pub unit: ProgressUnit,
+
+    /// The number of units that were processed.
+    pub done: u64,
+
+    /// The total number of units, if known.
+    pub total: Option<u64>,
+
+    /// The name of the chunk that is processed, if known.
+    pub chunk: Option<String>,
+}
+
+impl Progress {
+    #[doc(hidden)]
+    pub fn new(unit: ProgressUnit, done: u64, total: Option<u64>) -> Self {
+        Progress {
+            unit,
+            done,
+            total,
+            chunk: None,
+        }
+    }
+
+    #[doc(hidden)]
+    pub fn chunk(mut self, chunk: impl Into<String>) -> Self {
+        self.chunk = Some(chunk.into());
+        self
+    }
+}
+
+/// Reader that reports the number of bytes that were read.
+///
+/// The progress is reported when at least 1 MiB was read since the
+/// last report, when reading a new finalfusion chunk starts, and when
+/// the end of the data is reached. Seeks are reported as progress as
+/// well, since readers skip data by seeking.
+pub struct ProgressReader<R, F> {
+    inner: R,
+    callback: F,
+    start: u64,
+    pos: u64,
+    end: u64,
+    chunks: Vec<(u64, String)>,
+    reported: Option<(u64, Option<usize>)>,
+}
+
+impl<R, F> ProgressReader<R, F>
+where
+    R: Read + Seek,
+    F: FnMut(&Progress),
+{
+    /// Construct a reader that reports progress to `callback`.
+    ///
+    /// Progress is relative to the current position of `inner`. If
+    /// `inner` is positioned at the start of a finalfusion file, the
+    /// chunk offsets are determined, so that the chunk that is being
+    /// read can be reported.
+    pub fn new(mut inner: R, callback: F) -> Result<Self> {
+        let start = inner
+            .stream_position()
+            .map_err(|e| ErrorKind::io_error("Cannot get stream position", e))?;
+        let end = inner
+            .seek(SeekFrom::End(0))
+            .map_err(|e| ErrorKind::io_error("Cannot seek to end of data", e))?;
+
+        inner
+            .seek(SeekFrom::Start(start))
+            .map_err(|e| ErrorKind::io_error("Cannot seek to start of data", e))?;
+        // Data in other formats does not have chunks.
+        let chunks = chunk_offsets(&mut inner, end).unwrap_or_default();
+        inner
+            .seek(SeekFrom::Start(start))
+            .map_err(|e| ErrorKind::io_error("Cannot seek to start of data", e))?;
+
+        Ok(ProgressReader {
+            inner,
+            callback,
+            start,
+            pos: start,
+            end,
+            chunks,
+            reported: None,
+        })
+    }
+}
+
+impl<R, F> ProgressReader<R, F>
+where
+    F: FnMut(&Progress),
+{
+    /// Unwrap the inner reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Update the position and report the progress, if necessary.
+    fn report(&mut self, pos: u64) {
+        self.pos = pos;
+
+        let total = self.end.saturating_sub(self.start);
+        let done = pos.clamp(self.start, self.end) - self.start;
+        let chunk_idx = self.chunks.iter().rposition(|&(offset, _)| offset <= pos);
+
+        let report = match self.reported {
+            None => true,
+            Some((reported, reported_chunk)) => {
+                chunk_idx != reported_chunk
+                    || done.abs_diff(reported) >= REPORT_INTERVAL
+                    || (done == total && reported != done)
+            }
+        };
+        if !report {
+            return;
+        }
+
+        let mut progress = Progress::new(ProgressUnit::Bytes, done, Some(total));
+        if let Some(idx) = chunk_idx {
+            progress = progress.chunk(self.chunks[idx].1.clone());
+        }
+
+        (self.callback)(&progress);
+        self.reported = Some((done, chunk_idx));
+    }
+}
+
+impl<R, F> Read for ProgressReader<R, F>
+where
+    R: Read,
+    F: FnMut(&Progress),
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.report(self.pos + n as u64);
+        Ok(n)
+    }
+}
+
+impl<R, F> BufRead for ProgressReader<R, F>
+where
+    R: BufRead,
+    F: FnMut(&Progress),
+{
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.inner.consume(amt);
+        self.report(self.pos + amt as u64);
+    }
+}
+
+impl<R, F> Seek for ProgressReader<R, F>
+where
+    R: Seek,
+    F: FnMut(&Progress),
+{
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = self.inner.seek(pos)?;
+        self.report(pos);
+        Ok(pos)
+    }
+}
+
+/// Writer that reports the number of bytes that were written.
+///
+/// The progress is reported when at least 1 MiB was written since
+/// the last report and when the writer is flushed. The total number
+/// of bytes is not known while writing.
+pub struct ProgressWriter<W, F> {
+    inner: W,
+    callback: F,
+    pos: u64,
+    end: u64,
+    reported: u64,
+}
+
+impl<W, F> ProgressWriter<W, F>
+where
+    W: Write,
+    F: FnMut(&Progress),
+{
+    /// Construct a writer that reports progress to `callback`.
+    pub fn new(inner: W, callback: F) -> Self {
+        ProgressWriter {
+            inner,
+            callback,
+            pos: 0,
+            end: 0,
+            reported: 0,
+        }
+    }
+
+    /// Unwrap the inner writer.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    fn report(&mut self, force: bool) {
+        if (force && self.end != self.reported) || self.end - self.reported >= REPORT_INTERVAL {
+            (self.callback)(&Progress::new(ProgressUnit::Bytes, self.end, None));
+            self.reported = self.end;
+        }
+    }
+}
+
+impl<W, F> Write for ProgressWriter<W, F>
+where
+    W: Write,
+    F: FnMut(&Progress),
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.pos += n as u64;
+        self.end = self.end.max(self.pos);
+        self.report(false);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()?;
+        self.report(true);
+        Ok(())
+    }
+}
+
+impl<W, F> Seek for ProgressWriter<W, F>
+where
+    W: Write + Seek,
+    F: FnMut(&Progress),
+{
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let before = self.inner.stream_position()?;
+        let new_pos = self.inner.seek(pos)?;
+        self.pos = (self.pos + new_pos).saturating_sub(before);
+        Ok(new_pos)
+    }
+}
+
+/// Get the offsets and names of the chunks of a finalfusion file.
+///
+/// `end` is the offset of the end of the data.
+fn chunk_offsets<R>(read: &mut R, end: u64) -> Result<Vec<(u64, String)>>
+where
+    R: Read + Seek,
+{
+    Header::read_chunk(read)?;
+
+    let mut chunks = Vec::new();
+    let mut pos = read
+        .stream_position()
+        .map_err(|e| ErrorKind::io_error("Cannot get chunk position", e))?;
+    while pos < end {
+        let identifier = read
+            .read_u32::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read chunk identifier", e))?;
+        let chunk_len = read
+            .read_u64::<LittleEndian>()
+            .map_err(|e| ErrorKind::io_error("Cannot read chunk length", e))?;
+        chunks.push((pos, chunk_name(identifier)));
+
+        pos = pos
+            .checked_add(12)
+            .and_then(|pos| pos.checked_add(chunk_len))
+            .ok_or_else(|| ErrorKind::Format(format!("Invalid chunk length: {}", chunk_len)))?;
+        read.seek(SeekFrom::Start(pos))
+            .map_err(|e| ErrorKind::io_error("Cannot seek to next chunk", e))?;
+    }
+
+    Ok(chunks)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::{BufReader, Cursor, Read, Write};
+
+    use crate::io::{ReadEmbeddings, WriteEmbeddings};
+    use finalfusion_core::embeddings::Embeddings;
+    use finalfusion_core::storage::StorageWrap;
+    use finalfusion_core::vocab::VocabWrap;
+
+    use super::{ProgressReader, ProgressUnit, ProgressWriter};
+
+    #[test]
+    fn reader_reports_chunks() {
+        let f = File::open("../testdata/similarity.fifu").unwrap();
+        let len = f.metadata().unwrap().len();
+
+        let mut reports = Vec::new();
+        let mut reader =
+            ProgressReader::new(BufReader::new(f), |progress| reports.push(progress.clone()))
+                .unwrap();
+        let _: Embeddings<VocabWrap, StorageWrap> =
+            Embeddings::read_embeddings(&mut reader).unwrap();
+        drop(reader);
+
+        let chunks = reports
+            .iter()
+            .filter_map(|progress| progress.chunk.as_deref())
+            .fold(Vec::new(), |mut chunks, chunk| {
+                if chunks.last() != Some(&chunk) {
+                    chunks.push(chunk);
+                }
+                chunks
+            });
+        assert_eq!(chunks, &["SimpleVocab", "NdArray"]);
+
+        let last = reports.last().unwrap();
+        assert_eq!(last.unit, ProgressUnit::Bytes);
+        assert_eq!(last.done, len);
+        assert_eq!(last.total, Some(len));
+    }
+
+    #[test]
+    fn reader_without_chunks() {
+        let mut reports = Vec::new();
+        let mut reader = ProgressReader::new(Cursor::new(vec![0u8; 16]), |progress| {
+            reports.push(progress.clone())
+        })
+        .unwrap();
+        reader.read_to_end(&mut Vec::new()).unwrap();
+        drop(reader);
+
+        let last = reports.last().unwrap();
+        assert_eq!(last.done, 16);
+        assert_eq!(last.chunk, None);
+    }
+
+    #[test]
+    fn writer_reports_on_flush() {
+        let mut f = File::open("../testdata/similarity.fifu").unwrap();
+        let embeds: Embeddings<VocabWrap, StorageWrap> =
+            Embeddings::read_embeddings(&mut BufReader::new(&mut f)).unwrap();
+
+        let mut reports = Vec::new();
+        let mut writer = ProgressWriter::new(Cursor::new(Vec::new()), |progress| {
+            reports.push(progress.clone())
+        });
+        embeds.write_embeddings(&mut writer).unwrap();
+        writer.flush().unwrap();
+        let len = writer.into_inner().into_inner().len() as u64;
+
+        let last = reports.last().unwrap();
+        assert_eq!(last.unit, ProgressUnit::Bytes);
+        assert_eq!(last.done, len);
+        assert_eq!(last.total, None);
+    }
+}
@@ -1,7 +0,0 @@
-//! Readers/writers for other embedding formats.
-
-pub mod fasttext;
-
-pub mod text;
-
-pub mod word2vec;
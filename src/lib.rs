@@ -53,20 +53,31 @@
 //! Consult the documentation of the `fasttext`, `text` and
 //! `word2vec` modules for information on how to read fastText,
 //! GloVe, and word2vec embeddings.
+//!
+//! ## Crates
+//!
+//! This crate re-exports the functionality of three crates:
+//! `finalfusion-core` provides the `Embeddings`, vocabulary, and
+//! storage types; `finalfusion-io` reads and writes the finalfusion
+//! format; and `finalfusion-compat` reads and writes other formats.
+//! Crates that only use embeddings that are constructed in memory
+//! can depend on `finalfusion-core` alone.
 
-mod chunks;
-pub use chunks::{metadata, norms, storage, vocab};
-
-pub mod compat;
+pub use finalfusion_compat as compat;
 
-pub mod embeddings;
+pub use finalfusion_core::{
+    counts, embeddings, memory, metadata, normalization, norms, similarity, subword, transform,
+    vocab, warnings,
+};
 
-pub mod io;
+pub use finalfusion_io::{asset, diff, format, io, progress};
 
 pub mod prelude;
 
-pub mod similarity;
+pub mod storage {
+    //! Embedding matrix representations.
 
-pub mod subword;
+    pub use finalfusion_core::storage::*;
 
-pub(crate) mod util;
+    pub use finalfusion_io::storage::{MmapArrayEpoch, QuantizedArrayWriter, RemappableMmapArray};
+}
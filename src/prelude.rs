@@ -1,8 +1,8 @@
 //! Prelude exports the most commonly-used types and traits.
 
-pub use crate::chunks::storage::{StorageViewWrap, StorageWrap};
+pub use crate::storage::{StorageViewWrap, StorageWrap};
 
-pub use crate::chunks::vocab::VocabWrap;
+pub use crate::vocab::VocabWrap;
 
 pub use crate::compat::fasttext::ReadFastText;
 
@@ -12,7 +12,9 @@ pub use crate::compat::word2vec::ReadWord2Vec;
 
 pub use crate::embeddings::Embeddings;
 
-pub use crate::io::{MmapEmbeddings, ReadEmbeddings};
+pub use crate::io::{
+    MmapEmbeddings, PreadEmbeddings, ReadEmbeddings, ReadEmbeddingsSubset, ReadEmbeddingsTruncated,
+};
 
 #[cfg(test)]
 mod tests {
@@ -55,6 +57,14 @@ mod tests {
         assert!(embeds_view.embedding("Berlin").is_some());
     }
 
+    #[test]
+    fn prelude_allows_embedding_pread_lookups() {
+        let mut reader = BufReader::new(File::open("testdata/similarity.fifu").unwrap());
+        let embeds: Embeddings<VocabWrap, StorageWrap> =
+            Embeddings::pread_embeddings(&mut reader).unwrap();
+        assert!(embeds.embedding("Berlin").is_some());
+    }
+
     #[test]
     fn prelude_allows_reading_fasttext() {
         let mut reader = BufReader::new(File::open("testdata/fasttext.bin").unwrap());
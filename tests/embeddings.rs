@@ -0,0 +1,860 @@
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{BufReader, Cursor, Seek, SeekFrom};
+use std::sync::Arc;
+
+use approx::AbsDiffEq;
+use finalfusion::compat::fasttext::ReadFastText;
+use finalfusion::compat::word2vec::{ReadWord2Vec, ReadWord2VecRaw, Word2VecOptions};
+use finalfusion::counts::WordCounts;
+use finalfusion::embeddings::{Embeddings, Prune, Quantize, TryQuantize};
+use finalfusion::io::{
+    Features, MmapEmbeddings, PreadEmbeddings, ReadEmbeddings, ReadEmbeddingsStrict,
+    ReadEmbeddingsSubset, ReadEmbeddingsTruncated, WriteEmbeddings, WriteEmbeddingsFiltered,
+    WriteEmbeddingsStream,
+};
+use finalfusion::memory::{MemoryFootprint, MemoryUsage};
+use finalfusion::metadata::Metadata;
+use finalfusion::normalization::FnNormalization;
+use finalfusion::norms::NdNorms;
+use finalfusion::similarity::WordSimilarity;
+use finalfusion::storage::{
+    AccessPattern, BorrowedArray, MmapArray, NdArray, PreadArray, Storage, StorageView, StorageWrap,
+};
+use finalfusion::transform::{Centering, Projection, TransformPipeline};
+use finalfusion::vocab::{AliasVocab, MmapVocab, SimpleVocab, Vocab, VocabWrap};
+use finalfusion::warnings::Warnings;
+use finalfusion_core::util::l2_normalize;
+use ndarray::{array, s, Array1, Array2};
+use reductive::pq::PQ;
+use toml::toml;
+
+fn test_embeddings() -> Embeddings<SimpleVocab, NdArray> {
+    let mut reader = BufReader::new(File::open("testdata/similarity.bin").unwrap());
+    Embeddings::read_word2vec_binary_raw(
+        &mut reader,
+        &Word2VecOptions::default(),
+        &mut Warnings::new(),
+    )
+    .unwrap()
+}
+
+fn test_metadata() -> Metadata {
+    Metadata::new(toml! {
+        [hyperparameters]
+        dims = 300
+        ns = 5
+
+        [description]
+        description = "Test model"
+        language = "de"
+    })
+}
+
+#[test]
+fn embedding_into_equal_to_embedding() {
+    let mut reader = BufReader::new(File::open("testdata/fasttext.bin").unwrap());
+    let embeds = Embeddings::read_fasttext(&mut reader).unwrap();
+
+    // Known word
+    let mut target = Array1::zeros(embeds.dims());
+    assert!(embeds.embedding_into("ganz", target.view_mut()));
+    assert_eq!(target, embeds.embedding("ganz").unwrap());
+
+    // Unknown word
+    let mut target = Array1::zeros(embeds.dims());
+    assert!(embeds.embedding_into("iddqd", target.view_mut()));
+    assert_eq!(target, embeds.embedding("iddqd").unwrap());
+
+    // Unknown word, non-zero vector
+    assert!(embeds.embedding_into("idspispopd", target.view_mut()));
+    assert_eq!(target, embeds.embedding("idspispopd").unwrap());
+}
+
+#[test]
+fn transformed_lookups_are_consistent() {
+    let mut reader = BufReader::new(File::open("testdata/fasttext.bin").unwrap());
+    let mut embeds = Embeddings::read_fasttext(&mut reader).unwrap();
+    let dims = embeds.dims();
+    let untransformed = embeds.embedding("ganz").unwrap().into_owned();
+
+    let projection = Array2::from_shape_fn((dims, 5), |(r, c)| ((r + c) % 3) as f32 - 1.);
+    let pipeline = TransformPipeline::new()
+        .push(Centering::new(Array1::from_elem(dims, 0.1)))
+        .push(Projection::new(projection.clone()));
+    assert!(embeds.set_transform(Some(Arc::new(pipeline))).is_none());
+    assert_eq!(embeds.dims(), 5);
+
+    let expected = (&untransformed - 0.1).dot(&projection);
+    assert!(embeds
+        .embedding("ganz")
+        .unwrap()
+        .abs_diff_eq(&expected, 1e-5));
+    assert!(embeds
+        .embedding_with_norm("ganz")
+        .unwrap()
+        .embedding
+        .abs_diff_eq(&expected, 1e-5));
+
+    // Known and unknown words
+    for &word in &["ganz", "iddqd"] {
+        let mut target = Array1::zeros(embeds.dims());
+        assert!(embeds.embedding_into(word, target.view_mut()));
+        assert_eq!(target, embeds.embedding(word).unwrap());
+    }
+
+    let (batch, found) = embeds.embedding_batch(&["ganz", "iddqd"]);
+    assert_eq!(found, vec![true, true]);
+    assert!(batch
+        .row(0)
+        .abs_diff_eq(&embeds.embedding("ganz").unwrap(), 1e-5));
+    assert!(batch
+        .row(1)
+        .abs_diff_eq(&embeds.embedding("iddqd").unwrap(), 1e-5));
+
+    for (word, embedding) in embeds.iter().take(10) {
+        assert_eq!(embedding, embeds.embedding(&word).unwrap());
+    }
+
+    // The storage is not transformed.
+    assert_eq!(embeds.storage().shape().1, dims);
+    assert!(embeds.set_transform(None).is_some());
+    assert_eq!(embeds.embedding("ganz").unwrap(), untransformed);
+}
+
+#[test]
+fn normalized_lookups() {
+    let mut embeds = test_embeddings();
+    let berlin = embeds.embedding("Berlin").unwrap().into_owned();
+    assert!(embeds.embedding("berlin").is_none());
+
+    let lowercase = FnNormalization::new("lowercase", |word: &str| Cow::Owned(word.to_lowercase()));
+    assert!(embeds
+        .set_normalization(Some(Arc::new(lowercase)))
+        .is_none());
+
+    // Query words are normalized, the vocabulary is not.
+    assert!(embeds.embedding("Berlin").is_none());
+
+    embeds.normalize_vocab().unwrap();
+    assert_eq!(
+        embeds.vocab().idx("berlin"),
+        test_embeddings().vocab().idx("Berlin")
+    );
+    for &word in &["Berlin", "BERLIN", "berlin"] {
+        assert_eq!(embeds.embedding(word).unwrap(), berlin);
+        let mut target = Array1::zeros(embeds.dims());
+        assert!(embeds.embedding_into(word, target.view_mut()));
+        assert_eq!(target, berlin);
+        assert_eq!(embeds.embedding_with_norm(word).unwrap().embedding, berlin);
+    }
+
+    assert!(embeds.set_normalization(None).is_some());
+    assert!(embeds.embedding("Berlin").is_none());
+}
+
+#[test]
+fn retain_compacts_storage() {
+    let (metadata, vocab, storage, _) = test_embeddings().into_parts();
+    let norms = NdNorms::new(Array1::range(1., vocab.words_len() as f32 + 1., 1.));
+    let mut check_embeds = Embeddings::new(metadata, vocab, storage, norms);
+    let counts = (0..check_embeds.len() as u64).collect::<Vec<_>>();
+    check_embeds.set_counts(Some(WordCounts::new(counts)));
+    let retained = check_embeds.retain(|word| word.starts_with('B'));
+    assert!(retained.len() > 1 && retained.len() < check_embeds.len());
+    assert_eq!(retained.storage().shape().0, retained.len());
+    assert_eq!(retained.norms().unwrap().len(), retained.len());
+    for word in retained.vocab().words() {
+        assert!(word.starts_with('B'));
+        assert_eq!(retained.embedding(word), check_embeds.embedding(word));
+        assert_eq!(retained.count(word), check_embeds.count(word));
+        assert_eq!(
+            retained.embedding_with_norm(word).unwrap().norm,
+            check_embeds.embedding_with_norm(word).unwrap().norm
+        );
+    }
+
+    // Quantized codes are selected without reconstruction.
+    let quantized = check_embeds.quantize::<PQ<f32>>(10, 4, 5, 1, true);
+    let pruned = quantized.prune_to(&["Potsdam", "Berlin", "unknown", "Berlin"]);
+    assert_eq!(pruned.vocab().words(), &["Berlin", "Potsdam"]);
+    assert_eq!(pruned.storage().shape(), (2, check_embeds.dims()));
+    for &word in &["Berlin", "Potsdam"] {
+        assert_eq!(pruned.embedding(word), quantized.embedding(word));
+    }
+}
+
+fn merge_embeddings() -> (
+    Embeddings<SimpleVocab, NdArray>,
+    Embeddings<SimpleVocab, NdArray>,
+) {
+    let first = Embeddings::new(
+        None,
+        SimpleVocab::new(vec!["a".to_owned(), "b".to_owned()]),
+        NdArray::new(array![[1., 0.], [0., 1.]]),
+        NdNorms::new(array![2., 1.]),
+    );
+    let mut second = Embeddings::new(
+        None,
+        SimpleVocab::new(vec!["c".to_owned(), "a".to_owned()]),
+        NdArray::new(array![[0.6, 0.8], [0., 1.]]),
+        NdNorms::new(array![3., 2.]),
+    );
+    second.set_counts(Some(WordCounts::new(vec![5, 7])));
+
+    (first, second)
+}
+
+#[test]
+fn layered_uses_subwords_of_layers() {
+    let mut reader = BufReader::new(File::open("testdata/fasttext.bin").unwrap());
+    let fasttext = Embeddings::read_fasttext(&mut reader).unwrap();
+    let (first, _) = merge_embeddings();
+
+    // The layers do not have the same dimensionality.
+    assert!(Embeddings::layered(vec![first.into(), fasttext.clone().into()]).is_err());
+
+    let first = Embeddings::new(
+        None,
+        SimpleVocab::new(vec![fasttext.vocab().words()[1].clone(), "new".to_owned()]),
+        NdArray::new(Array2::ones((2, fasttext.dims()))),
+        NdNorms::new(array![1., 1.]),
+    );
+    let layered = Embeddings::layered(vec![first.into(), fasttext.clone().into()]).unwrap();
+    assert_eq!(layered.len(), fasttext.len() + 1);
+    assert_eq!(
+        layered.storage().shape().0,
+        fasttext.storage().shape().0 + 1
+    );
+    assert_eq!(
+        layered.embedding(&fasttext.vocab().words()[1]).unwrap(),
+        Array1::<f32>::ones(fasttext.dims())
+    );
+    for word in &[fasttext.vocab().words()[0].as_str(), "notinvocab"] {
+        assert_eq!(layered.embedding(word), fasttext.embedding(word));
+    }
+}
+
+#[test]
+fn retain_keeps_subwords() {
+    let mut reader = BufReader::new(File::open("testdata/fasttext.bin").unwrap());
+    let embeds = Embeddings::read_fasttext(&mut reader).unwrap();
+    let pruned = embeds.prune_to(&["ganz", "und"]);
+    assert_eq!(pruned.len(), 2);
+    assert_eq!(
+        pruned.storage().shape().0,
+        2 + embeds.vocab().vocab_len() - embeds.len()
+    );
+    assert_eq!(pruned.embedding("ganz"), embeds.embedding("ganz"));
+    assert_eq!(pruned.embedding("iddqd"), embeds.embedding("iddqd"));
+}
+
+#[test]
+fn byte_fallback_embeds_unknown_words() {
+    let embeds = test_embeddings();
+    let berlin = embeds.embedding("Berlin").unwrap().into_owned();
+    assert!(embeds.embedding("Bärlin").is_none());
+
+    let embeds = embeds.into_byte_fallback();
+    assert_eq!(embeds.embedding("Berlin").unwrap(), berlin);
+    assert!(embeds.embedding("Bärlin").is_some());
+
+    let mut cursor = Cursor::new(Vec::new());
+    embeds.write_embeddings(&mut cursor).unwrap();
+    cursor.seek(SeekFrom::Start(0)).unwrap();
+    let read_embeds: Embeddings<VocabWrap, StorageWrap> =
+        Embeddings::read_embeddings(&mut cursor).unwrap();
+    assert_eq!(read_embeds.embedding("Bärlin"), embeds.embedding("Bärlin"));
+}
+
+#[test]
+fn push() {
+    let mut reader = BufReader::new(File::open("testdata/similarity.bin").unwrap());
+    let mut embeds: Embeddings<SimpleVocab, NdArray> =
+        Embeddings::read_word2vec_binary(&mut reader).unwrap();
+    let len = embeds.len();
+    let dims = embeds.dims();
+    let unnormalized = embeds
+        .embedding_with_norm("Berlin")
+        .unwrap()
+        .into_unnormalized()
+        * 2.;
+
+    assert!(embeds.push("Berlin-Mitte", unnormalized.view()));
+    assert!(!embeds.push("Berlin", unnormalized.view()));
+    assert_eq!(embeds.len(), len + 1);
+    assert_eq!(embeds.storage().shape(), (len + 1, dims));
+    assert_eq!(embeds.norms().unwrap().len(), len + 1);
+
+    let pushed = embeds.embedding_with_norm("Berlin-Mitte").unwrap();
+    let check = embeds.embedding_with_norm("Berlin").unwrap();
+    assert!(pushed.embedding.abs_diff_eq(&check.embedding, 1e-5));
+    assert!((pushed.norm - 2. * check.norm).abs() < 1e-4);
+}
+
+#[test]
+fn push_reserved() {
+    let mut reader = BufReader::new(File::open("testdata/similarity.bin").unwrap());
+    let check_embeds: Embeddings<SimpleVocab, NdArray> =
+        Embeddings::read_word2vec_binary(&mut reader).unwrap();
+    let mut embeds = Embeddings::new(
+        None,
+        SimpleVocab::new(Vec::new()),
+        NdArray::new(Array2::zeros((0, check_embeds.dims()))),
+        NdNorms::new(Array1::zeros(0)),
+    );
+    embeds.reserve(check_embeds.len());
+    for (word, embedding) in check_embeds.iter() {
+        assert!(embeds.push(word, embedding.view()));
+    }
+    embeds.shrink_to_fit();
+
+    assert_eq!(embeds.vocab(), check_embeds.vocab());
+    assert!(embeds
+        .storage()
+        .view()
+        .abs_diff_eq(&check_embeds.storage().view(), 1e-6));
+}
+
+#[test]
+fn mmap() {
+    let check_embeds = test_embeddings();
+    let mut reader = BufReader::new(File::open("testdata/similarity.fifu").unwrap());
+    let embeds: Embeddings<SimpleVocab, MmapArray> =
+        Embeddings::mmap_embeddings(&mut reader).unwrap();
+    assert_eq!(embeds.vocab(), check_embeds.vocab());
+
+    #[cfg(target_endian = "little")]
+    assert_eq!(embeds.storage().view(), check_embeds.storage().view());
+}
+
+#[test]
+fn mmap_vocab() {
+    let check_embeds = test_embeddings();
+    let (metadata, vocab, storage, _) = check_embeds.clone().into_parts();
+    let embeds = Embeddings::new(
+        metadata,
+        MmapVocab::new(vocab.words()),
+        storage,
+        NdNorms::new(Array1::ones(vocab.words_len())),
+    );
+
+    let path = std::env::temp_dir().join(format!("mmap-vocab-embeds-{}.fifu", std::process::id()));
+    embeds
+        .write_embeddings(&mut File::create(&path).unwrap())
+        .unwrap();
+    let mut reader = BufReader::new(File::open(&path).unwrap());
+    let embeds: Embeddings<VocabWrap, StorageWrap> =
+        Embeddings::mmap_embeddings(&mut reader).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    match embeds.vocab() {
+        VocabWrap::MmapVocab(vocab) => assert!(vocab.is_mapped()),
+        _ => panic!("Vocabulary is not an MmapVocab"),
+    }
+    for word in check_embeds.vocab().words() {
+        assert_eq!(embeds.embedding(word), check_embeds.embedding(word));
+    }
+}
+
+#[test]
+fn lock_storage() {
+    let check_embeds = test_embeddings();
+    let mut reader = BufReader::new(File::open("testdata/similarity.fifu").unwrap());
+    let mmap_embeds: Embeddings<VocabWrap, StorageWrap> =
+        Embeddings::mmap_embeddings(&mut reader).unwrap();
+    let embeds: Embeddings<VocabWrap, StorageWrap> = check_embeds.clone().into();
+
+    for embeds in &[mmap_embeds, embeds] {
+        // Locking can fail due to resource limits, the embeddings
+        // must remain usable.
+        if embeds.lock_storage().is_ok() {
+            embeds.unlock_storage().unwrap();
+        }
+
+        assert_eq!(embeds.embedding("Berlin"), check_embeds.embedding("Berlin"));
+    }
+}
+
+#[test]
+fn mmap_advise() {
+    let check_embeds = test_embeddings();
+    let mut reader = BufReader::new(File::open("testdata/similarity.fifu").unwrap());
+    let embeds: Embeddings<VocabWrap, StorageWrap> =
+        Embeddings::mmap_embeddings(&mut reader).unwrap();
+
+    for &pattern in &[
+        AccessPattern::Random,
+        AccessPattern::Sequential,
+        AccessPattern::WillNeed,
+        AccessPattern::Normal,
+    ] {
+        embeds.advise(pattern).unwrap();
+    }
+    embeds.prefault();
+
+    assert_eq!(embeds.embedding("Berlin"), check_embeds.embedding("Berlin"));
+}
+
+fn check_truncated(embeds: &Embeddings<SimpleVocab, impl Storage>, dims: usize) {
+    let check_embeds = test_embeddings();
+    assert_eq!(embeds.vocab(), check_embeds.vocab());
+    assert_eq!(embeds.dims(), dims);
+
+    // Truncated embeddings are normalized, their norms are stored.
+    let norms = embeds.norms().unwrap();
+    for idx in 0..check_embeds.storage().shape().0 {
+        let mut truncated = check_embeds
+            .storage()
+            .view()
+            .slice(s![idx, ..dims])
+            .to_owned();
+        let norm = l2_normalize(truncated.view_mut());
+        assert_eq!(embeds.storage().embedding(idx).view(), truncated);
+        assert_eq!(norms[idx], norm);
+    }
+}
+
+#[test]
+fn read_truncated() {
+    let mut reader = BufReader::new(File::open("testdata/similarity.fifu").unwrap());
+    let embeds: Embeddings<SimpleVocab, NdArray> =
+        Embeddings::read_embeddings_truncated(&mut reader, 20).unwrap();
+    check_truncated(&embeds, 20);
+
+    let mut reader = BufReader::new(File::open("testdata/similarity.fifu").unwrap());
+    let embeds: Embeddings<SimpleVocab, MmapArray> =
+        Embeddings::read_embeddings_truncated(&mut reader, 20).unwrap();
+    check_truncated(&embeds, 20);
+
+    let mut reader = BufReader::new(File::open("testdata/similarity.fifu").unwrap());
+    let embeds: Embeddings<SimpleVocab, PreadArray> =
+        Embeddings::read_embeddings_truncated(&mut reader, 20).unwrap();
+    check_truncated(&embeds, 20);
+}
+
+#[test]
+fn write_mmap() {
+    let check_embeds = test_embeddings();
+    let mut reader = BufReader::new(File::open("testdata/similarity.fifu").unwrap());
+    let embeds: Embeddings<SimpleVocab, MmapArray> =
+        Embeddings::mmap_embeddings(&mut reader).unwrap();
+
+    let mut cursor = Cursor::new(Vec::new());
+    embeds.write_embeddings(&mut cursor).unwrap();
+    cursor.seek(SeekFrom::Start(0)).unwrap();
+    let embeds: Embeddings<SimpleVocab, NdArray> =
+        Embeddings::read_embeddings(&mut cursor).unwrap();
+    assert_eq!(embeds.storage().view(), check_embeds.storage().view());
+
+    // Truncated matrices are copied row by row.
+    let mut reader = BufReader::new(File::open("testdata/similarity.fifu").unwrap());
+    let embeds: Embeddings<SimpleVocab, MmapArray> =
+        Embeddings::read_embeddings_truncated(&mut reader, 20).unwrap();
+
+    let mut cursor = Cursor::new(Vec::new());
+    embeds.write_embeddings(&mut cursor).unwrap();
+    cursor.seek(SeekFrom::Start(0)).unwrap();
+    let embeds: Embeddings<SimpleVocab, NdArray> =
+        Embeddings::read_embeddings(&mut cursor).unwrap();
+    check_truncated(&embeds, 20);
+}
+
+#[test]
+fn into_dense() {
+    let check_embeds = test_embeddings();
+    let quantized_embeds = check_embeds.quantize::<PQ<f32>>(10, 4, 5, 1, true);
+    let reconstructed = quantized_embeds.storage().reconstruct();
+    let dense_embeds = quantized_embeds.into_dense();
+    assert_eq!(dense_embeds.vocab(), check_embeds.vocab());
+    assert_eq!(
+        dense_embeds.norms().map(|n| n.view()),
+        check_embeds.norms().map(|n| n.view())
+    );
+    assert_eq!(dense_embeds.storage().view(), reconstructed.view());
+}
+
+#[test]
+fn read_truncated_folds_norms() {
+    let check_embeds = test_embeddings();
+    let path = std::env::temp_dir().join(format!("truncated-{}.fifu", std::process::id()));
+    check_embeds
+        .write_embeddings(&mut File::create(&path).unwrap())
+        .unwrap();
+    let mut reader = BufReader::new(File::open(&path).unwrap());
+    let embeds: Embeddings<SimpleVocab, NdArray> =
+        Embeddings::read_embeddings_truncated(&mut reader, 20).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    // The unnormalized embeddings are the truncated unnormalized
+    // embeddings of the full model.
+    for word in check_embeds.vocab().words() {
+        let check = check_embeds
+            .embedding_with_norm(word)
+            .unwrap()
+            .into_unnormalized();
+        let unnormalized = embeds
+            .embedding_with_norm(word)
+            .unwrap()
+            .into_unnormalized();
+        assert!(unnormalized.abs_diff_eq(&check.slice(s![..20]), 1e-5));
+    }
+
+    // Queries use the normalized truncated embeddings.
+    let truncated = |word| {
+        let mut embedding = check_embeds
+            .embedding(word)
+            .unwrap()
+            .slice(s![..20])
+            .to_owned();
+        l2_normalize(embedding.view_mut());
+        embedding
+    };
+    let results = embeds.word_similarity("Berlin", 1).unwrap();
+    assert!(results[0]
+        .similarity
+        .into_inner()
+        .abs_diff_eq(&truncated("Berlin").dot(&truncated(&results[0].word)), 1e-5));
+}
+
+#[test]
+fn read_truncated_rejects_invalid_dims() {
+    let dims = test_embeddings().dims();
+    for &invalid in &[0, dims + 1] {
+        let mut reader = BufReader::new(File::open("testdata/similarity.fifu").unwrap());
+        assert!(
+            Embeddings::<SimpleVocab, NdArray>::read_embeddings_truncated(&mut reader, invalid)
+                .is_err()
+        );
+    }
+}
+
+fn write_to_vec(write: impl FnOnce(&mut Cursor<Vec<u8>>)) -> Vec<u8> {
+    let mut cursor = Cursor::new(Vec::new());
+    write(&mut cursor);
+    cursor.into_inner()
+}
+
+#[test]
+fn write_embeddings_allowed_words() {
+    let embeds = test_embeddings();
+    let words = ["Potsdam", "Berlin", "notinvocab", "Berlin"];
+    assert_eq!(
+        write_to_vec(|cursor| embeds.write_embeddings_allowed(cursor, &words).unwrap()),
+        write_to_vec(|cursor| embeds.prune_to(&words).write_embeddings(cursor).unwrap())
+    );
+}
+
+#[test]
+fn write_embeddings_denied_words() {
+    let mut embeds = test_embeddings();
+    embeds.set_metadata(Some(test_metadata()));
+    let words = ["Potsdam", "notinvocab"];
+    assert_eq!(
+        write_to_vec(|cursor| embeds.write_embeddings_denied(cursor, &words).unwrap()),
+        write_to_vec(|cursor| embeds
+            .retain(|word| word != "Potsdam")
+            .write_embeddings(cursor)
+            .unwrap())
+    );
+}
+
+#[test]
+fn write_embeddings_retained_quantized() {
+    let mut reader = BufReader::new(File::open("testdata/fasttext.bin").unwrap());
+    let embeds = Embeddings::read_fasttext(&mut reader).unwrap();
+    let quantized: Embeddings<VocabWrap, StorageWrap> =
+        embeds.quantize::<PQ<f32>>(10, 4, 5, 1, true).into();
+
+    let data = write_to_vec(|cursor| {
+        quantized
+            .write_embeddings_retained(cursor, |word| word.len() > 4)
+            .unwrap()
+    });
+    let filtered: Embeddings<VocabWrap, StorageWrap> =
+        Embeddings::read_embeddings(&mut Cursor::new(data)).unwrap();
+
+    let check = quantized.retain(|word| word.len() > 4);
+    assert_eq!(filtered.vocab(), check.vocab());
+    assert_eq!(filtered.storage().shape(), check.storage().shape());
+    assert_eq!(**filtered.counts().unwrap(), **check.counts().unwrap());
+    for word in filtered.vocab().words() {
+        assert_eq!(filtered.embedding(word), check.embedding(word));
+    }
+}
+
+#[test]
+fn read_subset_with_counts_and_subwords() {
+    let mut reader = BufReader::new(File::open("testdata/fasttext.bin").unwrap());
+    let embeds = Embeddings::read_fasttext(&mut reader).unwrap();
+    let words = [embeds.vocab().words()[3].clone(), "notinvocab".to_owned()];
+
+    let mut cursor = Cursor::new(Vec::new());
+    embeds.write_embeddings(&mut cursor).unwrap();
+    cursor.seek(SeekFrom::Start(0)).unwrap();
+    let subset: Embeddings<VocabWrap, NdArray> =
+        Embeddings::read_subset(&mut cursor, &words).unwrap();
+
+    let check = embeds.prune_to(&words);
+    assert_eq!(subset.vocab().words(), &words[..1]);
+    assert_eq!(subset.storage().view(), check.storage().view());
+    assert_eq!(
+        subset.norms().map(|norms| norms.to_vec()),
+        check.norms().map(|norms| norms.to_vec())
+    );
+    assert_eq!(**subset.counts().unwrap(), **check.counts().unwrap());
+    assert_eq!(
+        subset.embedding("notinvocab"),
+        check.embedding("notinvocab")
+    );
+}
+
+#[test]
+fn pread() {
+    let check_embeds = test_embeddings();
+    let mut reader = BufReader::new(File::open("testdata/similarity.fifu").unwrap());
+    let embeds: Embeddings<SimpleVocab, PreadArray> =
+        Embeddings::pread_embeddings(&mut reader).unwrap();
+    assert_eq!(embeds.vocab(), check_embeds.vocab());
+    assert_eq!(embeds.storage().shape(), check_embeds.storage().shape());
+    for idx in 0..check_embeds.storage().shape().0 {
+        assert_eq!(
+            embeds.storage().embedding(idx),
+            check_embeds.storage().embedding(idx)
+        );
+    }
+
+    // Writing copies the matrix from the file.
+    let mut cursor = Cursor::new(Vec::new());
+    embeds.write_embeddings(&mut cursor).unwrap();
+    cursor.seek(SeekFrom::Start(0)).unwrap();
+    let embeds: Embeddings<SimpleVocab, NdArray> =
+        Embeddings::read_embeddings(&mut cursor).unwrap();
+    assert_eq!(embeds.storage().view(), check_embeds.storage().view());
+}
+
+#[test]
+fn storage_embedding_into() {
+    fn check_embedding_into(storage: &impl Storage) {
+        let mut out = Array1::zeros(storage.shape().1);
+        for idx in 0..storage.shape().0 {
+            storage.embedding_into(idx, out.view_mut());
+            assert_eq!(out, storage.embedding(idx));
+        }
+    }
+
+    check_embedding_into(test_embeddings().storage());
+
+    let mut reader = BufReader::new(File::open("testdata/similarity.fifu").unwrap());
+    let embeds: Embeddings<SimpleVocab, MmapArray> =
+        Embeddings::mmap_embeddings(&mut reader).unwrap();
+    check_embedding_into(embeds.storage());
+
+    let mut reader = BufReader::new(File::open("testdata/similarity.fifu").unwrap());
+    let embeds: Embeddings<SimpleVocab, PreadArray> =
+        Embeddings::read_embeddings_truncated(&mut reader, 20).unwrap();
+    check_embedding_into(embeds.storage());
+}
+
+#[test]
+fn memory_usage() {
+    let embeds = test_embeddings();
+    let usage = embeds.memory_usage();
+    assert_eq!(usage.mapped, 0);
+    assert!(usage.resident > embeds.storage().view().len() * std::mem::size_of::<f32>());
+
+    let mut reader = BufReader::new(File::open("testdata/similarity.fifu").unwrap());
+    let embeds: Embeddings<SimpleVocab, MmapArray> =
+        Embeddings::mmap_embeddings(&mut reader).unwrap();
+    let usage = embeds.memory_usage();
+    let (rows, dims) = embeds.storage().shape();
+    assert_eq!(usage.mapped, rows * dims * std::mem::size_of::<f32>());
+    assert_eq!(
+        usage.resident,
+        embeds.vocab().memory_usage().resident + embeds.norms().memory_usage().resident
+    );
+
+    let mut reader = BufReader::new(File::open("testdata/similarity.fifu").unwrap());
+    let embeds: Embeddings<SimpleVocab, PreadArray> =
+        Embeddings::pread_embeddings(&mut reader).unwrap();
+    assert_eq!(embeds.storage().memory_usage(), MemoryFootprint::default());
+}
+
+#[test]
+fn write_read_simple_roundtrip() {
+    let check_embeds = test_embeddings();
+    let mut cursor = Cursor::new(Vec::new());
+    check_embeds.write_embeddings(&mut cursor).unwrap();
+    cursor.seek(SeekFrom::Start(0)).unwrap();
+    let embeds: Embeddings<SimpleVocab, NdArray> =
+        Embeddings::read_embeddings(&mut cursor).unwrap();
+    assert_eq!(embeds.storage().view(), check_embeds.storage().view());
+    assert_eq!(embeds.vocab(), check_embeds.vocab());
+}
+
+#[test]
+fn write_stream_is_equal_to_write() {
+    let mut reader = BufReader::new(File::open("testdata/similarity.fifu").unwrap());
+    let embeds: Embeddings<VocabWrap, StorageWrap> =
+        Embeddings::read_embeddings(&mut reader).unwrap();
+    let quantized: Embeddings<VocabWrap, StorageWrap> = embeds
+        .try_quantize::<PQ<f32>>(10, 4, 5, 1, true)
+        .unwrap()
+        .into();
+    let (_, vocab, storage, _) = test_embeddings().into_parts();
+    let norms = NdNorms::new(Array1::ones(vocab.words_len()));
+    let vocab = AliasVocab::new(VocabWrap::from(vocab), vec![("dieses".to_owned(), 0)]);
+    let aliased = Embeddings::new(
+        Some(test_metadata()),
+        VocabWrap::from(vocab),
+        StorageWrap::from(storage),
+        norms,
+    );
+
+    for embeds in &[quantized, aliased] {
+        let mut cursor = Cursor::new(Vec::new());
+        embeds.write_embeddings(&mut cursor).unwrap();
+
+        let mut data = Vec::new();
+        embeds.write_embeddings_stream(&mut data).unwrap();
+        assert_eq!(data, cursor.into_inner());
+    }
+}
+
+#[test]
+fn packed_codes_require_feature() {
+    // Version 1 headers store the features after the version.
+    let packed = test_embeddings().quantize::<PQ<f32>>(10, 4, 5, 1, true);
+    let data = write_to_vec(|cursor| packed.write_embeddings(cursor).unwrap());
+    assert_eq!(&data[4..8], &1u32.to_le_bytes());
+    let mut features = [0u8; 8];
+    features.copy_from_slice(&data[8..16]);
+    assert!(Features::from_bits(u64::from_le_bytes(features)).contains(Features::PACKED_CODES));
+
+    let unpacked = test_embeddings().quantize::<PQ<f32>>(10, 5, 5, 1, true);
+    let data = write_to_vec(|cursor| unpacked.write_embeddings(cursor).unwrap());
+    assert_eq!(&data[4..8], &0u32.to_le_bytes());
+}
+
+#[test]
+fn read_embeddings_strict_accepts_valid_files() {
+    let mut reader = BufReader::new(File::open("testdata/similarity.fifu").unwrap());
+    let check: Embeddings<VocabWrap, StorageWrap> =
+        Embeddings::read_embeddings(&mut reader).unwrap();
+    reader.seek(SeekFrom::Start(0)).unwrap();
+    let embeds: Embeddings<VocabWrap, StorageWrap> =
+        Embeddings::read_embeddings_strict(&mut reader).unwrap();
+    assert_eq!(embeds.vocab(), check.vocab());
+    assert_eq!(embeds.embedding("Berlin"), check.embedding("Berlin"));
+
+    let mut reader = BufReader::new(File::open("testdata/fasttext.bin").unwrap());
+    let fasttext = Embeddings::read_fasttext(&mut reader).unwrap();
+    let mut quantized = test_embeddings().quantize::<PQ<f32>>(10, 4, 5, 1, true);
+    quantized.set_metadata(Some(test_metadata()));
+    quantized.set_counts(Some(WordCounts::new(vec![1; quantized.len()])));
+    for data in &[
+        write_to_vec(|cursor| fasttext.write_embeddings(cursor).unwrap()),
+        write_to_vec(|cursor| quantized.write_embeddings(cursor).unwrap()),
+    ] {
+        let embeds: Embeddings<VocabWrap, StorageWrap> =
+            Embeddings::read_embeddings_strict(&mut Cursor::new(data)).unwrap();
+        let check: Embeddings<VocabWrap, StorageWrap> =
+            Embeddings::read_embeddings(&mut Cursor::new(data)).unwrap();
+        assert_eq!(embeds.vocab(), check.vocab());
+        assert_eq!(embeds.unknown_chunks(), check.unknown_chunks());
+    }
+}
+
+#[test]
+fn write_read_counts_roundtrip() {
+    let mut check_embeds = test_embeddings();
+    let counts = (0..check_embeds.len() as u64)
+        .map(|idx| 1000 - idx)
+        .collect::<Vec<_>>();
+    assert!(check_embeds
+        .set_counts(Some(WordCounts::new(counts)))
+        .is_none());
+    let berlin_idx = check_embeds.vocab().idx("Berlin").unwrap().word().unwrap();
+    assert_eq!(check_embeds.count("Berlin"), Some(1000 - berlin_idx as u64));
+    assert_eq!(check_embeds.count("Berlin-Mitte"), None);
+
+    let mut cursor = Cursor::new(Vec::new());
+    check_embeds.write_embeddings(&mut cursor).unwrap();
+    cursor.seek(SeekFrom::Start(0)).unwrap();
+    let mut embeds: Embeddings<SimpleVocab, NdArray> =
+        Embeddings::read_embeddings(&mut cursor).unwrap();
+    assert_eq!(embeds.counts(), check_embeds.counts());
+    assert_eq!(
+        embeds.norms().map(|n| n.view()),
+        check_embeds.norms().map(|n| n.view())
+    );
+
+    let berlin = embeds.embedding("Berlin").unwrap().into_owned();
+    assert!(embeds.push("Berlin-Mitte", berlin.view()));
+    assert_eq!(embeds.count("Berlin-Mitte"), Some(0));
+    assert_eq!(embeds.counts().unwrap().len(), embeds.len());
+}
+
+#[test]
+fn write_read_pruned_roundtrip() {
+    let mut reader = BufReader::new(File::open("testdata/similarity.bin").unwrap());
+    let mut embeds: Embeddings<SimpleVocab, NdArray> =
+        Embeddings::read_word2vec_binary(&mut reader).unwrap();
+    let embedding = embeds.embedding("Berlin").unwrap().into_owned();
+    assert!(embeds.push("Berlin2", embedding.view()));
+
+    // Normalization of the pushed embedding can change the least
+    // significant bits, so use a small tolerance.
+    let check_embeds = embeds.prune(1e-4);
+    assert_eq!(
+        check_embeds.storage().unique_embeddings().nrows(),
+        embeds.storage().shape().0 - 1
+    );
+
+    let mut cursor = Cursor::new(Vec::new());
+    check_embeds.write_embeddings(&mut cursor).unwrap();
+    cursor.seek(SeekFrom::Start(0)).unwrap();
+    let pruned: Embeddings<VocabWrap, StorageWrap> =
+        Embeddings::read_embeddings(&mut cursor).unwrap();
+    assert!(matches!(pruned.storage(), StorageWrap::DedupArray(_)));
+    for word in embeds.vocab().words() {
+        assert!(pruned
+            .embedding(word)
+            .unwrap()
+            .abs_diff_eq(&embeds.embedding(word).unwrap(), 1e-4));
+    }
+}
+
+#[test]
+fn write_borrowed_storage() {
+    let check_embeds = test_embeddings();
+    let embeds = Embeddings::new(
+        None,
+        check_embeds.vocab().clone(),
+        BorrowedArray::new(check_embeds.storage().view()),
+        NdNorms::new(vec![1f32; check_embeds.vocab().words_len()]),
+    );
+    assert_eq!(embeds.embedding("Berlin"), check_embeds.embedding("Berlin"));
+
+    let mut cursor = Cursor::new(Vec::new());
+    embeds.write_embeddings(&mut cursor).unwrap();
+    cursor.seek(SeekFrom::Start(0)).unwrap();
+    let embeds: Embeddings<SimpleVocab, NdArray> =
+        Embeddings::read_embeddings(&mut cursor).unwrap();
+    assert_eq!(embeds.storage().view(), check_embeds.storage().view());
+    assert_eq!(embeds.vocab(), check_embeds.vocab());
+}
+
+#[test]
+fn write_read_simple_metadata_roundtrip() {
+    let mut check_embeds = test_embeddings();
+    check_embeds.set_metadata(Some(test_metadata()));
+
+    let mut cursor = Cursor::new(Vec::new());
+    check_embeds.write_embeddings(&mut cursor).unwrap();
+    cursor.seek(SeekFrom::Start(0)).unwrap();
+    let embeds: Embeddings<SimpleVocab, NdArray> =
+        Embeddings::read_embeddings(&mut cursor).unwrap();
+    assert_eq!(embeds.storage().view(), check_embeds.storage().view());
+    assert_eq!(embeds.vocab(), check_embeds.vocab());
+}
@@ -0,0 +1,399 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::Arc;
+
+use ndarray::Axis;
+
+use finalfusion::compat::word2vec::ReadWord2Vec;
+use finalfusion::embeddings::Embeddings;
+use finalfusion::io::ReadEmbeddingsTruncated;
+use finalfusion::similarity::{
+    Analogy, EmbeddingSimilarity, ExplainSimilarity, Metric, Query, QueryError, QueryInput,
+    QueryOptions, QueryWorkspace, WordSimilarity,
+};
+use finalfusion::storage::{MmapArray, NdArray, StorageView};
+use finalfusion::transform::{Centering, L2Normalization, LookupTransform, TransformPipeline};
+use finalfusion::vocab::SimpleVocab;
+
+static SIMILARITY_ORDER_STUTTGART_10: &[&str] = &[
+    "Karlsruhe",
+    "Mannheim",
+    "München",
+    "Darmstadt",
+    "Heidelberg",
+    "Wiesbaden",
+    "Kassel",
+    "Düsseldorf",
+    "Leipzig",
+    "Berlin",
+];
+
+static SIMILARITY_ORDER: &[&str] = &[
+    "Potsdam",
+    "Hamburg",
+    "Leipzig",
+    "Dresden",
+    "München",
+    "Düsseldorf",
+    "Bonn",
+    "Stuttgart",
+    "Weimar",
+    "Berlin-Charlottenburg",
+    "Rostock",
+    "Karlsruhe",
+    "Chemnitz",
+    "Breslau",
+    "Wiesbaden",
+    "Hannover",
+    "Mannheim",
+    "Kassel",
+    "Köln",
+    "Danzig",
+    "Erfurt",
+    "Dessau",
+    "Bremen",
+    "Charlottenburg",
+    "Magdeburg",
+    "Neuruppin",
+    "Darmstadt",
+    "Jena",
+    "Wien",
+    "Heidelberg",
+    "Dortmund",
+    "Stettin",
+    "Schwerin",
+    "Neubrandenburg",
+    "Greifswald",
+    "Göttingen",
+    "Braunschweig",
+    "Berliner",
+    "Warschau",
+    "Berlin-Spandau",
+];
+
+static ANALOGY_ORDER: &[&str] = &[
+    "Deutschland",
+    "Westdeutschland",
+    "Sachsen",
+    "Mitteldeutschland",
+    "Brandenburg",
+    "Polen",
+    "Norddeutschland",
+    "Dänemark",
+    "Schleswig-Holstein",
+    "Österreich",
+    "Bayern",
+    "Thüringen",
+    "Bundesrepublik",
+    "Ostdeutschland",
+    "Preußen",
+    "Deutschen",
+    "Hessen",
+    "Potsdam",
+    "Mecklenburg",
+    "Niedersachsen",
+    "Hamburg",
+    "Süddeutschland",
+    "Bremen",
+    "Russland",
+    "Deutschlands",
+    "BRD",
+    "Litauen",
+    "Mecklenburg-Vorpommern",
+    "DDR",
+    "West-Berlin",
+    "Saarland",
+    "Lettland",
+    "Hannover",
+    "Rostock",
+    "Sachsen-Anhalt",
+    "Pommern",
+    "Schweden",
+    "Deutsche",
+    "deutschen",
+    "Westfalen",
+];
+
+#[test]
+fn test_similarity() {
+    let f = File::open("testdata/similarity.bin").unwrap();
+    let mut reader = BufReader::new(f);
+    let embeddings = Embeddings::read_word2vec_binary(&mut reader).unwrap();
+
+    let result = embeddings.word_similarity("Berlin", 40);
+    assert!(result.is_some());
+    let result = result.unwrap();
+    assert_eq!(40, result.len());
+
+    for (idx, word_similarity) in result.iter().enumerate() {
+        assert_eq!(SIMILARITY_ORDER[idx], word_similarity.word)
+    }
+
+    let result = embeddings.word_similarity("Berlin", 10);
+    assert!(result.is_some());
+    let result = result.unwrap();
+    assert_eq!(10, result.len());
+
+    println!("{:?}", result);
+
+    for (idx, word_similarity) in result.iter().enumerate() {
+        assert_eq!(SIMILARITY_ORDER[idx], word_similarity.word)
+    }
+}
+
+#[test]
+fn test_similarity_truncated_mmap() {
+    let mut reader = BufReader::new(File::open("testdata/similarity.fifu").unwrap());
+    let check: Embeddings<SimpleVocab, NdArray> =
+        Embeddings::read_embeddings_truncated(&mut reader, 20).unwrap();
+
+    let mut reader = BufReader::new(File::open("testdata/similarity.fifu").unwrap());
+    let embeddings: Embeddings<SimpleVocab, MmapArray> =
+        Embeddings::read_embeddings_truncated(&mut reader, 20).unwrap();
+
+    let result = embeddings.word_similarity("Berlin", 10).unwrap();
+    let check_result = check.word_similarity("Berlin", 10).unwrap();
+    assert_eq!(result.len(), 10);
+    for (word_similarity, check_similarity) in result.iter().zip(check_result.iter()) {
+        assert_eq!(word_similarity.word, check_similarity.word);
+        assert_eq!(word_similarity.similarity, check_similarity.similarity);
+    }
+}
+
+#[test]
+fn test_explain_similarity() {
+    let f = File::open("testdata/similarity.bin").unwrap();
+    let mut reader = BufReader::new(f);
+    let embeddings = Embeddings::read_word2vec_binary(&mut reader).unwrap();
+
+    let similarity = embeddings.word_similarity("Berlin", 1).unwrap()[0].similarity;
+    let explanation = embeddings
+        .explain_similarity("Berlin", "Potsdam", 5)
+        .unwrap();
+    assert!((explanation.similarity - similarity.into_inner()).abs() < 1e-5);
+    assert_eq!(explanation.contributions.len(), 5);
+    assert!(explanation
+        .contributions
+        .windows(2)
+        .all(|w| w[0].contribution >= w[1].contribution));
+
+    let berlin = embeddings.embedding("Berlin").unwrap();
+    let potsdam = embeddings.embedding("Potsdam").unwrap();
+    for contribution in &explanation.contributions {
+        let dim = contribution.dim;
+        assert!((contribution.contribution - berlin[dim] * potsdam[dim]).abs() < 1e-6);
+    }
+
+    let all = embeddings
+        .explain_similarity("Berlin", "Potsdam", usize::MAX)
+        .unwrap();
+    assert_eq!(all.contributions.len(), embeddings.dims());
+    let sum: f32 = all.contributions.iter().map(|c| c.contribution).sum();
+    assert!((sum - explanation.similarity).abs() < 1e-5);
+
+    assert_eq!(
+        embeddings
+            .explain_similarity("Berlin", "Atlantis", 5)
+            .unwrap_err(),
+        [true, false]
+    );
+}
+
+#[test]
+fn test_embedding_similarity() {
+    let f = File::open("testdata/similarity.bin").unwrap();
+    let mut reader = BufReader::new(f);
+    let embeddings = Embeddings::read_word2vec_binary(&mut reader).unwrap();
+    let embedding = embeddings.embedding("Berlin").unwrap();
+    let result = embeddings.embedding_similarity(embedding.view(), 10);
+    assert!(result.is_some());
+    let mut result = result.unwrap().into_iter();
+    assert_eq!(10, result.len());
+    assert_eq!(result.next().unwrap().word, "Berlin");
+
+    for (idx, word_similarity) in result.enumerate() {
+        assert_eq!(SIMILARITY_ORDER[idx], word_similarity.word)
+    }
+}
+
+#[test]
+fn test_similarity_limit() {
+    let f = File::open("testdata/similarity.bin").unwrap();
+    let mut reader = BufReader::new(f);
+    let embeddings = Embeddings::read_word2vec_binary(&mut reader).unwrap();
+
+    let result = embeddings.word_similarity("Stuttgart", 10);
+    assert!(result.is_some());
+    let result = result.unwrap();
+    assert_eq!(10, result.len());
+
+    println!("{:?}", result);
+
+    for (idx, word_similarity) in result.iter().enumerate() {
+        assert_eq!(SIMILARITY_ORDER_STUTTGART_10[idx], word_similarity.word)
+    }
+}
+
+#[test]
+fn test_analogy() {
+    let f = File::open("testdata/analogy.bin").unwrap();
+    let mut reader = BufReader::new(f);
+    let embeddings = Embeddings::read_word2vec_binary(&mut reader).unwrap();
+
+    let result = embeddings.analogy(["Paris", "Frankreich", "Berlin"], 40);
+    assert!(result.is_ok());
+    let result = result.unwrap();
+    assert_eq!(40, result.len());
+
+    for (idx, word_similarity) in result.iter().enumerate() {
+        assert_eq!(ANALOGY_ORDER[idx], word_similarity.word)
+    }
+}
+
+#[test]
+fn test_analogy_absent() {
+    let f = File::open("testdata/analogy.bin").unwrap();
+    let mut reader = BufReader::new(f);
+    let embeddings = Embeddings::read_word2vec_binary(&mut reader).unwrap();
+
+    assert_eq!(
+        embeddings.analogy(["Foo", "Frankreich", "Berlin"], 40),
+        Err([false, true, true])
+    );
+    assert_eq!(
+        embeddings.analogy(["Paris", "Foo", "Berlin"], 40),
+        Err([true, false, true])
+    );
+    assert_eq!(
+        embeddings.analogy(["Paris", "Frankreich", "Foo"], 40),
+        Err([true, true, false])
+    );
+}
+
+#[test]
+fn test_query() {
+    let f = File::open("testdata/similarity.bin").unwrap();
+    let mut reader = BufReader::new(f);
+    let embeddings = Embeddings::read_word2vec_binary(&mut reader).unwrap();
+
+    let result = embeddings
+        .query(QueryInput::Word("Berlin"), &QueryOptions::new(40))
+        .unwrap();
+    assert_eq!(result, embeddings.word_similarity("Berlin", 40).unwrap());
+
+    // Query words can be included in the results.
+    let options = QueryOptions::new(1).query_mask(vec![false]);
+    let result = embeddings
+        .query(QueryInput::Word("Berlin"), &options)
+        .unwrap();
+    assert_eq!(result[0].word, "Berlin");
+
+    assert_eq!(
+        embeddings.query(QueryInput::Word("Foo"), &QueryOptions::new(10)),
+        Err(QueryError {
+            present: vec![false]
+        })
+    );
+}
+
+#[test]
+fn test_query_metrics() {
+    let f = File::open("testdata/similarity.bin").unwrap();
+    let mut reader = BufReader::new(f);
+    let embeddings = Embeddings::read_word2vec_binary(&mut reader).unwrap();
+    let embedding = embeddings.embedding("Berlin").unwrap().into_owned() * 2.;
+
+    for &metric in &[Metric::Cosine, Metric::Euclidean] {
+        let options = QueryOptions::new(40).metric(metric);
+        let result = embeddings
+            .query(QueryInput::Embedding(embedding.view()), &options)
+            .unwrap();
+        assert_eq!(result[0].word, "Berlin");
+        for (idx, word_similarity) in result.iter().skip(1).enumerate() {
+            assert_eq!(SIMILARITY_ORDER[idx], word_similarity.word)
+        }
+    }
+
+    let options = QueryOptions::new(1).metric(Metric::Cosine);
+    let result = embeddings
+        .query(QueryInput::Embedding(embedding.view()), &options)
+        .unwrap();
+    assert!((result[0].similarity.into_inner() - 1.).abs() < 1e-5);
+}
+
+#[test]
+fn test_query_analogy() {
+    let f = File::open("testdata/analogy.bin").unwrap();
+    let mut reader = BufReader::new(f);
+    let embeddings = Embeddings::read_word2vec_binary(&mut reader).unwrap();
+
+    let query = ["Paris", "Frankreich", "Berlin"];
+    let result = embeddings
+        .query(QueryInput::Analogy(query), &QueryOptions::new(40))
+        .unwrap();
+    assert_eq!(result, embeddings.analogy(query, 40).unwrap());
+}
+
+#[test]
+fn test_query_with_workspace() {
+    let f = File::open("testdata/similarity.bin").unwrap();
+    let mut reader = BufReader::new(f);
+    let embeddings = Embeddings::read_word2vec_binary(&mut reader).unwrap();
+
+    let mut workspace = QueryWorkspace::new(&embeddings);
+    for &word in &["Berlin", "Stuttgart"] {
+        for &metric in &[Metric::Dot, Metric::Cosine, Metric::Euclidean] {
+            let options = QueryOptions::new(10).metric(metric);
+            assert_eq!(
+                embeddings
+                    .query_with(QueryInput::Word(word), &options, &mut workspace)
+                    .unwrap(),
+                embeddings.query(QueryInput::Word(word), &options).unwrap()
+            );
+        }
+    }
+}
+
+#[test]
+fn test_query_transformed() {
+    let f = File::open("testdata/similarity.bin").unwrap();
+    let mut reader = BufReader::new(f);
+    let mut embeddings: Embeddings<SimpleVocab, NdArray> =
+        Embeddings::read_word2vec_binary(&mut reader).unwrap();
+
+    let mean = embeddings.storage().view().mean_axis(Axis(0)).unwrap();
+    let pipeline = TransformPipeline::new()
+        .push(Centering::new(mean))
+        .push(L2Normalization);
+    let transformed = Embeddings::new_without_norms(
+        None,
+        embeddings.vocab().clone(),
+        NdArray::new(pipeline.transform(embeddings.storage().view())),
+    );
+    embeddings.set_transform(Some(Arc::new(pipeline)));
+
+    let mut workspace = QueryWorkspace::new(&embeddings);
+    for &metric in &[Metric::Dot, Metric::Cosine, Metric::Euclidean] {
+        let options = QueryOptions::new(10).metric(metric);
+        let expected = transformed
+            .query(QueryInput::Word("Berlin"), &options)
+            .unwrap();
+        assert_eq!(
+            embeddings
+                .query(QueryInput::Word("Berlin"), &options)
+                .unwrap(),
+            expected
+        );
+        assert_eq!(
+            embeddings
+                .query_with(QueryInput::Word("Berlin"), &options, &mut workspace)
+                .unwrap(),
+            expected
+        );
+    }
+
+    assert_eq!(
+        embeddings.analogy(["Berlin", "Potsdam", "Hamburg"], 10),
+        transformed.analogy(["Berlin", "Potsdam", "Hamburg"], 10)
+    );
+}